@@ -0,0 +1,218 @@
+use access::bencode::BRefAccess;
+use access::convert::BConvert;
+use access::dict::BDictAccess;
+use access::list::BListAccess;
+use error::{BencodeConvertErrorKind, BencodeConvertError};
+
+const KEY_PATH_SEPARATOR: u8 = b'.';
+
+/// Decoder for pulling typed values out of a bencode dictionary.
+///
+/// Values are either required, where a missing key is an error, or optional, where
+/// a missing key is `None`; in both cases a value of the wrong type is an error. Errors
+/// name the full key path, such as `info.files`, for values in nested dictionaries.
+pub struct BDictDecoder<'a, 'c, C: 'c, K: 'a, V: 'a> {
+    convert: &'c C,
+    dict:    &'a BDictAccess<K, V>,
+    path:    Vec<u8>
+}
+
+impl<'a, 'c, C, K, V> BDictDecoder<'a, 'c, C, K, V>
+    where C: BConvert, V: BRefAccess {
+    /// Create a new `BDictDecoder` for the given root dictionary.
+    pub fn new(convert: &'c C, dict: &'a BDictAccess<K, V>) -> BDictDecoder<'a, 'c, C, K, V> {
+        BDictDecoder{ convert: convert, dict: dict, path: Vec::new() }
+    }
+
+    /// Create a new `BDictDecoder` for a dictionary nested at the given key path.
+    pub fn with_path<P>(convert: &'c C, dict: &'a BDictAccess<K, V>, path: P) -> BDictDecoder<'a, 'c, C, K, V>
+        where P: AsRef<[u8]> {
+        BDictDecoder{ convert: convert, dict: dict, path: path.as_ref().to_owned() }
+    }
+
+    /// Key path of the dictionary being decoded.
+    pub fn path(&self) -> &[u8] {
+        &self.path
+    }
+
+    /// Access the underlying dictionary.
+    pub fn dict(&self) -> &'a BDictAccess<K, V> {
+        self.dict
+    }
+
+    /// Decode a required integer.
+    pub fn required_int<E>(&self, key: E) -> Result<i64, C::Error>
+        where E: AsRef<[u8]> {
+        let path = self.key_path(key.as_ref());
+
+        self.convert.convert_int(try!(self.lookup(key.as_ref(), &path)), &path)
+    }
+
+    /// Decode an optional integer.
+    pub fn optional_int<E>(&self, key: E) -> Result<Option<i64>, C::Error>
+        where E: AsRef<[u8]> {
+        let path = self.key_path(key.as_ref());
+
+        self.dict.lookup(key.as_ref())
+            .map(|value| self.convert.convert_int(value, &path).map(Some))
+            .unwrap_or(Ok(None))
+    }
+
+    /// Decode required bytes.
+    pub fn required_bytes<E>(&self, key: E) -> Result<&'a [u8], C::Error>
+        where E: AsRef<[u8]> {
+        let path = self.key_path(key.as_ref());
+
+        self.convert.convert_bytes(try!(self.lookup(key.as_ref(), &path)), &path)
+    }
+
+    /// Decode optional bytes.
+    pub fn optional_bytes<E>(&self, key: E) -> Result<Option<&'a [u8]>, C::Error>
+        where E: AsRef<[u8]> {
+        let path = self.key_path(key.as_ref());
+
+        self.dict.lookup(key.as_ref())
+            .map(|value| self.convert.convert_bytes(value, &path).map(Some))
+            .unwrap_or(Ok(None))
+    }
+
+    /// Decode a required UTF-8 string.
+    pub fn required_str<E>(&self, key: E) -> Result<&'a str, C::Error>
+        where E: AsRef<[u8]> {
+        let path = self.key_path(key.as_ref());
+
+        self.convert.convert_str(try!(self.lookup(key.as_ref(), &path)), &path)
+    }
+
+    /// Decode an optional UTF-8 string.
+    pub fn optional_str<E>(&self, key: E) -> Result<Option<&'a str>, C::Error>
+        where E: AsRef<[u8]> {
+        let path = self.key_path(key.as_ref());
+
+        self.dict.lookup(key.as_ref())
+            .map(|value| self.convert.convert_str(value, &path).map(Some))
+            .unwrap_or(Ok(None))
+    }
+
+    /// Decode a required list.
+    pub fn required_list<E>(&self, key: E) -> Result<&'a BListAccess<V::BType>, C::Error>
+        where E: AsRef<[u8]> {
+        let path = self.key_path(key.as_ref());
+
+        self.convert.convert_list(try!(self.lookup(key.as_ref(), &path)), &path)
+    }
+
+    /// Decode an optional list.
+    pub fn optional_list<E>(&self, key: E) -> Result<Option<&'a BListAccess<V::BType>>, C::Error>
+        where E: AsRef<[u8]> {
+        let path = self.key_path(key.as_ref());
+
+        self.dict.lookup(key.as_ref())
+            .map(|value| self.convert.convert_list(value, &path).map(Some))
+            .unwrap_or(Ok(None))
+    }
+
+    /// Decode a required dictionary, returning a decoder for it.
+    pub fn required_dict<E>(&self, key: E) -> Result<BDictDecoder<'a, 'c, C, V::BKey, V::BType>, C::Error>
+        where E: AsRef<[u8]> {
+        let path = self.key_path(key.as_ref());
+        let dict = try!(self.convert.convert_dict(try!(self.lookup(key.as_ref(), &path)), &path));
+
+        Ok(BDictDecoder::with_path(self.convert, dict, path))
+    }
+
+    /// Decode an optional dictionary, returning a decoder for it.
+    pub fn optional_dict<E>(&self, key: E) -> Result<Option<BDictDecoder<'a, 'c, C, V::BKey, V::BType>>, C::Error>
+        where E: AsRef<[u8]> {
+        let path = self.key_path(key.as_ref());
+
+        match self.dict.lookup(key.as_ref()) {
+            Some(value) => {
+                let dict = try!(self.convert.convert_dict(value, &path));
+
+                Ok(Some(BDictDecoder::with_path(self.convert, dict, path)))
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn lookup(&self, key: &[u8], path: &[u8]) -> Result<&'a V, C::Error> {
+        self.dict.lookup(key)
+            .ok_or_else(|| self.convert.handle_error(BencodeConvertError::from_kind(BencodeConvertErrorKind::MissingKey{ key: path.to_owned() })))
+    }
+
+    fn key_path(&self, key: &[u8]) -> Vec<u8> {
+        let mut path = self.path.clone();
+
+        if !path.is_empty() {
+            path.push(KEY_PATH_SEPARATOR);
+        }
+        path.extend_from_slice(key);
+
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::default::Default;
+
+    use access::bencode::BRefAccess;
+    use access::convert::BConvert;
+    use access::decoder::BDictDecoder;
+    use error::{BencodeConvertError, BencodeConvertErrorKind};
+    use reference::bencode_ref::BencodeRef;
+    use reference::decode_opt::BDecodeOpt;
+
+    struct KindConvert;
+
+    impl BConvert for KindConvert {
+        type Error = BencodeConvertErrorKind;
+
+        fn handle_error(&self, error: BencodeConvertError) -> BencodeConvertErrorKind {
+            error.0
+        }
+    }
+
+    #[test]
+    fn positive_decode_required_and_optional() {
+        let bencode = BencodeRef::decode(b"d4:infod6:lengthi5e4:name4:testee", BDecodeOpt::default()).unwrap();
+        let convert = KindConvert;
+        let decoder = BDictDecoder::new(&convert, bencode.dict().unwrap());
+
+        let info = decoder.required_dict("info").unwrap();
+
+        assert_eq!(5, info.required_int("length").unwrap());
+        assert_eq!("test", info.required_str("name").unwrap());
+        assert_eq!(None, info.optional_bytes("md5sum").unwrap());
+        assert_eq!(b"info", info.path());
+    }
+
+    #[test]
+    fn negative_missing_key_names_path() {
+        let bencode = BencodeRef::decode(b"d4:infod4:name4:testee", BDecodeOpt::default()).unwrap();
+        let convert = KindConvert;
+        let decoder = BDictDecoder::new(&convert, bencode.dict().unwrap());
+
+        let info = decoder.required_dict("info").unwrap();
+
+        match info.required_int("length") {
+            Err(BencodeConvertErrorKind::MissingKey{ key }) => assert_eq!(&b"info.length"[..], &key[..]),
+            _                                               => panic!("Expected MissingKey Error")
+        }
+    }
+
+    #[test]
+    fn negative_optional_wrong_type() {
+        let bencode = BencodeRef::decode(b"d4:infod6:lengthi5eee", BDecodeOpt::default()).unwrap();
+        let convert = KindConvert;
+        let decoder = BDictDecoder::new(&convert, bencode.dict().unwrap());
+
+        let info = decoder.optional_dict("info").unwrap().unwrap();
+
+        match info.optional_str("length") {
+            Err(BencodeConvertErrorKind::WrongType{ key, .. }) => assert_eq!(&b"info.length"[..], &key[..]),
+            _                                                  => panic!("Expected WrongType Error")
+        }
+    }
+}
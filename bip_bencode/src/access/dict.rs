@@ -1,6 +1,8 @@
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 
+use bytes::Bytes;
+
 /// Trait for working with generic map data structures.
 pub trait BDictAccess<K, V> {
     /// Convert the dictionary to an unordered list of key/value pairs.
@@ -61,4 +63,26 @@ impl<'a, V> BDictAccess<Cow<'a, [u8]>, V> for BTreeMap<Cow<'a, [u8]>, V> {
     fn remove(&mut self, key: &[u8]) -> Option<V> {
         self.remove(key)
     }
-}
\ No newline at end of file
+}
+
+impl<V> BDictAccess<Bytes, V> for BTreeMap<Bytes, V> {
+    fn to_list(&self) -> Vec<(&Bytes, &V)> {
+        self.iter().map(|(k, v)| (k, v)).collect()
+    }
+
+    fn lookup(&self, key: &[u8]) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn lookup_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn insert(&mut self, key: Bytes, value: V) -> Option<V> {
+        self.insert(key, value)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<V> {
+        self.remove(key)
+    }
+}
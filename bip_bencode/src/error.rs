@@ -54,6 +54,12 @@ error_chain! {
             description("Invalid Dictionary Duplicate Keys Found")
             display("Invalid Dictionary Key Found At {:?} For Key {:?}", pos, key)
         }
+        InvalidNonCanonical {
+            pos: usize
+         } {
+            description("Invalid Non Canonical Bencode Found")
+            display("Invalid Non Canonical Bencode Found At {:?}", pos)
+        }
         InvalidLengthNegative {
             pos: usize
          } {
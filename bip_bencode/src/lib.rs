@@ -35,12 +35,14 @@
 //!     }
 //! ```
 
+extern crate bytes;
 #[macro_use]
 extern crate error_chain;
 
 mod access;
 mod cow;
 mod mutable;
+mod owned;
 mod reference;
 mod error;
 
@@ -57,8 +59,10 @@ pub mod ext {
 
 pub use reference::bencode_ref::{BencodeRef};
 pub use mutable::bencode_mut::{BencodeMut};
+pub use owned::bencode_buf::{BencodeBuf};
 pub use access::bencode::{BRefAccess, BencodeRefKind, BMutAccess, BencodeMutKind};
 pub use access::convert::{BConvert};
+pub use access::decoder::BDictDecoder;
 pub use access::dict::BDictAccess;
 pub use access::list::BListAccess;
 pub use reference::decode_opt::BDecodeOpt;
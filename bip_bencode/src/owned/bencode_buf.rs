@@ -0,0 +1,268 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::str;
+use std::usize;
+
+use access::bencode::{BRefAccess, BMutAccess, BencodeRefKind};
+use access::dict::BDictAccess;
+use access::list::BListAccess;
+use mutable::bencode_mut::BencodeMut;
+use reference::bencode_ref::{BencodeRef, InnerBencodeRef};
+use reference::decode_opt::BDecodeOpt;
+use error::BencodeParseResult;
+
+use bytes::Bytes;
+
+/// Bencode object that holds shared references to the underlying data.
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub enum InnerBencodeBuf {
+    /// Bencode Integer.
+    Int(i64, Bytes),
+    /// Bencode Bytes.
+    Bytes(Bytes, Bytes),
+    /// Bencode List.
+    List(Vec<BencodeBuf>, Bytes),
+    /// Bencode Dictionary.
+    Dict(BTreeMap<Bytes, BencodeBuf>, Bytes),
+}
+
+impl Into<BencodeBuf> for InnerBencodeBuf {
+    fn into(self) -> BencodeBuf {
+        BencodeBuf{ inner: self }
+    }
+}
+
+/// `BencodeBuf` object that stores shared references to some `Bytes`.
+///
+/// Unlike `BencodeRef`, this object is not tied to the lifetime of the buffer
+/// it was decoded from, so it can be cheaply cloned and sent between tasks.
+/// Values are slices of the buffer it was decoded from, although `Bytes` may
+/// copy very small slices instead of sharing them.
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub struct BencodeBuf {
+    inner: InnerBencodeBuf
+}
+
+impl BencodeBuf {
+    /// Decode the given bytes into a `BencodeBuf` using the given decode options.
+    pub fn decode(bytes: Bytes, opts: BDecodeOpt) -> BencodeParseResult<BencodeBuf> {
+        let bencode_ref = try!(BencodeRef::decode(&bytes[..], opts));
+
+        Ok(from_ref(&bytes, &bencode_ref))
+    }
+
+    /// Get the `Bytes` of the current bencode byte representation.
+    pub fn buffer(&self) -> &Bytes {
+        match self.inner {
+            InnerBencodeBuf::Int(_, ref buffer)   => buffer,
+            InnerBencodeBuf::Bytes(_, ref buffer) => buffer,
+            InnerBencodeBuf::List(_, ref buffer)  => buffer,
+            InnerBencodeBuf::Dict(_, ref buffer)  => buffer
+        }
+    }
+
+    /// Access the bencode as a `BencodeRef` which references our buffer.
+    pub fn to_ref<'a>(&'a self) -> BencodeRef<'a> {
+        match self.inner {
+            InnerBencodeBuf::Int(n, ref buffer)         => InnerBencodeRef::Int(n, &buffer[..]).into(),
+            InnerBencodeBuf::Bytes(ref n, ref buffer)   => InnerBencodeRef::Bytes(&n[..], &buffer[..]).into(),
+            InnerBencodeBuf::List(ref list, ref buffer) => {
+                InnerBencodeRef::List(list.iter().map(BencodeBuf::to_ref).collect(), &buffer[..]).into()
+            },
+            InnerBencodeBuf::Dict(ref dict, ref buffer) => {
+                InnerBencodeRef::Dict(dict.iter().map(|(k, v)| (&k[..], v.to_ref())).collect(), &buffer[..]).into()
+            }
+        }
+    }
+
+    /// Access the bencode as a `BencodeMut` which references our buffer.
+    pub fn to_mut<'a>(&'a self) -> BencodeMut<'a> {
+        match self.inner {
+            InnerBencodeBuf::Int(n, _)       => BencodeMut::new_int(n),
+            InnerBencodeBuf::Bytes(ref n, _) => BencodeMut::new_bytes(Cow::Borrowed(&n[..])),
+            InnerBencodeBuf::List(ref list, _) => {
+                let mut bencode = BencodeMut::new_list();
+                {
+                    let bencode_list = bencode.list_mut().unwrap();
+
+                    for item in list {
+                        bencode_list.push(item.to_mut());
+                    }
+                }
+
+                bencode
+            },
+            InnerBencodeBuf::Dict(ref dict, _) => {
+                let mut bencode = BencodeMut::new_dict();
+                {
+                    let bencode_dict = bencode.dict_mut().unwrap();
+
+                    for (key, value) in dict {
+                        bencode_dict.insert(Cow::Borrowed(&key[..]), value.to_mut());
+                    }
+                }
+
+                bencode
+            }
+        }
+    }
+}
+
+/// Convert the `BencodeRef` into a `BencodeBuf` that holds slices of the given base buffer.
+///
+/// The `BencodeRef` must have been decoded from the base buffer.
+fn from_ref(base: &Bytes, bencode: &BencodeRef) -> BencodeBuf {
+    let slice_base = |slice: &[u8]| {
+        let start = slice.as_ptr() as usize - base.as_ptr() as usize;
+
+        base.slice(start, start + slice.len())
+    };
+    let buffer = slice_base(bencode.buffer());
+
+    match bencode.kind() {
+        BencodeRefKind::Int(n)      => InnerBencodeBuf::Int(n, buffer).into(),
+        BencodeRefKind::Bytes(n)    => InnerBencodeBuf::Bytes(slice_base(n), buffer).into(),
+        BencodeRefKind::List(list)  => {
+            let buf_list = (0..list.len())
+                .map(|index| from_ref(base, list.get(index).unwrap()))
+                .collect();
+
+            InnerBencodeBuf::List(buf_list, buffer).into()
+        },
+        BencodeRefKind::Dict(dict)  => {
+            let buf_dict = dict.to_list().into_iter()
+                .map(|(key, value)| (slice_base(*key), from_ref(base, value)))
+                .collect();
+
+            InnerBencodeBuf::Dict(buf_dict, buffer).into()
+        }
+    }
+}
+
+impl<'a> From<BencodeRef<'a>> for BencodeBuf {
+    /// Copies the buffer referenced by the `BencodeRef` once, and shares it between all `BencodeBuf`s.
+    fn from(bencode: BencodeRef<'a>) -> BencodeBuf {
+        let base = Bytes::from(bencode.buffer());
+        let bencode_ref = BencodeRef::decode(&base[..], BDecodeOpt::new(usize::MAX, false, true))
+            .expect("bip_bencode: Failed To Decode Already Decoded BencodeRef");
+
+        from_ref(&base, &bencode_ref)
+    }
+}
+
+impl<'a, 'b> From<&'b BencodeMut<'a>> for BencodeBuf {
+    /// Encodes the `BencodeMut` into a buffer which is shared between all `BencodeBuf`s.
+    fn from(bencode: &'b BencodeMut<'a>) -> BencodeBuf {
+        BencodeBuf::decode(bencode.encode().into(), BDecodeOpt::new(usize::MAX, false, true))
+            .expect("bip_bencode: Failed To Decode Encoded BencodeMut")
+    }
+}
+
+impl BRefAccess for BencodeBuf {
+    type BKey  = Bytes;
+    type BType = BencodeBuf;
+
+    fn kind<'b>(&'b self) -> BencodeRefKind<'b, Bytes, BencodeBuf> {
+        match self.inner {
+            InnerBencodeBuf::Int(n, _)       => BencodeRefKind::Int(n),
+            InnerBencodeBuf::Bytes(ref n, _) => BencodeRefKind::Bytes(n),
+            InnerBencodeBuf::List(ref n, _)  => BencodeRefKind::List(n),
+            InnerBencodeBuf::Dict(ref n, _)  => BencodeRefKind::Dict(n),
+        }
+    }
+
+    fn str(&self) -> Option<&str> {
+        match self.bytes() {
+            Some(n) => str::from_utf8(n).ok(),
+            None    => None
+        }
+    }
+
+    fn int(&self) -> Option<i64> {
+        match self.inner {
+            InnerBencodeBuf::Int(n, _) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn bytes(&self) -> Option<&[u8]> {
+        match self.inner {
+            InnerBencodeBuf::Bytes(ref n, _) => Some(&n[..]),
+            _ => None,
+        }
+    }
+
+    fn list(&self) -> Option<&BListAccess<BencodeBuf>> {
+        match self.inner {
+            InnerBencodeBuf::List(ref n, _) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn dict(&self) -> Option<&BDictAccess<Bytes, BencodeBuf>> {
+        match self.inner {
+            InnerBencodeBuf::Dict(ref n, _) => Some(n),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::default::Default;
+
+    use access::bencode::{BRefAccess, BMutAccess};
+    use mutable::bencode_mut::BencodeMut;
+    use owned::bencode_buf::BencodeBuf;
+    use reference::bencode_ref::BencodeRef;
+    use reference::decode_opt::BDecodeOpt;
+
+    use bytes::Bytes;
+
+    #[test]
+    fn positive_dict_nested_list_buffer() {
+        let nested_list_bytes = Bytes::from(&b"d3:asdl3:asdee"[..]);
+        let bencode = BencodeBuf::decode(nested_list_bytes.clone(), BDecodeOpt::default()).unwrap();
+
+        let bencode_dict = bencode.dict().unwrap();
+        let bencode_list = bencode_dict.lookup(&b"asd"[..]).unwrap();
+
+        assert_eq!(&b"l3:asde"[..], &bencode_list.buffer()[..]);
+        assert_eq!(&nested_list_bytes[..], &bencode.buffer()[..]);
+    }
+
+    #[test]
+    fn positive_convert_ref_round_trip() {
+        let dict_bytes = b"d3:asdli5e3:asdee";
+        let bencode_ref = BencodeRef::decode(&dict_bytes[..], BDecodeOpt::default()).unwrap();
+
+        let bencode = BencodeBuf::from(bencode_ref.clone());
+
+        assert_eq!(bencode_ref, bencode.to_ref());
+    }
+
+    #[test]
+    fn positive_convert_mut_round_trip() {
+        let mut bencode_list = BencodeMut::new_list();
+        bencode_list.list_mut().unwrap().push(BencodeMut::new_int(5));
+        bencode_list.list_mut().unwrap().push(BencodeMut::new_bytes((&b"asd"[..]).into()));
+
+        let mut bencode_mut = BencodeMut::new_dict();
+        bencode_mut.dict_mut().unwrap().insert((&b"asd"[..]).into(), bencode_list);
+
+        let bencode = BencodeBuf::from(&bencode_mut);
+
+        assert_eq!(&b"d3:asdli5e3:asdee"[..], &bencode.buffer()[..]);
+        assert_eq!(bencode_mut, bencode.to_mut());
+    }
+
+    #[test]
+    fn positive_owned_static() {
+        fn is_static<T: 'static + Send>(_: &T) {}
+
+        let bencode = BencodeBuf::decode(Bytes::from(&b"i5e"[..]), BDecodeOpt::default()).unwrap();
+
+        is_static(&bencode);
+        assert_eq!(Some(5), bencode.int());
+    }
+}
@@ -3,6 +3,7 @@ use std::collections::BTreeMap;
 use std::str;
 
 use access::bencode::{BRefAccess, BencodeRefKind};
+use mutable::encode;
 use reference::decode;
 use reference::decode_opt::BDecodeOpt;
 use access::dict::BDictAccess;
@@ -44,9 +45,38 @@ impl<'a> BencodeRef<'a> {
             return Err(BencodeParseError::from_kind(BencodeParseErrorKind::BytesEmpty{ pos: end_pos }));
         }
 
+        if opts.check_canonical() {
+            let canonical = bencode.encode_canonical();
+            let buffer = bencode.buffer();
+
+            // Report the first byte that differs from the canonical encoding
+            let opt_diff_pos = buffer.iter().zip(canonical.iter()).position(|(a, b)| a != b);
+            match (opt_diff_pos, buffer.len() == canonical.len()) {
+                (None, true)    => (),
+                (Some(pos), _)  => return Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidNonCanonical{ pos: pos })),
+                (None, false)   => {
+                    let pos = ::std::cmp::min(buffer.len(), canonical.len());
+
+                    return Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidNonCanonical{ pos: pos }))
+                }
+            }
+        }
+
         Ok(bencode)
     }
 
+    /// Re-encode the current bencode in its canonical form.
+    ///
+    /// The canonical form has sorted dictionary keys and minimal integer representations,
+    /// so two bencodes with the same logical value will always re-encode to the same bytes.
+    pub fn encode_canonical(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        encode::encode(self, &mut bytes);
+
+        bytes
+    }
+
     /// Get a byte slice of the current bencode byte representation.
     pub fn buffer(&self) -> &'a [u8] {
         match self.inner {
@@ -257,4 +287,42 @@ mod tests {
         let dict_bytes = b"d3:asd3:asde";
         assert_eq!(dict_bytes, bencode_dict.buffer());
     }
+
+    #[test]
+    fn positive_encode_canonical_sorts_keys() {
+        let dict_bytes = b"d1:bi1e1:ai2ee";
+        let bencode = BencodeRef::decode(&dict_bytes[..], BDecodeOpt::default()).unwrap();
+
+        assert_eq!(&b"d1:ai2e1:bi1ee"[..], &bencode.encode_canonical()[..]);
+    }
+
+    #[test]
+    fn positive_strict_canonical_dict() {
+        let dict_bytes = b"d1:ai2e1:bl3:asdee";
+
+        BencodeRef::decode(&dict_bytes[..], BDecodeOpt::strict()).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_strict_unsorted_keys() {
+        let dict_bytes = b"d1:bi1e1:ai2ee";
+
+        BencodeRef::decode(&dict_bytes[..], BDecodeOpt::strict()).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_strict_plus_sign_int() {
+        let dict_bytes = b"d1:ai+2ee";
+
+        BencodeRef::decode(&dict_bytes[..], BDecodeOpt::strict()).unwrap();
+    }
+
+    #[test]
+    fn positive_default_plus_sign_int() {
+        let dict_bytes = b"d1:ai+2ee";
+
+        BencodeRef::decode(&dict_bytes[..], BDecodeOpt::default()).unwrap();
+    }
 }
\ No newline at end of file
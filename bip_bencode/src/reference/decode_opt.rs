@@ -3,20 +3,32 @@ use std::default::Default;
 const DEFAULT_MAX_RECURSION:       usize = 50;
 const DEFAULT_CHECK_KEY_SORT:      bool = false;
 const DEFAULT_ENFORCE_FULL_DECODE: bool = true;
+const DEFAULT_CHECK_CANONICAL:     bool = false;
 
 /// Stores decoding options for modifying decode behavior.
 #[derive(Copy, Clone)]
 pub struct BDecodeOpt {
     max_recursion:       usize,
     check_key_sort:      bool,
-    enforce_full_decode: bool
+    enforce_full_decode: bool,
+    check_canonical:     bool
 }
 
 impl BDecodeOpt {
     /// Create a new `BDecodeOpt` object.
     pub fn new(max_recursion: usize, check_key_sort: bool, enforce_full_decode: bool) -> BDecodeOpt {
         BDecodeOpt{ max_recursion: max_recursion, check_key_sort: check_key_sort,
-                    enforce_full_decode: enforce_full_decode }
+                    enforce_full_decode: enforce_full_decode, check_canonical: DEFAULT_CHECK_CANONICAL }
+    }
+
+    /// Create a new strict `BDecodeOpt` object.
+    ///
+    /// Strict decoding enforces a full decode, sorted dictionary keys, and that the input
+    /// bytes are exactly the canonical encoding of the decoded bencode. This should be used
+    /// whenever a hash will be taken over the raw bytes of untrusted bencode.
+    pub fn strict() -> BDecodeOpt {
+        BDecodeOpt{ max_recursion: DEFAULT_MAX_RECURSION, check_key_sort: true,
+                    enforce_full_decode: true, check_canonical: true }
     }
 
     /// Maximum limit allowed when decoding bencode.
@@ -38,6 +50,11 @@ impl BDecodeOpt {
     pub fn enforce_full_decode(&self) -> bool {
         self.enforce_full_decode
     }
+
+    /// Whether or not an error should be thrown if the decoded bytes are not in canonical form.
+    pub fn check_canonical(&self) -> bool {
+        self.check_canonical
+    }
 }
 
 impl Default for BDecodeOpt {
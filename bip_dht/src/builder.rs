@@ -1,15 +1,31 @@
+use std::cmp;
 use std::collections::HashSet;
 use std::io;
 use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
 use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
 
 use bip_handshake::Handshaker;
 use bip_util::bt::InfoHash;
+use bip_util::metrics::{self, Metrics};
 use bip_util::net;
+use futures::sync::oneshot;
 use mio::Sender;
 
+use item::{Item, ItemKey};
+use message::want::Want;
 use router::Router;
+use storage::{self, AnnounceStats};
+use token;
 use worker::{self, OneshotTask, DhtEvent, ShutdownCause};
+use worker::lookup::{LookupConfig, LookupOptions};
+use worker::refresh::{self, TableConfig};
+use worker::trace::LookupTrace;
+
+const DEFAULT_QUERY_RATE: usize = 250;
+const DEFAULT_INBOUND_QUERY_RATE: usize = 10;
+const DEFAULT_MAX_THROTTLED_ADDRS: usize = 4096;
 
 /// Maintains a Distributed Hash (Routing) Table.
 pub struct MainlineDht {
@@ -30,6 +46,16 @@ impl MainlineDht {
         let send = try!(worker::start_mainline_dht(send_sock,
                                                    recv_sock,
                                                    builder.read_only,
+                                                   builder.want,
+                                                   builder.lookup_config,
+                                                   builder.table_config,
+                                                   builder.query_rate,
+                                                   builder.inbound_query_rate,
+                                                   builder.inbound_query_burst.unwrap_or(builder.inbound_query_rate),
+                                                   builder.max_throttled_addrs,
+                                                   builder.max_announces,
+                                                   builder.token_refresh_interval,
+                                                   builder.metrics,
                                                    builder.ext_addr,
                                                    handshaker,
                                                    kill_sock,
@@ -54,11 +80,95 @@ impl MainlineDht {
     /// If the initial bootstrap has not finished, the search will be queued and executed once
     /// the bootstrap has completed.
     pub fn search(&self, hash: InfoHash, announce: bool) {
-        if self.send.send(OneshotTask::StartLookup(hash, announce)).is_err() {
+        if self.send.send(OneshotTask::StartLookup(hash, LookupOptions{ announce: announce, ..Default::default() }, None)).is_err() {
             warn!("bip_dht: MainlineDht failed to send a start lookup message...");
         }
     }
 
+    /// Perform a search for the given InfoHash as a seed, with an optional announce on the closest nodes.
+    ///
+    /// Nodes will be asked to only give us leechers, and any announce will mark us as a seed so that
+    /// nodes can balance the seeds and leechers given to others (BEP 33). This should be used to
+    /// re-announce once a torrent has completed.
+    pub fn search_as_seed(&self, hash: InfoHash, announce: bool) {
+        if self.send.send(OneshotTask::StartLookup(hash, LookupOptions{ announce: announce, seed: true, ..Default::default() }, None)).is_err() {
+            warn!("bip_dht: MainlineDht failed to send a start seed lookup message...");
+        }
+    }
+
+    /// Perform a search for the given InfoHash, estimating the size of the swarm without announcing (BEP 33).
+    ///
+    /// Nodes will be asked for bloom filters of the seeds and leechers they have stored, which are
+    /// merged together and surfaced as a DhtEvent::ScrapeEstimate once the search has completed.
+    /// Any contacts found are still given to the Handshaker, as with a regular search.
+    pub fn scrape(&self, hash: InfoHash) {
+        if self.send.send(OneshotTask::StartLookup(hash, LookupOptions{ scrape: true, ..Default::default() }, None)).is_err() {
+            warn!("bip_dht: MainlineDht failed to send a start scrape lookup message...");
+        }
+    }
+
+    /// Perform a search for the given InfoHash, recording a trace of the search.
+    ///
+    /// The trace contains the nodes queried in order, their distances from the InfoHash,
+    /// their response times, and whether or not each response moved the search closer to
+    /// the InfoHash. The returned Receiver will resolve once the search has completed.
+    ///
+    /// This is useful for debugging searches that are not finding any contacts.
+    pub fn search_traced(&self, hash: InfoHash, announce: bool) -> oneshot::Receiver<LookupTrace> {
+        let (send, recv) = oneshot::channel();
+
+        if self.send.send(OneshotTask::StartLookup(hash, LookupOptions{ announce: announce, ..Default::default() }, Some(send))).is_err() {
+            warn!("bip_dht: MainlineDht failed to send a start traced lookup message...");
+        }
+
+        recv
+    }
+
+    /// Retrieve the most recent item stored under the given key (BEP 44).
+    ///
+    /// The returned Receiver will resolve to None if no node had a valid item stored.
+    ///
+    /// If the initial bootstrap has not finished, the lookup will be queued and executed once
+    /// the bootstrap has completed.
+    pub fn get_item(&self, key: ItemKey) -> oneshot::Receiver<Option<Item>> {
+        let (send, recv) = oneshot::channel();
+
+        if self.send.send(OneshotTask::StartGetItem(key, send)).is_err() {
+            warn!("bip_dht: MainlineDht failed to send a start get item message...");
+        }
+
+        recv
+    }
+
+    /// Store the given item on the nodes closest to its target (BEP 44).
+    ///
+    /// The cas (compare and swap) value, if given, is the sequence number that a mutable
+    /// item must currently have on a node for it to be overwritten. The returned Receiver
+    /// will resolve to the number of nodes that accepted the item.
+    ///
+    /// If the initial bootstrap has not finished, the put will be queued and executed once
+    /// the bootstrap has completed.
+    pub fn put_item(&self, item: Item, cas: Option<i64>) -> oneshot::Receiver<usize> {
+        let (send, recv) = oneshot::channel();
+
+        if self.send.send(OneshotTask::StartPutItem(item, cas, send)).is_err() {
+            warn!("bip_dht: MainlineDht failed to send a start put item message...");
+        }
+
+        recv
+    }
+
+    /// Retrieve the number of seeds and leechers that have announced themselves to us (BEP 33).
+    pub fn announce_stats(&self) -> oneshot::Receiver<AnnounceStats> {
+        let (send, recv) = oneshot::channel();
+
+        if self.send.send(OneshotTask::AnnounceStats(send)).is_err() {
+            warn!("bip_dht: MainlineDht failed to send an announce stats message...");
+        }
+
+        recv
+    }
+
     /// An event Receiver which will receive events occuring within the DHT.
     ///
     /// It is important to at least monitor the DHT for shutdown events as any calls
@@ -96,6 +206,16 @@ pub struct DhtBuilder {
     read_only: bool,
     src_addr: SocketAddr,
     ext_addr: Option<SocketAddr>,
+    want: Option<Want>,
+    lookup_config: LookupConfig,
+    table_config: TableConfig,
+    query_rate: usize,
+    inbound_query_rate: usize,
+    inbound_query_burst: Option<usize>,
+    max_throttled_addrs: usize,
+    max_announces: usize,
+    token_refresh_interval: Duration,
+    metrics: Arc<Metrics>,
 }
 
 impl DhtBuilder {
@@ -110,6 +230,16 @@ impl DhtBuilder {
             read_only: true,
             src_addr: net::default_route_v4(),
             ext_addr: None,
+            want: None,
+            lookup_config: LookupConfig::default(),
+            table_config: TableConfig::default(),
+            query_rate: DEFAULT_QUERY_RATE,
+            inbound_query_rate: DEFAULT_INBOUND_QUERY_RATE,
+            inbound_query_burst: None,
+            max_throttled_addrs: DEFAULT_MAX_THROTTLED_ADDRS,
+            max_announces: storage::MAX_ITEMS_STORED,
+            token_refresh_interval: Duration::from_secs(token::DEFAULT_REFRESH_INTERVAL_SECS as u64),
+            metrics: metrics::noop(),
         }
     }
 
@@ -169,6 +299,169 @@ impl DhtBuilder {
         self
     }
 
+    /// Set the address families of nodes we ask for in find_node and get_peers requests (BEP 32).
+    ///
+    /// If this is not supplied, the want argument is left out, and remote nodes will give us
+    /// nodes of the same family that our request was sent over.
+    pub fn set_want(mut self, want: Want) -> DhtBuilder {
+        self.want = Some(want);
+
+        self
+    }
+
+    /// Set the number of nodes we request from in parallel when starting a lookup.
+    ///
+    /// Subsequent rounds of the lookup request from one less node. Default value is 4.
+    pub fn set_lookup_alpha(mut self, alpha: usize) -> DhtBuilder {
+        self.lookup_config.alpha = cmp::max(alpha, 1);
+
+        self
+    }
+
+    /// Set the number of closest nodes a lookup starts with, and announces to.
+    ///
+    /// Default value is 8.
+    pub fn set_lookup_k(mut self, k: usize) -> DhtBuilder {
+        self.lookup_config.k = cmp::max(k, 1);
+
+        self
+    }
+
+    /// Set the timeout for lookup queries sent to nodes we have no round trip time history for.
+    ///
+    /// Queries to nodes we have history for use a timeout based on their round trip time.
+    /// Default value is 1.5 seconds.
+    pub fn set_lookup_query_timeout(mut self, timeout: Duration) -> DhtBuilder {
+        self.lookup_config.query_timeout = timeout;
+
+        self
+    }
+
+    /// Set the maximum duration of a lookup.
+    ///
+    /// Once exceeded, the lookup will stop sending requests and finish. By default, there is no maximum.
+    pub fn set_max_lookup_duration(mut self, duration: Duration) -> DhtBuilder {
+        self.lookup_config.max_duration = Some(duration);
+
+        self
+    }
+
+    /// Set whether our announces ask nodes to use the source port of the announce.
+    ///
+    /// Useful when behind a NAT that only maps the external port of our UDP socket, and our
+    /// handshaker accepts peers on that same port. Announces will always
+    /// do this if our handshaker reports a port of 0. Nodes always honor this for announces
+    /// sent to us. Default value is false.
+    pub fn set_implied_port(mut self, implied_port: bool) -> DhtBuilder {
+        self.lookup_config.implied_port = implied_port;
+
+        self
+    }
+
+    /// Set the interval between routing table refreshes.
+    ///
+    /// Each refresh pings a questionable node in the next bucket of the routing table.
+    /// Values are clamped between 100 milliseconds and 1 hour. Default value is 6 seconds.
+    pub fn set_refresh_interval(mut self, interval: Duration) -> DhtBuilder {
+        let min_interval = Duration::from_millis(refresh::MIN_REFRESH_INTERVAL_MILLIS);
+        let max_interval = Duration::from_millis(refresh::MAX_REFRESH_INTERVAL_MILLIS);
+
+        self.table_config.refresh_interval = cmp::min(cmp::max(interval, min_interval), max_interval);
+
+        self
+    }
+
+    /// Set how long a node can go without responding to or requesting from us before it
+    /// becomes questionable.
+    ///
+    /// Default value is 15 minutes (BEP 5).
+    pub fn set_questionable_timeout(mut self, timeout: Duration) -> DhtBuilder {
+        self.table_config.questionable_after = timeout;
+
+        self
+    }
+
+    /// Set the number of unanswered pings before a questionable node becomes bad, and can
+    /// be replaced in the routing table.
+    ///
+    /// Values below 1 are treated as 1. Default value is 2.
+    pub fn set_max_ping_retries(mut self, retries: usize) -> DhtBuilder {
+        self.table_config.max_ping_retries = cmp::max(retries, 1);
+
+        self
+    }
+
+    /// Set the maximum number of queries per second we will send to remote nodes.
+    ///
+    /// Queries over this rate are delayed, not dropped. A rate of zero disables
+    /// the limit. Default value is 250.
+    pub fn set_query_rate(mut self, queries_per_sec: usize) -> DhtBuilder {
+        self.query_rate = queries_per_sec;
+
+        self
+    }
+
+    /// Set the maximum number of queries per second we will process from any single
+    /// remote address.
+    ///
+    /// Queries over this rate are dropped, which keeps our node from being used to
+    /// reflect traffic at other hosts. A rate of zero disables the limit. Default value is 10.
+    pub fn set_inbound_query_rate(mut self, queries_per_sec: usize) -> DhtBuilder {
+        self.inbound_query_rate = queries_per_sec;
+
+        self
+    }
+
+    /// Set the maximum number of queries we will process from any single remote address
+    /// in a burst, after the address has been idle.
+    ///
+    /// Default value is the inbound query rate (one second worth of queries).
+    pub fn set_inbound_query_burst(mut self, max_queries: usize) -> DhtBuilder {
+        self.inbound_query_burst = Some(max_queries);
+
+        self
+    }
+
+    /// Set the maximum number of remote addresses we will track inbound query rates for.
+    ///
+    /// Once this many addresses are being tracked, and none of them are idle, queries from
+    /// new addresses are dropped. Default value is 4096.
+    pub fn set_max_throttled_addrs(mut self, max_addrs: usize) -> DhtBuilder {
+        self.max_throttled_addrs = max_addrs;
+
+        self
+    }
+
+    /// Set the maximum number of peer announces we will store for other nodes.
+    ///
+    /// Announces received while the storage is full are rejected. Default value is 500.
+    pub fn set_max_announces(mut self, max_announces: usize) -> DhtBuilder {
+        self.max_announces = max_announces;
+
+        self
+    }
+
+    /// Set how often the secret used to issue announce tokens is rotated.
+    ///
+    /// Tokens issued under the current or the previous secret are accepted, so a token is
+    /// valid for between one and two intervals. The interval is clamped to between 5 and 10
+    /// minutes (BEP 5). Default value is 5 minutes.
+    pub fn set_token_refresh_interval(mut self, interval: Duration) -> DhtBuilder {
+        self.token_refresh_interval = interval;
+
+        self
+    }
+
+    /// Provide the DHT with metrics to report queries to.
+    ///
+    /// Reports `bip_dht_queries_sent`, `bip_dht_queries_received`, and `bip_dht_queries_throttled`
+    /// counters. By default, all metrics are discarded.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> DhtBuilder {
+        self.metrics = metrics;
+
+        self
+    }
+
     /// Provide the DHT with the source address.
     ///
     /// If this is not supplied we will use the OS default route.
@@ -185,3 +478,73 @@ impl DhtBuilder {
         MainlineDht::with_builder(self, handshaker)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bip_util::bt::{self, InfoHash};
+    use mio::{EventLoop, Handler};
+
+    use builder::MainlineDht;
+    use worker::OneshotTask;
+    use worker::lookup::LookupOptions;
+
+    /// Handler that stops the event loop once it has received a task.
+    struct TaskReceiver {
+        opt_task: Option<OneshotTask>,
+    }
+
+    impl Handler for TaskReceiver {
+        type Timeout = ();
+        type Message = OneshotTask;
+
+        fn notify(&mut self, event_loop: &mut EventLoop<TaskReceiver>, task: OneshotTask) {
+            self.opt_task = Some(task);
+
+            event_loop.shutdown();
+        }
+    }
+
+    fn sent_task<F>(search: F) -> OneshotTask
+        where F: FnOnce(&MainlineDht)
+    {
+        let mut event_loop = EventLoop::new().unwrap();
+        let dht = MainlineDht { send: event_loop.channel() };
+
+        search(&dht);
+
+        let mut receiver = TaskReceiver { opt_task: None };
+        event_loop.run(&mut receiver).unwrap();
+
+        receiver.opt_task.unwrap()
+    }
+
+    #[test]
+    fn positive_search_as_seed_starts_seed_lookup() {
+        let hash: InfoHash = [1u8; bt::INFO_HASH_LEN].into();
+
+        match sent_task(|dht| dht.search_as_seed(hash, true)) {
+            OneshotTask::StartLookup(lookup_hash, LookupOptions{ announce: true, seed: true, scrape: false }, None) => assert_eq!(hash, lookup_hash),
+            _ => panic!("Expected An Announcing Seed Lookup"),
+        }
+    }
+
+    #[test]
+    fn positive_search_as_seed_without_announce() {
+        let hash: InfoHash = [1u8; bt::INFO_HASH_LEN].into();
+
+        match sent_task(|dht| dht.search_as_seed(hash, false)) {
+            OneshotTask::StartLookup(lookup_hash, LookupOptions{ announce: false, seed: true, scrape: false }, None) => assert_eq!(hash, lookup_hash),
+            _ => panic!("Expected A Non Announcing Seed Lookup"),
+        }
+    }
+
+    #[test]
+    fn negative_search_is_not_seed_lookup() {
+        let hash: InfoHash = [1u8; bt::INFO_HASH_LEN].into();
+
+        match sent_task(|dht| dht.search(hash, true)) {
+            OneshotTask::StartLookup(lookup_hash, LookupOptions{ announce: true, seed: false, scrape: false }, None) => assert_eq!(hash, lookup_hash),
+            _ => panic!("Expected An Announcing Leecher Lookup"),
+        }
+    }
+}
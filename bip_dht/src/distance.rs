@@ -0,0 +1,122 @@
+//! Kademlia distance metric and routing table math used by the dht.
+
+use std::cmp::Ordering;
+
+use bip_util::bt::NodeId;
+use bip_util::sha::{self, ShaHash, XorRep};
+use rand;
+
+/// Maximum number of buckets in a routing table (one per bit of a `NodeId`).
+pub const MAX_BUCKETS: usize = sha::SHA_HASH_LEN * 8;
+
+/// XOR distance between two node ids.
+pub fn xor_distance(lhs: NodeId, rhs: NodeId) -> ShaHash {
+    lhs ^ rhs
+}
+
+/// Compare the distances of two node ids from the given target.
+///
+/// Returns `Ordering::Less` if `lhs` is closer to the target than `rhs`.
+pub fn cmp_distance(target: NodeId, lhs: NodeId, rhs: NodeId) -> Ordering {
+    xor_distance(target, lhs).cmp(&xor_distance(target, rhs))
+}
+
+/// Number of leading bits that are identical between the local and remote node ids.
+pub fn leading_bit_count(local_node: NodeId, remote_node: NodeId) -> usize {
+    let diff_id = xor_distance(local_node, remote_node);
+
+    diff_id.bits().take_while(|&x| x == XorRep::Same).count()
+}
+
+/// Index of the bucket that the remote node would be placed in, for a routing table with
+/// the given local node id and the given number of buckets.
+///
+/// Our node id always falls within the last bucket, so nodes sharing at least as many bits
+/// with us as there are buckets will fall in the last bucket.
+pub fn bucket_index(local_node: NodeId, remote_node: NodeId, num_buckets: usize) -> usize {
+    bucket_placement(leading_bit_count(local_node, remote_node), num_buckets)
+}
+
+/// Take the number of leading bits that are the same between our node and the remote
+/// node and calculate a bucket index for that node id.
+pub fn bucket_placement(num_same_bits: usize, num_buckets: usize) -> usize {
+    // The index that the node should be placed in *eventually*, meaning
+    // when we create enough buckets for that bucket to appear.
+    let ideal_index = num_same_bits;
+
+    if ideal_index >= num_buckets {
+        num_buckets - 1
+    } else {
+        ideal_index
+    }
+}
+
+/// Generate a node id that would fall in the bucket at the given index for the local node id.
+///
+/// Panics if the index is out of bounds.
+pub fn bucket_node_id(local_node: NodeId, bucket_index: usize) -> NodeId {
+    local_node.flip_bit(bucket_index)
+}
+
+/// Generates a random NodeId.
+pub fn random_node_id() -> NodeId {
+    let mut random_sha_hash = [0u8; sha::SHA_HASH_LEN];
+
+    for byte in random_sha_hash.iter_mut() {
+        *byte = rand::random::<u8>();
+    }
+
+    ShaHash::from(random_sha_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use bip_util::bt::{self, NodeId};
+
+    #[test]
+    fn positive_xor_distance_self() {
+        let node_id: NodeId = [5u8; bt::NODE_ID_LEN].into();
+
+        assert_eq!(super::xor_distance(node_id, node_id), [0u8; bt::NODE_ID_LEN].into());
+    }
+
+    #[test]
+    fn positive_cmp_distance() {
+        let target: NodeId = [0u8; bt::NODE_ID_LEN].into();
+        let close: NodeId = [1u8; bt::NODE_ID_LEN].into();
+        let far: NodeId = [2u8; bt::NODE_ID_LEN].into();
+
+        assert_eq!(super::cmp_distance(target, close, far), Ordering::Less);
+        assert_eq!(super::cmp_distance(target, far, close), Ordering::Greater);
+        assert_eq!(super::cmp_distance(target, far, far), Ordering::Equal);
+    }
+
+    #[test]
+    fn positive_leading_bit_count_equal_ids() {
+        let node_id: NodeId = [5u8; bt::NODE_ID_LEN].into();
+
+        assert_eq!(super::leading_bit_count(node_id, node_id), super::MAX_BUCKETS);
+    }
+
+    #[test]
+    fn positive_bucket_node_id_in_bucket() {
+        let node_id: NodeId = [5u8; bt::NODE_ID_LEN].into();
+
+        for index in 0..super::MAX_BUCKETS {
+            let bucket_node_id = super::bucket_node_id(node_id, index);
+
+            assert_eq!(super::leading_bit_count(node_id, bucket_node_id), index);
+            assert_eq!(super::bucket_index(node_id, bucket_node_id, super::MAX_BUCKETS), index);
+        }
+    }
+
+    #[test]
+    fn positive_bucket_index_capped_at_last_bucket() {
+        let node_id: NodeId = [5u8; bt::NODE_ID_LEN].into();
+        let bucket_node_id = super::bucket_node_id(node_id, 10);
+
+        assert_eq!(super::bucket_index(node_id, bucket_node_id, 4), 3);
+    }
+}
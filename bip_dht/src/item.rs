@@ -0,0 +1,271 @@
+//! Arbitrary data items that can be stored in the DHT (BEP 44).
+
+use bip_bencode::Bencode;
+use bip_util::sha::{ShaHash, ShaHashBuilder};
+use crypto::ed25519;
+
+/// Maximum length of the bencoded value of an item.
+pub const MAX_VALUE_LEN: usize = 1000;
+
+/// Maximum length of the salt of a mutable item.
+pub const MAX_SALT_LEN: usize = 64;
+
+/// Length of an ed25519 public key.
+const PUBLIC_KEY_LEN: usize = 32;
+
+/// Length of an ed25519 signature.
+const SIGNATURE_LEN: usize = 64;
+
+/// Length of the seed used to generate an ed25519 key pair.
+const SEED_LEN: usize = 32;
+
+/// Returns true if the given bytes are a bencoded value that can be stored in the DHT.
+fn is_valid_value(value: &[u8]) -> bool {
+    value.len() <= MAX_VALUE_LEN && Bencode::decode(value).is_ok()
+}
+
+/// Build the buffer that is signed for a mutable item.
+fn signature_buffer(salt: &[u8], seq: i64, value: &[u8]) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(salt.len() + value.len() + 32);
+
+    if !salt.is_empty() {
+        buffer.extend_from_slice(format!("4:salt{}:", salt.len()).as_bytes());
+        buffer.extend_from_slice(salt);
+    }
+    buffer.extend_from_slice(format!("3:seqi{}e1:v", seq).as_bytes());
+    buffer.extend_from_slice(value);
+
+    buffer
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Item that can be stored in the DHT.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Item {
+    /// Item whose target is derived from its value.
+    Immutable(ImmutableItem),
+    /// Item whose target is derived from its public key and salt.
+    Mutable(MutableItem),
+}
+
+impl Item {
+    /// Target that the item is stored under.
+    pub fn target(&self) -> ShaHash {
+        match self {
+            &Item::Immutable(ref item) => item.target(),
+            &Item::Mutable(ref item) => item.target(),
+        }
+    }
+
+    /// Bencoded value of the item.
+    pub fn value(&self) -> &[u8] {
+        match self {
+            &Item::Immutable(ref item) => item.value(),
+            &Item::Mutable(ref item) => item.value(),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Item whose target is the SHA-1 hash of its bencoded value.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ImmutableItem {
+    value: Vec<u8>,
+}
+
+impl ImmutableItem {
+    /// Create a new ImmutableItem from the given bencoded value.
+    ///
+    /// Returns None if the value is not valid bencode or is too long.
+    pub fn new(value: Vec<u8>) -> Option<ImmutableItem> {
+        if is_valid_value(&value) {
+            Some(ImmutableItem { value: value })
+        } else {
+            None
+        }
+    }
+
+    /// Target that the item is stored under.
+    pub fn target(&self) -> ShaHash {
+        ShaHash::from_bytes(&self.value)
+    }
+
+    /// Bencoded value of the item.
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Item whose target is the SHA-1 hash of its public key and salt.
+///
+/// Mutable items are signed with an ed25519 key pair so that only the owner
+/// of the key pair can update the value stored under the target.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MutableItem {
+    public_key: Vec<u8>,
+    signature: Vec<u8>,
+    seq: i64,
+    salt: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl MutableItem {
+    /// Create and sign a new MutableItem with the key pair generated from the given seed.
+    ///
+    /// Returns None if the seed, salt, or value are invalid.
+    pub fn sign(seed: &[u8], salt: Vec<u8>, seq: i64, value: Vec<u8>) -> Option<MutableItem> {
+        if seed.len() != SEED_LEN || salt.len() > MAX_SALT_LEN || !is_valid_value(&value) {
+            return None;
+        }
+        let (secret_key, public_key) = ed25519::keypair(seed);
+        let signature = ed25519::signature(&signature_buffer(&salt, seq, &value), &secret_key);
+
+        Some(MutableItem {
+            public_key: public_key.to_vec(),
+            signature: signature.to_vec(),
+            seq: seq,
+            salt: salt,
+            value: value,
+        })
+    }
+
+    /// Create a MutableItem from an existing signature.
+    ///
+    /// Returns None if the signature does not verify or any of the parts are invalid.
+    pub fn from_parts(public_key: &[u8],
+                      signature: &[u8],
+                      salt: &[u8],
+                      seq: i64,
+                      value: &[u8])
+                      -> Option<MutableItem> {
+        if public_key.len() != PUBLIC_KEY_LEN || signature.len() != SIGNATURE_LEN ||
+           salt.len() > MAX_SALT_LEN || !is_valid_value(value) {
+            return None;
+        }
+
+        if ed25519::verify(&signature_buffer(salt, seq, value), public_key, signature) {
+            Some(MutableItem {
+                public_key: public_key.to_vec(),
+                signature: signature.to_vec(),
+                seq: seq,
+                salt: salt.to_vec(),
+                value: value.to_vec(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Target that the item is stored under.
+    pub fn target(&self) -> ShaHash {
+        mutable_target(&self.public_key, &self.salt)
+    }
+
+    /// Public key that signed the item.
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// Signature of the item.
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    /// Sequence number of the item.
+    pub fn seq(&self) -> i64 {
+        self.seq
+    }
+
+    /// Salt of the item (may be empty).
+    pub fn salt(&self) -> &[u8] {
+        &self.salt
+    }
+
+    /// Bencoded value of the item.
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+/// Target of a mutable item with the given public key and salt.
+fn mutable_target(public_key: &[u8], salt: &[u8]) -> ShaHash {
+    ShaHashBuilder::new().add_bytes(public_key).add_bytes(salt).build()
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Key used to retrieve an item from the DHT.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ItemKey {
+    /// Key for an immutable item with the given target.
+    Immutable(ShaHash),
+    /// Key for a mutable item with the given public key and salt.
+    Mutable(Vec<u8>, Vec<u8>),
+}
+
+impl ItemKey {
+    /// Target that the item is stored under.
+    pub fn target(&self) -> ShaHash {
+        match self {
+            &ItemKey::Immutable(target) => target,
+            &ItemKey::Mutable(ref public_key, ref salt) => mutable_target(public_key, salt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bip_util::sha::ShaHash;
+
+    use super::{ImmutableItem, MutableItem, ItemKey};
+
+    #[test]
+    fn positive_immutable_target() {
+        let item = ImmutableItem::new(b"12:Hello World!".to_vec()).unwrap();
+
+        assert_eq!(item.target(), ShaHash::from_bytes(b"12:Hello World!"));
+    }
+
+    #[test]
+    fn negative_immutable_invalid_bencode() {
+        assert!(ImmutableItem::new(b"12:Hello".to_vec()).is_none());
+    }
+
+    #[test]
+    fn positive_mutable_sign_and_verify() {
+        let item = MutableItem::sign(&[1u8; 32], b"foobar".to_vec(), 1, b"12:Hello World!".to_vec())
+            .unwrap();
+
+        let parsed = MutableItem::from_parts(item.public_key(),
+                                             item.signature(),
+                                             item.salt(),
+                                             item.seq(),
+                                             item.value());
+        assert_eq!(parsed, Some(item));
+    }
+
+    #[test]
+    fn negative_mutable_tampered_seq() {
+        let item = MutableItem::sign(&[1u8; 32], Vec::new(), 1, b"12:Hello World!".to_vec())
+            .unwrap();
+
+        assert!(MutableItem::from_parts(item.public_key(),
+                                        item.signature(),
+                                        item.salt(),
+                                        2,
+                                        item.value())
+            .is_none());
+    }
+
+    #[test]
+    fn positive_mutable_key_target() {
+        let item = MutableItem::sign(&[1u8; 32], b"foobar".to_vec(), 1, b"i5e".to_vec()).unwrap();
+        let key = ItemKey::Mutable(item.public_key().to_vec(), b"foobar".to_vec());
+
+        assert_eq!(key.target(), item.target());
+    }
+}
@@ -11,6 +11,8 @@ extern crate log;
 extern crate mio;
 extern crate rand;
 extern crate chrono;
+extern crate crypto;
+extern crate futures;
 #[macro_use]
 extern crate error_chain;
 
@@ -29,19 +31,28 @@ extern crate error_chain;
 // const VUZE_DHT: (&'static str, u16) = ("dht.aelitis.com", 6881);
 
 mod builder;
+pub mod distance;
 mod error;
+mod item;
 pub mod message;
 mod router;
 mod security;
 mod storage;
 mod routing;
+mod scrape;
 mod token;
 mod transaction;
 mod worker;
 
 pub use builder::{DhtBuilder, MainlineDht};
+pub use item::{Item, ImmutableItem, MutableItem, ItemKey};
+pub use message::want::Want;
 pub use router::Router;
+pub use scrape::ScrapeEstimate;
+pub use storage::AnnounceStats;
 pub use worker::{DhtEvent, ShutdownCause};
+pub use worker::lookup::LookupStats;
+pub use worker::trace::{LookupTrace, TraceEntry, TraceEvent, TraceNode, TraceRound};
 
 pub use bip_handshake::Handshaker;
 /// Test
@@ -1,6 +1,8 @@
 // TODO: Remove this when announces are implemented
 #![allow(unused)]
 
+use std::collections::BTreeMap;
+
 use bip_bencode::{Bencode, BencodeConvert, Dictionary};
 use bip_util::bt::{NodeId, InfoHash};
 
@@ -10,6 +12,7 @@ use error::DhtResult;
 
 const PORT_KEY: &'static str = "port";
 const IMPLIED_PORT_KEY: &'static str = "implied_port";
+const SEED_KEY: &'static str = "seed";
 
 // TODO: Integrate the Token type into the request message.
 
@@ -26,6 +29,7 @@ pub struct AnnouncePeerRequest<'a> {
     info_hash: InfoHash,
     token: &'a [u8],
     port: ConnectPort,
+    seed: bool,
 }
 
 impl<'a> AnnouncePeerRequest<'a> {
@@ -41,9 +45,17 @@ impl<'a> AnnouncePeerRequest<'a> {
             info_hash: info_hash,
             token: token,
             port: port,
+            seed: false,
         }
     }
 
+    /// Set whether or not the announcing peer is a seed (BEP 33).
+    pub fn with_seed(mut self, seed: bool) -> AnnouncePeerRequest<'a> {
+        self.seed = seed;
+
+        self
+    }
+
     pub fn from_parts(rqst_root: &Dictionary<'a, Bencode<'a>>,
                       trans_id: &'a [u8])
                       -> DhtResult<AnnouncePeerRequest<'a>> {
@@ -71,7 +83,13 @@ impl<'a> AnnouncePeerRequest<'a> {
             }
         };
 
-        Ok(AnnouncePeerRequest::new(trans_id, node_id, info_hash, token, response_port))
+        // Some clients send a seed flag so that get_peers responses can be balanced (BEP 33)
+        let seed = match rqst_root.lookup(SEED_KEY.as_bytes()).map(|n| n.int()) {
+            Some(Some(n)) => n != 0,
+            _ => false,
+        };
+
+        Ok(AnnouncePeerRequest::new(trans_id, node_id, info_hash, token, response_port).with_seed(seed))
     }
 
     pub fn transaction_id(&self) -> &'a [u8] {
@@ -94,6 +112,10 @@ impl<'a> AnnouncePeerRequest<'a> {
         self.port
     }
 
+    pub fn seed(&self) -> bool {
+        self.seed
+    }
+
     pub fn encode(&self) -> Vec<u8> {
         // In case a client errors out when the port key is not present, even when
         // implied port is specified, we will provide a dummy value in that case.
@@ -102,18 +124,25 @@ impl<'a> AnnouncePeerRequest<'a> {
             ConnectPort::Explicit(n) => (n, 0),
         };
 
+        let mut request_args = BTreeMap::new();
+
+        request_args.insert(message::NODE_ID_KEY.as_bytes(),
+                            ben_bytes!(self.node_id.as_ref()));
+        request_args.insert(IMPLIED_PORT_KEY.as_bytes(), ben_int!(implied_value));
+        request_args.insert(message::INFO_HASH_KEY.as_bytes(),
+                            ben_bytes!(self.info_hash.as_ref()));
+        request_args.insert(PORT_KEY.as_bytes(), ben_int!(displayed_port as i64));
+        request_args.insert(message::TOKEN_KEY.as_bytes(), ben_bytes!(self.token));
+        if self.seed {
+            request_args.insert(SEED_KEY.as_bytes(), ben_int!(1));
+        }
+
         (ben_map!{
             //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
             message::TRANSACTION_ID_KEY => ben_bytes!(self.trans_id),
             message::MESSAGE_TYPE_KEY => ben_bytes!(message::REQUEST_TYPE_KEY),
             message::REQUEST_TYPE_KEY => ben_bytes!(request::ANNOUNCE_PEER_TYPE_KEY),
-            request::REQUEST_ARGS_KEY => ben_map!{
-                message::NODE_ID_KEY => ben_bytes!(self.node_id.as_ref()),
-                IMPLIED_PORT_KEY => ben_int!(implied_value),
-                message::INFO_HASH_KEY => ben_bytes!(self.info_hash.as_ref()),
-                PORT_KEY => ben_int!(displayed_port as i64),
-                message::TOKEN_KEY => ben_bytes!(self.token)
-            }
+            request::REQUEST_ARGS_KEY => Bencode::Dict(request_args)
         })
             .encode()
     }
@@ -165,3 +194,63 @@ impl<'a> AnnouncePeerResponse<'a> {
             .encode()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bip_bencode::Bencode;
+    use bip_util::bt;
+
+    use message::MessageType;
+    use message::request::RequestType;
+    use message::response::ExpectedResponse;
+
+    use super::{AnnouncePeerRequest, ConnectPort};
+
+    fn round_trip(port: ConnectPort) -> ConnectPort {
+        let request = AnnouncePeerRequest::new(b"aa", [1u8; bt::NODE_ID_LEN].into(),
+                                               [2u8; bt::INFO_HASH_LEN].into(), b"token", port);
+        let bytes = request.encode();
+        let bencode = Bencode::decode(&bytes[..]).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+            MessageType::Request(RequestType::AnnouncePeer(parsed)) => parsed.connect_port(),
+            _ => panic!("bip_dht: Expected An AnnouncePeerRequest")
+        }
+    }
+
+    #[test]
+    fn positive_explicit_port_round_trip() {
+        assert_eq!(ConnectPort::Explicit(6881), round_trip(ConnectPort::Explicit(6881)));
+    }
+
+    #[test]
+    fn positive_implied_port_round_trip() {
+        assert_eq!(ConnectPort::Implied, round_trip(ConnectPort::Implied));
+    }
+
+    /// Parse an announce from another implementation, which sends both the port and implied port.
+    fn parse_with_port(implied_port: i64) -> ConnectPort {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"d1:ad2:id20:");
+        bytes.extend_from_slice(&[1u8; bt::NODE_ID_LEN]);
+        bytes.extend_from_slice(format!("12:implied_porti{}e9:info_hash20:", implied_port).as_bytes());
+        bytes.extend_from_slice(&[2u8; bt::INFO_HASH_LEN]);
+        bytes.extend_from_slice(b"4:porti6881e5:token5:tokene1:q13:announce_peer1:t2:aa1:y1:qe");
+        let bencode = Bencode::decode(&bytes[..]).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+            MessageType::Request(RequestType::AnnouncePeer(parsed)) => parsed.connect_port(),
+            _ => panic!("bip_dht: Expected An AnnouncePeerRequest")
+        }
+    }
+
+    #[test]
+    fn positive_implied_port_overrides_port() {
+        assert_eq!(ConnectPort::Implied, parse_with_port(1));
+    }
+
+    #[test]
+    fn positive_zero_implied_port_uses_port() {
+        assert_eq!(ConnectPort::Explicit(6881), parse_with_port(0));
+    }
+}
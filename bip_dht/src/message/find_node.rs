@@ -1,8 +1,11 @@
+use std::collections::BTreeMap;
+
 use bip_bencode::{Bencode, BencodeConvert, Dictionary};
 use bip_util::bt::NodeId;
 
 use message;
 use message::compact_info::CompactNodeInfo;
+use message::want::Want;
 use message::request::{self, RequestValidate};
 use message::response::ResponseValidate;
 use error::DhtResult;
@@ -12,6 +15,7 @@ pub struct FindNodeRequest<'a> {
     trans_id: &'a [u8],
     node_id: NodeId,
     target_id: NodeId,
+    want: Option<Want>,
 }
 
 impl<'a> FindNodeRequest<'a> {
@@ -20,9 +24,17 @@ impl<'a> FindNodeRequest<'a> {
             trans_id: trans_id,
             node_id: node_id,
             target_id: target_id,
+            want: None,
         }
     }
 
+    /// Set the address families of nodes we want in the response (BEP 32).
+    pub fn with_want(mut self, want: Option<Want>) -> FindNodeRequest<'a> {
+        self.want = want;
+
+        self
+    }
+
     /// Create a FindNodeRequest from parts.
     ///
     /// The target_key argument is provided for cases where, due to forward compatibility,
@@ -40,7 +52,11 @@ impl<'a> FindNodeRequest<'a> {
         let target_id_bytes = try!(validate.lookup_and_convert_bytes(rqst_root, target_key));
         let target_id = try!(validate.validate_node_id(target_id_bytes));
 
-        Ok(FindNodeRequest::new(trans_id, node_id, target_id))
+        let want = validate.lookup_and_convert_list(rqst_root, message::WANT_KEY)
+            .ok()
+            .map(Want::from_list);
+
+        Ok(FindNodeRequest::new(trans_id, node_id, target_id).with_want(want))
     }
 
     pub fn transaction_id(&self) -> &'a [u8] {
@@ -55,16 +71,27 @@ impl<'a> FindNodeRequest<'a> {
         self.target_id
     }
 
+    pub fn want(&self) -> Option<Want> {
+        self.want
+    }
+
     pub fn encode(&self) -> Vec<u8> {
+        let mut request_args = BTreeMap::new();
+
+        request_args.insert(message::NODE_ID_KEY.as_bytes(),
+                            ben_bytes!(self.node_id.as_ref()));
+        request_args.insert(message::TARGET_ID_KEY.as_bytes(),
+                            ben_bytes!(self.target_id.as_ref()));
+        if let Some(want) = self.want {
+            request_args.insert(message::WANT_KEY.as_bytes(), want.to_bencode());
+        }
+
         (ben_map!{
             //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
             message::TRANSACTION_ID_KEY => ben_bytes!(self.trans_id),
             message::MESSAGE_TYPE_KEY => ben_bytes!(message::REQUEST_TYPE_KEY),
             message::REQUEST_TYPE_KEY => ben_bytes!(request::FIND_NODE_TYPE_KEY),
-            request::REQUEST_ARGS_KEY => ben_map!{
-                message::NODE_ID_KEY => ben_bytes!(self.node_id.as_ref()),
-                message::TARGET_ID_KEY => ben_bytes!(self.target_id.as_ref())
-            }
+            request::REQUEST_ARGS_KEY => Bencode::Dict(request_args)
         })
             .encode()
     }
@@ -130,3 +157,41 @@ impl<'a> FindNodeResponse<'a> {
             .encode()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bip_bencode::Bencode;
+    use bip_util::bt;
+
+    use message::MessageType;
+    use message::request::RequestType;
+    use message::response::ExpectedResponse;
+    use message::want::Want;
+
+    use super::FindNodeRequest;
+
+    fn round_trip(request: FindNodeRequest) {
+        let bytes = request.encode();
+        let bencode = Bencode::decode(&bytes[..]).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+            MessageType::Request(RequestType::FindNode(parsed)) => assert_eq!(request, parsed),
+            _ => panic!("bip_dht: Expected A FindNodeRequest")
+        }
+    }
+
+    #[test]
+    fn positive_request_want_round_trip() {
+        let request = FindNodeRequest::new(b"aa", [1u8; bt::NODE_ID_LEN].into(), [2u8; bt::NODE_ID_LEN].into())
+            .with_want(Some(Want::new(true, true)));
+
+        round_trip(request);
+    }
+
+    #[test]
+    fn positive_request_no_want_round_trip() {
+        let request = FindNodeRequest::new(b"aa", [1u8; bt::NODE_ID_LEN].into(), [2u8; bt::NODE_ID_LEN].into());
+
+        round_trip(request);
+    }
+}
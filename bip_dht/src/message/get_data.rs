@@ -1,18 +1,290 @@
+use std::collections::BTreeMap;
+
 use bip_bencode::{Bencode, BencodeConvert, Dictionary};
+use bip_util::bt::NodeId;
+use bip_util::sha::ShaHash;
 
+use message;
+use message::compact_info::CompactNodeInfo;
+use message::request::{self, RequestValidate};
+use message::response::{self, ResponseValidate};
 use error::{DhtResult, DhtError, DhtErrorKind};
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct GetDataRequest<'a> {
-    trans_id: &'a [u8]
+    trans_id: &'a [u8],
+    node_id: NodeId,
+    target: ShaHash,
+    seq: Option<i64>,
 }
 
 impl<'a> GetDataRequest<'a> {
-    pub fn new(rqst_root: &Dictionary<'a, Bencode<'a>>, trans_id: &'a [u8]) -> DhtResult<GetDataRequest<'a>> {
-        unimplemented!();
+    pub fn new(trans_id: &'a [u8],
+               node_id: NodeId,
+               target: ShaHash,
+               seq: Option<i64>)
+               -> GetDataRequest<'a> {
+        GetDataRequest {
+            trans_id: trans_id,
+            node_id: node_id,
+            target: target,
+            seq: seq,
+        }
+    }
+
+    pub fn from_parts(rqst_root: &Dictionary<'a, Bencode<'a>>,
+                      trans_id: &'a [u8])
+                      -> DhtResult<GetDataRequest<'a>> {
+        let validate = RequestValidate::new(trans_id);
+
+        let node_id_bytes =
+            try!(validate.lookup_and_convert_bytes(rqst_root, message::NODE_ID_KEY));
+        let node_id = try!(validate.validate_node_id(node_id_bytes));
+
+        let target_bytes =
+            try!(validate.lookup_and_convert_bytes(rqst_root, message::TARGET_ID_KEY));
+        let target = try!(validate.validate_info_hash(target_bytes));
+
+        let seq = validate.lookup_and_convert_int(rqst_root, message::SEQ_KEY).ok();
+
+        Ok(GetDataRequest::new(trans_id, node_id, target, seq))
+    }
+
+    pub fn transaction_id(&self) -> &'a [u8] {
+        self.trans_id
+    }
+
+    pub fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    pub fn target(&self) -> ShaHash {
+        self.target
+    }
+
+    pub fn seq(&self) -> Option<i64> {
+        self.seq
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut request_args = BTreeMap::new();
+
+        request_args.insert(message::NODE_ID_KEY.as_bytes(),
+                            ben_bytes!(self.node_id.as_ref()));
+        request_args.insert(message::TARGET_ID_KEY.as_bytes(),
+                            ben_bytes!(self.target.as_ref()));
+        if let Some(seq) = self.seq {
+            request_args.insert(message::SEQ_KEY.as_bytes(), ben_int!(seq));
+        }
+
+        (ben_map!{
+            //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
+            message::TRANSACTION_ID_KEY => ben_bytes!(self.trans_id),
+            message::MESSAGE_TYPE_KEY => ben_bytes!(message::REQUEST_TYPE_KEY),
+            message::REQUEST_TYPE_KEY => ben_bytes!(request::GET_DATA_TYPE_KEY),
+            request::REQUEST_ARGS_KEY => Bencode::Dict(request_args)
+        })
+            .encode()
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Signature information present in a response for a mutable item.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct MutableInfo<'a> {
+    key: &'a [u8],
+    signature: &'a [u8],
+    seq: i64,
+}
+
+impl<'a> MutableInfo<'a> {
+    pub fn new(key: &'a [u8], signature: &'a [u8], seq: i64) -> MutableInfo<'a> {
+        MutableInfo {
+            key: key,
+            signature: signature,
+            seq: seq,
+        }
+    }
+
+    pub fn key(&self) -> &'a [u8] {
+        self.key
+    }
+
+    pub fn signature(&self) -> &'a [u8] {
+        self.signature
+    }
+
+    pub fn seq(&self) -> i64 {
+        self.seq
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct GetDataResponse<'a> {
+    trans_id: &'a [u8],
+    node_id: NodeId,
+    token: Option<&'a [u8]>,
+    nodes: Option<CompactNodeInfo<'a>>,
+    value: Option<&'a Bencode<'a>>,
+    mutable: Option<MutableInfo<'a>>,
+}
+
+impl<'a> GetDataResponse<'a> {
+    pub fn new(trans_id: &'a [u8],
+               node_id: NodeId,
+               token: Option<&'a [u8]>,
+               nodes: Option<CompactNodeInfo<'a>>,
+               value: Option<&'a Bencode<'a>>,
+               mutable: Option<MutableInfo<'a>>)
+               -> GetDataResponse<'a> {
+        GetDataResponse {
+            trans_id: trans_id,
+            node_id: node_id,
+            token: token,
+            nodes: nodes,
+            value: value,
+            mutable: mutable,
+        }
+    }
+
+    pub fn from_parts(rsp_root: &'a Dictionary<'a, Bencode<'a>>,
+                      trans_id: &'a [u8])
+                      -> DhtResult<GetDataResponse<'a>> {
+        let validate = ResponseValidate::new(trans_id);
+
+        let node_id_bytes = try!(validate.lookup_and_convert_bytes(rsp_root, message::NODE_ID_KEY));
+        let node_id = try!(validate.validate_node_id(node_id_bytes));
+
+        let token = validate.lookup_and_convert_bytes(rsp_root, message::TOKEN_KEY).ok();
+
+        let nodes = match validate.lookup_and_convert_bytes(rsp_root, message::NODES_KEY) {
+            Ok(nodes) => Some(try!(validate.validate_nodes(nodes))),
+            Err(_) => None,
+        };
+        let value = rsp_root.lookup(message::VALUE_KEY.as_bytes());
+
+        let mutable = match (validate.lookup_and_convert_bytes(rsp_root, message::KEY_KEY),
+                             validate.lookup_and_convert_bytes(rsp_root, message::SIGNATURE_KEY),
+                             validate.lookup_and_convert_int(rsp_root, message::SEQ_KEY)) {
+            (Ok(key), Ok(signature), Ok(seq)) => Some(MutableInfo::new(key, signature, seq)),
+            _ => None,
+        };
+
+        if nodes.is_none() && value.is_none() {
+            return Err(DhtError::from_kind(DhtErrorKind::InvalidResponse {
+                details: "Failed To Find nodes Or v In Get Response".to_owned(),
+            }));
+        }
+
+        Ok(GetDataResponse::new(trans_id, node_id, token, nodes, value, mutable))
     }
-    
+
     pub fn transaction_id(&self) -> &'a [u8] {
         self.trans_id
     }
-}
\ No newline at end of file
+
+    pub fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    pub fn token(&self) -> Option<&'a [u8]> {
+        self.token
+    }
+
+    pub fn nodes(&self) -> Option<CompactNodeInfo<'a>> {
+        self.nodes
+    }
+
+    pub fn value(&self) -> Option<&'a Bencode<'a>> {
+        self.value
+    }
+
+    pub fn mutable_info(&self) -> Option<MutableInfo<'a>> {
+        self.mutable
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut response_args = BTreeMap::new();
+
+        response_args.insert(message::NODE_ID_KEY.as_bytes(),
+                             ben_bytes!(self.node_id.as_ref()));
+        if let Some(token) = self.token {
+            response_args.insert(message::TOKEN_KEY.as_bytes(), ben_bytes!(token));
+        }
+        if let Some(nodes) = self.nodes {
+            response_args.insert(message::NODES_KEY.as_bytes(), ben_bytes!(nodes.nodes()));
+        }
+        if let Some(value) = self.value {
+            response_args.insert(message::VALUE_KEY.as_bytes(), value.clone());
+        }
+        if let Some(mutable) = self.mutable {
+            response_args.insert(message::KEY_KEY.as_bytes(), ben_bytes!(mutable.key()));
+            response_args.insert(message::SIGNATURE_KEY.as_bytes(),
+                                 ben_bytes!(mutable.signature()));
+            response_args.insert(message::SEQ_KEY.as_bytes(), ben_int!(mutable.seq()));
+        }
+
+        (ben_map!{
+            //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
+            message::TRANSACTION_ID_KEY => ben_bytes!(self.trans_id),
+            message::MESSAGE_TYPE_KEY => ben_bytes!(message::RESPONSE_TYPE_KEY),
+            response::RESPONSE_ARGS_KEY => Bencode::Dict(response_args)
+        })
+            .encode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bip_bencode::Bencode;
+    use bip_util::bt;
+
+    use message::MessageType;
+    use message::request::RequestType;
+    use message::response::{ResponseType, ExpectedResponse};
+
+    use super::{GetDataRequest, GetDataResponse, MutableInfo};
+
+    #[test]
+    fn positive_request_round_trip() {
+        let request = GetDataRequest::new(b"aa", [1u8; bt::NODE_ID_LEN].into(), [2u8; bt::INFO_HASH_LEN].into(), Some(5));
+        let bytes = request.encode();
+        let bencode = Bencode::decode(&bytes[..]).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+            MessageType::Request(RequestType::GetData(parsed)) => assert_eq!(request, parsed),
+            _ => panic!("bip_dht: Expected A GetDataRequest")
+        }
+    }
+
+    #[test]
+    fn positive_response_mutable_round_trip() {
+        let value_bytes = b"12:Hello World!";
+        let value = Bencode::decode(&value_bytes[..]).unwrap();
+        let (key, signature) = ([3u8; 32], [4u8; 64]);
+
+        let response = GetDataResponse::new(b"aa", [1u8; bt::NODE_ID_LEN].into(), Some(b"token"), None,
+                                            Some(&value), Some(MutableInfo::new(&key, &signature, 7)));
+        let bytes = response.encode();
+        let bencode = Bencode::decode(&bytes[..]).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::GetData).unwrap() {
+            MessageType::Response(ResponseType::GetData(parsed)) => {
+                assert_eq!(Some(&b"token"[..]), parsed.token());
+                assert_eq!(Some(value_bytes.to_vec()), parsed.value().map(|v| v.encode()));
+                assert_eq!(Some(MutableInfo::new(&key, &signature, 7)), parsed.mutable_info());
+            },
+            _ => panic!("bip_dht: Expected A GetDataResponse")
+        }
+    }
+
+    #[test]
+    fn negative_response_missing_nodes_and_value() {
+        let response = GetDataResponse::new(b"aa", [1u8; bt::NODE_ID_LEN].into(), Some(b"token"), None, None, None);
+        let bytes = response.encode();
+        let bencode = Bencode::decode(&bytes[..]).unwrap();
+
+        assert!(MessageType::new(&bencode, |_| ExpectedResponse::GetData).is_err());
+    }
+}
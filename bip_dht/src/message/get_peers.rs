@@ -5,15 +5,24 @@ use bip_util::bt::{NodeId, InfoHash};
 
 use message;
 use message::compact_info::{CompactNodeInfo, CompactValueInfo};
+use message::want::Want;
 use message::request::{self, RequestValidate};
 use message::response::{self, ResponseValidate};
 use error::{DhtResult, DhtErrorKind, DhtError};
 
+const NO_SEED_KEY: &'static str = "noseed";
+const SCRAPE_KEY: &'static str = "scrape";
+const SEEDS_FILTER_KEY: &'static str = "BFsd";
+const PEERS_FILTER_KEY: &'static str = "BFpe";
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct GetPeersRequest<'a> {
     trans_id: &'a [u8],
     node_id: NodeId,
     info_hash: InfoHash,
+    want: Option<Want>,
+    no_seed: bool,
+    scrape: bool,
 }
 
 impl<'a> GetPeersRequest<'a> {
@@ -22,9 +31,33 @@ impl<'a> GetPeersRequest<'a> {
             trans_id: trans_id,
             node_id: node_id,
             info_hash: info_hash,
+            want: None,
+            no_seed: false,
+            scrape: false,
         }
     }
 
+    /// Set the address families of nodes we want in the response (BEP 32).
+    pub fn with_want(mut self, want: Option<Want>) -> GetPeersRequest<'a> {
+        self.want = want;
+
+        self
+    }
+
+    /// Set whether or not seeds should be left out of the response (BEP 33).
+    pub fn with_no_seed(mut self, no_seed: bool) -> GetPeersRequest<'a> {
+        self.no_seed = no_seed;
+
+        self
+    }
+
+    /// Set whether or not scrape bloom filters should be included in the response (BEP 33).
+    pub fn with_scrape(mut self, scrape: bool) -> GetPeersRequest<'a> {
+        self.scrape = scrape;
+
+        self
+    }
+
     pub fn from_parts(rqst_root: &Dictionary<'a, Bencode<'a>>,
                       trans_id: &'a [u8])
                       -> DhtResult<GetPeersRequest<'a>> {
@@ -38,7 +71,22 @@ impl<'a> GetPeersRequest<'a> {
             try!(validate.lookup_and_convert_bytes(rqst_root, message::INFO_HASH_KEY));
         let info_hash = try!(validate.validate_info_hash(info_hash_bytes));
 
-        Ok(GetPeersRequest::new(trans_id, node_id, info_hash))
+        let want = validate.lookup_and_convert_list(rqst_root, message::WANT_KEY)
+            .ok()
+            .map(Want::from_list);
+
+        let no_seed = validate.lookup_and_convert_int(rqst_root, NO_SEED_KEY)
+            .map(|n| n != 0)
+            .unwrap_or(false);
+
+        let scrape = validate.lookup_and_convert_int(rqst_root, SCRAPE_KEY)
+            .map(|n| n != 0)
+            .unwrap_or(false);
+
+        Ok(GetPeersRequest::new(trans_id, node_id, info_hash)
+            .with_want(want)
+            .with_no_seed(no_seed)
+            .with_scrape(scrape))
     }
 
     pub fn transaction_id(&self) -> &'a [u8] {
@@ -53,16 +101,41 @@ impl<'a> GetPeersRequest<'a> {
         self.info_hash
     }
 
+    pub fn want(&self) -> Option<Want> {
+        self.want
+    }
+
+    pub fn no_seed(&self) -> bool {
+        self.no_seed
+    }
+
+    pub fn scrape(&self) -> bool {
+        self.scrape
+    }
+
     pub fn encode(&self) -> Vec<u8> {
+        let mut request_args = BTreeMap::new();
+
+        request_args.insert(message::NODE_ID_KEY.as_bytes(),
+                            ben_bytes!(self.node_id.as_ref()));
+        request_args.insert(message::INFO_HASH_KEY.as_bytes(),
+                            ben_bytes!(self.info_hash.as_ref()));
+        if let Some(want) = self.want {
+            request_args.insert(message::WANT_KEY.as_bytes(), want.to_bencode());
+        }
+        if self.no_seed {
+            request_args.insert(NO_SEED_KEY.as_bytes(), ben_int!(1));
+        }
+        if self.scrape {
+            request_args.insert(SCRAPE_KEY.as_bytes(), ben_int!(1));
+        }
+
         (ben_map!{
             //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
             message::TRANSACTION_ID_KEY => ben_bytes!(self.trans_id),
             message::MESSAGE_TYPE_KEY => ben_bytes!(message::REQUEST_TYPE_KEY),
             message::REQUEST_TYPE_KEY => ben_bytes!(request::GET_PEERS_TYPE_KEY),
-            request::REQUEST_ARGS_KEY => ben_map!{
-                message::NODE_ID_KEY => ben_bytes!(self.node_id.as_ref()),
-                message::INFO_HASH_KEY => ben_bytes!(self.info_hash.as_ref())
-            }
+            request::REQUEST_ARGS_KEY => Bencode::Dict(request_args)
         })
             .encode()
     }
@@ -83,6 +156,9 @@ pub struct GetPeersResponse<'a> {
     // because they are only used for bootstraping and not to announce to.
     token: Option<&'a [u8]>,
     info_type: CompactInfoType<'a>,
+    // Scrape bloom filters for seeds and leechers, only present if requested (BEP 33)
+    seeds_filter: Option<&'a [u8]>,
+    peers_filter: Option<&'a [u8]>,
 }
 
 impl<'a> GetPeersResponse<'a> {
@@ -96,9 +172,22 @@ impl<'a> GetPeersResponse<'a> {
             node_id: node_id,
             token: token,
             info_type: info_type,
+            seeds_filter: None,
+            peers_filter: None,
         }
     }
 
+    /// Set the scrape bloom filters for seeds and leechers (BEP 33).
+    pub fn with_scrape_filters(mut self,
+                               seeds_filter: &'a [u8],
+                               peers_filter: &'a [u8])
+                               -> GetPeersResponse<'a> {
+        self.seeds_filter = Some(seeds_filter);
+        self.peers_filter = Some(peers_filter);
+
+        self
+    }
+
     pub fn from_parts(rsp_root: &'a Dictionary<'a, Bencode<'a>>,
                       trans_id: &'a [u8])
                       -> DhtResult<GetPeersResponse<'a>> {
@@ -135,7 +224,14 @@ impl<'a> GetPeersResponse<'a> {
             }
         };
 
-        Ok(GetPeersResponse::new(trans_id, node_id, token, info_type))
+        let seeds_filter = validate.lookup_and_convert_bytes(rsp_root, SEEDS_FILTER_KEY);
+        let peers_filter = validate.lookup_and_convert_bytes(rsp_root, PEERS_FILTER_KEY);
+
+        let get_peers_rsp = GetPeersResponse::new(trans_id, node_id, token, info_type);
+        match (seeds_filter, peers_filter) {
+            (Ok(seeds), Ok(peers)) => Ok(get_peers_rsp.with_scrape_filters(seeds, peers)),
+            _ => Ok(get_peers_rsp),
+        }
     }
 
     pub fn transaction_id(&self) -> &'a [u8] {
@@ -154,6 +250,14 @@ impl<'a> GetPeersResponse<'a> {
         self.info_type
     }
 
+    pub fn seeds_filter(&self) -> Option<&'a [u8]> {
+        self.seeds_filter
+    }
+
+    pub fn peers_filter(&self) -> Option<&'a [u8]> {
+        self.peers_filter
+    }
+
     pub fn encode(&self) -> Vec<u8> {
         let mut response_args = BTreeMap::new();
 
@@ -180,6 +284,10 @@ impl<'a> GetPeersResponse<'a> {
                                      Bencode::List(values.values().to_vec()));
             }
         };
+        if let (Some(seeds), Some(peers)) = (self.seeds_filter, self.peers_filter) {
+            response_args.insert(SEEDS_FILTER_KEY.as_bytes(), ben_bytes!(seeds));
+            response_args.insert(PEERS_FILTER_KEY.as_bytes(), ben_bytes!(peers));
+        }
 
         (ben_map!{
             //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
@@ -191,3 +299,62 @@ impl<'a> GetPeersResponse<'a> {
             .encode()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bip_bencode::Bencode;
+    use bip_util::bt;
+
+    use message::MessageType;
+    use message::compact_info::CompactNodeInfo;
+    use message::request::RequestType;
+    use message::response::{ExpectedResponse, ResponseType};
+    use message::want::Want;
+    use scrape::ScrapeFilter;
+
+    use super::{GetPeersRequest, GetPeersResponse, CompactInfoType};
+
+    fn round_trip(request: GetPeersRequest) {
+        let bytes = request.encode();
+        let bencode = Bencode::decode(&bytes[..]).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+            MessageType::Request(RequestType::GetPeers(parsed)) => assert_eq!(request, parsed),
+            _ => panic!("bip_dht: Expected A GetPeersRequest")
+        }
+    }
+
+    #[test]
+    fn positive_request_want_round_trip() {
+        let request = GetPeersRequest::new(b"aa", [1u8; bt::NODE_ID_LEN].into(), [2u8; bt::INFO_HASH_LEN].into())
+            .with_want(Some(Want::new(true, true)));
+
+        round_trip(request);
+    }
+
+    #[test]
+    fn positive_request_no_want_round_trip() {
+        let request = GetPeersRequest::new(b"aa", [1u8; bt::NODE_ID_LEN].into(), [2u8; bt::INFO_HASH_LEN].into());
+
+        round_trip(request);
+    }
+
+    #[test]
+    fn positive_response_empty_scrape_filters_round_trip() {
+        let empty_filter = ScrapeFilter::new();
+        let response = GetPeersResponse::new(b"aa", [1u8; bt::NODE_ID_LEN].into(), Some(&b"token"[..]),
+                                             CompactInfoType::Nodes(CompactNodeInfo::new(&[]).unwrap()))
+            .with_scrape_filters(empty_filter.as_bytes(), empty_filter.as_bytes());
+
+        let bytes = response.encode();
+        let bencode = Bencode::decode(&bytes[..]).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::GetPeers).unwrap() {
+            MessageType::Response(ResponseType::GetPeers(parsed)) => {
+                assert_eq!(Some(empty_filter.as_bytes()), parsed.seeds_filter());
+                assert_eq!(Some(empty_filter.as_bytes()), parsed.peers_filter());
+            },
+            _ => panic!("bip_dht: Expected A GetPeersResponse")
+        }
+    }
+}
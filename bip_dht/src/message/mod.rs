@@ -6,6 +6,7 @@ use message::error::ErrorMessage;
 use error::{DhtError, DhtErrorKind, DhtResult};
 
 pub mod compact_info;
+pub mod want;
 
 pub mod request;
 pub mod response;
@@ -15,6 +16,9 @@ pub mod ping;
 pub mod find_node;
 pub mod get_peers;
 pub mod announce_peer;
+pub mod get_data;
+pub mod put_data;
+pub mod template;
 
 // Top level message keys
 const TRANSACTION_ID_KEY: &'static str = "t";
@@ -37,6 +41,17 @@ const TARGET_ID_KEY: &'static str = "target";
 const INFO_HASH_KEY: &'static str = "info_hash";
 const TOKEN_KEY: &'static str = "token";
 
+// Keys used for requesting address families (BEP 32)
+const WANT_KEY: &'static str = "want";
+
+// Keys used for storing arbitrary data (BEP 44)
+const VALUE_KEY: &'static str = "v";
+const KEY_KEY: &'static str = "k";
+const SIGNATURE_KEY: &'static str = "sig";
+const SEQ_KEY: &'static str = "seq";
+const SALT_KEY: &'static str = "salt";
+const CAS_KEY: &'static str = "cas";
+
 // ----------------------------------------------------------------------------//
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
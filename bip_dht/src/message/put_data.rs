@@ -1,18 +1,239 @@
+use std::collections::BTreeMap;
+
 use bip_bencode::{Bencode, BencodeConvert, Dictionary};
+use bip_util::bt::NodeId;
 
+use message;
+use message::error::{ErrorMessage, ErrorCode};
+use message::get_data::MutableInfo;
+use message::request::{self, RequestValidate};
+use message::response;
 use error::{DhtResult, DhtError, DhtErrorKind};
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct PutDataRequest<'a> {
-    trans_id: &'a [u8]
+    trans_id: &'a [u8],
+    node_id: NodeId,
+    token: &'a [u8],
+    value: &'a Bencode<'a>,
+    mutable: Option<MutableInfo<'a>>,
+    salt: Option<&'a [u8]>,
+    cas: Option<i64>,
 }
 
 impl<'a> PutDataRequest<'a> {
-    pub fn new(rqst_root: &Dictionary<'a, Bencode<'a>>, trans_id: &'a [u8]) -> DhtResult<PutDataRequest<'a>> {
-        unimplemented!();
+    pub fn new(trans_id: &'a [u8],
+               node_id: NodeId,
+               token: &'a [u8],
+               value: &'a Bencode<'a>,
+               mutable: Option<MutableInfo<'a>>,
+               salt: Option<&'a [u8]>,
+               cas: Option<i64>)
+               -> PutDataRequest<'a> {
+        PutDataRequest {
+            trans_id: trans_id,
+            node_id: node_id,
+            token: token,
+            value: value,
+            mutable: mutable,
+            salt: salt,
+            cas: cas,
+        }
+    }
+
+    pub fn from_parts(rqst_root: &'a Dictionary<'a, Bencode<'a>>,
+                      trans_id: &'a [u8])
+                      -> DhtResult<PutDataRequest<'a>> {
+        let validate = RequestValidate::new(trans_id);
+
+        let node_id_bytes =
+            try!(validate.lookup_and_convert_bytes(rqst_root, message::NODE_ID_KEY));
+        let node_id = try!(validate.validate_node_id(node_id_bytes));
+
+        let token = try!(validate.lookup_and_convert_bytes(rqst_root, message::TOKEN_KEY));
+        let value = try!(rqst_root.lookup(message::VALUE_KEY.as_bytes()).ok_or_else(|| {
+            let error_msg = ErrorMessage::new(trans_id.to_owned(),
+                                              ErrorCode::ProtocolError,
+                                              "Put Request Is Missing A Value".to_owned());
+
+            DhtError::from_kind(DhtErrorKind::InvalidRequest { msg: error_msg })
+        }));
+
+        let mutable = match (validate.lookup_and_convert_bytes(rqst_root, message::KEY_KEY),
+                             validate.lookup_and_convert_bytes(rqst_root, message::SIGNATURE_KEY),
+                             validate.lookup_and_convert_int(rqst_root, message::SEQ_KEY)) {
+            (Ok(key), Ok(signature), Ok(seq)) => Some(MutableInfo::new(key, signature, seq)),
+            _ => None,
+        };
+        let salt = validate.lookup_and_convert_bytes(rqst_root, message::SALT_KEY).ok();
+        let cas = validate.lookup_and_convert_int(rqst_root, message::CAS_KEY).ok();
+
+        Ok(PutDataRequest::new(trans_id, node_id, token, value, mutable, salt, cas))
     }
-    
+
     pub fn transaction_id(&self) -> &'a [u8] {
         self.trans_id
     }
-}
\ No newline at end of file
+
+    pub fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    pub fn token(&self) -> &'a [u8] {
+        self.token
+    }
+
+    pub fn value(&self) -> &'a Bencode<'a> {
+        self.value
+    }
+
+    pub fn mutable_info(&self) -> Option<MutableInfo<'a>> {
+        self.mutable
+    }
+
+    pub fn salt(&self) -> Option<&'a [u8]> {
+        self.salt
+    }
+
+    pub fn cas(&self) -> Option<i64> {
+        self.cas
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut request_args = BTreeMap::new();
+
+        request_args.insert(message::NODE_ID_KEY.as_bytes(),
+                            ben_bytes!(self.node_id.as_ref()));
+        request_args.insert(message::TOKEN_KEY.as_bytes(), ben_bytes!(self.token));
+        request_args.insert(message::VALUE_KEY.as_bytes(), self.value.clone());
+        if let Some(mutable) = self.mutable {
+            request_args.insert(message::KEY_KEY.as_bytes(), ben_bytes!(mutable.key()));
+            request_args.insert(message::SIGNATURE_KEY.as_bytes(),
+                                ben_bytes!(mutable.signature()));
+            request_args.insert(message::SEQ_KEY.as_bytes(), ben_int!(mutable.seq()));
+        }
+        if let Some(salt) = self.salt {
+            request_args.insert(message::SALT_KEY.as_bytes(), ben_bytes!(salt));
+        }
+        if let Some(cas) = self.cas {
+            request_args.insert(message::CAS_KEY.as_bytes(), ben_int!(cas));
+        }
+
+        (ben_map!{
+            //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
+            message::TRANSACTION_ID_KEY => ben_bytes!(self.trans_id),
+            message::MESSAGE_TYPE_KEY => ben_bytes!(message::REQUEST_TYPE_KEY),
+            message::REQUEST_TYPE_KEY => ben_bytes!(request::PUT_DATA_TYPE_KEY),
+            request::REQUEST_ARGS_KEY => Bencode::Dict(request_args)
+        })
+            .encode()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PutDataResponse<'a> {
+    trans_id: &'a [u8],
+    node_id: NodeId,
+}
+
+impl<'a> PutDataResponse<'a> {
+    pub fn new(trans_id: &'a [u8], node_id: NodeId) -> PutDataResponse<'a> {
+        PutDataResponse {
+            trans_id: trans_id,
+            node_id: node_id,
+        }
+    }
+
+    pub fn from_parts(rsp_root: &Dictionary<'a, Bencode<'a>>,
+                      trans_id: &'a [u8])
+                      -> DhtResult<PutDataResponse<'a>> {
+        let validate = RequestValidate::new(&trans_id);
+
+        let node_id_bytes =
+            try!(validate.lookup_and_convert_bytes(rsp_root, message::NODE_ID_KEY));
+        let node_id = try!(validate.validate_node_id(node_id_bytes));
+
+        Ok(PutDataResponse::new(trans_id, node_id))
+    }
+
+    pub fn transaction_id(&self) -> &'a [u8] {
+        self.trans_id
+    }
+
+    pub fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        (ben_map!{
+            //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
+            message::TRANSACTION_ID_KEY => ben_bytes!(self.trans_id),
+            message::MESSAGE_TYPE_KEY => ben_bytes!(message::RESPONSE_TYPE_KEY),
+            response::RESPONSE_ARGS_KEY => ben_map!{
+                message::NODE_ID_KEY => ben_bytes!(self.node_id.as_ref())
+            }
+        })
+            .encode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bip_bencode::Bencode;
+    use bip_util::bt;
+
+    use message::MessageType;
+    use message::get_data::MutableInfo;
+    use message::request::RequestType;
+    use message::response::ExpectedResponse;
+
+    use super::PutDataRequest;
+
+    #[test]
+    fn positive_immutable_request_round_trip() {
+        let value = Bencode::decode(&b"12:Hello World!"[..]).unwrap();
+
+        let request = PutDataRequest::new(b"aa", [1u8; bt::NODE_ID_LEN].into(), b"token", &value, None, None, None);
+        let bytes = request.encode();
+        let bencode = Bencode::decode(&bytes[..]).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+            MessageType::Request(RequestType::PutData(parsed)) => {
+                assert_eq!(&b"token"[..], parsed.token());
+                assert_eq!(value.encode(), parsed.value().encode());
+                assert_eq!(None, parsed.mutable_info());
+                assert_eq!(None, parsed.salt());
+                assert_eq!(None, parsed.cas());
+            },
+            _ => panic!("bip_dht: Expected A PutDataRequest")
+        }
+    }
+
+    #[test]
+    fn positive_mutable_request_round_trip() {
+        let value = Bencode::decode(&b"i5e"[..]).unwrap();
+        let (key, signature) = ([3u8; 32], [4u8; 64]);
+
+        let request = PutDataRequest::new(b"aa", [1u8; bt::NODE_ID_LEN].into(), b"token", &value,
+                                          Some(MutableInfo::new(&key, &signature, 2)), Some(b"salt"), Some(1));
+        let bytes = request.encode();
+        let bencode = Bencode::decode(&bytes[..]).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+            MessageType::Request(RequestType::PutData(parsed)) => {
+                assert_eq!(Some(MutableInfo::new(&key, &signature, 2)), parsed.mutable_info());
+                assert_eq!(Some(&b"salt"[..]), parsed.salt());
+                assert_eq!(Some(1), parsed.cas());
+            },
+            _ => panic!("bip_dht: Expected A PutDataRequest")
+        }
+    }
+
+    #[test]
+    fn negative_request_missing_value() {
+        let bytes = b"d1:ad2:id20:aaaaaaaaaaaaaaaaaaaa5:token5:tokene1:q3:put1:t2:aa1:y1:qe";
+        let bencode = Bencode::decode(&bytes[..]).unwrap();
+
+        assert!(MessageType::new(&bencode, |_| ExpectedResponse::None).is_err());
+    }
+}
@@ -7,6 +7,8 @@ use message::ping::PingRequest;
 use message::find_node::FindNodeRequest;
 use message::get_peers::GetPeersRequest;
 use message::announce_peer::AnnouncePeerRequest;
+use message::get_data::GetDataRequest;
+use message::put_data::PutDataRequest;
 use error::{DhtError, DhtErrorKind, DhtResult};
 
 pub const REQUEST_ARGS_KEY: &'static str = "a";
@@ -16,8 +18,8 @@ pub const PING_TYPE_KEY: &'static str = "ping";
 pub const FIND_NODE_TYPE_KEY: &'static str = "find_node";
 pub const GET_PEERS_TYPE_KEY: &'static str = "get_peers";
 pub const ANNOUNCE_PEER_TYPE_KEY: &'static str = "announce_peer";
-// const GET_DATA_TYPE_KEY:          &'static str = "get";
-// const PUT_DATA_TYPE_KEY:          &'static str = "put";
+pub const GET_DATA_TYPE_KEY: &'static str = "get";
+pub const PUT_DATA_TYPE_KEY: &'static str = "put";
 
 // ----------------------------------------------------------------------------//
 
@@ -68,12 +70,13 @@ pub enum RequestType<'a> {
     Ping(PingRequest<'a>),
     FindNode(FindNodeRequest<'a>),
     GetPeers(GetPeersRequest<'a>),
-    AnnouncePeer(AnnouncePeerRequest<'a>), /* GetData(GetDataRequest<'a>),
-                                            * PutData(PutDataRequest<'a>) */
+    AnnouncePeer(AnnouncePeerRequest<'a>),
+    GetData(GetDataRequest<'a>),
+    PutData(PutDataRequest<'a>),
 }
 
 impl<'a> RequestType<'a> {
-    pub fn from_parts(root: &Dictionary<'a, Bencode<'a>>,
+    pub fn from_parts(root: &'a Dictionary<'a, Bencode<'a>>,
                       trans_id: &'a [u8],
                       rqst_type: &str)
                       -> DhtResult<RequestType<'a>> {
@@ -98,14 +101,14 @@ impl<'a> RequestType<'a> {
                 let announce_peer_rqst = try!(AnnouncePeerRequest::from_parts(rqst_root, trans_id));
                 Ok(RequestType::AnnouncePeer(announce_peer_rqst))
             }
-            // GET_DATA_TYPE_KEY => {
-            // let get_data_rqst = try!(GetDataRequest::new(rqst_root, trans_id));
-            // Ok(RequestType::GetData(get_data_rqst))
-            // },
-            // PUT_DATA_TYPE_KEY => {
-            // let put_data_rqst = try!(PutDataRequest::new(rqst_root, trans_id));
-            // Ok(RequestType::PutData(put_data_rqst))
-            // },
+            GET_DATA_TYPE_KEY => {
+                let get_data_rqst = try!(GetDataRequest::from_parts(rqst_root, trans_id));
+                Ok(RequestType::GetData(get_data_rqst))
+            }
+            PUT_DATA_TYPE_KEY => {
+                let put_data_rqst = try!(PutDataRequest::from_parts(rqst_root, trans_id));
+                Ok(RequestType::PutData(put_data_rqst))
+            }
             unknown => {
                 if let Some(target_key) = forward_compatible_find_node(rqst_root) {
                     let find_node_rqst =
@@ -6,6 +6,8 @@ use message::ping::PingResponse;
 use message::find_node::FindNodeResponse;
 use message::get_peers::GetPeersResponse;
 use message::announce_peer::AnnouncePeerResponse;
+use message::get_data::GetDataResponse;
+use message::put_data::PutDataResponse;
 use error::{DhtError, DhtErrorKind, DhtResult};
 
 pub const RESPONSE_ARGS_KEY: &'static str = "r";
@@ -93,8 +95,9 @@ pub enum ResponseType<'a> {
     Ping(PingResponse<'a>),
     FindNode(FindNodeResponse<'a>),
     GetPeers(GetPeersResponse<'a>),
-    AnnouncePeer(AnnouncePeerResponse<'a>), /* GetData(GetDataResponse<'a>),
-                                             * PutData(PutDataResponse<'a>) */
+    AnnouncePeer(AnnouncePeerResponse<'a>),
+    GetData(GetDataResponse<'a>),
+    PutData(PutDataResponse<'a>),
 }
 
 impl<'a> ResponseType<'a> {
@@ -123,10 +126,12 @@ impl<'a> ResponseType<'a> {
                 Ok(ResponseType::AnnouncePeer(announce_peer_rsp))
             }
             ExpectedResponse::GetData => {
-                unimplemented!();
+                let get_data_rsp = try!(GetDataResponse::from_parts(rqst_root, trans_id));
+                Ok(ResponseType::GetData(get_data_rsp))
             }
             ExpectedResponse::PutData => {
-                unimplemented!();
+                let put_data_rsp = try!(PutDataResponse::from_parts(rqst_root, trans_id));
+                Ok(ResponseType::PutData(put_data_rsp))
             }
             ExpectedResponse::None => Err(DhtError::from_kind(DhtErrorKind::UnsolicitedResponse)),
         }
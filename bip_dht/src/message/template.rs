@@ -0,0 +1,217 @@
+use bip_util::sha::{self, ShaHash};
+use bip_util::bt::{NodeId, InfoHash};
+
+use message;
+use message::find_node::FindNodeRequest;
+use message::get_peers::GetPeersRequest;
+use message::ping::PingRequest;
+use message::want::Want;
+use transaction;
+
+// Requests are encoded once with placeholder values, which are then patched over for each message sent.
+// Since keys are sorted, the node id of every request sits right after the "d1:ad2:id20:" prefix.
+const PLACEHOLDER_TRANS_ID: [u8; transaction::TRANSACTION_ID_BYTES] = [0u8; transaction::TRANSACTION_ID_BYTES];
+
+/// Pre-encoded request with a patchable transaction id and target.
+///
+/// Building a request through its message type allocates an intermediate bencode structure
+/// for every message sent, a template only copies (or overwrites) the encoded bytes.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RequestTemplate {
+    bytes: Vec<u8>,
+    trans_id_offset: usize,
+    target_offset: Option<usize>,
+}
+
+impl RequestTemplate {
+    /// Create a template for a ping request.
+    pub fn ping(node_id: NodeId) -> RequestTemplate {
+        let bytes = PingRequest::new(&PLACEHOLDER_TRANS_ID, node_id).encode();
+
+        RequestTemplate::from_encoded(bytes, None)
+    }
+
+    /// Create a template for a find node request, defaulting to the given target.
+    pub fn find_node(node_id: NodeId, target_id: NodeId, want: Option<Want>) -> RequestTemplate {
+        let bytes = FindNodeRequest::new(&PLACEHOLDER_TRANS_ID, node_id, target_id)
+            .with_want(want)
+            .encode();
+
+        RequestTemplate::from_encoded(bytes, Some(message::TARGET_ID_KEY))
+    }
+
+    /// Create a template for a get peers request, defaulting to the given InfoHash.
+    pub fn get_peers(node_id: NodeId,
+                     info_hash: InfoHash,
+                     want: Option<Want>,
+                     no_seed: bool,
+                     scrape: bool)
+                     -> RequestTemplate {
+        let bytes = GetPeersRequest::new(&PLACEHOLDER_TRANS_ID, node_id, info_hash)
+            .with_want(want)
+            .with_no_seed(no_seed)
+            .with_scrape(scrape)
+            .encode();
+
+        RequestTemplate::from_encoded(bytes, Some(message::INFO_HASH_KEY))
+    }
+
+    fn from_encoded(bytes: Vec<u8>, opt_target_key: Option<&str>) -> RequestTemplate {
+        let node_id_end = message_prefix_len() + sha::SHA_HASH_LEN;
+
+        let trans_id_prefix = value_prefix(message::TRANSACTION_ID_KEY, transaction::TRANSACTION_ID_BYTES);
+        let trans_id_offset = find_last(&bytes, &trans_id_prefix)
+            .expect("bip_dht: Failed To Find Transaction ID In Request Template");
+
+        let target_offset = opt_target_key.map(|target_key| {
+            let target_prefix = value_prefix(target_key, sha::SHA_HASH_LEN);
+
+            find_after(&bytes, node_id_end, &target_prefix)
+                .expect("bip_dht: Failed To Find Target In Request Template")
+        });
+
+        RequestTemplate {
+            bytes: bytes,
+            trans_id_offset: trans_id_offset,
+            target_offset: target_offset,
+        }
+    }
+
+    /// Encode the request with the given transaction id.
+    ///
+    /// Panics if the transaction id is not the length of the ids we generate.
+    pub fn encode(&self, trans_id: &[u8]) -> Vec<u8> {
+        let mut bytes = self.bytes.clone();
+        self.patch(&mut bytes, trans_id, None);
+
+        bytes
+    }
+
+    /// Encode the request with the given transaction id and target.
+    ///
+    /// The target is ignored for templates without one (ping requests).
+    pub fn encode_with_target(&self, trans_id: &[u8], target: ShaHash) -> Vec<u8> {
+        let mut bytes = self.bytes.clone();
+        self.patch(&mut bytes, trans_id, Some(target));
+
+        bytes
+    }
+
+    /// Encode the request into the given buffer, reusing its allocation.
+    ///
+    /// Any existing contents of the buffer are cleared.
+    pub fn encode_into(&self, buffer: &mut Vec<u8>, trans_id: &[u8], opt_target: Option<ShaHash>) {
+        buffer.clear();
+        buffer.extend_from_slice(&self.bytes);
+
+        self.patch(buffer, trans_id, opt_target);
+    }
+
+    fn patch(&self, bytes: &mut [u8], trans_id: &[u8], opt_target: Option<ShaHash>) {
+        let trans_id_end = self.trans_id_offset + transaction::TRANSACTION_ID_BYTES;
+        bytes[self.trans_id_offset..trans_id_end].copy_from_slice(trans_id);
+
+        if let (Some(offset), Some(target)) = (self.target_offset, opt_target) {
+            bytes[offset..offset + sha::SHA_HASH_LEN].copy_from_slice(target.as_ref());
+        }
+    }
+}
+
+/// Length of the bytes that come before the node id in every request.
+fn message_prefix_len() -> usize {
+    value_prefix(message::NODE_ID_KEY, sha::SHA_HASH_LEN).len() + "d1:ad".len()
+}
+
+/// Bencoded key followed by the length prefix of its bytes value.
+fn value_prefix(key: &str, value_len: usize) -> Vec<u8> {
+    format!("{}:{}{}:", key.len(), key, value_len).into_bytes()
+}
+
+/// Offset of the value following the last occurrence of prefix.
+///
+/// The transaction id is the second to last key, so this avoids matching bytes within the arguments.
+fn find_last(bytes: &[u8], prefix: &[u8]) -> Option<usize> {
+    bytes.windows(prefix.len())
+        .rposition(|window| window == prefix)
+        .map(|position| position + prefix.len())
+}
+
+/// Offset of the value following the first occurrence of prefix at or after start.
+fn find_after(bytes: &[u8], start: usize, prefix: &[u8]) -> Option<usize> {
+    bytes[start..]
+        .windows(prefix.len())
+        .position(|window| window == prefix)
+        .map(|position| start + position + prefix.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use bip_util::bt::{self, NodeId};
+    use bip_util::sha::ShaHash;
+
+    use message::find_node::FindNodeRequest;
+    use message::get_peers::GetPeersRequest;
+    use message::ping::PingRequest;
+    use message::template::RequestTemplate;
+    use message::want::Want;
+
+    const TRANS_ID: &'static [u8] = &[1, 2, 3, 4, 5, 6, 7, 8];
+
+    fn node_id() -> NodeId {
+        [7u8; bt::NODE_ID_LEN].into()
+    }
+
+    #[test]
+    fn positive_ping_template_matches_request() {
+        let template = RequestTemplate::ping(node_id());
+
+        assert_eq!(template.encode(TRANS_ID), PingRequest::new(TRANS_ID, node_id()).encode());
+    }
+
+    #[test]
+    fn positive_find_node_template_patches_target() {
+        let target_one: ShaHash = [1u8; bt::NODE_ID_LEN].into();
+        let target_two: ShaHash = [2u8; bt::NODE_ID_LEN].into();
+        let want = Some(Want::new(true, false));
+        let template = RequestTemplate::find_node(node_id(), target_one, want);
+
+        let expected_one = FindNodeRequest::new(TRANS_ID, node_id(), target_one).with_want(want).encode();
+        let expected_two = FindNodeRequest::new(TRANS_ID, node_id(), target_two).with_want(want).encode();
+
+        assert_eq!(template.encode(TRANS_ID), expected_one);
+        assert_eq!(template.encode_with_target(TRANS_ID, target_two), expected_two);
+    }
+
+    #[test]
+    fn positive_get_peers_template_matches_request() {
+        let info_hash: ShaHash = [3u8; bt::INFO_HASH_LEN].into();
+        let template = RequestTemplate::get_peers(node_id(), info_hash, None, true, true);
+
+        let expected = GetPeersRequest::new(TRANS_ID, node_id(), info_hash)
+            .with_no_seed(true)
+            .with_scrape(true)
+            .encode();
+
+        assert_eq!(template.encode(TRANS_ID), expected);
+    }
+
+    #[test]
+    fn positive_encode_into_reuses_buffer() {
+        let template = RequestTemplate::ping(node_id());
+        let other_trans_id = [8u8, 7, 6, 5, 4, 3, 2, 1];
+
+        let mut buffer = Vec::new();
+        template.encode_into(&mut buffer, TRANS_ID, None);
+        template.encode_into(&mut buffer, &other_trans_id, None);
+
+        assert_eq!(buffer, PingRequest::new(&other_trans_id, node_id()).encode());
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_encode_wrong_trans_id_len() {
+        let template = RequestTemplate::ping(node_id());
+
+        template.encode(&[1, 2, 3]);
+    }
+}
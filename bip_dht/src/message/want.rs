@@ -0,0 +1,95 @@
+use bip_bencode::Bencode;
+
+// Address family identifiers for the want argument (BEP 32)
+const IPV4_WANT_KEY: &'static str = "n4";
+const IPV6_WANT_KEY: &'static str = "n6";
+
+/// Address families of nodes requested in a find_node or get_peers request (BEP 32).
+///
+/// If a request does not specify what it wants, nodes of the same address
+/// family that the request was sent over should be returned.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Want {
+    ipv4: bool,
+    ipv6: bool,
+}
+
+impl Want {
+    /// Create a new Want for the given address families.
+    pub fn new(ipv4: bool, ipv6: bool) -> Want {
+        Want {
+            ipv4: ipv4,
+            ipv6: ipv6,
+        }
+    }
+
+    /// Create a Want from a bencoded list of address family identifiers.
+    ///
+    /// Unrecognized identifiers are ignored.
+    pub fn from_list<'a>(list: &[Bencode<'a>]) -> Want {
+        let mut want = Want::new(false, false);
+
+        for family in list.iter().filter_map(|bencode| bencode.bytes()) {
+            if family == IPV4_WANT_KEY.as_bytes() {
+                want.ipv4 = true;
+            } else if family == IPV6_WANT_KEY.as_bytes() {
+                want.ipv6 = true;
+            }
+        }
+
+        want
+    }
+
+    /// Whether or not IPv4 nodes were requested.
+    pub fn ipv4(&self) -> bool {
+        self.ipv4
+    }
+
+    /// Whether or not IPv6 nodes were requested.
+    pub fn ipv6(&self) -> bool {
+        self.ipv6
+    }
+
+    /// Bencoded list of address family identifiers for this Want.
+    pub fn to_bencode<'a>(&self) -> Bencode<'a> {
+        let mut families = Vec::with_capacity(2);
+
+        if self.ipv4 {
+            families.push(ben_bytes!(IPV4_WANT_KEY));
+        }
+        if self.ipv6 {
+            families.push(ben_bytes!(IPV6_WANT_KEY));
+        }
+
+        Bencode::List(families)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Want;
+
+    #[test]
+    fn positive_want_from_list_both() {
+        let list = vec![ben_bytes!("n4"), ben_bytes!("n6")];
+
+        assert_eq!(Want::new(true, true), Want::from_list(&list));
+    }
+
+    #[test]
+    fn positive_want_from_list_ignores_unknown() {
+        let list = vec![ben_bytes!("n6"), ben_bytes!("n8"), ben_int!(4)];
+
+        assert_eq!(Want::new(false, true), Want::from_list(&list));
+    }
+
+    #[test]
+    fn positive_want_bencode_round_trip() {
+        let want = Want::new(true, false);
+
+        match want.to_bencode() {
+            ::bip_bencode::Bencode::List(list) => assert_eq!(want, Want::from_list(&list)),
+            _ => panic!("Want Was Not Encoded As A List"),
+        }
+    }
+}
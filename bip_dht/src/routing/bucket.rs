@@ -2,6 +2,7 @@
 #![allow(unused)]
 
 use std::iter::Filter;
+use std::mem;
 use std::net::{Ipv4Addr, SocketAddrV4, SocketAddr};
 use std::slice::Iter;
 
@@ -12,6 +13,16 @@ use routing::node::{Node, NodeStatus};
 /// Maximum number of nodes that should reside in any bucket.
 pub const MAX_BUCKET_SIZE: usize = 8;
 
+/// Outcome of adding a node to a bucket.
+pub enum BucketInsert {
+    /// Node was new to the bucket, along with the node it replaced if that node had ever responded to us.
+    Added(Option<Node>),
+    /// Node was already in the bucket, or it was ignored because it is bad.
+    Unchanged,
+    /// Bucket is full of nodes with an equal or better status than the node.
+    Full,
+}
+
 /// Bucket containing Nodes with identical bit prefixes.
 pub struct Bucket {
     nodes: [Node; MAX_BUCKET_SIZE],
@@ -60,12 +71,10 @@ impl Bucket {
     }
 
     /// Attempt to add the given Node to the bucket if it is not in a bad state.
-    ///
-    /// Returns false if the Node could not be placed in the bucket because it is full.
-    pub fn add_node(&mut self, new_node: Node) -> bool {
+    pub fn add_node(&mut self, new_node: Node) -> BucketInsert {
         let new_node_status = new_node.status();
         if new_node_status == NodeStatus::Bad {
-            return true;
+            return BucketInsert::Unchanged;
         }
 
         // See if this node is already in the table, in that case replace it if it
@@ -74,10 +83,11 @@ impl Bucket {
             let other_node_status = self.nodes[index].status();
 
             if new_node_status >= other_node_status {
+                new_node.inherit_rtt(&self.nodes[index]);
                 self.nodes[index] = new_node;
             }
 
-            return true;
+            return BucketInsert::Unchanged;
         }
 
         // See if any lower priority nodes are present in the table, we cant do
@@ -85,11 +95,16 @@ impl Bucket {
         // nodes in the case of a good status which helps with stability.
         let replace_index = self.nodes.iter().position(|node| node.status() < new_node_status);
         if let Some(index) = replace_index {
-            self.nodes[index] = new_node;
+            let old_node = mem::replace(&mut self.nodes[index], new_node);
 
-            true
+            // Placeholder nodes from when the bucket was created have never responded
+            if old_node.ever_responded() {
+                BucketInsert::Added(Some(old_node))
+            } else {
+                BucketInsert::Added(None)
+            }
         } else {
-            false
+            BucketInsert::Full
         }
     }
 }
@@ -2,14 +2,18 @@
 #![allow(unused)]
 
 use std::cell::Cell;
+use std::cmp;
 use std::fmt::{self, Debug, Formatter};
 use std::hash::{Hash, Hasher};
 use std::net::SocketAddr;
+use std::time;
 
 use bip_util::bt::NodeId;
 use bip_util::test;
 use chrono::{Duration, DateTime, UTC};
 
+use transaction::TransactionID;
+
 // TODO: Should remove as_* functions and replace them with from_requested, from_responded, etc to hide the logic
 // of the nodes initial status.
 
@@ -21,10 +25,14 @@ use chrono::{Duration, DateTime, UTC};
 // TODO: Should we be storing a SocketAddr instead of a SocketAddrV4?
 
 /// Maximum wait period before a node becomes questionable.
-const MAX_LAST_SEEN_MINS: i64 = 15;
+pub const MAX_LAST_SEEN_MINS: i64 = 15;
 
 /// Maximum number of requests before a Questionable node becomes Bad.
-const MAX_REFRESH_REQUESTS: usize = 2;
+pub const MAX_REFRESH_REQUESTS: usize = 2;
+
+/// Bounds on the query timeout calculated from a node's round trip time history.
+const MIN_QUERY_TIMEOUT_MS: u64 = 250;
+const MAX_QUERY_TIMEOUT_MS: u64 = 5000;
 
 /// Status of the node.
 /// Ordering of the enumerations is important, variants higher
@@ -36,6 +44,33 @@ pub enum NodeStatus {
     Good,
 }
 
+/// Timings that move a node between the good, questionable, and bad states.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NodeLifecycle {
+    questionable_after: Duration,
+    max_refresh_requests: usize,
+}
+
+impl NodeLifecycle {
+    /// Create a new NodeLifecycle where nodes become questionable after the given period of
+    /// inactivity, and bad after the given number of unanswered requests while questionable.
+    pub fn new(questionable_after: time::Duration, max_refresh_requests: usize) -> NodeLifecycle {
+        NodeLifecycle {
+            questionable_after: Duration::from_std(questionable_after).unwrap_or(Duration::max_value()),
+            max_refresh_requests: max_refresh_requests,
+        }
+    }
+}
+
+impl Default for NodeLifecycle {
+    fn default() -> NodeLifecycle {
+        NodeLifecycle {
+            questionable_after: Duration::minutes(MAX_LAST_SEEN_MINS),
+            max_refresh_requests: MAX_REFRESH_REQUESTS,
+        }
+    }
+}
+
 /// Node participating in the dht.
 pub struct Node {
     id: NodeId,
@@ -43,6 +78,12 @@ pub struct Node {
     last_request: Cell<Option<DateTime<UTC>>>,
     last_response: Cell<Option<DateTime<UTC>>>,
     refresh_requests: Cell<usize>,
+    // Transaction id and time of our most recent request to the node, which a response has to match to be sampled
+    pending_request: Cell<Option<(TransactionID, DateTime<UTC>)>>,
+    // Smoothed round trip time and its variation, in milliseconds (RFC 6298)
+    smoothed_rtt: Cell<Option<u64>>,
+    rtt_variation: Cell<u64>,
+    lifecycle: Cell<NodeLifecycle>,
 }
 
 impl Node {
@@ -54,6 +95,10 @@ impl Node {
             last_response: Cell::new(Some(UTC::now())),
             last_request: Cell::new(None),
             refresh_requests: Cell::new(0),
+            pending_request: Cell::new(None),
+            smoothed_rtt: Cell::new(None),
+            rtt_variation: Cell::new(0),
+            lifecycle: Cell::new(NodeLifecycle::default()),
         }
     }
 
@@ -69,6 +114,10 @@ impl Node {
             last_response: Cell::new(Some(last_response)),
             last_request: Cell::new(None),
             refresh_requests: Cell::new(0),
+            pending_request: Cell::new(None),
+            smoothed_rtt: Cell::new(None),
+            rtt_variation: Cell::new(0),
+            lifecycle: Cell::new(NodeLifecycle::default()),
         }
     }
 
@@ -80,11 +129,27 @@ impl Node {
             last_response: Cell::new(None),
             last_request: Cell::new(None),
             refresh_requests: Cell::new(0),
+            pending_request: Cell::new(None),
+            smoothed_rtt: Cell::new(None),
+            rtt_variation: Cell::new(0),
+            lifecycle: Cell::new(NodeLifecycle::default()),
         }
     }
 
-    /// Record that we sent the node a request.
-    pub fn local_request(&self) {
+    /// Set the timings used to calculate the status of the node.
+    pub fn set_lifecycle(&self, lifecycle: NodeLifecycle) {
+        self.lifecycle.set(lifecycle);
+    }
+
+    /// Whether or not the node has ever responded to us.
+    pub fn ever_responded(&self) -> bool {
+        self.last_response.get().is_some()
+    }
+
+    /// Record that we sent the node a request with the given transaction id.
+    pub fn local_request(&self, trans_id: TransactionID) {
+        self.pending_request.set(Some((trans_id, UTC::now())));
+
         if self.status() != NodeStatus::Good {
             let num_requests = self.refresh_requests.get() + 1;
 
@@ -104,6 +169,66 @@ impl Node {
         self.refresh_requests.set(0);
     }
 
+    /// Record that the node sent us a response to the request with the given transaction id.
+    ///
+    /// If it matches our most recent request to the node, the round trip time is sampled
+    /// and folded into the node's smoothed round trip time. Responses to older requests are
+    /// not sampled, since we no longer know when those requests were sent.
+    pub fn response_for(&self, trans_id: TransactionID) {
+        if let Some((request_id, request_time)) = self.pending_request.get() {
+            if request_id == trans_id {
+                let sample_ms = (UTC::now() - request_time).num_milliseconds();
+
+                self.record_rtt(if sample_ms < 0 { 0 } else { sample_ms as u64 });
+                self.pending_request.set(None);
+            }
+        }
+
+        self.remote_response();
+    }
+
+    /// Carry over round trip time history from a previous instance of the same node.
+    pub fn inherit_rtt(&self, other: &Node) {
+        if self.smoothed_rtt.get().is_none() {
+            self.smoothed_rtt.set(other.smoothed_rtt.get());
+            self.rtt_variation.set(other.rtt_variation.get());
+        }
+    }
+
+    /// Smoothed round trip time for the node in milliseconds, if we have any history.
+    pub fn smoothed_rtt(&self) -> Option<u64> {
+        self.smoothed_rtt.get()
+    }
+
+    /// Timeout in milliseconds to use for a query to this node.
+    ///
+    /// If we have no round trip time history for the node, the given default is used.
+    pub fn query_timeout_ms(&self, default_ms: u64) -> u64 {
+        match self.smoothed_rtt.get() {
+            Some(srtt) => {
+                let timeout = srtt + 4 * self.rtt_variation.get();
+
+                cmp::max(MIN_QUERY_TIMEOUT_MS, cmp::min(MAX_QUERY_TIMEOUT_MS, timeout))
+            }
+            None => default_ms,
+        }
+    }
+
+    fn record_rtt(&self, sample_ms: u64) {
+        match self.smoothed_rtt.get() {
+            Some(srtt) => {
+                let deviation = if srtt > sample_ms { srtt - sample_ms } else { sample_ms - srtt };
+
+                self.rtt_variation.set((3 * self.rtt_variation.get() + deviation) / 4);
+                self.smoothed_rtt.set(Some((7 * srtt + sample_ms) / 8));
+            }
+            None => {
+                self.rtt_variation.set(sample_ms / 2);
+                self.smoothed_rtt.set(Some(sample_ms));
+            }
+        }
+    }
+
     pub fn id(&self) -> NodeId {
         self.id
     }
@@ -181,6 +306,10 @@ impl Clone for Node {
             last_response: self.last_response.clone(),
             last_request: self.last_request.clone(),
             refresh_requests: self.refresh_requests.clone(),
+            pending_request: self.pending_request.clone(),
+            smoothed_rtt: self.smoothed_rtt.clone(),
+            rtt_variation: self.rtt_variation.clone(),
+            lifecycle: self.lifecycle.clone(),
         }
     }
 }
@@ -188,12 +317,13 @@ impl Clone for Node {
 impl Debug for Node {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
         f.write_fmt(format_args!("Node{{ id: {:?}, addr: {:?}, last_request: {:?}, \
-                                  last_response: {:?}, refresh_requests: {:?} }}",
+                                  last_response: {:?}, refresh_requests: {:?}, smoothed_rtt: {:?} }}",
                                  self.id,
                                  self.addr,
                                  self.last_request.get(),
                                  self.last_response.get(),
-                                 self.refresh_requests.get()))
+                                 self.refresh_requests.get(),
+                                 self.smoothed_rtt.get()))
     }
 }
 
@@ -214,7 +344,7 @@ fn recently_responded(node: &Node, curr_time: DateTime<UTC>) -> NodeStatus {
     };
 
     // Check if node has recently responded to us
-    let max_last_response = Duration::minutes(MAX_LAST_SEEN_MINS);
+    let max_last_response = node.lifecycle.get().questionable_after;
     if since_response < max_last_response {
         NodeStatus::Good
     } else {
@@ -228,7 +358,7 @@ fn recently_responded(node: &Node, curr_time: DateTime<UTC>) -> NodeStatus {
 /// Returns the final status of the node given that the first scenario found the node to be
 /// Questionable.
 fn recently_requested(node: &Node, curr_time: DateTime<UTC>) -> NodeStatus {
-    let max_last_request = Duration::minutes(MAX_LAST_SEEN_MINS);
+    let max_last_request = node.lifecycle.get().questionable_after;
 
     // Check if the node has recently request from us
     if let Some(request_time) = node.last_request.get() {
@@ -240,7 +370,7 @@ fn recently_requested(node: &Node, curr_time: DateTime<UTC>) -> NodeStatus {
     }
 
     // Check if we have request from node multiple times already without response
-    if node.refresh_requests.get() < MAX_REFRESH_REQUESTS {
+    if node.refresh_requests.get() < node.lifecycle.get().max_refresh_requests {
         NodeStatus::Questionable
     } else {
         NodeStatus::Bad
@@ -256,7 +386,12 @@ mod tests {
     use bip_util::test as bip_test;
     use chrono::Duration;
 
-    use routing::node::{Node, NodeStatus};
+    use routing::node::{Node, NodeLifecycle, NodeStatus};
+    use transaction::TransactionID;
+
+    fn trans_id(id: u8) -> TransactionID {
+        TransactionID::from_bytes(&[id; 8]).unwrap()
+    }
 
     #[test]
     fn positive_encode_node() {
@@ -340,12 +475,80 @@ mod tests {
                                          bip_test::dummy_socket_addr_v4());
 
         for _ in 0..super::MAX_REFRESH_REQUESTS {
-            node.local_request();
+            node.local_request(trans_id(0));
         }
 
         assert_eq!(node.status(), NodeStatus::Bad);
     }
 
+    #[test]
+    fn positive_node_idle_custom_lifecycle() {
+        let node = Node::as_good(bip_test::dummy_node_id(), bip_test::dummy_socket_addr_v4());
+        node.set_lifecycle(NodeLifecycle::new(Duration::minutes(1).to_std().unwrap(), 1));
+
+        let idle_time = bip_test::travel_into_past(Duration::minutes(2));
+        node.last_response.set(Some(idle_time));
+
+        assert_eq!(node.status(), NodeStatus::Questionable);
+
+        node.local_request(trans_id(0));
+        assert_eq!(node.status(), NodeStatus::Bad);
+    }
+
+    #[test]
+    fn positive_query_timeout_default_without_history() {
+        let node = Node::as_good(bip_test::dummy_node_id(), bip_test::dummy_socket_addr_v4());
+
+        assert_eq!(1500, node.query_timeout_ms(1500));
+    }
+
+    #[test]
+    fn positive_query_timeout_from_history() {
+        let node = Node::as_good(bip_test::dummy_node_id(), bip_test::dummy_socket_addr_v4());
+
+        node.pending_request.set(Some((trans_id(0), bip_test::travel_into_past(Duration::milliseconds(100)))));
+        node.response_for(trans_id(0));
+
+        let srtt = node.smoothed_rtt().unwrap();
+        assert!(srtt >= 100);
+
+        let timeout = node.query_timeout_ms(1500);
+        assert!(timeout >= super::MIN_QUERY_TIMEOUT_MS);
+        assert!(timeout < 1500);
+    }
+
+    #[test]
+    fn positive_query_timeout_bounded() {
+        let node = Node::as_good(bip_test::dummy_node_id(), bip_test::dummy_socket_addr_v4());
+
+        node.record_rtt(60000);
+
+        assert_eq!(super::MAX_QUERY_TIMEOUT_MS, node.query_timeout_ms(1500));
+    }
+
+    #[test]
+    fn negative_query_timeout_ignores_unmatched_response() {
+        let node = Node::as_good(bip_test::dummy_node_id(), bip_test::dummy_socket_addr_v4());
+
+        // Response to a request that timed out long ago should not be sampled
+        node.pending_request.set(Some((trans_id(1), bip_test::travel_into_past(Duration::minutes(5)))));
+        node.response_for(trans_id(0));
+
+        assert_eq!(None, node.smoothed_rtt());
+        assert_eq!(1500, node.query_timeout_ms(1500));
+    }
+
+    #[test]
+    fn positive_inherit_rtt() {
+        let old_node = Node::as_good(bip_test::dummy_node_id(), bip_test::dummy_socket_addr_v4());
+        old_node.record_rtt(100);
+
+        let new_node = Node::as_good(bip_test::dummy_node_id(), bip_test::dummy_socket_addr_v4());
+        new_node.inherit_rtt(&old_node);
+
+        assert_eq!(Some(100), new_node.smoothed_rtt());
+    }
+
     #[test]
     fn positive_good_status_ordering() {
         assert!(NodeStatus::Good > NodeStatus::Questionable);
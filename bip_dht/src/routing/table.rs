@@ -2,16 +2,28 @@
 #![allow(unused)]
 
 use std::iter::Filter;
+use std::mem;
+use std::net::SocketAddr;
 use std::slice::Iter;
 
 use bip_util::bt::NodeId;
-use bip_util::sha::{self, ShaHash, XorRep};
-use rand;
 
-use routing::bucket::{self, Bucket};
-use routing::node::{Node, NodeStatus};
+use distance::{leading_bit_count, bucket_placement};
+use routing::bucket::{self, Bucket, BucketInsert};
+use routing::node::{Node, NodeLifecycle, NodeStatus};
 
-pub const MAX_BUCKETS: usize = sha::SHA_HASH_LEN * 8;
+pub use distance::MAX_BUCKETS;
+
+/// Change to the set of nodes held in a RoutingTable.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TableEvent {
+    /// Node was added to the table.
+    NodeAdded(NodeId, SocketAddr),
+    /// Node was removed from the table.
+    ///
+    /// Only recorded once a bad node is replaced or dropped during a bucket split.
+    NodeRemoved(NodeId, SocketAddr),
+}
 
 /// Routing table containing a table of routing nodes as well
 /// as the id of the local node participating in the dht.
@@ -20,19 +32,33 @@ pub struct RoutingTable {
     // of the last bucket in the buckets array.
     buckets: Vec<Bucket>,
     node_id: NodeId,
+    lifecycle: NodeLifecycle,
+    events: Vec<TableEvent>,
 }
 
 impl RoutingTable {
     /// Create a new RoutingTable with the given node id as our id.
     pub fn new(node_id: NodeId) -> RoutingTable {
+        RoutingTable::with_lifecycle(node_id, NodeLifecycle::default())
+    }
+
+    /// Create a new RoutingTable with the given node id as our id, and the given lifecycle for added nodes.
+    pub fn with_lifecycle(node_id: NodeId, lifecycle: NodeLifecycle) -> RoutingTable {
         let buckets = vec![Bucket::new()];
 
         RoutingTable {
             buckets: buckets,
             node_id: node_id,
+            lifecycle: lifecycle,
+            events: Vec::new(),
         }
     }
 
+    /// Take the nodes that were added to or removed from the table since the last call.
+    pub fn take_events(&mut self) -> Vec<TableEvent> {
+        mem::replace(&mut self.events, Vec::new())
+    }
+
     /// Return the node id of the RoutingTable.
     pub fn node_id(&self) -> NodeId {
         self.node_id
@@ -81,6 +107,13 @@ impl RoutingTable {
 
     /// Add the node to the RoutingTable if there is space for it.
     pub fn add_node(&mut self, node: Node) {
+        node.set_lifecycle(self.lifecycle);
+
+        self.insert_node(node, true);
+    }
+
+    /// Add the node to the RoutingTable, optionally recording the change as an event.
+    fn insert_node(&mut self, node: Node, record: bool) {
         // Doing some checks and calculations here, outside of the recursion
         if node.status() == NodeStatus::Bad {
             return;
@@ -89,20 +122,31 @@ impl RoutingTable {
 
         // Should not add a node that has the same id as us
         if num_same_bits != MAX_BUCKETS {
-            self.bucket_node(node, num_same_bits);
+            self.bucket_node(node, num_same_bits, record);
         }
     }
 
     /// Recursively tries to place the node into some bucket.
-    fn bucket_node(&mut self, node: Node, num_same_bits: usize) {
+    fn bucket_node(&mut self, node: Node, num_same_bits: usize, record: bool) {
         let bucket_index = bucket_placement(num_same_bits, self.buckets.len());
 
         // Try to place in correct bucket
-        if !self.buckets[bucket_index].add_node(node.clone()) {
-            // Bucket was full, try to split it
-            if self.split_bucket(bucket_index) {
-                // Bucket split successfully, try to add again
-                self.bucket_node(node.clone(), num_same_bits);
+        match self.buckets[bucket_index].add_node(node.clone()) {
+            BucketInsert::Added(opt_old_node) => {
+                if record {
+                    if let Some(old_node) = opt_old_node {
+                        self.events.push(TableEvent::NodeRemoved(old_node.id(), old_node.addr()));
+                    }
+                    self.events.push(TableEvent::NodeAdded(node.id(), node.addr()));
+                }
+            }
+            BucketInsert::Unchanged => (),
+            BucketInsert::Full => {
+                // Bucket was full, try to split it
+                if self.split_bucket(bucket_index) {
+                    // Bucket split successfully, try to add again
+                    self.bucket_node(node.clone(), num_same_bits, record);
+                }
             }
         }
     }
@@ -127,8 +171,13 @@ impl RoutingTable {
         self.buckets.push(Bucket::new());
         self.buckets.push(Bucket::new());
 
+        // Nodes are only moving between buckets, unless they went bad and are dropped
         for node in split_bucket.iter() {
-            self.add_node(node.clone());
+            if node.status() != NodeStatus::Bad {
+                self.insert_node(node.clone(), false);
+            } else if node.ever_responded() {
+                self.events.push(TableEvent::NodeRemoved(node.id(), node.addr()));
+            }
         }
 
         true
@@ -140,41 +189,6 @@ fn can_split_bucket(num_buckets: usize, bucket_index: usize) -> bool {
     bucket_index == num_buckets - 1 && bucket_index != MAX_BUCKETS - 1
 }
 
-/// Generates a random NodeId.
-///
-/// TODO: Shouldnt use this in the future to get an id for the routing table,
-/// generate one from the security module to be compliant with the spec.
-pub fn random_node_id() -> NodeId {
-    let mut random_sha_hash = [0u8; sha::SHA_HASH_LEN];
-
-    for byte in random_sha_hash.iter_mut() {
-        *byte = rand::random::<u8>();
-    }
-
-    ShaHash::from(random_sha_hash)
-}
-
-/// Number of leading bits that are identical between the local and remote node ids.
-pub fn leading_bit_count(local_node: NodeId, remote_node: NodeId) -> usize {
-    let diff_id = local_node ^ remote_node;
-
-    diff_id.bits().take_while(|&x| x == XorRep::Same).count()
-}
-
-/// Take the number of leading bits that are the same between our node and the remote
-/// node and calculate a bucket index for that node id.
-fn bucket_placement(num_same_bits: usize, num_buckets: usize) -> usize {
-    // The index that the node should be placed in *eventually*, meaning
-    // when we create enough buckets for that bucket to appear.
-    let ideal_index = num_same_bits;
-
-    if ideal_index >= num_buckets {
-        num_buckets - 1
-    } else {
-        ideal_index
-    }
-}
-
 // ----------------------------------------------------------------------------//
 
 #[derive(Copy, Clone)]
@@ -453,19 +467,86 @@ mod tests {
     use bip_util::bt::{self, NodeId};
     use bip_util::test as bip_test;
 
-    use routing::table::{self, RoutingTable, BucketContents};
+    use routing::table::{self, RoutingTable, BucketContents, TableEvent};
     use routing::bucket;
-    use routing::node::Node;
+    use routing::node::{self, Node, NodeStatus};
+    use transaction::TransactionID;
+
+    #[test]
+    fn positive_add_node_events() {
+        let table_id = [1u8; bt::NODE_ID_LEN];
+        let mut table = RoutingTable::new(table_id.into());
 
-    // TODO: Move into bip_util crate
-    fn flip_id_bit_at_index(node_id: NodeId, index: usize) -> NodeId {
-        let mut id_bytes: [u8; bt::NODE_ID_LEN] = node_id.into();
-        let (byte_index, bit_index) = (index / 8, index % 8);
+        let mut node_id = table_id;
+        node_id[0] |= 128;
+
+        let block_addrs = bip_test::dummy_block_socket_addrs(bucket::MAX_BUCKET_SIZE as u16);
+        for addr in block_addrs.iter() {
+            table.add_node(Node::as_good(node_id.into(), *addr));
+        }
+        // Re-adding a node that is already in the table does not change the table
+        table.add_node(Node::as_good(node_id.into(), block_addrs[0]));
+
+        let expected: Vec<TableEvent> = block_addrs.iter()
+            .map(|addr| TableEvent::NodeAdded(node_id.into(), *addr))
+            .collect();
+        assert_eq!(table.take_events(), expected);
+        assert!(table.take_events().is_empty());
+    }
+
+    #[test]
+    fn positive_replace_node_events() {
+        let table_id = [1u8; bt::NODE_ID_LEN];
+        let mut table = RoutingTable::new(table_id.into());
+
+        let mut node_id = table_id;
+        node_id[0] |= 128;
+
+        let block_addrs = bip_test::dummy_block_socket_addrs((bucket::MAX_BUCKET_SIZE + 1) as u16);
+        for addr in block_addrs[..bucket::MAX_BUCKET_SIZE].iter() {
+            table.add_node(Node::as_questionable(node_id.into(), *addr));
+        }
+        table.take_events();
+
+        // Good node takes the place of the first questionable node instead of splitting the bucket
+        let replacement_addr = block_addrs[bucket::MAX_BUCKET_SIZE];
+        table.add_node(Node::as_good(node_id.into(), replacement_addr));
+
+        assert_eq!(table.take_events(),
+                   vec![TableEvent::NodeRemoved(node_id.into(), block_addrs[0]),
+                        TableEvent::NodeAdded(node_id.into(), replacement_addr)]);
+        assert_eq!(table.buckets.len(), 1);
+    }
+
+    #[test]
+    fn positive_split_bucket_bad_node_events() {
+        let table_id = [1u8; bt::NODE_ID_LEN];
+        let mut table = RoutingTable::new(table_id.into());
+
+        let mut node_id = table_id;
+        node_id[0] |= 128;
+
+        let block_addrs = bip_test::dummy_block_socket_addrs(bucket::MAX_BUCKET_SIZE as u16);
+        for addr in block_addrs.iter() {
+            table.add_node(Node::as_questionable(node_id.into(), *addr));
+        }
+        table.take_events();
+
+        // Ping the first node until it goes bad
+        let trans_id = TransactionID::from_bytes(&[0u8; 8]).unwrap();
+        {
+            let bad_node = table.buckets[0].iter().find(|node| node.addr() == block_addrs[0]).unwrap();
+            for _ in 0..node::MAX_REFRESH_REQUESTS {
+                bad_node.local_request(trans_id);
+            }
+            assert_eq!(bad_node.status(), NodeStatus::Bad);
+        }
 
-        let actual_bit_index = 7 - bit_index;
-        id_bytes[byte_index] ^= 1 << actual_bit_index;
+        assert!(table.split_bucket(0));
 
-        id_bytes.into()
+        assert_eq!(table.take_events(),
+                   vec![TableEvent::NodeRemoved(node_id.into(), block_addrs[0])]);
+        assert!(table.buckets.iter().all(|bucket| bucket.iter().all(|node| node.addr() != block_addrs[0])));
     }
 
     #[test]
@@ -604,7 +685,7 @@ mod tests {
         let block_addrs = bip_test::dummy_block_socket_addrs(bucket::MAX_BUCKET_SIZE as u16);
         for bit_flip_index in 0..table::MAX_BUCKETS {
             for addr_index in 0..block_addrs.len() {
-                let bucket_node_id = flip_id_bit_at_index(table_id.into(), bit_flip_index);
+                let bucket_node_id = NodeId::from(table_id).flip_bit(bit_flip_index);
 
                 table.add_node(Node::as_good(bucket_node_id, block_addrs[addr_index]));
             }
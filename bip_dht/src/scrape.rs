@@ -0,0 +1,181 @@
+use std::net::IpAddr;
+
+use bip_util::sha::ShaHash;
+
+/// Length of a scrape bloom filter in bytes (BEP 33).
+pub const SCRAPE_FILTER_LEN: usize = 256;
+
+const SCRAPE_FILTER_BITS: usize = SCRAPE_FILTER_LEN * 8;
+const SCRAPE_FILTER_HASHES: usize = 2;
+
+/// Bloom filter of peer addresses used to scrape a swarm through the DHT (BEP 33).
+///
+/// Filters returned from different nodes can be merged together, which gives an estimate
+/// for the union of all addresses stored across those nodes without double counting.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ScrapeFilter {
+    bits: Vec<u8>,
+}
+
+impl ScrapeFilter {
+    /// Create a new, empty, ScrapeFilter.
+    pub fn new() -> ScrapeFilter {
+        ScrapeFilter { bits: vec![0u8; SCRAPE_FILTER_LEN] }
+    }
+
+    /// Create a ScrapeFilter from the given bytes, or None if the length is invalid.
+    pub fn from_bytes(bytes: &[u8]) -> Option<ScrapeFilter> {
+        if bytes.len() == SCRAPE_FILTER_LEN {
+            Some(ScrapeFilter { bits: bytes.to_vec() })
+        } else {
+            None
+        }
+    }
+
+    /// Insert the given address into the filter.
+    pub fn insert(&mut self, ip: IpAddr) {
+        let hash = match ip {
+            IpAddr::V4(v4_ip) => ShaHash::from_bytes(&v4_ip.octets()),
+            IpAddr::V6(v6_ip) => ShaHash::from_bytes(&v6_ip.octets()),
+        };
+        let hash_bytes: &[u8] = hash.as_ref();
+
+        for chunk in hash_bytes.chunks(2).take(SCRAPE_FILTER_HASHES) {
+            let index = (chunk[0] as usize | (chunk[1] as usize) << 8) % SCRAPE_FILTER_BITS;
+
+            self.bits[index / 8] |= 0x01 << (index % 8);
+        }
+    }
+
+    /// Merge the given filter into this filter.
+    pub fn merge(&mut self, other: &ScrapeFilter) {
+        for (dst, src) in self.bits.iter_mut().zip(other.bits.iter()) {
+            *dst |= *src;
+        }
+    }
+
+    /// Estimate the number of distinct addresses inserted into the filter.
+    pub fn estimate(&self) -> usize {
+        let num_zero = self.bits.iter().map(|byte| byte.count_zeros() as usize).sum::<usize>();
+        // A saturated filter would give us an infinite estimate, cap it at the largest we can give
+        let num_zero = if num_zero == 0 { 1 } else { num_zero };
+
+        let m = SCRAPE_FILTER_BITS as f64;
+        let k = SCRAPE_FILTER_HASHES as f64;
+        let size = (num_zero as f64 / m).ln() / (k * (1.0 - 1.0 / m).ln());
+
+        size.round() as usize
+    }
+
+    /// Raw bytes of the filter.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Estimated size of a swarm, gathered from the DHT without announcing (BEP 33).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ScrapeEstimate {
+    num_seeds: usize,
+    num_leechers: usize,
+}
+
+impl ScrapeEstimate {
+    pub fn new(seeds: &ScrapeFilter, leechers: &ScrapeFilter) -> ScrapeEstimate {
+        ScrapeEstimate {
+            num_seeds: seeds.estimate(),
+            num_leechers: leechers.estimate(),
+        }
+    }
+
+    /// Estimated number of seeds in the swarm.
+    pub fn num_seeds(&self) -> usize {
+        self.num_seeds
+    }
+
+    /// Estimated number of leechers in the swarm.
+    pub fn num_leechers(&self) -> usize {
+        self.num_leechers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use scrape::{self, ScrapeFilter};
+
+    #[test]
+    fn positive_empty_filter_estimate() {
+        let filter = ScrapeFilter::new();
+
+        assert!(filter.as_bytes().iter().all(|&byte| byte == 0));
+        assert_eq!(filter.estimate(), 0);
+    }
+
+    #[test]
+    fn positive_duplicate_insert_estimate() {
+        let mut filter = ScrapeFilter::new();
+
+        for _ in 0..10 {
+            filter.insert(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+        }
+
+        assert_eq!(filter.estimate(), 1);
+    }
+
+    // Bloom filter given in BEP 33 for 192.0.2.0 to 192.0.2.255 and 2001:DB8:: to 2001:DB8::3E7
+    const BEP_33_FILTER_HEX: &'static str = concat!(
+        "F6C3F5EAA07FFD91BDE89F777F26FB2BFF37BDB8FB2BBAA2FD3DDDE7BACFFF75",
+        "EE7CCBAEFE5EEDB1FBFAFF67F6ABFF5E43DDBCA3FD9B9FFDF4FFD3E9DFF12D1B",
+        "DF59DB53DBE9FA5B7FF3B8FDFCDE1AFB8BEDD7BE2F3EE71EBBBFE93BCDEEFE14",
+        "8246C2BC5DBFF7E7EFDCF24FD8DC7ADFFD8FFFDFDDFFF7A4BBEEDF5CB95CE81F",
+        "C7FCFF1FF4FFFFDFE5F7FDCBB7FD79B3FA1FC77BFE07FFF905B7B7FFC7FEFEFF",
+        "E0B8370BB0CD3F5B7F2BD93FEB4386CFDD6F7FD5BFAF2E9EBFFFFEECD67ADBF7",
+        "C67F17EFD5D75EBA6FFEBA7FFF47A91EB1BFBB53E8ABFB5762ABE8FF237279BF",
+        "EFBFEEF5FFC5FEBFDFE5ADFFADFEE1FB737FFFFBFD9F6AEFFEEE76B6FD8F72EF");
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len() / 2).map(|index| u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn positive_bep_33_test_vector() {
+        let mut filter = ScrapeFilter::new();
+
+        for index in 0..256 {
+            filter.insert(IpAddr::V4(Ipv4Addr::new(192, 0, 2, index as u8)));
+        }
+        for index in 0..1000 {
+            filter.insert(IpAddr::V6(Ipv6Addr::new(0x2001, 0xDB8, 0, 0, 0, 0, 0, index)));
+        }
+
+        assert_eq!(filter.as_bytes(), &from_hex(BEP_33_FILTER_HEX)[..]);
+        // BEP 33 gives an estimate of 1224.93
+        assert_eq!(filter.estimate(), 1225);
+    }
+
+    #[test]
+    fn positive_merge_overlapping_filters() {
+        let (mut filter_one, mut filter_two) = (ScrapeFilter::new(), ScrapeFilter::new());
+
+        for index in 0..20 {
+            filter_one.insert(IpAddr::V4(Ipv4Addr::new(10, 0, 0, index)));
+        }
+        for index in 10..30 {
+            filter_two.insert(IpAddr::V4(Ipv4Addr::new(10, 0, 0, index)));
+        }
+        filter_one.merge(&filter_two);
+
+        let estimate = filter_one.estimate();
+        assert!(estimate >= 28 && estimate <= 32);
+    }
+
+    #[test]
+    fn negative_from_bytes_wrong_length() {
+        assert!(ScrapeFilter::from_bytes(&[0u8; scrape::SCRAPE_FILTER_LEN - 1]).is_none());
+        assert!(ScrapeFilter::from_bytes(&[0u8; scrape::SCRAPE_FILTER_LEN]).is_some());
+    }
+}
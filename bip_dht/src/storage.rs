@@ -3,34 +3,85 @@ use std::collections::hash_map::Entry;
 use std::net::SocketAddr;
 
 use bip_util::bt::InfoHash;
+use bip_util::sha::ShaHash;
 use chrono::{UTC, DateTime, Duration};
 
-const MAX_ITEMS_STORED: usize = 500;
+use item::Item;
+
+pub const MAX_ITEMS_STORED: usize = 500;
+
+/// Number of seeds and leechers held in an `AnnounceStorage` (BEP 33).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AnnounceStats {
+    num_seeds: usize,
+    num_leechers: usize,
+}
+
+impl AnnounceStats {
+    fn new(num_seeds: usize, num_leechers: usize) -> AnnounceStats {
+        AnnounceStats {
+            num_seeds: num_seeds,
+            num_leechers: num_leechers,
+        }
+    }
+
+    /// Number of stored contacts that announced themselves as seeds.
+    pub fn num_seeds(&self) -> usize {
+        self.num_seeds
+    }
+
+    /// Number of stored contacts that did not announce themselves as seeds.
+    pub fn num_leechers(&self) -> usize {
+        self.num_leechers
+    }
+
+    /// Fraction of stored contacts that are seeds, or zero if there are no contacts.
+    pub fn seed_ratio(&self) -> f64 {
+        let total = self.num_seeds + self.num_leechers;
+
+        if total == 0 {
+            0.0
+        } else {
+            self.num_seeds as f64 / total as f64
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------//
 
 /// Manages storage and expiration of contact information for a number of InfoHashs.
 pub struct AnnounceStorage {
     storage: HashMap<InfoHash, Vec<AnnounceItem>>,
     expires: Vec<ItemExpiration>,
+    max_items: usize,
 }
 
 impl AnnounceStorage {
     /// Create a new AnnounceStorage object.
     pub fn new() -> AnnounceStorage {
+        AnnounceStorage::with_max_items(MAX_ITEMS_STORED)
+    }
+
+    /// Create a new AnnounceStorage object which stores at most max_items contacts.
+    pub fn with_max_items(max_items: usize) -> AnnounceStorage {
         AnnounceStorage {
             storage: HashMap::new(),
             expires: Vec::new(),
+            max_items: max_items,
         }
     }
 
     /// Returns true if the item was added/it's existing expiration updated, false otherwise.
-    pub fn add_item(&mut self, info_hash: InfoHash, address: SocketAddr) -> bool {
-        self.add(info_hash, address, UTC::now())
+    ///
+    /// If the contact was already stored, whether or not it is a seed will be updated.
+    pub fn add_item(&mut self, info_hash: InfoHash, address: SocketAddr, seed: bool) -> bool {
+        self.add(info_hash, address, seed, UTC::now())
     }
 
-    fn add(&mut self, info_hash: InfoHash, address: SocketAddr, curr_time: DateTime<UTC>) -> bool {
+    fn add(&mut self, info_hash: InfoHash, address: SocketAddr, seed: bool, curr_time: DateTime<UTC>) -> bool {
         // Clear out any old contacts that we have stored
         self.remove_expired_items(curr_time);
-        let item = AnnounceItem::new(info_hash, address);
+        let item = AnnounceItem::new(info_hash, address, seed);
         let item_expiration = item.expiration();
 
         // Check if we already have the item and want to update it's expiration
@@ -70,21 +121,96 @@ impl AnnounceStorage {
         }
     }
 
+    /// Invoke the closure once for each contact for the given InfoHash, along with whether or not it is a seed.
+    pub fn find_seeded_items<F>(&mut self, info_hash: &InfoHash, mut item_func: F)
+        where F: FnMut(SocketAddr, bool)
+    {
+        self.remove_expired_items(UTC::now());
+
+        if let Some(items) = self.storage.get(info_hash) {
+            for item in items {
+                item_func(item.address(), item.is_seed());
+            }
+        }
+    }
+
+    /// Invoke the closure for at most max_items contacts for the given InfoHash.
+    ///
+    /// Seeds and leechers are alternated so that the contacts given are balanced between
+    /// the two. If no_seed is set (the requester is a seed itself), only leechers are given.
+    pub fn find_balanced_items<F>(&mut self, info_hash: &InfoHash, max_items: usize, no_seed: bool, mut item_func: F)
+        where F: FnMut(SocketAddr)
+    {
+        self.remove_expired_items(UTC::now());
+
+        if let Some(items) = self.storage.get(info_hash) {
+            let mut leechers = items.iter().filter(|item| !item.is_seed());
+            let mut seeds = items.iter().filter(|item| item.is_seed() && !no_seed);
+
+            let mut num_given = 0;
+            let mut next_seed = false;
+            while num_given < max_items {
+                let opt_item = if next_seed {
+                    seeds.next().or_else(|| leechers.next())
+                } else {
+                    leechers.next().or_else(|| seeds.next())
+                };
+
+                match opt_item {
+                    Some(item) => item_func(item.address()),
+                    None => break,
+                }
+
+                num_given += 1;
+                next_seed = !next_seed;
+            }
+        }
+    }
+
+    /// Number of seeds and leechers stored across all InfoHashes.
+    pub fn stats(&mut self) -> AnnounceStats {
+        self.remove_expired_items(UTC::now());
+
+        let num_seeds = self.storage.values().flat_map(|items| items.iter()).filter(|item| item.is_seed()).count();
+
+        AnnounceStats::new(num_seeds, self.expires.len() - num_seeds)
+    }
+
+    /// Number of seeds and leechers stored for the given InfoHash.
+    pub fn torrent_stats(&mut self, info_hash: &InfoHash) -> AnnounceStats {
+        self.remove_expired_items(UTC::now());
+
+        self.storage.get(info_hash)
+            .map(|items| {
+                let num_seeds = items.iter().filter(|item| item.is_seed()).count();
+
+                AnnounceStats::new(num_seeds, items.len() - num_seeds)
+            })
+            .unwrap_or(AnnounceStats::new(0, 0))
+    }
+
     /// Returns None if the contact could not be inserted, else, returns Some(true) if the contact was already
     /// in the table (and was replaced by the new entry) or Some(false) if the contact was not already in the
     /// table but was inserted.
     fn insert_contact(&mut self, item: AnnounceItem) -> Option<bool> {
         let item_info_hash = item.info_hash();
 
-        // Check if the contact is already in our list
+        // Check if the contact is already in our list, updating whether or not it is a seed
         let already_in_list = if let Some(items) = self.storage.get_mut(&item_info_hash) {
-            items.iter().any(|a| a == &item)
+            match items.iter_mut().find(|a| **a == item) {
+                Some(existing) => {
+                    existing.seed = item.is_seed();
+
+                    true
+                }
+                None => false,
+            }
         } else {
             false
         };
 
         // Check if we need to insert it into the list and if we have room
-        match (already_in_list, self.expires.len() < MAX_ITEMS_STORED) {
+        match (already_in_list, self.expires.len() < self.max_items) {
             (false, true) => {
                 // Place it into the appropriate list
                 match self.storage.entry(item_info_hash) {
@@ -130,14 +256,22 @@ impl AnnounceStorage {
 
 // ----------------------------------------------------------------------------//
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 struct AnnounceItem {
     expiration: ItemExpiration,
+    seed: bool,
 }
 
 impl AnnounceItem {
-    pub fn new(info_hash: InfoHash, address: SocketAddr) -> AnnounceItem {
-        AnnounceItem { expiration: ItemExpiration::new(info_hash, address) }
+    pub fn new(info_hash: InfoHash, address: SocketAddr, seed: bool) -> AnnounceItem {
+        AnnounceItem {
+            expiration: ItemExpiration::new(info_hash, address),
+            seed: seed,
+        }
+    }
+
+    pub fn is_seed(&self) -> bool {
+        self.seed
     }
 
     pub fn expiration(&self) -> ItemExpiration {
@@ -153,6 +287,15 @@ impl AnnounceItem {
     }
 }
 
+// Items are the same contact regardless of whether or not they are a seed
+impl PartialEq for AnnounceItem {
+    fn eq(&self, other: &AnnounceItem) -> bool {
+        self.expiration == other.expiration
+    }
+}
+
+impl Eq for AnnounceItem {}
+
 // ----------------------------------------------------------------------------//
 
 const EXPIRATION_TIME_HOURS: i64 = 24;
@@ -194,13 +337,91 @@ impl PartialEq for ItemExpiration {
 
 impl Eq for ItemExpiration {}
 
+// ----------------------------------------------------------------------------//
+
+const MAX_DATA_ITEMS_STORED: usize = 500;
+const DATA_EXPIRATION_TIME_HOURS: i64 = 2;
+
+/// Reason that a put for an item was rejected.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PutItemError {
+    /// Storage is full.
+    StorageFull,
+    /// Compare and swap sequence number did not match the stored sequence number.
+    CasMismatch,
+    /// Sequence number was less than the stored sequence number.
+    SequenceTooLow,
+}
+
+/// Manages storage and expiration of arbitrary data items (BEP 44).
+pub struct ItemStorage {
+    storage: HashMap<ShaHash, (Item, DateTime<UTC>)>,
+}
+
+impl ItemStorage {
+    /// Create a new ItemStorage object.
+    pub fn new() -> ItemStorage {
+        ItemStorage { storage: HashMap::new() }
+    }
+
+    /// Store the item, optionally checking the sequence number of the stored item against cas.
+    ///
+    /// Items are assumed to have been validated (signature checked) before being stored.
+    pub fn put_item(&mut self, item: Item, cas: Option<i64>) -> Result<(), PutItemError> {
+        self.put(item, cas, UTC::now())
+    }
+
+    fn put(&mut self, item: Item, cas: Option<i64>, curr_time: DateTime<UTC>) -> Result<(), PutItemError> {
+        self.remove_expired_items(curr_time);
+        let target = item.target();
+
+        match (self.storage.get(&target), &item) {
+            (Some(&(Item::Mutable(ref old), _)), &Item::Mutable(ref new)) => {
+                if cas.map(|cas| cas != old.seq()).unwrap_or(false) {
+                    return Err(PutItemError::CasMismatch);
+                } else if new.seq() < old.seq() {
+                    return Err(PutItemError::SequenceTooLow);
+                }
+            }
+            (Some(_), _) => (),
+            (None, _) if self.storage.len() >= MAX_DATA_ITEMS_STORED => {
+                return Err(PutItemError::StorageFull);
+            }
+            (None, _) => (),
+        }
+
+        self.storage.insert(target, (item, curr_time));
+
+        Ok(())
+    }
+
+    /// Retrieve the item stored under the given target.
+    pub fn find_item(&mut self, target: &ShaHash) -> Option<&Item> {
+        self.find(target, UTC::now())
+    }
+
+    fn find(&mut self, target: &ShaHash, curr_time: DateTime<UTC>) -> Option<&Item> {
+        self.remove_expired_items(curr_time);
+
+        self.storage.get(target).map(|&(ref item, _)| item)
+    }
+
+    /// Prunes all expired items from the storage.
+    fn remove_expired_items(&mut self, curr_time: DateTime<UTC>) {
+        self.storage.retain(|_, &mut (_, inserted)| {
+            curr_time - inserted < Duration::hours(DATA_EXPIRATION_TIME_HOURS)
+        });
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use bip_util::bt;
     use bip_util::test as bip_test;
 
     use chrono::Duration;
-    use storage::{self, AnnounceStorage};
+    use item::{Item, ImmutableItem, MutableItem};
+    use storage::{self, AnnounceStorage, ItemStorage, PutItemError};
 
     #[test]
     fn positive_add_and_retrieve_contact() {
@@ -208,7 +429,7 @@ mod tests {
         let info_hash = [0u8; bt::INFO_HASH_LEN].into();
         let sock_addr = bip_test::dummy_socket_addr_v4();
 
-        assert!(announce_store.add_item(info_hash, sock_addr));
+        assert!(announce_store.add_item(info_hash, sock_addr, false));
 
         let mut items = Vec::new();
         announce_store.find_items(&info_hash, |a| items.push(a));
@@ -224,7 +445,7 @@ mod tests {
         let sock_addrs = bip_test::dummy_block_socket_addrs(storage::MAX_ITEMS_STORED as u16);
 
         for sock_addr in sock_addrs.iter() {
-            assert!(announce_store.add_item(info_hash, *sock_addr));
+            assert!(announce_store.add_item(info_hash, *sock_addr, false));
         }
 
         let mut items = Vec::new();
@@ -243,14 +464,14 @@ mod tests {
         let sock_addrs = bip_test::dummy_block_socket_addrs((storage::MAX_ITEMS_STORED + 1) as u16);
 
         for sock_addr in sock_addrs.iter().take(storage::MAX_ITEMS_STORED) {
-            assert!(announce_store.add_item(info_hash, *sock_addr));
+            assert!(announce_store.add_item(info_hash, *sock_addr, false));
         }
 
         // Try to add a new item
         let other_info_hash = [1u8; bt::INFO_HASH_LEN].into();
 
         // Returns false because it wasnt added
-        assert!(!announce_store.add_item(other_info_hash, sock_addrs[sock_addrs.len() - 1]));
+        assert!(!announce_store.add_item(other_info_hash, sock_addrs[sock_addrs.len() - 1], false));
         // Closure not invoked because it wasnt added
         let mut times_invoked = 0;
         announce_store.find_items(&other_info_hash, |_| times_invoked += 1);
@@ -258,7 +479,7 @@ mod tests {
 
         // Try to add all of the initial nodes again (renew)
         for sock_addr in sock_addrs.iter().take(storage::MAX_ITEMS_STORED) {
-            assert!(announce_store.add_item(info_hash, *sock_addr));
+            assert!(announce_store.add_item(info_hash, *sock_addr, false));
         }
     }
 
@@ -270,14 +491,14 @@ mod tests {
 
         // Fill up the announce storage completely
         for sock_addr in sock_addrs.iter().take(storage::MAX_ITEMS_STORED) {
-            assert!(announce_store.add_item(info_hash, *sock_addr));
+            assert!(announce_store.add_item(info_hash, *sock_addr, false));
         }
 
         // Try to add a new item into the storage (under a different info hash)
         let other_info_hash = [1u8; bt::INFO_HASH_LEN].into();
 
         // Returned false because it wasnt added
-        assert!(!announce_store.add_item(other_info_hash, sock_addrs[sock_addrs.len() - 1]));
+        assert!(!announce_store.add_item(other_info_hash, sock_addrs[sock_addrs.len() - 1], false));
         // Closure not invoked because it wasnt added
         let mut times_invoked = 0;
         announce_store.find_items(&other_info_hash, |_| times_invoked += 1);
@@ -288,6 +509,7 @@ mod tests {
             bip_test::travel_into_future(Duration::hours(storage::EXPIRATION_TIME_HOURS));
         assert!(announce_store.add(other_info_hash,
                                    sock_addrs[sock_addrs.len() - 1],
+                                   false,
                                    mock_current_time));
         // Closure invoked because it was added
         announce_store.find_items(&other_info_hash, |_| times_invoked += 1);
@@ -304,18 +526,18 @@ mod tests {
         // Fill up first info hash
         let num_contacts_first = storage::MAX_ITEMS_STORED / 2;
         for sock_addr in sock_addrs.iter().take(num_contacts_first) {
-            assert!(announce_store.add_item(info_hash_one, *sock_addr));
+            assert!(announce_store.add_item(info_hash_one, *sock_addr, false));
         }
 
         // Fill up second info hash
         let num_contacts_second = storage::MAX_ITEMS_STORED - num_contacts_first;
         for sock_addr in sock_addrs.iter().skip(num_contacts_first).take(num_contacts_second) {
-            assert!(announce_store.add_item(info_hash_two, *sock_addr));
+            assert!(announce_store.add_item(info_hash_two, *sock_addr, false));
         }
 
         // Try to add a third info hash with a contact
         let info_hash_three = [2u8; bt::INFO_HASH_LEN].into();
-        assert!(!announce_store.add_item(info_hash_three, sock_addrs[sock_addrs.len() - 1]));
+        assert!(!announce_store.add_item(info_hash_three, sock_addrs[sock_addrs.len() - 1], false));
         // Closure not invoked because it was not added
         let mut times_invoked = 0;
         announce_store.find_items(&info_hash_three, |_| times_invoked += 1);
@@ -326,9 +548,157 @@ mod tests {
             bip_test::travel_into_future(Duration::hours(storage::EXPIRATION_TIME_HOURS));
         assert!(announce_store.add(info_hash_three,
                                    sock_addrs[sock_addrs.len() - 1],
+                                   false,
                                    mock_current_time));
         // Closure invoked because it was added
         announce_store.find_items(&info_hash_three, |_| times_invoked += 1);
         assert_eq!(times_invoked, 1);
     }
+
+    #[test]
+    fn positive_balanced_items_alternate() {
+        let mut announce_store = AnnounceStorage::new();
+        let info_hash = [0u8; bt::INFO_HASH_LEN].into();
+        let sock_addrs = bip_test::dummy_block_socket_addrs(6);
+
+        // First four are seeds, last two are leechers
+        for (index, sock_addr) in sock_addrs.iter().enumerate() {
+            assert!(announce_store.add_item(info_hash, *sock_addr, index < 4));
+        }
+
+        let mut items = Vec::new();
+        announce_store.find_balanced_items(&info_hash, 4, false, |a| items.push(a));
+
+        assert_eq!(items, vec![sock_addrs[4], sock_addrs[0], sock_addrs[5], sock_addrs[1]]);
+    }
+
+    #[test]
+    fn positive_balanced_items_no_seed() {
+        let mut announce_store = AnnounceStorage::new();
+        let info_hash = [0u8; bt::INFO_HASH_LEN].into();
+        let sock_addrs = bip_test::dummy_block_socket_addrs(4);
+
+        for (index, sock_addr) in sock_addrs.iter().enumerate() {
+            assert!(announce_store.add_item(info_hash, *sock_addr, index < 3));
+        }
+
+        let mut items = Vec::new();
+        announce_store.find_balanced_items(&info_hash, 4, true, |a| items.push(a));
+
+        assert_eq!(items, vec![sock_addrs[3]]);
+    }
+
+    #[test]
+    fn positive_seeded_items_flags() {
+        let mut announce_store = AnnounceStorage::new();
+        let info_hash = [0u8; bt::INFO_HASH_LEN].into();
+        let sock_addrs = bip_test::dummy_block_socket_addrs(2);
+
+        assert!(announce_store.add_item(info_hash, sock_addrs[0], true));
+        assert!(announce_store.add_item(info_hash, sock_addrs[1], false));
+
+        let mut items = Vec::new();
+        announce_store.find_seeded_items(&info_hash, |a, seed| items.push((a, seed)));
+
+        assert_eq!(items, vec![(sock_addrs[0], true), (sock_addrs[1], false)]);
+    }
+
+    #[test]
+    fn positive_stats_seed_ratio() {
+        let mut announce_store = AnnounceStorage::new();
+        let info_hash_one = [0u8; bt::INFO_HASH_LEN].into();
+        let info_hash_two = [1u8; bt::INFO_HASH_LEN].into();
+        let sock_addrs = bip_test::dummy_block_socket_addrs(4);
+
+        assert!(announce_store.add_item(info_hash_one, sock_addrs[0], true));
+        assert!(announce_store.add_item(info_hash_one, sock_addrs[1], false));
+        assert!(announce_store.add_item(info_hash_two, sock_addrs[2], true));
+        assert!(announce_store.add_item(info_hash_two, sock_addrs[3], true));
+
+        let stats = announce_store.stats();
+        assert_eq!(stats.num_seeds(), 3);
+        assert_eq!(stats.num_leechers(), 1);
+        assert_eq!(stats.seed_ratio(), 0.75);
+
+        assert_eq!(announce_store.torrent_stats(&info_hash_one).seed_ratio(), 0.5);
+    }
+
+    #[test]
+    fn positive_renew_contact_updates_seed() {
+        let mut announce_store = AnnounceStorage::new();
+        let info_hash = [0u8; bt::INFO_HASH_LEN].into();
+        let sock_addr = bip_test::dummy_socket_addr_v4();
+
+        assert!(announce_store.add_item(info_hash, sock_addr, false));
+        assert!(announce_store.add_item(info_hash, sock_addr, true));
+
+        let stats = announce_store.torrent_stats(&info_hash);
+        assert_eq!(stats.num_seeds(), 1);
+        assert_eq!(stats.num_leechers(), 0);
+    }
+
+    #[test]
+    fn negative_max_items_configured() {
+        let mut announce_store = AnnounceStorage::with_max_items(1);
+        let info_hash = [0u8; bt::INFO_HASH_LEN].into();
+        let sock_addrs = bip_test::dummy_block_socket_addrs(2);
+
+        assert!(announce_store.add_item(info_hash, sock_addrs[0], false));
+        assert!(!announce_store.add_item(info_hash, sock_addrs[1], false));
+    }
+
+    #[test]
+    fn positive_put_and_find_immutable_item() {
+        let mut item_store = ItemStorage::new();
+        let item = Item::Immutable(ImmutableItem::new(b"12:Hello World!".to_vec()).unwrap());
+        let target = item.target();
+
+        assert_eq!(item_store.put_item(item.clone(), None), Ok(()));
+        assert_eq!(item_store.find_item(&target), Some(&item));
+    }
+
+    #[test]
+    fn positive_put_mutable_item_higher_seq() {
+        let mut item_store = ItemStorage::new();
+        let first = Item::Mutable(MutableItem::sign(&[0u8; 32], Vec::new(), 1, b"i1e".to_vec()).unwrap());
+        let second = Item::Mutable(MutableItem::sign(&[0u8; 32], Vec::new(), 2, b"i2e".to_vec()).unwrap());
+        let target = first.target();
+
+        assert_eq!(item_store.put_item(first, None), Ok(()));
+        assert_eq!(item_store.put_item(second.clone(), Some(1)), Ok(()));
+        assert_eq!(item_store.find_item(&target), Some(&second));
+    }
+
+    #[test]
+    fn negative_put_mutable_item_lower_seq() {
+        let mut item_store = ItemStorage::new();
+        let first = Item::Mutable(MutableItem::sign(&[0u8; 32], Vec::new(), 2, b"i1e".to_vec()).unwrap());
+        let second = Item::Mutable(MutableItem::sign(&[0u8; 32], Vec::new(), 1, b"i2e".to_vec()).unwrap());
+
+        assert_eq!(item_store.put_item(first, None), Ok(()));
+        assert_eq!(item_store.put_item(second, None), Err(PutItemError::SequenceTooLow));
+    }
+
+    #[test]
+    fn negative_put_mutable_item_cas_mismatch() {
+        let mut item_store = ItemStorage::new();
+        let first = Item::Mutable(MutableItem::sign(&[0u8; 32], Vec::new(), 1, b"i1e".to_vec()).unwrap());
+        let second = Item::Mutable(MutableItem::sign(&[0u8; 32], Vec::new(), 2, b"i2e".to_vec()).unwrap());
+
+        assert_eq!(item_store.put_item(first, None), Ok(()));
+        assert_eq!(item_store.put_item(second, Some(5)), Err(PutItemError::CasMismatch));
+    }
+
+    #[test]
+    fn positive_item_expires() {
+        let mut item_store = ItemStorage::new();
+        let item = Item::Immutable(ImmutableItem::new(b"i5e".to_vec()).unwrap());
+        let target = item.target();
+
+        assert_eq!(item_store.put_item(item, None), Ok(()));
+
+        let mock_current_time =
+            bip_test::travel_into_future(Duration::hours(storage::DATA_EXPIRATION_TIME_HOURS));
+        assert!(item_store.find(&target, mock_current_time).is_none());
+    }
 }
@@ -1,4 +1,6 @@
+use std::cmp;
 use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::Duration as StdDuration;
 
 use chrono::{DateTime, Duration, UTC};
 use rand;
@@ -8,25 +10,26 @@ use bip_util::convert;
 use bip_util::sha::{self, ShaHash};
 use bip_util::net::IpAddr;
 
-/// We will partially follow the bittorrent implementation for issuing tokens to nodes, the
-/// secret will change every 10 minutes and tokens up to 10 minutes old will be accepted. This
-/// is in contrast with the bittorrent implementation where the secret changes every 5 minutes
-/// and tokens up to 10 minutes old are accepted. Updating of the token will take place lazily.
-/// However, with our implementation we are not going to store tokens that we have issued, instead,
+/// We will follow the bittorrent implementation for issuing tokens to nodes, by default the
+/// secret will change every 5 minutes and tokens issued under the current or the previous secret
+/// will be accepted. Updating of the secret will take place lazily, whenever a token is checked out
+/// or checked in. With our implementation we are not going to store tokens that we have issued, instead,
 /// store the secret and check if the token they gave us is valid for the current or last secret.
 /// This is technically not what we want, but it will have essentially the same result when we
 /// assume that nobody other than us knows the secret.
 
-/// With this scheme we can guarantee that the minimum amount of time a token can be valid for
-/// is the maximum amount of time a token is valid for in bittorrent in order to provide interop.
 /// Since we arent storing the tokens we generate (which is awesome) we CANT track how long each
 /// individual token has been checked out from the store and so each token is valid for some time
-/// between 10 and 20 minutes in contrast with 5 and 10 minutes.
+/// between one and two refresh intervals (5 and 10 minutes by default). The refresh interval is
+/// configurable, but is kept between 5 and 10 minutes so tokens are valid for at least as long
+/// as other implementations expect (BEP 5).
 
-const REFRESH_INTERVAL_MINS: i64 = 10;
+pub const DEFAULT_REFRESH_INTERVAL_SECS: i64 = 5 * 60;
+const MIN_REFRESH_INTERVAL_SECS: i64 = 5 * 60;
+const MAX_REFRESH_INTERVAL_SECS: i64 = 10 * 60;
 
-const IPV4_SECRET_BUFFER_LEN: usize = 4 + 4;
-const IPV6_SECRET_BUFFER_LEN: usize = 16 + 4;
+const IPV4_SECRET_BUFFER_LEN: usize = 4 + 8;
+const IPV6_SECRET_BUFFER_LEN: usize = 16 + 8;
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Token {
@@ -70,25 +73,33 @@ impl AsRef<[u8]> for Token {
 
 #[derive(Copy, Clone)]
 pub struct TokenStore {
-    curr_secret: u32,
-    last_secret: u32,
+    curr_secret: u64,
+    last_secret: u64,
     last_refresh: DateTime<UTC>,
+    refresh_interval: Duration,
 }
 
 impl TokenStore {
-    pub fn new() -> TokenStore {
+    /// Create a new `TokenStore` rotating its secret every refresh interval.
+    ///
+    /// The interval will be clamped to between 5 and 10 minutes.
+    pub fn with_refresh_interval(refresh_interval: StdDuration) -> TokenStore {
         // We cant just use a placeholder for the last secret as that would allow external
         // nodes to exploit recently started dhts. Instead, just generate another placeholder
         // secret for the last secret with the assumption that we wont get a valid announce
         // under that secret. We could go the option route but that isnt as clean.
-        let curr_secret = rand::random::<u32>();
-        let last_secret = rand::random::<u32>();
+        let curr_secret = rand::random::<u64>();
+        let last_secret = rand::random::<u64>();
         let last_refresh = UTC::now();
 
+        let interval_secs = cmp::min(MAX_REFRESH_INTERVAL_SECS as u64, refresh_interval.as_secs()) as i64;
+        let clamped_interval_secs = cmp::max(MIN_REFRESH_INTERVAL_SECS, interval_secs);
+
         TokenStore {
             curr_secret: curr_secret,
             last_secret: last_secret,
             last_refresh: last_refresh,
+            refresh_interval: Duration::seconds(clamped_interval_secs),
         }
     }
 
@@ -105,17 +116,20 @@ impl TokenStore {
     }
 
     fn refresh_check(&mut self) {
-        match intervals_passed(self.last_refresh) {
+        let curr_time = UTC::now();
+
+        match intervals_passed(self.last_refresh, curr_time, self.refresh_interval) {
             0 => (),
             1 => {
                 self.last_secret = self.curr_secret;
-                self.curr_secret = rand::random::<u32>();
-                self.last_refresh = UTC::now();
+                self.curr_secret = rand::random::<u64>();
+                // Stay on the schedule, a token checked out late in an interval should not live longer
+                self.last_refresh = self.last_refresh + self.refresh_interval;
             }
             _ => {
-                self.last_secret = rand::random::<u32>();
-                self.curr_secret = rand::random::<u32>();
-                self.last_refresh = UTC::now();
+                self.last_secret = rand::random::<u64>();
+                self.curr_secret = rand::random::<u64>();
+                self.last_refresh = curr_time;
             }
         };
     }
@@ -126,17 +140,14 @@ impl TokenStore {
 /// invalid.
 ///
 /// Returns the number of intervals that have passed since the last refresh time.
-fn intervals_passed(last_refresh: DateTime<UTC>) -> i64 {
-    let refresh_duration = Duration::minutes(REFRESH_INTERVAL_MINS);
-    let curr_time = UTC::now();
-
+fn intervals_passed(last_refresh: DateTime<UTC>, curr_time: DateTime<UTC>, refresh_interval: Duration) -> i64 {
     let diff_time = curr_time - last_refresh;
 
-    diff_time.num_minutes() / refresh_duration.num_minutes()
+    diff_time.num_seconds() / refresh_interval.num_seconds()
 }
 
 /// Generate a token from an ip address and a secret.
-fn generate_token_from_addr(addr: IpAddr, secret: u32) -> Token {
+fn generate_token_from_addr(addr: IpAddr, secret: u64) -> Token {
     match addr {
         IpAddr::V4(v4) => generate_token_from_addr_v4(v4, secret),
         IpAddr::V6(v6) => generate_token_from_addr_v6(v6, secret),
@@ -144,10 +155,10 @@ fn generate_token_from_addr(addr: IpAddr, secret: u32) -> Token {
 }
 
 /// Generate a token from an ipv4 address and a secret.
-fn generate_token_from_addr_v4(v4_addr: Ipv4Addr, secret: u32) -> Token {
+fn generate_token_from_addr_v4(v4_addr: Ipv4Addr, secret: u64) -> Token {
     let mut buffer = [0u8; IPV4_SECRET_BUFFER_LEN];
     let v4_bytes = convert::ipv4_to_bytes_be(v4_addr);
-    let secret_bytes = convert::four_bytes_to_array(secret);
+    let secret_bytes = convert::eight_bytes_to_array(secret);
 
     let source_iter = v4_bytes.iter().chain(secret_bytes.iter());
     for (dst, src) in buffer.iter_mut().zip(source_iter) {
@@ -159,10 +170,10 @@ fn generate_token_from_addr_v4(v4_addr: Ipv4Addr, secret: u32) -> Token {
 }
 
 /// Generate a token from an ipv6 address and a secret.
-fn generate_token_from_addr_v6(v6_addr: Ipv6Addr, secret: u32) -> Token {
+fn generate_token_from_addr_v6(v6_addr: Ipv6Addr, secret: u64) -> Token {
     let mut buffer = [0u8; IPV6_SECRET_BUFFER_LEN];
     let v6_bytes = convert::ipv6_to_bytes_be(v6_addr);
-    let secret_bytes = convert::four_bytes_to_array(secret);
+    let secret_bytes = convert::eight_bytes_to_array(secret);
 
     let source_iter = v6_bytes.iter().chain(secret_bytes.iter());
     for (dst, src) in buffer.iter_mut().zip(source_iter) {
@@ -174,7 +185,7 @@ fn generate_token_from_addr_v6(v6_addr: Ipv6Addr, secret: u32) -> Token {
 }
 
 /// Validate a token given an ip address and the two current secrets.
-fn validate_token_from_addr(addr: IpAddr, token: Token, secret_one: u32, secret_two: u32) -> bool {
+fn validate_token_from_addr(addr: IpAddr, token: Token, secret_one: u64, secret_two: u64) -> bool {
     match addr {
         IpAddr::V4(v4) => {
             validate_token_from_addr_v4(v4, token, secret_one) ||
@@ -188,25 +199,35 @@ fn validate_token_from_addr(addr: IpAddr, token: Token, secret_one: u32, secret_
 }
 
 /// Validate a token given an ipv4 address and one secret.
-fn validate_token_from_addr_v4(v4_addr: Ipv4Addr, token: Token, secret: u32) -> bool {
-    generate_token_from_addr_v4(v4_addr, secret) == token
+fn validate_token_from_addr_v4(v4_addr: Ipv4Addr, token: Token, secret: u64) -> bool {
+    let expected_token = generate_token_from_addr_v4(v4_addr, secret);
+
+    convert::constant_time_eq(expected_token.as_ref(), token.as_ref())
 }
 
 /// Validate a token given an ipv6 address and one secret.
-fn validate_token_from_addr_v6(v6_addr: Ipv6Addr, token: Token, secret: u32) -> bool {
-    generate_token_from_addr_v6(v6_addr, secret) == token
+fn validate_token_from_addr_v6(v6_addr: Ipv6Addr, token: Token, secret: u64) -> bool {
+    let expected_token = generate_token_from_addr_v6(v6_addr, secret);
+
+    convert::constant_time_eq(expected_token.as_ref(), token.as_ref())
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration as StdDuration;
+
     use chrono::Duration;
     use bip_util::test as bip_test;
 
     use token::TokenStore;
 
+    fn new_store() -> TokenStore {
+        TokenStore::with_refresh_interval(StdDuration::from_secs(super::DEFAULT_REFRESH_INTERVAL_SECS as u64))
+    }
+
     #[test]
     fn positive_accept_valid_v4_token() {
-        let mut store = TokenStore::new();
+        let mut store = new_store();
         let v4_addr = bip_test::dummy_ipv4_addr();
 
         let valid_token = store.checkout(v4_addr);
@@ -216,7 +237,7 @@ mod tests {
 
     #[test]
     fn positive_accept_valid_v6_token() {
-        let mut store = TokenStore::new();
+        let mut store = new_store();
         let v6_addr = bip_test::dummy_ipv6_addr();
 
         let valid_token = store.checkout(v6_addr);
@@ -226,12 +247,12 @@ mod tests {
 
     #[test]
     fn positive_accept_v4_token_from_second_secret() {
-        let mut store = TokenStore::new();
+        let mut store = new_store();
         let v4_addr = bip_test::dummy_ipv4_addr();
 
         let valid_token = store.checkout(v4_addr);
 
-        let past_offset = Duration::minutes((super::REFRESH_INTERVAL_MINS * 2) - 1);
+        let past_offset = Duration::seconds((super::DEFAULT_REFRESH_INTERVAL_SECS * 2) - 1);
         let past_time = bip_test::travel_into_past(past_offset);
         store.last_refresh = past_time;
 
@@ -240,27 +261,89 @@ mod tests {
 
     #[test]
     fn positive_accept_v6_token_from_second_secret() {
-        let mut store = TokenStore::new();
+        let mut store = new_store();
         let v6_addr = bip_test::dummy_ipv6_addr();
 
         let valid_token = store.checkout(v6_addr);
 
-        let past_offset = Duration::minutes((super::REFRESH_INTERVAL_MINS * 2) - 1);
+        let past_offset = Duration::seconds((super::DEFAULT_REFRESH_INTERVAL_SECS * 2) - 1);
         let past_time = bip_test::travel_into_past(past_offset);
         store.last_refresh = past_time;
 
         assert!(store.checkin(v6_addr, valid_token));
     }
 
+    #[test]
+    fn positive_refresh_interval_clamped() {
+        let short_store = TokenStore::with_refresh_interval(StdDuration::from_secs(60));
+        let long_store = TokenStore::with_refresh_interval(StdDuration::from_secs(60 * 60));
+        let store = TokenStore::with_refresh_interval(StdDuration::from_secs(7 * 60));
+
+        assert_eq!(Duration::seconds(super::MIN_REFRESH_INTERVAL_SECS), short_store.refresh_interval);
+        assert_eq!(Duration::seconds(super::MAX_REFRESH_INTERVAL_SECS), long_store.refresh_interval);
+        assert_eq!(Duration::minutes(7), store.refresh_interval);
+    }
+
+    #[test]
+    fn positive_accept_token_within_custom_interval() {
+        let mut store = TokenStore::with_refresh_interval(StdDuration::from_secs(10 * 60));
+        let v4_addr = bip_test::dummy_ipv4_addr();
+
+        let valid_token = store.checkout(v4_addr);
+
+        let past_offset = Duration::minutes(19);
+        store.last_refresh = bip_test::travel_into_past(past_offset);
+
+        assert!(store.checkin(v4_addr, valid_token));
+    }
+
+    #[test]
+    fn negative_reject_token_after_custom_interval() {
+        let mut store = TokenStore::with_refresh_interval(StdDuration::from_secs(10 * 60));
+        let v4_addr = bip_test::dummy_ipv4_addr();
+
+        let valid_token = store.checkout(v4_addr);
+
+        let past_offset = Duration::minutes(20);
+        store.last_refresh = bip_test::travel_into_past(past_offset);
+
+        assert!(!store.checkin(v4_addr, valid_token));
+    }
+
+    #[test]
+    fn negative_reject_token_from_other_addr() {
+        let mut store = new_store();
+        let v4_addr = bip_test::dummy_ipv4_addr();
+        let v6_addr = bip_test::dummy_ipv6_addr();
+
+        let valid_token = store.checkout(v4_addr);
+
+        assert!(!store.checkin(v6_addr, valid_token));
+    }
+
+    #[test]
+    fn positive_rotation_stays_on_schedule() {
+        let mut store = new_store();
+        let v4_addr = bip_test::dummy_ipv4_addr();
+
+        // Secret was due to be rotated one second ago
+        let past_offset = Duration::seconds(super::DEFAULT_REFRESH_INTERVAL_SECS + 1);
+        store.last_refresh = bip_test::travel_into_past(past_offset);
+        store.checkout(v4_addr);
+
+        // Rotation should be recorded at the scheduled time, not at the time of checkout
+        assert!(store.last_refresh <= bip_test::travel_into_past(Duration::seconds(1)));
+    }
+
     #[test]
     #[should_panic]
     fn negative_reject_expired_v4_token() {
-        let mut store = TokenStore::new();
+        let mut store = new_store();
         let v4_addr = bip_test::dummy_ipv4_addr();
 
         let valid_token = store.checkout(v4_addr);
 
-        let past_offset = Duration::minutes(super::REFRESH_INTERVAL_MINS * 2);
+        let past_offset = Duration::seconds(super::DEFAULT_REFRESH_INTERVAL_SECS * 2);
         let past_time = bip_test::travel_into_past(past_offset);
         store.last_refresh = past_time;
 
@@ -270,12 +353,12 @@ mod tests {
     #[test]
     #[should_panic]
     fn negative_reject_expired_v6_token() {
-        let mut store = TokenStore::new();
+        let mut store = new_store();
         let v6_addr = bip_test::dummy_ipv6_addr();
 
         let valid_token = store.checkout(v6_addr);
 
-        let past_offset = Duration::minutes(super::REFRESH_INTERVAL_MINS * 2);
+        let past_offset = Duration::seconds(super::DEFAULT_REFRESH_INTERVAL_SECS * 2);
         let past_time = bip_test::travel_into_past(past_offset);
         store.last_refresh = past_time;
 
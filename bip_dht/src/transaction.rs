@@ -34,7 +34,7 @@ use bip_util::convert;
 // cause a panic or not (debug and release should have similar semantics)!
 
 // Together these make up 8 bytes, or, a u64
-const TRANSACTION_ID_BYTES: usize = ACTION_ID_BYTES + MESSAGE_ID_BYTES;
+pub const TRANSACTION_ID_BYTES: usize = ACTION_ID_BYTES + MESSAGE_ID_BYTES;
 const ACTION_ID_BYTES: usize = 5;
 const MESSAGE_ID_BYTES: usize = 3;
 
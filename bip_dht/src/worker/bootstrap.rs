@@ -3,16 +3,19 @@ use std::net::SocketAddr;
 use std::sync::mpsc::SyncSender;
 
 use bip_handshake::Handshaker;
-use bip_util::bt::{self, NodeId};
+use bip_util::bt::NodeId;
 use mio::{Timeout, EventLoop};
 
-use message::find_node::FindNodeRequest;
+use distance;
+use message::template::RequestTemplate;
+use message::want::Want;
 use routing::bucket::Bucket;
 use routing::node::{Node, NodeStatus};
 use routing::table::{self, RoutingTable, BucketContents};
 use transaction::{MIDGenerator, TransactionID};
 use worker::ScheduledTask;
 use worker::handler::DhtHandler;
+use worker::messenger::OutgoingMessage;
 
 const BOOTSTRAP_INITIAL_TIMEOUT: u64 = 2500;
 const BOOTSTRAP_NODE_TIMEOUT: u64 = 500;
@@ -38,13 +41,16 @@ pub struct TableBootstrap {
     active_messages: HashMap<TransactionID, Timeout>,
     starting_routers: HashSet<SocketAddr>,
     curr_bootstrap_bucket: usize,
+    // Find node requests targeting our own id, patched with the bucket targets
+    request_template: RequestTemplate,
 }
 
 impl TableBootstrap {
     pub fn new<I>(table_id: NodeId,
                   id_generator: MIDGenerator,
                   nodes: Vec<SocketAddr>,
-                  routers: I)
+                  routers: I,
+                  want: Option<Want>)
                   -> TableBootstrap
         where I: Iterator<Item = SocketAddr>
     {
@@ -57,11 +63,12 @@ impl TableBootstrap {
             starting_routers: router_filter,
             active_messages: HashMap::new(),
             curr_bootstrap_bucket: 0,
+            request_template: RequestTemplate::find_node(table_id, table_id, want),
         }
     }
 
     pub fn start_bootstrap<H>(&mut self,
-                              out: &SyncSender<(Vec<u8>, SocketAddr)>,
+                              out: &SyncSender<OutgoingMessage>,
                               event_loop: &mut EventLoop<DhtHandler<H>>)
                               -> BootstrapStatus
         where H: Handshaker
@@ -87,11 +94,10 @@ impl TableBootstrap {
         // Insert the timeout into the active bootstraps just so we can check if a response was valid (and begin the bucket bootstraps)
         self.active_messages.insert(trans_id, timeout);
 
-        let find_node_msg = FindNodeRequest::new(trans_id.as_ref(), self.table_id, self.table_id)
-            .encode();
+        let find_node_msg = self.request_template.encode(trans_id.as_ref());
         // Ping all initial routers and nodes
         for addr in self.starting_routers.iter().chain(self.starting_nodes.iter()) {
-            if out.send((find_node_msg.clone(), *addr)).is_err() {
+            if out.send(OutgoingMessage::Query(find_node_msg.clone(), *addr)).is_err() {
                 error!("bip_dht: Failed to send bootstrap message to router through channel...");
                 return BootstrapStatus::Failed;
             }
@@ -107,7 +113,7 @@ impl TableBootstrap {
     pub fn recv_response<'a, H>(&mut self,
                                 trans_id: &TransactionID,
                                 table: &RoutingTable,
-                                out: &SyncSender<(Vec<u8>, SocketAddr)>,
+                                out: &SyncSender<OutgoingMessage>,
                                 event_loop: &mut EventLoop<DhtHandler<H>>)
                                 -> BootstrapStatus
         where H: Handshaker
@@ -143,7 +149,7 @@ impl TableBootstrap {
     pub fn recv_timeout<H>(&mut self,
                            trans_id: &TransactionID,
                            table: &RoutingTable,
-                           out: &SyncSender<(Vec<u8>, SocketAddr)>,
+                           out: &SyncSender<OutgoingMessage>,
                            event_loop: &mut EventLoop<DhtHandler<H>>)
                            -> BootstrapStatus
         where H: Handshaker
@@ -165,12 +171,12 @@ impl TableBootstrap {
     // Returns true if there are more buckets to bootstrap, false otherwise
     fn bootstrap_next_bucket<H>(&mut self,
                                 table: &RoutingTable,
-                                out: &SyncSender<(Vec<u8>, SocketAddr)>,
+                                out: &SyncSender<OutgoingMessage>,
                                 event_loop: &mut EventLoop<DhtHandler<H>>)
                                 -> BootstrapStatus
         where H: Handshaker
     {
-        let target_id = flip_id_bit_at_index(self.table_id, self.curr_bootstrap_bucket);
+        let target_id = distance::bucket_node_id(self.table_id, self.curr_bootstrap_bucket);
 
         // Get the optimal iterator to bootstrap the current bucket
         if self.curr_bootstrap_bucket == 0 || self.curr_bootstrap_bucket == 1 {
@@ -227,7 +233,7 @@ impl TableBootstrap {
                                          nodes: I,
                                          target_id: NodeId,
                                          table: &RoutingTable,
-                                         out: &SyncSender<(Vec<u8>, SocketAddr)>,
+                                         out: &SyncSender<OutgoingMessage>,
                                          event_loop: &mut EventLoop<DhtHandler<H>>)
                                          -> BootstrapStatus
         where I: Iterator<Item = &'a Node>,
@@ -241,14 +247,14 @@ impl TableBootstrap {
         for node in nodes.take(BOOTSTRAP_PINGS_PER_BUCKET) {
             // Generate a transaction id
             let trans_id = self.id_generator.generate();
-            let find_node_msg = FindNodeRequest::new(trans_id.as_ref(), self.table_id, target_id)
-                .encode();
+            let find_node_msg = self.request_template.encode_with_target(trans_id.as_ref(), target_id);
 
-            // Add a timeout for the node
+            // Add a timeout for the node, based on how quickly it has responded in the past
+            let timeout_ms = node.query_timeout_ms(BOOTSTRAP_NODE_TIMEOUT);
             let res_timeout =
-                event_loop.timeout_ms((BOOTSTRAP_NODE_TIMEOUT,
+                event_loop.timeout_ms((timeout_ms,
                                        ScheduledTask::CheckBootstrapTimeout(trans_id)),
-                                      BOOTSTRAP_NODE_TIMEOUT);
+                                      timeout_ms);
             let timeout = if let Ok(t) = res_timeout {
                 t
             } else {
@@ -257,13 +263,13 @@ impl TableBootstrap {
             };
 
             // Send the message to the node
-            if out.send((find_node_msg, node.addr())).is_err() {
+            if out.send(OutgoingMessage::Query(find_node_msg, node.addr())).is_err() {
                 error!("bip_dht: Could not send a bootstrap message through the channel...");
                 return BootstrapStatus::Failed;
             }
 
             // Mark that we requested from the node
-            node.local_request();
+            node.local_request(trans_id);
 
             // Create an entry for the timeout in the map
             self.active_messages.insert(trans_id, timeout);
@@ -289,15 +295,3 @@ impl TableBootstrap {
         }
     }
 }
-
-/// Panics if index is out of bounds.
-/// TODO: Move into bip_util crate
-fn flip_id_bit_at_index(node_id: NodeId, index: usize) -> NodeId {
-    let mut id_bytes: [u8; bt::NODE_ID_LEN] = node_id.into();
-    let (byte_index, bit_index) = (index / 8, index % 8);
-
-    let actual_bit_index = 7 - bit_index;
-    id_bytes[byte_index] ^= 1 << actual_bit_index;
-
-    id_bytes.into()
-}
@@ -3,14 +3,18 @@ use std::convert::AsRef;
 use std::io;
 use std::net::{SocketAddr, UdpSocket, SocketAddrV4, SocketAddrV6};
 use std::mem;
+use std::sync::Arc;
 use std::sync::mpsc::{self, SyncSender};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use bip_bencode::Bencode;
 use bip_handshake::Handshaker;
 use bip_util::bt::InfoHash;
 use bip_util::convert;
+use bip_util::metrics::Metrics;
 use bip_util::net::IpAddr;
+use futures::sync::oneshot;
 use log::LogLevel;
 use mio::{self, EventLoop, Handler};
 
@@ -23,18 +27,27 @@ use message::error::{ErrorCode, ErrorMessage};
 use message::request::RequestType;
 use message::response::{ResponseType, ExpectedResponse};
 use message::compact_info::{CompactNodeInfo, CompactValueInfo};
+use message::want::Want;
+use message::get_data::{GetDataResponse, MutableInfo};
+use message::put_data::PutDataResponse;
+use item::{self, Item, ImmutableItem, MutableItem};
 use router::Router;
 use routing::node::Node;
 use routing::table::RoutingTable;
-use storage::AnnounceStorage;
+use scrape::{ScrapeFilter, ScrapeEstimate};
+use storage::{AnnounceStorage, AnnounceStats, ItemStorage, PutItemError};
 use token::{TokenStore, Token};
 use transaction::{AIDGenerator, TransactionID, ActionID};
 use worker::{OneshotTask, ScheduledTask, DhtEvent, ShutdownCause};
 use worker::bootstrap::{TableBootstrap, BootstrapStatus};
-use worker::lookup::{TableLookup, LookupStatus};
-use worker::refresh::{TableRefresh, RefreshStatus};
-
-use routing::table::BucketContents;
+use worker::item_lookup::{TableItemLookup, ItemLookupStatus, ItemOperation};
+use worker::lookup::{TableLookup, LookupStatus, LookupConfig, LookupOptions, LookupStats};
+use worker::messenger::OutgoingMessage;
+use worker::refresh::{TableRefresh, RefreshStatus, TableConfig};
+use worker::throttle::QueryThrottle;
+use worker::trace::{LookupTrace, LookupTracer};
+
+use routing::table::{BucketContents, TableEvent};
 use routing::node::NodeStatus;
 
 // TODO: Update modules to use find_node on the routing table to update the status of a given node.
@@ -42,17 +55,44 @@ use routing::node::NodeStatus;
 const MAX_BOOTSTRAP_ATTEMPTS: usize = 3;
 const BOOTSTRAP_GOOD_NODE_THRESHOLD: usize = 10;
 
+// Keeps get_peers responses comfortably within a single udp packet
+const MAX_GET_PEERS_VALUES: usize = 100;
+
+const QUERIES_RECEIVED_METRIC: &'static str = "bip_dht_queries_received";
+const QUERIES_THROTTLED_METRIC: &'static str = "bip_dht_queries_throttled";
+
 /// Spawns a DHT handler that maintains our routing table and executes our actions on the DHT.
 pub fn create_dht_handler<H>(table: RoutingTable,
-                             out: SyncSender<(Vec<u8>, SocketAddr)>,
+                             out: SyncSender<OutgoingMessage>,
                              read_only: bool,
+                             want: Option<Want>,
+                             lookup_config: LookupConfig,
+                             table_config: TableConfig,
+                             inbound_query_rate: usize,
+                             inbound_query_burst: usize,
+                             max_throttled_addrs: usize,
+                             max_announces: usize,
+                             token_refresh_interval: Duration,
+                             metrics: Arc<Metrics>,
                              handshaker: H,
                              kill_sock: UdpSocket,
                              kill_addr: SocketAddr)
                              -> io::Result<mio::Sender<OneshotTask>>
     where H: Handshaker + 'static
 {
-    let mut handler = DhtHandler::new(table, out, read_only, handshaker);
+    let mut handler = DhtHandler::new(table,
+                                      out,
+                                      read_only,
+                                      want,
+                                      lookup_config,
+                                      table_config,
+                                      inbound_query_rate,
+                                      inbound_query_burst,
+                                      max_throttled_addrs,
+                                      max_announces,
+                                      token_refresh_interval,
+                                      metrics,
+                                      handshaker);
     let mut event_loop = try!(EventLoop::new());
 
     let loop_channel = event_loop.channel();
@@ -94,14 +134,18 @@ enum TableAction {
     ///
     /// Includes number of bootstrap attempts.
     Bootstrap(TableBootstrap, usize),
+    /// Item lookup action.
+    ItemLookup(TableItemLookup),
 }
 
 /// Actions that we want to perform on our RoutingTable after bootstrapping finishes.
 enum PostBootstrapAction {
     /// Future lookup action.
-    Lookup(InfoHash, bool),
+    Lookup(InfoHash, LookupOptions, Option<oneshot::Sender<LookupTrace>>),
     /// Future refresh action.
     Refresh(TableRefresh, TransactionID),
+    /// Future item lookup action.
+    ItemLookup(ItemOperation),
 }
 
 /// Storage for our EventLoop to invoke actions upon.
@@ -114,13 +158,21 @@ pub struct DhtHandler<H> {
 /// to table actions while still being able to pass around the bulky parameters.
 struct DetachedDhtHandler<H> {
     read_only: bool,
+    // Address families we ask for in outgoing find_node and get_peers requests
+    want: Option<Want>,
+    // Parameters for our get_peers lookups
+    lookup_config: LookupConfig,
+    // Limits the rate of requests we process from each remote address
+    query_throttle: QueryThrottle,
+    metrics: Arc<Metrics>,
     handshaker: H,
-    out_channel: SyncSender<(Vec<u8>, SocketAddr)>,
+    out_channel: SyncSender<OutgoingMessage>,
     token_store: TokenStore,
     aid_generator: AIDGenerator,
     bootstrapping: bool,
     routing_table: RoutingTable,
     active_stores: AnnounceStorage,
+    item_stores: ItemStorage,
     // If future actions is not empty, that means we are still bootstrapping
     // since we will always spin up a table refresh action after bootstrapping.
     future_actions: Vec<PostBootstrapAction>,
@@ -131,8 +183,17 @@ impl<H> DhtHandler<H>
     where H: Handshaker
 {
     fn new(table: RoutingTable,
-           out: SyncSender<(Vec<u8>, SocketAddr)>,
+           out: SyncSender<OutgoingMessage>,
            read_only: bool,
+           want: Option<Want>,
+           lookup_config: LookupConfig,
+           table_config: TableConfig,
+           inbound_query_rate: usize,
+           inbound_query_burst: usize,
+           max_throttled_addrs: usize,
+           max_announces: usize,
+           token_refresh_interval: Duration,
+           metrics: Arc<Metrics>,
            handshaker: H)
            -> DhtHandler<H> {
         let mut aid_generator = AIDGenerator::new();
@@ -140,18 +201,23 @@ impl<H> DhtHandler<H>
         // Insert the refresh task to execute after the bootstrap
         let mut mid_generator = aid_generator.generate();
         let refresh_trans_id = mid_generator.generate();
-        let table_refresh = TableRefresh::new(mid_generator);
+        let table_refresh = TableRefresh::new(mid_generator, want, table_config.refresh_interval);
         let future_actions = vec![PostBootstrapAction::Refresh(table_refresh, refresh_trans_id)];
 
         let detached = DetachedDhtHandler {
             read_only: read_only,
+            want: want,
+            lookup_config: lookup_config,
+            query_throttle: QueryThrottle::new(inbound_query_rate, inbound_query_burst, max_throttled_addrs),
+            metrics: metrics,
             handshaker: handshaker,
             out_channel: out,
-            token_store: TokenStore::new(),
+            token_store: TokenStore::with_refresh_interval(token_refresh_interval),
             aid_generator: aid_generator,
             bootstrapping: false,
             routing_table: table,
-            active_stores: AnnounceStorage::new(),
+            active_stores: AnnounceStorage::with_max_items(max_announces),
+            item_stores: ItemStorage::new(),
             future_actions: future_actions,
             event_notifiers: Vec::new(),
         };
@@ -180,17 +246,35 @@ impl<H> Handler for DhtHandler<H>
             OneshotTask::StartBootstrap(routers, nodes) => {
                 handle_start_bootstrap(self, event_loop, routers, nodes);
             }
-            OneshotTask::StartLookup(info_hash, should_announce) => {
+            OneshotTask::StartLookup(info_hash, options, opt_trace) => {
                 handle_start_lookup(&mut self.table_actions,
                                     &mut self.detached,
                                     event_loop,
                                     info_hash,
-                                    should_announce);
+                                    options,
+                                    opt_trace);
+            }
+            OneshotTask::StartGetItem(key, sender) => {
+                handle_start_item_lookup(&mut self.table_actions,
+                                         &mut self.detached,
+                                         event_loop,
+                                         ItemOperation::Get(key, sender));
+            }
+            OneshotTask::StartPutItem(item, cas, sender) => {
+                handle_start_item_lookup(&mut self.table_actions,
+                                         &mut self.detached,
+                                         event_loop,
+                                         ItemOperation::Put(item, cas, sender));
+            }
+            OneshotTask::AnnounceStats(sender) => {
+                handle_announce_stats(self, sender);
             }
             OneshotTask::Shutdown(cause) => {
                 handle_shutdown(self, event_loop, cause);
             }
         }
+
+        broadcast_table_events(&mut self.detached);
     }
 
     fn timeout(&mut self, event_loop: &mut EventLoop<DhtHandler<H>>, data: (u64, ScheduledTask)) {
@@ -212,7 +296,12 @@ impl<H> Handler for DhtHandler<H>
             ScheduledTask::CheckLookupEndGame(trans_id) => {
                 handle_check_lookup_endgame(self, event_loop, trans_id);
             }
+            ScheduledTask::CheckItemLookupTimeout(trans_id) => {
+                handle_check_item_lookup_timeout(self, event_loop, trans_id);
+            }
         }
+
+        broadcast_table_events(&mut self.detached);
     }
 }
 
@@ -232,11 +321,41 @@ fn broadcast_dht_event(notifiers: &mut Vec<mpsc::Sender<DhtEvent>>, event: DhtEv
     notifiers.retain(|send| send.send(event).is_ok());
 }
 
+/// Broadcast the nodes that were added to or removed from the routing table.
+fn broadcast_table_events<H>(work_storage: &mut DetachedDhtHandler<H>) {
+    for table_event in work_storage.routing_table.take_events() {
+        let event = match table_event {
+            TableEvent::NodeAdded(id, addr) => DhtEvent::NodeAdded(id, addr),
+            TableEvent::NodeRemoved(id, addr) => DhtEvent::NodeRemoved(id, addr),
+        };
+
+        broadcast_dht_event(&mut work_storage.event_notifiers, event);
+    }
+}
+
+/// Broadcast the statistics (and scrape estimate) for a completed lookup, followed by its completion.
+fn broadcast_lookup_completed(notifiers: &mut Vec<mpsc::Sender<DhtEvent>>,
+                              info_hash: InfoHash,
+                              stats: LookupStats,
+                              opt_scrape: Option<ScrapeEstimate>) {
+    if let Some(scrape) = opt_scrape {
+        broadcast_dht_event(notifiers, DhtEvent::ScrapeEstimate(info_hash, scrape));
+    }
+    broadcast_dht_event(notifiers, DhtEvent::LookupStatistics(info_hash, stats));
+    broadcast_dht_event(notifiers, DhtEvent::LookupCompleted(info_hash));
+}
+
 /// Number of good nodes in the RoutingTable.
 fn num_good_nodes(table: &RoutingTable) -> usize {
     table.closest_nodes(table.node_id()).filter(|n| n.status() == NodeStatus::Good).count()
 }
 
+/// Whether or not a request wants IPv4 nodes (BEP 32), which is the only family we currently
+/// store. Requests without a want argument get nodes of the family they were sent over.
+fn wants_ipv4_nodes(want: Option<Want>) -> bool {
+    want.map(|want| want.ipv4()).unwrap_or(true)
+}
+
 /// We should rebootstrap if we have a low number of nodes.
 fn should_rebootstrap(table: &RoutingTable) -> bool {
     num_good_nodes(table) <= BOOTSTRAP_GOOD_NODE_THRESHOLD
@@ -264,18 +383,22 @@ fn broadcast_bootstrap_completed<H>(action_id: ActionID,
     let mut future_actions = work_storage.future_actions.split_off(0);
     for table_action in future_actions.drain(..) {
         match table_action {
-            PostBootstrapAction::Lookup(info_hash, should_announce) => {
+            PostBootstrapAction::Lookup(info_hash, options, opt_trace) => {
                 handle_start_lookup(table_actions,
                                     work_storage,
                                     event_loop,
                                     info_hash,
-                                    should_announce);
+                                    options,
+                                    opt_trace);
             }
             PostBootstrapAction::Refresh(refresh, trans_id) => {
                 table_actions.insert(trans_id.action_id(), TableAction::Refresh(refresh));
 
                 handle_check_table_refresh(table_actions, work_storage, event_loop, trans_id);
             }
+            PostBootstrapAction::ItemLookup(operation) => {
+                handle_start_item_lookup(table_actions, work_storage, event_loop, operation);
+            }
         }
     }
 }
@@ -358,6 +481,7 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
             Some(&TableAction::Lookup(_)) => ExpectedResponse::GetPeers,
             Some(&TableAction::Refresh(_)) => ExpectedResponse::FindNode,
             Some(&TableAction::Bootstrap(_, _)) => ExpectedResponse::FindNode,
+            Some(&TableAction::ItemLookup(ref lookup)) => lookup.expected_response(),
             None => ExpectedResponse::None,
         }
     });
@@ -373,6 +497,17 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
         }
     }
 
+    // Drop requests from remote nodes that are querying us too often
+    if let Ok(MessageType::Request(_)) = message {
+        work_storage.metrics.counter(QUERIES_RECEIVED_METRIC, 1);
+
+        if !work_storage.query_throttle.allow(IpAddr::from_socket_addr(addr), Instant::now()) {
+            info!("bip_dht: Throttled a request from {}...", addr);
+            work_storage.metrics.counter(QUERIES_THROTTLED_METRIC, 1);
+            return;
+        }
+    }
+
     // Process the given message
     match message {
         Ok(MessageType::Request(RequestType::Ping(p))) => {
@@ -386,7 +521,7 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                                              work_storage.routing_table.node_id());
             let ping_msg = ping_rsp.encode();
 
-            if work_storage.out_channel.send((ping_msg, addr)).is_err() {
+            if work_storage.out_channel.send(OutgoingMessage::Response(ping_msg, addr)).is_err() {
                 error!("bip_dht: Failed to send a ping response on the out channel...");
                 shutdown_event_loop(event_loop, ShutdownCause::Unspecified);
             }
@@ -398,10 +533,12 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
             // Node requested from us, mark it in the Routingtable
             work_storage.routing_table.find_node(&node).map(|n| n.remote_request());
 
-            // Grab the closest nodes
+            // Grab the closest nodes, unless they only want nodes we cant give them (BEP 32)
             let mut closest_nodes_bytes = Vec::with_capacity(26 * 8);
-            for node in work_storage.routing_table.closest_nodes(f.target_id()).take(8) {
-                closest_nodes_bytes.extend_from_slice(&node.encode());
+            if wants_ipv4_nodes(f.want()) {
+                for node in work_storage.routing_table.closest_nodes(f.target_id()).take(8) {
+                    closest_nodes_bytes.extend_from_slice(&node.encode());
+                }
             }
 
             let find_node_rsp = FindNodeResponse::new(f.transaction_id(),
@@ -410,7 +547,7 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                 .unwrap();
             let find_node_msg = find_node_rsp.encode();
 
-            if work_storage.out_channel.send((find_node_msg, addr)).is_err() {
+            if work_storage.out_channel.send(OutgoingMessage::Response(find_node_msg, addr)).is_err() {
                 error!("bip_dht: Failed to send a find node response on the out channel...");
                 shutdown_event_loop(event_loop, ShutdownCause::Unspecified);
             }
@@ -423,10 +560,9 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
             work_storage.routing_table.find_node(&node).map(|n| n.remote_request());
 
             // TODO: Move socket address serialization code into bip_util
-            // TODO: Check what the maximum number of values we can give without overflowing a udp packet
-            // Also, if we arent going to give all of the contacts, we may want to shuffle which ones we give
+            // Seeds and leechers are balanced in what we give, leaving out seeds if the requester is one (BEP 33)
             let mut contact_info_bytes = Vec::with_capacity(6 * 20);
-            work_storage.active_stores.find_items(&g.info_hash(), |addr| {
+            work_storage.active_stores.find_balanced_items(&g.info_hash(), MAX_GET_PEERS_VALUES, g.no_seed(), |addr| {
                 let mut bytes = [0u8; 6];
                 let port = addr.port();
 
@@ -457,10 +593,12 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                 contact_info_bencode.push(ben_bytes!(&contact_info_bytes[start..end]));
             }
 
-            // Grab the closest nodes
+            // Grab the closest nodes, unless they only want nodes we cant give them (BEP 32)
             let mut closest_nodes_bytes = Vec::with_capacity(26 * 8);
-            for node in work_storage.routing_table.closest_nodes(g.info_hash()).take(8) {
-                closest_nodes_bytes.extend_from_slice(&node.encode());
+            if wants_ipv4_nodes(g.want()) {
+                for node in work_storage.routing_table.closest_nodes(g.info_hash()).take(8) {
+                    closest_nodes_bytes.extend_from_slice(&node.encode());
+                }
             }
 
             // Wrap up the nodes/values we are going to be giving them
@@ -472,13 +610,30 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                 CompactInfoType::Nodes(CompactNodeInfo::new(&closest_nodes_bytes).unwrap())
             };
 
-            let get_peers_rsp = GetPeersResponse::new(g.transaction_id(),
-                                                      work_storage.routing_table.node_id(),
-                                                      Some(token.as_ref()),
-                                                      comapct_info_type);
+            // Summarize the seeds and leechers we have stored, if they asked for it (BEP 33)
+            let (mut seeds_filter, mut leechers_filter) = (ScrapeFilter::new(), ScrapeFilter::new());
+            if g.scrape() {
+                work_storage.active_stores.find_seeded_items(&g.info_hash(), |addr, seed| {
+                    if seed {
+                        seeds_filter.insert(addr.ip());
+                    } else {
+                        leechers_filter.insert(addr.ip());
+                    }
+                });
+            }
+
+            let mut get_peers_rsp = GetPeersResponse::new(g.transaction_id(),
+                                                          work_storage.routing_table.node_id(),
+                                                          Some(token.as_ref()),
+                                                          comapct_info_type);
+            // Empty filters still tell them that we have nothing stored, so they are always included
+            if g.scrape() {
+                get_peers_rsp = get_peers_rsp.with_scrape_filters(seeds_filter.as_bytes(),
+                                                                  leechers_filter.as_bytes());
+            }
             let get_peers_msg = get_peers_rsp.encode();
 
-            if work_storage.out_channel.send((get_peers_msg, addr)).is_err() {
+            if work_storage.out_channel.send(OutgoingMessage::Response(get_peers_msg, addr)).is_err() {
                 error!("bip_dht: Failed to send a get peers response on the out channel...");
                 shutdown_event_loop(event_loop, ShutdownCause::Unspecified);
             }
@@ -496,23 +651,7 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                 Err(_) => false,
             };
 
-            // Create a socket address based on the implied/explicit port number
-            let connect_addr = match a.connect_port() {
-                ConnectPort::Implied => addr,
-                ConnectPort::Explicit(port) => {
-                    match addr {
-                        SocketAddr::V4(v4_addr) => {
-                            SocketAddr::V4(SocketAddrV4::new(*v4_addr.ip(), port))
-                        }
-                        SocketAddr::V6(v6_addr) => {
-                            SocketAddr::V6(SocketAddrV6::new(*v6_addr.ip(),
-                                                             port,
-                                                             v6_addr.flowinfo(),
-                                                             v6_addr.scope_id()))
-                        }
-                    }
-                }
-            };
+            let connect_addr = announce_connect_addr(a.connect_port(), addr);
 
             // Resolve type of response we are going to send
             let response_msg = if !is_valid {
@@ -523,7 +662,7 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                                   "Received An Invalid Token".to_owned())
                     .encode()
             } else if work_storage.active_stores
-                .add_item(a.info_hash(), connect_addr) {
+                .add_item(a.info_hash(), connect_addr, a.seed()) {
                 // Node successfully stored the value with us, send an announce response
                 AnnouncePeerResponse::new(a.transaction_id(), work_storage.routing_table.node_id())
                     .encode()
@@ -538,16 +677,149 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                     .encode()
             };
 
-            if work_storage.out_channel.send((response_msg, addr)).is_err() {
+            if work_storage.out_channel.send(OutgoingMessage::Response(response_msg, addr)).is_err() {
                 error!("bip_dht: Failed to send an announce peer response on the out channel...");
                 shutdown_event_loop(event_loop, ShutdownCause::Unspecified);
             }
         }
+        Ok(MessageType::Request(RequestType::GetData(g))) => {
+            info!("bip_dht: Received a GetDataRequest...");
+            let node = Node::as_good(g.node_id(), addr);
+
+            // Node requested from us, mark it in the Routingtable
+            work_storage.routing_table.find_node(&node).map(|n| n.remote_request());
+
+            // Grab the closest nodes
+            let mut closest_nodes_bytes = Vec::with_capacity(26 * 8);
+            for node in work_storage.routing_table.closest_nodes(g.target()).take(8) {
+                closest_nodes_bytes.extend_from_slice(&node.encode());
+            }
+            let token = work_storage.token_store.checkout(IpAddr::from_socket_addr(addr));
+
+            // Only give out the item if it is newer than what the requester already has
+            let opt_item = work_storage.item_stores.find_item(&g.target()).and_then(|item| {
+                match (item, g.seq()) {
+                    (&Item::Mutable(ref mutable), Some(seq)) if mutable.seq() <= seq => None,
+                    _ => Some(item),
+                }
+            });
+            let opt_value = opt_item.and_then(|item| Bencode::decode(item.value()).ok());
+            let opt_mutable = match opt_item {
+                Some(&Item::Mutable(ref mutable)) => {
+                    Some(MutableInfo::new(mutable.public_key(), mutable.signature(), mutable.seq()))
+                }
+                _ => None,
+            };
+
+            let get_data_rsp = GetDataResponse::new(g.transaction_id(),
+                                                    work_storage.routing_table.node_id(),
+                                                    Some(token.as_ref()),
+                                                    CompactNodeInfo::new(&closest_nodes_bytes).ok(),
+                                                    opt_value.as_ref(),
+                                                    opt_mutable);
+            let get_data_msg = get_data_rsp.encode();
+
+            if work_storage.out_channel.send(OutgoingMessage::Response(get_data_msg, addr)).is_err() {
+                error!("bip_dht: Failed to send a get data response on the out channel...");
+                shutdown_event_loop(event_loop, ShutdownCause::Unspecified);
+            }
+        }
+        Ok(MessageType::Request(RequestType::PutData(p))) => {
+            info!("bip_dht: Received a PutDataRequest...");
+            let node = Node::as_good(p.node_id(), addr);
+
+            // Node requested from us, mark it in the Routingtable
+            work_storage.routing_table.find_node(&node).map(|n| n.remote_request());
+
+            // Validate the token
+            let is_valid = match Token::new(p.token()) {
+                Ok(t) => work_storage.token_store.checkin(IpAddr::from_socket_addr(addr), t),
+                Err(_) => false,
+            };
+            let value = p.value().encode();
+            let salt = p.salt().unwrap_or(&[]);
+
+            // Resolve type of response we are going to send
+            let response_msg = if !is_valid {
+                // Node gave us an invalid token
+                warn!("bip_dht: Remote node sent us an invalid token for a PutDataRequest...");
+                ErrorMessage::new(p.transaction_id().to_vec(),
+                                  ErrorCode::ProtocolError,
+                                  "Received An Invalid Token".to_owned())
+                    .encode()
+            } else if value.len() > item::MAX_VALUE_LEN {
+                ErrorMessage::new(p.transaction_id().to_vec(),
+                                  ErrorCode::MessageTooBig,
+                                  "Value Is Too Big".to_owned())
+                    .encode()
+            } else if salt.len() > item::MAX_SALT_LEN {
+                ErrorMessage::new(p.transaction_id().to_vec(),
+                                  ErrorCode::SaltTooBig,
+                                  "Salt Is Too Big".to_owned())
+                    .encode()
+            } else {
+                let opt_item = match p.mutable_info() {
+                    Some(mutable) => {
+                        MutableItem::from_parts(mutable.key(),
+                                                mutable.signature(),
+                                                salt,
+                                                mutable.seq(),
+                                                &value)
+                            .map(Item::Mutable)
+                    }
+                    None => ImmutableItem::new(value).map(Item::Immutable),
+                };
+
+                match opt_item.map(|item| work_storage.item_stores.put_item(item, p.cas())) {
+                    None => {
+                        warn!("bip_dht: Remote node sent us an invalid signature for a \
+                               PutDataRequest...");
+                        ErrorMessage::new(p.transaction_id().to_vec(),
+                                          ErrorCode::InvalidSignature,
+                                          "Invalid Signature".to_owned())
+                            .encode()
+                    }
+                    Some(Ok(())) => {
+                        PutDataResponse::new(p.transaction_id(),
+                                             work_storage.routing_table.node_id())
+                            .encode()
+                    }
+                    Some(Err(PutItemError::StorageFull)) => {
+                        warn!("bip_dht: ItemStorage failed to store an item because it is \
+                               full...");
+                        ErrorMessage::new(p.transaction_id().to_vec(),
+                                          ErrorCode::ServerError,
+                                          "Item Storage Is Full".to_owned())
+                            .encode()
+                    }
+                    Some(Err(PutItemError::CasMismatch)) => {
+                        ErrorMessage::new(p.transaction_id().to_vec(),
+                                          ErrorCode::CasMismatch,
+                                          "CAS Mismatch".to_owned())
+                            .encode()
+                    }
+                    Some(Err(PutItemError::SequenceTooLow)) => {
+                        ErrorMessage::new(p.transaction_id().to_vec(),
+                                          ErrorCode::SequenceTooLow,
+                                          "Sequence Number Less Than Current".to_owned())
+                            .encode()
+                    }
+                }
+            };
+
+            if work_storage.out_channel.send(OutgoingMessage::Response(response_msg, addr)).is_err() {
+                error!("bip_dht: Failed to send a put data response on the out channel...");
+                shutdown_event_loop(event_loop, ShutdownCause::Unspecified);
+            }
+        }
         Ok(MessageType::Response(ResponseType::FindNode(f))) => {
             info!("bip_dht: Received a FindNodeResponse...");
             let trans_id = TransactionID::from_bytes(f.transaction_id()).unwrap();
             let node = Node::as_good(f.node_id(), addr);
 
+            // Sample the round trip time before the node is replaced in the table
+            work_storage.routing_table.find_node(&node).map(|n| n.response_for(trans_id));
+
             // Add the payload nodes as questionable
             for (id, v4_addr) in f.nodes() {
                 let sock_addr = SocketAddr::V4(v4_addr);
@@ -571,6 +843,11 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                         error!("bip_dht: Resolved a FindNodeResponse ActionID to a TableLookup...");
                         None
                     }
+                    Some(&mut TableAction::ItemLookup(_)) => {
+                        error!("bip_dht: Resolved a FindNodeResponse ActionID to a \
+                                TableItemLookup...");
+                        None
+                    }
                     None => {
                         error!("bip_dht: Resolved a TransactionID to a FindNodeResponse but no \
                                 action found...");
@@ -641,6 +918,9 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
             let trans_id = TransactionID::from_bytes(g.transaction_id()).unwrap();
             let node = Node::as_good(g.node_id(), addr);
 
+            // Sample the round trip time before the node is replaced in the table
+            work_storage.routing_table.find_node(&node).map(|n| n.response_for(trans_id));
+
             work_storage.routing_table.add_node(node.clone());
 
             let opt_lookup = {
@@ -656,6 +936,11 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                                 TableBootstrap...");
                         None
                     }
+                    Some(&mut TableAction::ItemLookup(_)) => {
+                        error!("bip_dht: Resolved a GetPeersResponse ActionID to a \
+                                TableItemLookup...");
+                        None
+                    }
                     None => {
                         error!("bip_dht: Resolved a TransactionID to a GetPeersResponse but no \
                                 action found...");
@@ -673,8 +958,10 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                                            event_loop) {
                     LookupStatus::Searching => (),
                     LookupStatus::Completed => {
-                        broadcast_dht_event(&mut work_storage.event_notifiers,
-                                            DhtEvent::LookupCompleted(lookup.info_hash()))
+                        broadcast_lookup_completed(&mut work_storage.event_notifiers,
+                                                   lookup.info_hash(),
+                                                   lookup.stats(),
+                                                   lookup.scrape_estimate())
                     }
                     LookupStatus::Failed => {
                         shutdown_event_loop(event_loop, ShutdownCause::Unspecified)
@@ -688,6 +975,67 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                 }
             }
         }
+        Ok(MessageType::Response(ResponseType::GetData(g))) => {
+            info!("bip_dht: Received a GetDataResponse...");
+            let trans_id = TransactionID::from_bytes(g.transaction_id()).unwrap();
+            let node = Node::as_good(g.node_id(), addr);
+
+            // Sample the round trip time before the node is replaced in the table
+            work_storage.routing_table.find_node(&node).map(|n| n.response_for(trans_id));
+
+            work_storage.routing_table.add_node(node.clone());
+
+            let opt_status = match table_actions.get_mut(&trans_id.action_id()) {
+                Some(&mut TableAction::ItemLookup(ref mut lookup)) => {
+                    Some(lookup.recv_get_response(node,
+                                                  &trans_id,
+                                                  g,
+                                                  &work_storage.routing_table,
+                                                  &work_storage.out_channel,
+                                                  event_loop))
+                }
+                Some(_) => {
+                    error!("bip_dht: Resolved a GetDataResponse ActionID to a non item lookup \
+                            action...");
+                    None
+                }
+                None => {
+                    error!("bip_dht: Resolved a TransactionID to a GetDataResponse but no \
+                            action found...");
+                    None
+                }
+            };
+
+            handle_item_lookup_status(table_actions, event_loop, trans_id, opt_status);
+        }
+        Ok(MessageType::Response(ResponseType::PutData(p))) => {
+            info!("bip_dht: Received a PutDataResponse...");
+            let trans_id = TransactionID::from_bytes(p.transaction_id()).unwrap();
+            let node = Node::as_good(p.node_id(), addr);
+
+            // Sample the round trip time before the node is replaced in the table
+            work_storage.routing_table.find_node(&node).map(|n| n.response_for(trans_id));
+
+            work_storage.routing_table.add_node(node);
+
+            let opt_status = match table_actions.get_mut(&trans_id.action_id()) {
+                Some(&mut TableAction::ItemLookup(ref mut lookup)) => {
+                    Some(lookup.recv_put_response(&trans_id, event_loop))
+                }
+                Some(_) => {
+                    error!("bip_dht: Resolved a PutDataResponse ActionID to a non item lookup \
+                            action...");
+                    None
+                }
+                None => {
+                    error!("bip_dht: Resolved a TransactionID to a PutDataResponse but no \
+                            action found...");
+                    None
+                }
+            };
+
+            handle_item_lookup_status(table_actions, event_loop, trans_id, opt_status);
+        }
         Ok(MessageType::Response(ResponseType::Ping(_))) => {
             info!("bip_dht: Received a PingResponse...");
 
@@ -711,6 +1059,11 @@ fn handle_register_sender<H>(handler: &mut DhtHandler<H>, sender: mpsc::Sender<D
     handler.detached.event_notifiers.push(sender);
 }
 
+fn handle_announce_stats<H>(handler: &mut DhtHandler<H>, sender: oneshot::Sender<AnnounceStats>) {
+    // Client may have dropped the receiver, which is fine
+    let _ = sender.send(handler.detached.active_stores.stats());
+}
+
 fn handle_start_bootstrap<H>(handler: &mut DhtHandler<H>,
                              event_loop: &mut EventLoop<DhtHandler<H>>,
                              routers: Vec<Router>,
@@ -727,7 +1080,8 @@ fn handle_start_bootstrap<H>(handler: &mut DhtHandler<H>,
     let mut table_bootstrap = TableBootstrap::new(work_storage.routing_table.node_id(),
                                                   mid_generator,
                                                   nodes,
-                                                  router_iter);
+                                                  router_iter,
+                                                  work_storage.want);
 
     // Begin the bootstrap operation
     let bootstrap_status = table_bootstrap.start_bootstrap(&work_storage.out_channel, event_loop);
@@ -768,7 +1122,8 @@ fn handle_start_lookup<H>(table_actions: &mut HashMap<ActionID, TableAction>,
                           work_storage: &mut DetachedDhtHandler<H>,
                           event_loop: &mut EventLoop<DhtHandler<H>>,
                           info_hash: InfoHash,
-                          should_announce: bool)
+                          options: LookupOptions,
+                          opt_trace: Option<oneshot::Sender<LookupTrace>>)
     where H: Handshaker
 {
     let mid_generator = work_storage.aid_generator.generate();
@@ -776,13 +1131,17 @@ fn handle_start_lookup<H>(table_actions: &mut HashMap<ActionID, TableAction>,
 
     if work_storage.bootstrapping {
         // Queue it up if we are currently bootstrapping
-        work_storage.future_actions.push(PostBootstrapAction::Lookup(info_hash, should_announce));
+        work_storage.future_actions
+            .push(PostBootstrapAction::Lookup(info_hash, options, opt_trace));
     } else {
         // Start the lookup right now if not bootstrapping
         match TableLookup::new(work_storage.routing_table.node_id(),
                                info_hash,
                                mid_generator,
-                               should_announce,
+                               options,
+                               opt_trace.map(|sender| LookupTracer::new(info_hash, sender)),
+                               work_storage.want,
+                               work_storage.lookup_config,
                                &work_storage.routing_table,
                                &work_storage.out_channel,
                                event_loop) {
@@ -794,6 +1153,34 @@ fn handle_start_lookup<H>(table_actions: &mut HashMap<ActionID, TableAction>,
     }
 }
 
+fn handle_start_item_lookup<H>(table_actions: &mut HashMap<ActionID, TableAction>,
+                               work_storage: &mut DetachedDhtHandler<H>,
+                               event_loop: &mut EventLoop<DhtHandler<H>>,
+                               operation: ItemOperation)
+    where H: Handshaker
+{
+    let mid_generator = work_storage.aid_generator.generate();
+    let action_id = mid_generator.action_id();
+
+    if work_storage.bootstrapping {
+        // Queue it up if we are currently bootstrapping
+        work_storage.future_actions.push(PostBootstrapAction::ItemLookup(operation));
+    } else {
+        // Start the item lookup right now if not bootstrapping
+        match TableItemLookup::new(work_storage.routing_table.node_id(),
+                                   mid_generator,
+                                   operation,
+                                   &work_storage.routing_table,
+                                   &work_storage.out_channel,
+                                   event_loop) {
+            Some(lookup) => {
+                table_actions.insert(action_id, TableAction::ItemLookup(lookup));
+            }
+            None => shutdown_event_loop(event_loop, ShutdownCause::Unspecified),
+        }
+    }
+}
+
 fn handle_shutdown<H>(handler: &mut DhtHandler<H>,
                       event_loop: &mut EventLoop<DhtHandler<H>>,
                       cause: ShutdownCause)
@@ -829,6 +1216,11 @@ fn handle_check_table_refresh<H>(table_actions: &mut HashMap<ActionID, TableActi
                     TableBootstrap found...");
             None
         }
+        Some(&mut TableAction::ItemLookup(_)) => {
+            error!("bip_dht: Resolved a TransactionID to a check table refresh but \
+                    TableItemLookup found...");
+            None
+        }
         None => {
             error!("bip_dht: Resolved a TransactionID to a check table refresh but no action \
                     found...");
@@ -870,6 +1262,11 @@ fn handle_check_bootstrap_timeout<H>(handler: &mut DhtHandler<H>,
                         TableRefresh found...");
                 None
             }
+            Some(&mut TableAction::ItemLookup(_)) => {
+                error!("bip_dht: Resolved a TransactionID to a check table bootstrap but \
+                        TableItemLookup found...");
+                None
+            }
             None => {
                 error!("bip_dht: Resolved a TransactionID to a check table bootstrap but no \
                         action found...");
@@ -918,7 +1315,9 @@ fn handle_check_lookup_timeout<H>(handler: &mut DhtHandler<H>,
                                       &work_storage.routing_table,
                                       &work_storage.out_channel,
                                       event_loop),
-                  lookup.info_hash()))
+                  lookup.info_hash(),
+                  lookup.stats(),
+                  lookup.scrape_estimate()))
         }
         Some(&mut TableAction::Bootstrap(_, _)) => {
             error!("bip_dht: Resolved a TransactionID to a check table lookup but TableBootstrap \
@@ -930,6 +1329,11 @@ fn handle_check_lookup_timeout<H>(handler: &mut DhtHandler<H>,
                     found...");
             None
         }
+        Some(&mut TableAction::ItemLookup(_)) => {
+            error!("bip_dht: Resolved a TransactionID to a check table lookup but \
+                    TableItemLookup found...");
+            None
+        }
         None => {
             error!("bip_dht: Resolved a TransactionID to a check table lookup but no action \
                     found...");
@@ -939,15 +1343,14 @@ fn handle_check_lookup_timeout<H>(handler: &mut DhtHandler<H>,
 
     match opt_lookup_info {
         None => (),
-        Some((LookupStatus::Searching, _)) => (),
-        Some((LookupStatus::Completed, info_hash)) => {
-            broadcast_dht_event(&mut work_storage.event_notifiers,
-                                DhtEvent::LookupCompleted(info_hash))
+        Some((LookupStatus::Searching, _, _, _)) => (),
+        Some((LookupStatus::Completed, info_hash, stats, opt_scrape)) => {
+            broadcast_lookup_completed(&mut work_storage.event_notifiers, info_hash, stats, opt_scrape)
         }
-        Some((LookupStatus::Failed, _)) => {
+        Some((LookupStatus::Failed, _, _, _)) => {
             shutdown_event_loop(event_loop, ShutdownCause::Unspecified)
         }
-        Some((LookupStatus::Values(v), info_hash)) => {
+        Some((LookupStatus::Values(v), info_hash, _, _)) => {
             // Add values to handshaker
             for v4_addr in v {
                 let sock_addr = SocketAddr::V4(v4_addr);
@@ -970,7 +1373,9 @@ fn handle_check_lookup_endgame<H>(handler: &mut DhtHandler<H>,
             Some((lookup.recv_finished(work_storage.handshaker.port(),
                                        &work_storage.routing_table,
                                        &work_storage.out_channel),
-                  lookup.info_hash()))
+                  lookup.info_hash(),
+                  lookup.stats(),
+                  lookup.scrape_estimate()))
         }
         Some(TableAction::Bootstrap(_, _)) => {
             error!("bip_dht: Resolved a TransactionID to a check table lookup but TableBootstrap \
@@ -982,6 +1387,11 @@ fn handle_check_lookup_endgame<H>(handler: &mut DhtHandler<H>,
                     found...");
             None
         }
+        Some(TableAction::ItemLookup(_)) => {
+            error!("bip_dht: Resolved a TransactionID to a check table lookup but \
+                    TableItemLookup found...");
+            None
+        }
         None => {
             error!("bip_dht: Resolved a TransactionID to a check table lookup but no action \
                     found...");
@@ -991,15 +1401,14 @@ fn handle_check_lookup_endgame<H>(handler: &mut DhtHandler<H>,
 
     match opt_lookup_info {
         None => (),
-        Some((LookupStatus::Searching, _)) => (),
-        Some((LookupStatus::Completed, info_hash)) => {
-            broadcast_dht_event(&mut work_storage.event_notifiers,
-                                DhtEvent::LookupCompleted(info_hash))
+        Some((LookupStatus::Searching, _, _, _)) => (),
+        Some((LookupStatus::Completed, info_hash, stats, opt_scrape)) => {
+            broadcast_lookup_completed(&mut work_storage.event_notifiers, info_hash, stats, opt_scrape)
         }
-        Some((LookupStatus::Failed, _)) => {
+        Some((LookupStatus::Failed, _, _, _)) => {
             shutdown_event_loop(event_loop, ShutdownCause::Unspecified)
         }
-        Some((LookupStatus::Values(v), info_hash)) => {
+        Some((LookupStatus::Values(v), info_hash, _, _)) => {
             // Add values to handshaker
             for v4_addr in v {
                 let sock_addr = SocketAddr::V4(v4_addr);
@@ -1009,3 +1418,109 @@ fn handle_check_lookup_endgame<H>(handler: &mut DhtHandler<H>,
         }
     }
 }
+
+fn handle_check_item_lookup_timeout<H>(handler: &mut DhtHandler<H>,
+                                       event_loop: &mut EventLoop<DhtHandler<H>>,
+                                       trans_id: TransactionID)
+    where H: Handshaker
+{
+    let (work_storage, table_actions) = (&mut handler.detached, &mut handler.table_actions);
+
+    let opt_status = match table_actions.get_mut(&trans_id.action_id()) {
+        Some(&mut TableAction::ItemLookup(ref mut lookup)) => {
+            Some(lookup.recv_timeout(&work_storage.routing_table,
+                                     &work_storage.out_channel,
+                                     event_loop))
+        }
+        Some(_) => {
+            error!("bip_dht: Resolved a TransactionID to a check item lookup but a non item \
+                    lookup action found...");
+            None
+        }
+        None => {
+            error!("bip_dht: Resolved a TransactionID to a check item lookup but no action \
+                    found...");
+            None
+        }
+    };
+
+    handle_item_lookup_status(table_actions, event_loop, trans_id, opt_status);
+}
+
+/// Remove the item lookup if it has completed, or shutdown the dht if it has failed.
+fn handle_item_lookup_status<H>(table_actions: &mut HashMap<ActionID, TableAction>,
+                                event_loop: &mut EventLoop<DhtHandler<H>>,
+                                trans_id: TransactionID,
+                                opt_status: Option<ItemLookupStatus>)
+    where H: Handshaker
+{
+    match opt_status {
+        None => (),
+        Some(ItemLookupStatus::Searching) => (),
+        Some(ItemLookupStatus::Completed) => {
+            table_actions.remove(&trans_id.action_id());
+        }
+        Some(ItemLookupStatus::Failed) => {
+            shutdown_event_loop(event_loop, ShutdownCause::Unspecified)
+        }
+    }
+}
+
+/// Address that an announcing node can be connected to, based on the implied/explicit port number.
+fn announce_connect_addr(connect_port: ConnectPort, addr: SocketAddr) -> SocketAddr {
+    match connect_port {
+        ConnectPort::Implied => addr,
+        ConnectPort::Explicit(port) => {
+            match addr {
+                SocketAddr::V4(v4_addr) => {
+                    SocketAddr::V4(SocketAddrV4::new(*v4_addr.ip(), port))
+                }
+                SocketAddr::V6(v6_addr) => {
+                    SocketAddr::V6(SocketAddrV6::new(*v6_addr.ip(),
+                                                     port,
+                                                     v6_addr.flowinfo(),
+                                                     v6_addr.scope_id()))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use message::announce_peer::ConnectPort;
+    use message::want::Want;
+
+    #[test]
+    fn positive_wants_ipv4_nodes_without_want() {
+        assert!(super::wants_ipv4_nodes(None));
+    }
+
+    #[test]
+    fn positive_wants_ipv4_nodes_with_ipv4() {
+        assert!(super::wants_ipv4_nodes(Some(Want::new(true, false))));
+        assert!(super::wants_ipv4_nodes(Some(Want::new(true, true))));
+    }
+
+    #[test]
+    fn negative_wants_ipv4_nodes_only_ipv6() {
+        assert!(!super::wants_ipv4_nodes(Some(Want::new(false, true))));
+    }
+
+    #[test]
+    fn positive_announce_implied_port_uses_source_port() {
+        let v4_addr = "10.0.0.1:50000".parse().unwrap();
+        let v6_addr = "[::1]:50000".parse().unwrap();
+
+        assert_eq!(v4_addr, super::announce_connect_addr(ConnectPort::Implied, v4_addr));
+        assert_eq!(v6_addr, super::announce_connect_addr(ConnectPort::Implied, v6_addr));
+    }
+
+    #[test]
+    fn positive_announce_explicit_port_replaces_source_port() {
+        assert_eq!("10.0.0.1:6881".parse().unwrap(),
+                   super::announce_connect_addr(ConnectPort::Explicit(6881), "10.0.0.1:50000".parse().unwrap()));
+        assert_eq!("[::1]:6881".parse().unwrap(),
+                   super::announce_connect_addr(ConnectPort::Explicit(6881), "[::1]:50000".parse().unwrap()));
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::mpsc::SyncSender;
+
+use bip_bencode::Bencode;
+use bip_handshake::Handshaker;
+use bip_util::bt::NodeId;
+use bip_util::sha::ShaHash;
+use futures::sync::oneshot;
+use mio::{EventLoop, Timeout};
+
+use item::{Item, ItemKey, ImmutableItem, MutableItem};
+use message::get_data::{GetDataRequest, GetDataResponse, MutableInfo};
+use message::put_data::PutDataRequest;
+use message::response::ExpectedResponse;
+use routing::bucket;
+use routing::node::{Node, NodeStatus};
+use routing::table::RoutingTable;
+use transaction::{MIDGenerator, TransactionID};
+use worker::ScheduledTask;
+use worker::handler::DhtHandler;
+use worker::messenger::OutgoingMessage;
+
+const ITEM_LOOKUP_TIMEOUT_MS: u64 = 3000;
+const ITEM_PUT_TIMEOUT_MS: u64 = 1500;
+
+const MAX_ITEM_LOOKUP_REQUESTS: usize = 64;
+const PUT_PICK_NUM: usize = 8;
+
+type Distance = ShaHash;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ItemLookupStatus {
+    Searching,
+    Completed,
+    Failed,
+}
+
+/// Operation that the item lookup will perform once the closest nodes are found.
+pub enum ItemOperation {
+    /// Retrieve the item, notifying the sender with the most recent item found.
+    Get(ItemKey, oneshot::Sender<Option<Item>>),
+    /// Store the item, notifying the sender with the number of nodes that stored it.
+    Put(Item, Option<i64>, oneshot::Sender<usize>),
+}
+
+pub struct TableItemLookup {
+    table_id: NodeId,
+    target: ShaHash,
+    id_generator: MIDGenerator,
+    operation: Option<ItemOperation>,
+    // Whether or not we are in the put phase (get phase completed)
+    in_put: bool,
+    active_lookups: HashSet<TransactionID>,
+    requested_nodes: HashSet<Node>,
+    // Nodes that gave us a write token, sorted by distance to the target
+    token_nodes: Vec<(Distance, Node, Vec<u8>)>,
+    best_item: Option<Item>,
+    num_stored: usize,
+    timeout: Option<Timeout>,
+}
+
+impl TableItemLookup {
+    pub fn new<H>(table_id: NodeId,
+                  id_generator: MIDGenerator,
+                  operation: ItemOperation,
+                  table: &RoutingTable,
+                  out: &SyncSender<OutgoingMessage>,
+                  event_loop: &mut EventLoop<DhtHandler<H>>)
+                  -> Option<TableItemLookup>
+        where H: Handshaker
+    {
+        let target = match operation {
+            ItemOperation::Get(ref key, _) => key.target(),
+            ItemOperation::Put(ref item, _, _) => item.target(),
+        };
+
+        let mut item_lookup = TableItemLookup {
+            table_id: table_id,
+            target: target,
+            id_generator: id_generator,
+            operation: Some(operation),
+            in_put: false,
+            active_lookups: HashSet::new(),
+            requested_nodes: HashSet::new(),
+            token_nodes: Vec::new(),
+            best_item: None,
+            num_stored: 0,
+            timeout: None,
+        };
+
+        // Set an overall timeout for the get phase
+        let trans_id = item_lookup.id_generator.generate();
+        match event_loop.timeout_ms((0, ScheduledTask::CheckItemLookupTimeout(trans_id)),
+                                    ITEM_LOOKUP_TIMEOUT_MS) {
+            Ok(t) => item_lookup.timeout = Some(t),
+            Err(_) => {
+                error!("bip_dht: Failed to set a timeout for an item lookup...");
+                return None;
+            }
+        }
+
+        let initial_nodes: Vec<Node> = table.closest_nodes(target)
+            .filter(|n| n.status() == NodeStatus::Good)
+            .take(bucket::MAX_BUCKET_SIZE)
+            .cloned()
+            .collect();
+        for node in initial_nodes {
+            if !item_lookup.request_node(node, table, out) {
+                return None;
+            }
+        }
+
+        if item_lookup.active_lookups.is_empty() &&
+           item_lookup.finish_get(table, out, event_loop) == ItemLookupStatus::Failed {
+            None
+        } else {
+            Some(item_lookup)
+        }
+    }
+
+    /// Response type that we are expecting for this lookup.
+    pub fn expected_response(&self) -> ExpectedResponse {
+        if self.in_put {
+            ExpectedResponse::PutData
+        } else {
+            ExpectedResponse::GetData
+        }
+    }
+
+    pub fn recv_get_response<'a, H>(&mut self,
+                                    node: Node,
+                                    trans_id: &TransactionID,
+                                    msg: GetDataResponse<'a>,
+                                    table: &RoutingTable,
+                                    out: &SyncSender<OutgoingMessage>,
+                                    event_loop: &mut EventLoop<DhtHandler<H>>)
+                                    -> ItemLookupStatus
+        where H: Handshaker
+    {
+        if self.in_put || !self.active_lookups.remove(trans_id) {
+            warn!("bip_dht: Received expired/unsolicited get response for an item lookup...");
+            return self.current_status();
+        }
+        let node_dist = self.target ^ node.id();
+
+        if let Some(token) = msg.token() {
+            insert_sorted_token(&mut self.token_nodes, node_dist, node, token.to_vec());
+        }
+
+        if let Some(value) = msg.value() {
+            self.recv_value(value, msg.mutable_info());
+        }
+
+        // Only follow nodes that are closer to the target than the responding node
+        if let Some(nodes) = msg.nodes() {
+            for (id, v4_addr) in nodes {
+                let closer_node = Node::as_questionable(id, SocketAddr::V4(v4_addr));
+
+                if (self.target ^ id) < node_dist && !self.request_node(closer_node, table, out) {
+                    return ItemLookupStatus::Failed;
+                }
+            }
+        }
+
+        if self.active_lookups.is_empty() {
+            self.clear_timeout(event_loop);
+            self.finish_get(table, out, event_loop)
+        } else {
+            self.current_status()
+        }
+    }
+
+    pub fn recv_put_response<H>(&mut self,
+                                trans_id: &TransactionID,
+                                event_loop: &mut EventLoop<DhtHandler<H>>)
+                                -> ItemLookupStatus
+        where H: Handshaker
+    {
+        if !self.in_put || !self.active_lookups.remove(trans_id) {
+            warn!("bip_dht: Received expired/unsolicited put response for an item lookup...");
+            return self.current_status();
+        }
+        self.num_stored += 1;
+
+        if self.active_lookups.is_empty() {
+            self.clear_timeout(event_loop);
+            self.finish_put()
+        } else {
+            self.current_status()
+        }
+    }
+
+    pub fn recv_timeout<H>(&mut self,
+                           table: &RoutingTable,
+                           out: &SyncSender<OutgoingMessage>,
+                           event_loop: &mut EventLoop<DhtHandler<H>>)
+                           -> ItemLookupStatus
+        where H: Handshaker
+    {
+        self.timeout = None;
+        self.active_lookups.clear();
+
+        if self.in_put {
+            self.finish_put()
+        } else {
+            self.finish_get(table, out, event_loop)
+        }
+    }
+
+    fn current_status(&self) -> ItemLookupStatus {
+        if self.operation.is_some() {
+            ItemLookupStatus::Searching
+        } else {
+            ItemLookupStatus::Completed
+        }
+    }
+
+    fn clear_timeout<H>(&mut self, event_loop: &mut EventLoop<DhtHandler<H>>)
+        where H: Handshaker
+    {
+        if let Some(timeout) = self.timeout.take() {
+            event_loop.clear_timeout(timeout);
+        }
+    }
+
+    /// Send a get request to the node, returns false if a fatal error occurred.
+    fn request_node(&mut self,
+                    node: Node,
+                    table: &RoutingTable,
+                    out: &SyncSender<OutgoingMessage>)
+                    -> bool {
+        if self.requested_nodes.len() >= MAX_ITEM_LOOKUP_REQUESTS ||
+           self.requested_nodes.contains(&node) {
+            return true;
+        }
+        let trans_id = self.id_generator.generate();
+
+        let get_data_msg = GetDataRequest::new(trans_id.as_ref(), self.table_id, self.target, None)
+            .encode();
+        if out.send(OutgoingMessage::Query(get_data_msg, node.addr())).is_err() {
+            error!("bip_dht: Could not send an item lookup message through the channel...");
+            return false;
+        }
+
+        // We requested from the node, mark it down if the node is in our routing table
+        table.find_node(&node).map(|n| n.local_request(trans_id));
+
+        self.active_lookups.insert(trans_id);
+        self.requested_nodes.insert(node);
+
+        true
+    }
+
+    /// Validate and store the value if it is the most recent one we have seen.
+    fn recv_value<'a>(&mut self, value: &Bencode<'a>, opt_mutable: Option<MutableInfo<'a>>) {
+        let value_bytes = value.encode();
+
+        let opt_item = match (self.operation.as_ref(), opt_mutable) {
+            (Some(&ItemOperation::Get(ItemKey::Immutable(_), _)), _) => {
+                ImmutableItem::new(value_bytes)
+                    .and_then(|i| if i.target() == self.target { Some(Item::Immutable(i)) } else { None })
+            }
+            (Some(&ItemOperation::Get(ItemKey::Mutable(ref key, ref salt), _)), Some(mutable)) => {
+                if mutable.key() == &key[..] {
+                    MutableItem::from_parts(mutable.key(), mutable.signature(), salt, mutable.seq(), &value_bytes)
+                        .map(Item::Mutable)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        match (opt_item, self.best_item.take()) {
+            (Some(Item::Mutable(new)), Some(Item::Mutable(old))) => {
+                if new.seq() > old.seq() {
+                    self.best_item = Some(Item::Mutable(new));
+                } else {
+                    self.best_item = Some(Item::Mutable(old));
+                }
+            }
+            (Some(new), None) => self.best_item = Some(new),
+            (None, old) => {
+                warn!("bip_dht: Item lookup received an item that failed validation...");
+                self.best_item = old;
+            }
+            (Some(_), old) => self.best_item = old,
+        }
+    }
+
+    /// Finish the get phase of the lookup, starting the put phase if we are storing an item.
+    fn finish_get<H>(&mut self,
+                     table: &RoutingTable,
+                     out: &SyncSender<OutgoingMessage>,
+                     event_loop: &mut EventLoop<DhtHandler<H>>)
+                     -> ItemLookupStatus
+        where H: Handshaker
+    {
+        match self.operation.take() {
+            Some(ItemOperation::Get(_, sender)) => {
+                let _ = sender.send(self.best_item.take());
+
+                ItemLookupStatus::Completed
+            }
+            Some(ItemOperation::Put(item, cas, sender)) => {
+                self.in_put = true;
+
+                let status = self.start_put(&item, cas, table, out, event_loop);
+                self.operation = Some(ItemOperation::Put(item, cas, sender));
+
+                if status == ItemLookupStatus::Failed {
+                    status
+                } else if self.active_lookups.is_empty() {
+                    self.clear_timeout(event_loop);
+                    self.finish_put()
+                } else {
+                    status
+                }
+            }
+            None => ItemLookupStatus::Completed,
+        }
+    }
+
+    fn start_put<H>(&mut self,
+                    item: &Item,
+                    cas: Option<i64>,
+                    table: &RoutingTable,
+                    out: &SyncSender<OutgoingMessage>,
+                    event_loop: &mut EventLoop<DhtHandler<H>>)
+                    -> ItemLookupStatus
+        where H: Handshaker
+    {
+        let value = match Bencode::decode(item.value()) {
+            Ok(value) => value,
+            Err(_) => {
+                error!("bip_dht: Item lookup failed to decode a previously validated value...");
+                return ItemLookupStatus::Failed;
+            }
+        };
+        let (mutable, salt) = match item {
+            &Item::Immutable(_) => (None, None),
+            &Item::Mutable(ref m) => {
+                let salt = if m.salt().is_empty() { None } else { Some(m.salt()) };
+
+                (Some(MutableInfo::new(m.public_key(), m.signature(), m.seq())), salt)
+            }
+        };
+
+        let trans_id = self.id_generator.generate();
+        match event_loop.timeout_ms((0, ScheduledTask::CheckItemLookupTimeout(trans_id)),
+                                    ITEM_PUT_TIMEOUT_MS) {
+            Ok(t) => self.timeout = Some(t),
+            Err(_) => {
+                error!("bip_dht: Failed to set a timeout for an item put...");
+                return ItemLookupStatus::Failed;
+            }
+        }
+
+        for &(_, ref node, ref token) in self.token_nodes.iter().take(PUT_PICK_NUM) {
+            let trans_id = self.id_generator.generate();
+
+            let put_data_msg = PutDataRequest::new(trans_id.as_ref(),
+                                                   self.table_id,
+                                                   token,
+                                                   &value,
+                                                   mutable,
+                                                   salt,
+                                                   cas)
+                .encode();
+            if out.send(OutgoingMessage::Query(put_data_msg, node.addr())).is_err() {
+                error!("bip_dht: Could not send an item put message through the channel...");
+                return ItemLookupStatus::Failed;
+            }
+            table.find_node(node).map(|n| n.local_request(trans_id));
+
+            self.active_lookups.insert(trans_id);
+        }
+
+        ItemLookupStatus::Searching
+    }
+
+    fn finish_put(&mut self) -> ItemLookupStatus {
+        if let Some(ItemOperation::Put(_, _, sender)) = self.operation.take() {
+            let _ = sender.send(self.num_stored);
+        }
+
+        ItemLookupStatus::Completed
+    }
+}
+
+/// Inserts the node and token into the list based on the distance from the target.
+fn insert_sorted_token(nodes: &mut Vec<(Distance, Node, Vec<u8>)>,
+                       distance: Distance,
+                       node: Node,
+                       token: Vec<u8>) {
+    if nodes.iter().any(|&(_, ref n, _)| n == &node) {
+        return;
+    }
+
+    let index = match nodes.binary_search_by(|&(dist, _, _)| dist.cmp(&distance)) {
+        Ok(index) => index,
+        Err(index) => index,
+    };
+    nodes.insert(index, (distance, node, token));
+}
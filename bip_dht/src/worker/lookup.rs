@@ -1,6 +1,8 @@
+use std::cmp;
 use std::collections::{HashMap, HashSet};
 use std::net::{SocketAddrV4, SocketAddr};
 use std::sync::mpsc::SyncSender;
+use std::time::{Duration, Instant};
 
 use bip_handshake::Handshaker;
 use bip_util::bt::{self, NodeId, InfoHash};
@@ -9,16 +11,20 @@ use bip_util::sha::ShaHash;
 use mio::{EventLoop, Timeout};
 
 use message::announce_peer::{AnnouncePeerRequest, ConnectPort};
-use message::get_peers::{GetPeersRequest, CompactInfoType, GetPeersResponse};
+use message::get_peers::{CompactInfoType, GetPeersResponse};
+use message::template::RequestTemplate;
+use message::want::Want;
 use routing::bucket;
 use routing::node::{Node, NodeStatus};
 use routing::table::RoutingTable;
+use scrape::{ScrapeFilter, ScrapeEstimate};
 use transaction::{MIDGenerator, TransactionID};
 use worker::ScheduledTask;
 use worker::handler::DhtHandler;
+use worker::messenger::OutgoingMessage;
+use worker::trace::{self, LookupTracer, TraceRound};
 
-const LOOKUP_TIMEOUT_MS: u64 = 1500;
-const ENDGAME_TIMEOUT_MS: u64 = 1500;
+const DEFAULT_QUERY_TIMEOUT_MS: u64 = 1500;
 
 // Currently using the aggressive variant of the standard lookup procedure.
 // https://people.kth.se/~rauljc/p2p11/jimenez2011subsecond.pdf
@@ -26,13 +32,62 @@ const ENDGAME_TIMEOUT_MS: u64 = 1500;
 // TODO: Handle case where a request round fails, should we fail the whole lookup (clear acvite lookups?)
 // TODO: Clean up the code in this module.
 
-const INITIAL_PICK_NUM: usize = 4; // Alpha
-const ITERATIVE_PICK_NUM: usize = 3; // Beta
-const ANNOUNCE_PICK_NUM: usize = 8; // # Announces
+const DEFAULT_ALPHA: usize = 4;
 
 type Distance = ShaHash;
 type DistanceToBeat = ShaHash;
 
+/// Parameters used when performing a lookup.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LookupConfig {
+    /// Number of nodes requested from in the initial round (the iterative rounds request one less).
+    pub alpha: usize,
+    /// Number of closest nodes the lookup starts with, and announces to.
+    pub k: usize,
+    /// Timeout for a query to a node we have no round trip time history for.
+    pub query_timeout: Duration,
+    /// Maximum duration of the lookup, after which no new requests are sent.
+    pub max_duration: Option<Duration>,
+    /// Whether announces ask nodes to use the source port of the announce (BEP 5 implied_port).
+    pub implied_port: bool,
+}
+
+/// Options for a single lookup, deciding what we ask nodes for and whether we announce.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LookupOptions {
+    /// Announce our contact information to the closest nodes once the lookup completes.
+    pub announce: bool,
+    /// Whether or not we are a seed for the torrent, sent along with requests and announces (BEP 33).
+    pub seed: bool,
+    /// Ask nodes for bloom filters of their seeds and leechers, to estimate the swarm size (BEP 33).
+    pub scrape: bool,
+}
+
+impl Default for LookupConfig {
+    fn default() -> LookupConfig {
+        LookupConfig {
+            alpha: DEFAULT_ALPHA,
+            k: bucket::MAX_BUCKET_SIZE,
+            query_timeout: Duration::from_millis(DEFAULT_QUERY_TIMEOUT_MS),
+            max_duration: None,
+            implied_port: false,
+        }
+    }
+}
+
+/// Statistics for a finished lookup.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LookupStats {
+    /// Number of nodes we sent a request to.
+    pub nodes_queried: usize,
+    /// Number of nodes that responded to our request.
+    pub responses: usize,
+    /// Number of requests that timed out (endgame requests are not counted).
+    pub timeouts: usize,
+    /// Time elapsed since the lookup was started.
+    pub elapsed: Duration,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum LookupStatus {
     Searching,
@@ -49,6 +104,12 @@ pub struct TableLookup {
     recv_values: bool,
     id_generator: MIDGenerator,
     will_announce: bool,
+    // Whether or not we are a seed for the torrent, sent along with requests and announces (BEP 33)
+    is_seed: bool,
+    // Merged seed and leecher bloom filters, only present if we are scraping the swarm (BEP 33)
+    scrape: Option<(ScrapeFilter, ScrapeFilter)>,
+    // Every request of the lookup is the same aside from the transaction id
+    request_template: RequestTemplate,
     // DistanceToBeat is the distance that the responses of the current lookup needs to beat,
     // interestingly enough (and super important), this distance may not be eqaul to the
     // requested node's distance
@@ -58,6 +119,13 @@ pub struct TableLookup {
     // Storing whether or not it has ever been pinged so that we
     // can perform the brute force lookup if the lookup failed
     all_sorted_nodes: Vec<(Distance, Node, bool)>,
+    // Only present if the client asked for a trace of this lookup
+    tracer: Option<LookupTracer>,
+    config: LookupConfig,
+    started: Instant,
+    nodes_queried: usize,
+    responses: usize,
+    timeouts: usize,
 }
 
 // Gather nodes
@@ -66,25 +134,28 @@ impl TableLookup {
     pub fn new<H>(table_id: NodeId,
                   target_id: InfoHash,
                   id_generator: MIDGenerator,
-                  will_announce: bool,
+                  options: LookupOptions,
+                  tracer: Option<LookupTracer>,
+                  want: Option<Want>,
+                  config: LookupConfig,
                   table: &RoutingTable,
-                  out: &SyncSender<(Vec<u8>, SocketAddr)>,
+                  out: &SyncSender<OutgoingMessage>,
                   event_loop: &mut EventLoop<DhtHandler<H>>)
                   -> Option<TableLookup>
         where H: Handshaker
     {
-        // Pick a buckets worth of nodes and put them into the all_sorted_nodes list
-        let mut all_sorted_nodes = Vec::with_capacity(bucket::MAX_BUCKET_SIZE);
+        // Pick k of the closest nodes and put them into the all_sorted_nodes list
+        let mut all_sorted_nodes = Vec::with_capacity(config.k);
         for node in table.closest_nodes(target_id)
             .filter(|n| n.status() == NodeStatus::Good)
-            .take(bucket::MAX_BUCKET_SIZE) {
+            .take(config.k) {
             insert_sorted_node(&mut all_sorted_nodes, target_id, node.clone(), false);
         }
 
         // Call pick_initial_nodes with the all_sorted_nodes list as an iterator
-        let initial_pick_nodes = pick_initial_nodes(all_sorted_nodes.iter_mut());
+        let initial_pick_nodes = pick_initial_nodes(all_sorted_nodes.iter_mut(), config.alpha);
         let initial_pick_nodes_filtered =
-            initial_pick_nodes.iter().filter(|&&(_, good)| good).map(|&(ref node, _)| {
+            initial_pick_nodes.iter().map(|node| {
                 let distance_to_beat = node.id() ^ target_id;
 
                 (node, distance_to_beat)
@@ -97,19 +168,35 @@ impl TableLookup {
             in_endgame: false,
             recv_values: false,
             id_generator: id_generator,
-            will_announce: will_announce,
+            will_announce: options.announce,
+            is_seed: options.seed,
+            scrape: if options.scrape { Some((ScrapeFilter::new(), ScrapeFilter::new())) } else { None },
+            request_template: RequestTemplate::get_peers(table_id, target_id, want, options.seed, options.scrape),
             all_sorted_nodes: all_sorted_nodes,
             announce_tokens: HashMap::new(),
             requested_nodes: HashSet::new(),
-            active_lookups: HashMap::with_capacity(INITIAL_PICK_NUM),
+            active_lookups: HashMap::with_capacity(config.alpha),
+            tracer: tracer,
+            config: config,
+            started: Instant::now(),
+            nodes_queried: 0,
+            responses: 0,
+            timeouts: 0,
         };
 
         // Call start_request_round with the list of initial_nodes (return even if the search completed...for now :D)
-        if table_lookup.start_request_round(initial_pick_nodes_filtered, table, out, event_loop) !=
-           LookupStatus::Failed {
-            Some(table_lookup)
-        } else {
-            None
+        match table_lookup.start_request_round(initial_pick_nodes_filtered,
+                                               TraceRound::Initial,
+                                               table,
+                                               out,
+                                               event_loop) {
+            LookupStatus::Failed => None,
+            LookupStatus::Completed => {
+                // No nodes to request from, hand off the trace now since no endgame will occur
+                table_lookup.tracer.take().map(|tracer| tracer.finish(0));
+                Some(table_lookup)
+            }
+            _ => Some(table_lookup),
         }
     }
 
@@ -117,12 +204,26 @@ impl TableLookup {
         self.target_id
     }
 
+    pub fn stats(&self) -> LookupStats {
+        LookupStats {
+            nodes_queried: self.nodes_queried,
+            responses: self.responses,
+            timeouts: self.timeouts,
+            elapsed: self.started.elapsed(),
+        }
+    }
+
+    /// Estimated size of the swarm from the merged scrape filters, if we are scraping.
+    pub fn scrape_estimate(&self) -> Option<ScrapeEstimate> {
+        self.scrape.as_ref().map(|&(ref seeds, ref leechers)| ScrapeEstimate::new(seeds, leechers))
+    }
+
     pub fn recv_response<'a, H>(&mut self,
                                 node: Node,
                                 trans_id: &TransactionID,
                                 msg: GetPeersResponse<'a>,
                                 table: &RoutingTable,
-                                out: &SyncSender<(Vec<u8>, SocketAddr)>,
+                                out: &SyncSender<OutgoingMessage>,
                                 event_loop: &mut EventLoop<DhtHandler<H>>)
                                 -> LookupStatus
         where H: Handshaker
@@ -135,6 +236,7 @@ impl TableLookup {
                    lookup...");
             return self.current_lookup_status();
         };
+        self.responses += 1;
 
         // Cancel the timeout (if this is not an endgame response)
         if !self.in_endgame {
@@ -146,6 +248,17 @@ impl TableLookup {
             self.announce_tokens.insert(node, token.to_vec());
         }
 
+        // Merge in any scrape filters, ignoring them if we did not ask for them
+        if let Some((ref mut seeds, ref mut leechers)) = self.scrape {
+            let opt_seeds = msg.seeds_filter().and_then(ScrapeFilter::from_bytes);
+            let opt_leechers = msg.peers_filter().and_then(ScrapeFilter::from_bytes);
+
+            if let (Some(rsp_seeds), Some(rsp_leechers)) = (opt_seeds, opt_leechers) {
+                seeds.merge(&rsp_seeds);
+                leechers.merge(&rsp_leechers);
+            }
+        }
+
         // Pull out the contact information from the message
         let (opt_values, opt_nodes) = match msg.info_type() {
             CompactInfoType::Nodes(n) => (None, Some(n)),
@@ -156,6 +269,13 @@ impl TableLookup {
             CompactInfoType::Both(n, v) => (Some(v.into_iter().collect()), Some(n)),
         };
 
+        if let Some(ref mut tracer) = self.tracer {
+            let num_nodes = opt_nodes.map_or(0, |n| n.into_iter().count());
+            let num_values = opt_values.as_ref().map_or(0, |v: &Vec<SocketAddrV4>| v.len());
+
+            tracer.responded(trans_id, num_nodes, num_values);
+        }
+
         // Check if we beat the distance, get the next distance to beat
         let (iterate_nodes, next_dist_to_beat) = if let Some(nodes) = opt_nodes {
             let requested_nodes = &self.requested_nodes;
@@ -182,9 +302,12 @@ impl TableLookup {
 
             // Check if we got closer (equal to is not enough)
             let iterate_nodes = if next_dist_to_beat < dist_to_beat {
+                self.tracer.as_mut().map(|tracer| tracer.iterated(next_dist_to_beat));
+
                 let iterate_nodes = pick_iterate_nodes(nodes.into_iter()
                                                            .filter(&already_requested),
-                                                       self.target_id);
+                                                       self.target_id,
+                                                       iterative_pick_num(self.config.alpha));
 
                 // Push nodes into the all nodes list
                 for (id, v4_addr) in nodes {
@@ -197,6 +320,8 @@ impl TableLookup {
 
                 Some(iterate_nodes)
             } else {
+                self.tracer.as_mut().map(|tracer| tracer.stalled(dist_to_beat));
+
                 // Push nodes into the all nodes list
                 for (id, v4_addr) in nodes {
                     let addr = SocketAddr::V4(v4_addr);
@@ -220,8 +345,11 @@ impl TableLookup {
                 let filtered_nodes = nodes.iter()
                     .filter(|&&(_, good)| good)
                     .map(|&(ref n, _)| (n, next_dist_to_beat));
-                if self.start_request_round(filtered_nodes, table, out, event_loop) ==
-                   LookupStatus::Failed {
+                if self.start_request_round(filtered_nodes,
+                                            TraceRound::Iterative,
+                                            table,
+                                            out,
+                                            event_loop) == LookupStatus::Failed {
                     return LookupStatus::Failed;
                 }
             }
@@ -243,7 +371,7 @@ impl TableLookup {
     pub fn recv_timeout<H>(&mut self,
                            trans_id: &TransactionID,
                            table: &RoutingTable,
-                           out: &SyncSender<(Vec<u8>, SocketAddr)>,
+                           out: &SyncSender<OutgoingMessage>,
                            event_loop: &mut EventLoop<DhtHandler<H>>)
                            -> LookupStatus
         where H: Handshaker
@@ -253,6 +381,8 @@ impl TableLookup {
                    lookup...");
             return self.current_lookup_status();
         }
+        self.timeouts += 1;
+        self.tracer.as_mut().map(|tracer| tracer.timed_out(trans_id));
 
         if !self.in_endgame {
             // If there are not more active lookups, start the endgame
@@ -269,9 +399,10 @@ impl TableLookup {
     pub fn recv_finished(&mut self,
                          handshake_port: u16,
                          table: &RoutingTable,
-                         out: &SyncSender<(Vec<u8>, SocketAddr)>)
+                         out: &SyncSender<OutgoingMessage>)
                          -> LookupStatus {
         let mut fatal_error = false;
+        let mut num_announced = 0;
 
         // Announce if we were told to
         if self.will_announce {
@@ -281,7 +412,7 @@ impl TableLookup {
             for &(_, ref node, _) in self.all_sorted_nodes
                 .iter()
                 .filter(|&&(_, ref node, _)| announce_tokens.contains_key(node))
-                .take(ANNOUNCE_PICK_NUM) {
+                .take(self.config.k) {
                 let trans_id = self.id_generator.generate();
                 let token = announce_tokens.get(node).unwrap();
 
@@ -290,10 +421,11 @@ impl TableLookup {
                                              self.table_id,
                                              self.target_id,
                                              token.as_ref(),
-                                             ConnectPort::Explicit(handshake_port));
+                                             announce_connect_port(self.config.implied_port, handshake_port))
+                        .with_seed(self.is_seed);
                 let announce_peer_msg = announce_peer_req.encode();
 
-                if out.send((announce_peer_msg, node.addr())).is_err() {
+                if out.send(OutgoingMessage::Query(announce_peer_msg, node.addr())).is_err() {
                     error!("bip_dht: TableLookup announce request failed to send through the out \
                             channel...");
                     fatal_error = true;
@@ -301,7 +433,8 @@ impl TableLookup {
 
                 if !fatal_error {
                     // We requested from the node, marke it down if the node is in our routing table
-                    table.find_node(node).map(|n| n.local_request());
+                    table.find_node(node).map(|n| n.local_request(trans_id));
+                    num_announced += 1;
                 }
             }
         }
@@ -310,6 +443,10 @@ impl TableLookup {
         self.active_lookups.clear();
         self.in_endgame = false;
 
+        if let Some(tracer) = self.tracer.take() {
+            tracer.finish(num_announced);
+        }
+
         if fatal_error {
             LookupStatus::Failed
         } else {
@@ -317,6 +454,15 @@ impl TableLookup {
         }
     }
 
+    /// Milliseconds left before the lookup exceeds its maximum duration, if any.
+    fn remaining_ms(&self) -> Option<u64> {
+        self.config.max_duration.map(|max_duration| {
+            let elapsed_ms = trace::duration_to_ms(self.started.elapsed());
+
+            trace::duration_to_ms(max_duration).saturating_sub(elapsed_ms)
+        })
+    }
+
     fn current_lookup_status(&self) -> LookupStatus {
         if self.in_endgame || !self.active_lookups.is_empty() {
             LookupStatus::Searching
@@ -327,23 +473,33 @@ impl TableLookup {
 
     fn start_request_round<'a, H, I>(&mut self,
                                      nodes: I,
+                                     round: TraceRound,
                                      table: &RoutingTable,
-                                     out: &SyncSender<(Vec<u8>, SocketAddr)>,
+                                     out: &SyncSender<OutgoingMessage>,
                                      event_loop: &mut EventLoop<DhtHandler<H>>)
                                      -> LookupStatus
         where I: Iterator<Item = (&'a Node, DistanceToBeat)>,
               H: Handshaker
     {
+        // Past our maximum duration, let any outstanding requests finish without starting new ones
+        let opt_remaining_ms = self.remaining_ms();
+        if opt_remaining_ms == Some(0) {
+            return self.current_lookup_status();
+        }
+
         // Loop through the given nodes
         let mut messages_sent = 0;
+        let default_timeout_ms = trace::duration_to_ms(self.config.query_timeout);
         for (node, dist_to_beat) in nodes {
             // Generate a transaction id for this message
             let trans_id = self.id_generator.generate();
 
-            // Try to start a timeout for the node
+            // Try to start a timeout for the node, based on how quickly it has responded in the past
+            let node_timeout_ms = table.find_node(node).map_or(default_timeout_ms, |n| n.query_timeout_ms(default_timeout_ms));
+            let timeout_ms = opt_remaining_ms.map_or(node_timeout_ms, |remaining_ms| cmp::min(remaining_ms, node_timeout_ms));
             let res_timeout =
                 event_loop.timeout_ms((0, ScheduledTask::CheckLookupTimeout(trans_id)),
-                                      LOOKUP_TIMEOUT_MS);
+                                      timeout_ms);
             let timeout = if let Ok(t) = res_timeout {
                 t
             } else {
@@ -355,20 +511,21 @@ impl TableLookup {
             self.active_lookups.insert(trans_id, (dist_to_beat, timeout));
 
             // Send the message to the node
-            let get_peers_msg =
-                GetPeersRequest::new(trans_id.as_ref(), self.table_id, self.target_id).encode();
-            if out.send((get_peers_msg, node.addr())).is_err() {
+            let get_peers_msg = self.request_template.encode(trans_id.as_ref());
+            if out.send(OutgoingMessage::Query(get_peers_msg, node.addr())).is_err() {
                 error!("bip_dht: Could not send a lookup message through the channel...");
                 return LookupStatus::Failed;
             }
 
             // We requested from the node, mark it down
             self.requested_nodes.insert(node.clone());
+            self.tracer.as_mut().map(|tracer| tracer.requested(trans_id, node, round));
 
             // Update the node in the routing table
-            table.find_node(node).map(|n| n.local_request());
+            table.find_node(node).map(|n| n.local_request(trans_id));
 
             messages_sent += 1;
+            self.nodes_queried += 1;
         }
 
         if messages_sent == 0 {
@@ -381,16 +538,21 @@ impl TableLookup {
 
     fn start_endgame_round<H>(&mut self,
                               table: &RoutingTable,
-                              out: &SyncSender<(Vec<u8>, SocketAddr)>,
+                              out: &SyncSender<OutgoingMessage>,
                               event_loop: &mut EventLoop<DhtHandler<H>>)
                               -> LookupStatus
         where H: Handshaker
     {
         // Entering the endgame phase
         self.in_endgame = true;
+        self.tracer.as_mut().map(|tracer| tracer.endgame());
 
-        // Try to start a global message timeout for the endgame
-        let res_timeout = event_loop.timeout_ms((0, ScheduledTask::CheckLookupEndGame(self.id_generator.generate())), ENDGAME_TIMEOUT_MS);
+        // Try to start a global message timeout for the endgame, which does not run past our maximum duration
+        let opt_remaining_ms = self.remaining_ms();
+        let default_timeout_ms = trace::duration_to_ms(self.config.query_timeout);
+        let endgame_timeout_ms = opt_remaining_ms.map_or(default_timeout_ms, |remaining_ms| cmp::min(remaining_ms, default_timeout_ms));
+
+        let res_timeout = event_loop.timeout_ms((0, ScheduledTask::CheckLookupEndGame(self.id_generator.generate())), endgame_timeout_ms);
         let timeout = if let Ok(t) = res_timeout {
             t
         } else {
@@ -398,8 +560,8 @@ impl TableLookup {
             return LookupStatus::Failed;
         };
 
-        // Request all unpinged nodes if we didnt receive any values
-        if !self.recv_values {
+        // Request all unpinged nodes if we didnt receive any values (and have time left to)
+        if !self.recv_values && opt_remaining_ms != Some(0) {
             for node_info in self.all_sorted_nodes.iter_mut().filter(|&&mut (_, _, req)| !req) {
                 let &mut (ref node_dist, ref node, ref mut req) = node_info;
 
@@ -412,18 +574,21 @@ impl TableLookup {
                 self.active_lookups.insert(trans_id, (*node_dist, timeout));
 
                 // Send the message to the node
-                let get_peers_msg =
-                    GetPeersRequest::new(trans_id.as_ref(), self.table_id, self.target_id).encode();
-                if out.send((get_peers_msg, node.addr())).is_err() {
+                let get_peers_msg = self.request_template.encode(trans_id.as_ref());
+                if out.send(OutgoingMessage::Query(get_peers_msg, node.addr())).is_err() {
                     error!("bip_dht: Could not send an endgame message through the channel...");
                     return LookupStatus::Failed;
                 }
 
                 // Mark that we requested from the node in the RoutingTable
-                table.find_node(node).map(|n| n.local_request());
+                table.find_node(node).map(|n| n.local_request(trans_id));
+                self.tracer
+                    .as_mut()
+                    .map(|tracer| tracer.requested(trans_id, node, TraceRound::EndGame));
 
                 // Mark that we requested from the node
                 *req = true;
+                self.nodes_queried += 1;
             }
         }
 
@@ -431,35 +596,36 @@ impl TableLookup {
     }
 }
 
+/// Number of nodes to ping on iterative rounds, given the number of nodes pinged on the first round.
+fn iterative_pick_num(alpha: usize) -> usize {
+    cmp::max(alpha, 2) - 1
+}
+
 /// Picks a number of nodes from the sorted distance iterator to ping on the first round.
-fn pick_initial_nodes<'a, I>(sorted_nodes: I) -> [(Node, bool); INITIAL_PICK_NUM]
+fn pick_initial_nodes<'a, I>(sorted_nodes: I, num_pick: usize) -> Vec<Node>
     where I: Iterator<Item = &'a mut (Distance, Node, bool)>
 {
-    let dummy_id = [0u8; bt::NODE_ID_LEN].into();
-    let default = (Node::as_bad(dummy_id, net::default_route_v4()), false);
-
-    let mut pick_nodes = [default.clone(), default.clone(), default.clone(), default.clone()];
-    for (src, dst) in sorted_nodes.zip(pick_nodes.iter_mut()) {
-        dst.0 = src.1.clone();
-        dst.1 = true;
-
-        // Mark that the node has been requested from
-        src.2 = true;
-    }
-
-    pick_nodes
+    sorted_nodes.take(num_pick)
+        .map(|src| {
+            // Mark that the node has been requested from
+            src.2 = true;
+
+            src.1.clone()
+        })
+        .collect()
 }
 
 /// Picks a number of nodes from the unsorted distance iterator to ping on iterative rounds.
 fn pick_iterate_nodes<I>(unsorted_nodes: I,
-                         target_id: InfoHash)
-                         -> [(Node, bool); ITERATIVE_PICK_NUM]
+                         target_id: InfoHash,
+                         num_pick: usize)
+                         -> Vec<(Node, bool)>
     where I: Iterator<Item = (NodeId, SocketAddrV4)>
 {
     let dummy_id = [0u8; bt::NODE_ID_LEN].into();
     let default = (Node::as_bad(dummy_id, net::default_route_v4()), false);
 
-    let mut pick_nodes = [default.clone(), default.clone(), default.clone()];
+    let mut pick_nodes = vec![default; num_pick];
     for (id, v4_addr) in unsorted_nodes {
         let addr = SocketAddr::V4(v4_addr);
         let node = Node::as_questionable(id, addr);
@@ -518,3 +684,69 @@ fn insert_sorted_node(nodes: &mut Vec<(Distance, Node, bool)>,
         Err(ins_index) => nodes.insert(ins_index, (node_dist, node, pinged)),
     };
 }
+
+/// Port that we announce to nodes, a handshake port of zero means it is unknown to us.
+fn announce_connect_port(implied_port: bool, handshake_port: u16) -> ConnectPort {
+    if implied_port || handshake_port == 0 {
+        ConnectPort::Implied
+    } else {
+        ConnectPort::Explicit(handshake_port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddrV4};
+
+    use bip_util::bt::{self, NodeId};
+    use bip_util::test as bip_test;
+
+    use message::announce_peer::ConnectPort;
+    use routing::node::Node;
+
+    fn node_with_id(id: u8) -> Node {
+        let node_id: NodeId = [id; bt::NODE_ID_LEN].into();
+
+        Node::as_good(node_id, bip_test::dummy_socket_addr_v4())
+    }
+
+    #[test]
+    fn positive_announce_connect_port() {
+        assert_eq!(ConnectPort::Explicit(6881), super::announce_connect_port(false, 6881));
+        assert_eq!(ConnectPort::Implied, super::announce_connect_port(true, 6881));
+        assert_eq!(ConnectPort::Implied, super::announce_connect_port(false, 0));
+    }
+
+    #[test]
+    fn positive_iterative_pick_num_one_less_than_alpha() {
+        assert_eq!(3, super::iterative_pick_num(4));
+        assert_eq!(1, super::iterative_pick_num(2));
+        assert_eq!(1, super::iterative_pick_num(1));
+    }
+
+    #[test]
+    fn positive_pick_initial_nodes_marks_requested() {
+        let target = [0u8; bt::INFO_HASH_LEN].into();
+        let mut sorted_nodes = Vec::new();
+        for id in 1..6 {
+            super::insert_sorted_node(&mut sorted_nodes, target, node_with_id(id), false);
+        }
+
+        let picked = super::pick_initial_nodes(sorted_nodes.iter_mut(), 2);
+
+        assert_eq!(vec![node_with_id(1), node_with_id(2)], picked);
+        assert_eq!(2, sorted_nodes.iter().filter(|&&(_, _, req)| req).count());
+    }
+
+    #[test]
+    fn positive_pick_iterate_nodes_closest() {
+        let target = [0u8; bt::INFO_HASH_LEN].into();
+        let addr = SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 6881);
+        let nodes = (1..6).rev().map(|id| ([id; bt::NODE_ID_LEN].into(), addr));
+
+        let picked = super::pick_iterate_nodes(nodes, target, 2);
+
+        assert_eq!(2, picked.len());
+        assert!(picked.iter().all(|&(_, used)| used));
+    }
+}
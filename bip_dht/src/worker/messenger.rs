@@ -1,19 +1,67 @@
+use std::collections::VecDeque;
 use std::net::{SocketAddr, UdpSocket};
-use std::sync::mpsc::{self, SyncSender};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
 use std::thread;
+use std::time::Instant;
 
+use bip_util::metrics::Metrics;
 use mio::Sender;
 
 use worker::OneshotTask;
+use worker::throttle::TokenBucket;
 
 const OUTGOING_MESSAGE_CAPACITY: usize = 4096;
 
-pub fn create_outgoing_messenger(socket: UdpSocket) -> SyncSender<(Vec<u8>, SocketAddr)> {
-    let (send, recv) = mpsc::sync_channel::<(Vec<u8>, SocketAddr)>(OUTGOING_MESSAGE_CAPACITY);
+const QUERIES_SENT_METRIC: &'static str = "bip_dht_queries_sent";
+
+/// Message to be written to the socket by the outgoing messenger.
+pub enum OutgoingMessage {
+    /// Query for a remote node, counted against the outgoing query rate.
+    Query(Vec<u8>, SocketAddr),
+    /// Response to a remote node, sent without delay.
+    Response(Vec<u8>, SocketAddr),
+}
+
+/// Spawns a thread that writes messages to the given socket.
+///
+/// Outgoing queries are limited to query_rate per second, where zero disables the limit.
+/// Queries over the limit are delayed, while responses are always sent right away.
+pub fn create_outgoing_messenger(socket: UdpSocket,
+                                 query_rate: usize,
+                                 metrics: Arc<Metrics>)
+                                 -> SyncSender<OutgoingMessage> {
+    let (send, recv) = mpsc::sync_channel::<OutgoingMessage>(OUTGOING_MESSAGE_CAPACITY);
 
     thread::spawn(move || {
-        for (message, addr) in recv {
-            send_bytes(&socket, &message[..], addr);
+        let mut query_bucket = if query_rate != 0 {
+            Some(TokenBucket::new(query_rate, Instant::now()))
+        } else {
+            None
+        };
+        let mut delayed_queries = VecDeque::new();
+
+        loop {
+            send_delayed_queries(&socket, &mut query_bucket, &mut delayed_queries, &*metrics);
+
+            let message = match recv_message(&recv, &mut query_bucket, &delayed_queries) {
+                Ok(Some(message)) => message,
+                Ok(None) => continue,
+                Err(()) => break,
+            };
+
+            match message {
+                OutgoingMessage::Response(bytes, addr) => send_bytes(&socket, &bytes[..], addr),
+                OutgoingMessage::Query(bytes, addr) => {
+                    if delayed_queries.len() < OUTGOING_MESSAGE_CAPACITY {
+                        delayed_queries.push_back((bytes, addr));
+                    } else {
+                        warn!("bip_dht: Outgoing messenger dropped a query to {}, too many queries are \
+                               delayed...",
+                              addr);
+                    }
+                }
+            }
         }
 
         info!("bip_dht: Outgoing messenger received a channel hangup, exiting thread...");
@@ -22,6 +70,43 @@ pub fn create_outgoing_messenger(socket: UdpSocket) -> SyncSender<(Vec<u8>, Sock
     send
 }
 
+/// Receive the next message, waiting at most until a token is available if we have delayed queries.
+///
+/// Returns None if we timed out, or an error if the channel hung up.
+fn recv_message(recv: &Receiver<OutgoingMessage>,
+                query_bucket: &mut Option<TokenBucket>,
+                delayed_queries: &VecDeque<(Vec<u8>, SocketAddr)>)
+                -> Result<Option<OutgoingMessage>, ()> {
+    match *query_bucket {
+        Some(ref mut bucket) if !delayed_queries.is_empty() => {
+            match recv.recv_timeout(bucket.time_until_token(Instant::now())) {
+                Ok(message) => Ok(Some(message)),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => Err(()),
+            }
+        }
+        _ => recv.recv().map(Some).map_err(|_| ()),
+    }
+}
+
+/// Send as many of the delayed queries as the query bucket allows.
+fn send_delayed_queries(socket: &UdpSocket,
+                        query_bucket: &mut Option<TokenBucket>,
+                        delayed_queries: &mut VecDeque<(Vec<u8>, SocketAddr)>,
+                        metrics: &Metrics) {
+    while !delayed_queries.is_empty() {
+        let has_token = query_bucket.as_mut().map_or(true, |bucket| bucket.try_take(Instant::now()));
+        if !has_token {
+            break;
+        }
+
+        let (bytes, addr) = delayed_queries.pop_front().unwrap();
+        metrics.counter(QUERIES_SENT_METRIC, 1);
+
+        send_bytes(socket, &bytes[..], addr);
+    }
+}
+
 fn send_bytes(socket: &UdpSocket, bytes: &[u8], addr: SocketAddr) {
     let mut bytes_sent = 0;
 
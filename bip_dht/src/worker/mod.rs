@@ -1,23 +1,37 @@
 use std::io;
 use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
 use std::sync::mpsc;
+use std::time::Duration;
 
 use bip_handshake::Handshaker;
-use bip_util::bt::InfoHash;
+use bip_util::bt::{InfoHash, NodeId};
+use bip_util::metrics::Metrics;
+use futures::sync::oneshot;
 use mio;
 
+use distance;
+use item::{Item, ItemKey};
+use message::want::Want;
 use router::Router;
-use routing::table::{self, RoutingTable};
+use routing::table::RoutingTable;
+use scrape::ScrapeEstimate;
+use storage::AnnounceStats;
 use transaction::TransactionID;
+use worker::lookup::{LookupConfig, LookupOptions, LookupStats};
+use worker::refresh::TableConfig;
+use worker::trace::LookupTrace;
 
 pub mod bootstrap;
 pub mod handler;
+pub mod item_lookup;
 pub mod lookup;
 pub mod messenger;
 pub mod refresh;
+pub mod throttle;
+pub mod trace;
 
 /// Task that our DHT will execute immediately.
-#[derive(Clone)]
 pub enum OneshotTask {
     /// Process an incoming message from a remote node.
     Incoming(Vec<u8>, SocketAddr),
@@ -25,8 +39,14 @@ pub enum OneshotTask {
     RegisterSender(mpsc::Sender<DhtEvent>),
     /// Load a new bootstrap operation into worker storage.
     StartBootstrap(Vec<Router>, Vec<SocketAddr>),
-    /// Start a lookup for the given InfoHash.
-    StartLookup(InfoHash, bool),
+    /// Start a lookup for the given InfoHash with the given options, optionally tracing the lookup.
+    StartLookup(InfoHash, LookupOptions, Option<oneshot::Sender<LookupTrace>>),
+    /// Start a lookup for the item with the given key.
+    StartGetItem(ItemKey, oneshot::Sender<Option<Item>>),
+    /// Start a lookup to store the given item, with an optional compare and swap sequence number.
+    StartPutItem(Item, Option<i64>, oneshot::Sender<usize>),
+    /// Retrieve statistics for the announces stored with us.
+    AnnounceStats(oneshot::Sender<AnnounceStats>),
     /// Gracefully shutdown the DHT and associated workers.
     Shutdown(ShutdownCause),
 }
@@ -42,6 +62,8 @@ pub enum ScheduledTask {
     CheckLookupTimeout(TransactionID),
     /// Check the progress of the lookup endgame.
     CheckLookupEndGame(TransactionID),
+    /// Check the progress of a current item lookup.
+    CheckItemLookupTimeout(TransactionID),
 }
 
 /// Event that occured within the DHT which clients may be interested in.
@@ -51,6 +73,17 @@ pub enum DhtEvent {
     BootstrapCompleted,
     /// Lookup operation for the given InfoHash completed.
     LookupCompleted(InfoHash),
+    /// Statistics for the lookup operation of the given InfoHash, sent just before it completes.
+    LookupStatistics(InfoHash, LookupStats),
+    /// Estimated size of the swarm for a scrape lookup of the given InfoHash, sent just before it completes.
+    ScrapeEstimate(InfoHash, ScrapeEstimate),
+    /// Node was added to our routing table.
+    NodeAdded(NodeId, SocketAddr),
+    /// Node was removed from our routing table.
+    ///
+    /// Nodes that go bad stay in the table until they are replaced by a new node, or
+    /// dropped when their bucket is split, so this is not sent when the node goes bad.
+    NodeRemoved(NodeId, SocketAddr),
     /// DHT is shutting down for some reason.
     ShuttingDown(ShutdownCause),
 }
@@ -71,6 +104,16 @@ pub enum ShutdownCause {
 pub fn start_mainline_dht<H>(send_socket: UdpSocket,
                              recv_socket: UdpSocket,
                              read_only: bool,
+                             want: Option<Want>,
+                             lookup_config: LookupConfig,
+                             table_config: TableConfig,
+                             query_rate: usize,
+                             inbound_query_rate: usize,
+                             inbound_query_burst: usize,
+                             max_throttled_addrs: usize,
+                             max_announces: usize,
+                             token_refresh_interval: Duration,
+                             metrics: Arc<Metrics>,
                              _: Option<SocketAddr>,
                              handshaker: H,
                              kill_sock: UdpSocket,
@@ -78,13 +121,22 @@ pub fn start_mainline_dht<H>(send_socket: UdpSocket,
                              -> io::Result<mio::Sender<OneshotTask>>
     where H: Handshaker + 'static
 {
-    let outgoing = messenger::create_outgoing_messenger(send_socket);
+    let outgoing = messenger::create_outgoing_messenger(send_socket, query_rate, metrics.clone());
 
     // TODO: Utilize the security extension.
-    let routing_table = RoutingTable::new(table::random_node_id());
+    let routing_table = RoutingTable::with_lifecycle(distance::random_node_id(), table_config.node_lifecycle());
     let message_sender = try!(handler::create_dht_handler(routing_table,
                                                           outgoing,
                                                           read_only,
+                                                          want,
+                                                          lookup_config,
+                                                          table_config,
+                                                          inbound_query_rate,
+                                                          inbound_query_burst,
+                                                          max_throttled_addrs,
+                                                          max_announces,
+                                                          token_refresh_interval,
+                                                          metrics,
                                                           handshaker,
                                                           kill_sock,
                                                           kill_addr));
@@ -1,19 +1,55 @@
-use std::net::SocketAddr;
 use std::sync::mpsc::SyncSender;
+use std::time::Duration;
 
 use bip_handshake::Handshaker;
-use bip_util::bt::{self, NodeId};
 use mio::EventLoop;
 
+use distance;
 use message::find_node::FindNodeRequest;
-use routing::node::NodeStatus;
+use message::want::Want;
+use routing::node::{self, NodeLifecycle, NodeStatus};
 use routing::table::{self, RoutingTable};
 use transaction::MIDGenerator;
 use worker::ScheduledTask;
 use worker::handler::DhtHandler;
+use worker::messenger::OutgoingMessage;
+use worker::trace;
 
 const REFRESH_INTERVAL_TIMEOUT: u64 = 6000;
 
+/// Shortest interval allowed between refreshes, anything lower would keep the worker busy refreshing.
+pub const MIN_REFRESH_INTERVAL_MILLIS: u64 = 100;
+/// Longest interval allowed between refreshes.
+pub const MAX_REFRESH_INTERVAL_MILLIS: u64 = 60 * 60 * 1000;
+
+/// Parameters used when maintaining the routing table.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TableConfig {
+    /// Interval between refreshes, each refresh pings a questionable node in the next bucket.
+    pub refresh_interval: Duration,
+    /// Period without activity from a node before it becomes questionable.
+    pub questionable_after: Duration,
+    /// Number of unanswered pings before a questionable node becomes bad.
+    pub max_ping_retries: usize,
+}
+
+impl TableConfig {
+    /// Timings for nodes added to the routing table.
+    pub fn node_lifecycle(&self) -> NodeLifecycle {
+        NodeLifecycle::new(self.questionable_after, self.max_ping_retries)
+    }
+}
+
+impl Default for TableConfig {
+    fn default() -> TableConfig {
+        TableConfig {
+            refresh_interval: Duration::from_millis(REFRESH_INTERVAL_TIMEOUT),
+            questionable_after: Duration::from_secs(node::MAX_LAST_SEEN_MINS as u64 * 60),
+            max_ping_retries: node::MAX_REFRESH_REQUESTS,
+        }
+    }
+}
+
 pub enum RefreshStatus {
     /// Refresh is in progress.
     Refreshing,
@@ -24,19 +60,23 @@ pub enum RefreshStatus {
 pub struct TableRefresh {
     id_generator: MIDGenerator,
     curr_refresh_bucket: usize,
+    want: Option<Want>,
+    refresh_interval_ms: u64,
 }
 
 impl TableRefresh {
-    pub fn new(id_generator: MIDGenerator) -> TableRefresh {
+    pub fn new(id_generator: MIDGenerator, want: Option<Want>, refresh_interval: Duration) -> TableRefresh {
         TableRefresh {
             id_generator: id_generator,
             curr_refresh_bucket: 0,
+            want: want,
+            refresh_interval_ms: trace::duration_to_ms(refresh_interval),
         }
     }
 
     pub fn continue_refresh<H>(&mut self,
                                table: &RoutingTable,
-                               out: &SyncSender<(Vec<u8>, SocketAddr)>,
+                               out: &SyncSender<OutgoingMessage>,
                                event_loop: &mut EventLoop<DhtHandler<H>>)
                                -> RefreshStatus
         where H: Handshaker
@@ -44,7 +84,7 @@ impl TableRefresh {
         if self.curr_refresh_bucket == table::MAX_BUCKETS {
             self.curr_refresh_bucket = 0;
         }
-        let target_id = flip_id_bit_at_index(table.node_id(), self.curr_refresh_bucket);
+        let target_id = distance::bucket_node_id(table.node_id(), self.curr_refresh_bucket);
 
         info!("bip_dht: Performing a refresh for bucket {}",
               self.curr_refresh_bucket);
@@ -56,18 +96,19 @@ impl TableRefresh {
             let trans_id = self.id_generator.generate();
 
             // Construct the message
-            let find_node_req = FindNodeRequest::new(trans_id.as_ref(), table.node_id(), target_id);
+            let find_node_req = FindNodeRequest::new(trans_id.as_ref(), table.node_id(), target_id)
+                .with_want(self.want);
             let find_node_msg = find_node_req.encode();
 
             // Send the message
-            if out.send((find_node_msg, node.addr())).is_err() {
+            if out.send(OutgoingMessage::Query(find_node_msg, node.addr())).is_err() {
                 error!("bip_dht: TableRefresh failed to send a refresh message to the out \
                         channel...");
                 return RefreshStatus::Failed;
             }
 
             // Mark that we requested from the node
-            node.local_request();
+            node.local_request(trans_id);
         }
 
         // Generate a dummy transaction id (only the action id will be used)
@@ -75,7 +116,7 @@ impl TableRefresh {
 
         // Start a timer for the next refresh
         if event_loop.timeout_ms((0, ScheduledTask::CheckTableRefresh(trans_id)),
-                        REFRESH_INTERVAL_TIMEOUT)
+                        self.refresh_interval_ms)
             .is_err() {
             error!("bip_dht: TableRefresh failed to set a timeout for the next refresh...");
             return RefreshStatus::Failed;
@@ -86,14 +127,3 @@ impl TableRefresh {
         RefreshStatus::Refreshing
     }
 }
-
-/// Panics if index is out of bounds.
-fn flip_id_bit_at_index(node_id: NodeId, index: usize) -> NodeId {
-    let mut id_bytes: [u8; bt::NODE_ID_LEN] = node_id.into();
-    let (byte_index, bit_index) = (index / 8, index % 8);
-
-    let actual_bit_index = 7 - bit_index;
-    id_bytes[byte_index] ^= 1 << actual_bit_index;
-
-    id_bytes.into()
-}
@@ -0,0 +1,218 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use bip_util::net::IpAddr;
+
+/// Token bucket allowing some number of events per second.
+///
+/// By default, the bucket holds at most one second worth of tokens, so bursts
+/// of up to the rate are allowed after being idle.
+pub struct TokenBucket {
+    rate: usize,
+    capacity: usize,
+    tokens: f64,
+    last_fill: Instant,
+}
+
+impl TokenBucket {
+    /// Create a new, full, TokenBucket allowing rate events per second.
+    pub fn new(rate: usize, now: Instant) -> TokenBucket {
+        TokenBucket::with_capacity(rate, rate, now)
+    }
+
+    /// Create a new, full, TokenBucket allowing rate events per second, with bursts of up to capacity events.
+    pub fn with_capacity(rate: usize, capacity: usize, now: Instant) -> TokenBucket {
+        TokenBucket {
+            rate: rate,
+            capacity: capacity,
+            tokens: capacity as f64,
+            last_fill: now,
+        }
+    }
+
+    /// Take a token from the bucket, returning false if none are available.
+    pub fn try_take(&mut self, now: Instant) -> bool {
+        self.fill(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Duration until a token will be available in the bucket.
+    pub fn time_until_token(&mut self, now: Instant) -> Duration {
+        self.fill(now);
+
+        if self.tokens >= 1.0 || self.rate == 0 {
+            Duration::from_millis(0)
+        } else {
+            let nanos = (1.0 - self.tokens) / self.rate as f64 * 1_000_000_000.0;
+
+            Duration::new(0, nanos.ceil() as u32)
+        }
+    }
+
+    fn fill(&mut self, now: Instant) {
+        if now <= self.last_fill {
+            return;
+        }
+
+        let elapsed = now - self.last_fill;
+        let elapsed_secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
+
+        self.tokens = (self.tokens + elapsed_secs * self.rate as f64).min(self.capacity as f64);
+        self.last_fill = now;
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Limits the rate of queries accepted from each remote address.
+pub struct QueryThrottle {
+    rate: usize,
+    burst: usize,
+    max_addrs: usize,
+    buckets: HashMap<IpAddr, TokenBucket>,
+    tracked: VecDeque<IpAddr>,
+}
+
+impl QueryThrottle {
+    /// Create a new QueryThrottle allowing rate queries per second from each address,
+    /// with bursts of up to burst queries, tracking at most max_addrs addresses.
+    ///
+    /// A rate of zero disables throttling.
+    pub fn new(rate: usize, burst: usize, max_addrs: usize) -> QueryThrottle {
+        QueryThrottle {
+            rate: rate,
+            burst: burst,
+            max_addrs: max_addrs,
+            buckets: HashMap::new(),
+            tracked: VecDeque::new(),
+        }
+    }
+
+    /// Returns true if a query from the given address should be processed.
+    ///
+    /// If we are already tracking the maximum number of addresses, the address we have been
+    /// tracking the longest is dropped to make room, so that spoofed addresses can't be used to
+    /// grow our state without bound, or to keep us from answering new nodes.
+    pub fn allow(&mut self, addr: IpAddr, now: Instant) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+
+        if !self.buckets.contains_key(&addr) {
+            if self.buckets.len() >= self.max_addrs {
+                if let Some(oldest_addr) = self.tracked.pop_front() {
+                    self.buckets.remove(&oldest_addr);
+                }
+            }
+
+            self.buckets.insert(addr, TokenBucket::with_capacity(self.rate, self.burst, now));
+            self.tracked.push_back(addr);
+        }
+
+        self.buckets.get_mut(&addr).unwrap().try_take(now)
+    }
+
+    /// Number of addresses currently being tracked.
+    pub fn num_tracked(&self) -> usize {
+        self.buckets.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use std::time::{Duration, Instant};
+
+    use bip_util::net::IpAddr;
+
+    use super::{TokenBucket, QueryThrottle};
+
+    #[test]
+    fn positive_token_bucket_allows_burst() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(3, now);
+
+        assert!(bucket.try_take(now));
+        assert!(bucket.try_take(now));
+        assert!(bucket.try_take(now));
+        assert!(!bucket.try_take(now));
+    }
+
+    #[test]
+    fn positive_token_bucket_refills() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2, now);
+
+        assert!(bucket.try_take(now));
+        assert!(bucket.try_take(now));
+        assert!(bucket.time_until_token(now) > Duration::from_millis(0));
+
+        let later = now + Duration::from_millis(500);
+        assert_eq!(Duration::from_millis(0), bucket.time_until_token(later));
+        assert!(bucket.try_take(later));
+        assert!(!bucket.try_take(later));
+    }
+
+    #[test]
+    fn positive_query_throttle_per_address() {
+        let now = Instant::now();
+        let mut throttle = QueryThrottle::new(1, 1, 16);
+
+        let addr_one = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let addr_two = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        assert!(throttle.allow(addr_one, now));
+        assert!(!throttle.allow(addr_one, now));
+        assert!(throttle.allow(addr_two, now));
+    }
+
+    #[test]
+    fn positive_query_throttle_disabled() {
+        let now = Instant::now();
+        let mut throttle = QueryThrottle::new(0, 0, 16);
+
+        let addr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        for _ in 0..100 {
+            assert!(throttle.allow(addr, now));
+        }
+    }
+
+    #[test]
+    fn positive_query_throttle_burst() {
+        let now = Instant::now();
+        let mut throttle = QueryThrottle::new(1, 3, 16);
+
+        let addr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        assert!(throttle.allow(addr, now));
+        assert!(throttle.allow(addr, now));
+        assert!(throttle.allow(addr, now));
+        assert!(!throttle.allow(addr, now));
+    }
+
+    #[test]
+    fn positive_query_throttle_max_addresses() {
+        let now = Instant::now();
+        let mut throttle = QueryThrottle::new(1, 1, 1);
+
+        let addr_one = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let addr_two = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        assert!(throttle.allow(addr_one, now));
+        assert!(!throttle.allow(addr_one, now));
+
+        // New addresses are still answered, the oldest address is dropped to make room
+        assert!(throttle.allow(addr_two, now));
+        assert_eq!(1, throttle.num_tracked());
+
+        assert!(!throttle.allow(addr_two, now));
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use bip_util::bt::{NodeId, InfoHash};
+use bip_util::sha::ShaHash;
+use futures::sync::oneshot;
+
+use routing::node::Node;
+use transaction::TransactionID;
+
+/// Round of a lookup that a request was sent in.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TraceRound {
+    /// Request was sent to one of the closest nodes in our routing table.
+    Initial,
+    /// Request was sent to a node returned by a closer node.
+    Iterative,
+    /// Request was sent to a previously unrequested node during the endgame.
+    EndGame,
+}
+
+/// Node that was involved in a lookup.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TraceNode {
+    id: NodeId,
+    addr: SocketAddr,
+    distance: ShaHash,
+}
+
+impl TraceNode {
+    fn new(node: &Node, target: InfoHash) -> TraceNode {
+        TraceNode {
+            id: node.id(),
+            addr: node.addr(),
+            distance: target ^ node.id(),
+        }
+    }
+
+    /// Id of the node.
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    /// Address of the node.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Distance of the node from the lookup target.
+    pub fn distance(&self) -> ShaHash {
+        self.distance
+    }
+}
+
+/// Event that occured during a lookup.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TraceEvent {
+    /// Sent a request to the node in the given round.
+    Requested(TraceNode, TraceRound),
+    /// Node responded after the given time with the given number of nodes and values.
+    Responded(TraceNode, Duration, usize, usize),
+    /// Node did not respond in time.
+    TimedOut(TraceNode),
+    /// Response contained nodes closer than the given distance so we iterated on them.
+    Iterated(ShaHash),
+    /// Response did not contain any nodes closer than the given distance.
+    Stalled(ShaHash),
+    /// No requests were outstanding so the endgame was started.
+    EndGame,
+    /// Lookup finished, after announcing to the given number of nodes.
+    Finished(usize),
+}
+
+/// Event in a lookup along with the time it occured relative to the start of the lookup.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TraceEntry {
+    elapsed: Duration,
+    event: TraceEvent,
+}
+
+impl TraceEntry {
+    /// Time since the start of the lookup that the event occured.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Event that occured.
+    pub fn event(&self) -> TraceEvent {
+        self.event
+    }
+}
+
+/// Ordered trace of all events that occured during a single lookup.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LookupTrace {
+    info_hash: InfoHash,
+    entries: Vec<TraceEntry>,
+}
+
+impl LookupTrace {
+    /// InfoHash that was searched for.
+    pub fn info_hash(&self) -> InfoHash {
+        self.info_hash
+    }
+
+    /// Events of the lookup, in the order that they occured.
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+
+    /// Total number of requests that were sent.
+    pub fn num_requested(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| {
+                match e.event {
+                    TraceEvent::Requested(..) => true,
+                    _ => false,
+                }
+            })
+            .count()
+    }
+
+    /// Total number of responses that were received.
+    pub fn num_responded(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| {
+                match e.event {
+                    TraceEvent::Responded(..) => true,
+                    _ => false,
+                }
+            })
+            .count()
+    }
+}
+
+impl fmt::Display for LookupTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "Lookup Trace For {:?}", self.info_hash));
+
+        for entry in self.entries.iter() {
+            let elapsed_ms = duration_to_ms(entry.elapsed);
+
+            try!(match entry.event {
+                TraceEvent::Requested(node, round) => {
+                    writeln!(f,
+                             "[{:>6}ms] Requested {} ({:?}) At Distance {:?}",
+                             elapsed_ms,
+                             node.addr,
+                             round,
+                             node.distance)
+                }
+                TraceEvent::Responded(node, rtt, nodes, values) => {
+                    writeln!(f,
+                             "[{:>6}ms] Response From {} In {}ms With {} Nodes And {} Values",
+                             elapsed_ms,
+                             node.addr,
+                             duration_to_ms(rtt),
+                             nodes,
+                             values)
+                }
+                TraceEvent::TimedOut(node) => {
+                    writeln!(f, "[{:>6}ms] Timeout From {}", elapsed_ms, node.addr)
+                }
+                TraceEvent::Iterated(distance) => {
+                    writeln!(f, "[{:>6}ms] Iterating On Distance {:?}", elapsed_ms, distance)
+                }
+                TraceEvent::Stalled(distance) => {
+                    writeln!(f, "[{:>6}ms] Failed To Beat Distance {:?}", elapsed_ms, distance)
+                }
+                TraceEvent::EndGame => writeln!(f, "[{:>6}ms] Starting EndGame", elapsed_ms),
+                TraceEvent::Finished(announced) => {
+                    writeln!(f,
+                             "[{:>6}ms] Finished, Announced To {} Nodes",
+                             elapsed_ms,
+                             announced)
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+pub fn duration_to_ms(duration: Duration) -> u64 {
+    duration.as_secs().saturating_mul(1000).saturating_add((duration.subsec_nanos() / 1_000_000) as u64)
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Records events for a lookup and hands off the trace once the lookup finishes.
+pub struct LookupTracer {
+    start: Instant,
+    trace: LookupTrace,
+    pending: HashMap<TransactionID, (TraceNode, Instant)>,
+    sender: oneshot::Sender<LookupTrace>,
+}
+
+impl LookupTracer {
+    pub fn new(info_hash: InfoHash, sender: oneshot::Sender<LookupTrace>) -> LookupTracer {
+        LookupTracer {
+            start: Instant::now(),
+            trace: LookupTrace {
+                info_hash: info_hash,
+                entries: Vec::new(),
+            },
+            pending: HashMap::new(),
+            sender: sender,
+        }
+    }
+
+    pub fn requested(&mut self, trans_id: TransactionID, node: &Node, round: TraceRound) {
+        let trace_node = TraceNode::new(node, self.trace.info_hash);
+
+        self.pending.insert(trans_id, (trace_node, Instant::now()));
+        self.push(TraceEvent::Requested(trace_node, round));
+    }
+
+    pub fn responded(&mut self, trans_id: &TransactionID, num_nodes: usize, num_values: usize) {
+        if let Some((trace_node, sent)) = self.pending.remove(trans_id) {
+            self.push(TraceEvent::Responded(trace_node, sent.elapsed(), num_nodes, num_values));
+        }
+    }
+
+    pub fn timed_out(&mut self, trans_id: &TransactionID) {
+        if let Some((trace_node, _)) = self.pending.remove(trans_id) {
+            self.push(TraceEvent::TimedOut(trace_node));
+        }
+    }
+
+    pub fn iterated(&mut self, distance: ShaHash) {
+        self.push(TraceEvent::Iterated(distance));
+    }
+
+    pub fn stalled(&mut self, distance: ShaHash) {
+        self.push(TraceEvent::Stalled(distance));
+    }
+
+    pub fn endgame(&mut self) {
+        self.push(TraceEvent::EndGame);
+    }
+
+    /// Mark any outstanding requests as timed out and send the trace to the client.
+    pub fn finish(mut self, num_announced: usize) {
+        let mut outstanding: Vec<(TraceNode, Instant)> =
+            self.pending.drain().map(|(_, info)| info).collect();
+        outstanding.sort_by(|a, b| a.1.cmp(&b.1));
+        for (trace_node, _) in outstanding {
+            self.push(TraceEvent::TimedOut(trace_node));
+        }
+        self.push(TraceEvent::Finished(num_announced));
+
+        if self.sender.send(self.trace).is_err() {
+            info!("bip_dht: Client dropped the receiver for a lookup trace...");
+        }
+    }
+
+    fn push(&mut self, event: TraceEvent) {
+        self.trace.entries.push(TraceEntry {
+            elapsed: self.start.elapsed(),
+            event: event,
+        });
+    }
+}
@@ -58,7 +58,7 @@ mod benches {
 
         for res_message in block_recv {
             match res_message.unwrap() {
-                ODiskMessage::TorrentAdded(_)      => { break; },
+                ODiskMessage::TorrentAdded(_, _)   => { break; },
                 ODiskMessage::FoundGoodPiece(_, _) => (),
                 _                                  => panic!("Didn't Receive TorrentAdded")
             }
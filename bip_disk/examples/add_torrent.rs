@@ -42,9 +42,10 @@ fn main() {
     let mut good_pieces = 0;
     for recv_msg in disk_recv.wait() {
         match recv_msg.unwrap() {
-            ODiskMessage::TorrentAdded(hash) => {
+            ODiskMessage::TorrentAdded(hash, summary) => {
                 println!("Torrent With Hash {:?} Successfully Added", hash);
                 println!("Torrent Has {} Good Pieces Out Of {} Total Pieces", good_pieces, total_pieces);
+                println!("Torrent Has {} Bytes Present And {} Missing Pieces", summary.bytes_present(), summary.missing_pieces());
                 break;
             }
             ODiskMessage::FoundGoodPiece(_, _) => { good_pieces += 1},
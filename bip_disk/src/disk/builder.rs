@@ -1,23 +1,38 @@
+use std::cmp;
+use std::sync::Arc;
+
 use disk::fs::FileSystem;
 use disk::manager::{DiskManager};
+use disk::verify::{PieceVerifier, Sha1Verifier};
 
+use bip_util::metrics::{self, Metrics};
 use futures_cpupool::Builder;
 
 const DEFAULT_PENDING_SIZE:   usize = 10;
 const DEFAULT_COMPLETED_SIZE: usize = 10;
+const DEFAULT_BLOCK_SIZE:     usize = 10;
+const DEFAULT_CHUNK_SIZE:     usize = 256 * 1024;
 
 /// `DiskManagerBuilder` for building `DiskManager`s with different settings.
 pub struct DiskManagerBuilder {
     builder:        Builder,
     pending_size:   usize,
-    completed_size: usize
+    completed_size: usize,
+    block_size:     usize,
+    verifier:       Arc<PieceVerifier + Send + Sync>,
+    metrics:        Arc<Metrics>,
+    pre_open:       bool,
+    verbose:        bool,
+    chunk_size:     usize
 }
 
 impl DiskManagerBuilder {
     /// Create a new `DiskManagerBuilder`.
     pub fn new() -> DiskManagerBuilder {
         DiskManagerBuilder{ builder: Builder::new(), pending_size: DEFAULT_PENDING_SIZE,
-                            completed_size: DEFAULT_COMPLETED_SIZE }
+                            completed_size: DEFAULT_COMPLETED_SIZE, block_size: DEFAULT_BLOCK_SIZE,
+                            verifier: Arc::new(Sha1Verifier::new()), metrics: metrics::noop(), pre_open: false,
+                            verbose: false, chunk_size: DEFAULT_CHUNK_SIZE }
     }
 
     /// Use a custom `Builder` for the `CpuPool`.
@@ -32,12 +47,69 @@ impl DiskManagerBuilder {
         self
     }
 
-    /// Specify the buffer capacity for completed `ODiskMessage`s.
+    /// Specify the buffer capacity for completed control `ODiskMessage`s.
+    ///
+    /// Control messages are any messages not carrying a block, and are always
+    /// yielded from the stream before any buffered block messages.
     pub fn with_stream_buffer_capacity(mut self, size: usize) -> DiskManagerBuilder {
         self.completed_size = size;
         self
     }
 
+    /// Specify the buffer capacity for completed block `ODiskMessage`s.
+    ///
+    /// Block messages are `BlockLoaded`, `BlockProcessed`, `LoadBlockError`, and `ProcessBlockError`.
+    pub fn with_block_buffer_capacity(mut self, size: usize) -> DiskManagerBuilder {
+        self.block_size = size;
+        self
+    }
+
+    /// Use a custom `PieceVerifier` for checking pieces.
+    ///
+    /// By default, pieces are verified using their SHA-1 hash.
+    pub fn with_piece_verifier<V>(mut self, verifier: V) -> DiskManagerBuilder
+        where V: PieceVerifier + Send + Sync + 'static {
+        self.verifier = Arc::new(verifier);
+        self
+    }
+
+    /// Use the given `Metrics` for reporting disk activity.
+    ///
+    /// Reports `bip_disk_blocks_written`, `bip_disk_blocks_read`, and `bip_disk_bytes_written` counters.
+    /// By default, all metrics are discarded.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> DiskManagerBuilder {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Open every file for a torrent when it is added, before any pieces are checked.
+    ///
+    /// Any path or permission problems will be reported as a `TorrentError` for the torrent up front,
+    /// instead of during the first block read or write. When used with a `FileHandleCache`, the opened
+    /// handles will be cached (up to the capacity of the cache) for subsequent block IO.
+    pub fn with_pre_open_files(mut self, pre_open: bool) -> DiskManagerBuilder {
+        self.pre_open = pre_open;
+        self
+    }
+
+    /// Report the hash values computed for every verified piece.
+    ///
+    /// Each `FoundGoodPiece` or `FoundBadPiece` message will be preceded by a `PieceVerified` message
+    /// holding the expected and actual hash of the piece, so that results can be audited without re-hashing.
+    pub fn with_verbose_verification(mut self, verbose: bool) -> DiskManagerBuilder {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Specify the number of bytes read at a time when hashing a piece.
+    ///
+    /// Bounds the memory used for each piece being verified, regardless of the piece length of
+    /// a torrent, if the `PieceVerifier` can verify from the hash of a piece. Defaults to 256 KiB.
+    pub fn with_hash_chunk_size(mut self, size: usize) -> DiskManagerBuilder {
+        self.chunk_size = cmp::max(size, 1);
+        self
+    }
+
     /// Retrieve the `CpuPool` builder.
     pub fn worker_config(&mut self) -> &mut Builder {
         &mut self.builder
@@ -53,6 +125,36 @@ impl DiskManagerBuilder {
         self.completed_size
     }
 
+    /// Retrieve the block buffer capacity.
+    pub fn block_buffer_capacity(&self) -> usize {
+        self.block_size
+    }
+
+    /// Retrieve the `PieceVerifier`.
+    pub fn piece_verifier(&self) -> Arc<PieceVerifier + Send + Sync> {
+        self.verifier.clone()
+    }
+
+    /// Retrieve the `Metrics`.
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// Retrieve whether or not files are opened when a torrent is added.
+    pub fn pre_open_files(&self) -> bool {
+        self.pre_open
+    }
+
+    /// Retrieve whether or not hash values are reported for verified pieces.
+    pub fn verbose_verification(&self) -> bool {
+        self.verbose
+    }
+
+    /// Retrieve the number of bytes read at a time when hashing a piece.
+    pub fn hash_chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
     /// Build a `DiskManager` with the given `FileSystem`.
     pub fn build<F>(self, fs: F) -> DiskManager<F>
         where F: FileSystem + Send + Sync + 'static {
@@ -61,6 +61,25 @@ impl<F> FileSystem for FileHandleCache<F> where F: FileSystem {
         self.inner.sync_file(path)
     }
 
+    fn move_file<P, Q>(&self, from: P, to: Q) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static,
+              Q: AsRef<Path> + Send + 'static {
+        // Make sure we dont hand out handles to either path while (or after) moving
+        self.run_with_lock(|cache, _| {
+            cache.remove(from.as_ref());
+            cache.remove(to.as_ref());
+        });
+
+        self.inner.move_file(from, to)
+    }
+
+    fn flush_file(&self, file: &mut Self::File) -> io::Result<()> {
+        let mut lock_file = file.lock()
+            .expect("bip_disk: Failed To Lock File In FileHandleCache::flush_file");
+
+        self.inner.flush_file(&mut *lock_file)
+    }
+
     fn file_size(&self, file: &Self::File) -> io::Result<u64> {
         let lock_file = file.lock()
         .expect("bip_disk: Failed To Lock File In FileHandleCache::file_size");
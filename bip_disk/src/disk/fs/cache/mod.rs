@@ -1 +1,2 @@
-pub mod file_handle;
\ No newline at end of file
+pub mod file_handle;
+pub mod write_back;
\ No newline at end of file
@@ -0,0 +1,374 @@
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::Bound;
+use std::sync::{Arc, Mutex, MutexGuard, Condvar};
+use std::path::{PathBuf, Path};
+use std::io;
+
+use disk::fs::FileSystem;
+
+/// Default number of dirty bytes buffered before all dirty data is flushed.
+const DEFAULT_MAX_DIRTY_BYTES: usize = 16 * 1024 * 1024;
+
+/// File handle for a `WriteBackCache`.
+pub struct WriteBackFile<T> {
+    path: PathBuf,
+    file: Arc<Mutex<T>>
+}
+
+/// Dirty regions of a single file, keyed by offset.
+///
+/// Regions never overlap or touch, adjacent writes are coalesced into a single region.
+struct DirtyFile<T> {
+    file:    Arc<Mutex<T>>,
+    regions: BTreeMap<u64, Vec<u8>>
+}
+
+impl<T> DirtyFile<T> {
+    fn new(file: Arc<Mutex<T>>) -> DirtyFile<T> {
+        DirtyFile{ file: file, regions: BTreeMap::new() }
+    }
+
+    /// Insert the given write, returning the change in the number of dirty bytes.
+    ///
+    /// The write must not extend past `u64::MAX`.
+    fn insert(&mut self, offset: u64, buffer: &[u8]) -> isize {
+        self.merge(offset, buffer, true)
+    }
+
+    /// Insert data that failed to be written, returning the change in the number of dirty bytes.
+    ///
+    /// Any data written since the failed data was taken takes precedence over it.
+    fn reinsert(&mut self, offset: u64, buffer: &[u8]) -> isize {
+        self.merge(offset, buffer, false)
+    }
+
+    fn merge(&mut self, offset: u64, buffer: &[u8], newest: bool) -> isize {
+        let end = offset + buffer.len() as u64;
+
+        // Find all regions that overlap or touch the write
+        let touching: Vec<u64> = self.regions.range((Bound::Unbounded, Bound::Included(end)))
+            .filter(|&(&start, data)| start + data.len() as u64 >= offset)
+            .map(|(&start, _)| start)
+            .collect();
+
+        let mut merged_start = offset;
+        let mut merged_end = end;
+        for start in touching.iter() {
+            let region_end = start + self.regions[start].len() as u64;
+
+            merged_start = cmp::min(merged_start, *start);
+            merged_end = cmp::max(merged_end, region_end);
+        }
+
+        // Whichever data is copied in last takes precedence
+        let mut merged = vec![0u8; (merged_end - merged_start) as usize];
+        let relative_offset = (offset - merged_start) as usize;
+        if !newest {
+            merged[relative_offset..(relative_offset + buffer.len())].copy_from_slice(buffer);
+        }
+
+        let mut removed_bytes = 0;
+        for start in touching {
+            let data = self.regions.remove(&start).unwrap();
+            let relative_start = (start - merged_start) as usize;
+
+            merged[relative_start..(relative_start + data.len())].copy_from_slice(&data);
+            removed_bytes += data.len();
+        }
+
+        if newest {
+            merged[relative_offset..(relative_offset + buffer.len())].copy_from_slice(buffer);
+        }
+
+        let added_bytes = merged.len();
+        self.regions.insert(merged_start, merged);
+
+        added_bytes as isize - removed_bytes as isize
+    }
+
+    /// Remove all regions overlapping the given range.
+    fn take_overlapping(&mut self, offset: u64, length: u64) -> BTreeMap<u64, Vec<u8>> {
+        let end = offset.saturating_add(length);
+
+        let overlapping: Vec<u64> = self.regions.range(..end)
+            .filter(|&(&start, data)| start + data.len() as u64 > offset)
+            .map(|(&start, _)| start)
+            .collect();
+
+        overlapping.into_iter()
+            .map(|start| (start, self.regions.remove(&start).unwrap()))
+            .collect()
+    }
+
+    /// End of the furthest dirty region.
+    fn dirty_end(&self) -> u64 {
+        self.regions.iter()
+            .next_back()
+            .map(|(&start, data)| start + data.len() as u64)
+            .unwrap_or(0)
+    }
+}
+
+/// Dirty files, and the total number of dirty bytes across them.
+///
+/// Paths that have dirty data in flight are tracked, so that no one reads (or flushes)
+/// the path until the data taken out of `files` has been written (or put back).
+struct DirtyState<T> {
+    files:    HashMap<PathBuf, DirtyFile<T>>,
+    flushing: HashSet<PathBuf>,
+    bytes:    usize
+}
+
+/// Sum of the lengths of the given regions.
+fn regions_bytes(regions: &BTreeMap<u64, Vec<u8>>) -> usize {
+    regions.values().map(|data| data.len()).sum()
+}
+
+//----------------------------------------------------------------------------//
+
+/// Buffers writes in memory, coalescing adjacent writes so they can be flushed as one contiguous write.
+///
+/// This is especially useful for spinning disks, where many small (16 KB) block writes
+/// scattered across a piece are far slower than a single write of the whole piece.
+///
+/// Dirty data is flushed when it is read, when the file is flushed (such as before a completed
+/// piece is verified), synced or moved, when the cache is dropped, or once the number of dirty
+/// bytes exceeds the configured maximum. Since writes are deferred, any errors writing dirty data
+/// will be returned from the call that caused the flush, and the data that was not written will
+/// be kept dirty so it can be flushed again later.
+pub struct WriteBackCache<F> where F: FileSystem {
+    dirty:     Mutex<DirtyState<F::File>>,
+    flushed:   Condvar,
+    max_dirty: usize,
+    inner:     F
+}
+
+impl<F> WriteBackCache<F> where F: FileSystem {
+    /// Create a new `WriteBackCache` with an inner `FileSystem` which will be written to when flushing.
+    pub fn new(inner: F) -> WriteBackCache<F> {
+        WriteBackCache::with_max_dirty_bytes(inner, DEFAULT_MAX_DIRTY_BYTES)
+    }
+
+    /// Create a new `WriteBackCache` which will buffer at most `max_dirty` bytes before flushing.
+    pub fn with_max_dirty_bytes(inner: F, max_dirty: usize) -> WriteBackCache<F> {
+        let state = DirtyState{ files: HashMap::new(), flushing: HashSet::new(), bytes: 0 };
+
+        WriteBackCache{ dirty: Mutex::new(state), flushed: Condvar::new(), max_dirty: max_dirty, inner: inner }
+    }
+
+    /// Number of bytes currently buffered and not yet written to the inner `FileSystem`.
+    pub fn dirty_bytes(&self) -> usize {
+        self.run_with_lock(|state| state.bytes)
+    }
+
+    /// Flush all dirty data to the inner `FileSystem`.
+    ///
+    /// Every dirty file is flushed, even if flushing one of them fails, the first error is returned.
+    pub fn flush(&self) -> io::Result<()> {
+        let paths = self.run_with_lock(|state| state.files.keys().cloned().collect::<Vec<_>>());
+
+        paths.into_iter()
+            .map(|path| self.flush_path(&path))
+            .fold(Ok(()), |result, flush_result| result.and(flush_result))
+    }
+
+    /// Flush all dirty data for the given path to the inner `FileSystem`.
+    fn flush_path(&self, path: &Path) -> io::Result<()> {
+        self.flush_with(path, |dirty_file| ::std::mem::replace(&mut dirty_file.regions, BTreeMap::new()))
+    }
+
+    /// Flush the dirty regions for the given path that are taken out by the given closure.
+    ///
+    /// Blocks while any other thread has dirty data for the path in flight.
+    fn flush_with<T>(&self, path: &Path, take: T) -> io::Result<()>
+        where T: FnOnce(&mut DirtyFile<F::File>) -> BTreeMap<u64, Vec<u8>> {
+        let (file, regions) = {
+            let mut lock_state = self.lock_flushed(path);
+            let DirtyState{ ref mut files, ref mut flushing, ref mut bytes } = *lock_state;
+
+            let (file, regions, is_clean) = match files.get_mut(path) {
+                Some(dirty_file) => {
+                    let regions = take(dirty_file);
+
+                    (dirty_file.file.clone(), regions, dirty_file.regions.is_empty())
+                },
+                None => return Ok(())
+            };
+            if is_clean {
+                files.remove(path);
+            }
+            if regions.is_empty() {
+                return Ok(())
+            }
+
+            *bytes -= regions_bytes(&regions);
+            flushing.insert(path.to_path_buf());
+
+            (file, regions)
+        };
+
+        let result = self.write_regions(&file, regions);
+
+        let mut lock_state = self.dirty.lock()
+            .expect("bip_disk: Failed To Lock Dirty State In WriteBackCache::flush_with");
+        lock_state.flushing.remove(path);
+
+        let result = match result {
+            Ok(()) => Ok(()),
+            Err((error, unwritten)) => {
+                let DirtyState{ ref mut files, ref mut bytes, .. } = *lock_state;
+                let dirty_file = files.entry(path.to_path_buf()).or_insert_with(|| DirtyFile::new(file.clone()));
+
+                for (offset, data) in unwritten {
+                    let delta = dirty_file.reinsert(offset, &data);
+                    *bytes = (*bytes as isize + delta) as usize;
+                }
+
+                Err(error)
+            }
+        };
+        self.flushed.notify_all();
+
+        result
+    }
+
+    /// Write the given regions, on error, return the regions (or parts of regions) that were not written.
+    fn write_regions(&self, file: &Mutex<F::File>, mut regions: BTreeMap<u64, Vec<u8>>)
+        -> Result<(), (io::Error, BTreeMap<u64, Vec<u8>>)> {
+        let mut lock_file = file.lock()
+            .expect("bip_disk: Failed To Lock File In WriteBackCache::write_regions");
+
+        while let Some(offset) = regions.keys().next().cloned() {
+            let data = regions.remove(&offset).unwrap();
+            let mut written = 0;
+
+            while written < data.len() {
+                let result = match self.inner.write_file(&mut *lock_file, offset + written as u64, &data[written..]) {
+                    Ok(0)     => Err(io::Error::new(io::ErrorKind::WriteZero, "Failed To Write Dirty Region")),
+                    Ok(bytes) => Ok(bytes),
+                    Err(error) => Err(error)
+                };
+
+                match result {
+                    Ok(bytes) => written += bytes,
+                    Err(error) => {
+                        regions.insert(offset + written as u64, data[written..].to_vec());
+
+                        return Err((error, regions))
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lock the dirty state once no dirty data for the given path is in flight.
+    fn lock_flushed(&self, path: &Path) -> MutexGuard<DirtyState<F::File>> {
+        let mut lock_state = self.dirty.lock()
+            .expect("bip_disk: Failed To Lock Dirty State In WriteBackCache::lock_flushed");
+
+        while lock_state.flushing.contains(path) {
+            lock_state = self.flushed.wait(lock_state)
+                .expect("bip_disk: Failed To Wait On Dirty State In WriteBackCache::lock_flushed");
+        }
+
+        lock_state
+    }
+
+    fn run_with_lock<C, R>(&self, call: C) -> R
+        where C: FnOnce(&mut DirtyState<F::File>) -> R {
+        let mut lock_state = self.dirty.lock()
+            .expect("bip_disk: Failed To Lock Dirty State In WriteBackCache::run_with_lock");
+
+        call(&mut *lock_state)
+    }
+}
+
+impl<F> Drop for WriteBackCache<F> where F: FileSystem {
+    fn drop(&mut self) {
+        // Nobody is left to report errors to
+        let _ = self.flush();
+    }
+}
+
+impl<F> FileSystem for WriteBackCache<F> where F: FileSystem {
+    type File = WriteBackFile<F::File>;
+
+    fn open_file<P>(&self, path: P) -> io::Result<Self::File>
+        where P: AsRef<Path> + Send + 'static {
+        let path_buf = path.as_ref().to_path_buf();
+        let file = try!(self.inner.open_file(path));
+
+        Ok(WriteBackFile{ path: path_buf, file: Arc::new(Mutex::new(file)) })
+    }
+
+    fn sync_file<P>(&self, path: P) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static {
+        try!(self.flush_path(path.as_ref()));
+
+        self.inner.sync_file(path)
+    }
+
+    fn move_file<P, Q>(&self, from: P, to: Q) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static,
+              Q: AsRef<Path> + Send + 'static {
+        try!(self.flush_path(from.as_ref()));
+        try!(self.flush_path(to.as_ref()));
+
+        self.inner.move_file(from, to)
+    }
+
+    fn flush_file(&self, file: &mut Self::File) -> io::Result<()> {
+        self.flush_path(&file.path)
+    }
+
+    fn file_size(&self, file: &Self::File) -> io::Result<u64> {
+        // Dirty data in flight has not made it to the inner file yet
+        let dirty_end = self.lock_flushed(&file.path)
+            .files.get(&file.path)
+            .map(|dirty_file| dirty_file.dirty_end())
+            .unwrap_or(0);
+
+        let lock_file = file.file.lock()
+            .expect("bip_disk: Failed To Lock File In WriteBackCache::file_size");
+        let inner_size = try!(self.inner.file_size(&*lock_file));
+
+        Ok(cmp::max(inner_size, dirty_end))
+    }
+
+    fn read_file(&self, file: &mut Self::File, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        // Flush any dirty data we are about to read (waiting on any in flight), so the read sees it
+        let length = buffer.len() as u64;
+        try!(self.flush_with(&file.path, |dirty_file| dirty_file.take_overlapping(offset, length)));
+
+        let mut lock_file = file.file.lock()
+            .expect("bip_disk: Failed To Lock File In WriteBackCache::read_file");
+
+        self.inner.read_file(&mut *lock_file, offset, buffer)
+    }
+
+    fn write_file(&self, file: &mut Self::File, offset: u64, buffer: &[u8]) -> io::Result<usize> {
+        let max_dirty = self.max_dirty;
+
+        if offset.checked_add(buffer.len() as u64).is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Write Extends Past The Maximum File Size"))
+        }
+
+        let over_limit = self.run_with_lock(|state| {
+            let delta = state.files.entry(file.path.clone())
+                .or_insert_with(|| DirtyFile::new(file.file.clone()))
+                .insert(offset, buffer);
+            state.bytes = (state.bytes as isize + delta) as usize;
+
+            state.bytes > max_dirty
+        });
+
+        if over_limit {
+            try!(self.flush());
+        }
+
+        Ok(buffer.len())
+    }
+}
@@ -21,6 +21,29 @@ pub trait FileSystem {
     fn sync_file<P>(&self, path: P) -> io::Result<()>
         where P: AsRef<Path> + Send + 'static;
 
+    /// Move the file from one path to another.
+    ///
+    /// Intermediate directories for the new path will be created if necessary. This
+    /// should be atomic where the underlying file system allows it.
+    ///
+    /// By default, files can not be moved, and an error is returned.
+    fn move_file<P, Q>(&self, from: P, to: Q) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static,
+              Q: AsRef<Path> + Send + 'static {
+        let _ = (from, to);
+
+        Err(io::Error::new(io::ErrorKind::Other, "FileSystem Does Not Support Moving Files"))
+    }
+
+    /// Write out any data for the file that the file system is buffering in memory.
+    ///
+    /// This is called before a completed piece is verified. By default, nothing is buffered.
+    fn flush_file(&self, file: &mut Self::File) -> io::Result<()> {
+        let _ = file;
+
+        Ok(())
+    }
+
     /// Get the size of the file in bytes.
     fn file_size(&self, file: &Self::File) -> io::Result<u64>;
 
@@ -49,6 +72,16 @@ impl<'a, F> FileSystem for &'a F where F: FileSystem {
         FileSystem::sync_file(*self, path)
     }
 
+    fn move_file<P, Q>(&self, from: P, to: Q) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static,
+              Q: AsRef<Path> + Send + 'static {
+        FileSystem::move_file(*self, from, to)
+    }
+
+    fn flush_file(&self, file: &mut Self::File) -> io::Result<()> {
+        FileSystem::flush_file(*self, file)
+    }
+
     fn file_size(&self, file: &Self::File) -> io::Result<u64> {
         FileSystem::file_size(*self, file)
     }
@@ -1,7 +1,8 @@
 use std::path::{Path, PathBuf};
-use std::io::{self, Write, Read, Seek, SeekFrom};
+use std::io::{self};
 use std::fs::{self, File, OpenOptions};
 use std::borrow::Cow;
+use std::sync::{Arc, Mutex, Condvar};
 
 use disk::fs::FileSystem;
 
@@ -19,16 +20,43 @@ impl NativeFile {
     }
 }
 
+/// Writes at least this large are considered large sequential writes.
+const DEFAULT_LARGE_WRITE_SIZE:    usize = 64 * 1024;
+/// Number of threads allowed to perform large sequential writes at once.
+const DEFAULT_LARGE_WRITE_THREADS: usize = 2;
+
 /// File system that maps to the OS file system.
+///
+/// Reads and writes use positional IO where the platform supports it, so
+/// pooled tasks sharing a file handle do not race on the file cursor. Large
+/// sequential writes are pinned to a limited number of threads at a time so
+/// that they do not thrash the disk when many tasks are writing at once.
 pub struct NativeFileSystem {
-    current_dir: PathBuf
+    current_dir:      PathBuf,
+    large_write_size: usize,
+    large_writers:    Arc<WriterLimit>
 }
 
 impl NativeFileSystem {
     /// Initialize a new `NativeFileSystem` with the default directory set.
     pub fn with_directory<P>(default: P) -> NativeFileSystem
         where P: AsRef<Path> {
-        NativeFileSystem{ current_dir: default.as_ref().to_path_buf() }
+        NativeFileSystem{ current_dir: default.as_ref().to_path_buf(), large_write_size: DEFAULT_LARGE_WRITE_SIZE,
+                          large_writers: Arc::new(WriterLimit::new(DEFAULT_LARGE_WRITE_THREADS)) }
+    }
+
+    /// Set the size at which writes are considered large sequential writes.
+    pub fn with_large_write_size(mut self, size: usize) -> NativeFileSystem {
+        self.large_write_size = size;
+        self
+    }
+
+    /// Set the number of threads allowed to perform large sequential writes at once.
+    ///
+    /// Values less than one will be treated as one.
+    pub fn with_large_write_threads(mut self, threads: usize) -> NativeFileSystem {
+        self.large_writers = Arc::new(WriterLimit::new(threads));
+        self
     }
 }
 
@@ -48,20 +76,136 @@ impl FileSystem for NativeFileSystem {
         Ok(())
     }
 
+    fn move_file<P, Q>(&self, from: P, to: Q) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static,
+              Q: AsRef<Path> + Send + 'static {
+        let combine_from = combine_user_path(&from, &self.current_dir);
+        let combine_to = combine_user_path(&to, &self.current_dir);
+
+        if let Some(parent_dir) = combine_to.parent() {
+            try!(fs::create_dir_all(parent_dir));
+        }
+
+        // Renaming is atomic, but will fail across devices, so fall back to a copy
+        fs::rename(&combine_from, &combine_to)
+            .or_else(|_| {
+                try!(fs::copy(&combine_from, &combine_to));
+
+                fs::remove_file(&combine_from)
+            })
+    }
+
     fn file_size(&self, file: &NativeFile) -> io::Result<u64> {
         file.file.metadata().map(|metadata| metadata.len())
     }
 
     fn read_file(&self, file: &mut NativeFile, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
-        try!(file.file.seek(SeekFrom::Start(offset)));
-
-        file.file.read(buffer)
+        positional::read_at(&mut file.file, offset, buffer)
     }
 
     fn write_file(&self, file: &mut NativeFile, offset: u64, buffer: &[u8]) -> io::Result<usize> {
-        try!(file.file.seek(SeekFrom::Start(offset)));
+        // Hold on to the permit (if any) until the write has completed
+        let _opt_permit = if buffer.len() >= self.large_write_size {
+            Some(self.large_writers.acquire())
+        } else {
+            None
+        };
+
+        positional::write_at(&mut file.file, offset, buffer)
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// Limits the number of threads that can hold a permit at once.
+struct WriterLimit {
+    active: Mutex<usize>,
+    max:    usize,
+    signal: Condvar
+}
+
+impl WriterLimit {
+    fn new(max: usize) -> WriterLimit {
+        WriterLimit{ active: Mutex::new(0), max: if max == 0 { 1 } else { max }, signal: Condvar::new() }
+    }
+
+    /// Block until a permit is available.
+    fn acquire(&self) -> WriterPermit {
+        let mut active = self.active.lock()
+            .expect("bip_disk: Failed To Lock Active Writers In WriterLimit::acquire");
+
+        while *active >= self.max {
+            active = self.signal.wait(active)
+                .expect("bip_disk: Failed To Wait On Active Writers In WriterLimit::acquire");
+        }
+        *active += 1;
+
+        WriterPermit{ limit: self }
+    }
+}
+
+/// Permit that is given back to the `WriterLimit` when dropped.
+struct WriterPermit<'a> {
+    limit: &'a WriterLimit
+}
+
+impl<'a> Drop for WriterPermit<'a> {
+    fn drop(&mut self) {
+        let mut active = self.limit.active.lock()
+            .expect("bip_disk: Failed To Lock Active Writers In WriterPermit::drop");
+
+        *active -= 1;
+        self.limit.signal.notify_one();
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+#[cfg(unix)]
+mod positional {
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::fs::FileExt;
+
+    pub fn read_at(file: &mut File, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        file.read_at(buffer, offset)
+    }
+
+    pub fn write_at(file: &mut File, offset: u64, buffer: &[u8]) -> io::Result<usize> {
+        file.write_at(buffer, offset)
+    }
+}
+
+#[cfg(windows)]
+mod positional {
+    use std::fs::File;
+    use std::io;
+    use std::os::windows::fs::FileExt;
+
+    pub fn read_at(file: &mut File, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        file.seek_read(buffer, offset)
+    }
+
+    pub fn write_at(file: &mut File, offset: u64, buffer: &[u8]) -> io::Result<usize> {
+        file.seek_write(buffer, offset)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod positional {
+    use std::fs::File;
+    use std::io::{self, Write, Read, Seek, SeekFrom};
+
+    pub fn read_at(file: &mut File, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        try!(file.seek(SeekFrom::Start(offset)));
+
+        file.read(buffer)
+    }
+
+    pub fn write_at(file: &mut File, offset: u64, buffer: &[u8]) -> io::Result<usize> {
+        try!(file.seek(SeekFrom::Start(offset)));
 
-        file.file.write(buffer)
+        file.write(buffer)
     }
 }
 
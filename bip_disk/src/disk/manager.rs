@@ -6,12 +6,14 @@ use disk::{IDiskMessage, ODiskMessage};
 use disk::tasks;
 use disk::tasks::context::DiskManagerContext;
 use disk::builder::DiskManagerBuilder;
+use disk::range::RangeStream;
 
 use crossbeam::sync::MsQueue;
 use futures::task::{self, Task};
 use futures::sync::mpsc::{self, Receiver};
 use futures::{StartSend, Poll, Stream, Sink, AsyncSink, Async};
 use futures_cpupool::{CpuPool};
+use bip_util::bt::InfoHash;
 
 /// `DiskManager` object which handles the storage of `Blocks` to the `FileSystem`.
 pub struct DiskManager<F> {
@@ -25,15 +27,23 @@ impl<F> DiskManager<F> {
         let cur_sink_capacity = Arc::new(AtomicUsize::new(0));
         let sink_capacity = builder.sink_buffer_capacity();
         let stream_capacity = builder.stream_buffer_capacity();
+        let block_capacity = builder.block_buffer_capacity();
+        let verifier = builder.piece_verifier();
+        let metrics = builder.metrics();
+        let pre_open = builder.pre_open_files();
+        let verbose = builder.verbose_verification();
+        let chunk_size = builder.hash_chunk_size();
         let pool_builder = builder.worker_config();
 
         let (out_send, out_recv) = mpsc::channel(stream_capacity);
-        let context = DiskManagerContext::new(out_send, fs);
+        let (block_send, block_recv) = mpsc::channel(block_capacity);
+        let context = DiskManagerContext::new(out_send, block_send, fs, verifier, metrics, pre_open, verbose,
+            chunk_size);
         let task_queue = Arc::new(MsQueue::new());
 
         let sink = DiskManagerSink::new(pool_builder.create(), context, sink_capacity, cur_sink_capacity.clone(),
             task_queue.clone());
-        let stream = DiskManagerStream::new(out_recv, cur_sink_capacity, task_queue.clone());
+        let stream = DiskManagerStream::new(out_recv, block_recv, cur_sink_capacity, task_queue.clone());
 
         DiskManager{ sink: sink, stream: stream }
     }
@@ -44,6 +54,16 @@ impl<F> DiskManager<F> {
     pub fn into_parts(self) -> (DiskManagerSink<F>, DiskManagerStream) {
         (self.sink, self.stream)
     }
+
+    /// Number of blocks that have failed their checksum before being written.
+    pub fn checksum_mismatches(&self) -> usize {
+        self.sink.checksum_mismatches()
+    }
+
+    /// Stream the given byte range of the torrent, waiting for pieces to be verified as needed.
+    pub fn read_range(&self, hash: InfoHash, offset: u64, length: u64) -> RangeStream<F> {
+        self.sink.read_range(hash, offset, length)
+    }
 }
 
 impl<F> Sink for DiskManager<F> where F: FileSystem + Send + Sync + 'static {
@@ -93,6 +113,20 @@ impl<F> DiskManagerSink<F> {
                          cur_capacity: cur_capacity, task_queue: task_queue }
     }
 
+    /// Number of blocks that have failed their checksum before being written.
+    pub fn checksum_mismatches(&self) -> usize {
+        self.context.checksum_mismatches()
+    }
+
+    /// Stream the given byte range of the torrent, waiting for pieces to be verified as needed.
+    ///
+    /// Offsets are relative to the start of the torrent, with files laid out in the order
+    /// they appear in the info dictionary. The stream will error if the range extends past
+    /// the end of the torrent, if the torrent is not (or is no longer) added, or if it is paused.
+    pub fn read_range(&self, hash: InfoHash, offset: u64, length: u64) -> RangeStream<F> {
+        RangeStream::new(self.pool.clone(), self.context.clone(), hash, offset, length)
+    }
+
     fn try_submit_work(&self) -> bool {
         let cur_capacity = self.cur_capacity.fetch_add(1, Ordering::SeqCst);
 
@@ -146,20 +180,41 @@ impl<F> Sink for DiskManagerSink<F> where F: FileSystem + Send + Sync + 'static
 //----------------------------------------------------------------------------//
 
 /// `DiskManagerStream` which is the stream portion of a `DiskManager`.
+///
+/// Control messages are always yielded before block messages, so that a backlog of
+/// blocks won't delay notifications such as `TorrentAdded` or `FoundGoodPiece`. As a
+/// result, a control message may be yielded before block messages that were produced
+/// ahead of it (see `ODiskMessage`).
 pub struct DiskManagerStream {
     recv:         Receiver<ODiskMessage>,
+    block_recv:   Receiver<ODiskMessage>,
     cur_capacity: Arc<AtomicUsize>,
     task_queue:   Arc<MsQueue<Task>>
 }
 
 impl DiskManagerStream {
-    fn new(recv: Receiver<ODiskMessage>, cur_capacity: Arc<AtomicUsize>, task_queue: Arc<MsQueue<Task>>) -> DiskManagerStream {
-        DiskManagerStream{ recv: recv, cur_capacity: cur_capacity, task_queue: task_queue }
+    fn new(recv: Receiver<ODiskMessage>, block_recv: Receiver<ODiskMessage>, cur_capacity: Arc<AtomicUsize>,
+           task_queue: Arc<MsQueue<Task>>) -> DiskManagerStream {
+        DiskManagerStream{ recv: recv, block_recv: block_recv, cur_capacity: cur_capacity, task_queue: task_queue }
     }
 
     fn complete_work(&self) {
         self.cur_capacity.fetch_sub(1, Ordering::SeqCst);
     }
+
+    fn poll_lanes(&mut self) -> Poll<Option<ODiskMessage>, ()> {
+        match try!(self.recv.poll()) {
+            Async::Ready(Some(msg)) => Ok(Async::Ready(Some(msg))),
+            // Control lane is empty (or closed), fall back to the block lane
+            control_poll => {
+                match (control_poll, try!(self.block_recv.poll())) {
+                    (_, Async::Ready(Some(msg)))               => Ok(Async::Ready(Some(msg))),
+                    (Async::Ready(None), Async::Ready(None))   => Ok(Async::Ready(None)),
+                    _                                          => Ok(Async::NotReady)
+                }
+            }
+        }
+    }
 }
 
 impl Stream for DiskManagerStream {
@@ -169,10 +224,13 @@ impl Stream for DiskManagerStream {
     fn poll(&mut self) -> Poll<Option<ODiskMessage>, ()> {
         info!("Polling DiskManagerStream For ODiskMessage");
 
-        match self.recv.poll() {
-            res @ Ok(Async::Ready(Some(ODiskMessage::TorrentAdded(_)))) |
+        match self.poll_lanes() {
+            res @ Ok(Async::Ready(Some(ODiskMessage::TorrentAdded(_, _)))) |
             res @ Ok(Async::Ready(Some(ODiskMessage::TorrentRemoved(_)))) |
             res @ Ok(Async::Ready(Some(ODiskMessage::TorrentSynced(_)))) |
+            res @ Ok(Async::Ready(Some(ODiskMessage::TorrentMoved(_)))) |
+            res @ Ok(Async::Ready(Some(ODiskMessage::TorrentPaused(_)))) |
+            res @ Ok(Async::Ready(Some(ODiskMessage::TorrentResumed(_)))) |
             res @ Ok(Async::Ready(Some(ODiskMessage::BlockLoaded(_)))) |
             res @ Ok(Async::Ready(Some(ODiskMessage::BlockProcessed(_)))) => {
                 self.complete_work();
@@ -190,4 +248,76 @@ impl Stream for DiskManagerStream {
             other => other
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+
+    use super::DiskManagerStream;
+    use disk::ODiskMessage;
+    use memory::block::{BlockMetadata, BlockMut};
+
+    use bip_util::bt::{self, InfoHash};
+    use bytes::BytesMut;
+    use crossbeam::sync::MsQueue;
+    use futures::sink::Sink;
+    use futures::stream::Stream;
+    use futures::sync::mpsc;
+
+    fn block_loaded(hash: InfoHash) -> ODiskMessage {
+        ODiskMessage::BlockLoaded(BlockMut::new(BlockMetadata::new(hash, 0, 0, 0), BytesMut::new()))
+    }
+
+    #[test]
+    fn positive_control_before_buffered_blocks() {
+        let hash: InfoHash = [1u8; bt::INFO_HASH_LEN].into();
+        let (out_send, out_recv) = mpsc::channel(10);
+        let (block_send, block_recv) = mpsc::channel(10);
+        let stream = DiskManagerStream::new(out_recv, block_recv, Arc::new(AtomicUsize::new(4)),
+            Arc::new(MsQueue::new()));
+
+        {
+            let mut block_send = block_send.wait();
+            for _ in 0..3 {
+                block_send.send(block_loaded(hash)).unwrap();
+            }
+        }
+        out_send.wait().send(ODiskMessage::TorrentSynced(hash)).unwrap();
+
+        let messages: Vec<ODiskMessage> = stream.wait().map(Result::unwrap).collect();
+
+        assert_eq!(4, messages.len());
+        match messages[0] {
+            ODiskMessage::TorrentSynced(_) => (),
+            ref unexpected @ _             => panic!("Unexpected Message: {:?}", unexpected)
+        };
+        for msg in &messages[1..] {
+            match msg {
+                &ODiskMessage::BlockLoaded(_) => (),
+                unexpected @ _                => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    }
+
+    #[test]
+    fn positive_block_lane_drained_after_control_closed() {
+        let hash: InfoHash = [1u8; bt::INFO_HASH_LEN].into();
+        let (out_send, out_recv) = mpsc::channel::<ODiskMessage>(10);
+        let (block_send, block_recv) = mpsc::channel(10);
+        let stream = DiskManagerStream::new(out_recv, block_recv, Arc::new(AtomicUsize::new(2)),
+            Arc::new(MsQueue::new()));
+
+        drop(out_send);
+        {
+            let mut block_send = block_send.wait();
+            block_send.send(block_loaded(hash)).unwrap();
+            block_send.send(block_loaded(hash)).unwrap();
+        }
+
+        let messages: Vec<ODiskMessage> = stream.wait().map(Result::unwrap).collect();
+
+        assert_eq!(2, messages.len());
+    }
+}
@@ -1,5 +1,9 @@
+use std::path::PathBuf;
+
 use error::{TorrentError, BlockError};
 use memory::block::{Block, BlockMut};
+use disk::summary::TorrentSummary;
+use disk::verify::PieceVerification;
 
 use bip_metainfo::Metainfo;
 use bip_util::bt::{InfoHash};
@@ -7,6 +11,9 @@ use bip_util::bt::{InfoHash};
 pub mod builder;
 pub mod manager;
 pub mod fs;
+pub mod range;
+pub mod summary;
+pub mod verify;
 mod tasks;
 
 //----------------------------------------------------------------------------//
@@ -21,6 +28,9 @@ pub enum IDiskMessage {
     /// Note, this will NOT remove any data from the `FileSystem`,
     /// and as an added convenience, this message will also trigger
     /// a `IDiskMessage::SyncTorrent` message.
+    ///
+    /// If the torrent is still going through its initial check, the check
+    /// will be stopped, and the `AddTorrent` will fail with `CheckCancelled`.
     RemoveTorrent(InfoHash),
     /// Message to tell the `FileSystem` to sync the torrent.
     ///
@@ -33,6 +43,21 @@ pub enum IDiskMessage {
     /// message should be sent, otherwise, `IDiskMessage::RemoveTorrent` is
     /// sufficient.
     SyncTorrent(InfoHash),
+    /// Message to move the files of a torrent to the given directory.
+    ///
+    /// The directory is interpreted by the `FileSystem` the same way the directory
+    /// in the info dictionary is. Files are moved atomically where the `FileSystem`
+    /// allows it, and if any file fails to move, files already moved will be put back.
+    /// Blocks processed after this message completes will go to the new directory.
+    MoveTorrent(InfoHash, PathBuf),
+    /// Message to pause block IO for the torrent.
+    ///
+    /// While paused, `LoadBlock` and `ProcessBlock` messages (as well as range reads) for the
+    /// torrent will be rejected with a `TorrentPaused` error, but the torrent (and its piece state)
+    /// will be kept around, so it does not have to be re-checked when it is resumed.
+    PauseTorrent(InfoHash),
+    /// Message to resume block IO for a torrent paused with `IDiskMessage::PauseTorrent`.
+    ResumeTorrent(InfoHash),
     /// Message to load the given block in to memory.
     LoadBlock(BlockMut),
     /// Message to process the given block and persist it.
@@ -40,23 +65,42 @@ pub enum IDiskMessage {
 }
 
 /// Messages that can be received from the `DiskManager`.
+///
+/// Block messages (`BlockLoaded`, `BlockProcessed`, `LoadBlockError`, and `ProcessBlockError`)
+/// are buffered separately from all other messages, and any buffered control message is yielded
+/// ahead of them. This means ordering is only kept within each of those two groups; for example,
+/// a `TorrentRemoved` can be received before a `BlockLoaded` for a `LoadBlock` that was sent
+/// before the `RemoveTorrent`.
 #[derive(Debug)]
 pub enum ODiskMessage {
     /// Message indicating that the torrent has been added.
     ///
     /// Any good pieces already existing for the torrent will be sent
-    /// as `FoundGoodPiece` messages BEFORE this message is sent.
-    TorrentAdded(InfoHash),
+    /// as `FoundGoodPiece` messages BEFORE this message is sent, along
+    /// with a `TorrentSummary` of the initial check of the torrent.
+    TorrentAdded(InfoHash, TorrentSummary),
     /// Message indicating that the torrent has been removed.
     TorrentRemoved(InfoHash),
     /// Message indicating that the torrent has been synced.
     TorrentSynced(InfoHash),
+    /// Message indicating that the torrent has been moved.
+    TorrentMoved(InfoHash),
+    /// Message indicating that the torrent has been paused.
+    TorrentPaused(InfoHash),
+    /// Message indicating that the torrent has been resumed.
+    TorrentResumed(InfoHash),
     /// Message indicating that a good piece has been identified for
     /// the given torrent (hash), as well as the piece index.
     FoundGoodPiece(InfoHash, u64),
     /// Message indicating that a bad piece has been identified for
     /// the given torrent (hash), as well as the piece index.
     FoundBadPiece(InfoHash, u64),
+    /// Message holding the hash values computed while verifying a piece.
+    ///
+    /// Only sent if enabled with `DiskManagerBuilder::with_verbose_verification`, in
+    /// which case it will be sent before the `FoundGoodPiece` or `FoundBadPiece`
+    /// message for the piece.
+    PieceVerified(InfoHash, PieceVerification),
     /// Message indicating that the given block has been loaded.
     BlockLoaded(BlockMut),
     /// Message indicating that the given block has been processed.
@@ -0,0 +1,80 @@
+use disk::fs::FileSystem;
+use disk::tasks;
+use disk::tasks::context::DiskManagerContext;
+use error::{BlockError, BlockErrorKind};
+
+use bip_util::bt::InfoHash;
+use bytes::Bytes;
+use futures::{Async, Future, Poll, Stream};
+use futures_cpupool::{CpuPool, CpuFuture};
+
+/// Stream of the bytes in a range of a torrent.
+///
+/// Bytes are yielded in order, at most one piece at a time, as soon as the pieces
+/// covering them have been verified. If a piece has not been verified yet, the
+/// stream will wait until it is, which makes it suitable for playing media while
+/// it is still being downloaded.
+pub struct RangeStream<F> {
+    pool:        CpuPool,
+    context:     DiskManagerContext<F>,
+    hash:        InfoHash,
+    offset:      u64,
+    end:         u64,
+    reader:      usize,
+    opt_error:   Option<BlockError>,
+    opt_pending: Option<CpuFuture<Option<Bytes>, BlockError>>
+}
+
+impl<F> RangeStream<F> {
+    pub fn new(pool: CpuPool, context: DiskManagerContext<F>, hash: InfoHash, offset: u64, length: u64) -> RangeStream<F> {
+        let reader = context.new_reader();
+
+        // A range that overflows can never be in bounds, so it is reported on the first poll
+        let (end, opt_error) = match offset.checked_add(length) {
+            Some(end) => (end, None),
+            None      => (offset, Some(BlockError::from_kind(BlockErrorKind::RangeOutOfBounds{ hash: hash, offset: offset, length: length })))
+        };
+
+        RangeStream{ pool: pool, context: context, hash: hash, offset: offset, end: end, reader: reader,
+                     opt_error: opt_error, opt_pending: None }
+    }
+}
+
+impl<F> Drop for RangeStream<F> {
+    fn drop(&mut self) {
+        self.context.unpark_reader(self.reader);
+    }
+}
+
+impl<F> Stream for RangeStream<F> where F: FileSystem + Send + Sync + 'static {
+    type Item = Bytes;
+    type Error = BlockError;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, BlockError> {
+        if let Some(error) = self.opt_error.take() {
+            return Err(error)
+        }
+
+        if self.offset >= self.end {
+            return Ok(Async::Ready(None))
+        }
+
+        let mut pending = self.opt_pending.take()
+            .unwrap_or_else(|| tasks::read_range_on_pool(self.hash, self.offset, self.end, self.reader, &self.pool, self.context.clone()));
+
+        match try!(pending.poll()) {
+            Async::Ready(Some(bytes)) => {
+                self.offset += bytes.len() as u64;
+
+                Ok(Async::Ready(Some(bytes)))
+            },
+            // Piece has not been verified, we will be woken up when piece states change
+            Async::Ready(None)        => Ok(Async::NotReady),
+            Async::NotReady           => {
+                self.opt_pending = Some(pending);
+
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+/// Summary of the initial check performed when a torrent is added.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TorrentSummary {
+    good_pieces:    u64,
+    bad_pieces:     u64,
+    missing_pieces: u64,
+    bytes_present:  u64,
+    files_created:  usize
+}
+
+impl TorrentSummary {
+    /// Create a new `TorrentSummary`.
+    pub fn new(good_pieces: u64, bad_pieces: u64, missing_pieces: u64, bytes_present: u64, files_created: usize) -> TorrentSummary {
+        TorrentSummary{ good_pieces: good_pieces, bad_pieces: bad_pieces, missing_pieces: missing_pieces,
+                        bytes_present: bytes_present, files_created: files_created }
+    }
+
+    /// Number of pieces that were found to be good.
+    pub fn good_pieces(&self) -> u64 {
+        self.good_pieces
+    }
+
+    /// Number of pieces that were found to be bad.
+    ///
+    /// These are pieces that had data in an existing file, but failed verification.
+    pub fn bad_pieces(&self) -> u64 {
+        self.bad_pieces
+    }
+
+    /// Number of pieces that were missing.
+    ///
+    /// These are pieces that lie entirely within files that had to be created.
+    pub fn missing_pieces(&self) -> u64 {
+        self.missing_pieces
+    }
+
+    /// Number of bytes covered by good pieces.
+    pub fn bytes_present(&self) -> u64 {
+        self.bytes_present
+    }
+
+    /// Number of files that had to be created.
+    pub fn files_created(&self) -> usize {
+        self.files_created
+    }
+}
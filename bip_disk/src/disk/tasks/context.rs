@@ -1,45 +1,180 @@
 use std::sync::{Arc, RwLock, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::collections::HashMap;
+use std::mem;
+use std::path::{Path, PathBuf};
 
 use disk::ODiskMessage;
+use disk::verify::PieceVerifier;
 use disk::tasks::helpers::piece_checker::PieceCheckerState;
 
 use bip_metainfo::Metainfo;
 use bip_util::bt::InfoHash;
+use bip_util::metrics::Metrics;
 use futures::sync::mpsc::Sender;
 use futures::sink::Sink;
 use futures::sink::Wait;
+use futures::task::{self, Task};
 
 pub struct DiskManagerContext<F> {
     torrents:    Arc<RwLock<HashMap<InfoHash, Mutex<MetainfoState>>>>,
+    checking:    Arc<Mutex<HashMap<InfoHash, Vec<Arc<AtomicBool>>>>>,
     out:         Sender<ODiskMessage>,
-    fs:          Arc<F>
+    block_out:   Sender<ODiskMessage>,
+    fs:          Arc<F>,
+    verifier:    Arc<PieceVerifier + Send + Sync>,
+    metrics:     Arc<Metrics>,
+    mismatches:  Arc<AtomicUsize>,
+    readers:     Arc<Mutex<HashMap<usize, Task>>>,
+    next_reader: Arc<AtomicUsize>,
+    pre_open:    bool,
+    verbose:     bool,
+    chunk_size:  usize
 }
 
 pub struct MetainfoState {
-    file:  Metainfo,
-    state: PieceCheckerState
+    file:      Metainfo,
+    directory: Option<PathBuf>,
+    state:     PieceCheckerState,
+    paused:    bool
 }
 
 impl MetainfoState {
     pub fn new(file: Metainfo, state: PieceCheckerState) -> MetainfoState {
-        MetainfoState{ file: file, state: state }
+        let directory = file.info().directory().map(Path::to_path_buf);
+
+        MetainfoState{ file: file, directory: directory, state: state, paused: false }
     }
 }
 
 impl<F> DiskManagerContext<F> {
-    pub fn new(out: Sender<ODiskMessage>, fs: F) -> DiskManagerContext<F> {
-        DiskManagerContext{ torrents: Arc::new(RwLock::new(HashMap::new())), out: out, fs: Arc::new(fs) }
+    pub fn new(out: Sender<ODiskMessage>, block_out: Sender<ODiskMessage>, fs: F, verifier: Arc<PieceVerifier + Send + Sync>,
+               metrics: Arc<Metrics>, pre_open: bool, verbose: bool, chunk_size: usize) -> DiskManagerContext<F> {
+        DiskManagerContext{ torrents: Arc::new(RwLock::new(HashMap::new())), checking: Arc::new(Mutex::new(HashMap::new())), out: out, block_out: block_out, fs: Arc::new(fs),
+                            verifier: verifier, metrics: metrics, mismatches: Arc::new(AtomicUsize::new(0)),
+                            readers: Arc::new(Mutex::new(HashMap::new())), next_reader: Arc::new(AtomicUsize::new(0)),
+                            pre_open: pre_open, verbose: verbose,
+                            chunk_size: chunk_size }
     }
 
+    /// Sender for control messages (torrent and piece state changes).
     pub fn blocking_sender(&self) -> Wait<Sender<ODiskMessage>> {
         self.out.clone().wait()
     }
 
+    /// Sender for block messages (loaded or processed blocks, and their errors).
+    pub fn blocking_block_sender(&self) -> Wait<Sender<ODiskMessage>> {
+        self.block_out.clone().wait()
+    }
+
     pub fn filesystem(&self) -> &F {
         &self.fs
     }
 
+    pub fn verifier(&self) -> &(PieceVerifier + Send + Sync) {
+        &*self.verifier
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &*self.metrics
+    }
+
+    /// Whether or not all files should be opened when a torrent is added.
+    pub fn pre_open_files(&self) -> bool {
+        self.pre_open
+    }
+
+    /// Whether or not hash values should be reported for verified pieces.
+    pub fn verbose_verification(&self) -> bool {
+        self.verbose
+    }
+
+    /// Number of bytes read at a time when hashing a piece.
+    pub fn hash_chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Record that a block failed its checksum.
+    pub fn add_checksum_mismatch(&self) {
+        self.mismatches.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn checksum_mismatches(&self) -> usize {
+        self.mismatches.load(Ordering::SeqCst)
+    }
+
+    /// Allocate a new id for a reader to park under.
+    pub fn new_reader(&self) -> usize {
+        self.next_reader.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Register the current task under the given reader id, to be woken up the next time piece states change.
+    pub fn park_reader(&self, reader: usize) {
+        self.readers.lock()
+            .expect("bip_disk: DiskManagerContext::park_reader Failed To Lock Readers")
+            .insert(reader, task::current());
+    }
+
+    /// Stop waiting on piece states to change for the given reader id.
+    pub fn unpark_reader(&self, reader: usize) {
+        self.readers.lock()
+            .expect("bip_disk: DiskManagerContext::unpark_reader Failed To Lock Readers")
+            .remove(&reader);
+    }
+
+    /// Wake up all tasks waiting on piece states to change.
+    pub fn notify_readers(&self) {
+        let readers = {
+            let mut lock_readers = self.readers.lock()
+                .expect("bip_disk: DiskManagerContext::notify_readers Failed To Lock Readers");
+
+            mem::replace(&mut *lock_readers, HashMap::new())
+        };
+
+        for (_, reader) in readers {
+            reader.notify();
+        }
+    }
+
+    /// Register the initial check for the torrent, returning the flag that will be set if it is removed during the check.
+    ///
+    /// Every check gets a fresh flag, so a torrent re-added while a cancelled check is still running is not cancelled,
+    /// while removing the torrent cancels every check that is still running for it.
+    pub fn begin_check(&self, hash: InfoHash) -> Arc<AtomicBool> {
+        let mut lock_checking = self.checking.lock()
+            .expect("bip_disk: DiskManagerContext::begin_check Failed To Lock Checking");
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        lock_checking.entry(hash).or_insert_with(Vec::new).push(cancel.clone());
+
+        cancel
+    }
+
+    /// Insert the torrent whose initial check has finished, unless it was removed during the check.
+    ///
+    /// Returns None if the check was cancelled, otherwise whether or not the torrent was inserted.
+    pub fn finish_check(&self, file: Metainfo, state: PieceCheckerState, cancel: &Arc<AtomicBool>) -> Option<bool> {
+        let mut lock_checking = self.checking.lock()
+            .expect("bip_disk: DiskManagerContext::finish_check Failed To Lock Checking");
+
+        let hash = file.info().info_hash();
+        remove_check(&mut lock_checking, hash, cancel);
+
+        if cancel.load(Ordering::SeqCst) {
+            None
+        } else {
+            Some(self.insert_torrent(file, state))
+        }
+    }
+
+    /// Unregister the initial check for the torrent, if it failed before it could finish.
+    pub fn end_check(&self, hash: InfoHash, cancel: &Arc<AtomicBool>) {
+        let mut lock_checking = self.checking.lock()
+            .expect("bip_disk: DiskManagerContext::end_check Failed To Lock Checking");
+
+        remove_check(&mut lock_checking, hash, cancel);
+    }
+
     pub fn insert_torrent(&self, file: Metainfo, state: PieceCheckerState) -> bool {
         let mut write_torrents = self.torrents.write()
             .expect("bip_disk: DiskManagerContext::insert_torrents Failed To Write Torrent");
@@ -54,8 +189,47 @@ impl<F> DiskManagerContext<F> {
         hash_not_exists
     }
 
+    /// Set whether or not block IO for the torrent is paused.
+    pub fn set_paused(&self, hash: InfoHash, paused: bool) -> bool {
+        let read_torrents = self.torrents.read()
+            .expect("bip_disk: DiskManagerContext::set_paused Failed To Read Torrent");
+
+        match read_torrents.get(&hash) {
+            Some(state) => {
+                state.lock()
+                    .expect("bip_disk: DiskManagerContext::set_paused Failed To Lock State")
+                    .paused = paused;
+
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Run the given closure with the torrent, if it is not paused.
+    ///
+    /// Returns None if the torrent was not found, otherwise whether or not the closure was run.
+    pub fn update_active_torrent<C>(&self, hash: InfoHash, call: C) -> Option<bool>
+        where C: FnOnce(&Metainfo, Option<&Path>, &mut PieceCheckerState) {
+        let read_torrents = self.torrents.read()
+            .expect("bip_disk: DiskManagerContext::update_active_torrent Failed To Read Torrent");
+
+        read_torrents.get(&hash).map(|state| {
+            let mut lock_state = state.lock()
+                .expect("bip_disk: DiskManagerContext::update_active_torrent Failed To Lock State");
+            let deref_state = &mut *lock_state;
+
+            if !deref_state.paused {
+                call(&deref_state.file, deref_state.directory.as_ref().map(PathBuf::as_path), &mut deref_state.state);
+            }
+
+            !deref_state.paused
+        })
+    }
+
+    /// Run the given closure with the torrent, the directory its files are currently in, and its piece state.
     pub fn update_torrent<C>(&self, hash: InfoHash, call: C) -> bool
-        where C: FnOnce(&Metainfo, &mut PieceCheckerState) {
+        where C: FnOnce(&Metainfo, Option<&Path>, &mut PieceCheckerState) {
         let read_torrents = self.torrents.read()
             .expect("bip_disk: DiskManagerContext::update_torrent Failed To Read Torrent");
 
@@ -65,7 +239,7 @@ impl<F> DiskManagerContext<F> {
                     .expect("bip_disk: DiskManagerContext::update_torrent Failed To Lock State");
                 let deref_state = &mut *lock_state;
 
-                call(&deref_state.file, &mut deref_state.state);
+                call(&deref_state.file, deref_state.directory.as_ref().map(PathBuf::as_path), &mut deref_state.state);
 
                 true
             },
@@ -73,18 +247,77 @@ impl<F> DiskManagerContext<F> {
         }
     }
 
+    /// Run the given closure with the torrent, and the directory its files are currently in.
+    ///
+    /// If the closure returns a new directory, the torrent files will be mapped to that directory.
+    pub fn relocate_torrent<C>(&self, hash: InfoHash, call: C) -> bool
+        where C: FnOnce(&Metainfo, Option<&Path>) -> Option<PathBuf> {
+        let read_torrents = self.torrents.read()
+            .expect("bip_disk: DiskManagerContext::relocate_torrent Failed To Read Torrent");
+
+        match read_torrents.get(&hash) {
+            Some(state) => {
+                let mut lock_state = state.lock()
+                    .expect("bip_disk: DiskManagerContext::relocate_torrent Failed To Lock State");
+                let deref_state = &mut *lock_state;
+
+                let opt_new_directory = call(&deref_state.file, deref_state.directory.as_ref().map(PathBuf::as_path));
+                if let Some(new_directory) = opt_new_directory {
+                    deref_state.directory = Some(new_directory);
+                }
+
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Remove the torrent, cancelling its initial check if it is still being added.
     pub fn remove_torrent(&self, hash: InfoHash) -> bool {
+        // Hold the checking lock throughout, so a check cant finish between us looking for the torrent and the check
+        let lock_checking = self.checking.lock()
+            .expect("bip_disk: DiskManagerContext::remove_torrent Failed To Lock Checking");
         let mut write_torrents = self.torrents.write()
             .expect("bip_disk: DiskManagerContext::remove_torrent Failed To Write Torrent");
 
-        write_torrents.remove(&hash)
-            .map(|_| true)
-            .unwrap_or(false)
+        let removed = write_torrents.remove(&hash).is_some();
+        let cancelled = match lock_checking.get(&hash) {
+            Some(cancels) => {
+                for cancel in cancels.iter() {
+                    cancel.store(true, Ordering::SeqCst);
+                }
+
+                true
+            },
+            None => false
+        };
+
+        removed || cancelled
+    }
+}
+
+/// Unregister the given check for the torrent, leaving any other checks for it registered.
+fn remove_check(checking: &mut HashMap<InfoHash, Vec<Arc<AtomicBool>>>, hash: InfoHash, cancel: &Arc<AtomicBool>) {
+    let is_empty = match checking.get_mut(&hash) {
+        Some(cancels) => {
+            cancels.retain(|current| !Arc::ptr_eq(current, cancel));
+
+            cancels.is_empty()
+        },
+        None => false
+    };
+
+    if is_empty {
+        checking.remove(&hash);
     }
 }
 
 impl<F> Clone for DiskManagerContext<F> {
     fn clone(&self) -> DiskManagerContext<F> {
-        DiskManagerContext{ torrents: self.torrents.clone(), out: self.out.clone(), fs: self.fs.clone() }
+        DiskManagerContext{ torrents: self.torrents.clone(), checking: self.checking.clone(), out: self.out.clone(), block_out: self.block_out.clone(),
+                            fs: self.fs.clone(), verifier: self.verifier.clone(), metrics: self.metrics.clone(),
+                            mismatches: self.mismatches.clone(), readers: self.readers.clone(),
+                            next_reader: self.next_reader.clone(), pre_open: self.pre_open,
+                            verbose: self.verbose, chunk_size: self.chunk_size }
     }
 }
\ No newline at end of file
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use disk::ODiskMessage;
+
+use bip_util::bt::InfoHash;
+use futures::sink::Wait;
+use futures::sync::mpsc::Sender;
+
+/// Batch of outgoing messages generated while executing a single task.
+///
+/// Messages are sent together, with a single flush, instead of flushing the
+/// sender for every message. Piece state messages for the same piece are
+/// coalesced, so only the latest state for a piece is sent out.
+pub struct MessageBatch {
+    messages: Vec<ODiskMessage>,
+    pieces:   HashMap<(InfoHash, u64), usize>
+}
+
+impl MessageBatch {
+    pub fn new() -> MessageBatch {
+        MessageBatch{ messages: Vec::new(), pieces: HashMap::new() }
+    }
+
+    /// Add the message to the batch, replacing any earlier state for the same piece.
+    pub fn push(&mut self, message: ODiskMessage) {
+        let opt_key = piece_key(&message);
+        let opt_existing = opt_key.and_then(|key| self.pieces.get(&key).cloned());
+
+        match (opt_existing, opt_key) {
+            (Some(index), _)  => self.messages[index] = message,
+            (None, Some(key)) => {
+                self.pieces.insert(key, self.messages.len());
+                self.messages.push(message);
+            },
+            (None, None)      => self.messages.push(message)
+        }
+    }
+
+    /// Add all messages from the other batch to this batch.
+    pub fn append(&mut self, other: MessageBatch) {
+        for message in other.messages {
+            self.push(message);
+        }
+    }
+
+    /// Send all messages in the batch, flushing the sender once.
+    pub fn send_all(self, sender: &mut Wait<Sender<ODiskMessage>>) {
+        if self.messages.is_empty() {
+            return
+        }
+
+        for message in self.messages {
+            sender.send(message)
+                .expect("bip_disk: Failed To Send Batched Message");
+        }
+
+        sender.flush()
+            .expect("bip_disk: Failed To Flush Batched Messages");
+    }
+}
+
+fn piece_key(message: &ODiskMessage) -> Option<(InfoHash, u64)> {
+    match message {
+        &ODiskMessage::FoundGoodPiece(hash, index) |
+        &ODiskMessage::FoundBadPiece(hash, index)  => Some((hash, index)),
+        _                                          => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MessageBatch;
+    use disk::ODiskMessage;
+
+    use bip_util::bt::{self, InfoHash};
+    use futures::stream::Stream;
+    use futures::sink::Sink;
+    use futures::sync::mpsc;
+
+    fn collect_batch(batch: MessageBatch) -> Vec<ODiskMessage> {
+        let (send, recv) = mpsc::channel(10);
+
+        batch.send_all(&mut send.wait());
+
+        recv.wait().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn positive_coalesce_same_piece() {
+        let hash: InfoHash = [1u8; bt::INFO_HASH_LEN].into();
+        let mut batch = MessageBatch::new();
+
+        batch.push(ODiskMessage::FoundBadPiece(hash, 0));
+        batch.push(ODiskMessage::FoundGoodPiece(hash, 1));
+        batch.push(ODiskMessage::FoundGoodPiece(hash, 0));
+
+        let messages = collect_batch(batch);
+
+        assert_eq!(2, messages.len());
+        match (&messages[0], &messages[1]) {
+            (&ODiskMessage::FoundGoodPiece(_, 0), &ODiskMessage::FoundGoodPiece(_, 1)) => (),
+            _ => panic!("bip_disk: Piece Messages Were Not Coalesced")
+        }
+    }
+
+    #[test]
+    fn positive_keep_different_torrents() {
+        let hash_one: InfoHash = [1u8; bt::INFO_HASH_LEN].into();
+        let hash_two: InfoHash = [2u8; bt::INFO_HASH_LEN].into();
+        let mut batch = MessageBatch::new();
+
+        batch.push(ODiskMessage::FoundGoodPiece(hash_one, 0));
+        batch.push(ODiskMessage::FoundGoodPiece(hash_two, 0));
+        batch.push(ODiskMessage::TorrentSynced(hash_one));
+
+        assert_eq!(3, collect_batch(batch).len());
+    }
+
+    #[test]
+    fn positive_coalesce_appended_batch() {
+        let hash: InfoHash = [1u8; bt::INFO_HASH_LEN].into();
+        let mut batch = MessageBatch::new();
+        let mut other_batch = MessageBatch::new();
+
+        batch.push(ODiskMessage::FoundBadPiece(hash, 0));
+        other_batch.push(ODiskMessage::FoundGoodPiece(hash, 0));
+        other_batch.push(ODiskMessage::FoundGoodPiece(hash, 1));
+        batch.append(other_batch);
+
+        let messages = collect_batch(batch);
+
+        assert_eq!(2, messages.len());
+        match (&messages[0], &messages[1]) {
+            (&ODiskMessage::FoundGoodPiece(_, 0), &ODiskMessage::FoundGoodPiece(_, 1)) => (),
+            _ => panic!("bip_disk: Appended Piece Messages Were Not Coalesced")
+        }
+    }
+}
@@ -2,6 +2,7 @@ use std::path::{Path, PathBuf};
 
 use bip_metainfo::File;
 
+pub mod batch;
 pub mod piece_accessor;
 pub mod piece_checker;
 
@@ -1,47 +1,74 @@
 use std::collections::{HashMap, HashSet};
 use std::cmp;
 use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use disk::tasks::helpers::piece_accessor::PieceAccessor;
 use disk::fs::{FileSystem};
+use disk::verify::{PieceVerifier, PieceVerification};
+use disk::summary::TorrentSummary;
 use memory::block::BlockMetadata;
 use error::{TorrentResult, TorrentError, TorrentErrorKind};
 use disk::tasks::helpers;
 
 use bip_metainfo::{Info};
-use bip_util::bt::InfoHash;
+use bip_util::sha::ShaHashBuilder;
 
-/// Calculates hashes on existing files within the file system given and reports good/bad pieces.
+/// Verifies pieces on existing files within the file system given and reports good/bad pieces.
 pub struct PieceChecker<'a, F> {
     fs:            F,
+    verifier:      &'a PieceVerifier,
+    directory:     Option<&'a Path>,
     info_dict:     &'a Info,
-    checker_state: &'a mut PieceCheckerState
+    checker_state: &'a mut PieceCheckerState,
+    opt_cancel:    Option<&'a AtomicBool>
 }
 
 impl<'a, F> PieceChecker<'a, F> where F: FileSystem + 'a {
-    /// Create the initial PieceCheckerState for the PieceChecker.
-    pub fn init_state(fs: F, info_dict: &'a Info) -> TorrentResult<PieceCheckerState> {
+    /// Create the initial PieceCheckerState for the PieceChecker, as well as a summary of the initial check.
+    ///
+    /// If record hashes is set, a `PieceVerification` will be recorded for every piece verified with the state.
+    /// Pieces will be read chunk size bytes at a time, if the verifier can verify from the hash of a piece.
+    ///
+    /// The check is stopped between pieces if the cancel flag is set, failing with `CheckCancelled`.
+    pub fn init_state(fs: F, verifier: &'a PieceVerifier, info_dict: &'a Info, record_hashes: bool, chunk_size: usize,
+                      cancel: &'a AtomicBool) -> TorrentResult<(PieceCheckerState, TorrentSummary)> {
         let total_blocks = info_dict.pieces().count();
         let last_piece_size = last_piece_size(info_dict);
 
-        let mut checker_state = PieceCheckerState::new(total_blocks, last_piece_size);
-        {
-            let mut piece_checker = PieceChecker::with_state(fs, info_dict, &mut checker_state);
+        let mut checker_state = PieceCheckerState::new(total_blocks, last_piece_size, record_hashes, chunk_size);
+        let created_files = {
+            let mut piece_checker = PieceChecker::with_state(fs, verifier, info_dict.directory(), info_dict, &mut checker_state);
+            piece_checker.opt_cancel = Some(cancel);
             
-            try!(piece_checker.validate_files_sizes());
+            let created_files = try!(piece_checker.validate_files_sizes());
             try!(piece_checker.fill_checker_state());
-            try!(piece_checker.calculate_diff());
-        }
+            let diff_result = piece_checker.calculate_diff();
+
+            // Cancelling shows up as an io error from the diff, so check for it first
+            if cancel.load(Ordering::SeqCst) {
+                return Err(TorrentError::from_kind(TorrentErrorKind::CheckCancelled{ hash: info_dict.info_hash() }))
+            }
+            try!(diff_result);
+
+            created_files
+        };
+        let summary = torrent_summary(info_dict, &checker_state.new_states, &created_files);
 
-        Ok(checker_state)
+        Ok((checker_state, summary))
     }
 
     /// Create a new PieceChecker with the given state.
-    pub fn with_state(fs: F, info_dict: &'a Info, checker_state: &'a mut PieceCheckerState) -> PieceChecker<'a, F> {
+    pub fn with_state(fs: F, verifier: &'a PieceVerifier, directory: Option<&'a Path>, info_dict: &'a Info,
+                      checker_state: &'a mut PieceCheckerState) -> PieceChecker<'a, F> {
         PieceChecker {
             fs:            fs,
+            verifier:      verifier,
+            directory:     directory,
             info_dict:     info_dict,
-            checker_state: checker_state
+            checker_state: checker_state,
+            opt_cancel:    None
         }
     }
 
@@ -49,24 +76,67 @@ impl<'a, F> PieceChecker<'a, F> where F: FileSystem + 'a {
     /// to be retrieved by the caller.
     pub fn calculate_diff(self) -> io::Result<()> {
         let piece_length = self.info_dict.piece_length() as u64;
+        // Only need room for a single chunk if we can hash the piece as we read it
+        let hash_chunks = self.verifier.verifies_hash();
+        let buffer_length = if hash_chunks {
+            cmp::min(self.checker_state.chunk_size as u64, piece_length)
+        } else {
+            piece_length
+        };
         // TODO: Use Block Allocator
-        let mut piece_buffer = vec![0u8; piece_length as usize];
+        let mut piece_buffer = vec![0u8; buffer_length as usize];
 
-        let info_dict = self.info_dict;
-        let piece_accessor = PieceAccessor::new(&self.fs, self.info_dict);
+        let (info_dict, verifier) = (self.info_dict, self.verifier);
+        let piece_accessor = PieceAccessor::new(&self.fs, self.directory, self.info_dict);
+        
+        let record_hashes = self.checker_state.record_hashes;
+        let opt_cancel = self.opt_cancel;
         
         try!(self.checker_state.run_with_whole_pieces(piece_length as usize, |message| {
-            try!(piece_accessor.read_piece(&mut piece_buffer[..message.block_length()], message));
-            
-            let calculated_hash = InfoHash::from_bytes(&piece_buffer[..message.block_length()]);
-            let expected_hash = InfoHash::from_hash(info_dict
-                .pieces()
-                .skip(message.piece_index() as usize)
-                .next()
-                .expect("bip_peer: Piece Checker Failed To Retrieve Expected Hash"))
-                .expect("bip_peer: Wrong Length Of Expected Hash Received");
+            if opt_cancel.map(|cancel| cancel.load(Ordering::SeqCst)).unwrap_or(false) {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "Piece Check Cancelled"))
+            }
+            // Make sure every block of the piece has made it to disk before we verify it
+            try!(piece_accessor.flush_piece(message));
+
+            if hash_chunks {
+                let mut hash_builder = ShaHashBuilder::new();
+                let mut chunk_offset = 0;
+
+                while chunk_offset < message.block_length() {
+                    let chunk_length = cmp::min(piece_buffer.len(), message.block_length() - chunk_offset);
+                    let chunk_message = BlockMetadata::new(message.info_hash(), message.piece_index(),
+                                                           message.block_offset() + chunk_offset as u64, chunk_length);
+
+                    let chunk_data = &mut piece_buffer[..chunk_length];
+                    try!(piece_accessor.read_piece(chunk_data, &chunk_message));
+
+                    hash_builder = hash_builder.add_bytes(chunk_data);
+                    chunk_offset += chunk_length;
+                }
+                let piece_hash = hash_builder.build();
+
+                let is_good = verifier.verify_hash(info_dict, message.piece_index(), piece_hash);
+                let opt_verification = if record_hashes {
+                    Some(PieceVerification::with_hash(info_dict, message.piece_index(), piece_hash, is_good))
+                } else {
+                    None
+                };
+
+                Ok((is_good, opt_verification))
+            } else {
+                let piece_data = &mut piece_buffer[..message.block_length()];
+                try!(piece_accessor.read_piece(piece_data, message));
                 
-            Ok(calculated_hash == expected_hash)
+                let is_good = verifier.verify(info_dict, message.piece_index(), piece_data);
+                let opt_verification = if record_hashes {
+                    Some(PieceVerification::new(info_dict, message.piece_index(), piece_data, is_good))
+                } else {
+                    None
+                };
+
+                Ok((is_good, opt_verification))
+            }
         }));
 
         Ok(())
@@ -100,12 +170,16 @@ impl<'a, F> PieceChecker<'a, F> where F: FileSystem + 'a {
     /// Otherwise, if the file exists and it is of the correct size, it will be left alone. If it is of the wrong
     /// size, an error will be thrown as we do not want to overwrite and existing file that maybe just had the same
     /// name as a file in our dictionary.
-    fn validate_files_sizes(&mut self) -> TorrentResult<()> {
+    ///
+    /// Returns whether or not each file in the info dictionary had to be created.
+    fn validate_files_sizes(&mut self) -> TorrentResult<Vec<bool>> {
+        let mut created_files = Vec::new();
+
         for file in self.info_dict.files() {
-            let file_path = helpers::build_path(self.info_dict.directory(), file);
+            let file_path = helpers::build_path(self.directory, file);
             let expected_size = file.length() as u64;
 
-            try!(self.fs.open_file(file_path.clone())
+            let created = try!(self.fs.open_file(file_path.clone())
                 .map_err(|err| err.into())
                 .and_then(|mut file| {
                 // File May Or May Not Have Existed Before, If The File Is Zero
@@ -118,6 +192,8 @@ impl<'a, F> PieceChecker<'a, F> where F: FileSystem + 'a {
                 if !size_matches && size_is_zero {
                     self.fs.write_file(&mut file, expected_size - 1, &[0])
                         .expect("bip_peer: Failed To Create File When Validating Sizes");
+
+                    return Ok(true)
                 } else if !size_matches {
                     return Err(TorrentError::from_kind(TorrentErrorKind::ExistingFileSizeCheck{
                         file_path: file_path,
@@ -126,11 +202,13 @@ impl<'a, F> PieceChecker<'a, F> where F: FileSystem + 'a {
                     }))
                 }
                 
-                Ok(())
+                Ok(false)
             }));
+
+            created_files.push(created);
         }
 
-        Ok(())
+        Ok(created_files)
     }
 }
 
@@ -141,6 +219,67 @@ fn last_piece_size(info_dict: &Info) -> usize {
     (total_bytes % piece_length) as usize
 }
 
+/// Summarize the initial piece states of a torrent.
+///
+/// Bad pieces lying entirely within files that had to be created are counted as missing.
+fn torrent_summary(info_dict: &Info, piece_states: &[PieceState], created_files: &[bool]) -> TorrentSummary {
+    let piece_length = info_dict.piece_length() as u64;
+    let total_bytes: u64 = info_dict.files().map(|file| file.length() as u64).sum();
+    let total_pieces = info_dict.pieces().count() as u64;
+
+    // Byte ranges (within the whole torrent) of the files we had to create
+    let mut created_ranges = Vec::new();
+    let mut file_start = 0;
+    for (file, &created) in info_dict.files().zip(created_files.iter()) {
+        let file_end = file_start + file.length() as u64;
+
+        if created {
+            created_ranges.push((file_start, file_end));
+        }
+        file_start = file_end;
+    }
+
+    let (mut good_pieces, mut bad_pieces, mut missing_pieces, mut bytes_present) = (0, 0, 0, 0);
+    for piece_state in piece_states {
+        match piece_state {
+            &PieceState::Good(index) => {
+                let piece_start = index * piece_length;
+                let piece_end = cmp::min(piece_start + piece_length, total_bytes);
+
+                good_pieces += 1;
+                bytes_present += piece_end - piece_start;
+            },
+            &PieceState::Bad(index) => {
+                let piece_start = index * piece_length;
+                let piece_end = cmp::min(piece_start + piece_length, total_bytes);
+
+                if range_is_covered(&created_ranges, piece_start, piece_end) {
+                    missing_pieces += 1;
+                } else {
+                    bad_pieces += 1;
+                }
+            }
+        }
+    }
+    // Any pieces we did not get to check can't be present
+    missing_pieces += total_pieces.saturating_sub(good_pieces + bad_pieces + missing_pieces);
+
+    TorrentSummary::new(good_pieces, bad_pieces, missing_pieces, bytes_present, created_files.iter().filter(|&&created| created).count())
+}
+
+/// True if the range from start to end is covered by the given (sorted) ranges.
+fn range_is_covered(ranges: &[(u64, u64)], start: u64, end: u64) -> bool {
+    let mut covered_to = start;
+
+    for &(range_start, range_end) in ranges {
+        if range_start <= covered_to && covered_to < range_end {
+            covered_to = range_end;
+        }
+    }
+
+    covered_to >= end
+}
+
 // ----------------------------------------------------------------------------//
 
 /// Stores state for the PieceChecker between invocations.
@@ -148,6 +287,9 @@ pub struct PieceCheckerState {
     new_states:      Vec<PieceState>,
     old_states:      HashSet<PieceState>,
     pending_blocks:  HashMap<u64, Vec<BlockMetadata>>,
+    verifications:   HashMap<u64, PieceVerification>,
+    record_hashes:   bool,
+    chunk_size:      usize,
     total_blocks:    usize,
     last_block_size: usize
 }
@@ -162,27 +304,37 @@ pub enum PieceState {
 
 impl PieceCheckerState {
     /// Create a new PieceCheckerState.
-    pub fn new(total_blocks: usize, last_block_size: usize) -> PieceCheckerState {
+    pub fn new(total_blocks: usize, last_block_size: usize, record_hashes: bool, chunk_size: usize) -> PieceCheckerState {
         PieceCheckerState {
             new_states: Vec::new(),
             old_states: HashSet::new(),
             pending_blocks: HashMap::new(),
+            verifications: HashMap::new(),
+            record_hashes: record_hashes,
+            chunk_size: chunk_size,
             total_blocks: total_blocks,
             last_block_size: last_block_size
         }
     }
 
+    /// Whether or not the given piece has been identified as good.
+    pub fn is_good(&self, index: u64) -> bool {
+        self.old_states.contains(&PieceState::Good(index)) || self.new_states.contains(&PieceState::Good(index))
+    }
+
     /// Add a pending piece block to the current pending blocks.
     pub fn add_pending_block(&mut self, msg: BlockMetadata) {
         self.pending_blocks.entry(msg.piece_index()).or_insert(Vec::new()).push(msg);
     }
     
-    /// Run the given closures against NewGood and NewBad messages. Each of the messages will
-    /// then either be dropped (NewBad) or converted to OldGood (NewGood).
+    /// Run the given closures against NewGood and NewBad messages, along with the recorded
+    /// verification for the piece, if any. Each of the messages will then either be dropped
+    /// (NewBad) or converted to OldGood (NewGood).
     pub fn run_with_diff<F>(&mut self, mut callback: F)
-        where F: FnMut(&PieceState) {
+        where F: FnMut(&PieceState, Option<PieceVerification>) {
         for piece_state in self.new_states.drain(..) {
-            callback(&piece_state);
+            let index = match piece_state { PieceState::Good(index) | PieceState::Bad(index) => index };
+            callback(&piece_state, self.verifications.remove(&index));
 
             self.old_states.insert(piece_state);
         }
@@ -191,11 +343,12 @@ impl PieceCheckerState {
     /// Pass any pieces that have not been identified as OldGood into the callback which determines
     /// if the piece is good or bad so it can be marked as NewGood or NewBad.
     fn run_with_whole_pieces<F>(&mut self, piece_length: usize, mut callback: F) -> io::Result<()>
-        where F: FnMut(&BlockMetadata) -> io::Result<bool> {
+        where F: FnMut(&BlockMetadata) -> io::Result<(bool, Option<PieceVerification>)> {
         self.merge_pieces();
 
         let new_states = &mut self.new_states;
         let old_states = &self.old_states;
+        let verifications = &mut self.verifications;
 
         let total_blocks = self.total_blocks;
         let last_block_size = self.last_block_size;
@@ -203,7 +356,11 @@ impl PieceCheckerState {
         for messages in self.pending_blocks.values_mut()
             .filter(|ref messages| piece_is_complete(total_blocks, last_block_size, piece_length, messages))
             .filter(|ref messages| !old_states.contains(&PieceState::Good(messages[0].piece_index()))) {
-            let is_good = try!(callback(&messages[0]));
+            let (is_good, opt_verification) = try!(callback(&messages[0]));
+
+            if let Some(verification) = opt_verification {
+                verifications.insert(messages[0].piece_index(), verification);
+            }
 
             if is_good {
                 new_states.push(PieceState::Good(messages[0].piece_index()));
@@ -364,5 +521,17 @@ mod tests {
         assert_eq!(expected, merged.unwrap());
     }
 
+    #[test]
+    fn positive_range_is_covered_across_ranges() {
+        let ranges = [(0, 10), (10, 20), (30, 40)];
+
+        assert!(super::range_is_covered(&ranges, 5, 20));
+    }
 
+    #[test]
+    fn negative_range_is_covered_with_gap() {
+        let ranges = [(0, 10), (10, 20), (30, 40)];
+
+        assert!(!super::range_is_covered(&ranges, 15, 35));
+    }
 }
\ No newline at end of file
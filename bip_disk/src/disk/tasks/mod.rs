@@ -1,39 +1,66 @@
+use std::cmp;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
 use disk::fs::FileSystem;
+use disk::summary::TorrentSummary;
 use disk::{IDiskMessage, ODiskMessage};
 use disk::tasks::helpers::piece_checker::{PieceChecker, PieceCheckerState, PieceState};
 use disk::tasks::helpers::piece_accessor::PieceAccessor;
+use disk::tasks::helpers::batch::MessageBatch;
 use disk::tasks::context::DiskManagerContext;
-use memory::block::{Block, BlockMut};
+use memory::block::{Block, BlockMut, BlockMetadata};
+use memory::checksum;
 use error::{TorrentResult, BlockResult, BlockError, BlockErrorKind, TorrentError, TorrentErrorKind};
 
-use bip_metainfo::Metainfo;
+use bip_metainfo::{Metainfo, Info};
 use bip_util::bt::InfoHash;
-use futures::sink::Wait;
-use futures::sync::mpsc::Sender;
-use futures_cpupool::CpuPool;
+use bytes::Bytes;
+use futures_cpupool::{CpuPool, CpuFuture};
 
 pub mod context;
 mod helpers;
 
+const BLOCKS_READ_METRIC:    &'static str = "bip_disk_blocks_read";
+const BLOCKS_WRITTEN_METRIC: &'static str = "bip_disk_blocks_written";
+const BYTES_WRITTEN_METRIC:  &'static str = "bip_disk_bytes_written";
+
 pub fn execute_on_pool<F>(msg: IDiskMessage, pool: &CpuPool, context: DiskManagerContext<F>)
     where F: FileSystem + Send + Sync + 'static {
+    // Register the check before handing off the task, so a removal sent right after will always find it
+    let opt_cancel = match msg {
+        IDiskMessage::AddTorrent(ref metainfo) => Some(context.begin_check(metainfo.info().info_hash())),
+        _                                      => None
+    };
+
     pool.spawn_fn(move || {
-        let mut blocking_sender = context.blocking_sender();
+        // Control messages generated by the task are sent together, with a single flush
+        let mut batch = MessageBatch::new();
 
         let out_msg = match msg {
             IDiskMessage::AddTorrent(metainfo) => {
                 let info_hash = metainfo.info().info_hash();
+                let cancel = opt_cancel.expect("bip_disk: Failed To Register Check In execute_on_pool");
                 
-                match execute_add_torrent(metainfo, &context, &mut blocking_sender) {
-                    Ok(_)    => ODiskMessage::TorrentAdded(info_hash),
-                    Err(err) => ODiskMessage::TorrentError(info_hash, err)
-                }
+                let out_msg = match execute_add_torrent(metainfo, &context, &cancel, &mut batch) {
+                    Ok(summary) => ODiskMessage::TorrentAdded(info_hash, summary),
+                    Err(err)    => ODiskMessage::TorrentError(info_hash, err)
+                };
+                context.end_check(info_hash, &cancel);
+                context.notify_readers();
+
+                out_msg
             },
             IDiskMessage::RemoveTorrent(hash) => {
-                match execute_remove_torrent(hash, &context) {
+                let out_msg = match execute_remove_torrent(hash, &context) {
                     Ok(_)    => ODiskMessage::TorrentRemoved(hash),
                     Err(err) => ODiskMessage::TorrentError(hash, err)
-                }
+                };
+                // Readers waiting on the torrent should find out that it was removed
+                context.notify_readers();
+
+                out_msg
             },
             IDiskMessage::SyncTorrent(hash) => {
                 match execute_sync_torrent(hash, &context) {
@@ -41,6 +68,24 @@ pub fn execute_on_pool<F>(msg: IDiskMessage, pool: &CpuPool, context: DiskManage
                     Err(err) => ODiskMessage::TorrentError(hash, err)
                 }
             },
+            IDiskMessage::MoveTorrent(hash, directory) => {
+                match execute_move_torrent(hash, directory, &context) {
+                    Ok(_)    => ODiskMessage::TorrentMoved(hash),
+                    Err(err) => ODiskMessage::TorrentError(hash, err)
+                }
+            },
+            IDiskMessage::PauseTorrent(hash) => {
+                match execute_pause_torrent(hash, true, &context) {
+                    Ok(_)    => ODiskMessage::TorrentPaused(hash),
+                    Err(err) => ODiskMessage::TorrentError(hash, err)
+                }
+            },
+            IDiskMessage::ResumeTorrent(hash) => {
+                match execute_pause_torrent(hash, false, &context) {
+                    Ok(_)    => ODiskMessage::TorrentResumed(hash),
+                    Err(err) => ODiskMessage::TorrentError(hash, err)
+                }
+            },
             IDiskMessage::LoadBlock(mut block) => {
                 match execute_load_block(&mut block, &context) {
                     Ok(_)    => ODiskMessage::BlockLoaded(block),
@@ -48,35 +93,105 @@ pub fn execute_on_pool<F>(msg: IDiskMessage, pool: &CpuPool, context: DiskManage
                 }
             },
             IDiskMessage::ProcessBlock(mut block) => {
-                match execute_process_block(&mut block, &context, &mut blocking_sender) {
+                let out_msg = match execute_process_block(&mut block, &context, &mut batch) {
                     Ok(_)    => ODiskMessage::BlockProcessed(block),
                     Err(err) => ODiskMessage::ProcessBlockError(block, err)
-                }
+                };
+                context.notify_readers();
+
+                out_msg
             }
         };
 
-        blocking_sender.send(out_msg)
-            .expect("bip_disk: Failed To Send Out Message In execute_on_pool");
-        blocking_sender.flush()
-            .expect("bip_disk: Failed to Flush Out Messages In execute_on_pool");
+        // Blocks go out on their own lane, so they can't hold up control messages
+        if is_block_message(&out_msg) {
+            batch.send_all(&mut context.blocking_sender());
+
+            let mut block_sender = context.blocking_block_sender();
+            block_sender.send(out_msg)
+                .expect("bip_disk: Failed To Send Out Message In execute_on_pool");
+            block_sender.flush()
+                .expect("bip_disk: Failed to Flush Out Messages In execute_on_pool");
+        } else {
+            batch.push(out_msg);
+            batch.send_all(&mut context.blocking_sender());
+        }
         
         Ok::<(),()>(())
     }).forget()
 }
 
-fn execute_add_torrent<F>(file: Metainfo, context: &DiskManagerContext<F>, blocking_sender: &mut Wait<Sender<ODiskMessage>>) -> TorrentResult<()>
-    where F: FileSystem {
+/// Read the bytes from `offset` up to the end of the piece containing it (or `end`) on the pool.
+///
+/// Resolves to `None` if the piece has not been verified yet, in which case the
+/// current task will stay parked under `reader`, and will be woken up the next time
+/// piece states change. Otherwise, the reader is unparked.
+pub fn read_range_on_pool<F>(hash: InfoHash, offset: u64, end: u64, reader: usize, pool: &CpuPool, context: DiskManagerContext<F>)
+    -> CpuFuture<Option<Bytes>, BlockError>
+    where F: FileSystem + Send + Sync + 'static {
+    // Register before the piece is checked, so we cant miss it becoming good
+    context.park_reader(reader);
+
+    pool.spawn_fn(move || {
+        let result = execute_read_range(hash, offset, end, &context);
+
+        // Only readers waiting on a piece should stick around, otherwise they would leak for complete torrents
+        match result {
+            Ok(None) => (),
+            _        => context.unpark_reader(reader)
+        }
+
+        result
+    })
+}
+
+fn is_block_message(msg: &ODiskMessage) -> bool {
+    match msg {
+        &ODiskMessage::BlockLoaded(_)          |
+        &ODiskMessage::BlockProcessed(_)       |
+        &ODiskMessage::LoadBlockError(_, _)    |
+        &ODiskMessage::ProcessBlockError(_, _) => true,
+        _                                      => false
+    }
+}
+
+fn execute_add_torrent<F>(file: Metainfo, context: &DiskManagerContext<F>, cancel: &Arc<AtomicBool>, batch: &mut MessageBatch)
+    -> TorrentResult<TorrentSummary> where F: FileSystem {
     let info_hash = file.info().info_hash();
-    let mut init_state = try!(PieceChecker::init_state(context.filesystem(), file.info()));
+    if context.pre_open_files() {
+        try!(pre_open_files(context.filesystem(), file.info()));
+    }
+
+    let (mut init_state, summary) = try!(PieceChecker::init_state(context.filesystem(), context.verifier(), file.info(),
+                                                                         context.verbose_verification(), context.hash_chunk_size(),
+                                                                         cancel));
 
     // In case we are resuming a download, we need to send the diff for the newly added torrent
-    send_piece_diff(&mut init_state, info_hash, blocking_sender, true);
+    let mut diff_batch = MessageBatch::new();
+    send_piece_diff(&mut init_state, info_hash, &mut diff_batch, true);
     
-    if context.insert_torrent(file, init_state) {
-        Ok(())
-    } else {
-        Err(TorrentError::from_kind(TorrentErrorKind::ExistingInfoHash{ hash: info_hash }))
+    match context.finish_check(file, init_state, cancel) {
+        Some(true)  => {
+            batch.append(diff_batch);
+
+            Ok(summary)
+        },
+        Some(false) => Err(TorrentError::from_kind(TorrentErrorKind::ExistingInfoHash{ hash: info_hash })),
+        None        => Err(TorrentError::from_kind(TorrentErrorKind::CheckCancelled{ hash: info_hash }))
+    }
+}
+
+/// Open each file in the torrent so that path or permission errors are surfaced before any pieces are checked.
+fn pre_open_files<F>(fs: &F, info: &Info) -> TorrentResult<()>
+    where F: FileSystem {
+    for file in info.files() {
+        let file_path = helpers::build_path(info.directory(), file);
+
+        try!(fs.open_file(file_path.clone())
+            .map_err(|err| TorrentError::with_chain(err, TorrentErrorKind::FileOpen{ file_path: file_path })));
     }
+
+    Ok(())
 }
 
 fn execute_remove_torrent<F>(hash: InfoHash, context: &DiskManagerContext<F>) -> TorrentResult<()>
@@ -93,9 +208,7 @@ fn execute_sync_torrent<F>(hash: InfoHash, context: &DiskManagerContext<F>) -> T
     let filesystem = context.filesystem();
 
     let mut sync_result = Ok(());
-    let found_hash = context.update_torrent(hash, |metainfo_file, _| {
-        let opt_parent_dir = metainfo_file.info().directory();
-
+    let found_hash = context.update_torrent(hash, |metainfo_file, opt_parent_dir, _| {
         for file in metainfo_file.info().files() {
             let path = helpers::build_path(opt_parent_dir, file);
 
@@ -110,60 +223,163 @@ fn execute_sync_torrent<F>(hash: InfoHash, context: &DiskManagerContext<F>) -> T
     }
 }
 
+fn execute_move_torrent<F>(hash: InfoHash, directory: PathBuf, context: &DiskManagerContext<F>) -> TorrentResult<()>
+    where F: FileSystem {
+    let filesystem = context.filesystem();
+
+    let mut move_result = Ok(());
+    let found_hash = context.relocate_torrent(hash, |metainfo_file, opt_parent_dir| {
+        let mut moved_paths = Vec::new();
+
+        for file in metainfo_file.info().files() {
+            let old_path = helpers::build_path(opt_parent_dir, file);
+            let new_path = helpers::build_path(Some(&directory), file);
+
+            match filesystem.move_file(old_path.clone(), new_path.clone()) {
+                Ok(_)    => moved_paths.push((old_path, new_path)),
+                Err(err) => {
+                    // Put back any files we already moved, so the torrent stays in one place
+                    for (old_path, new_path) in moved_paths.into_iter().rev() {
+                        let _ = filesystem.move_file(new_path, old_path);
+                    }
+                    move_result = Err(err);
+
+                    return None
+                }
+            }
+        }
+
+        Some(directory)
+    });
+
+    if found_hash {
+        Ok(try!(move_result))
+    } else {
+        Err(TorrentError::from_kind(TorrentErrorKind::InfoHashNotFound{ hash: hash }))
+    }
+}
+
+fn execute_pause_torrent<F>(hash: InfoHash, paused: bool, context: &DiskManagerContext<F>) -> TorrentResult<()>
+    where F: FileSystem {
+    if context.set_paused(hash, paused) {
+        Ok(())
+    } else {
+        Err(TorrentError::from_kind(TorrentErrorKind::InfoHashNotFound{ hash: hash }))
+    }
+}
+
 fn execute_load_block<F>(block: &mut BlockMut, context: &DiskManagerContext<F>) -> BlockResult<()>
     where F: FileSystem {
     let metadata = block.metadata();
     let info_hash = metadata.info_hash();
 
     let mut access_result = Ok(());
-    let found_hash = context.update_torrent(info_hash, |metainfo_file, _| {
-        let piece_accessor = PieceAccessor::new(context.filesystem(), metainfo_file.info());
+    let opt_active = context.update_active_torrent(info_hash, |metainfo_file, opt_parent_dir, _| {
+        let piece_accessor = PieceAccessor::new(context.filesystem(), opt_parent_dir, metainfo_file.info());
 
         // Read The Piece In From The Filesystem
         access_result = piece_accessor.read_piece(&mut *block, &metadata)
     });
 
-    if found_hash {
-        Ok(try!(access_result))
-    } else {
-        Err(BlockError::from_kind(BlockErrorKind::InfoHashNotFound{ hash: info_hash }))
+    match opt_active {
+        Some(true)  => {
+            try!(access_result);
+            context.metrics().counter(BLOCKS_READ_METRIC, 1);
+
+            Ok(())
+        },
+        Some(false) => Err(BlockError::from_kind(BlockErrorKind::TorrentPaused{ hash: info_hash })),
+        None        => Err(BlockError::from_kind(BlockErrorKind::InfoHashNotFound{ hash: info_hash }))
+    }
+}
+
+fn execute_read_range<F>(hash: InfoHash, offset: u64, end: u64, context: &DiskManagerContext<F>) -> BlockResult<Option<Bytes>>
+    where F: FileSystem {
+    let mut read_result = Ok(None);
+    let opt_active = context.update_active_torrent(hash, |metainfo_file, opt_parent_dir, checker_state| {
+        let info_dict = metainfo_file.info();
+        let total_length = info_dict.files().map(|file| file.length()).sum::<u64>();
+
+        if end > total_length {
+            read_result = Err(BlockError::from_kind(BlockErrorKind::RangeOutOfBounds{ hash: hash, offset: offset, length: end - offset }));
+            return
+        }
+
+        let piece_length = info_dict.piece_length();
+        let piece_index = offset / piece_length;
+
+        if checker_state.is_good(piece_index) {
+            let piece_offset = offset % piece_length;
+            let read_length = cmp::min(piece_length - piece_offset, end - offset);
+
+            let metadata = BlockMetadata::new(hash, piece_index, piece_offset, read_length as usize);
+            let mut buffer = vec![0u8; read_length as usize];
+
+            read_result = PieceAccessor::new(context.filesystem(), opt_parent_dir, info_dict)
+                .read_piece(&mut buffer[..], &metadata)
+                .map(|_| Some(Bytes::from(buffer)))
+                .map_err(BlockError::from);
+        }
+    });
+
+    match opt_active {
+        Some(true)  => read_result,
+        Some(false) => Err(BlockError::from_kind(BlockErrorKind::TorrentPaused{ hash: hash })),
+        None        => Err(BlockError::from_kind(BlockErrorKind::InfoHashNotFound{ hash: hash }))
     }
 }
 
-fn execute_process_block<F>(block: &mut Block, context: &DiskManagerContext<F>, blocking_sender: &mut Wait<Sender<ODiskMessage>>) -> BlockResult<()>
+fn execute_process_block<F>(block: &mut Block, context: &DiskManagerContext<F>, batch: &mut MessageBatch) -> BlockResult<()>
     where F: FileSystem {
     let metadata = block.metadata();
     let info_hash = metadata.info_hash();
 
+    // Make sure the block wasnt corrupted between being received and being handed to us
+    if let Some(expected) = metadata.checksum() {
+        let actual = checksum::adler32(&block[..]);
+
+        if expected != actual {
+            context.add_checksum_mismatch();
+
+            return Err(BlockError::from_kind(BlockErrorKind::ChecksumMismatch{ expected: expected, actual: actual }))
+        }
+    }
+
     let mut block_result = Ok(());
-    let found_hash = context.update_torrent(info_hash, |metainfo_file, mut checker_state| {
+    let opt_active = context.update_active_torrent(info_hash, |metainfo_file, opt_parent_dir, mut checker_state| {
         info!("Processsing Block, Acquired Torrent Lock For {:?}", metainfo_file.info().info_hash());
 
-        let piece_accessor = PieceAccessor::new(context.filesystem(), metainfo_file.info());
+        let piece_accessor = PieceAccessor::new(context.filesystem(), opt_parent_dir, metainfo_file.info());
 
         // Write Out Piece Out To The Filesystem And Recalculate The Diff
         block_result = piece_accessor.write_piece(&block, &metadata)
             .and_then(|_| {
                 checker_state.add_pending_block(metadata);
                 
-                PieceChecker::with_state(context.filesystem(), metainfo_file.info(), &mut checker_state)
+                PieceChecker::with_state(context.filesystem(), context.verifier(), opt_parent_dir, metainfo_file.info(), &mut checker_state)
                     .calculate_diff()
             });
 
-        send_piece_diff(checker_state, metainfo_file.info().info_hash(), blocking_sender, false);
+        send_piece_diff(checker_state, metainfo_file.info().info_hash(), batch, false);
 
         info!("Processsing Block, Released Torrent Lock For {:?}", metainfo_file.info().info_hash());
     });
 
-    if found_hash {
-        Ok(try!(block_result))
-    } else {
-        Err(BlockError::from_kind(BlockErrorKind::InfoHashNotFound{ hash: info_hash }))
+    match opt_active {
+        Some(true)  => {
+            try!(block_result);
+            context.metrics().counter(BLOCKS_WRITTEN_METRIC, 1);
+            context.metrics().counter(BYTES_WRITTEN_METRIC, metadata.block_length() as u64);
+
+            Ok(())
+        },
+        Some(false) => Err(BlockError::from_kind(BlockErrorKind::TorrentPaused{ hash: info_hash })),
+        None        => Err(BlockError::from_kind(BlockErrorKind::InfoHashNotFound{ hash: info_hash }))
     }
 }
 
-fn send_piece_diff(checker_state: &mut PieceCheckerState, hash: InfoHash, blocking_sender: &mut Wait<Sender<ODiskMessage>>, ignore_bad: bool) {
-    checker_state.run_with_diff(|piece_state| {
+fn send_piece_diff(checker_state: &mut PieceCheckerState, hash: InfoHash, batch: &mut MessageBatch, ignore_bad: bool) {
+    checker_state.run_with_diff(|piece_state, opt_verification| {
         let opt_out_msg = match (piece_state, ignore_bad) {
             (&PieceState::Good(index), _)    => Some(ODiskMessage::FoundGoodPiece(hash, index)),
             (&PieceState::Bad(index), false) => Some(ODiskMessage::FoundBadPiece(hash, index)),
@@ -171,10 +387,11 @@ fn send_piece_diff(checker_state: &mut PieceCheckerState, hash: InfoHash, blocki
         };
 
         if let Some(out_msg) = opt_out_msg {
-            blocking_sender.send(out_msg)
-                .expect("bip_disk: Failed To Send Piece State Message");
-            blocking_sender.flush()
-            .expect("bip_disk: Failed To Flush Piece State Message");
+            if let Some(verification) = opt_verification {
+                batch.push(ODiskMessage::PieceVerified(hash, verification));
+            }
+
+            batch.push(out_msg);
         }
     })
 }
\ No newline at end of file
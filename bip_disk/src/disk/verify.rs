@@ -0,0 +1,153 @@
+use bip_metainfo::Info;
+use bip_util::sha::ShaHash;
+
+/// Trait for verifying that the data for a piece is correct.
+///
+/// Implementations are called from the `DiskManager` worker threads whenever
+/// a piece is fully present on the `FileSystem`, so they must be thread safe.
+pub trait PieceVerifier {
+    /// Returns true if the given data is correct for the piece at the given index.
+    ///
+    /// Verifiers that hash the data should implement `verify_hash` instead, so that
+    /// verbose verification can reuse the hash rather than hashing each piece twice.
+    fn verify(&self, info_dict: &Info, piece_index: u64, data: &[u8]) -> bool;
+
+    /// Whether or not pieces can be verified from their SHA-1 hash alone, using `verify_hash`.
+    ///
+    /// If so, pieces will be hashed in chunks as they are read, instead of being read
+    /// entirely into memory. By default, pieces are always passed to `verify`.
+    fn verifies_hash(&self) -> bool {
+        false
+    }
+
+    /// Returns true if the given SHA-1 hash of the data is correct for the piece at the given index.
+    ///
+    /// Only called if `verifies_hash` returns true.
+    fn verify_hash(&self, _info_dict: &Info, _piece_index: u64, _hash: ShaHash) -> bool {
+        false
+    }
+}
+
+impl<'a, V> PieceVerifier for &'a V where V: PieceVerifier {
+    fn verify(&self, info_dict: &Info, piece_index: u64, data: &[u8]) -> bool {
+        PieceVerifier::verify(*self, info_dict, piece_index, data)
+    }
+
+    fn verifies_hash(&self) -> bool {
+        PieceVerifier::verifies_hash(*self)
+    }
+
+    fn verify_hash(&self, info_dict: &Info, piece_index: u64, hash: ShaHash) -> bool {
+        PieceVerifier::verify_hash(*self, info_dict, piece_index, hash)
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// `PieceVerifier` which checks the SHA-1 hash of a piece against the pieces in the info dictionary.
+///
+/// This is the verifier used by default.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Sha1Verifier;
+
+impl Sha1Verifier {
+    /// Create a new `Sha1Verifier`.
+    pub fn new() -> Sha1Verifier {
+        Sha1Verifier
+    }
+}
+
+impl PieceVerifier for Sha1Verifier {
+    fn verify(&self, info_dict: &Info, piece_index: u64, data: &[u8]) -> bool {
+        self.verify_hash(info_dict, piece_index, ShaHash::from_bytes(data))
+    }
+
+    fn verifies_hash(&self) -> bool {
+        true
+    }
+
+    fn verify_hash(&self, info_dict: &Info, piece_index: u64, hash: ShaHash) -> bool {
+        match expected_piece_hash(info_dict, piece_index) {
+            Some(expected_hash) => hash == expected_hash,
+            None                => false
+        }
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// `PieceVerifier` which considers every piece to be good.
+///
+/// Useful for testing, or when the data is known to be good, but
+/// should NOT be used when downloading from untrusted peers. Pieces
+/// are only hashed if verbose verification is turned on.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NullVerifier;
+
+impl NullVerifier {
+    /// Create a new `NullVerifier`.
+    pub fn new() -> NullVerifier {
+        NullVerifier
+    }
+}
+
+impl PieceVerifier for NullVerifier {
+    fn verify(&self, _info_dict: &Info, _piece_index: u64, _data: &[u8]) -> bool {
+        true
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// Hash values for a piece that was verified, for external auditing.
+///
+/// The actual hash is always the SHA-1 hash of the piece data, regardless of
+/// the `PieceVerifier` that decided whether or not the piece was good.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PieceVerification {
+    index:    u64,
+    good:     bool,
+    expected: Option<ShaHash>,
+    actual:   ShaHash
+}
+
+impl PieceVerification {
+    /// Create a new `PieceVerification` for the given piece data.
+    pub fn new(info_dict: &Info, piece_index: u64, data: &[u8], good: bool) -> PieceVerification {
+        PieceVerification::with_hash(info_dict, piece_index, ShaHash::from_bytes(data), good)
+    }
+
+    /// Create a new `PieceVerification` for piece data with the given SHA-1 hash.
+    pub fn with_hash(info_dict: &Info, piece_index: u64, actual: ShaHash, good: bool) -> PieceVerification {
+        PieceVerification{ index: piece_index, good: good, expected: expected_piece_hash(info_dict, piece_index),
+                           actual: actual }
+    }
+
+    /// Index of the piece that was verified.
+    pub fn piece_index(&self) -> u64 {
+        self.index
+    }
+
+    /// Whether or not the piece was found to be good.
+    pub fn is_good(&self) -> bool {
+        self.good
+    }
+
+    /// Hash of the piece from the info dictionary, if one exists for the index.
+    pub fn expected_hash(&self) -> Option<ShaHash> {
+        self.expected
+    }
+
+    /// Hash computed from the piece data.
+    pub fn actual_hash(&self) -> ShaHash {
+        self.actual
+    }
+}
+
+/// Hash for the given piece from the info dictionary.
+fn expected_piece_hash(info_dict: &Info, piece_index: u64) -> Option<ShaHash> {
+    info_dict.pieces()
+        .skip(piece_index as usize)
+        .next()
+        .and_then(|hash| ShaHash::from_hash(hash).ok())
+}
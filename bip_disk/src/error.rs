@@ -19,6 +19,27 @@ error_chain! {
             description("Failed To Load/Process Block Because Torrent Is Not Loaded")
             display("Failed To Load/Process Block Because The InfoHash {:?} It Is Not Currently Added", hash)
         }
+        TorrentPaused {
+            hash: InfoHash
+        } {
+            description("Failed To Load/Process Block Because Torrent Is Paused")
+            display("Failed To Load/Process Block Because The InfoHash {:?} Is Currently Paused", hash)
+        }
+        ChecksumMismatch {
+            expected: u32,
+            actual:   u32
+        } {
+            description("Failed To Process Block Because The Block Checksum Did Not Match")
+            display("Failed To Process Block Because The Block Checksum {:x} Did Not Match The Expected Checksum {:x}", actual, expected)
+        }
+        RangeOutOfBounds {
+            hash:   InfoHash,
+            offset: u64,
+            length: u64
+        } {
+            description("Failed To Read Range Because It Extends Past The End Of The Torrent")
+            display("Failed To Read Range At Offset {} With Length {} Because It Extends Past The End Of The Torrent {:?}", offset, length, hash)
+        }
     }
 }
 
@@ -41,12 +62,24 @@ error_chain! {
             description("Failed To Add Torrent Because Size Checker Failed For A File")
             display("Failed To Add Torrent Because Size Checker Failed For {:?} Where File Size Was {} But Should Have Been {}", file_path, actual_size, expected_size)
         }
+        FileOpen {
+            file_path: PathBuf
+        } {
+            description("Failed To Add Torrent Because A File Could Not Be Opened")
+            display("Failed To Add Torrent Because The File {:?} Could Not Be Opened", file_path)
+        }
         ExistingInfoHash {
             hash: InfoHash
         } {
             description("Failed To Add Torrent Because Another Torrent With The Same InfoHash Is Already Added")
             display("Failed To Add Torrent Because Another Torrent With The Same InfoHash {:?} Is Already Added", hash)
         }
+        CheckCancelled {
+            hash: InfoHash
+        } {
+            description("Failed To Add Torrent Because It Was Removed During The Initial Check")
+            display("Failed To Add Torrent Because The InfoHash {:?} Was Removed During The Initial Check", hash)
+        }
         InfoHashNotFound {
             hash: InfoHash
         } {
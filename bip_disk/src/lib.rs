@@ -18,8 +18,11 @@ pub mod error;
 
 pub use disk::{IDiskMessage, ODiskMessage};
 pub use disk::fs::FileSystem;
+pub use disk::verify::{PieceVerifier, PieceVerification};
+pub use disk::summary::TorrentSummary;
 pub use disk::builder::DiskManagerBuilder;
 pub use disk::manager::{DiskManager, DiskManagerSink, DiskManagerStream};
+pub use disk::range::RangeStream;
 
 pub use memory::block::{Block, BlockMut, BlockMetadata};
 
@@ -31,6 +34,12 @@ pub mod fs {
 /// Built in objects implementing `FileSystem` for caching.
 pub mod fs_cache {
     pub use disk::fs::cache::file_handle::FileHandleCache;
+    pub use disk::fs::cache::write_back::{WriteBackCache, WriteBackFile};
+}
+
+/// Built in objects implementing `PieceVerifier`.
+pub mod verify {
+    pub use disk::verify::{Sha1Verifier, NullVerifier};
 }
 
 pub use bip_util::bt::InfoHash;
\ No newline at end of file
@@ -1,6 +1,7 @@
 use std::ops::{Deref, DerefMut};
 
 use bip_util::bt::{self, InfoHash};
+use memory::checksum;
 
 use bytes::{Bytes, BytesMut};
 
@@ -12,13 +13,22 @@ pub struct BlockMetadata {
     info_hash:    InfoHash,
     piece_index:  u64,
     block_offset: u64,
-    block_length: usize
+    block_length: usize,
+    checksum:     Option<u32>
 }
 
 impl BlockMetadata {
     pub fn new(info_hash: InfoHash, piece_index: u64, block_offset: u64, block_length: usize) -> BlockMetadata {
         BlockMetadata{ info_hash: info_hash, piece_index: piece_index,
-                       block_offset: block_offset, block_length: block_length }
+                       block_offset: block_offset, block_length: block_length, checksum: None }
+    }
+
+    /// Attach the given checksum (Adler-32) of the block data to the metadata.
+    ///
+    /// Blocks with a checksum attached will be re-verified before being written to disk.
+    pub fn with_checksum(mut self, checksum: Option<u32>) -> BlockMetadata {
+        self.checksum = checksum;
+        self
     }
 
     pub fn with_default_hash(piece_index: u64, block_offset: u64, block_length: usize) -> BlockMetadata {
@@ -40,6 +50,10 @@ impl BlockMetadata {
     pub fn block_length(&self) -> usize {
         self.block_length
     }
+
+    pub fn checksum(&self) -> Option<u32> {
+        self.checksum
+    }
 }
 
 impl Default for BlockMetadata {
@@ -68,6 +82,22 @@ impl Block {
         self.metadata
     }
 
+    /// Calculate a checksum over the block data, and attach it to the metadata.
+    ///
+    /// This should be called as soon as the block is received, so that corruption
+    /// occurring between the peer layer and the disk layer can be detected.
+    pub fn with_checksum(mut self) -> Block {
+        self.metadata = self.metadata.with_checksum(Some(checksum::adler32(&self.block_data)));
+        self
+    }
+
+    /// Returns false if a checksum is attached and it does not match the block data.
+    pub fn verify_checksum(&self) -> bool {
+        self.metadata.checksum()
+            .map(|expected| expected == checksum::adler32(&self.block_data))
+            .unwrap_or(true)
+    }
+
     pub fn into_parts(self) -> (BlockMetadata, Bytes) {
         (self.metadata, self.block_data)
     }
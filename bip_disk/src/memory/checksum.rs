@@ -0,0 +1,44 @@
+//! Lightweight checksum used to detect corruption of blocks in flight.
+
+const ADLER_MOD: u32 = 65521;
+// Largest number of bytes we can sum before our accumulators could overflow
+const ADLER_NMAX: usize = 5552;
+
+/// Calculate the Adler-32 checksum of the given bytes.
+pub fn adler32(bytes: &[u8]) -> u32 {
+    let mut a = 1u32;
+    let mut b = 0u32;
+
+    for chunk in bytes.chunks(ADLER_NMAX) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+
+        a %= ADLER_MOD;
+        b %= ADLER_MOD;
+    }
+
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn positive_adler32_empty() {
+        assert_eq!(1, super::adler32(&[]));
+    }
+
+    #[test]
+    fn positive_adler32_wikipedia() {
+        assert_eq!(0x11E60398, super::adler32(b"Wikipedia"));
+    }
+
+    #[test]
+    fn positive_adler32_large_buffer() {
+        let buffer = vec![0xFFu8; 100000];
+
+        // Ensure our accumulators dont overflow over multiple chunks
+        assert_eq!(0x149A302C, super::adler32(&buffer));
+    }
+}
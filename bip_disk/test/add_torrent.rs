@@ -33,9 +33,9 @@ fn positive_add_torrent() {
     let mut core = Core::new().unwrap();
 
     // Run a core loop until we get the TorrentAdded message
-    let good_pieces = ::core_loop_with_timeout(&mut core, 500, (0, recv), |good_pieces, recv, msg| {
+    let (good_pieces, summary) = ::core_loop_with_timeout(&mut core, 500, (0, recv), |good_pieces, recv, msg| {
         match msg {
-            ODiskMessage::TorrentAdded(_)      => Loop::Break(good_pieces),
+            ODiskMessage::TorrentAdded(_, sum) => Loop::Break((good_pieces, sum)),
             ODiskMessage::FoundGoodPiece(_, _) => Loop::Continue((good_pieces + 1, recv)),
             unexpected @ _                     => panic!("Unexpected Message: {:?}", unexpected)
         }
@@ -43,6 +43,13 @@ fn positive_add_torrent() {
 
     assert_eq!(0, good_pieces);
 
+    // Verify that every piece is missing, since we had to create both non empty files
+    assert_eq!(0, summary.good_pieces());
+    assert_eq!(0, summary.bad_pieces());
+    assert_eq!(3, summary.missing_pieces());
+    assert_eq!(0, summary.bytes_present());
+    assert_eq!(2, summary.files_created());
+
     // Verify file a in file system
     let mut received_file_a = filesystem.open_file(data_a.1).unwrap();
     assert_eq!(50, filesystem.file_size(&received_file_a).unwrap());
@@ -0,0 +1,195 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use {MultiFileDirectAccessor, InMemoryFileSystem, InMemoryFile};
+use bip_disk::{DiskManagerBuilder, IDiskMessage, ODiskMessage, FileSystem};
+use bip_disk::error::TorrentErrorKind;
+use bip_metainfo::{MetainfoBuilder, PieceLength, Metainfo};
+use tokio_core::reactor::{Core};
+use futures::future::{Loop, Future};
+use futures::stream::Stream;
+use futures::sink::Sink;
+
+/// File system which refuses to open any file named "a".
+#[derive(Clone)]
+struct DeniedFileSystem {
+    inner: InMemoryFileSystem
+}
+
+impl FileSystem for DeniedFileSystem {
+    type File = InMemoryFile;
+
+    fn open_file<P>(&self, path: P) -> io::Result<Self::File>
+        where P: AsRef<Path> + Send + 'static {
+        if path.as_ref().ends_with("a") {
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, "Permission Denied"))
+        } else {
+            self.inner.open_file(path)
+        }
+    }
+
+    fn sync_file<P>(&self, path: P) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static {
+        self.inner.sync_file(path)
+    }
+
+    fn move_file<P, Q>(&self, from: P, to: Q) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static,
+              Q: AsRef<Path> + Send + 'static {
+        self.inner.move_file(from, to)
+    }
+
+    fn file_size(&self, file: &Self::File) -> io::Result<u64> {
+        self.inner.file_size(file)
+    }
+
+    fn read_file(&self, file: &mut Self::File, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.inner.read_file(file, offset, buffer)
+    }
+
+    fn write_file(&self, file: &mut Self::File, offset: u64, buffer: &[u8]) -> io::Result<usize> {
+        self.inner.write_file(file, offset, buffer)
+    }
+}
+
+#[test]
+fn negative_add_torrent_pre_open_denied() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(50), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+    let info_hash = metainfo_file.info().info_hash();
+
+    // Spin up a disk manager that opens all files up front
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .with_pre_open_files(true)
+        .build(DeniedFileSystem{ inner: filesystem.clone() });
+
+    let (send, recv) = disk_manager.split();
+    send.send(IDiskMessage::AddTorrent(metainfo_file)).wait().unwrap();
+
+    let mut core = Core::new().unwrap();
+
+    // Run a core loop until we get the TorrentError message
+    let (error_hash, error_path) = ::core_loop_with_timeout(&mut core, 500, ((), recv), |_, _, msg| {
+        match msg {
+            ODiskMessage::TorrentError(hash, err) => {
+                match err.kind() {
+                    &TorrentErrorKind::FileOpen{ ref file_path } => Loop::Break((hash, file_path.clone())),
+                    unexpected @ _                              => panic!("Unexpected Error Kind: {:?}", unexpected)
+                }
+            },
+            unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+        }
+    });
+
+    assert_eq!(info_hash, error_hash);
+    assert!(error_path.ends_with("a"));
+
+    // No file should have been created, since the first file failed to open
+    assert_eq!(0, filesystem.run_with_lock(|files| files.len()));
+}
+
+/// File system which records when files are opened, and when any file is read from.
+#[derive(Clone)]
+struct RecordingFileSystem {
+    inner:  InMemoryFileSystem,
+    events: Arc<Mutex<Vec<Option<PathBuf>>>>
+}
+
+impl FileSystem for RecordingFileSystem {
+    type File = InMemoryFile;
+
+    fn open_file<P>(&self, path: P) -> io::Result<Self::File>
+        where P: AsRef<Path> + Send + 'static {
+        self.events.lock().unwrap().push(Some(path.as_ref().to_path_buf()));
+
+        self.inner.open_file(path)
+    }
+
+    fn sync_file<P>(&self, path: P) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static {
+        self.inner.sync_file(path)
+    }
+
+    fn move_file<P, Q>(&self, from: P, to: Q) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static,
+              Q: AsRef<Path> + Send + 'static {
+        self.inner.move_file(from, to)
+    }
+
+    fn file_size(&self, file: &Self::File) -> io::Result<u64> {
+        self.inner.file_size(file)
+    }
+
+    fn read_file(&self, file: &mut Self::File, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.events.lock().unwrap().push(None);
+
+        self.inner.read_file(file, offset, buffer)
+    }
+
+    fn write_file(&self, file: &mut Self::File, offset: u64, buffer: &[u8]) -> io::Result<usize> {
+        self.inner.write_file(file, offset, buffer)
+    }
+}
+
+/// Add a torrent to a disk manager, returning the files opened and read from, in order.
+fn add_torrent_events(pre_open: bool) -> Vec<Option<PathBuf>> {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(50), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+    let info_hash = metainfo_file.info().info_hash();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let disk_manager = DiskManagerBuilder::new()
+        .with_pre_open_files(pre_open)
+        .build(RecordingFileSystem{ inner: InMemoryFileSystem::new(), events: events.clone() });
+
+    let (send, recv) = disk_manager.split();
+    send.send(IDiskMessage::AddTorrent(metainfo_file)).wait().unwrap();
+
+    let mut core = Core::new().unwrap();
+
+    // Run a core loop until we get the TorrentAdded message
+    let added_hash = ::core_loop_with_timeout(&mut core, 500, ((), recv), |_, _, msg| {
+        match msg {
+            ODiskMessage::TorrentAdded(hash, _) => Loop::Break(hash),
+            unexpected @ _                      => panic!("Unexpected Message: {:?}", unexpected)
+        }
+    });
+    assert_eq!(info_hash, added_hash);
+
+    let events = events.lock().unwrap().clone();
+    events
+}
+
+#[test]
+fn positive_add_torrent_pre_open() {
+    let plain_events = add_torrent_events(false);
+    let pre_open_events = add_torrent_events(true);
+
+    // Every file is opened, in order, before the torrent is checked
+    let expected: Vec<Option<PathBuf>> = vec![Some("/path/to/file/a".into()), Some("/path/to/file/b".into())];
+    assert_eq!(&expected[..], &pre_open_events[..2]);
+
+    // Which is in addition to the files opened while checking the torrent
+    let opens = |events: &[Option<PathBuf>]| events.iter().filter(|event| event.is_some()).count();
+    assert_eq!(opens(&plain_events) + 2, opens(&pre_open_events));
+}
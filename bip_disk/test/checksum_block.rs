@@ -0,0 +1,67 @@
+use {MultiFileDirectAccessor, InMemoryFileSystem};
+use bip_disk::{DiskManagerBuilder, IDiskMessage, ODiskMessage, BlockMetadata, Block};
+use bip_disk::error::BlockErrorKind;
+use bip_metainfo::{MetainfoBuilder, PieceLength, Metainfo};
+use bytes::BytesMut;
+use tokio_core::reactor::{Core};
+use futures::future::{Loop};
+use futures::sink::Sink;
+
+#[test]
+fn negative_process_block_checksum_mismatch() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+
+    // Spin up a disk manager and add our created torrent to it
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .build(filesystem.clone());
+
+    let mut process_bytes = BytesMut::new();
+    process_bytes.extend_from_slice(&data_b.0[1..(50 + 1)]);
+
+    // Attach a checksum, then corrupt the block after the fact
+    let process_block = Block::new(BlockMetadata::new(metainfo_file.info().info_hash(), 1, 0, 50), process_bytes.freeze())
+        .with_checksum();
+    let (metadata, process_bytes) = process_block.into_parts();
+    let mut corrupt_bytes = BytesMut::from(&process_bytes[..]);
+    corrupt_bytes[0] = corrupt_bytes[0].wrapping_add(1);
+    let process_block = Block::new(metadata, corrupt_bytes.freeze());
+
+    assert!(!process_block.verify_checksum());
+
+    let (send, recv) = disk_manager.into_parts();
+    let mut blocking_send = send.clone().wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+
+    let mut core = Core::new().unwrap();
+    ::core_loop_with_timeout(&mut core, 500, ((blocking_send, Some(process_block)), recv),
+        |(mut blocking_send, opt_pblock), recv, msg| {
+            match msg {
+                ODiskMessage::TorrentAdded(_, _) => {
+                    blocking_send.send(IDiskMessage::ProcessBlock(opt_pblock.unwrap())).unwrap();
+                    Loop::Continue(((blocking_send, None), recv))
+                },
+                ODiskMessage::ProcessBlockError(_, err) => {
+                    match err.kind() {
+                        &BlockErrorKind::ChecksumMismatch{ expected, actual } => assert!(expected != actual),
+                        unexpected @ _ => panic!("Unexpected Error: {:?}", unexpected)
+                    }
+                    Loop::Break(())
+                },
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+
+    assert_eq!(1, send.checksum_mismatches());
+}
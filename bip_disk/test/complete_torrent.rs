@@ -35,7 +35,7 @@ fn positive_complete_torrent() {
     // Run a core loop until we get the TorrentAdded message
     let (good_pieces, recv) = ::core_loop_with_timeout(&mut core, 500, (0, recv), |good_pieces, recv, msg| {
         match msg {
-            ODiskMessage::TorrentAdded(_)      => Loop::Break((good_pieces, recv)),
+            ODiskMessage::TorrentAdded(_, _)   => Loop::Break((good_pieces, recv)),
             ODiskMessage::FoundGoodPiece(_, _) => Loop::Continue((good_pieces + 1, recv)),
             unexpected @ _                     => panic!("Unexpected Message: {:?}", unexpected)
         }
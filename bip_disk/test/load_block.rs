@@ -43,7 +43,7 @@ fn positive_load_block() {
     let (pblock, lblock) = ::core_loop_with_timeout(&mut core, 500, ((blocking_send, Some(process_block), Some(load_block)), recv),
         |(mut blocking_send, opt_pblock, opt_lblock), recv, msg| {
             match msg {
-                ODiskMessage::TorrentAdded(_) => {
+                ODiskMessage::TorrentAdded(_, _) => {
                     blocking_send.send(IDiskMessage::ProcessBlock(opt_pblock.unwrap())).unwrap();
                     Loop::Continue(((blocking_send, None, opt_lblock), recv))
                 },
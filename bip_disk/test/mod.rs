@@ -3,6 +3,7 @@ extern crate bip_disk;
 extern crate bip_util;
 extern crate bytes;
 extern crate futures;
+extern crate futures_cpupool;
 extern crate tokio_core;
 extern crate rand;
 
@@ -24,12 +25,20 @@ use futures::stream::Stream;
 use futures::sink::{Sink, Wait};
 
 mod add_torrent;
+mod add_torrent_pre_open;
 mod disk_manager_send_backpressure;
+mod checksum_block;
 mod complete_torrent;
 mod load_block;
+mod move_torrent;
+mod pause_torrent;
 mod process_block;
+mod read_range;
 mod remove_torrent;
+mod remove_torrent_during_check;
 mod resume_torrent;
+mod verify_piece;
+mod write_back_cache;
 
 /// Generate buffer of size random bytes.
 fn random_buffer(size: usize) -> Vec<u8> {
@@ -181,6 +190,16 @@ impl FileSystem for InMemoryFileSystem {
         Ok(())
     }
 
+    fn move_file<P, Q>(&self, from: P, to: Q) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static,
+              Q: AsRef<Path> + Send + 'static {
+        self.run_with_lock(|files| {
+            files.remove(from.as_ref())
+                .map(|file_buffer| { files.insert(to.as_ref().to_path_buf(), file_buffer); })
+                .ok_or(io::Error::new(io::ErrorKind::NotFound, "File Not Found"))
+        })
+    }
+
     fn file_size(&self, file: &Self::File) -> io::Result<u64> {
         self.run_with_lock(|files| {
             files.get(&file.path)
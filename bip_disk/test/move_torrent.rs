@@ -0,0 +1,95 @@
+use std::path::Path;
+
+use {MultiFileDirectAccessor, InMemoryFileSystem};
+use bip_disk::{DiskManagerBuilder, IDiskMessage, ODiskMessage, BlockMetadata, Block, BlockMut};
+use bip_metainfo::{MetainfoBuilder, PieceLength, Metainfo};
+use bytes::BytesMut;
+use tokio_core::reactor::{Core};
+use futures::future::{Loop};
+use futures::stream::Stream;
+use futures::sink::Sink;
+
+#[test]
+fn positive_move_torrent() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+    let info_hash = metainfo_file.info().info_hash();
+
+    // Spin up a disk manager and add our created torrent to it
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .build(filesystem.clone());
+
+    let mut process_block = BytesMut::new();
+    process_block.extend_from_slice(&data_b.0[1..(50 + 1)]);
+
+    let mut load_block = BytesMut::with_capacity(50);
+    load_block.extend_from_slice(&[0u8; 50]);
+
+    let process_block = Block::new(BlockMetadata::new(info_hash, 1, 0, 50), process_block.freeze());
+    let load_block    = BlockMut::new(BlockMetadata::new(info_hash, 1, 0, 50), load_block);
+
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+
+    // Process a block, move the torrent, then load the block back from the new directory
+    let mut core = Core::new().unwrap();
+    let (pblock, lblock) = ::core_loop_with_timeout(&mut core, 500, ((blocking_send, Some(process_block), Some(load_block)), recv),
+        |(mut blocking_send, opt_pblock, opt_lblock), recv, msg| {
+            match msg {
+                ODiskMessage::TorrentAdded(_, _) => {
+                    blocking_send.send(IDiskMessage::ProcessBlock(opt_pblock.unwrap())).unwrap();
+                    Loop::Continue(((blocking_send, None, opt_lblock), recv))
+                },
+                ODiskMessage::BlockProcessed(block) => {
+                    blocking_send.send(IDiskMessage::MoveTorrent(info_hash, "/my/moved/".into())).unwrap();
+                    Loop::Continue(((blocking_send, Some(block), opt_lblock), recv))
+                },
+                ODiskMessage::TorrentMoved(_) => {
+                    blocking_send.send(IDiskMessage::LoadBlock(opt_lblock.unwrap())).unwrap();
+                    Loop::Continue(((blocking_send, opt_pblock, None), recv))
+                },
+                ODiskMessage::BlockLoaded(block) => Loop::Break((opt_pblock.unwrap(), block)),
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+
+    // Verify lblock contains our data, and all files were moved
+    assert_eq!(*pblock, *lblock);
+    filesystem.run_with_lock(|files| {
+        assert_eq!(2, files.len());
+        assert!(files.keys().all(|path| path.starts_with(Path::new("/my/moved/"))));
+    });
+}
+
+#[test]
+fn negative_move_torrent_not_found() {
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .build(filesystem);
+
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::MoveTorrent([0u8; 20].into(), "/my/moved/".into())).unwrap();
+
+    let mut core = Core::new().unwrap();
+    ::core_loop_with_timeout(&mut core, 500, ((), recv),
+        |_, _, msg| {
+            match msg {
+                ODiskMessage::TorrentError(_, _) => Loop::Break(()),
+                unexpected @ _                   => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+}
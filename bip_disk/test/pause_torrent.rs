@@ -0,0 +1,162 @@
+use {MultiFileDirectAccessor, InMemoryFileSystem};
+use bip_disk::{DiskManagerBuilder, IDiskMessage, ODiskMessage, BlockMetadata, Block};
+use bip_disk::error::BlockErrorKind;
+use bip_metainfo::{MetainfoBuilder, PieceLength, Metainfo};
+use bytes::BytesMut;
+use tokio_core::reactor::{Core};
+use futures::future::{Loop, Future};
+use futures::stream::Stream;
+use futures::sink::Sink;
+
+#[test]
+fn positive_pause_resume_torrent() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+    let info_hash = metainfo_file.info().info_hash();
+
+    // Spin up a disk manager and add our created torrent to it
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .build(filesystem.clone());
+
+    let mut process_bytes = BytesMut::new();
+    process_bytes.extend_from_slice(&data_b.0[1..(50 + 1)]);
+    let process_bytes = process_bytes.freeze();
+
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+
+    let mut core = Core::new().unwrap();
+    let (mut blocking_send, recv) = ::core_loop_with_timeout(&mut core, 500, (blocking_send, recv),
+        |mut blocking_send, recv, msg| {
+            match msg {
+                ODiskMessage::TorrentAdded(_, _) => {
+                    blocking_send.send(IDiskMessage::PauseTorrent(info_hash)).unwrap();
+                    Loop::Continue((blocking_send, recv))
+                },
+                ODiskMessage::TorrentPaused(_) => Loop::Break((blocking_send, recv)),
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+
+    // Blocks for a paused torrent should be rejected
+    let process_block = Block::new(BlockMetadata::new(info_hash, 1, 0, 50), process_bytes.clone());
+    blocking_send.send(IDiskMessage::ProcessBlock(process_block)).unwrap();
+
+    let (mut blocking_send, recv) = ::core_loop_with_timeout(&mut core, 500, (blocking_send, recv),
+        |mut blocking_send, recv, msg| {
+            match msg {
+                ODiskMessage::ProcessBlockError(_, err) => {
+                    match err.kind() {
+                        &BlockErrorKind::TorrentPaused{ .. } => (),
+                        unexpected @ _                       => panic!("Unexpected Error: {:?}", unexpected)
+                    }
+
+                    blocking_send.send(IDiskMessage::ResumeTorrent(info_hash)).unwrap();
+                    Loop::Continue((blocking_send, recv))
+                },
+                ODiskMessage::TorrentResumed(_) => Loop::Break((blocking_send, recv)),
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+
+    // Once resumed, blocks should be processed again
+    let process_block = Block::new(BlockMetadata::new(info_hash, 1, 0, 50), process_bytes);
+    blocking_send.send(IDiskMessage::ProcessBlock(process_block)).unwrap();
+
+    ::core_loop_with_timeout(&mut core, 500, ((), recv),
+        |_, _, msg| {
+            match msg {
+                ODiskMessage::BlockProcessed(_) => Loop::Break(()),
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+}
+
+#[test]
+fn negative_read_range_paused_torrent() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+    let info_hash = metainfo_file.info().info_hash();
+
+    // Spin up a disk manager and add our created torrent to it
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .build(filesystem.clone());
+
+    let mut torrent_data = data_a.0.clone();
+    torrent_data.extend_from_slice(&data_b.0);
+
+    let (send, recv) = disk_manager.into_parts();
+    let mut blocking_send = send.clone().wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+
+    // Pause the torrent once the piece covering our range is good
+    let mut core = Core::new().unwrap();
+    ::core_loop_with_timeout(&mut core, 500, (blocking_send, recv),
+        |mut blocking_send, recv, msg| {
+            match msg {
+                ODiskMessage::TorrentAdded(_, _) => {
+                    ::send_block(&mut blocking_send, &torrent_data[0..1024], info_hash, 0, 0, 1024, |_| ());
+                    Loop::Continue((blocking_send, recv))
+                },
+                ODiskMessage::BlockProcessed(_) => Loop::Continue((blocking_send, recv)),
+                ODiskMessage::FoundGoodPiece(_, _) => {
+                    blocking_send.send(IDiskMessage::PauseTorrent(info_hash)).unwrap();
+                    Loop::Continue((blocking_send, recv))
+                },
+                ODiskMessage::TorrentPaused(_) => Loop::Break(()),
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+
+    let result = send.read_range(info_hash, 0, 100).collect().wait();
+    match result.as_ref().map_err(|err| err.kind()) {
+        Err(&BlockErrorKind::TorrentPaused{ hash }) => assert_eq!(info_hash, hash),
+        unexpected @ _                             => panic!("Unexpected Result: {:?}", unexpected)
+    }
+}
+
+#[test]
+fn negative_pause_torrent_not_found() {
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .build(filesystem.clone());
+
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::PauseTorrent([0u8; 20].into())).unwrap();
+
+    let mut core = Core::new().unwrap();
+    ::core_loop_with_timeout(&mut core, 500, ((), recv),
+        |_, _, msg| {
+            match msg {
+                ODiskMessage::TorrentError(_, _) => Loop::Break(()),
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+}
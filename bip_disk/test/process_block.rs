@@ -39,7 +39,7 @@ fn positive_process_block() {
     ::core_loop_with_timeout(&mut core, 500, ((blocking_send, Some(process_block)), recv),
         |(mut blocking_send, opt_pblock), recv, msg| {
             match msg {
-                ODiskMessage::TorrentAdded(_) => {
+                ODiskMessage::TorrentAdded(_, _) => {
                     blocking_send.send(IDiskMessage::ProcessBlock(opt_pblock.unwrap())).unwrap();
                     Loop::Continue(((blocking_send, None), recv))
                 },
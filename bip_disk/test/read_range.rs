@@ -0,0 +1,126 @@
+use {MultiFileDirectAccessor, InMemoryFileSystem};
+use bip_disk::{DiskManagerBuilder, IDiskMessage, ODiskMessage};
+use bip_disk::error::BlockErrorKind;
+use bip_metainfo::{MetainfoBuilder, PieceLength, Metainfo};
+use tokio_core::reactor::{Core};
+use futures::future::{Loop, Future};
+use futures::stream::Stream;
+use futures::sink::Sink;
+
+#[test]
+fn positive_read_range_across_pieces() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+    let info_hash = metainfo_file.info().info_hash();
+
+    // Spin up a disk manager and add our created torrent to its
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .build(filesystem.clone());
+
+    let mut torrent_data = data_a.0.clone();
+    torrent_data.extend_from_slice(&data_b.0);
+
+    let (send, recv) = disk_manager.into_parts();
+    let mut blocking_send = send.clone().wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+
+    // Range spans the file boundary and the boundary between the first two pieces
+    let range = send.read_range(info_hash, 1000, 100);
+
+    let mut core = Core::new().unwrap();
+    ::core_loop_with_timeout(&mut core, 500, ((blocking_send, 0), recv),
+        |(mut blocking_send, good_pieces), recv, msg| {
+            match msg {
+                ODiskMessage::TorrentAdded(_, _) => {
+                    ::send_block(&mut blocking_send, &torrent_data[0..1024], info_hash, 0, 0, 1024, |_| ());
+                    ::send_block(&mut blocking_send, &torrent_data[1024..2048], info_hash, 1, 0, 1024, |_| ());
+
+                    Loop::Continue(((blocking_send, good_pieces), recv))
+                },
+                ODiskMessage::BlockProcessed(_)     => Loop::Continue(((blocking_send, good_pieces), recv)),
+                ODiskMessage::FoundGoodPiece(_, _) if good_pieces == 1 => Loop::Break(()),
+                ODiskMessage::FoundGoodPiece(_, _) => Loop::Continue(((blocking_send, good_pieces + 1), recv)),
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+
+    let chunks = range.collect().wait().unwrap();
+    let read_data: Vec<u8> = chunks.iter().flat_map(|chunk| chunk.iter().cloned()).collect();
+
+    // Read should have been split at the piece boundary
+    assert_eq!(2, chunks.len());
+    assert_eq!(&torrent_data[1000..1100], &read_data[..]);
+}
+
+#[test]
+fn negative_read_range_out_of_bounds() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+    let info_hash = metainfo_file.info().info_hash();
+
+    // Spin up a disk manager and add our created torrent to its
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .build(filesystem.clone());
+
+    let (send, recv) = disk_manager.into_parts();
+    let mut blocking_send = send.clone().wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+
+    let mut core = Core::new().unwrap();
+    ::core_loop_with_timeout(&mut core, 500, ((), recv),
+        |_, _, msg| {
+            match msg {
+                ODiskMessage::TorrentAdded(_, _) => Loop::Break(()),
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+
+    let result = send.read_range(info_hash, 3000, 100).collect().wait();
+    match result.as_ref().map_err(|err| err.kind()) {
+        Err(&BlockErrorKind::RangeOutOfBounds{ offset, length, .. }) => {
+            assert_eq!(3000, offset);
+            assert_eq!(100, length);
+        },
+        unexpected @ _ => panic!("Unexpected Result: {:?}", unexpected)
+    }
+}
+
+#[test]
+fn negative_read_range_overflows() {
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .build(filesystem);
+
+    let (send, _) = disk_manager.into_parts();
+
+    let result = send.read_range([0u8; 20].into(), 1, u64::max_value()).collect().wait();
+    match result.as_ref().map_err(|err| err.kind()) {
+        Err(&BlockErrorKind::RangeOutOfBounds{ offset, length, .. }) => {
+            assert_eq!(1, offset);
+            assert_eq!(u64::max_value(), length);
+        },
+        unexpected @ _ => panic!("Unexpected Result: {:?}", unexpected)
+    }
+}
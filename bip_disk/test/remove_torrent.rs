@@ -38,7 +38,7 @@ fn positive_remove_torrent() {
     let (mut blocking_send, good_pieces, recv) = ::core_loop_with_timeout(&mut core, 500, ((blocking_send, 0), recv),
         |(mut blocking_send, good_pieces), recv, msg| {
             match msg {
-                ODiskMessage::TorrentAdded(_)      => {
+                ODiskMessage::TorrentAdded(_, _)   => {
                     blocking_send.send(IDiskMessage::RemoveTorrent(info_hash)).unwrap();
                     Loop::Continue(((blocking_send, good_pieces), recv))
                 },
@@ -0,0 +1,261 @@
+use {MultiFileDirectAccessor, InMemoryFileSystem, InMemoryFile};
+use bip_disk::{DiskManagerBuilder, FileSystem, IDiskMessage, ODiskMessage, BlockMetadata, Block};
+use bip_disk::error::TorrentErrorKind;
+use bip_metainfo::{MetainfoBuilder, PieceLength, Metainfo};
+use bytes::BytesMut;
+use tokio_core::reactor::{Core};
+use futures::future::{Loop};
+use futures::stream::Stream;
+use futures::sink::Sink;
+use futures_cpupool::Builder;
+
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, Condvar};
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::time::Duration;
+
+/// File system that holds up the initial check of a torrent until it is released.
+#[derive(Clone)]
+struct BlockingFileSystem {
+    inner:    InMemoryFileSystem,
+    checking: Arc<Mutex<Sender<()>>>,
+    released: Arc<(Mutex<bool>, Condvar)>
+}
+
+impl BlockingFileSystem {
+    fn new() -> (BlockingFileSystem, Receiver<()>) {
+        let (send, recv) = mpsc::channel();
+
+        (BlockingFileSystem{ inner: InMemoryFileSystem::new(), checking: Arc::new(Mutex::new(send)),
+                             released: Arc::new((Mutex::new(false), Condvar::new())) }, recv)
+    }
+
+    fn release(&self) {
+        let &(ref lock, ref condvar) = &*self.released;
+
+        *lock.lock().unwrap() = true;
+        condvar.notify_all();
+    }
+}
+
+impl FileSystem for BlockingFileSystem {
+    type File = InMemoryFile;
+
+    fn open_file<P>(&self, path: P) -> io::Result<Self::File>
+        where P: AsRef<Path> + Send + 'static {
+        self.inner.open_file(path)
+    }
+
+    fn sync_file<P>(&self, path: P) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static {
+        self.inner.sync_file(path)
+    }
+
+    fn file_size(&self, file: &Self::File) -> io::Result<u64> {
+        // File sizes are validated at the start of the check
+        let _ = self.checking.lock().unwrap().send(());
+
+        let &(ref lock, ref condvar) = &*self.released;
+        let mut released = lock.lock().unwrap();
+        while !*released {
+            released = condvar.wait(released).unwrap();
+        }
+
+        self.inner.file_size(file)
+    }
+
+    fn read_file(&self, file: &mut Self::File, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.inner.read_file(file, offset, buffer)
+    }
+
+    fn write_file(&self, file: &mut Self::File, offset: u64, buffer: &[u8]) -> io::Result<usize> {
+        self.inner.write_file(file, offset, buffer)
+    }
+}
+
+/// Worker config with a thread left over for the removal while two checks are blocked.
+fn blocking_worker_config() -> Builder {
+    let mut builder = Builder::new();
+    builder.pool_size(3);
+
+    builder
+}
+
+fn any_metainfo() -> (Vec<u8>, Metainfo) {
+    let data_a = (::random_buffer(1024 * 1024), "/path/to/file/a".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(), vec![data_a.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+
+    (data_a.0, Metainfo::from_bytes(metainfo_bytes).unwrap())
+}
+
+#[test]
+fn positive_remove_torrent_during_check() {
+    let (data, metainfo_file) = any_metainfo();
+    let info_hash = metainfo_file.info().info_hash();
+
+    // Spin up a disk manager, then add our created torrent, and wait for its check to start
+    let (filesystem, checking) = BlockingFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .with_worker_config(blocking_worker_config())
+        .build(filesystem.clone());
+
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+    checking.recv_timeout(Duration::from_millis(5000)).unwrap();
+
+    // Remove the torrent while it is still being checked
+    blocking_send.send(IDiskMessage::RemoveTorrent(info_hash)).unwrap();
+
+    let mut core = Core::new().unwrap();
+    let recv = ::core_loop_with_timeout(&mut core, 500, ((), recv),
+        |_, recv, msg| {
+            match msg {
+                ODiskMessage::TorrentRemoved(hash) if hash == info_hash => Loop::Break(recv),
+                unexpected                                             => panic!("Unexpected Message: {:?}", unexpected)
+            }
+    });
+
+    // Once the check gets to continue, it should find out that it was cancelled
+    filesystem.release();
+
+    let recv = ::core_loop_with_timeout(&mut core, 5000, ((), recv),
+        |_, recv, msg| {
+            match msg {
+                ODiskMessage::TorrentError(_, err) => {
+                    match err.kind() {
+                        &TorrentErrorKind::CheckCancelled{ hash } if hash == info_hash => Loop::Break(recv),
+                        unexpected                                                     => panic!("Unexpected Error: {:?}", unexpected)
+                    }
+                },
+                unexpected => panic!("Unexpected Message: {:?}", unexpected)
+            }
+    });
+
+    // Torrent should not be around anymore
+    let mut process_bytes = BytesMut::new();
+    process_bytes.extend_from_slice(&data[0..50]);
+
+    let process_block = Block::new(BlockMetadata::new(info_hash, 0, 0, 50), process_bytes.freeze());
+
+    blocking_send.send(IDiskMessage::ProcessBlock(process_block)).unwrap();
+
+    ::core_loop_with_timeout(&mut core, 500, ((), recv),
+        |_, _, msg| {
+            match msg {
+                ODiskMessage::ProcessBlockError(_, _) => Loop::Break(()),
+                unexpected                            => panic!("Unexpected Message: {:?}", unexpected)
+            }
+    });
+}
+
+#[test]
+fn positive_add_torrent_removed_during_check() {
+    let (_, metainfo_file) = any_metainfo();
+    let info_hash = metainfo_file.info().info_hash();
+
+    let (filesystem, checking) = BlockingFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .with_worker_config(blocking_worker_config())
+        .build(filesystem.clone());
+
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file.clone())).unwrap();
+    checking.recv_timeout(Duration::from_millis(5000)).unwrap();
+
+    blocking_send.send(IDiskMessage::RemoveTorrent(info_hash)).unwrap();
+
+    let mut core = Core::new().unwrap();
+    let recv = ::core_loop_with_timeout(&mut core, 500, ((), recv),
+        |_, recv, msg| {
+            match msg {
+                ODiskMessage::TorrentRemoved(hash) if hash == info_hash => Loop::Break(recv),
+                unexpected                                             => panic!("Unexpected Message: {:?}", unexpected)
+            }
+    });
+
+    // Add the torrent back before the cancelled check has finished
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+    filesystem.release();
+
+    // Only the first check should be cancelled
+    ::core_loop_with_timeout(&mut core, 5000, ((false, false), recv),
+        |(cancelled, added), recv, msg| {
+            let (cancelled, added) = match msg {
+                ODiskMessage::TorrentAdded(hash, _) if hash == info_hash => (cancelled, true),
+                ODiskMessage::FoundGoodPiece(_, _)                       => (cancelled, added),
+                ODiskMessage::TorrentError(_, err) => {
+                    match err.kind() {
+                        &TorrentErrorKind::CheckCancelled{ hash } if hash == info_hash && !cancelled => (true, added),
+                        unexpected                                                                   => panic!("Unexpected Error: {:?}", unexpected)
+                    }
+                },
+                unexpected => panic!("Unexpected Message: {:?}", unexpected)
+            };
+
+            if cancelled && added {
+                Loop::Break(())
+            } else {
+                Loop::Continue(((cancelled, added), recv))
+            }
+    });
+}
+
+#[test]
+fn positive_remove_torrent_cancels_every_check() {
+    let (_, metainfo_file) = any_metainfo();
+    let info_hash = metainfo_file.info().info_hash();
+
+    let (filesystem, checking) = BlockingFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .with_worker_config(blocking_worker_config())
+        .build(filesystem.clone());
+
+    // Add the torrent twice, so that two checks for it are running at once
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file.clone())).unwrap();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+    checking.recv_timeout(Duration::from_millis(5000)).unwrap();
+    checking.recv_timeout(Duration::from_millis(5000)).unwrap();
+
+    blocking_send.send(IDiskMessage::RemoveTorrent(info_hash)).unwrap();
+
+    let mut core = Core::new().unwrap();
+    let recv = ::core_loop_with_timeout(&mut core, 500, ((), recv),
+        |_, recv, msg| {
+            match msg {
+                ODiskMessage::TorrentRemoved(hash) if hash == info_hash => Loop::Break(recv),
+                unexpected                                             => panic!("Unexpected Message: {:?}", unexpected)
+            }
+    });
+
+    filesystem.release();
+
+    // Neither check should go on to add the torrent
+    ::core_loop_with_timeout(&mut core, 5000, (0, recv),
+        |cancelled, recv, msg| {
+            let cancelled = match msg {
+                ODiskMessage::TorrentError(_, err) => {
+                    match err.kind() {
+                        &TorrentErrorKind::CheckCancelled{ hash } if hash == info_hash => cancelled + 1,
+                        unexpected                                                     => panic!("Unexpected Error: {:?}", unexpected)
+                    }
+                },
+                unexpected => panic!("Unexpected Message: {:?}", unexpected)
+            };
+
+            if cancelled == 2 {
+                Loop::Break(())
+            } else {
+                Loop::Continue((cancelled, recv))
+            }
+    });
+}
@@ -0,0 +1,361 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use {MultiFileDirectAccessor, InMemoryFileSystem};
+use bip_disk::{DiskManagerBuilder, IDiskMessage, ODiskMessage, PieceVerifier};
+use bip_disk::verify::{NullVerifier, Sha1Verifier};
+use bip_metainfo::{MetainfoBuilder, PieceLength, Metainfo, Info};
+use bip_util::sha::ShaHash;
+use tokio_core::reactor::{Core};
+use futures::future::{Loop};
+use futures::stream::Stream;
+use futures::sink::Sink;
+
+#[test]
+fn positive_null_verifier_good_pieces() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+
+    // Spin up a disk manager that does not verify pieces, none of the data is on disk
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .with_piece_verifier(NullVerifier::new())
+        .build(filesystem.clone());
+
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+
+    let mut core = Core::new().unwrap();
+    let good_pieces = ::core_loop_with_timeout(&mut core, 500, (0, recv),
+        |good_pieces, recv, msg| {
+            match msg {
+                ODiskMessage::TorrentAdded(_, _)   => Loop::Break(good_pieces),
+                ODiskMessage::FoundGoodPiece(_, _) => Loop::Continue((good_pieces + 1, recv)),
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+
+    // Every piece should have been reported as good, even though the data is all zeroes
+    assert_eq!(3, good_pieces);
+}
+
+#[test]
+fn positive_verbose_verification_reports_hashes() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+    let expected_hashes: Vec<ShaHash> = metainfo_file.info().pieces()
+        .map(|hash| ShaHash::from_hash(hash).unwrap())
+        .collect();
+
+    // Spin up a disk manager that does not verify pieces, but reports their hashes
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .with_piece_verifier(NullVerifier::new())
+        .with_verbose_verification(true)
+        .build(filesystem.clone());
+
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+
+    let mut core = Core::new().unwrap();
+    let (verifications, good_pieces) = ::core_loop_with_timeout(&mut core, 500, ((Vec::new(), 0), recv),
+        |(mut verifications, good_pieces), recv, msg| {
+            match msg {
+                ODiskMessage::TorrentAdded(_, _)             => Loop::Break((verifications, good_pieces)),
+                ODiskMessage::FoundGoodPiece(_, _)           => Loop::Continue(((verifications, good_pieces + 1), recv)),
+                ODiskMessage::PieceVerified(_, verification) => {
+                    verifications.push(verification);
+
+                    Loop::Continue(((verifications, good_pieces), recv))
+                },
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+
+    assert_eq!(3, good_pieces);
+    assert_eq!(3, verifications.len());
+
+    // None of the data is on disk, so the hashes were computed over zeroes
+    for verification in verifications {
+        let index = verification.piece_index() as usize;
+
+        assert!(verification.is_good());
+        assert_eq!(Some(expected_hashes[index]), verification.expected_hash());
+        assert!(verification.expected_hash() != Some(verification.actual_hash()));
+    }
+}
+
+/// Verifier that accepts every piece from its hash, regardless of its data.
+struct AcceptingHashVerifier;
+
+impl PieceVerifier for AcceptingHashVerifier {
+    fn verify(&self, _info_dict: &Info, _piece_index: u64, _data: &[u8]) -> bool {
+        true
+    }
+
+    fn verifies_hash(&self) -> bool {
+        true
+    }
+
+    fn verify_hash(&self, _info_dict: &Info, _piece_index: u64, _hash: ShaHash) -> bool {
+        true
+    }
+}
+
+#[test]
+fn positive_chunked_hashing_matches_whole_piece() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+
+    // Spin up a disk manager that hashes pieces in chunks that dont evenly divide the piece length
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .with_piece_verifier(AcceptingHashVerifier)
+        .with_verbose_verification(true)
+        .with_hash_chunk_size(100)
+        .build(filesystem.clone());
+
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+
+    let mut core = Core::new().unwrap();
+    let verifications = ::core_loop_with_timeout(&mut core, 500, (Vec::new(), recv),
+        |mut verifications, recv, msg| {
+            match msg {
+                ODiskMessage::TorrentAdded(_, _)             => Loop::Break(verifications),
+                ODiskMessage::FoundGoodPiece(_, _)           => Loop::Continue((verifications, recv)),
+                ODiskMessage::PieceVerified(_, verification) => {
+                    verifications.push(verification);
+
+                    Loop::Continue((verifications, recv))
+                },
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+
+    assert_eq!(3, verifications.len());
+
+    // None of the data is on disk, so the hashes should be that of whole pieces of zeroes
+    for verification in verifications {
+        let piece_length = if verification.piece_index() == 2 { 975 } else { 1024 };
+
+        assert_eq!(ShaHash::from_bytes(&vec![0u8; piece_length]), verification.actual_hash());
+    }
+}
+
+#[test]
+fn positive_chunked_hashing_good_piece() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+    let mut files_bytes = Vec::new();
+    files_bytes.extend_from_slice(&data_a.0);
+    files_bytes.extend_from_slice(&data_b.0);
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+    let info_hash = metainfo_file.info().info_hash();
+
+    // Spin up a disk manager that verifies pieces in chunks
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .with_hash_chunk_size(100)
+        .build(filesystem.clone());
+
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+
+    let mut core = Core::new().unwrap();
+    let recv = ::core_loop_with_timeout(&mut core, 500, ((), recv), |_, recv, msg| {
+        match msg {
+            ODiskMessage::TorrentAdded(_, _) => Loop::Break(recv),
+            unexpected @ _                   => panic!("Unexpected Message: {:?}", unexpected)
+        }
+    });
+
+    // Send piece 0, which spans both files
+    ::send_block(&mut blocking_send, &files_bytes[0..1024], info_hash, 0, 0, 1024, |_| ());
+
+    let good_pieces = ::core_loop_with_timeout(&mut core, 500, (Vec::new(), recv),
+        |mut good_pieces, recv, msg| {
+            match msg {
+                ODiskMessage::FoundGoodPiece(_, index) => {
+                    good_pieces.push(index);
+
+                    Loop::Continue((good_pieces, recv))
+                },
+                ODiskMessage::BlockProcessed(_)        => Loop::Break(good_pieces),
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+
+    assert_eq!(vec![0], good_pieces);
+}
+
+/// Verifier that rejects every piece, regardless of its data.
+struct RejectingVerifier;
+
+impl PieceVerifier for RejectingVerifier {
+    fn verify(&self, _info_dict: &Info, _piece_index: u64, _data: &[u8]) -> bool {
+        false
+    }
+}
+
+#[test]
+fn negative_custom_verifier_rejects_good_piece() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+    let mut files_bytes = Vec::new();
+    files_bytes.extend_from_slice(&data_a.0);
+    files_bytes.extend_from_slice(&data_b.0);
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+    let info_hash = metainfo_file.info().info_hash();
+
+    // Spin up a disk manager that never accepts a piece, even if the SHA-1 hash matches
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .with_piece_verifier(RejectingVerifier)
+        .build(filesystem.clone());
+
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+
+    let mut core = Core::new().unwrap();
+    let recv = ::core_loop_with_timeout(&mut core, 500, ((), recv), |_, recv, msg| {
+        match msg {
+            ODiskMessage::TorrentAdded(_, _) => Loop::Break(recv),
+            unexpected @ _                   => panic!("Unexpected Message: {:?}", unexpected)
+        }
+    });
+
+    // Send piece 0 with the correct data
+    ::send_block(&mut blocking_send, &files_bytes[0..1024], info_hash, 0, 0, 1024, |_| ());
+
+    let bad_pieces = ::core_loop_with_timeout(&mut core, 500, (Vec::new(), recv),
+        |mut bad_pieces, recv, msg| {
+            match msg {
+                ODiskMessage::FoundBadPiece(_, index) => {
+                    bad_pieces.push(index);
+
+                    Loop::Continue((bad_pieces, recv))
+                },
+                ODiskMessage::BlockProcessed(_)       => Loop::Break(bad_pieces),
+                unexpected @ _ => panic!("Unexpected Message: {:?}", unexpected)
+            }
+        }
+    );
+
+    assert_eq!(vec![0], bad_pieces);
+}
+
+/// Verifier that checks SHA-1 hashes, counting how each piece was verified.
+struct CountingVerifier {
+    verified:        Arc<AtomicUsize>,
+    verified_hashes: Arc<AtomicUsize>
+}
+
+impl PieceVerifier for CountingVerifier {
+    fn verify(&self, info_dict: &Info, piece_index: u64, data: &[u8]) -> bool {
+        self.verified.fetch_add(1, Ordering::SeqCst);
+
+        Sha1Verifier::new().verify(info_dict, piece_index, data)
+    }
+
+    fn verifies_hash(&self) -> bool {
+        true
+    }
+
+    fn verify_hash(&self, info_dict: &Info, piece_index: u64, hash: ShaHash) -> bool {
+        self.verified_hashes.fetch_add(1, Ordering::SeqCst);
+
+        Sha1Verifier::new().verify_hash(info_dict, piece_index, hash)
+    }
+}
+
+#[test]
+fn positive_verbose_verification_hashes_once() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+
+    // Spin up a disk manager that reports hashes, and reuses them for verification
+    let (verified, verified_hashes) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .with_piece_verifier(CountingVerifier{ verified: verified.clone(), verified_hashes: verified_hashes.clone() })
+        .with_verbose_verification(true)
+        .build(filesystem.clone());
+
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+
+    let mut core = Core::new().unwrap();
+    ::core_loop_with_timeout(&mut core, 500, ((), recv), |_, _, msg| {
+        match msg {
+            ODiskMessage::TorrentAdded(_, _) => Loop::Break(()),
+            unexpected @ _                   => panic!("Unexpected Message: {:?}", unexpected)
+        }
+    });
+
+    // Each piece was hashed a single time, with that hash being both verified and recorded
+    assert_eq!(0, verified.load(Ordering::SeqCst));
+    assert_eq!(3, verified_hashes.load(Ordering::SeqCst));
+}
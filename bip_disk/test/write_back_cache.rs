@@ -0,0 +1,148 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use {InMemoryFileSystem, InMemoryFile};
+use bip_disk::FileSystem;
+use bip_disk::fs_cache::WriteBackCache;
+
+fn inner_len(fs: &InMemoryFileSystem, path: &str) -> usize {
+    fs.run_with_lock(|files| files.get(Path::new(path)).map(|buffer| buffer.len()).unwrap_or(0))
+}
+
+/// File system which fails every write while the flag is set.
+struct FailingFileSystem {
+    inner: InMemoryFileSystem,
+    fail:  Arc<AtomicBool>
+}
+
+impl FileSystem for FailingFileSystem {
+    type File = InMemoryFile;
+
+    fn open_file<P>(&self, path: P) -> io::Result<Self::File>
+        where P: AsRef<Path> + Send + 'static {
+        self.inner.open_file(path)
+    }
+
+    fn sync_file<P>(&self, path: P) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static {
+        self.inner.sync_file(path)
+    }
+
+    fn file_size(&self, file: &Self::File) -> io::Result<u64> {
+        self.inner.file_size(file)
+    }
+
+    fn read_file(&self, file: &mut Self::File, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.inner.read_file(file, offset, buffer)
+    }
+
+    fn write_file(&self, file: &mut Self::File, offset: u64, buffer: &[u8]) -> io::Result<usize> {
+        if self.fail.load(Ordering::SeqCst) {
+            Err(io::Error::new(io::ErrorKind::Other, "Write Failed"))
+        } else {
+            self.inner.write_file(file, offset, buffer)
+        }
+    }
+}
+
+#[test]
+fn positive_write_back_coalesce_until_read() {
+    let inner = InMemoryFileSystem::new();
+    let cache = WriteBackCache::new(inner.clone());
+
+    let mut file = cache.open_file("a").unwrap();
+    cache.write_file(&mut file, 0, &[1u8; 16]).unwrap();
+    cache.write_file(&mut file, 16, &[2u8; 16]).unwrap();
+
+    assert_eq!(0, inner_len(&inner, "a"));
+    assert_eq!(32, cache.dirty_bytes());
+    assert_eq!(32, cache.file_size(&file).unwrap());
+
+    let mut buffer = [0u8; 32];
+    cache.read_file(&mut file, 0, &mut buffer).unwrap();
+
+    assert_eq!(&[1u8; 16], &buffer[..16]);
+    assert_eq!(&[2u8; 16], &buffer[16..]);
+    assert_eq!(32, inner_len(&inner, "a"));
+    assert_eq!(0, cache.dirty_bytes());
+}
+
+#[test]
+fn positive_write_back_flush_over_max_dirty() {
+    let inner = InMemoryFileSystem::new();
+    let cache = WriteBackCache::with_max_dirty_bytes(inner.clone(), 32);
+
+    let mut file = cache.open_file("a").unwrap();
+    cache.write_file(&mut file, 0, &[1u8; 32]).unwrap();
+    assert_eq!(0, inner_len(&inner, "a"));
+
+    cache.write_file(&mut file, 64, &[2u8; 1]).unwrap();
+    assert_eq!(65, inner_len(&inner, "a"));
+    assert_eq!(0, cache.dirty_bytes());
+}
+
+#[test]
+fn positive_write_back_flush_on_drop() {
+    let inner = InMemoryFileSystem::new();
+
+    {
+        let cache = WriteBackCache::new(inner.clone());
+        let mut file = cache.open_file("a").unwrap();
+
+        cache.write_file(&mut file, 0, &[1u8; 16]).unwrap();
+    }
+
+    assert_eq!(16, inner_len(&inner, "a"));
+}
+
+#[test]
+fn positive_write_back_flush_file() {
+    let inner = InMemoryFileSystem::new();
+    let cache = WriteBackCache::new(inner.clone());
+
+    let mut file = cache.open_file("a").unwrap();
+    cache.write_file(&mut file, 0, &[1u8; 16]).unwrap();
+    cache.flush_file(&mut file).unwrap();
+
+    assert_eq!(16, inner_len(&inner, "a"));
+    assert_eq!(0, cache.dirty_bytes());
+}
+
+#[test]
+fn positive_write_back_keep_dirty_on_error() {
+    let inner = InMemoryFileSystem::new();
+    let fail = Arc::new(AtomicBool::new(false));
+    let cache = WriteBackCache::new(FailingFileSystem{ inner: inner.clone(), fail: fail.clone() });
+
+    let mut file = cache.open_file("a").unwrap();
+    cache.write_file(&mut file, 0, &[1u8; 16]).unwrap();
+
+    fail.store(true, Ordering::SeqCst);
+    assert!(cache.flush().is_err());
+    assert_eq!(16, cache.dirty_bytes());
+
+    // Writes made after the failed flush take precedence over the data that failed
+    cache.write_file(&mut file, 8, &[2u8; 16]).unwrap();
+    assert_eq!(24, cache.dirty_bytes());
+
+    fail.store(false, Ordering::SeqCst);
+    let mut buffer = [0u8; 24];
+    cache.read_file(&mut file, 0, &mut buffer).unwrap();
+
+    assert_eq!(&[1u8; 8], &buffer[..8]);
+    assert_eq!(&[2u8; 16], &buffer[8..]);
+    assert_eq!(0, cache.dirty_bytes());
+}
+
+#[test]
+fn negative_write_back_write_past_max_size() {
+    let inner = InMemoryFileSystem::new();
+    let cache = WriteBackCache::new(inner.clone());
+
+    let mut file = cache.open_file("a").unwrap();
+
+    assert!(cache.write_file(&mut file, u64::max_value() - 1, &[1u8; 4]).is_err());
+    assert_eq!(0, cache.dirty_bytes());
+}
@@ -0,0 +1,181 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bip_util::bt::InfoHash;
+
+const INBOUND_RATE_WINDOW_SECS: u64 = 1;
+const MAX_TRACKED_HASHES:        usize = 1000;
+
+/// Connection rate context that a `HandshakeFilter` can make load aware decisions with.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FilterContext {
+    /// Number of inbound connections accepted in the last second.
+    pub inbound_rate: usize,
+    /// Number of handshakes currently in progress.
+    pub in_progress: usize,
+    /// Total number of handshakes completed by the handshaker.
+    pub completed: usize,
+    /// Total number of handshakes completed for the `InfoHash` of the handshake, if known.
+    ///
+    /// Counts are only kept for a bounded number of info hashes, when a new info hash is
+    /// seen, the count of the info hash with the fewest completed handshakes is dropped.
+    pub hash_completed: Option<usize>
+}
+
+//----------------------------------------------------------------------------------//
+
+struct TrackerState {
+    inbound:        VecDeque<Instant>,
+    in_progress:    usize,
+    completed:      usize,
+    hash_completed: HashMap<InfoHash, usize>
+}
+
+impl TrackerState {
+    fn prune_inbound(&mut self, now: Instant) {
+        let window = Duration::from_secs(INBOUND_RATE_WINDOW_SECS);
+
+        while self.inbound.front().map_or(false, |&accepted| now.duration_since(accepted) >= window) {
+            self.inbound.pop_front();
+        }
+    }
+
+    fn complete_hash(&mut self, hash: InfoHash) {
+        // Remote peers pick the info hashes, so make room by dropping the least completed hash
+        if !self.hash_completed.contains_key(&hash) && self.hash_completed.len() >= MAX_TRACKED_HASHES {
+            let opt_least = self.hash_completed.iter()
+                .min_by_key(|&(_, &completed)| completed)
+                .map(|(&least, _)| least);
+
+            opt_least.map(|least| self.hash_completed.remove(&least));
+        }
+
+        *self.hash_completed.entry(hash).or_insert(0) += 1;
+    }
+}
+
+/// Tracks the connection rates that make up a `FilterContext`.
+#[derive(Clone)]
+pub struct ContextTracker {
+    state: Arc<Mutex<TrackerState>>
+}
+
+impl ContextTracker {
+    pub fn new() -> ContextTracker {
+        let state = TrackerState{ inbound: VecDeque::new(), in_progress: 0, completed: 0, hash_completed: HashMap::new() };
+
+        ContextTracker{ state: Arc::new(Mutex::new(state)) }
+    }
+
+    /// Record that an inbound connection was accepted.
+    pub fn record_inbound(&self) {
+        self.run_with_lock(|state| {
+            let now = Instant::now();
+
+            state.prune_inbound(now);
+            state.inbound.push_back(now);
+        });
+    }
+
+    /// Record that a handshake was started.
+    pub fn start_handshake(&self) {
+        self.run_with_lock(|state| state.in_progress += 1);
+    }
+
+    /// Record that a handshake finished, completing for the given `InfoHash` if it succeeded.
+    pub fn finish_handshake(&self, opt_hash: Option<&InfoHash>) {
+        self.run_with_lock(|state| {
+            state.in_progress = state.in_progress.saturating_sub(1);
+
+            if let Some(hash) = opt_hash {
+                state.completed += 1;
+                state.complete_hash(*hash);
+            }
+        });
+    }
+
+    /// Snapshot the current `FilterContext` for a handshake with the given `InfoHash`.
+    pub fn context(&self, opt_hash: Option<&InfoHash>) -> FilterContext {
+        self.run_with_lock(|state| {
+            state.prune_inbound(Instant::now());
+
+            FilterContext{ inbound_rate: state.inbound.len(), in_progress: state.in_progress, completed: state.completed,
+                           hash_completed: opt_hash.map(|hash| state.hash_completed.get(hash).cloned().unwrap_or(0)) }
+        })
+    }
+
+    fn run_with_lock<C, R>(&self, call: C) -> R
+        where C: FnOnce(&mut TrackerState) -> R {
+        let mut lock_state = self.state.lock()
+            .expect("bip_handshake: Poisoned Lock In ContextTracker");
+
+        call(&mut *lock_state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ContextTracker, MAX_TRACKED_HASHES};
+
+    use bip_util::bt::{self, InfoHash};
+
+    fn any_info_hash() -> InfoHash {
+        [55u8; bt::INFO_HASH_LEN].into()
+    }
+
+    #[test]
+    fn positive_context_inbound_rate() {
+        let tracker = ContextTracker::new();
+
+        tracker.record_inbound();
+        tracker.record_inbound();
+
+        assert_eq!(2, tracker.context(None).inbound_rate);
+    }
+
+    #[test]
+    fn positive_context_handshake_counts() {
+        let tracker = ContextTracker::new();
+
+        tracker.start_handshake();
+        tracker.start_handshake();
+        tracker.finish_handshake(Some(&any_info_hash()));
+
+        let context = tracker.context(Some(&any_info_hash()));
+        assert_eq!(1, context.in_progress);
+        assert_eq!(1, context.completed);
+        assert_eq!(Some(1), context.hash_completed);
+    }
+
+    #[test]
+    fn positive_context_failed_handshake_not_completed() {
+        let tracker = ContextTracker::new();
+
+        tracker.start_handshake();
+        tracker.finish_handshake(None);
+
+        let context = tracker.context(Some(&any_info_hash()));
+        assert_eq!(0, context.in_progress);
+        assert_eq!(0, context.completed);
+        assert_eq!(Some(0), context.hash_completed);
+    }
+
+    #[test]
+    fn positive_context_hash_completed_bounded() {
+        let tracker = ContextTracker::new();
+
+        tracker.finish_handshake(Some(&any_info_hash()));
+        tracker.finish_handshake(Some(&any_info_hash()));
+        for index in 0..MAX_TRACKED_HASHES {
+            let mut hash = [0u8; bt::INFO_HASH_LEN];
+            hash[0] = (index >> 8) as u8;
+            hash[1] = index as u8;
+
+            tracker.finish_handshake(Some(&hash.into()));
+        }
+
+        assert_eq!(MAX_TRACKED_HASHES, tracker.run_with_lock(|state| state.hash_completed.len()));
+        assert_eq!(Some(2), tracker.context(Some(&any_info_hash())).hash_completed);
+    }
+}
@@ -2,15 +2,22 @@ use std::sync::Arc;
 use std::sync::RwLock;
 
 use filter::{HandshakeFilter};
+use filter::context::ContextTracker;
 
 #[derive(Clone)]
 pub struct Filters {
-    filters: Arc<RwLock<Vec<Box<HandshakeFilter + Send + Sync>>>>
+    filters: Arc<RwLock<Vec<Box<HandshakeFilter + Send + Sync>>>>,
+    tracker: ContextTracker
 }
 
 impl Filters {
     pub fn new() -> Filters {
-        Filters{ filters: Arc::new(RwLock::new(Vec::new())) }
+        Filters{ filters: Arc::new(RwLock::new(Vec::new())), tracker: ContextTracker::new() }
+    }
+
+    /// Tracker for the connection rates passed to filters as a `FilterContext`.
+    pub fn tracker(&self) -> &ContextTracker {
+        &self.tracker
     }
 
     pub fn add_filter<F>(&self, filter: F)
@@ -89,6 +96,7 @@ pub mod test_filters {
 
     use message::protocol::Protocol;
     use filter::{HandshakeFilter, FilterDecision};
+    use filter::context::FilterContext;
 
     use bip_util::bt::PeerId;
 
@@ -170,6 +178,33 @@ pub mod test_filters {
             }
         }
     }
+
+    //----------------------------------------------------------------------------------//
+
+    #[derive(PartialEq, Eq)]
+    pub struct BlockInboundRateFilter {
+        max_rate: usize
+    }
+
+    impl BlockInboundRateFilter {
+        pub fn new(max_rate: usize) -> BlockInboundRateFilter {
+            BlockInboundRateFilter{ max_rate: max_rate }
+        }
+    }
+
+    impl HandshakeFilter for BlockInboundRateFilter {
+        fn as_any(&self) -> &Any {
+            self
+        }
+
+        fn on_context(&self, context: &FilterContext) -> FilterDecision {
+            if context.inbound_rate > self.max_rate {
+                FilterDecision::Block
+            } else {
+                FilterDecision::Pass
+            }
+        }
+    }
 }
 
 #[cfg(test)]
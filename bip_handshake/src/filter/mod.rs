@@ -4,9 +4,11 @@ use std::any::Any;
 
 use message::protocol::Protocol;
 use message::extensions::{Extensions};
+use filter::context::FilterContext;
 
 use bip_util::bt::{InfoHash, PeerId};
 
+pub mod context;
 pub mod filters;
 
 /// Trait for adding and removing `HandshakeFilter`s.
@@ -71,6 +73,12 @@ pub trait HandshakeFilter {
 
     /// Make a filter decision based on the `PeerId`.
     fn on_pid(&self, opt_pid: Option<&PeerId>) -> FilterDecision { FilterDecision::Pass }
+
+    /// Make a filter decision based on the current connection rates of the handshaker.
+    ///
+    /// This is invoked alongside the other methods, so a filter can take the `FilterContext`
+    /// into account without having to track connections outside of the handshaker.
+    fn on_context(&self, context: &FilterContext) -> FilterDecision { FilterDecision::Pass }
 }
 
 //----------------------------------------------------------------------------------//
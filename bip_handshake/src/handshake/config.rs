@@ -1,23 +1,41 @@
 use std::time::Duration;
 use std::default::Default;
 
+use transport::SocketOptions;
+
 const DEFAULT_HANDSHAKE_BUFFER_SIZE: usize = 1000;
 const DEFAULT_WAIT_BUFFER_SIZE:      usize = 10;
 const DEFAULT_DONE_BUFFER_SIZE:      usize = 10;
+const DEFAULT_EVENT_BUFFER_SIZE:     usize = 1000;
+const DEFAULT_MAX_HALF_OPEN:         usize = 1;
+const DEFAULT_MAX_BUFFER_MEMORY:     usize = 1024 * 1024;
+const DEFAULT_MAX_ACCEPT_BATCH:      usize = 32;
 
 /// Once we get parallel handshake support (requires
 /// mpmc future channel support, we can bump this up).
 const DEFAULT_HANDSHAKE_TIMEOUT_MILLIS:         u64 = 1000;
 const DEFAULT_HANDSHAKE_CONNECT_TIMEOUT_MILLIS: u64 = 1000;
 
+const DEFAULT_RESTART_DELAY_MILLIS: u64   = 1000;
+const DEFAULT_RESTART_ATTEMPTS:     usize = 5;
+
 /// Configures the internals of a `Handshaker`.
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
 pub struct HandshakerConfig {
     sink_buffer_size:  usize,
     wait_buffer_size:  usize,
     done_buffer_size:  usize,
+    event_buffer_size: usize,
+    max_half_open:     usize,
+    max_buffer_memory: usize,
     handshake_timeout: Duration,
-    connect_timeout:   Duration
+    read_timeout:      Option<Duration>,
+    connect_timeout:   Duration,
+    restart_delay:     Duration,
+    restart_attempts:  usize,
+    listen_backlog:    Option<i32>,
+    max_accept_batch:  usize,
+    socket_options:    SocketOptions
 }
 
 impl HandshakerConfig {
@@ -42,13 +60,58 @@ impl HandshakerConfig {
         self
     }
 
+    /// Sets the buffer size that `HandshakerEvents` uses internally
+    /// to store events before they are yielded.
+    ///
+    /// Any events generated while the buffer is full will be dropped.
+    pub fn with_event_buffer_size(mut self, size: usize) -> HandshakerConfig {
+        self.event_buffer_size = size;
+        self
+    }
+
+    /// Sets the maximum number of outbound connections that `Handshaker`
+    /// will have in progress at any given time (a minimum of one will be used).
+    ///
+    /// Any `InitiateMessage`s received while at the limit will be queued
+    /// (in the sink buffer) until an in progress connection completes.
+    ///
+    /// Defaults to one, which connects to peers one at a time.
+    pub fn with_max_half_open(mut self, max: usize) -> HandshakerConfig {
+        self.max_half_open = max;
+        self
+    }
+
+    /// Sets the maximum number of bytes that `Handshaker` will
+    /// use for the buffers of handshakes that are in progress.
+    ///
+    /// Any connections made while at the limit will be dropped,
+    /// which protects memory constrained devices from connection storms.
+    pub fn with_max_buffer_memory(mut self, bytes: usize) -> HandshakerConfig {
+        self.max_buffer_memory = bytes;
+        self
+    }
+
     /// Sets the handshake timeout that `Handshaker` uses to
-    /// make sure peers dont take too long to respond to us.
+    /// make sure peers dont take too long to accept our handshake.
+    ///
+    /// Unless a separate read timeout is set, this also bounds how long
+    /// we wait for peers to send us their handshake.
     pub fn with_handshake_timeout(mut self, timeout: Duration) -> HandshakerConfig {
         self.handshake_timeout = timeout;
         self
     }
 
+    /// Sets the handshake read timeout that `Handshaker` uses to
+    /// make sure peers dont take too long to send us their handshake.
+    ///
+    /// This applies to both inbound and outbound connections, so peers
+    /// that connect to us but never send a handshake wont hold on to
+    /// our resources indefinitely. Defaults to the handshake timeout.
+    pub fn with_handshake_read_timeout(mut self, timeout: Duration) -> HandshakerConfig {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
     /// Sets the connect timeout that `Handshaker` uses to
     /// make sure peers dont take too long to respond to our
     /// connection (regardless of the underlying transport).
@@ -57,6 +120,47 @@ impl HandshakerConfig {
         self
     }
 
+    /// Sets the delay that `Handshaker` waits before attempting
+    /// to restart the listener after it encounters a fatal error.
+    pub fn with_restart_delay(mut self, delay: Duration) -> HandshakerConfig {
+        self.restart_delay = delay;
+        self
+    }
+
+    /// Sets the number of consecutive attempts that `Handshaker`
+    /// makes to restart the listener before giving up (zero will
+    /// disable restarting of the listener).
+    pub fn with_restart_attempts(mut self, attempts: usize) -> HandshakerConfig {
+        self.restart_attempts = attempts;
+        self
+    }
+
+    /// Sets the backlog of pending connections that the kernel will
+    /// queue up for the listener before refusing new connections.
+    ///
+    /// Defaults to the platform default used by the `Transport`.
+    pub fn with_listen_backlog(mut self, backlog: i32) -> HandshakerConfig {
+        self.listen_backlog = Some(backlog);
+        self
+    }
+
+    /// Sets the maximum number of connections that `Handshaker` will accept
+    /// from the listener at once (a minimum of one will be used), before
+    /// yielding to other tasks running on the event loop.
+    pub fn with_max_accept_batch(mut self, max: usize) -> HandshakerConfig {
+        self.max_accept_batch = max;
+        self
+    }
+
+    /// Sets the `SocketOptions` that the `Transport` will apply to
+    /// the listener, and to both inbound and outbound connections.
+    ///
+    /// Defaults to no options, leaving the platform defaults in place.
+    pub fn with_socket_options(mut self, options: SocketOptions) -> HandshakerConfig {
+        self.socket_options = options;
+        self
+    }
+
     /// Gets the sink buffer size.
     pub fn sink_buffer_size(&self) -> usize {
         self.sink_buffer_size
@@ -72,15 +176,60 @@ impl HandshakerConfig {
         self.done_buffer_size
     }
 
+    /// Gets the event buffer size.
+    pub fn event_buffer_size(&self) -> usize {
+        self.event_buffer_size
+    }
+
+    /// Gets the max number of half open connections.
+    pub fn max_half_open(&self) -> usize {
+        self.max_half_open
+    }
+
+    /// Gets the max number of bytes used for handshake buffers.
+    pub fn max_buffer_memory(&self) -> usize {
+        self.max_buffer_memory
+    }
+
     /// Gets the handshake timeout.
     pub fn handshake_timeout(&self) -> Duration {
         self.handshake_timeout
     }
 
+    /// Gets the handshake read timeout.
+    pub fn handshake_read_timeout(&self) -> Duration {
+        self.read_timeout.unwrap_or(self.handshake_timeout)
+    }
+
     /// Gets the handshake connection initiation timeout.
     pub fn connect_timeout(&self) -> Duration {
         self.connect_timeout
     }
+
+    /// Gets the listener restart delay.
+    pub fn restart_delay(&self) -> Duration {
+        self.restart_delay
+    }
+
+    /// Gets the number of listener restart attempts.
+    pub fn restart_attempts(&self) -> usize {
+        self.restart_attempts
+    }
+
+    /// Gets the listener backlog, if one was set.
+    pub fn listen_backlog(&self) -> Option<i32> {
+        self.listen_backlog
+    }
+
+    /// Gets the max number of connections accepted at once.
+    pub fn max_accept_batch(&self) -> usize {
+        self.max_accept_batch
+    }
+
+    /// Gets the socket options.
+    pub fn socket_options(&self) -> SocketOptions {
+        self.socket_options
+    }
 }
 
 impl Default for HandshakerConfig {
@@ -89,8 +238,17 @@ impl Default for HandshakerConfig {
             sink_buffer_size: DEFAULT_HANDSHAKE_BUFFER_SIZE,
             wait_buffer_size: DEFAULT_WAIT_BUFFER_SIZE,
             done_buffer_size: DEFAULT_DONE_BUFFER_SIZE,
+            event_buffer_size: DEFAULT_EVENT_BUFFER_SIZE,
+            max_half_open: DEFAULT_MAX_HALF_OPEN,
+            max_buffer_memory: DEFAULT_MAX_BUFFER_MEMORY,
             handshake_timeout: Duration::from_millis(DEFAULT_HANDSHAKE_TIMEOUT_MILLIS),
-            connect_timeout: Duration::from_millis(DEFAULT_HANDSHAKE_CONNECT_TIMEOUT_MILLIS)
+            read_timeout: None,
+            connect_timeout: Duration::from_millis(DEFAULT_HANDSHAKE_CONNECT_TIMEOUT_MILLIS),
+            restart_delay: Duration::from_millis(DEFAULT_RESTART_DELAY_MILLIS),
+            restart_attempts: DEFAULT_RESTART_ATTEMPTS,
+            listen_backlog: None,
+            max_accept_batch: DEFAULT_MAX_ACCEPT_BATCH,
+            socket_options: SocketOptions::default()
          }
     }
 }
\ No newline at end of file
@@ -1,120 +1,247 @@
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 use bittorrent::message::HandshakeMessage;
 use bittorrent::framed::FramedHandshake;
 use message::extensions::Extensions;
 use handshake::handler::HandshakeType;
 use message::initiate::InitiateMessage;
-use message::complete::CompleteMessage;
+use message::complete::{CompleteMessage, Direction};
 use filter::filters::Filters;
 use handshake::handler;
 use handshake::handler::timer::HandshakeTimer;
+use handshake::memory::{self, HandshakeMemory};
+use handshake::negotiate::Negotiation;
+use handshake::restart::HandshakeFailure;
+use transport::TransportKind;
 
 use bip_util::bt::{PeerId};
-use futures::future::Future;
+use bip_util::metrics::Metrics;
+use futures::future::{self, Future};
 use futures::stream::Stream;
 use futures::sink::Sink;
 use tokio_io::{AsyncRead, AsyncWrite};
 
-pub fn execute_handshake<S>(item: HandshakeType<S>, context: &(Extensions, PeerId, Filters, HandshakeTimer))
+const HANDSHAKES_COMPLETED_METRIC: &'static str = "bip_handshake_handshakes_completed";
+const HANDSHAKES_FAILED_METRIC:    &'static str = "bip_handshake_handshakes_failed";
+
+pub fn execute_handshake<S>(item: HandshakeType<S>, context: &(Extensions, PeerId, Filters, HandshakeTimer, HandshakeTimer, Negotiation,
+                                                                      HandshakeMemory, Arc<Metrics>, TransportKind))
     -> Box<Future<Item=Option<CompleteMessage<S>>, Error=()>> where S: AsyncRead + AsyncWrite + 'static {
-    let &(ref ext, ref pid, ref filters, ref timer) = context;
+    let &(ref ext, ref pid, ref filters, ref timer, ref read_timer, ref negotiation, ref memory, ref metrics, ref kind) = context;
+    let addr = match item {
+        HandshakeType::Initiate(_, ref init_msg) => *init_msg.address(),
+        HandshakeType::Complete(_, addr)         => addr
+    };
+
+    // Drop the connection if buffering its handshake would put us over our memory limit
+    let reservation = match memory.reserve(memory::handshake_buffer_len()) {
+        Some(reservation) => reservation,
+        None              => {
+            metrics.counter(HANDSHAKES_FAILED_METRIC, 1);
+            negotiation.report_failure(&addr, HandshakeFailure::MemoryLimit);
+
+            return Box::new(future::ok(None))
+        }
+    };
+    let metrics = metrics.clone();
+    let tracker = filters.tracker().clone();
+    tracker.start_handshake();
+
+    let handshake = match item {
+        HandshakeType::Initiate(sock, init_msg) => initiate_handshake(sock, init_msg, *ext, *pid, filters.clone(), timer.clone(), read_timer.clone(),
+                                                                      negotiation.clone(), *kind),
+        HandshakeType::Complete(sock, addr)     => complete_handshake(sock, addr, *ext, *pid, filters.clone(), timer.clone(), read_timer.clone(),
+                                                                      negotiation.clone(), *kind)
+    };
+
+    // Hold on to our reservation until the handshake finishes
+    Box::new(handshake.then(move |result| {
+        drop(reservation);
+
+        match result {
+            Ok(Some(ref complete_msg)) => {
+                metrics.counter(HANDSHAKES_COMPLETED_METRIC, 1);
+                tracker.finish_handshake(Some(complete_msg.hash()));
+            },
+            _                          => {
+                metrics.counter(HANDSHAKES_FAILED_METRIC, 1);
+                tracker.finish_handshake(None);
+            }
+        }
 
-    match item {
-        HandshakeType::Initiate(sock, init_msg) => initiate_handshake(sock, init_msg, *ext, *pid, filters.clone(), timer.clone()),
-        HandshakeType::Complete(sock, addr)     => complete_handshake(sock, addr, *ext, *pid, filters.clone(), timer.clone())
-    }
+        result
+    }))
 }
 
-fn initiate_handshake<S>(sock: S, init_msg: InitiateMessage, ext: Extensions, pid: PeerId, filters: Filters, timer: HandshakeTimer)
+fn initiate_handshake<S>(sock: S, init_msg: InitiateMessage, ext: Extensions, pid: PeerId, filters: Filters, timer: HandshakeTimer,
+                         read_timer: HandshakeTimer, negotiation: Negotiation, kind: TransportKind)
     -> Box<Future<Item=Option<CompleteMessage<S>>, Error=()>> where S: AsyncRead + AsyncWrite + 'static {
     let framed = FramedHandshake::new(sock);
     
     let (prot, hash, addr) = init_msg.into_parts();
     let handshake_msg = HandshakeMessage::from_parts(prot.clone(), ext, hash, pid);
+    let reporter = negotiation.clone();
 
+    // Failures are reported as they happen, except for negotiation aborts and rejections which the negotiation already reported
     let composed_future = timer.timeout(
             framed.send(handshake_msg)
-                .map_err(|_| ())
+                .map_err(|_| HandshakeFailure::Disconnected)
         )
+        .map_err(Some)
         .and_then(move |framed| {
-            timer.timeout(
+            read_timer.timeout(
                 framed.into_future()
-                    .map_err(|_| ())
-                    .and_then(|(opt_msg, framed)| opt_msg.ok_or(())
+                    .map_err(|_| HandshakeFailure::Disconnected)
+                    .and_then(|(opt_msg, framed)| opt_msg.ok_or(HandshakeFailure::Disconnected)
                     .map(|msg| (msg, framed)))
             )
+            .map_err(Some)
             .and_then(move |(msg, framed)| {
                 let (remote_prot, remote_ext, remote_hash, remote_pid) = msg.into_parts();
                 let socket = framed.into_inner();
                 
                 // Check that it responds with the same hash and protocol, also check our filters
-                if remote_hash != hash ||
-                    remote_prot != prot ||
-                    handler::should_filter(Some(&addr), Some(&remote_prot), Some(&remote_ext), Some(&remote_hash), Some(&remote_pid), &filters) {
-                    Err(())
+                if remote_hash != hash {
+                    Err(Some(HandshakeFailure::WrongInfoHash))
+                } else if remote_prot != prot {
+                    Err(Some(HandshakeFailure::BadProtocol))
+                } else if handler::should_filter(Some(&addr), Some(&remote_prot), Some(&remote_ext), Some(&remote_hash), Some(&remote_pid), &filters) {
+                    Err(Some(HandshakeFailure::Filtered))
+                } else if !negotiation.validate(&addr, &remote_prot, &remote_ext, &remote_pid) {
+                    Err(None)
                 } else {
-                    Ok(Some(CompleteMessage::new(prot, ext.union(&remote_ext), hash, remote_pid, addr, socket)))
+                    // Our handshake was already sent, so a downgrade only affects the extensions we use
+                    negotiation.negotiate(&addr, &remote_prot, &remote_ext, ext)
+                        .map(|neg_ext| {
+                            let both_ext = neg_ext.union(&remote_ext);
+                            let data = negotiation.reserved_data(&addr, &both_ext);
+
+                            Some(CompleteMessage::new(prot, both_ext, hash, remote_pid, addr, Direction::Outbound, kind, socket).with_negotiated(data))
+                        })
+                        .ok_or(None)
                 }
             })
         })
-        .or_else(|_| Ok(None));
+        .or_else(move |opt_failure| {
+            if let Some(failure) = opt_failure {
+                reporter.report_failure(&addr, failure);
+            }
+
+            Ok(None)
+        });
 
     Box::new(composed_future)
 }
 
-fn complete_handshake<S>(sock: S, addr: SocketAddr, ext: Extensions, pid: PeerId, filters: Filters, timer: HandshakeTimer)
+fn complete_handshake<S>(sock: S, addr: SocketAddr, ext: Extensions, pid: PeerId, filters: Filters, timer: HandshakeTimer,
+                         read_timer: HandshakeTimer, negotiation: Negotiation, kind: TransportKind)
     -> Box<Future<Item=Option<CompleteMessage<S>>, Error=()>> where S: AsyncRead + AsyncWrite + 'static {
     let framed = FramedHandshake::new(sock);
+    let reporter = negotiation.clone();
 
-    let composed_future = timer.timeout(
+    // Peers that connect to us but never send their handshake are dropped after the read timeout
+    let composed_future = read_timer.timeout(
             framed.into_future()
-                .map_err(|_| ())
+                .map_err(|_| HandshakeFailure::Disconnected)
                 .and_then(|(opt_msg, framed)| {
-                    opt_msg.ok_or(())
+                    opt_msg.ok_or(HandshakeFailure::Disconnected)
                         .map(|msg| (msg, framed))
             })
         )
+        .map_err(Some)
         .and_then(move |(msg, framed)| {
             let (remote_prot, remote_ext, remote_hash, remote_pid) = msg.into_parts();
             
-            // Check our filters
-            if handler::should_filter(Some(&addr), Some(&remote_prot), Some(&remote_ext), Some(&remote_hash), Some(&remote_pid), &filters) {
-                Err(())
+            // Check our filters and validator, then let the negotiator pick the extensions we advertise back to the peer
+            let res_ext = if handler::should_filter(Some(&addr), Some(&remote_prot), Some(&remote_ext), Some(&remote_hash), Some(&remote_pid), &filters) {
+                Err(Some(HandshakeFailure::Filtered))
+            } else if !negotiation.validate(&addr, &remote_prot, &remote_ext, &remote_pid) {
+                Err(None)
             } else {
+                negotiation.negotiate(&addr, &remote_prot, &remote_ext, ext).ok_or(None)
+            };
+
+            res_ext.map(|ext| {
                 let handshake_msg = HandshakeMessage::from_parts(remote_prot.clone(), ext, remote_hash, pid);
 
-                Ok(timer.timeout(framed.send(handshake_msg)
-                        .map_err(|_| ())
+                timer.timeout(framed.send(handshake_msg)
+                        .map_err(|_| HandshakeFailure::Disconnected)
                         .map(move |framed| {
                             let socket = framed.into_inner();
+                            let both_ext = ext.union(&remote_ext);
+                            let data = negotiation.reserved_data(&addr, &both_ext);
 
-                            Some(CompleteMessage::new(remote_prot, ext.union(&remote_ext), remote_hash, remote_pid, addr, socket))
+                            Some(CompleteMessage::new(remote_prot, both_ext, remote_hash, remote_pid, addr, Direction::Inbound, kind, socket)
+                                .with_negotiated(data))
                         })
-                ))
-            }
+                )
+                .map_err(Some)
+            })
         })
         .flatten()
-        .or_else(|_| Ok(None));
+        .or_else(move |opt_failure| {
+            if let Some(failure) = opt_failure {
+                reporter.report_failure(&addr, failure);
+            }
+
+            Ok(None)
+        });
 
     Box::new(composed_future)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::{Cursor};
+    use std::io::{self, Cursor, Read, Write};
+    use std::net::SocketAddr;
+    use std::sync::Arc;
     use std::time::Duration;
 
     use super::{HandshakeMessage};
     use message::extensions::{self, Extensions};
     use message::protocol::Protocol;
     use message::initiate::InitiateMessage;
+    use message::complete::Direction;
+    use transport::TransportKind;
     use filter::filters::Filters;
     use handshake::handler::timer::HandshakeTimer;
+    use handshake::negotiate::{AbortReason, Negotiation, NegotiationDecision};
+    use handshake::restart::{self, HandshakerEvent, HandshakeFailure};
 
     use bip_util::bt::{self, PeerId, InfoHash};
+    use tokio_io::{AsyncRead, AsyncWrite};
     use tokio_timer;
+    use futures::{Async, Poll};
     use futures::future::{self, Future};
+    use futures::stream::Stream;
+
+    /// Socket for a remote peer that accepts our data, but never sends any of its own.
+    struct StalledSocket;
+
+    impl Read for StalledSocket {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::WouldBlock, "StalledSocket Never Sends Data"))
+        }
+    }
+
+    impl AsyncRead for StalledSocket {}
+
+    impl Write for StalledSocket {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncWrite for StalledSocket {
+        fn shutdown(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
 
     fn any_peer_id() -> PeerId {
         [22u8; bt::PEER_ID_LEN].into()
@@ -136,6 +263,10 @@ mod tests {
         HandshakeTimer::new(tokio_timer::wheel().build(), Duration::from_millis(100))
     }
 
+    fn any_negotiation() -> Negotiation {
+        Negotiation::new(None, restart::event_queue(10).0)
+    }
+
     #[test]
     fn positive_initiate_handshake() {
         let remote_pid = any_peer_id();
@@ -163,13 +294,16 @@ mod tests {
         let init_timer = any_handshake_timer();
 
         // Wrap in lazy since we can call wait on non sized types...
-        let complete_message = future::lazy(|| super::initiate_handshake(writer, init_message, init_ext, init_pid, init_filters, init_timer)).wait().unwrap().unwrap();
+        let complete_message = future::lazy(|| super::initiate_handshake(writer, init_message, init_ext, init_pid, init_filters, init_timer.clone(), init_timer,
+                                                             any_negotiation(), TransportKind::Tcp)).wait().unwrap().unwrap();
 
         assert_eq!(init_prot, *complete_message.protocol());
         assert_eq!(init_ext, *complete_message.extensions());
         assert_eq!(init_hash, *complete_message.hash());
         assert_eq!(remote_pid, *complete_message.peer_id());
         assert_eq!(remote_addr, *complete_message.address());
+        assert_eq!(Direction::Outbound, complete_message.direction());
+        assert_eq!(TransportKind::Tcp, complete_message.transport());
 
         let sent_message = HandshakeMessage::from_bytes(&complete_message.socket().get_ref()[..remote_message.write_len()]).unwrap().1;
         let local_message = HandshakeMessage::from_parts(init_prot, init_ext, init_hash, init_pid);
@@ -202,13 +336,16 @@ mod tests {
         let comp_timer = any_handshake_timer();
 
         // Wrap in lazy since we can call wait on non sized types...
-        let complete_message = future::lazy(|| super::complete_handshake(writer, remote_addr, comp_ext, comp_pid, comp_filters, comp_timer)).wait().unwrap().unwrap();
+        let complete_message = future::lazy(|| super::complete_handshake(writer, remote_addr, comp_ext, comp_pid, comp_filters, comp_timer.clone(), comp_timer,
+                                                             any_negotiation(), TransportKind::Utp)).wait().unwrap().unwrap();
 
         assert_eq!(remote_protocol, *complete_message.protocol());
         assert_eq!(comp_ext, *complete_message.extensions());
         assert_eq!(remote_hash, *complete_message.hash());
         assert_eq!(remote_pid, *complete_message.peer_id());
         assert_eq!(remote_addr, *complete_message.address());
+        assert_eq!(Direction::Inbound, complete_message.direction());
+        assert_eq!(TransportKind::Utp, complete_message.transport());
 
         let sent_message = HandshakeMessage::from_bytes(&complete_message.socket().get_ref()[remote_message.write_len()..]).unwrap().1;
         let local_message = HandshakeMessage::from_parts(remote_protocol, comp_ext, remote_hash, comp_pid);
@@ -218,4 +355,97 @@ mod tests {
         assert_eq!(local_message, sent_message);
         assert_eq!(remote_message, recv_message);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn negative_initiate_handshake_read_timeout() {
+        let remote_addr = "1.2.3.4:5".parse().unwrap();
+        let init_message = InitiateMessage::new(Protocol::BitTorrent, any_info_hash(), remote_addr);
+
+        let timer = HandshakeTimer::new(tokio_timer::wheel().build(), Duration::from_millis(10000));
+        let read_timer = any_handshake_timer();
+
+        let opt_complete_message = future::lazy(|| super::initiate_handshake(StalledSocket, init_message, any_extensions(), any_other_peer_id(),
+                                                                             Filters::new(), timer, read_timer, any_negotiation(), TransportKind::Tcp)).wait().unwrap();
+
+        assert!(opt_complete_message.is_none());
+    }
+
+    #[test]
+    fn negative_complete_handshake_read_timeout() {
+        let remote_addr = "1.2.3.4:5".parse().unwrap();
+
+        let timer = HandshakeTimer::new(tokio_timer::wheel().build(), Duration::from_millis(10000));
+        let read_timer = any_handshake_timer();
+
+        let opt_complete_message = future::lazy(|| super::complete_handshake(StalledSocket, remote_addr, any_extensions(), any_other_peer_id(),
+                                                                             Filters::new(), timer, read_timer, any_negotiation(), TransportKind::Tcp)).wait().unwrap();
+
+        assert!(opt_complete_message.is_none());
+    }
+
+    #[test]
+    fn positive_complete_handshake_negotiate_downgrade() {
+        let remote_addr = "1.2.3.4:5".parse().unwrap();
+        let remote_message = HandshakeMessage::from_parts(Protocol::BitTorrent, any_extensions(), any_info_hash(), any_peer_id());
+
+        let mut writer = Cursor::new(vec![0u8; remote_message.write_len() * 2]);
+        remote_message.write_bytes(&mut writer).unwrap();
+        writer.set_position(0);
+
+        let negotiation = Negotiation::new(Some(Arc::new(|_: &SocketAddr, _: &Protocol, _: &Extensions| {
+            NegotiationDecision::Downgrade(Extensions::new())
+        })), restart::event_queue(10).0);
+
+        let complete_message = future::lazy(|| super::complete_handshake(writer, remote_addr, any_extensions(), any_other_peer_id(), Filters::new(),
+                                                                         any_handshake_timer(), any_handshake_timer(), negotiation, TransportKind::Tcp)).wait().unwrap().unwrap();
+
+        let sent_message = HandshakeMessage::from_bytes(&complete_message.socket().get_ref()[remote_message.write_len()..]).unwrap().1;
+        let (_, sent_ext, _, _) = sent_message.into_parts();
+
+        assert_eq!(Extensions::new(), sent_ext);
+    }
+
+    #[test]
+    fn negative_complete_handshake_negotiate_abort() {
+        let remote_addr = "1.2.3.4:5".parse().unwrap();
+        let remote_message = HandshakeMessage::from_parts(Protocol::Custom(b"Weird Protocol".to_vec()), any_extensions(), any_info_hash(), any_peer_id());
+
+        let mut writer = Cursor::new(vec![0u8; remote_message.write_len() * 2]);
+        remote_message.write_bytes(&mut writer).unwrap();
+        writer.set_position(0);
+
+        let negotiation = Negotiation::new(Some(Arc::new(|_: &SocketAddr, prot: &Protocol, _: &Extensions| {
+            match prot {
+                &Protocol::BitTorrent => NegotiationDecision::Continue,
+                _                     => NegotiationDecision::Abort(AbortReason::UnsupportedProtocol)
+            }
+        })), restart::event_queue(10).0);
+
+        let opt_complete_message = future::lazy(|| super::complete_handshake(writer, remote_addr, any_extensions(), any_other_peer_id(), Filters::new(),
+                                                                             any_handshake_timer(), any_handshake_timer(), negotiation, TransportKind::Tcp)).wait().unwrap();
+
+        assert!(opt_complete_message.is_none());
+    }
+
+    #[test]
+    fn negative_initiate_handshake_wrong_info_hash() {
+        let remote_addr = "1.2.3.4:5".parse().unwrap();
+        let remote_message = HandshakeMessage::from_parts(Protocol::BitTorrent, any_extensions(), [66u8; bt::INFO_HASH_LEN].into(), any_peer_id());
+
+        let mut writer = Cursor::new(vec![0u8; remote_message.write_len() * 2]);
+        writer.set_position(remote_message.write_len() as u64);
+        remote_message.write_bytes(&mut writer).unwrap();
+        writer.set_position(0);
+
+        let init_message = InitiateMessage::new(Protocol::BitTorrent, any_info_hash(), remote_addr);
+        let (event_send, event_recv) = restart::event_queue(10);
+
+        let opt_complete_message = future::lazy(|| super::initiate_handshake(writer, init_message, any_extensions(), any_other_peer_id(), Filters::new(),
+                                                                             any_handshake_timer(), any_handshake_timer(), Negotiation::new(None, event_send),
+                                                                             TransportKind::Tcp)).wait().unwrap();
+        assert!(opt_complete_message.is_none());
+
+        let events = event_recv.collect().wait().unwrap();
+        assert_eq!(vec![HandshakerEvent::HandshakeFailed(remote_addr, HandshakeFailure::WrongInfoHash)], events);
+    }
+}
@@ -1,5 +1,8 @@
+use std::io;
+
 use handshake::handler::HandshakeType;
-use transport::Transport;
+use handshake::restart::{EventSender, HandshakerEvent, HandshakeFailure};
+use transport::{ConfiguredSocket, SocketOptions, Transport};
 use message::initiate::InitiateMessage;
 use filter::filters::Filters;
 use handshake::handler;
@@ -9,23 +12,37 @@ use futures::future::{self, Future};
 use tokio_core::reactor::Handle;
 
 /// Handle the initiation of connections, which are returned as a HandshakeType.
-pub fn initiator_handler<T>(item: InitiateMessage, context: &(T, Filters, Handle, HandshakeTimer)) -> Box<Future<Item=Option<HandshakeType<T::Socket>>,Error=()>>
-    where T: Transport {
-    let &(ref transport, ref filters, ref handle, ref timer) = context;
+///
+/// Filtered peers and failed connections are reported as a `HandshakerEvent`.
+pub fn initiator_handler<T>(item: InitiateMessage, context: &(T, Filters, Handle, HandshakeTimer, EventSender, SocketOptions))
+    -> Box<Future<Item=Option<HandshakeType<T::Socket>>,Error=()>> where T: Transport + Clone + 'static {
+    let &(ref transport, ref filters, ref handle, ref timer, ref events, ref options) = context;
+    let addr = *item.address();
 
     if handler::should_filter(Some(item.address()), Some(item.protocol()), None, Some(item.hash()), None, filters) {
+        events.send(HandshakerEvent::HandshakeFailed(addr, HandshakeFailure::Filtered));
+
         Box::new(future::ok(None))
     } else {
+        let events = events.clone();
         let res_connect = transport.connect(item.address(), handle)
-            .map(|connect| timer.timeout(connect));
+            .map(|connect| timer.timeout(ConfiguredSocket::new(transport.clone(), connect, *options)));
 
         Box::new(future::lazy(|| res_connect)
             .flatten()
             .map(|socket| {
                 Some(HandshakeType::Initiate(socket, item))
             })
-            .or_else(|_| Ok(None))
-        )    
+            .or_else(move |error| {
+                let failure = match error.kind() {
+                    io::ErrorKind::TimedOut => HandshakeFailure::Timeout,
+                    _                       => HandshakeFailure::ConnectFailed
+                };
+                events.send(HandshakerEvent::HandshakeFailed(addr, failure));
+
+                Ok(None)
+            })
+        )
     }
 }
 
@@ -36,12 +53,14 @@ mod tests {
     use filter::filters::test_filters::{BlockAddrFilter, BlockProtocolFilter, BlockPeerIdFilter};
     use message::protocol::Protocol;
     use message::initiate::InitiateMessage;
+    use transport::SocketOptions;
     use transport::test_transports::MockTransport;
     use handshake::handler::timer::HandshakeTimer;
+    use handshake::restart::{self, HandshakerEvent, HandshakeFailure};
     use std::time::Duration;
 
     use bip_util::bt::{self, InfoHash, PeerId};
-    use futures::Future;
+    use futures::{Future, Stream};
     use tokio_core::reactor::{Core};
     use tokio_timer;
 
@@ -59,7 +78,7 @@ mod tests {
         let exp_message = InitiateMessage::new(Protocol::BitTorrent, any_info_hash(), "1.2.3.4:5".parse().unwrap());
         let timer = HandshakeTimer::new(tokio_timer::wheel().build(), Duration::from_millis(1000));
 
-        let recv_enum_item = super::initiator_handler(exp_message.clone(), &(MockTransport, Filters::new(), core.handle(), timer)).wait().unwrap();
+        let recv_enum_item = super::initiator_handler(exp_message.clone(), &(MockTransport, Filters::new(), core.handle(), timer, restart::event_queue(10).0, SocketOptions::default())).wait().unwrap();
         let recv_item = match recv_enum_item {
             Some(HandshakeType::Initiate(_, msg)) => msg,
             Some(HandshakeType::Complete(_, _))   |
@@ -79,7 +98,7 @@ mod tests {
 
         let exp_message = InitiateMessage::new(Protocol::BitTorrent, any_info_hash(), "1.2.3.4:5".parse().unwrap());
 
-        let recv_enum_item = super::initiator_handler(exp_message.clone(), &(MockTransport, filters, core.handle(), timer)).wait().unwrap();
+        let recv_enum_item = super::initiator_handler(exp_message.clone(), &(MockTransport, filters, core.handle(), timer, restart::event_queue(10).0, SocketOptions::default())).wait().unwrap();
         let recv_item = match recv_enum_item {
             Some(HandshakeType::Initiate(_, msg)) => msg,
             Some(HandshakeType::Complete(_, _))   |
@@ -99,7 +118,7 @@ mod tests {
 
         let exp_message = InitiateMessage::new(Protocol::BitTorrent, any_info_hash(), "1.2.3.4:5".parse().unwrap());
 
-        let recv_enum_item = super::initiator_handler(exp_message.clone(), &(MockTransport, filters, core.handle(), timer)).wait().unwrap();
+        let recv_enum_item = super::initiator_handler(exp_message.clone(), &(MockTransport, filters, core.handle(), timer, restart::event_queue(10).0, SocketOptions::default())).wait().unwrap();
         let recv_item = match recv_enum_item {
             Some(HandshakeType::Initiate(_, msg)) => msg,
             Some(HandshakeType::Complete(_, _))   |
@@ -119,11 +138,15 @@ mod tests {
 
         let exp_message = InitiateMessage::new(Protocol::Custom(vec![1, 2, 3, 4]), any_info_hash(), "1.2.3.4:5".parse().unwrap());
 
-        let recv_enum_item = super::initiator_handler(exp_message.clone(), &(MockTransport, filters, core.handle(), timer)).wait().unwrap();
+        let (event_send, event_recv) = restart::event_queue(10);
+        let recv_enum_item = super::initiator_handler(exp_message.clone(), &(MockTransport, filters, core.handle(), timer, event_send, SocketOptions::default())).wait().unwrap();
         match recv_enum_item {
             None                                => (),
             Some(HandshakeType::Initiate(_, _)) |
             Some(HandshakeType::Complete(_, _)) => panic!("Expected No Handshake")
         }
+
+        let events = event_recv.collect().wait().unwrap();
+        assert_eq!(vec![HandshakerEvent::HandshakeFailed(*exp_message.address(), HandshakeFailure::Filtered)], events);
     }
 }
\ No newline at end of file
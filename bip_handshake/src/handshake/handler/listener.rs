@@ -1,6 +1,7 @@
 use std::net::SocketAddr;
 
 use handshake::handler::HandshakeType;
+use handshake::restart::{EventSender, HandshakerEvent, HandshakeFailure};
 use filter::filters::Filters;
 use handshake::handler;
 
@@ -12,10 +13,14 @@ pub struct ListenerHandler<S> {
 }
 
 impl<S> ListenerHandler<S> {
-    pub fn new(item: (S, SocketAddr), context: &Filters) -> ListenerHandler<S> {
+    pub fn new(item: (S, SocketAddr), context: &(Filters, EventSender)) -> ListenerHandler<S> {
         let (sock, addr) = item;
+        let &(ref filters, ref events) = context;
+        filters.tracker().record_inbound();
         
-        let opt_item = if handler::should_filter(Some(&addr), None, None, None, None, context) {
+        let opt_item = if handler::should_filter(Some(&addr), None, None, None, None, filters) {
+            events.send(HandshakerEvent::HandshakeFailed(addr, HandshakeFailure::Filtered));
+
             None
         } else {
             Some(HandshakeType::Complete(sock, addr))
@@ -39,15 +44,16 @@ mod tests {
     use super::ListenerHandler;
     use filter::filters::Filters;
     use handshake::handler::HandshakeType;
-    use filter::filters::test_filters::{BlockAddrFilter, BlockProtocolFilter};
+    use handshake::restart::{self, HandshakerEvent, HandshakeFailure};
+    use filter::filters::test_filters::{BlockAddrFilter, BlockProtocolFilter, BlockInboundRateFilter};
     use message::protocol::Protocol;
 
-    use futures::Future;
+    use futures::{Future, Stream};
 
     #[test]
     fn positive_empty_filter() {
         let exp_item = ("Testing", "0.0.0.0:0".parse().unwrap());
-        let handler = ListenerHandler::new(exp_item.clone(), &Filters::new());
+        let handler = ListenerHandler::new(exp_item.clone(), &(Filters::new(), restart::event_queue(10).0));
 
         let recv_enum_item = handler.wait().unwrap();
 
@@ -66,7 +72,7 @@ mod tests {
         filters.add_filter(BlockAddrFilter::new("1.2.3.4:5".parse().unwrap()));
 
         let exp_item = ("Testing", "0.0.0.0:0".parse().unwrap());
-        let handler = ListenerHandler::new(exp_item.clone(), &filters);
+        let handler = ListenerHandler::new(exp_item.clone(), &(filters, restart::event_queue(10).0));
 
         let recv_enum_item = handler.wait().unwrap();
 
@@ -85,7 +91,7 @@ mod tests {
         filters.add_filter(BlockProtocolFilter::new(Protocol::BitTorrent));
 
         let exp_item = ("Testing", "0.0.0.0:0".parse().unwrap());
-        let handler = ListenerHandler::new(exp_item.clone(), &filters);
+        let handler = ListenerHandler::new(exp_item.clone(), &(filters, restart::event_queue(10).0));
 
         let recv_enum_item = handler.wait().unwrap();
 
@@ -104,7 +110,8 @@ mod tests {
         filters.add_filter(BlockAddrFilter::new("0.0.0.0:0".parse().unwrap()));
 
         let exp_item = ("Testing", "0.0.0.0:0".parse().unwrap());
-        let handler = ListenerHandler::new(exp_item.clone(), &filters);
+        let (event_send, event_recv) = restart::event_queue(10);
+        let handler = ListenerHandler::new(exp_item.clone(), &(filters, event_send));
 
         let recv_enum_item = handler.wait().unwrap();
 
@@ -113,5 +120,21 @@ mod tests {
             Some(HandshakeType::Initiate(_, _)) => panic!("Expected No HandshakeType"),
             None                                => ()
         }
+
+        let events = event_recv.collect().wait().unwrap();
+        assert_eq!(vec![HandshakerEvent::HandshakeFailed(exp_item.1, HandshakeFailure::Filtered)], events);
+    }
+
+    #[test]
+    fn positive_fails_inbound_rate_filter() {
+        let filters = Filters::new();
+        filters.add_filter(BlockInboundRateFilter::new(1));
+
+        let exp_item = ("Testing", "0.0.0.0:0".parse().unwrap());
+        let first_item = ListenerHandler::new(exp_item.clone(), &(filters.clone(), restart::event_queue(10).0)).wait().unwrap();
+        let second_item = ListenerHandler::new(exp_item.clone(), &(filters, restart::event_queue(10).0)).wait().unwrap();
+
+        assert!(first_item.is_some());
+        assert!(second_item.is_none());
     }
-}
\ No newline at end of file
+}
@@ -86,6 +86,9 @@ pub fn should_filter(addr: Option<&SocketAddr>, prot: Option<&Protocol>, ext: Op
     let mut ext_filter = FilterDecision::Pass;
     let mut hash_filter = FilterDecision::Pass;
     let mut pid_filter = FilterDecision::Pass;
+    let mut context_filter = FilterDecision::Pass;
+
+    let context = filters.tracker().context(hash);
 
     // Choose on individual fields
     filters.access_filters(|ref_filters| {
@@ -95,9 +98,10 @@ pub fn should_filter(addr: Option<&SocketAddr>, prot: Option<&Protocol>, ext: Op
             ext_filter = ext_filter.choose(ref_filter.on_ext(ext));
             hash_filter = hash_filter.choose(ref_filter.on_hash(hash));
             pid_filter = pid_filter.choose(ref_filter.on_pid(pid));
+            context_filter = context_filter.choose(ref_filter.on_context(&context));
         }
     });
 
     // Choose across the results of individual fields
-    addr_filter.choose(prot_filter).choose(ext_filter).choose(hash_filter).choose(pid_filter) == FilterDecision::Block
+    addr_filter.choose(prot_filter).choose(ext_filter).choose(hash_filter).choose(pid_filter).choose(context_filter) == FilterDecision::Block
 }
\ No newline at end of file
@@ -1,5 +1,7 @@
 use std::time::Duration;
 
+use handshake::restart::HandshakeFailure;
+
 use futures::Future;
 use tokio_timer::{Timer, TimeoutError, Timeout};
 
@@ -20,6 +22,12 @@ impl HandshakeTimer {
     }
 }
 
+impl<F> From<TimeoutError<F>> for HandshakeFailure {
+    fn from(_: TimeoutError<F>) -> HandshakeFailure {
+        HandshakeFailure::Timeout
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
@@ -2,6 +2,9 @@ use std::net::{SocketAddr, Ipv4Addr, SocketAddrV4};
 use std::io;
 use std::time::Duration;
 use std::cmp;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use discovery::DiscoveryInfo;
 use message::initiate::InitiateMessage;
@@ -11,15 +14,19 @@ use handshake::handler::handshaker;
 use handshake::handler::initiator;
 use handshake::handler::listener::ListenerHandler;
 use handshake::handler;
-use transport::Transport;
+use transport::{self, Transport};
 use local_addr::LocalAddr;
 use filter::filters::Filters;
 use filter::{HandshakeFilter, HandshakeFilters};
 use handshake::config::HandshakerConfig;
 use handshake::handler::timer::HandshakeTimer;
+use handshake::memory::HandshakeMemory;
+use handshake::negotiate::{Negotiation, ProtocolNegotiator, ReservedNegotiator, PeerIdValidator};
+use handshake::restart::{self, RestartListener, HandshakerEvents};
 
 use bip_util::bt::PeerId;
 use bip_util::convert;
+use bip_util::metrics::{self, Metrics};
 use futures::{StartSend, Poll};
 use futures::sync::mpsc::{self, Sender, Receiver, SendError};
 use futures::sink::Sink;
@@ -30,13 +37,17 @@ use tokio_timer::{self};
 use rand::{self, Rng};
 
 /// Build configuration for `Handshaker` object creation.
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct HandshakerBuilder {
-    bind:   SocketAddr,
-    port:   u16,
-    pid:    PeerId,
-    ext:    Extensions,
-    config: HandshakerConfig
+    bind:       SocketAddr,
+    port:       u16,
+    pid:        PeerId,
+    ext:        Extensions,
+    config:     HandshakerConfig,
+    metrics:    Arc<Metrics>,
+    negotiator: Option<Arc<ProtocolNegotiator + Send + Sync>>,
+    validator:  Option<Arc<PeerIdValidator + Send + Sync>>,
+    reserved:   Vec<Arc<ReservedNegotiator + Send + Sync>>
 }
 
 impl HandshakerBuilder {
@@ -52,7 +63,8 @@ impl HandshakerBuilder {
         let default_peer_id = PeerId::from_bytes(&convert::four_bytes_to_array(seed));
 
         HandshakerBuilder{ bind: default_sock_addr, port: default_v4_port, pid: default_peer_id,
-                           ext: Extensions::new(), config: HandshakerConfig::default() }
+                           ext: Extensions::new(), config: HandshakerConfig::default(), metrics: metrics::noop(),
+                           negotiator: None, validator: None, reserved: Vec::new() }
     }
 
     /// Address that the host will listen on.
@@ -67,7 +79,7 @@ impl HandshakerBuilder {
     /// Port that external peers should connect on.
     ///
     /// Defaults to the port that is being listened on (will only work if the
-    /// host is not natted).
+    /// host is not natted). Can be changed after building, see `HandshakerSink::set_port`.
     pub fn with_open_port(&mut self, port: u16) -> &mut HandshakerBuilder {
         self.port = port;
 
@@ -101,6 +113,50 @@ impl HandshakerBuilder {
         self
     }
 
+    /// Metrics that handshake results will be reported to.
+    ///
+    /// Reports `bip_handshake_handshakes_completed` and `bip_handshake_handshakes_failed`
+    /// counters. Defaults to discarding all metrics.
+    pub fn with_metrics(&mut self, metrics: Arc<Metrics>) -> &mut HandshakerBuilder {
+        self.metrics = metrics;
+
+        self
+    }
+
+    /// Negotiator that will inspect the protocol and extensions of each remote peer mid handshake.
+    ///
+    /// The negotiator can continue the handshake, downgrade the extensions we use with the
+    /// peer, or abort the handshake, which is reported as a `HandshakerEvent`. Defaults to
+    /// continuing all handshakes.
+    pub fn with_protocol_negotiator<N>(&mut self, negotiator: N) -> &mut HandshakerBuilder
+        where N: ProtocolNegotiator + Send + Sync + 'static {
+        self.negotiator = Some(Arc::new(negotiator));
+
+        self
+    }
+
+    /// Validator that will inspect the `PeerId` of each remote peer mid handshake.
+    ///
+    /// The validator can reject the peer with a reason, which is reported as a `HandshakerEvent`.
+    /// Defaults to accepting all peer ids.
+    pub fn with_peer_id_validator<V>(&mut self, validator: V) -> &mut HandshakerBuilder
+        where V: PeerIdValidator + Send + Sync + 'static {
+        self.validator = Some(Arc::new(validator));
+
+        self
+    }
+
+    /// Negotiator for an extension advertised through the reserved bits of the handshake.
+    ///
+    /// The negotiator will set its reserved bits on top of the extensions we advertise, and any
+    /// data it returns for a completed handshake will be attached to the `CompleteMessage`.
+    pub fn add_reserved_negotiator<N>(&mut self, negotiator: N) -> &mut HandshakerBuilder
+        where N: ReservedNegotiator + Send + Sync + 'static {
+        self.reserved.push(Arc::new(negotiator));
+
+        self
+    }
+
     /// Build a `Handshaker` over the given `Transport` with a `Remote` instance.
     pub fn build<T>(&self, transport: T, handle: Handle) -> io::Result<Handshaker<T::Socket>>
         where T: Transport + 'static {
@@ -111,12 +167,28 @@ impl HandshakerBuilder {
 //----------------------------------------------------------------------------------//
 
 /// Handshaker which is both `Stream` and `Sink`.
+///
+/// If the underlying listener encounters a fatal error, it will be restarted on the same
+/// address, preserving any filters, configuration, and the peer id of the `Handshaker`.
 pub struct Handshaker<S> {
     sink:   HandshakerSink,
-    stream: HandshakerStream<S>
+    stream: HandshakerStream<S>,
+    events: Option<HandshakerEvents>
 }
 
 impl<S> Handshaker<S> {
+    /// Take the `Stream` of `HandshakerEvent`s describing listener restarts and aborted negotiations.
+    ///
+    /// Events are only queued up once they have been taken, so clients that never take them
+    /// dont pay for them. Returns `None` if the events have already been taken.
+    pub fn take_events(&mut self) -> Option<HandshakerEvents> {
+        self.events.take().map(|events| {
+            restart::start_receiving(&events);
+
+            events
+        })
+    }
+
     /// Splits the `Handshaker` into its parts.
     ///
     /// This is an enhanced version of `Stream::split` in that the returned `Sink` implements
@@ -124,6 +196,18 @@ impl<S> Handshaker<S> {
     pub fn into_parts(self) -> (HandshakerSink, HandshakerStream<S>) {
         (self.sink, self.stream)
     }
+
+    /// Number of bytes currently used by the buffers of in progress handshakes.
+    pub fn buffer_memory_usage(&self) -> usize {
+        self.sink.buffer_memory_usage()
+    }
+
+    /// Set the port that external peers should connect on.
+    ///
+    /// See `HandshakerSink::set_port`.
+    pub fn set_port(&self, port: u16) {
+        self.sink.set_port(port)
+    }
 }
 
 impl<S> DiscoveryInfo for Handshaker<S> {
@@ -139,41 +223,62 @@ impl<S> DiscoveryInfo for Handshaker<S> {
 impl<S> Handshaker<S> where S: AsyncRead + AsyncWrite + 'static {
     fn with_builder<T>(builder: &HandshakerBuilder, transport: T, handle: Handle) -> io::Result<Handshaker<T::Socket>>
         where T: Transport<Socket=S> + 'static {
-        let listener = try!(transport.listen(&builder.bind, &handle));
-
-        // Resolve our "real" public port
-        let open_port = if builder.port == 0 {
-            try!(listener.local_addr()).port()
-        } else { builder.port };
+        let listener = try!(transport::listen(&transport, &builder.bind, builder.config.listen_backlog(), &builder.config.socket_options(), &handle));
+        let listen_addr = try!(listener.local_addr());
+        let kind = transport.kind();
 
         let config = builder.config;
         let (addr_send, addr_recv) = mpsc::channel(config.sink_buffer_size());
         let (hand_send, hand_recv) = mpsc::channel(config.wait_buffer_size());
         let (sock_send, sock_recv) = mpsc::channel(config.done_buffer_size());
-        
+        let (event_send, event_recv) = restart::idle_event_queue(config.event_buffer_size());
+
         let filters = Filters::new();
-        let (handshake_timer, initiate_timer) = configured_handshake_timers(config.handshake_timeout(), config.connect_timeout());
+        let memory = HandshakeMemory::new(config.max_buffer_memory());
+        let (handshake_timer, read_timer, initiate_timer) = configured_handshake_timers(config.handshake_timeout(), config.handshake_read_timeout(),
+                                                                                        config.connect_timeout());
+
+        // Restart on the address we actually bound to, so our advertised port stays the same
+        let transport = Rc::new(transport);
+        let listener = RestartListener::new(transport.clone(), listen_addr, listener, handle.clone(), config.restart_delay(),
+                                            config.restart_attempts(), event_send.clone())
+            .with_backlog(config.listen_backlog())
+            .with_socket_options(config.socket_options())
+            .with_max_accept_batch(config.max_accept_batch());
+        let negotiation = Negotiation::new(builder.negotiator.clone(), event_send.clone())
+            .with_validator(builder.validator.clone())
+            .with_reserved(builder.reserved.clone());
+
+        // Advertise the reserved bits of any custom extensions alongside our own
+        let mut ext = builder.ext;
+        for negotiator in builder.reserved.iter() {
+            negotiator.reserve(&mut ext);
+        }
+
+        // Connect to peers in parallel, but only up to the max half open, any excess will sit in the sink buffer
+        let initiate_context = (transport, filters.clone(), handle.clone(), initiate_timer, event_send.clone(), config.socket_options());
+        let initiated = addr_recv.map(move |item| initiator::initiator_handler(item, &initiate_context))
+            .buffer_unordered(cmp::max(config.max_half_open(), 1));
 
         // Hook up our pipeline of handlers which will take some connection info, process it, and forward it
-        handler::loop_handler(addr_recv, initiator::initiator_handler, hand_send.clone(), (transport, filters.clone(), handle.clone(), initiate_timer), &handle);
-        handler::loop_handler(listener, ListenerHandler::new, hand_send, filters.clone(), &handle);
-        handler::loop_handler(hand_recv.map(Result::Ok).buffer_unordered(100), handshaker::execute_handshake, sock_send, (builder.ext, builder.pid, filters.clone(), handshake_timer), &handle);
+        handler::loop_handler(initiated, |opt_item, _: &()| Ok::<_, ()>(opt_item), hand_send.clone(), (), &handle);
+        handler::loop_handler(listener, ListenerHandler::new, hand_send, (filters.clone(), event_send), &handle);
+        handler::loop_handler(hand_recv.map(Result::Ok).buffer_unordered(100), handshaker::execute_handshake, sock_send, (ext, builder.pid, filters.clone(), handshake_timer, read_timer, negotiation, memory.clone(), builder.metrics.clone(), kind), &handle);
 
-        let sink = HandshakerSink::new(addr_send, open_port, builder.pid, filters);
+        let sink = HandshakerSink::new(addr_send, listen_addr.port(), builder.port, builder.pid, filters, memory);
         let stream = HandshakerStream::new(sock_recv);
-
-        Ok(Handshaker{ sink: sink, stream: stream })
+        Ok(Handshaker{ sink: sink, stream: stream, events: Some(event_recv) })
     }
 }
 
-/// Configure a timer wheel and create a `HandshakeTimer`.
-fn configured_handshake_timers(duration_one: Duration, duration_two: Duration) -> (HandshakeTimer, HandshakeTimer) {
+/// Configure a timer wheel and create a `HandshakeTimer` for each duration.
+fn configured_handshake_timers(duration_one: Duration, duration_two: Duration, duration_three: Duration) -> (HandshakeTimer, HandshakeTimer, HandshakeTimer) {
     let timer = tokio_timer::wheel()
         .num_slots(64)
-        .max_timeout(cmp::max(duration_one, duration_two))
+        .max_timeout(cmp::max(duration_one, cmp::max(duration_two, duration_three)))
         .build();
 
-    (HandshakeTimer::new(timer.clone(), duration_one), HandshakeTimer::new(timer, duration_two))
+    (HandshakeTimer::new(timer.clone(), duration_one), HandshakeTimer::new(timer.clone(), duration_two), HandshakeTimer::new(timer, duration_three))
 }
 
 impl<S> Sink for Handshaker<S> {
@@ -219,21 +324,43 @@ impl<S> HandshakeFilters for Handshaker<S> {
 /// `Sink` portion of the `Handshaker` for initiating handshakes.
 #[derive(Clone)]
 pub struct HandshakerSink {
-    send:    Sender<InitiateMessage>,
-    port:    u16,
-    pid:     PeerId,
-    filters: Filters
+    send:        Sender<InitiateMessage>,
+    listen_port: u16,
+    // Shared across clones, so all discovery services see port changes
+    open_port:   Arc<AtomicUsize>,
+    pid:         PeerId,
+    filters:     Filters,
+    memory:      HandshakeMemory
 }
 
 impl HandshakerSink {
-    fn new(send: Sender<InitiateMessage>, port: u16, pid: PeerId, filters: Filters) -> HandshakerSink {
-        HandshakerSink{ send: send, port: port, pid: pid, filters: filters }
+    fn new(send: Sender<InitiateMessage>, listen_port: u16, open_port: u16, pid: PeerId, filters: Filters, memory: HandshakeMemory) -> HandshakerSink {
+        HandshakerSink{ send: send, listen_port: listen_port, open_port: Arc::new(AtomicUsize::new(open_port as usize)),
+                        pid: pid, filters: filters, memory: memory }
+    }
+
+    /// Number of bytes currently used by the buffers of in progress handshakes.
+    pub fn buffer_memory_usage(&self) -> usize {
+        self.memory.used()
+    }
+
+    /// Set the port that external peers should connect on.
+    ///
+    /// Useful for port mapping services (such as UPnP) which only know the external port
+    /// after the `Handshaker` was built. Takes effect for this sink and all of its clones, so
+    /// any future announces will advertise the new port. A port of 0 will revert to advertising
+    /// the port that is being listened on.
+    pub fn set_port(&self, port: u16) {
+        self.open_port.store(port as usize, Ordering::SeqCst);
     }
 }
 
 impl DiscoveryInfo for HandshakerSink {
     fn port(&self) -> u16 {
-        self.port
+        match self.open_port.load(Ordering::SeqCst) as u16 {
+            0    => self.listen_port,
+            port => port
+        }
     }
 
     fn peer_id(&self) -> PeerId {
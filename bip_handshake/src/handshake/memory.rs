@@ -0,0 +1,99 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bittorrent::message;
+
+/// Number of bytes reserved for each in flight handshake.
+///
+/// This is the worst case size of both the read and write buffers, where
+/// the protocol string in each handshake message is as long as possible.
+pub fn handshake_buffer_len() -> usize {
+    message::write_len_with_protocol_len(u8::max_value()) * 2
+}
+
+/// Accounts for memory used by in flight handshake buffers.
+#[derive(Clone)]
+pub struct HandshakeMemory {
+    used: Arc<AtomicUsize>,
+    max:  usize
+}
+
+impl HandshakeMemory {
+    /// Create a new `HandshakeMemory` which allows up to `max` bytes to be reserved.
+    pub fn new(max: usize) -> HandshakeMemory {
+        HandshakeMemory{ used: Arc::new(AtomicUsize::new(0)), max: max }
+    }
+
+    /// Number of bytes currently reserved.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    /// Try to reserve the given number of bytes.
+    ///
+    /// Returns `None` if the reservation would put us over the max.
+    pub fn reserve(&self, bytes: usize) -> Option<MemoryReservation> {
+        let mut current = self.used.load(Ordering::SeqCst);
+
+        loop {
+            let new = match current.checked_add(bytes) {
+                Some(new) if new <= self.max => new,
+                _                            => return None
+            };
+
+            let previous = self.used.compare_and_swap(current, new, Ordering::SeqCst);
+            if previous == current {
+                return Some(MemoryReservation{ used: self.used.clone(), bytes: bytes })
+            }
+
+            current = previous;
+        }
+    }
+}
+
+/// Bytes reserved from a `HandshakeMemory`, released when dropped.
+pub struct MemoryReservation {
+    used:  Arc<AtomicUsize>,
+    bytes: usize
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.used.fetch_sub(self.bytes, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HandshakeMemory;
+
+    #[test]
+    fn positive_reserve_under_max() {
+        let memory = HandshakeMemory::new(100);
+
+        let _reservation = memory.reserve(60).unwrap();
+
+        assert_eq!(60, memory.used());
+    }
+
+    #[test]
+    fn positive_reservation_released_on_drop() {
+        let memory = HandshakeMemory::new(100);
+
+        let reservation = memory.reserve(60).unwrap();
+        drop(reservation);
+
+        assert_eq!(0, memory.used());
+        assert!(memory.reserve(100).is_some());
+    }
+
+    #[test]
+    fn negative_reserve_over_max() {
+        let memory = HandshakeMemory::new(100);
+
+        let _reservation = memory.reserve(60).unwrap();
+
+        assert!(memory.reserve(41).is_none());
+        assert_eq!(60, memory.used());
+    }
+}
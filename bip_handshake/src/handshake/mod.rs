@@ -1,3 +1,6 @@
 pub mod config;
 pub mod handler;
-pub mod handshaker;
\ No newline at end of file
+pub mod handshaker;
+pub mod memory;
+pub mod negotiate;
+pub mod restart;
\ No newline at end of file
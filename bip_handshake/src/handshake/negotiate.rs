@@ -0,0 +1,340 @@
+use std::any::Any;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use handshake::restart::{EventSender, HandshakerEvent, HandshakeFailure};
+use message::extensions::Extensions;
+use message::protocol::Protocol;
+
+use bip_util::bt::PeerId;
+
+/// Decision made by a `ProtocolNegotiator` after inspecting the remote handshake.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum NegotiationDecision {
+    /// Continue the handshake with our own extensions.
+    Continue,
+    /// Continue the handshake, but advertise and use the given extensions in place of our own.
+    ///
+    /// Extensions that we did not enable ourselves are ignored.
+    Downgrade(Extensions),
+    /// Abort the handshake for the given reason.
+    Abort(AbortReason)
+}
+
+/// Reason that a `ProtocolNegotiator` aborted a handshake.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AbortReason {
+    /// Peer is using a protocol that we don't support.
+    UnsupportedProtocol,
+    /// Peer advertised extensions that we are not compatible with.
+    UnsupportedExtensions,
+    /// Peer is running a client that we don't want to talk to.
+    BlockedClient,
+    /// Application specific reason, identified by the given code.
+    Other(u32)
+}
+
+/// Reason that a `PeerIdValidator` rejected a peer.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RejectReason {
+    /// Peer id did not carry the authentication we expected.
+    Unauthenticated,
+    /// Peer id belongs to a peer that we don't want to talk to.
+    BlockedPeer,
+    /// Application specific reason, identified by the given code.
+    Other(u32)
+}
+
+/// Trait for inspecting the protocol and reserved bits of a remote peer mid handshake.
+///
+/// This allows compatibility shims for clients that misbehave with certain extensions,
+/// or that use non standard protocol strings, without having to fork the handshaker.
+pub trait ProtocolNegotiator {
+    /// Decide whether to continue, downgrade, or abort the handshake with the given peer.
+    fn negotiate(&self, addr: &SocketAddr, prot: &Protocol, ext: &Extensions) -> NegotiationDecision;
+}
+
+impl<F> ProtocolNegotiator for F where F: Fn(&SocketAddr, &Protocol, &Extensions) -> NegotiationDecision {
+    fn negotiate(&self, addr: &SocketAddr, prot: &Protocol, ext: &Extensions) -> NegotiationDecision {
+        self(addr, prot, ext)
+    }
+}
+
+/// Trait for validating the `PeerId` of a remote peer mid handshake.
+///
+/// Private swarms often encode authentication material in the peer id, a validator
+/// lets them reject peers that fail to authenticate before the connection is handed off.
+pub trait PeerIdValidator {
+    /// Returns the reason for rejecting the peer, or `Ok` if the peer may continue the handshake.
+    fn validate(&self, addr: &SocketAddr, prot: &Protocol, ext: &Extensions, pid: &PeerId) -> Result<(), RejectReason>;
+}
+
+impl<F> PeerIdValidator for F where F: Fn(&SocketAddr, &Protocol, &Extensions, &PeerId) -> Result<(), RejectReason> {
+    fn validate(&self, addr: &SocketAddr, prot: &Protocol, ext: &Extensions, pid: &PeerId) -> Result<(), RejectReason> {
+        self(addr, prot, ext, pid)
+    }
+}
+
+/// Trait for negotiating an extension that is advertised through the reserved bits of the handshake.
+///
+/// This allows extensions outside of the built in `Extension`s (for example, the dht or fast extension)
+/// to be negotiated generically, attaching any negotiated data to the `CompleteMessage`.
+pub trait ReservedNegotiator {
+    /// Set the reserved bits for the extension in the `Extensions` that we advertise.
+    fn reserve(&self, ext: &mut Extensions);
+
+    /// Inspect the `Extensions` that both we and the peer support once the handshake completes.
+    ///
+    /// Any data returned will be attached to the `CompleteMessage`, and can be retrieved by type.
+    fn negotiated(&self, addr: &SocketAddr, ext: &Extensions) -> Option<Box<Any + Send>>;
+}
+
+/// Data attached to a `CompleteMessage` by any `ReservedNegotiator`s.
+pub struct NegotiatedData {
+    data: Vec<Box<Any + Send>>
+}
+
+impl NegotiatedData {
+    /// Create a new, empty, `NegotiatedData`.
+    pub fn new() -> NegotiatedData {
+        NegotiatedData{ data: Vec::new() }
+    }
+
+    /// Add the given data.
+    pub fn add(&mut self, data: Box<Any + Send>) {
+        self.data.push(data);
+    }
+
+    /// Retrieve the first data of type `T`, if any.
+    pub fn get<T>(&self) -> Option<&T>
+        where T: Any {
+        self.data.iter().filter_map(|data| data.downcast_ref::<T>()).next()
+    }
+
+    /// Whether or not there is any negotiated data.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+//----------------------------------------------------------------------------------//
+
+/// Applies an optional `ProtocolNegotiator` and `PeerIdValidator` to handshakes, reporting any aborts or failures.
+#[derive(Clone)]
+pub struct Negotiation {
+    opt_negotiator: Option<Arc<ProtocolNegotiator + Send + Sync>>,
+    opt_validator:  Option<Arc<PeerIdValidator + Send + Sync>>,
+    reserved:       Vec<Arc<ReservedNegotiator + Send + Sync>>,
+    events:         EventSender
+}
+
+impl Negotiation {
+    pub fn new(opt_negotiator: Option<Arc<ProtocolNegotiator + Send + Sync>>, events: EventSender) -> Negotiation {
+        Negotiation{ opt_negotiator: opt_negotiator, opt_validator: None, reserved: Vec::new(), events: events }
+    }
+
+    /// Use the given `PeerIdValidator` for remote peers.
+    pub fn with_validator(mut self, opt_validator: Option<Arc<PeerIdValidator + Send + Sync>>) -> Negotiation {
+        self.opt_validator = opt_validator;
+        self
+    }
+
+    /// Use the given `ReservedNegotiator`s when handshakes complete.
+    pub fn with_reserved(mut self, reserved: Vec<Arc<ReservedNegotiator + Send + Sync>>) -> Negotiation {
+        self.reserved = reserved;
+        self
+    }
+
+    /// Gather the data for each `ReservedNegotiator` given the `Extensions` both peers support.
+    pub fn reserved_data(&self, addr: &SocketAddr, ext: &Extensions) -> NegotiatedData {
+        let mut data = NegotiatedData::new();
+
+        for negotiated in self.reserved.iter().filter_map(|negotiator| negotiator.negotiated(addr, ext)) {
+            data.add(negotiated);
+        }
+
+        data
+    }
+
+    /// Report that the handshake with the given peer failed.
+    pub fn report_failure(&self, addr: &SocketAddr, failure: HandshakeFailure) {
+        self.events.send(HandshakerEvent::HandshakeFailed(*addr, failure));
+    }
+
+    /// Validate the `PeerId` of the remote peer.
+    ///
+    /// Returns `false` if the handshake should be aborted.
+    pub fn validate(&self, addr: &SocketAddr, remote_prot: &Protocol, remote_ext: &Extensions, remote_pid: &PeerId) -> bool {
+        let result = self.opt_validator.as_ref()
+            .map(|validator| validator.validate(addr, remote_prot, remote_ext, remote_pid))
+            .unwrap_or(Ok(()));
+
+        match result {
+            Ok(())      => true,
+            Err(reason) => {
+                self.events.send(HandshakerEvent::PeerIdRejected(*addr, reason));
+
+                false
+            }
+        }
+    }
+
+    /// Negotiate the extensions we will use with the remote peer.
+    ///
+    /// Returns `None` if the handshake should be aborted.
+    pub fn negotiate(&self, addr: &SocketAddr, remote_prot: &Protocol, remote_ext: &Extensions, ext: Extensions) -> Option<Extensions> {
+        let decision = self.opt_negotiator.as_ref()
+            .map(|negotiator| negotiator.negotiate(addr, remote_prot, remote_ext))
+            .unwrap_or(NegotiationDecision::Continue);
+
+        match decision {
+            NegotiationDecision::Continue              => Some(ext),
+            // Only keep the bits that we had enabled, so we never advertise more than our own extensions
+            NegotiationDecision::Downgrade(downgraded) => Some(downgraded.union(&ext)),
+            NegotiationDecision::Abort(reason)         => {
+                self.events.send(HandshakerEvent::NegotiationAborted(*addr, reason));
+
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use super::{AbortReason, Negotiation, NegotiationDecision, RejectReason, ReservedNegotiator};
+    use handshake::restart::{self, HandshakerEvent};
+    use message::extensions::{self, Extensions, Extension};
+    use message::protocol::Protocol;
+
+    use bip_util::bt::{self, PeerId};
+    use futures::future::Future;
+    use futures::stream::Stream;
+
+    fn any_addr() -> SocketAddr {
+        "1.2.3.4:5".parse().unwrap()
+    }
+
+    fn any_extensions() -> Extensions {
+        [255u8; extensions::NUM_EXTENSION_BYTES].into()
+    }
+
+    #[test]
+    fn positive_negotiate_without_negotiator() {
+        let (send, _recv) = restart::event_queue(10);
+        let negotiation = Negotiation::new(None, send);
+
+        assert_eq!(Some(any_extensions()), negotiation.negotiate(&any_addr(), &Protocol::BitTorrent, &Extensions::new(), any_extensions()));
+    }
+
+    #[test]
+    fn positive_negotiate_downgrade() {
+        let (send, _recv) = restart::event_queue(10);
+        let negotiation = Negotiation::new(Some(Arc::new(|_: &SocketAddr, _: &Protocol, _: &Extensions| {
+            NegotiationDecision::Downgrade(Extensions::new())
+        })), send);
+
+        assert_eq!(Some(Extensions::new()), negotiation.negotiate(&any_addr(), &Protocol::BitTorrent, &Extensions::new(), any_extensions()));
+    }
+
+    #[test]
+    fn positive_negotiate_downgrade_ignores_extra_bits() {
+        let (send, _recv) = restart::event_queue(10);
+        let negotiation = Negotiation::new(Some(Arc::new(|_: &SocketAddr, _: &Protocol, _: &Extensions| {
+            NegotiationDecision::Downgrade(any_extensions())
+        })), send);
+
+        let mut our_ext = Extensions::new();
+        our_ext.add(Extension::ExtensionProtocol);
+
+        assert_eq!(Some(our_ext), negotiation.negotiate(&any_addr(), &Protocol::BitTorrent, &Extensions::new(), our_ext));
+    }
+
+    #[test]
+    fn negative_negotiate_abort_sends_event() {
+        let (send, recv) = restart::event_queue(10);
+        let negotiation = Negotiation::new(Some(Arc::new(|_: &SocketAddr, prot: &Protocol, _: &Extensions| {
+            match prot {
+                &Protocol::Custom(_) => NegotiationDecision::Abort(AbortReason::UnsupportedProtocol),
+                _                    => NegotiationDecision::Continue
+            }
+        })), send);
+
+        assert_eq!(None, negotiation.negotiate(&any_addr(), &Protocol::Custom(b"Weird".to_vec()), &Extensions::new(), any_extensions()));
+        drop(negotiation);
+
+        let events = recv.collect().wait().unwrap();
+        assert_eq!(vec![HandshakerEvent::NegotiationAborted(any_addr(), AbortReason::UnsupportedProtocol)], events);
+    }
+
+    #[test]
+    fn positive_validate_without_validator() {
+        let (send, _recv) = restart::event_queue(10);
+        let negotiation = Negotiation::new(None, send);
+
+        assert!(negotiation.validate(&any_addr(), &Protocol::BitTorrent, &Extensions::new(), &[0u8; bt::PEER_ID_LEN].into()));
+    }
+
+    #[test]
+    fn negative_validate_reject_sends_event() {
+        let (send, recv) = restart::event_queue(10);
+        let negotiation = Negotiation::new(None, send).with_validator(Some(Arc::new(|_: &SocketAddr, _: &Protocol, _: &Extensions, pid: &PeerId| {
+            if pid.as_ref().starts_with(b"-PRIV-") {
+                Ok(())
+            } else {
+                Err(RejectReason::Unauthenticated)
+            }
+        })));
+
+        let mut valid_pid = [0u8; bt::PEER_ID_LEN];
+        valid_pid[..6].copy_from_slice(b"-PRIV-");
+
+        assert!(negotiation.validate(&any_addr(), &Protocol::BitTorrent, &Extensions::new(), &valid_pid.into()));
+        assert!(!negotiation.validate(&any_addr(), &Protocol::BitTorrent, &Extensions::new(), &[0u8; bt::PEER_ID_LEN].into()));
+        drop(negotiation);
+
+        let events = recv.collect().wait().unwrap();
+        assert_eq!(vec![HandshakerEvent::PeerIdRejected(any_addr(), RejectReason::Unauthenticated)], events);
+    }
+
+    struct DhtNegotiator;
+
+    impl ReservedNegotiator for DhtNegotiator {
+        fn reserve(&self, ext: &mut Extensions) {
+            ext.add(Extension::Dht);
+        }
+
+        fn negotiated(&self, _: &SocketAddr, ext: &Extensions) -> Option<Box<Any + Send>> {
+            if ext.contains(Extension::Dht) {
+                Some(Box::new(Extension::Dht as usize))
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn positive_reserved_data_negotiated() {
+        let (send, _recv) = restart::event_queue(10);
+        let negotiation = Negotiation::new(None, send).with_reserved(vec![Arc::new(DhtNegotiator)]);
+
+        let mut ext = Extensions::new();
+        DhtNegotiator.reserve(&mut ext);
+
+        let data = negotiation.reserved_data(&any_addr(), &ext);
+        assert_eq!(Some(&63usize), data.get::<usize>());
+    }
+
+    #[test]
+    fn negative_reserved_data_not_negotiated() {
+        let (send, _recv) = restart::event_queue(10);
+        let negotiation = Negotiation::new(None, send).with_reserved(vec![Arc::new(DhtNegotiator)]);
+
+        let data = negotiation.reserved_data(&any_addr(), &Extensions::new());
+        assert!(data.is_empty());
+    }
+}
@@ -0,0 +1,519 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use handshake::negotiate::{AbortReason, RejectReason};
+use transport::{self, SocketOptions, Transport};
+use local_addr::LocalAddr;
+
+use futures::{Poll, Async};
+use futures::future::Future;
+use futures::task::{self, Task};
+use futures::stream::Stream;
+use tokio_core::reactor::{Handle, Timeout};
+
+/// Reason that a handshake with a peer failed.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum HandshakeFailure {
+    /// Peer was blocked by one of our filters.
+    Filtered,
+    /// Connection to the peer could not be established.
+    ConnectFailed,
+    /// Peer did not connect, or complete the handshake, in time.
+    Timeout,
+    /// Peer disconnected before completing the handshake.
+    Disconnected,
+    /// Peer responded with a different protocol than the one we initiated with.
+    BadProtocol,
+    /// Peer responded with a different info hash than the one we initiated with.
+    WrongInfoHash,
+    /// Buffering the handshake would have put us over our handshake memory limit.
+    MemoryLimit
+}
+
+/// Event describing a change in the state of the `Handshaker` listener, or a failed handshake.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum HandshakerEvent {
+    /// Listener encountered a fatal error and will be restarted.
+    ListenerFailed(io::ErrorKind),
+    /// Attempt to restart the listener failed.
+    RestartFailed(io::ErrorKind),
+    /// Listener was restarted, and is listening on the given address.
+    Restarted(SocketAddr),
+    /// Listener could not be restarted and will no longer accept connections.
+    Stopped,
+    /// Handshake with the given peer was aborted by the `ProtocolNegotiator` for the given reason.
+    NegotiationAborted(SocketAddr, AbortReason),
+    /// Handshake with the given peer was aborted by the `PeerIdValidator` for the given reason.
+    PeerIdRejected(SocketAddr, RejectReason),
+    /// Handshake with the given peer failed, or the peer was filtered, for the given reason.
+    HandshakeFailed(SocketAddr, HandshakeFailure)
+}
+
+/// Create a queue that holds at most `capacity` `HandshakerEvent`s.
+#[cfg(test)]
+pub fn event_queue(capacity: usize) -> (EventSender, HandshakerEvents) {
+    new_event_queue(capacity, true)
+}
+
+/// Create a queue that holds at most `capacity` `HandshakerEvent`s, but drops
+/// all events until `start_receiving` is called for the `HandshakerEvents`.
+pub fn idle_event_queue(capacity: usize) -> (EventSender, HandshakerEvents) {
+    new_event_queue(capacity, false)
+}
+
+/// Start queueing up events for the given (idle) `HandshakerEvents`.
+pub fn start_receiving(events: &HandshakerEvents) {
+    events.shared.lock().expect("bip_handshake: Failed To Lock Event Queue").receiving = true;
+}
+
+fn new_event_queue(capacity: usize, receiving: bool) -> (EventSender, HandshakerEvents) {
+    let queue = EventQueue{ events: VecDeque::new(), capacity: capacity, senders: 1, receiving: receiving, task: None };
+    let shared = Arc::new(Mutex::new(queue));
+
+    (EventSender{ shared: shared.clone() }, HandshakerEvents{ shared: shared })
+}
+
+struct EventQueue {
+    events:    VecDeque<HandshakerEvent>,
+    capacity:  usize,
+    senders:   usize,
+    receiving: bool,
+    task:      Option<Task>
+}
+
+/// Sending half of a `HandshakerEvents` queue.
+///
+/// Events are dropped once the queue is full, so peers can't grow our memory
+/// usage by triggering events that the client never reads.
+pub struct EventSender {
+    shared: Arc<Mutex<EventQueue>>
+}
+
+impl EventSender {
+    /// Queue up the given event, dropping it if the queue is full or the receiver is gone.
+    pub fn send(&self, event: HandshakerEvent) {
+        let mut queue = self.shared.lock().expect("bip_handshake: Failed To Lock Event Queue");
+
+        if queue.receiving && queue.events.len() < queue.capacity {
+            queue.events.push_back(event);
+            queue.task.take().map(|task| task.notify());
+        }
+    }
+}
+
+impl Clone for EventSender {
+    fn clone(&self) -> EventSender {
+        self.shared.lock().expect("bip_handshake: Failed To Lock Event Queue").senders += 1;
+
+        EventSender{ shared: self.shared.clone() }
+    }
+}
+
+impl Drop for EventSender {
+    fn drop(&mut self) {
+        let mut queue = self.shared.lock().expect("bip_handshake: Failed To Lock Event Queue");
+        queue.senders -= 1;
+
+        if queue.senders == 0 {
+            queue.task.take().map(|task| task.notify());
+        }
+    }
+}
+
+/// `Stream` of `HandshakerEvent`s for a `Handshaker`.
+///
+/// Only a bounded number of events are queued up, newer events are dropped
+/// if this stream is not polled often enough.
+pub struct HandshakerEvents {
+    shared: Arc<Mutex<EventQueue>>
+}
+
+impl Stream for HandshakerEvents {
+    type Item = HandshakerEvent;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<HandshakerEvent>, ()> {
+        let mut queue = self.shared.lock().expect("bip_handshake: Failed To Lock Event Queue");
+
+        if let Some(event) = queue.events.pop_front() {
+            Ok(Async::Ready(Some(event)))
+        } else if queue.senders == 0 {
+            Ok(Async::Ready(None))
+        } else {
+            queue.task = Some(task::current());
+
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+impl Drop for HandshakerEvents {
+    fn drop(&mut self) {
+        let mut queue = self.shared.lock().expect("bip_handshake: Failed To Lock Event Queue");
+
+        queue.receiving = false;
+        queue.events.clear();
+    }
+}
+
+//----------------------------------------------------------------------------------//
+
+enum ListenerState<L> {
+    Listening(L),
+    Waiting(Timeout),
+    Stopped
+}
+
+enum RestartAction {
+    Failed(io::ErrorKind),
+    Rebind
+}
+
+/// Listener that will rebind itself, using the given `Transport`, after fatal errors.
+///
+/// Consecutive failed restarts are limited by the given number of attempts, after which the
+/// listener will stop (a successful restart resets the number of attempts).
+pub struct RestartListener<T> where T: Transport {
+    transport:    Rc<T>,
+    bind:         SocketAddr,
+    handle:       Handle,
+    delay:        Duration,
+    max_attempts: usize,
+    attempts:     usize,
+    backlog:      Option<i32>,
+    options:      SocketOptions,
+    max_accept:   usize,
+    accepted:     usize,
+    state:        ListenerState<T::Listener>,
+    events:       EventSender
+}
+
+impl<T> RestartListener<T> where T: Transport {
+    pub fn new(transport: Rc<T>, bind: SocketAddr, listener: T::Listener, handle: Handle, delay: Duration,
+               max_attempts: usize, events: EventSender) -> RestartListener<T> {
+        RestartListener{ transport: transport, bind: bind, handle: handle, delay: delay, max_attempts: max_attempts,
+                         attempts: 0, backlog: None, options: SocketOptions::default(), max_accept: usize::max_value(), accepted: 0,
+                         state: ListenerState::Listening(listener), events: events }
+    }
+
+    /// Rebind the listener with the given backlog.
+    pub fn with_backlog(mut self, backlog: Option<i32>) -> RestartListener<T> {
+        self.backlog = backlog;
+        self
+    }
+
+    /// Rebind the listener with the given `SocketOptions`.
+    pub fn with_socket_options(mut self, options: SocketOptions) -> RestartListener<T> {
+        self.options = options;
+        self
+    }
+
+    /// Accept at most the given number of connections (a minimum of one) before yielding to other tasks.
+    pub fn with_max_accept_batch(mut self, max: usize) -> RestartListener<T> {
+        self.max_accept = cmp::max(max, 1);
+        self
+    }
+
+    fn send_event(&self, event: HandshakerEvent) {
+        self.events.send(event);
+    }
+
+    fn wait_or_stop(&mut self) -> ListenerState<T::Listener> {
+        let opt_timeout = if self.attempts < self.max_attempts {
+            Timeout::new(self.delay, &self.handle).ok()
+        } else {
+            None
+        };
+
+        match opt_timeout {
+            Some(timeout) => ListenerState::Waiting(timeout),
+            None          => {
+                self.send_event(HandshakerEvent::Stopped);
+
+                ListenerState::Stopped
+            }
+        }
+    }
+
+    fn rebind(&mut self) -> ListenerState<T::Listener> {
+        self.attempts += 1;
+
+        let res_listener = transport::listen(&*self.transport, &self.bind, self.backlog, &self.options, &self.handle)
+            .and_then(|listener| listener.local_addr().map(|addr| (listener, addr)));
+
+        match res_listener {
+            Ok((listener, addr)) => {
+                self.attempts = 0;
+                self.send_event(HandshakerEvent::Restarted(addr));
+
+                ListenerState::Listening(listener)
+            },
+            Err(error) => {
+                self.send_event(HandshakerEvent::RestartFailed(error.kind()));
+
+                self.wait_or_stop()
+            }
+        }
+    }
+}
+
+impl<T> Stream for RestartListener<T> where T: Transport {
+    type Item = (T::Socket, SocketAddr);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, io::Error> {
+        // Give other tasks a chance to run if we have been accepting connections back to back
+        if self.accepted >= self.max_accept {
+            self.accepted = 0;
+            task::current().notify();
+
+            return Ok(Async::NotReady)
+        }
+
+        loop {
+            let action = match self.state {
+                ListenerState::Listening(ref mut listener) => {
+                    match listener.poll() {
+                        Ok(Async::Ready(Some((socket, addr)))) => {
+                            self.accepted += 1;
+
+                            // Peer may have already hung up, in which case we drop the connection instead of the listener
+                            if self.transport.configure_socket(&socket, &self.options).is_ok() {
+                                return Ok(Async::Ready(Some((socket, addr))))
+                            }
+
+                            continue
+                        },
+                        Ok(Async::Ready(None))                 => return Ok(Async::Ready(None)),
+                        Ok(Async::NotReady)                    => {
+                            self.accepted = 0;
+
+                            return Ok(Async::NotReady)
+                        },
+                        Err(error)                             => RestartAction::Failed(error.kind())
+                    }
+                },
+                ListenerState::Waiting(ref mut timeout) => {
+                    match timeout.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        _                   => RestartAction::Rebind
+                    }
+                },
+                ListenerState::Stopped => return Ok(Async::Ready(None))
+            };
+
+            self.state = match action {
+                RestartAction::Failed(kind) => {
+                    self.send_event(HandshakerEvent::ListenerFailed(kind));
+
+                    self.wait_or_stop()
+                },
+                RestartAction::Rebind => self.rebind()
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::io::{self, Cursor};
+    use std::net::SocketAddr;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::{self as restart, RestartListener, HandshakerEvent, HandshakeFailure};
+    use transport::Transport;
+    use local_addr::LocalAddr;
+
+    use futures::{Poll, Async};
+    use futures::future::{self, Future, FutureResult};
+    use futures::stream::Stream;
+    use tokio_core::reactor::{Core, Handle};
+
+    /// Transport which fails to listen for the given number of attempts.
+    struct FailingTransport {
+        failures: Cell<usize>
+    }
+
+    impl FailingTransport {
+        fn new(failures: usize) -> FailingTransport {
+            FailingTransport{ failures: Cell::new(failures) }
+        }
+    }
+
+    impl Transport for FailingTransport {
+        type Socket       = Cursor<Vec<u8>>;
+        type FutureSocket = FutureResult<Self::Socket, io::Error>;
+        type Listener     = FailingListener;
+
+        fn connect(&self, _addr: &SocketAddr, _handle: &Handle) -> io::Result<Self::FutureSocket> {
+            Ok(future::ok(Cursor::new(Vec::new())))
+        }
+
+        fn listen(&self, addr: &SocketAddr, _handle: &Handle) -> io::Result<Self::Listener> {
+            if self.failures.get() == 0 {
+                Ok(FailingListener::new(*addr, false))
+            } else {
+                self.failures.set(self.failures.get() - 1);
+
+                Err(io::Error::new(io::ErrorKind::AddrInUse, "Address In Use"))
+            }
+        }
+    }
+
+    /// Listener which either errors out, or never yields a connection.
+    struct FailingListener {
+        addr: SocketAddr,
+        fail: bool
+    }
+
+    impl FailingListener {
+        fn new(addr: SocketAddr, fail: bool) -> FailingListener {
+            FailingListener{ addr: addr, fail: fail }
+        }
+    }
+
+    impl LocalAddr for FailingListener {
+        fn local_addr(&self) -> io::Result<SocketAddr> {
+            Ok(self.addr)
+        }
+    }
+
+    impl Stream for FailingListener {
+        type Item = (Cursor<Vec<u8>>, SocketAddr);
+        type Error = io::Error;
+
+        fn poll(&mut self) -> Poll<Option<Self::Item>, io::Error> {
+            if self.fail {
+                Err(io::Error::new(io::ErrorKind::Other, "Listener Failed"))
+            } else {
+                Ok(Async::NotReady)
+            }
+        }
+    }
+
+    /// Transport whose listener always has a connection ready.
+    struct ReadyTransport;
+
+    impl Transport for ReadyTransport {
+        type Socket       = Cursor<Vec<u8>>;
+        type FutureSocket = FutureResult<Self::Socket, io::Error>;
+        type Listener     = ReadyListener;
+
+        fn connect(&self, _addr: &SocketAddr, _handle: &Handle) -> io::Result<Self::FutureSocket> {
+            Ok(future::ok(Cursor::new(Vec::new())))
+        }
+
+        fn listen(&self, addr: &SocketAddr, _handle: &Handle) -> io::Result<Self::Listener> {
+            Ok(ReadyListener{ addr: *addr })
+        }
+    }
+
+    struct ReadyListener {
+        addr: SocketAddr
+    }
+
+    impl LocalAddr for ReadyListener {
+        fn local_addr(&self) -> io::Result<SocketAddr> {
+            Ok(self.addr)
+        }
+    }
+
+    impl Stream for ReadyListener {
+        type Item = (Cursor<Vec<u8>>, SocketAddr);
+        type Error = io::Error;
+
+        fn poll(&mut self) -> Poll<Option<Self::Item>, io::Error> {
+            Ok(Async::Ready(Some((Cursor::new(Vec::new()), self.addr))))
+        }
+    }
+
+    fn any_addr() -> SocketAddr {
+        "127.0.0.1:5000".parse().unwrap()
+    }
+
+    #[test]
+    fn positive_yield_after_max_accept_batch() {
+        let core = Core::new().unwrap();
+        let (send, _recv) = restart::event_queue(10);
+
+        let mut listener = RestartListener::new(Rc::new(ReadyTransport), any_addr(), ReadyListener{ addr: any_addr() },
+                                                core.handle(), Duration::from_millis(0), 0, send)
+            .with_max_accept_batch(2);
+
+        let ready = future::poll_fn(|| {
+            let ready: Vec<bool> = (0..4).map(|_| listener.poll().unwrap().is_ready()).collect();
+
+            Ok::<_, ()>(Async::Ready(ready))
+        }).wait().unwrap();
+
+        assert_eq!(vec![true, true, false, true], ready);
+    }
+
+    #[test]
+    fn positive_restart_after_failed_attempt() {
+        let mut core = Core::new().unwrap();
+        let (send, recv) = restart::event_queue(10);
+
+        let listener = RestartListener::new(Rc::new(FailingTransport::new(1)), any_addr(), FailingListener::new(any_addr(), true),
+                                            core.handle(), Duration::from_millis(0), 2, send);
+        core.handle().spawn(listener.for_each(|_| Ok(())).map_err(|_| ()));
+
+        let events = core.run(recv.take(3).collect()).unwrap();
+
+        assert_eq!(vec![HandshakerEvent::ListenerFailed(io::ErrorKind::Other),
+                        HandshakerEvent::RestartFailed(io::ErrorKind::AddrInUse),
+                        HandshakerEvent::Restarted(any_addr())], events);
+    }
+
+    #[test]
+    fn negative_stop_after_max_attempts() {
+        let mut core = Core::new().unwrap();
+        let (send, recv) = restart::event_queue(10);
+
+        let listener = RestartListener::new(Rc::new(FailingTransport::new(5)), any_addr(), FailingListener::new(any_addr(), true),
+                                            core.handle(), Duration::from_millis(0), 2, send);
+        core.handle().spawn(listener.for_each(|_| Ok(())).map_err(|_| ()));
+
+        let events = core.run(recv.collect()).unwrap();
+
+        assert_eq!(vec![HandshakerEvent::ListenerFailed(io::ErrorKind::Other),
+                        HandshakerEvent::RestartFailed(io::ErrorKind::AddrInUse),
+                        HandshakerEvent::RestartFailed(io::ErrorKind::AddrInUse),
+                        HandshakerEvent::Stopped], events);
+    }
+
+    #[test]
+    fn positive_event_queue_drops_events_when_full() {
+        let (send, recv) = restart::event_queue(2);
+        let addr = "1.2.3.4:5".parse().unwrap();
+
+        for _ in 0..5 {
+            send.send(HandshakerEvent::HandshakeFailed(addr, HandshakeFailure::Timeout));
+        }
+        drop(send);
+
+        let events = recv.collect().wait().unwrap();
+        assert_eq!(2, events.len());
+    }
+
+    #[test]
+    fn positive_idle_event_queue_drops_events_until_receiving() {
+        let (send, recv) = restart::idle_event_queue(2);
+        let addr = "1.2.3.4:5".parse().unwrap();
+
+        send.send(HandshakerEvent::HandshakeFailed(addr, HandshakeFailure::Timeout));
+        restart::start_receiving(&recv);
+        send.send(HandshakerEvent::HandshakeFailed(addr, HandshakeFailure::Filtered));
+        drop(send);
+
+        let events = recv.collect().wait().unwrap();
+        assert_eq!(vec![HandshakerEvent::HandshakeFailed(addr, HandshakeFailure::Filtered)], events);
+    }
+}
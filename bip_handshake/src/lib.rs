@@ -1,6 +1,9 @@
 extern crate bip_util;
 extern crate bytes;
 extern crate futures;
+extern crate net2;
+#[cfg(feature = "tls")]
+extern crate native_tls;
 #[macro_use]
 extern crate nom;
 extern crate rand;
@@ -8,6 +11,8 @@ extern crate tokio_core;
 #[macro_use]
 extern crate tokio_io;
 extern crate tokio_timer;
+#[cfg(feature = "tls")]
+extern crate tokio_tls;
 
 mod bittorrent;
 mod handshake;
@@ -16,24 +21,31 @@ mod filter;
 mod discovery;
 mod local_addr;
 mod transport;
+#[cfg(feature = "tls")]
+mod tls;
 
-pub use message::complete::CompleteMessage;
+pub use message::complete::{CompleteMessage, CompleteParts, Direction};
 pub use message::initiate::InitiateMessage;
 pub use message::protocol::Protocol;
 pub use message::extensions::{Extensions, Extension};
 
 pub use handshake::config::HandshakerConfig;
 pub use handshake::handshaker::{HandshakerBuilder, Handshaker, HandshakerStream, HandshakerSink};
+pub use handshake::restart::{HandshakerEvent, HandshakerEvents, HandshakeFailure};
+pub use handshake::negotiate::{AbortReason, NegotiationDecision, ProtocolNegotiator, ReservedNegotiator, NegotiatedData, PeerIdValidator, RejectReason};
 
 pub use filter::{FilterDecision, HandshakeFilter, HandshakeFilters};
+pub use filter::context::FilterContext;
 
 pub use discovery::DiscoveryInfo;
 pub use local_addr::LocalAddr;
-pub use transport::Transport;
+pub use transport::{Transport, TransportKind, SocketOptions};
 
 /// Built in objects implementing `Transport`.
 pub mod transports {
     pub use transport::{TcpTransport, TcpListenerStream};
+    #[cfg(feature = "tls")]
+    pub use tls::{TlsTransport, TlsListenerStream, TlsStreamConnect};
 }
 
 pub use bip_util::bt::{PeerId, InfoHash};
\ No newline at end of file
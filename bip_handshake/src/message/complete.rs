@@ -2,9 +2,43 @@ use std::net::SocketAddr;
 
 use message::protocol::Protocol;
 use message::extensions::{Extensions};
+use handshake::negotiate::NegotiatedData;
+
+use transport::TransportKind;
 
 use bip_util::bt::{InfoHash, PeerId};
 
+/// Direction that a peer connection was established in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Peer connected to us.
+    Inbound,
+    /// We connected to the peer.
+    Outbound
+}
+
+/// Parts of a `CompleteMessage`, returned from `CompleteMessage::into_parts`.
+pub struct CompleteParts<S> {
+    /// Protocol that this peer is operating over.
+    pub protocol:   Protocol,
+    /// Extensions that both you and the peer support.
+    pub extensions: Extensions,
+    /// Hash that the peer is interested in.
+    pub hash:       InfoHash,
+    /// Id that the peer has given itself.
+    pub peer_id:    PeerId,
+    /// Address the peer is connected to us on.
+    pub address:    SocketAddr,
+    /// Direction that the connection was established in.
+    pub direction:  Direction,
+    /// Kind of transport that the connection was established over.
+    pub transport:  TransportKind,
+    /// Data negotiated through the reserved bits of the handshake.
+    pub negotiated: NegotiatedData,
+    /// Socket of some type S, that we use to communicate with the peer.
+    pub socket:     S
+}
+
 /// Message containing completed handshaking information.
 pub struct CompleteMessage<S> {
     prot: Protocol,
@@ -12,13 +46,24 @@ pub struct CompleteMessage<S> {
     hash: InfoHash,
     pid:  PeerId,
     addr: SocketAddr,
+    dir:  Direction,
+    kind: TransportKind,
+    data: NegotiatedData,
     sock: S
 }
 
 impl<S> CompleteMessage<S> {
     /// Create a new `CompleteMessage` over the given socket S.
-    pub fn new(prot: Protocol, ext: Extensions, hash: InfoHash, pid: PeerId, addr: SocketAddr, sock: S) -> CompleteMessage<S> {
-        CompleteMessage{ prot: prot, ext: ext, hash: hash, pid: pid, addr: addr, sock: sock }
+    pub fn new(prot: Protocol, ext: Extensions, hash: InfoHash, pid: PeerId, addr: SocketAddr, dir: Direction, kind: TransportKind,
+               sock: S) -> CompleteMessage<S> {
+        CompleteMessage{ prot: prot, ext: ext, hash: hash, pid: pid, addr: addr, dir: dir, kind: kind,
+                         data: NegotiatedData::new(), sock: sock }
+    }
+
+    /// Attach the data negotiated by any `ReservedNegotiator`s.
+    pub fn with_negotiated(mut self, data: NegotiatedData) -> CompleteMessage<S> {
+        self.data = data;
+        self
     }
 
     /// Protocol that this peer is operating over.
@@ -46,13 +91,54 @@ impl<S> CompleteMessage<S> {
         &self.addr
     }
 
+    /// Direction that the connection was established in.
+    pub fn direction(&self) -> Direction {
+        self.dir
+    }
+
+    /// Kind of transport that the connection was established over.
+    pub fn transport(&self) -> TransportKind {
+        self.kind
+    }
+
+    /// Data negotiated through the reserved bits of the handshake.
+    pub fn negotiated(&self) -> &NegotiatedData {
+        &self.data
+    }
+
     /// Socket of some type S, that we use to communicate with the peer.
     pub fn socket(&self) -> &S {
         &self.sock
     }
 
     /// Break the `CompleteMessage` into its parts.
-    pub fn into_parts(self) -> (Protocol, Extensions, InfoHash, PeerId, SocketAddr, S) {
-        (self.prot, self.ext, self.hash, self.pid, self.addr, self.sock)
+    pub fn into_parts(self) -> CompleteParts<S> {
+        CompleteParts{ protocol: self.prot, extensions: self.ext, hash: self.hash, peer_id: self.pid, address: self.addr,
+                       direction: self.dir, transport: self.kind, negotiated: self.data, socket: self.sock }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompleteMessage, Direction};
+    use handshake::negotiate::NegotiatedData;
+    use message::extensions::Extensions;
+    use message::protocol::Protocol;
+    use transport::TransportKind;
+
+    use bip_util::bt;
+
+    #[test]
+    fn positive_into_parts_keeps_negotiated_data() {
+        let mut data = NegotiatedData::new();
+        data.add(Box::new(5u32));
+
+        let complete = CompleteMessage::new(Protocol::BitTorrent, Extensions::new(), [0u8; bt::INFO_HASH_LEN].into(),
+                                            [0u8; bt::PEER_ID_LEN].into(), "1.2.3.4:5".parse().unwrap(), Direction::Inbound,
+                                            TransportKind::Tcp, ())
+            .with_negotiated(data);
+        let parts = complete.into_parts();
+
+        assert_eq!(Some(&5u32), parts.negotiated.get::<u32>());
+    }
+}
@@ -9,7 +9,11 @@ pub const NUM_EXTENSION_BYTES: usize = 8;
 /// Enumeration of all extensions that can be activated.
 pub enum Extension {
     /// Support for the extension protocol `http://www.bittorrent.org/beps/bep_0010.html`.
-    ExtensionProtocol = 43
+    ExtensionProtocol = 43,
+    /// Support for the fast extension `http://www.bittorrent.org/beps/bep_0006.html`.
+    FastExtension = 61,
+    /// Support for the dht port message `http://www.bittorrent.org/beps/bep_0005.html`.
+    Dht = 63
 }
 
 /// `Extensions` supported by either end of a handshake.
@@ -31,25 +35,43 @@ impl Extensions {
 
     /// Add the given extension to the list of supported `Extensions`.
     pub fn add(&mut self, extension: Extension) {
-        let active_bit = extension as usize;
+        self.add_bit(extension as usize)
+    }
+
+    /// Remove the given extension from the list of supported `Extensions`.
+    pub fn remove(&mut self, extension: Extension) {
+        self.remove_bit(extension as usize)
+    }
+
+    /// Check if a given extension is activated.
+    pub fn contains(&self, extension: Extension) -> bool {
+        self.contains_bit(extension as usize)
+    }
+
+    /// Set the given reserved bit, where bit zero is the most significant bit of the first byte.
+    ///
+    /// Panics if the bit is not less than `NUM_EXTENSION_BYTES * 8`.
+    pub fn add_bit(&mut self, active_bit: usize) {
         let byte_index = active_bit / 8;
         let bit_index = active_bit % 8;
 
         self.bytes[byte_index] |= 0x80 >> bit_index;
     }
 
-    /// Remove the given extension from the list of supported `Extensions`.
-    pub fn remove(&mut self, extension: Extension) {
-        let active_bit = extension as usize;
+    /// Clear the given reserved bit.
+    ///
+    /// Panics if the bit is not less than `NUM_EXTENSION_BYTES * 8`.
+    pub fn remove_bit(&mut self, active_bit: usize) {
         let byte_index = active_bit / 8;
         let bit_index = active_bit % 8;
 
         self.bytes[byte_index] &= !(0x80 >> bit_index);
     }
 
-    /// Check if a given extension is activated.
-    pub fn contains(&self, extension: Extension) -> bool {
-        let active_bit = extension as usize;
+    /// Check if the given reserved bit is set.
+    ///
+    /// Panics if the bit is not less than `NUM_EXTENSION_BYTES * 8`.
+    pub fn contains_bit(&self, active_bit: usize) -> bool {
         let byte_index = active_bit / 8;
         let bit_index = active_bit % 8;
 
@@ -58,6 +80,11 @@ impl Extensions {
         self.bytes[byte_index] & (0x80 >> bit_index) != 0
     }
 
+    /// Raw reserved bytes of the `Extensions`.
+    pub fn as_bytes(&self) -> &[u8; NUM_EXTENSION_BYTES] {
+        &self.bytes
+    }
+
     /// Write the `Extensions` to the given writer.
     pub fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
         where W: Write {
@@ -123,4 +150,28 @@ mod tests {
         assert_eq!(expected_extensions, extensions);
         assert!(!extensions.contains(Extension::ExtensionProtocol));
     }
+
+    #[test]
+    fn positive_add_fast_extension() {
+        let mut extensions = Extensions::new();
+        extensions.add(Extension::FastExtension);
+
+        let expected_extensions: Extensions = [0, 0, 0, 0, 0, 0, 0, 0x04].into();
+
+        assert_eq!(expected_extensions, extensions);
+        assert!(extensions.contains(Extension::FastExtension));
+        assert!(!extensions.contains(Extension::ExtensionProtocol));
+    }
+
+    #[test]
+    fn positive_add_dht_bit() {
+        let mut extensions = Extensions::new();
+        extensions.add_bit(63);
+
+        let expected_extensions: Extensions = [0, 0, 0, 0, 0, 0, 0, 0x01].into();
+
+        assert_eq!(expected_extensions, extensions);
+        assert!(extensions.contains(Extension::Dht));
+        assert!(extensions.contains_bit(63));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,252 @@
+use std::cmp;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use local_addr::LocalAddr;
+use transport::{self, Transport, SocketOptions, TcpTransport, TcpListenerStream};
+
+use futures::{Async, Poll};
+use futures::future::{Future, MapErr};
+use futures::stream::{Stream, FuturesUnordered};
+use native_tls::{self, TlsConnector, TlsAcceptor};
+use tokio_core::net::{TcpStream, TcpStreamNew, Incoming};
+use tokio_core::reactor::Handle;
+use tokio_timer::{self, Timer, Timeout};
+use tokio_tls::{TlsConnectorExt, TlsAcceptorExt, TlsStream, AcceptAsync};
+
+const DEFAULT_MAX_PENDING_ACCEPTS:   usize = 100;
+const DEFAULT_ACCEPT_TIMEOUT_MILLIS: u64   = 10000;
+
+/// Future connection from a `TlsTransport`.
+pub type TlsStreamConnect = Box<Future<Item=TlsStream<TcpStream>, Error=io::Error>>;
+
+/// Defines a `Transport` operating over TLS wrapped TCP.
+///
+/// Useful for private swarms where every peer connection is required to be encrypted
+/// and authenticated. Socket options and backlogs are applied to the underlying TCP sockets.
+#[derive(Clone)]
+pub struct TlsTransport {
+    connector:    Arc<TlsConnector>,
+    opt_acceptor: Option<Arc<TlsAcceptor>>,
+    opt_domain:   Option<String>,
+    validate:     bool,
+    max_pending:  usize,
+    timeout:      Duration,
+    // Shared by every listener, so restarting a listener doesnt start another timer thread
+    timer:        Timer
+}
+
+impl TlsTransport {
+    /// Create a new `TlsTransport` that connects using the given `TlsConnector`.
+    ///
+    /// Certificates will be validated, and a `TlsAcceptor` must be added before listening.
+    pub fn new(connector: TlsConnector) -> TlsTransport {
+        let timeout = Duration::from_millis(DEFAULT_ACCEPT_TIMEOUT_MILLIS);
+
+        TlsTransport{ connector: Arc::new(connector), opt_acceptor: None, opt_domain: None, validate: true,
+                      max_pending: DEFAULT_MAX_PENDING_ACCEPTS, timeout: timeout, timer: accept_timer(timeout) }
+    }
+
+    /// Accept incoming connections using the given `TlsAcceptor`.
+    pub fn with_acceptor(mut self, acceptor: TlsAcceptor) -> TlsTransport {
+        self.opt_acceptor = Some(Arc::new(acceptor));
+        self
+    }
+
+    /// Domain sent via SNI, and which peer certificates are validated against.
+    ///
+    /// If not set, the ip address of the peer is used.
+    pub fn with_domain(mut self, opt_domain: Option<String>) -> TlsTransport {
+        self.opt_domain = opt_domain;
+        self
+    }
+
+    /// Whether or not peer certificates should be validated.
+    ///
+    /// Disabling validation also disables SNI, since the underlying TLS implementation
+    /// only sends the domain when it is validating against it.
+    pub fn with_certificate_validation(mut self, validate: bool) -> TlsTransport {
+        self.validate = validate;
+        self
+    }
+
+    /// Maximum number of accepted connections (a minimum of one) that can be in the middle of the TLS handshake.
+    ///
+    /// Once reached, no more connections will be accepted until a pending handshake finishes.
+    ///
+    /// Defaults to 100.
+    pub fn with_max_pending_accepts(mut self, max_pending: usize) -> TlsTransport {
+        self.max_pending = cmp::max(max_pending, 1);
+        self
+    }
+
+    /// Time an accepted connection has to complete the TLS handshake before it is dropped.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn with_accept_timeout(mut self, timeout: Duration) -> TlsTransport {
+        self.timeout = timeout;
+        self.timer = accept_timer(timeout);
+        self
+    }
+
+    fn wrap_connect(&self, addr: &SocketAddr, tcp_connect: TcpStreamNew) -> TlsStreamConnect {
+        let connector = self.connector.clone();
+        let domain = self.opt_domain.clone().unwrap_or_else(|| addr.ip().to_string());
+        let validate = self.validate;
+
+        Box::new(tcp_connect.and_then(move |stream| {
+            let tls_connect = if validate {
+                connector.connect_async(&domain, stream)
+            } else {
+                connector.danger_connect_async_without_providing_domain_for_certificate_verification_and_server_name_indication(stream)
+            };
+
+            tls_connect.map_err(tls_error)
+        }))
+    }
+
+    fn acceptor(&self) -> io::Result<Arc<TlsAcceptor>> {
+        self.opt_acceptor.clone()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "TlsTransport Requires A TlsAcceptor To Listen"))
+    }
+
+    fn wrap_listener(&self, acceptor: Arc<TlsAcceptor>, listener: TcpListenerStream<Incoming>) -> TlsListenerStream<TcpListenerStream<Incoming>> {
+        TlsListenerStream::new(acceptor, listener, self.timer.clone(), self.timeout, self.max_pending)
+    }
+}
+
+/// Configure a timer wheel that can time out TLS handshakes of the given duration.
+fn accept_timer(timeout: Duration) -> Timer {
+    tokio_timer::wheel()
+        .num_slots(64)
+        .max_timeout(timeout)
+        .build()
+}
+
+impl Transport for TlsTransport {
+    type Socket = TlsStream<TcpStream>;
+    type FutureSocket = TlsStreamConnect;
+    type Listener = TlsListenerStream<TcpListenerStream<Incoming>>;
+
+    fn connect(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<Self::FutureSocket> {
+        let tcp_connect = try!(TcpTransport.connect(addr, handle));
+
+        Ok(self.wrap_connect(addr, tcp_connect))
+    }
+
+    fn listen(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<Self::Listener> {
+        let acceptor = try!(self.acceptor());
+        let listener = try!(TcpTransport.listen(addr, handle));
+
+        Ok(self.wrap_listener(acceptor, listener))
+    }
+
+    fn listen_with_backlog(&self, addr: &SocketAddr, backlog: i32, handle: &Handle) -> io::Result<Self::Listener> {
+        let acceptor = try!(self.acceptor());
+        let listener = try!(TcpTransport.listen_with_backlog(addr, backlog, handle));
+
+        Ok(self.wrap_listener(acceptor, listener))
+    }
+
+    fn listen_with_options(&self, addr: &SocketAddr, opt_backlog: Option<i32>, options: &SocketOptions, handle: &Handle) -> io::Result<Self::Listener> {
+        let acceptor = try!(self.acceptor());
+        let listener = try!(TcpTransport.listen_with_options(addr, opt_backlog, options, handle));
+
+        Ok(self.wrap_listener(acceptor, listener))
+    }
+
+    fn configure_socket(&self, socket: &Self::Socket, options: &SocketOptions) -> io::Result<()> {
+        transport::configure_stream(socket.get_ref().get_ref(), options)
+    }
+}
+
+//----------------------------------------------------------------------------------//
+
+/// Listener stream that completes the TLS handshake for every connection accepted by `L`.
+///
+/// Connections that fail the TLS handshake, or that do not complete it in time, are dropped
+/// without affecting the listener. While the maximum number of handshakes are pending, no
+/// more connections are accepted from `L`.
+pub struct TlsListenerStream<L> {
+    acceptor:    Arc<TlsAcceptor>,
+    listener:    L,
+    timer:       Timer,
+    timeout:     Duration,
+    max_pending: usize,
+    pending:     FuturesUnordered<PendingAccept>,
+    finished:    bool
+}
+
+impl<L> TlsListenerStream<L> {
+    fn new(acceptor: Arc<TlsAcceptor>, listener: L, timer: Timer, timeout: Duration, max_pending: usize) -> TlsListenerStream<L> {
+        TlsListenerStream{ acceptor: acceptor, listener: listener, timer: timer, timeout: timeout, max_pending: max_pending,
+                           pending: FuturesUnordered::new(), finished: false }
+    }
+}
+
+impl<L> Stream for TlsListenerStream<L> where L: Stream<Item=(TcpStream, SocketAddr), Error=io::Error> {
+    type Item = (TlsStream<TcpStream>, SocketAddr);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            // Start handshakes for new connections while we have room, so we get woken up for the next one
+            while !self.finished && self.pending.len() < self.max_pending {
+                match try!(self.listener.poll()) {
+                    Async::Ready(Some((stream, addr))) => {
+                        let accept = self.acceptor.accept_async(stream).map_err(tls_error as fn(native_tls::Error) -> io::Error);
+
+                        self.pending.push(PendingAccept{ accept: self.timer.timeout(accept, self.timeout), addr: addr });
+                    },
+                    Async::Ready(None)                 => self.finished = true,
+                    Async::NotReady                    => break
+                }
+            }
+
+            // Only handshakes that have made progress are polled
+            match self.pending.poll() {
+                Ok(Async::Ready(Some(item))) => return Ok(Async::Ready(Some(item))),
+                // Handshake failed or timed out, which made room for another connection
+                Err(_)                       => continue,
+                Ok(Async::Ready(None))       |
+                Ok(Async::NotReady)          => break
+            }
+        }
+
+        if self.finished && self.pending.is_empty() {
+            Ok(Async::Ready(None))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+fn tls_error(error: native_tls::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, error)
+}
+
+/// TLS handshake for a connection accepted from the given address.
+struct PendingAccept {
+    accept: Timeout<MapErr<AcceptAsync<TcpStream>, fn(native_tls::Error) -> io::Error>>,
+    addr:   SocketAddr
+}
+
+impl Future for PendingAccept {
+    type Item = (TlsStream<TcpStream>, SocketAddr);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match try!(self.accept.poll()) {
+            Async::Ready(stream) => Ok(Async::Ready((stream, self.addr))),
+            Async::NotReady      => Ok(Async::NotReady)
+        }
+    }
+}
+
+impl<L> LocalAddr for TlsListenerStream<L> where L: LocalAddr {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
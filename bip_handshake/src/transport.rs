@@ -1,15 +1,31 @@
 use std::io;
 use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::Duration;
 
 use local_addr::LocalAddr;
 
-use futures::Poll;
+use net2::TcpBuilder;
+#[cfg(unix)]
+use net2::unix::UnixTcpBuilderExt;
+use futures::{Async, Poll};
 use futures::future::Future;
 use futures::stream::Stream;
 use tokio_core::net::{TcpStream, TcpStreamNew, Incoming, TcpListener};
 use tokio_core::reactor::Handle;
 use tokio_io::{AsyncRead, AsyncWrite};
 
+/// Kind of transport that a connection was established over.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TransportKind {
+    /// Connection over TCP.
+    Tcp,
+    /// Connection over uTP.
+    Utp,
+    /// Connection tunneled through a proxy.
+    Proxy
+}
+
 /// Trait for initializing connections over an abstract `Transport`.
 pub trait Transport {
     /// Concrete socket.
@@ -26,6 +42,201 @@ pub trait Transport {
 
     /// Listen to the given address for this transport, using the supplied `Handle`.
     fn listen(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<Self::Listener>;
+
+    /// Listen to the given address for this transport with the given backlog, using the supplied `Handle`.
+    ///
+    /// Defaults to ignoring the backlog, and calling `Transport::listen`.
+    fn listen_with_backlog(&self, addr: &SocketAddr, backlog: i32, handle: &Handle) -> io::Result<Self::Listener> {
+        let _ = backlog;
+
+        self.listen(addr, handle)
+    }
+
+    /// Apply the connection `SocketOptions` to a socket that was connected or accepted over this transport.
+    ///
+    /// Defaults to ignoring the options.
+    fn configure_socket(&self, socket: &Self::Socket, options: &SocketOptions) -> io::Result<()> {
+        let _ = (socket, options);
+
+        Ok(())
+    }
+
+    /// Listen to the given address for this transport with the given backlog (if one was specified) and
+    /// `SocketOptions`, using the supplied `Handle`.
+    ///
+    /// Defaults to ignoring the options, and calling `Transport::listen_with_backlog` or `Transport::listen`.
+    fn listen_with_options(&self, addr: &SocketAddr, opt_backlog: Option<i32>, options: &SocketOptions, handle: &Handle) -> io::Result<Self::Listener> {
+        let _ = options;
+
+        match opt_backlog {
+            Some(backlog) => self.listen_with_backlog(addr, backlog, handle),
+            None          => self.listen(addr, handle)
+        }
+    }
+
+    /// Kind of transport that connections are established over.
+    ///
+    /// Defaults to `TransportKind::Tcp`.
+    fn kind(&self) -> TransportKind {
+        TransportKind::Tcp
+    }
+}
+
+impl<T> Transport for Rc<T> where T: Transport {
+    type Socket = T::Socket;
+    type FutureSocket = T::FutureSocket;
+    type Listener = T::Listener;
+
+    fn connect(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<Self::FutureSocket> {
+        (**self).connect(addr, handle)
+    }
+
+    fn listen(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<Self::Listener> {
+        (**self).listen(addr, handle)
+    }
+
+    fn listen_with_backlog(&self, addr: &SocketAddr, backlog: i32, handle: &Handle) -> io::Result<Self::Listener> {
+        (**self).listen_with_backlog(addr, backlog, handle)
+    }
+
+    fn configure_socket(&self, socket: &Self::Socket, options: &SocketOptions) -> io::Result<()> {
+        (**self).configure_socket(socket, options)
+    }
+
+    fn listen_with_options(&self, addr: &SocketAddr, opt_backlog: Option<i32>, options: &SocketOptions, handle: &Handle) -> io::Result<Self::Listener> {
+        (**self).listen_with_options(addr, opt_backlog, options, handle)
+    }
+
+    fn kind(&self) -> TransportKind {
+        (**self).kind()
+    }
+}
+
+//----------------------------------------------------------------------------------//
+
+/// Options for the sockets created by a `Transport`.
+///
+/// Options that are not set are left at the defaults of the operating system. Transports
+/// are free to ignore options that do not apply to them.
+///
+/// Connection options are applied once a connection has been established, so buffer sizes
+/// will not affect the window scaling negotiated during the TCP handshake.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    nodelay:          Option<bool>,
+    reuse_address:    Option<bool>,
+    reuse_port:       Option<bool>,
+    keepalive:        Option<Duration>,
+    send_buffer_size: Option<usize>,
+    recv_buffer_size: Option<usize>
+}
+
+impl SocketOptions {
+    /// Create a new `SocketOptions`, with no options set.
+    pub fn new() -> SocketOptions {
+        SocketOptions::default()
+    }
+
+    /// Sets `TCP_NODELAY` on every connection.
+    pub fn with_nodelay(mut self, nodelay: bool) -> SocketOptions {
+        self.nodelay = Some(nodelay);
+        self
+    }
+
+    /// Sets `SO_REUSEADDR` on the listener.
+    ///
+    /// If not set, `TcpTransport` will only reuse addresses on unix, matching the standard library.
+    pub fn with_reuse_address(mut self, reuse: bool) -> SocketOptions {
+        self.reuse_address = Some(reuse);
+        self
+    }
+
+    /// Sets `SO_REUSEPORT` on the listener.
+    ///
+    /// Only supported on unix, `TcpTransport` will ignore this on other platforms.
+    pub fn with_reuse_port(mut self, reuse: bool) -> SocketOptions {
+        self.reuse_port = Some(reuse);
+        self
+    }
+
+    /// Enables `SO_KEEPALIVE` on every connection, with the given idle time before probes are sent.
+    pub fn with_keepalive(mut self, idle: Duration) -> SocketOptions {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    /// Sets `SO_SNDBUF` on every connection.
+    pub fn with_send_buffer_size(mut self, size: usize) -> SocketOptions {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets `SO_RCVBUF` on every connection.
+    pub fn with_recv_buffer_size(mut self, size: usize) -> SocketOptions {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Gets the `TCP_NODELAY` option.
+    pub fn nodelay(&self) -> Option<bool> {
+        self.nodelay
+    }
+
+    /// Gets the `SO_REUSEADDR` option.
+    pub fn reuse_address(&self) -> Option<bool> {
+        self.reuse_address
+    }
+
+    /// Gets the `SO_REUSEPORT` option.
+    pub fn reuse_port(&self) -> Option<bool> {
+        self.reuse_port
+    }
+
+    /// Gets the `SO_KEEPALIVE` idle time.
+    pub fn keepalive(&self) -> Option<Duration> {
+        self.keepalive
+    }
+
+    /// Gets the `SO_SNDBUF` option.
+    pub fn send_buffer_size(&self) -> Option<usize> {
+        self.send_buffer_size
+    }
+
+    /// Gets the `SO_RCVBUF` option.
+    pub fn recv_buffer_size(&self) -> Option<usize> {
+        self.recv_buffer_size
+    }
+}
+
+//----------------------------------------------------------------------------------//
+
+/// Future socket from a `Transport`, which applies the connection `SocketOptions` once connected.
+pub struct ConfiguredSocket<T> where T: Transport {
+    transport: T,
+    connect:   T::FutureSocket,
+    options:   SocketOptions
+}
+
+impl<T> ConfiguredSocket<T> where T: Transport {
+    /// Create a new `ConfiguredSocket` that will apply the given `SocketOptions` to the socket from `connect`.
+    pub fn new(transport: T, connect: T::FutureSocket, options: SocketOptions) -> ConfiguredSocket<T> {
+        ConfiguredSocket{ transport: transport, connect: connect, options: options }
+    }
+}
+
+impl<T> Future for ConfiguredSocket<T> where T: Transport {
+    type Item = T::Socket;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let socket = match try!(self.connect.poll()) {
+            Async::Ready(socket) => socket,
+            Async::NotReady      => return Ok(Async::NotReady)
+        };
+        try!(self.transport.configure_socket(&socket, &self.options));
+
+        Ok(Async::Ready(socket))
+    }
 }
 
 //----------------------------------------------------------------------------------//
@@ -48,6 +259,72 @@ impl Transport for TcpTransport {
 
         Ok(TcpListenerStream::new(listen_addr, listener.incoming()))
     }
+
+    fn listen_with_backlog(&self, addr: &SocketAddr, backlog: i32, handle: &Handle) -> io::Result<Self::Listener> {
+        self.listen_with_options(addr, Some(backlog), &SocketOptions::default(), handle)
+    }
+
+    fn listen_with_options(&self, addr: &SocketAddr, opt_backlog: Option<i32>, options: &SocketOptions, handle: &Handle) -> io::Result<Self::Listener> {
+        let builder = try!(tcp_builder(addr));
+
+        // Match the behavior of the standard library, which only reuses addresses on unix
+        try!(builder.reuse_address(options.reuse_address().unwrap_or(cfg!(unix))));
+        if let Some(reuse) = options.reuse_port() {
+            try!(reuse_port(&builder, reuse));
+        }
+        // Standard library also uses a backlog of 128
+        let std_listener = try!(try!(builder.bind(addr)).listen(opt_backlog.unwrap_or(128)));
+
+        let listener = try!(TcpListener::from_listener(std_listener, addr, handle));
+        let listen_addr = try!(listener.local_addr());
+
+        Ok(TcpListenerStream::new(listen_addr, listener.incoming()))
+    }
+
+    fn configure_socket(&self, socket: &Self::Socket, options: &SocketOptions) -> io::Result<()> {
+        configure_stream(socket, options)
+    }
+}
+
+fn tcp_builder(addr: &SocketAddr) -> io::Result<TcpBuilder> {
+    match *addr {
+        SocketAddr::V4(_) => TcpBuilder::new_v4(),
+        SocketAddr::V6(_) => TcpBuilder::new_v6()
+    }
+}
+
+#[cfg(unix)]
+fn reuse_port(builder: &TcpBuilder, reuse: bool) -> io::Result<()> {
+    builder.reuse_port(reuse).map(|_| ())
+}
+
+#[cfg(not(unix))]
+fn reuse_port(_builder: &TcpBuilder, _reuse: bool) -> io::Result<()> {
+    Ok(())
+}
+
+/// Apply the connection `SocketOptions` to the given `TcpStream`.
+pub fn configure_stream(stream: &TcpStream, options: &SocketOptions) -> io::Result<()> {
+    if let Some(nodelay) = options.nodelay() {
+        try!(stream.set_nodelay(nodelay));
+    }
+    if let Some(idle) = options.keepalive() {
+        try!(stream.set_keepalive(Some(idle)));
+    }
+    if let Some(size) = options.send_buffer_size() {
+        try!(stream.set_send_buffer_size(size));
+    }
+    if let Some(size) = options.recv_buffer_size() {
+        try!(stream.set_recv_buffer_size(size));
+    }
+
+    Ok(())
+}
+
+/// Listen on the given `Transport`, with the given backlog if one was specified, and the given `SocketOptions`.
+pub fn listen<T>(transport: &T, addr: &SocketAddr, opt_backlog: Option<i32>, options: &SocketOptions, handle: &Handle) -> io::Result<T::Listener>
+    where T: Transport {
+    transport.listen_with_options(addr, opt_backlog, options, handle)
 }
 
 /// Convenient object that wraps a listener stream `L`, and also implements `LocalAddr`.
@@ -92,6 +369,7 @@ pub mod test_transports {
     use futures::stream::{self, Stream, Empty};
     use tokio_core::reactor::Handle;
 
+    #[derive(Clone)]
     pub struct MockTransport;
 
     impl Transport for MockTransport {
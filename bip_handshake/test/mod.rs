@@ -1,6 +1,8 @@
 extern crate bip_handshake;
 extern crate bip_util;
 extern crate futures;
+#[cfg(feature = "tls")]
+extern crate native_tls;
 extern crate tokio_io;
 extern crate tokio_core;
 
@@ -11,6 +13,13 @@ mod test_filter_allow_all;
 mod test_filter_block_all;
 mod test_filter_whitelist_same_data;
 mod test_filter_whitelist_diff_data;
+mod test_max_half_open;
+mod test_max_buffer_memory;
+mod test_handshake_read_timeout;
+mod test_set_port;
+mod test_socket_options;
+#[cfg(feature = "tls")]
+mod test_tls_transport;
 
 //----------------------------------------------------------------------------------//
 
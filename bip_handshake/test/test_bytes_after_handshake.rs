@@ -44,7 +44,7 @@ fn positive_recover_bytes() {
     let recv_buffer = core.run(handshaker_one.into_future()
         .map_err(|_| ())
         .and_then(|(opt_message, _)| {
-            let (_, _, _, _, _, sock) = opt_message.unwrap().into_parts();
+            let sock = opt_message.unwrap().into_parts().socket;
 
             io::read_exact(sock, vec![0u8; 100])
                 .map_err(|_| ())
@@ -0,0 +1,71 @@
+use std::net::TcpStream;
+use std::time::Duration;
+
+use bip_handshake::{HandshakerBuilder, HandshakerConfig, DiscoveryInfo};
+use bip_handshake::transports::TcpTransport;
+
+use bip_util::bt::{self};
+use tokio_core::reactor::{Core, Timeout};
+
+#[test]
+fn positive_release_stalled_inbound_handshake() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+
+    let mut handshaker_addr = "127.0.0.1:0".parse().unwrap();
+    let handshaker_pid = [4u8; bt::PEER_ID_LEN].into();
+
+    // Long handshake timeout, so only the read timeout can release the stalled connection
+    let handshaker = HandshakerBuilder::new()
+        .with_bind_addr(handshaker_addr)
+        .with_peer_id(handshaker_pid)
+        .with_config(HandshakerConfig::default()
+            .with_handshake_timeout(Duration::from_millis(10000))
+            .with_handshake_read_timeout(Duration::from_millis(200)))
+        .build(TcpTransport, core.handle()).unwrap();
+
+    handshaker_addr.set_port(handshaker.port());
+
+    // Connect, but never send the protocol header
+    let _stalled_stream = TcpStream::connect(handshaker_addr).unwrap();
+
+    core.run(Timeout::new(Duration::from_millis(50), &handle).unwrap()).unwrap();
+    assert!(handshaker.buffer_memory_usage() > 0);
+
+    core.run(Timeout::new(Duration::from_millis(500), &handle).unwrap()).unwrap();
+    assert_eq!(0, handshaker.buffer_memory_usage());
+}
+
+#[test]
+fn positive_handshake_timeout_bounds_read() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+
+    let mut handshaker_addr = "127.0.0.1:0".parse().unwrap();
+    let handshaker_pid = [4u8; bt::PEER_ID_LEN].into();
+
+    // Without a separate read timeout, the handshake timeout applies to the read
+    let handshaker = HandshakerBuilder::new()
+        .with_bind_addr(handshaker_addr)
+        .with_peer_id(handshaker_pid)
+        .with_config(HandshakerConfig::default()
+            .with_handshake_timeout(Duration::from_millis(200)))
+        .build(TcpTransport, core.handle()).unwrap();
+
+    handshaker_addr.set_port(handshaker.port());
+
+    let _stalled_stream = TcpStream::connect(handshaker_addr).unwrap();
+
+    core.run(Timeout::new(Duration::from_millis(50), &handle).unwrap()).unwrap();
+    assert!(handshaker.buffer_memory_usage() > 0);
+
+    core.run(Timeout::new(Duration::from_millis(500), &handle).unwrap()).unwrap();
+    assert_eq!(0, handshaker.buffer_memory_usage());
+}
+
+#[test]
+fn positive_read_timeout_defaults_to_handshake_timeout() {
+    let config = HandshakerConfig::default().with_handshake_timeout(Duration::from_millis(10000));
+
+    assert_eq!(Duration::from_millis(10000), config.handshake_read_timeout());
+}
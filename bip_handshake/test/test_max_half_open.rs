@@ -0,0 +1,130 @@
+use std::cell::Cell;
+use std::io;
+use std::net::SocketAddr;
+use std::rc::Rc;
+
+use bip_handshake::{HandshakerBuilder, HandshakerConfig, InitiateMessage, Protocol, DiscoveryInfo, Transport};
+use bip_handshake::transports::TcpTransport;
+
+use bip_util::bt::{self};
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::{Core, Handle};
+use futures::{Future, Poll, Async};
+use futures::stream::Stream;
+use futures::sink::Sink;
+
+/// Transport that tracks the most connects it has seen in progress at once.
+#[derive(Clone)]
+struct CountingTransport {
+    in_progress:     Rc<Cell<usize>>,
+    max_in_progress: Rc<Cell<usize>>
+}
+
+impl CountingTransport {
+    fn new() -> CountingTransport {
+        CountingTransport{ in_progress: Rc::new(Cell::new(0)), max_in_progress: Rc::new(Cell::new(0)) }
+    }
+}
+
+struct CountingConnect {
+    connect:     <TcpTransport as Transport>::FutureSocket,
+    in_progress: Rc<Cell<usize>>
+}
+
+impl Future for CountingConnect {
+    type Item = TcpStream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<TcpStream, io::Error> {
+        let result = self.connect.poll();
+
+        match result {
+            Ok(Async::NotReady) => (),
+            _                   => self.in_progress.set(self.in_progress.get() - 1)
+        }
+
+        result
+    }
+}
+
+impl Transport for CountingTransport {
+    type Socket = TcpStream;
+    type FutureSocket = CountingConnect;
+    type Listener = <TcpTransport as Transport>::Listener;
+
+    fn connect(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<CountingConnect> {
+        let connect = try!(TcpTransport.connect(addr, handle));
+
+        let in_progress = self.in_progress.get() + 1;
+        self.in_progress.set(in_progress);
+        if in_progress > self.max_in_progress.get() {
+            self.max_in_progress.set(in_progress);
+        }
+
+        Ok(CountingConnect{ connect: connect, in_progress: self.in_progress.clone() })
+    }
+
+    fn listen(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<Self::Listener> {
+        TcpTransport.listen(addr, handle)
+    }
+}
+
+/// Initiate a number of handshakes using the given config, returning the most connects that were in progress at once.
+fn max_in_progress_initiates(config: HandshakerConfig) -> usize {
+    let mut core = Core::new().unwrap();
+    let transport = CountingTransport::new();
+
+    let handshaker_one_addr = "127.0.0.1:0".parse().unwrap();
+    let handshaker_one_pid = [4u8; bt::PEER_ID_LEN].into();
+
+    let handshaker_one = HandshakerBuilder::new()
+        .with_bind_addr(handshaker_one_addr)
+        .with_peer_id(handshaker_one_pid)
+        .with_config(config)
+        .build(transport.clone(), core.handle()).unwrap();
+
+    let mut handshaker_two_addr = "127.0.0.1:0".parse().unwrap();
+    let handshaker_two_pid = [5u8; bt::PEER_ID_LEN].into();
+
+    let handshaker_two = HandshakerBuilder::new()
+        .with_bind_addr(handshaker_two_addr)
+        .with_peer_id(handshaker_two_pid)
+        .build(TcpTransport, core.handle()).unwrap();
+
+    handshaker_two_addr.set_port(handshaker_two.port());
+
+    let (sink_one, stream_one) = handshaker_one.into_parts();
+    let mut blocking_sink_one = sink_one.wait();
+    for index in 0..3 {
+        blocking_sink_one.send(InitiateMessage::new(Protocol::BitTorrent, [index as u8; bt::INFO_HASH_LEN].into(), handshaker_two_addr))
+            .unwrap();
+    }
+
+    // All initiates should complete, even though only some can be in progress at a time
+    let (items_one, items_two) = core.run(stream_one.take(3).collect()
+        .join(handshaker_two.take(3).collect())
+        .map_err(|_| ())
+    ).unwrap();
+
+    assert_eq!(3, items_one.len());
+    assert_eq!(3, items_two.len());
+
+    for item in items_one {
+        assert_eq!(handshaker_two_addr, *item.address());
+        assert_eq!(handshaker_two_pid, *item.peer_id());
+    }
+
+    transport.max_in_progress.get()
+}
+
+#[test]
+fn positive_default_initiates_one_at_a_time() {
+    assert_eq!(1, max_in_progress_initiates(HandshakerConfig::default()));
+}
+
+#[test]
+fn positive_queue_initiates_over_max_half_open() {
+    let max_in_progress = max_in_progress_initiates(HandshakerConfig::default().with_max_half_open(2));
+
+    assert!(max_in_progress >= 1 && max_in_progress <= 2);
+}
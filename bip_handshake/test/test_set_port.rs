@@ -0,0 +1,39 @@
+use bip_handshake::{HandshakerBuilder, DiscoveryInfo};
+use bip_handshake::transports::TcpTransport;
+
+use tokio_core::reactor::{Core};
+
+#[test]
+fn positive_set_port_shared_across_clones() {
+    let core = Core::new().unwrap();
+
+    let handshaker = HandshakerBuilder::new()
+        .with_bind_addr("127.0.0.1:0".parse().unwrap())
+        .with_open_port(6881)
+        .build(TcpTransport, core.handle()).unwrap();
+    assert_eq!(6881, handshaker.port());
+
+    let (sink, _stream) = handshaker.into_parts();
+    let sink_clone = sink.clone();
+
+    sink.set_port(51413);
+    assert_eq!(51413, sink.port());
+    assert_eq!(51413, sink_clone.port());
+}
+
+#[test]
+fn positive_set_port_zero_reverts_to_listen_port() {
+    let core = Core::new().unwrap();
+
+    let listen_handshaker = HandshakerBuilder::new()
+        .with_bind_addr("127.0.0.1:0".parse().unwrap())
+        .build(TcpTransport, core.handle()).unwrap();
+    let listen_port = listen_handshaker.port();
+    assert!(listen_port != 0);
+
+    listen_handshaker.set_port(51413);
+    assert_eq!(51413, listen_handshaker.port());
+
+    listen_handshaker.set_port(0);
+    assert_eq!(listen_port, listen_handshaker.port());
+}
@@ -0,0 +1,63 @@
+use bip_handshake::{HandshakerBuilder, HandshakerConfig, InitiateMessage, Protocol, DiscoveryInfo, SocketOptions};
+use bip_handshake::transports::TcpTransport;
+
+use bip_util::bt::{self};
+use tokio_core::reactor::{Core};
+use futures::Future;
+use futures::stream::Stream;
+use futures::sink::Sink;
+use std::time::Duration;
+
+// Larger than the default buffer sizes on common platforms
+const BUFFER_SIZE: usize = 256 * 1024;
+
+#[test]
+fn positive_socket_options_applied_both_directions() {
+    let mut core = Core::new().unwrap();
+    let options = SocketOptions::new()
+        .with_nodelay(true)
+        .with_reuse_address(true)
+        .with_keepalive(Duration::from_secs(60))
+        .with_send_buffer_size(BUFFER_SIZE)
+        .with_recv_buffer_size(BUFFER_SIZE);
+    let config = HandshakerConfig::default()
+        .with_socket_options(options);
+
+    let handshaker_one = HandshakerBuilder::new()
+        .with_bind_addr("127.0.0.1:0".parse().unwrap())
+        .with_peer_id([4u8; bt::PEER_ID_LEN].into())
+        .with_config(config)
+        .build(TcpTransport, core.handle()).unwrap();
+
+    let mut handshaker_two_addr = "127.0.0.1:0".parse().unwrap();
+    let handshaker_two = HandshakerBuilder::new()
+        .with_bind_addr(handshaker_two_addr)
+        .with_peer_id([5u8; bt::PEER_ID_LEN].into())
+        .with_config(config)
+        .build(TcpTransport, core.handle()).unwrap();
+
+    handshaker_two_addr.set_port(handshaker_two.port());
+
+    let (item_one, item_two) = core.run(handshaker_one
+        .send(InitiateMessage::new(Protocol::BitTorrent, [55u8; bt::INFO_HASH_LEN].into(), handshaker_two_addr))
+        .map_err(|_| ())
+        .and_then(|handshaker_one| {
+            handshaker_one.into_future()
+                .join(handshaker_two.into_future())
+                .map_err(|_| ())
+        })
+        .map(|((opt_item_one, _), (opt_item_two, _))| {
+            (opt_item_one.unwrap(), opt_item_two.unwrap())
+        })
+    ).unwrap();
+
+    // Outbound connection was configured once connected, inbound connection was configured when accepted
+    for socket in [item_one.socket(), item_two.socket()].iter() {
+        assert!(socket.nodelay().unwrap());
+        assert_eq!(Some(Duration::from_secs(60)), socket.keepalive().unwrap());
+
+        // Some platforms (linux) report double the size that was set, to account for bookkeeping overhead
+        assert!(socket.send_buffer_size().unwrap() >= BUFFER_SIZE);
+        assert!(socket.recv_buffer_size().unwrap() >= BUFFER_SIZE);
+    }
+}
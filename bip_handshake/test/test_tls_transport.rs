@@ -0,0 +1,96 @@
+use std::io;
+use std::net::TcpStream;
+use std::time::Duration;
+
+use {TimeoutResult};
+use bip_handshake::{Transport, LocalAddr};
+use bip_handshake::transports::TlsTransport;
+
+use futures::{Future};
+use futures::stream::Stream;
+use native_tls::{TlsConnector, TlsAcceptor, Pkcs12};
+use tokio_core::reactor::{Core, Timeout};
+use tokio_io::io::{read_exact, write_all};
+
+/// Self signed certificate, only used for testing.
+const IDENTITY: &'static [u8] = include_bytes!("data/identity.p12");
+const IDENTITY_PASSWORD: &'static str = "bip_handshake";
+
+fn any_tls_transport() -> TlsTransport {
+    let identity = Pkcs12::from_der(IDENTITY, IDENTITY_PASSWORD).unwrap();
+    let acceptor = TlsAcceptor::builder(identity).unwrap().build().unwrap();
+    let connector = TlsConnector::builder().unwrap().build().unwrap();
+
+    TlsTransport::new(connector)
+        .with_acceptor(acceptor)
+        .with_certificate_validation(false)
+}
+
+#[test]
+fn positive_tls_connect_accept_round_trip() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let transport = any_tls_transport();
+
+    let listener = transport.listen(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let listen_addr = listener.local_addr().unwrap();
+
+    let connect = transport.connect(&listen_addr, &handle).unwrap()
+        .and_then(|stream| write_all(stream, b"hello"));
+    let accept = listener.into_future()
+        .map_err(|(err, _)| err)
+        .and_then(|(opt_item, _)| {
+            let (stream, _) = opt_item.unwrap();
+
+            read_exact(stream, [0u8; 5])
+        });
+
+    let (_, (_, buffer)) = core.run(connect.join(accept)).unwrap();
+
+    assert_eq!(b"hello", &buffer);
+}
+
+#[test]
+fn positive_tls_stalled_accept_times_out() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let transport = any_tls_transport()
+        .with_max_pending_accepts(1)
+        .with_accept_timeout(Duration::from_millis(100));
+
+    let listener = transport.listen(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let listen_addr = listener.local_addr().unwrap();
+
+    // Never sends anything, so it takes up the only pending accept until it times out
+    let _stalled = TcpStream::connect(listen_addr).unwrap();
+
+    let connect = transport.connect(&listen_addr, &handle).unwrap()
+        .and_then(|stream| write_all(stream, b"hello"));
+    let accept = listener.into_future()
+        .map_err(|(err, _)| err)
+        .and_then(|(opt_item, _)| {
+            let (stream, _) = opt_item.unwrap();
+
+            read_exact(stream, [0u8; 5])
+        });
+
+    let timeout = Timeout::new(Duration::from_millis(5000), &handle).unwrap().map(|_| TimeoutResult::TimedOut).map_err(|_| ());
+    let result = connect.join(accept).map(|_| TimeoutResult::GotResult).map_err(|_| ());
+
+    let timeout_result = core.run(result.select(timeout).map(|(item, _)| item).map_err(|_| ())).unwrap();
+
+    assert_eq!(TimeoutResult::GotResult, timeout_result);
+}
+
+#[test]
+fn negative_tls_listen_without_acceptor() {
+    let core = Core::new().unwrap();
+    let connector = TlsConnector::builder().unwrap().build().unwrap();
+    let transport = TlsTransport::new(connector);
+
+    match transport.listen(&"127.0.0.1:0".parse().unwrap(), &core.handle()) {
+        Err(ref err) if err.kind() == io::ErrorKind::InvalidInput => (),
+        Err(err) => panic!("Unexpected Error: {:?}", err),
+        Ok(_)    => panic!("Listened Without A TlsAcceptor")
+    }
+}
@@ -131,6 +131,11 @@ impl MagnetLink {
             _ => None,
         }
     }
+
+    /// Trackers (`tr` params) that were given in the link.
+    pub fn get_trackers(&self) -> &[String] {
+        &self.address_tracker
+    }
 }
 
 
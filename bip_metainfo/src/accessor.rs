@@ -1,8 +1,9 @@
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, Cursor, Read};
 use std::path::{Path, PathBuf};
 
 use bip_util::sha::ShaHash;
+use crossbeam;
 use walkdir::{self, WalkDir, DirEntry};
 
 /// Trait for types convertible as a Result into some Accessor.
@@ -66,10 +67,27 @@ pub enum PieceAccess<'a> {
 
 // ----------------------------------------------------------------------------//
 
+/// Order in which a `FileAccessor` will access the files within a directory.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FileOrder {
+    /// Files are ordered by their relative paths, compared component by component.
+    ///
+    /// This ordering does not depend on the file system, so building a torrent from
+    /// the same directory will always produce the same info hash.
+    Lexicographic,
+    /// Files are ordered as they are returned by the file system.
+    ///
+    /// This ordering may differ between file systems (or even between runs), so the
+    /// info hash of the resulting torrent may not be reproducible.
+    FileSystem
+}
+
 /// Accessor that pulls data in from the file system.
 pub struct FileAccessor {
-    absolute_path:  PathBuf,
-    directory_name: Option<PathBuf>,
+    absolute_path:    PathBuf,
+    directory_name:   Option<PathBuf>,
+    file_order:       FileOrder,
+    metadata_threads: usize
 }
 
 impl FileAccessor {
@@ -89,8 +107,53 @@ impl FileAccessor {
         Ok(FileAccessor {
             absolute_path: absolute_path,
             directory_name: directory_name,
+            file_order: FileOrder::FileSystem,
+            metadata_threads: 1
         })
     }
+
+    /// Set the order that files within a directory will be accessed in.
+    ///
+    /// Defaults to `FileOrder::FileSystem`, so existing torrents built from the same
+    /// directory keep the same info hash.
+    pub fn set_file_order(mut self, file_order: FileOrder) -> FileAccessor {
+        self.file_order = file_order;
+        self
+    }
+
+    /// Set the number of threads used to collect the metadata of files within a directory.
+    ///
+    /// The order of the files is not affected by the number of threads. Defaults to 1.
+    pub fn set_metadata_threads(mut self, threads: usize) -> FileAccessor {
+        if threads == 0 {
+            panic!("bip_metainfo: Cannot Collect Metadata With threads == 0");
+        }
+
+        self.metadata_threads = threads;
+        self
+    }
+
+    /// Get the order that files within a directory will be accessed in.
+    pub fn file_order(&self) -> FileOrder {
+        self.file_order
+    }
+
+    /// Walk the file system, returning the paths of all files in the order they should be accessed.
+    fn file_paths(&self) -> io::Result<Vec<PathBuf>> {
+        let walk_dir = match self.file_order {
+            FileOrder::Lexicographic => WalkDir::new(&self.absolute_path).sort_by(|a, b| a.file_name().cmp(b.file_name())),
+            FileOrder::FileSystem    => WalkDir::new(&self.absolute_path)
+        };
+
+        let mut paths = Vec::new();
+        for res_entry in walk_dir.into_iter().filter(entry_file_filter) {
+            let entry = try!(res_entry);
+
+            paths.push(entry.path().to_path_buf());
+        }
+
+        Ok(paths)
+    }
 }
 
 impl IntoAccessor for FileAccessor {
@@ -125,14 +188,14 @@ impl Accessor for FileAccessor {
             self.absolute_path.iter().count() - 1
         };
 
-        for res_entry in WalkDir::new(&self.absolute_path).into_iter().filter(entry_file_filter) {
-            let entry = try!(res_entry);
-            let entry_metadata = try!(entry.metadata());
+        // Walking the directory is serial, but collecting the metadata can be split across threads
+        let file_paths = try!(self.file_paths());
+        let file_lengths = try!(collect_file_lengths(&file_paths, self.metadata_threads));
 
-            let file_length = entry_metadata.len();
+        for (path, file_length) in file_paths.iter().zip(file_lengths) {
             // TODO: Switch to using strip_relative when it is stabilized
             let relative_path =
-                entry.path().iter().skip(num_skip_paths).fold(PathBuf::new(), |mut acc, nex| {
+                path.iter().skip(num_skip_paths).fold(PathBuf::new(), |mut acc, nex| {
                     acc.push(nex);
                     acc
                 });
@@ -146,9 +209,9 @@ impl Accessor for FileAccessor {
     fn access_pieces<C>(&self, mut callback: C) -> io::Result<()>
         where C: for<'a> FnMut(PieceAccess<'a>) -> io::Result<()>
     {
-        for res_entry in WalkDir::new(&self.absolute_path).into_iter().filter(entry_file_filter) {
-            let entry = try!(res_entry);
-            let mut file = try!(File::open(entry.path()));
+        // Pieces have to be accessed in the same order that the metadata was accessed in
+        for path in try!(self.file_paths()) {
+            let mut file = try!(File::open(path));
 
             try!(callback(PieceAccess::Compute(&mut file)));
         }
@@ -162,6 +225,35 @@ fn entry_file_filter(res_entry: &walkdir::Result<DirEntry>) -> bool {
     res_entry.as_ref().map(|f| f.file_type().is_file()).unwrap_or(true)
 }
 
+/// Collect the lengths of the given files, in order, splitting the work across the given number of threads.
+fn collect_file_lengths(paths: &[PathBuf], threads: usize) -> io::Result<Vec<u64>> {
+    if threads <= 1 || paths.len() <= 1 {
+        return file_lengths(paths);
+    }
+
+    let chunk_size = (paths.len() + threads - 1) / threads;
+    let chunk_results: Vec<io::Result<Vec<u64>>> = crossbeam::scope(|scope| {
+        let handles: Vec<_> = paths.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || file_lengths(chunk)))
+            .collect();
+
+        handles.into_iter().map(|handle| handle.join()).collect()
+    });
+
+    let mut lengths = Vec::with_capacity(paths.len());
+    for res_chunk in chunk_results {
+        lengths.extend(try!(res_chunk));
+    }
+
+    Ok(lengths)
+}
+
+fn file_lengths(paths: &[PathBuf]) -> io::Result<Vec<u64>> {
+    paths.iter()
+        .map(|path| fs::metadata(path).map(|metadata| metadata.len()))
+        .collect()
+}
+
 // ----------------------------------------------------------------------------//
 
 /// Accessor that pulls data in directly from memory.
@@ -212,3 +304,67 @@ impl<'a> Accessor for DirectAccessor<'a> {
         callback(PieceAccess::Compute(&mut cursor))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    use super::{Accessor, FileAccessor, FileOrder};
+
+    use rand::{self, Rng};
+
+    fn create_test_dir() -> PathBuf {
+        let dir_name = format!("bip_metainfo_accessor_{}", rand::thread_rng().gen::<u64>());
+        let root = env::temp_dir().join(dir_name);
+
+        fs::create_dir_all(root.join("a")).unwrap();
+        for &(path, len) in &[("b.txt", 3), ("a.txt", 5), ("a/c.txt", 7)] {
+            File::create(root.join(path)).unwrap().write_all(&vec![0u8; len]).unwrap();
+        }
+
+        root
+    }
+
+    fn collect_metadata(accessor: &FileAccessor) -> Vec<(u64, PathBuf)> {
+        let mut metadata = Vec::new();
+        accessor.access_metadata(|len, path| metadata.push((len, path.to_path_buf()))).unwrap();
+
+        metadata
+    }
+
+    #[test]
+    fn positive_lexicographic_file_order() {
+        let root = create_test_dir();
+        let accessor = FileAccessor::new(&root).unwrap().set_file_order(FileOrder::Lexicographic);
+
+        let metadata = collect_metadata(&accessor);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(vec![(7, Path::new("a").join("c.txt")), (5, PathBuf::from("a.txt")), (3, PathBuf::from("b.txt"))], metadata);
+    }
+
+    #[test]
+    fn positive_default_file_system_order() {
+        let root = create_test_dir();
+        let accessor = FileAccessor::new(&root).unwrap();
+
+        assert_eq!(FileOrder::FileSystem, accessor.file_order());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn positive_metadata_threads_same_order() {
+        let root = create_test_dir();
+        let single_accessor = FileAccessor::new(&root).unwrap();
+        let multi_accessor = FileAccessor::new(&root).unwrap().set_metadata_threads(2);
+
+        let single_metadata = collect_metadata(&single_accessor);
+        let multi_metadata = collect_metadata(&multi_accessor);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(single_metadata, multi_metadata);
+    }
+}
@@ -0,0 +1,316 @@
+use std::cell::RefCell;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+use accessor::{Accessor, IntoAccessor, PieceAccess};
+
+use tar::Archive;
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+/// Accessor that pulls data in directly from a tar archive.
+///
+/// Paths of files within the archive are used as the paths within the torrent, with the
+/// given name used as the torrent (directory) name, and only regular files are included.
+/// Since metadata and pieces are accessed separately, the reader is seeked back to its
+/// start and streamed through for each access.
+pub struct TarAccessor<R> {
+    name:   PathBuf,
+    reader: RefCell<R>,
+}
+
+impl<R> TarAccessor<R>
+    where R: Read + Seek
+{
+    /// Create a new TarAccessor from the given torrent name and archive reader.
+    pub fn new<N>(name: N, reader: R) -> TarAccessor<R>
+        where N: Into<PathBuf>
+    {
+        TarAccessor {
+            name: name.into(),
+            reader: RefCell::new(reader),
+        }
+    }
+}
+
+impl<R> IntoAccessor for TarAccessor<R>
+    where R: Read + Seek
+{
+    type Accessor = TarAccessor<R>;
+
+    fn into_accessor(self) -> io::Result<TarAccessor<R>> {
+        Ok(self)
+    }
+}
+
+impl<R> Accessor for TarAccessor<R>
+    where R: Read + Seek
+{
+    fn access_directory(&self) -> Option<&Path> {
+        Some(self.name.as_ref())
+    }
+
+    fn access_metadata<C>(&self, mut callback: C) -> io::Result<()>
+        where C: FnMut(u64, &Path)
+    {
+        let mut reader = self.reader.borrow_mut();
+        try!(reader.seek(SeekFrom::Start(0)));
+
+        let mut archive = Archive::new(&mut *reader);
+        for res_entry in try!(archive.entries()) {
+            let entry = try!(res_entry);
+
+            if entry.header().entry_type().is_file() {
+                let file_length = try!(entry.header().size());
+                let relative_path = try!(archive_path(&*try!(entry.path())));
+
+                callback(file_length, relative_path.as_path());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn access_pieces<C>(&self, mut callback: C) -> io::Result<()>
+        where C: for<'a> FnMut(PieceAccess<'a>) -> io::Result<()>
+    {
+        let mut reader = self.reader.borrow_mut();
+        try!(reader.seek(SeekFrom::Start(0)));
+
+        let mut archive = Archive::new(&mut *reader);
+        for res_entry in try!(archive.entries()) {
+            let mut entry = try!(res_entry);
+
+            if entry.header().entry_type().is_file() {
+                try!(callback(PieceAccess::Compute(&mut entry)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Accessor that pulls data in directly from a zip archive.
+///
+/// Paths of files within the archive are used as the paths within the torrent, with the
+/// given name used as the torrent (directory) name, and directory entries are skipped.
+/// Files are accessed in the order they appear in the central directory of the archive.
+pub struct ZipAccessor<R>
+    where R: Read + Seek
+{
+    name:    PathBuf,
+    archive: RefCell<ZipArchive<R>>,
+}
+
+impl<R> ZipAccessor<R>
+    where R: Read + Seek
+{
+    /// Create a new ZipAccessor from the given torrent name and archive reader.
+    ///
+    /// Fails if the central directory of the archive could not be read.
+    pub fn new<N>(name: N, reader: R) -> io::Result<ZipAccessor<R>>
+        where N: Into<PathBuf>
+    {
+        let archive = try!(ZipArchive::new(reader).map_err(zip_error));
+
+        Ok(ZipAccessor {
+            name: name.into(),
+            archive: RefCell::new(archive),
+        })
+    }
+}
+
+impl<R> IntoAccessor for ZipAccessor<R>
+    where R: Read + Seek
+{
+    type Accessor = ZipAccessor<R>;
+
+    fn into_accessor(self) -> io::Result<ZipAccessor<R>> {
+        Ok(self)
+    }
+}
+
+impl<R> Accessor for ZipAccessor<R>
+    where R: Read + Seek
+{
+    fn access_directory(&self) -> Option<&Path> {
+        Some(self.name.as_ref())
+    }
+
+    fn access_metadata<C>(&self, mut callback: C) -> io::Result<()>
+        where C: FnMut(u64, &Path)
+    {
+        let mut archive = self.archive.borrow_mut();
+
+        for index in 0..archive.len() {
+            let entry = try!(archive.by_index(index).map_err(zip_error));
+
+            if !entry.name().ends_with('/') {
+                let relative_path = try!(archive_path(Path::new(entry.name())));
+
+                callback(entry.size(), relative_path.as_path());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn access_pieces<C>(&self, mut callback: C) -> io::Result<()>
+        where C: for<'a> FnMut(PieceAccess<'a>) -> io::Result<()>
+    {
+        let mut archive = self.archive.borrow_mut();
+
+        for index in 0..archive.len() {
+            let mut entry = try!(archive.by_index(index).map_err(zip_error));
+
+            if !entry.name().ends_with('/') {
+                try!(callback(PieceAccess::Compute(&mut entry)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Convert a `ZipError` into an `io::Error`.
+fn zip_error(error: ZipError) -> io::Error {
+    match error {
+        ZipError::Io(io_error) => io_error,
+        other                  => io::Error::new(io::ErrorKind::InvalidData, other.to_string()),
+    }
+}
+
+/// Convert a path within an archive into a relative path suitable for a torrent.
+///
+/// Rejects paths that are absolute or that would escape the root of the archive.
+fn archive_path(path: &Path) -> io::Result<PathBuf> {
+    let mut relative_path = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => relative_path.push(name),
+            Component::CurDir       => (),
+            _                       => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("Invalid Path In Archive: {:?}", path)))
+            }
+        }
+    }
+
+    if relative_path.as_os_str().is_empty() {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "Empty Path In Archive"))
+    } else {
+        Ok(relative_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use std::path::{Path, PathBuf};
+
+    use bip_util::sha::ShaHash;
+    use tar;
+    use zip::{CompressionMethod, ZipWriter};
+
+    use {Metainfo, MetainfoBuilder, PieceLength};
+    use super::{TarAccessor, ZipAccessor};
+
+    const FILES: &'static [(&'static str, &'static [u8])] = &[("backup/a.txt", b"Hello World!"),
+                                                              ("backup/nested/b.txt", b"Goodbye World!")];
+
+    fn tar_archive() -> Cursor<Vec<u8>> {
+        let mut builder = tar::Builder::new(Vec::new());
+
+        for &(path, contents) in FILES {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+
+            builder.append_data(&mut header, path, contents).unwrap();
+        }
+
+        Cursor::new(builder.into_inner().unwrap())
+    }
+
+    fn zip_archive() -> Cursor<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+
+        for &(path, contents) in FILES {
+            writer.start_file(path, CompressionMethod::Stored).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+
+        let mut cursor = writer.finish().unwrap();
+        cursor.set_position(0);
+        cursor
+    }
+
+    fn assert_archive_metainfo(metainfo_bytes: Vec<u8>) {
+        let metainfo = Metainfo::from_bytes(metainfo_bytes).unwrap();
+        let info = metainfo.info();
+
+        assert_eq!(Some(Path::new("archive")), info.directory());
+
+        let files: Vec<(u64, PathBuf)> = info.files().map(|file| (file.length(), file.path().to_path_buf())).collect();
+        let expected_files: Vec<(u64, PathBuf)> = FILES.iter()
+            .map(|&(path, contents)| (contents.len() as u64, PathBuf::from(path)))
+            .collect();
+        assert_eq!(expected_files, files);
+
+        let all_contents: Vec<u8> = FILES.iter().flat_map(|&(_, contents)| contents.iter().cloned()).collect();
+        let expected_piece = ShaHash::from_bytes(&all_contents);
+        assert_eq!(Some(expected_piece.as_ref()), info.pieces().next());
+    }
+
+    #[test]
+    fn positive_tar_accessor_build() {
+        let metainfo_bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(1024))
+            .build(1, TarAccessor::new("archive", tar_archive()), |_| ())
+            .unwrap();
+
+        assert_archive_metainfo(metainfo_bytes);
+    }
+
+    #[test]
+    fn positive_zip_accessor_build() {
+        let accessor = ZipAccessor::new("archive", zip_archive()).unwrap();
+        let metainfo_bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(1024))
+            .build(1, accessor, |_| ())
+            .unwrap();
+
+        assert_archive_metainfo(metainfo_bytes);
+    }
+
+    #[test]
+    fn negative_zip_accessor_not_an_archive() {
+        assert!(ZipAccessor::new("archive", Cursor::new(vec![0u8; 100])).is_err());
+    }
+
+    #[test]
+    fn positive_archive_path_strips_current_dir() {
+        let path = super::archive_path(Path::new("./backup/file.txt")).unwrap();
+
+        assert_eq!(path, PathBuf::from("backup/file.txt"));
+    }
+
+    #[test]
+    fn negative_archive_path_parent_dir() {
+        assert!(super::archive_path(Path::new("backup/../../file.txt")).is_err());
+    }
+
+    #[test]
+    fn negative_archive_path_absolute() {
+        assert!(super::archive_path(Path::new("/backup/file.txt")).is_err());
+    }
+
+    #[test]
+    fn negative_archive_path_empty() {
+        assert!(super::archive_path(Path::new("./")).is_err());
+    }
+}
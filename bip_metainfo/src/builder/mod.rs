@@ -4,7 +4,7 @@ use bip_bencode::{BencodeMut, BMutAccess, BRefAccess};
 use bip_util::sha::{self, ShaHash};
 
 use accessor::{Accessor, IntoAccessor};
-use error::ParseResult;
+use error::{ParseError, ParseErrorKind, ParseResult};
 use parse;
 
 mod buffer;
@@ -219,6 +219,17 @@ impl<'a> MetainfoBuilder<'a> {
 
         build_with_accessor(threads, accessor, progress, Some(self.root), self.info.info, self.info.piece_length)
     }
+
+    /// Build the metainfo file from the given accessor and precomputed piece hashes.
+    ///
+    /// Only the file metadata is read from the accessor, see `InfoBuilder::build_from_hashes`.
+    pub fn build_from_hashes<A>(self, accessor: A, pieces: &[ShaHash]) -> ParseResult<Vec<u8>>
+        where A: IntoAccessor
+    {
+        let accessor = try!(accessor.into_accessor());
+
+        build_from_hashes_with_accessor(accessor, pieces, Some(self.root), self.info.info, self.info.piece_length)
+    }
 }
 
 // ----------------------------------------------------------------------------//
@@ -268,6 +279,22 @@ impl<'a> InfoBuilder<'a> {
 
         build_with_accessor(threads, accessor, progress, None, self.info, self.piece_length)
     }
+
+    /// Build the info dictionary from the given accessor and precomputed piece hashes.
+    ///
+    /// Only the file metadata is read from the accessor, file contents are never read or
+    /// hashed, which is useful when re-creating a torrent for data that was already hashed
+    /// (such as from the resume data of another client). The piece length should be set to
+    /// the `PieceLength::Custom` that the hashes were computed with.
+    ///
+    /// Returns an error if the number of hashes does not match the number of pieces.
+    pub fn build_from_hashes<A>(self, accessor: A, pieces: &[ShaHash]) -> ParseResult<Vec<u8>>
+        where A: IntoAccessor
+    {
+        let accessor = try!(accessor.into_accessor());
+
+        build_from_hashes_with_accessor(accessor, pieces, None, self.info, self.piece_length)
+    }
 }
 
 // ----------------------------------------------------------------------------//
@@ -284,15 +311,7 @@ fn build_with_accessor<'a, A, C>(threads:       usize,
             panic!("bip_metainfo: Cannot Build Metainfo File With threads == 0");
         }
 
-        // Collect all of the file information into a list
-        let mut files_info = Vec::new();
-        try!(accessor.access_metadata(|len, path| {
-            let path_list: Vec<String> = path.iter()
-                .map(|os_str| os_str.to_string_lossy().into_owned())
-                .collect();
-
-            files_info.push((len, path_list));
-        }));
+        let files_info = try!(collect_files_info(&accessor));
 
         // Build the pieces for the data our accessor is pointing at
         let total_files_len = files_info.iter().fold(0, |acc, nex| acc + nex.0);
@@ -305,6 +324,54 @@ fn build_with_accessor<'a, A, C>(threads:       usize,
                                                             progress));
         let pieces = map_pieces_list(pieces_list.into_iter().map(|(_, piece)| piece));
 
+        encode_with_files_info(&accessor, files_info, piece_length, pieces, opt_root, info)
+}
+
+fn build_from_hashes_with_accessor<'a, A>(accessor:     A,
+                                          pieces:       &[ShaHash],
+                                          opt_root:     Option<BencodeMut<'a>>,
+                                          info:         BencodeMut<'a>,
+                                          piece_length: PieceLength) -> ParseResult<Vec<u8>>
+    where A: Accessor {
+        let files_info = try!(collect_files_info(&accessor));
+
+        // Hashes must cover the data exactly, otherwise peers would reject every piece
+        let total_files_len = files_info.iter().fold(0, |acc, nex| acc + nex.0);
+        let piece_length = determine_piece_length(total_files_len, piece_length);
+        let total_num_pieces = ((total_files_len as f64) / (piece_length as f64)).ceil() as u64;
+        if pieces.len() as u64 != total_num_pieces {
+            return Err(ParseError::from_kind(ParseErrorKind::PieceCountMismatch{
+                expected: total_num_pieces,
+                found:    pieces.len() as u64
+            }))
+        }
+        let pieces = map_pieces_list(pieces.iter().cloned());
+
+        encode_with_files_info(&accessor, files_info, piece_length, pieces, opt_root, info)
+}
+
+/// Collect all of the file information into a list, without accessing any file contents.
+fn collect_files_info<A>(accessor: &A) -> ParseResult<Vec<(u64, Vec<String>)>>
+    where A: Accessor {
+        let mut files_info = Vec::new();
+        try!(accessor.access_metadata(|len, path| {
+            let path_list: Vec<String> = path.iter()
+                .map(|os_str| os_str.to_string_lossy().into_owned())
+                .collect();
+
+            files_info.push((len, path_list));
+        }));
+
+        Ok(files_info)
+}
+
+fn encode_with_files_info<'a, A>(accessor:     &A,
+                                 files_info:   Vec<(u64, Vec<String>)>,
+                                 piece_length: usize,
+                                 pieces:       Vec<u8>,
+                                 opt_root:     Option<BencodeMut<'a>>,
+                                 info:         BencodeMut<'a>) -> ParseResult<Vec<u8>>
+    where A: Accessor {
         let mut single_file_name = String::new();
         let access_directory = accessor.access_directory().map(|path| path.to_string_lossy());
 
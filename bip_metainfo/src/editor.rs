@@ -0,0 +1,258 @@
+//! Editing the non info fields of an existing Metainfo file.
+use std::collections::BTreeMap;
+
+use bip_bencode::{BencodeRef, BencodeMut, BMutAccess, BRefAccess, BDecodeOpt};
+use bip_util::bt::InfoHash;
+
+use metainfo::{self, Metainfo};
+use parse;
+use error::ParseResult;
+
+/// Editor for the fields of an existing metainfo file that live outside of the info dictionary.
+///
+/// The info dictionary, as well as any fields that are not edited, are kept as the
+/// exact bytes they were parsed from, so the info hash of the torrent is preserved.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MetainfoEditor {
+    // Encoded values of the root dictionary, keyed (and so sorted) by their raw key.
+    entries: BTreeMap<Vec<u8>, Vec<u8>>
+}
+
+impl MetainfoEditor {
+    /// Create a `MetainfoEditor` from metainfo file bytes.
+    pub fn from_bytes<B>(bytes: B) -> ParseResult<MetainfoEditor>
+        where B: AsRef<[u8]>
+    {
+        let bytes_slice = bytes.as_ref();
+
+        // Make sure we are editing a valid metainfo file to begin with
+        try!(Metainfo::from_bytes(bytes_slice));
+
+        let root_bencode = try!(BencodeRef::decode(bytes_slice, BDecodeOpt::default()));
+        let root_dict = try!(parse::parse_root_dict(&root_bencode));
+
+        let entries = root_dict.to_list().into_iter()
+            .map(|(key, value)| (key.to_vec(), value.buffer().to_vec()))
+            .collect();
+
+        Ok(MetainfoEditor{ entries: entries })
+    }
+
+    /// Set or unset the main tracker that this torrent file points to.
+    pub fn set_main_tracker(self, opt_tracker_url: Option<&str>) -> MetainfoEditor {
+        self.set_entry(parse::ANNOUNCE_URL_KEY, opt_tracker_url.map(|url| ben_bytes!(url)))
+    }
+
+    /// Set or unset the announce-list content.
+    pub fn set_trackers(self, opt_trackers: Option<&[Vec<String>]>) -> MetainfoEditor {
+        let opt_bencode = opt_trackers.map(|groups| {
+            let mut list = BencodeMut::new_list();
+
+            {
+                let list_access = list.list_mut().unwrap();
+
+                for group in groups.iter() {
+                    list_access.push(string_list(group));
+                }
+            }
+
+            list
+        });
+
+        self.set_entry(parse::ANNOUNCE_LIST_KEY, opt_bencode)
+    }
+
+    /// Set or unset the web seeds (BEP 19) for the torrent.
+    pub fn set_web_seeds(self, opt_web_seeds: Option<&[String]>) -> MetainfoEditor {
+        self.set_entry(parse::URL_LIST_KEY, opt_web_seeds.map(string_list))
+    }
+
+    /// Set or unset the creation date for the torrent.
+    pub fn set_creation_date(self, opt_secs_epoch: Option<i64>) -> MetainfoEditor {
+        self.set_entry(parse::CREATION_DATE_KEY, opt_secs_epoch.map(|secs_epoch| ben_int!(secs_epoch)))
+    }
+
+    /// Set or unset a comment for the torrent file.
+    pub fn set_comment(self, opt_comment: Option<&str>) -> MetainfoEditor {
+        self.set_entry(parse::COMMENT_KEY, opt_comment.map(|comment| ben_bytes!(comment)))
+    }
+
+    /// Set or unset the created by for the torrent file.
+    pub fn set_created_by(self, opt_created_by: Option<&str>) -> MetainfoEditor {
+        self.set_entry(parse::CREATED_BY_KEY, opt_created_by.map(|created_by| ben_bytes!(created_by)))
+    }
+
+    /// Get the current main tracker.
+    pub fn get_main_tracker(&self) -> Option<String> {
+        self.lookup_entry(parse::ANNOUNCE_URL_KEY, |bencode| bencode.str().map(String::from))
+    }
+
+    /// Get the current announce-list content.
+    pub fn get_trackers(&self) -> Option<Vec<Vec<String>>> {
+        self.lookup_entry(parse::ANNOUNCE_LIST_KEY, |bencode| bencode.list().map(parse::convert_announce_list))
+    }
+
+    /// Get the current web seeds.
+    ///
+    /// A single web seed that was not encoded as a list will be returned as a list of one.
+    pub fn get_web_seeds(&self) -> Option<Vec<String>> {
+        self.lookup_entry(parse::URL_LIST_KEY, |bencode| {
+            bencode.str()
+                .map(|url| vec![url.to_owned()])
+                .or_else(|| {
+                    bencode.list().map(|list| {
+                        list.into_iter()
+                            .filter_map(|bencode_str| bencode_str.str())
+                            .map(String::from)
+                            .collect()
+                    })
+                })
+        })
+    }
+
+    /// Get the current creation date.
+    pub fn get_creation_date(&self) -> Option<i64> {
+        self.lookup_entry(parse::CREATION_DATE_KEY, |bencode| bencode.int())
+    }
+
+    /// Get the current comment.
+    pub fn get_comment(&self) -> Option<String> {
+        self.lookup_entry(parse::COMMENT_KEY, |bencode| bencode.str().map(String::from))
+    }
+
+    /// Get the current created by.
+    pub fn get_created_by(&self) -> Option<String> {
+        self.lookup_entry(parse::CREATED_BY_KEY, |bencode| bencode.str().map(String::from))
+    }
+
+    /// Hash of the (untouched) info dictionary.
+    pub fn info_hash(&self) -> InfoHash {
+        let info_bytes = self.entries.get(parse::INFO_KEY)
+            .expect("bip_metainfo: MetainfoEditor::info_hash Missing Info Dictionary");
+
+        InfoHash::from_bytes(info_bytes)
+    }
+
+    /// Retrieve the bencoded bytes for the edited metainfo file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        metainfo::encode_dict_entries(&self.entries)
+    }
+
+    /// Parse the edited metainfo file into a `Metainfo`.
+    pub fn to_metainfo(&self) -> ParseResult<Metainfo> {
+        Metainfo::from_bytes(self.to_bytes())
+    }
+
+    fn set_entry(mut self, key: &[u8], opt_bencode: Option<BencodeMut>) -> MetainfoEditor {
+        if let Some(bencode) = opt_bencode {
+            self.entries.insert(key.to_vec(), bencode.encode());
+        } else {
+            self.entries.remove(key);
+        }
+
+        self
+    }
+
+    fn lookup_entry<F, T>(&self, key: &[u8], convert: F) -> Option<T>
+        where F: for<'a> FnOnce(&BencodeRef<'a>) -> Option<T> {
+        self.entries.get(key)
+            .and_then(|bytes| BencodeRef::decode(bytes, BDecodeOpt::default()).ok())
+            .and_then(|bencode| convert(&bencode))
+    }
+}
+
+/// Encode the given strings as a bencoded list.
+fn string_list<'a>(strings: &'a [String]) -> BencodeMut<'a> {
+    let mut list = BencodeMut::new_list();
+
+    {
+        let list_access = list.list_mut().unwrap();
+
+        for string in strings.iter() {
+            list_access.push(ben_bytes!(&string[..]));
+        }
+    }
+
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use builder::MetainfoBuilder;
+    use accessor::DirectAccessor;
+    use metainfo::Metainfo;
+    use editor::MetainfoEditor;
+
+    fn build_metainfo_bytes() -> Vec<u8> {
+        let accessor = DirectAccessor::new("FileName.txt", b"This is our file data");
+
+        MetainfoBuilder::new()
+            .set_main_tracker(Some("udp://tracker.example.com:6969"))
+            .set_comment(Some("Original Comment"))
+            .build(1, accessor, |_| ())
+            .unwrap()
+    }
+
+    #[test]
+    fn positive_edit_preserves_info_hash() {
+        let bytes = build_metainfo_bytes();
+        let original = Metainfo::from_bytes(&bytes).unwrap();
+
+        let trackers = vec![vec!["http://a.example.com/announce".to_owned()],
+                            vec!["http://b.example.com/announce".to_owned()]];
+        let web_seeds = vec!["http://seed.example.com/".to_owned()];
+        let edited = MetainfoEditor::from_bytes(&bytes).unwrap()
+            .set_main_tracker(Some("http://a.example.com/announce"))
+            .set_trackers(Some(&trackers[..]))
+            .set_web_seeds(Some(&web_seeds[..]))
+            .set_comment(None)
+            .set_creation_date(Some(1000))
+            .to_metainfo()
+            .unwrap();
+
+        assert_eq!(original.info().info_hash(), edited.info().info_hash());
+        assert_eq!(original.info().raw_bytes(), edited.info().raw_bytes());
+
+        assert_eq!(Some("http://a.example.com/announce"), edited.main_tracker());
+        assert_eq!(Some(&trackers), edited.trackers());
+        assert_eq!(None, edited.comment());
+        assert_eq!(Some(1000), edited.creation_date());
+    }
+
+    #[test]
+    fn positive_edit_getters() {
+        let web_seeds = vec!["http://seed.example.com/".to_owned()];
+        let editor = MetainfoEditor::from_bytes(build_metainfo_bytes()).unwrap()
+            .set_web_seeds(Some(&web_seeds[..]))
+            .set_created_by(Some("bip_metainfo"));
+
+        assert_eq!(Some("udp://tracker.example.com:6969".to_owned()), editor.get_main_tracker());
+        assert_eq!(Some("Original Comment".to_owned()), editor.get_comment());
+        assert_eq!(Some("bip_metainfo".to_owned()), editor.get_created_by());
+        assert_eq!(Some(web_seeds), editor.get_web_seeds());
+        assert_eq!(None, editor.get_trackers());
+    }
+
+    #[test]
+    fn positive_no_edits_round_trip() {
+        let bytes = build_metainfo_bytes();
+
+        assert_eq!(bytes, MetainfoEditor::from_bytes(&bytes).unwrap().to_bytes());
+    }
+
+    #[test]
+    fn positive_unknown_fields_preserved() {
+        let metainfo = Metainfo::from_bytes(build_metainfo_bytes()).unwrap();
+
+        let bytes = [&b"d4:info"[..], metainfo.info().raw_bytes(),
+                     &b"8:url-list24:http://seed.example.com/8:x-customi5ee"[..]].concat();
+
+        let editor = MetainfoEditor::from_bytes(&bytes).unwrap()
+            .set_comment(Some("Edited Comment"));
+        let edited_bytes = editor.to_bytes();
+
+        assert_eq!(Some(vec!["http://seed.example.com/".to_owned()]), editor.get_web_seeds());
+        assert_eq!(metainfo.info().info_hash(), editor.info_hash());
+        assert!(edited_bytes.ends_with(b"8:x-customi5ee"));
+    }
+}
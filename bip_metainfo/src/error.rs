@@ -24,5 +24,18 @@ error_chain! {
             description("Missing Data Detected In File")
             display("Missing Data Detected In File: {}", details)
         }
+        RoundTripMismatch {
+            details: String
+        } {
+            description("Re-Encoded File Does Not Match Original")
+            display("Re-Encoded File Does Not Match Original: {}", details)
+        }
+        PieceCountMismatch {
+            expected: u64,
+            found:    u64
+        } {
+            description("Number Of Piece Hashes Does Not Match The File Data")
+            display("Number Of Piece Hashes Does Not Match The File Data, Expected {} But Found {}", expected, found)
+        }
     }
 }
@@ -1,5 +1,9 @@
 //! Iterators over torrent file information.
 
+use std::cmp;
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+
 use bip_util::sha;
 
 use metainfo::File;
@@ -60,4 +64,107 @@ impl<'a> Iterator for Pieces<'a> {
             None
         }
     }
-}
\ No newline at end of file
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Iterator over each unrecognized key, and its bencoded value, within a dictionary.
+pub struct UnknownKeys<'a> {
+    entries: btree_map::Iter<'a, Vec<u8>, Vec<u8>>,
+}
+
+impl<'a> UnknownKeys<'a> {
+    pub fn new(entries: &'a BTreeMap<Vec<u8>, Vec<u8>>) -> UnknownKeys<'a> {
+        UnknownKeys { entries: entries.iter() }
+    }
+}
+
+impl<'a> Iterator for UnknownKeys<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<(&'a [u8], &'a [u8])> {
+        self.entries.next().map(|(key, value)| (&key[..], &value[..]))
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Contiguous region of a single file covered by some byte range of the torrent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FileSegment<'a> {
+    file: &'a File,
+    offset: u64,
+    length: u64,
+}
+
+impl<'a> FileSegment<'a> {
+    /// File that the segment is in.
+    pub fn file(&self) -> &'a File {
+        self.file
+    }
+
+    /// Offset of the segment from the start of the file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Number of bytes in the segment.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+}
+
+/// Iterator over each `FileSegment` covered by a byte range of the torrent.
+pub struct FileSegments<'a> {
+    files: Files<'a>,
+    bytes_to_skip: u64,
+    bytes_left: u64,
+}
+
+impl<'a> FileSegments<'a> {
+    /// Create a `FileSegments` over the given files, starting `offset` bytes into the torrent
+    /// and covering `length` bytes.
+    ///
+    /// Files are treated as one contiguous byte range, in the order given. Iteration stops early
+    /// if the range extends past the end of the last file.
+    pub fn new(files: &'a [File], offset: u64, length: u64) -> FileSegments<'a> {
+        FileSegments {
+            files: Files::new(files),
+            bytes_to_skip: offset,
+            bytes_left: length,
+        }
+    }
+}
+
+impl<'a> Iterator for FileSegments<'a> {
+    type Item = FileSegment<'a>;
+
+    fn next(&mut self) -> Option<FileSegment<'a>> {
+        while self.bytes_left != 0 {
+            let file = match self.files.next() {
+                Some(file) => file,
+                None => return None,
+            };
+
+            // Skip files (or the starting portion of a file) before the range
+            if self.bytes_to_skip >= file.length() {
+                self.bytes_to_skip -= file.length();
+                continue;
+            }
+
+            let offset = self.bytes_to_skip;
+            let length = cmp::min(file.length() - offset, self.bytes_left);
+
+            self.bytes_to_skip = 0;
+            self.bytes_left -= length;
+
+            return Some(FileSegment {
+                file: file,
+                offset: offset,
+                length: length,
+            });
+        }
+
+        None
+    }
+}
@@ -65,12 +65,19 @@ extern crate crossbeam;
 extern crate walkdir;
 #[macro_use]
 extern crate error_chain;
+#[cfg(feature = "archive")]
+extern crate tar;
+#[cfg(feature = "archive")]
+extern crate zip;
 
 #[cfg(test)]
 extern crate rand;
 
 mod accessor;
+#[cfg(feature = "archive")]
+mod archive;
 mod builder;
+mod editor;
 pub mod error;
 mod metainfo;
 mod parse;
@@ -79,6 +86,9 @@ pub mod iter;
 
 pub use bip_util::bt::InfoHash;
 
-pub use accessor::{Accessor, IntoAccessor, DirectAccessor, FileAccessor, PieceAccess};
+pub use accessor::{Accessor, IntoAccessor, DirectAccessor, FileAccessor, FileOrder, PieceAccess};
+#[cfg(feature = "archive")]
+pub use archive::{TarAccessor, ZipAccessor};
 pub use builder::{MetainfoBuilder, PieceLength, InfoBuilder};
-pub use metainfo::{Info, Metainfo, File};
+pub use editor::MetainfoEditor;
+pub use metainfo::{Info, Metainfo, File, EncodeOpt};
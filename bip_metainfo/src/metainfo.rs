@@ -1,16 +1,17 @@
 //! Accessing the fields of a Metainfo file.
+use std::collections::BTreeMap;
+use std::cmp;
 use std::path::{Path, PathBuf};
 use std::io;
 
-use bip_bencode::{BencodeRef, BDictAccess, BDecodeOpt, BRefAccess};
+use bip_bencode::{BencodeRef, BencodeMut, BDictAccess, BDecodeOpt, BRefAccess, BMutAccess};
 use bip_util::bt::InfoHash;
 use bip_util::sha::{self, ShaHash};
 
 use accessor::{Accessor, PieceAccess, IntoAccessor};
-use builder::{MetainfoBuilder, InfoBuilder, PieceLength};
 use parse;
 use error::{ParseError, ParseErrorKind, ParseResult};
-use iter::{Files, Pieces};
+use iter::{Files, FileSegments, Pieces, UnknownKeys};
 
 /// Contains optional metadata for a torrent file.
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -21,6 +22,8 @@ pub struct Metainfo {
     encoding: Option<String>,
     created_by: Option<String>,
     creation_date: Option<i64>,
+    // Encoded values of root dictionary entries that we do not recognize, keyed by their raw key.
+    unknown: BTreeMap<Vec<u8>, Vec<u8>>,
     info: Info,
 }
 
@@ -70,18 +73,88 @@ impl Metainfo {
     }
 
     /// Retrieve the bencoded bytes for the `Metainfo` file.
+    ///
+    /// The info dictionary is always encoded as the exact bytes it was parsed from,
+    /// so the info hash of the re-encoded file will not change.
     pub fn to_bytes(&self) -> Vec<u8> {
-        // Since there are no file system accesses here, should be fine to unwrap
-        MetainfoBuilder::new()
-            .set_main_tracker(self.main_tracker())
-            .set_creation_date(self.creation_date())
-            .set_comment(self.comment())
-            .set_created_by(self.created_by())
-            .set_private_flag(self.info().is_private())
-            // TODO: Revisit this cast...
-            .set_piece_length(PieceLength::Custom(self.info().piece_length() as usize))
-            .build(1, &self.info, |_| ())
-            .unwrap()
+        self.to_bytes_with_opt(EncodeOpt::default())
+    }
+
+    /// Retrieve the bencoded bytes for the `Metainfo` file, using the given `EncodeOpt`.
+    pub fn to_bytes_with_opt(&self, opt: EncodeOpt) -> Vec<u8> {
+        let mut entries = if opt.preserve_unknown() {
+            self.unknown.clone()
+        } else {
+            BTreeMap::new()
+        };
+
+        self.announce.as_ref().map(|announce| entries.insert(parse::ANNOUNCE_URL_KEY.to_vec(), ben_bytes!(&announce[..]).encode()));
+        self.announce_list.as_ref().map(|announce_list| entries.insert(parse::ANNOUNCE_LIST_KEY.to_vec(), encode_announce_list(announce_list)));
+        self.comment.as_ref().map(|comment| entries.insert(parse::COMMENT_KEY.to_vec(), ben_bytes!(&comment[..]).encode()));
+        self.encoding.as_ref().map(|encoding| entries.insert(parse::ENCODING_KEY.to_vec(), ben_bytes!(&encoding[..]).encode()));
+        self.created_by.as_ref().map(|created_by| entries.insert(parse::CREATED_BY_KEY.to_vec(), ben_bytes!(&created_by[..]).encode()));
+        self.creation_date.map(|creation_date| entries.insert(parse::CREATION_DATE_KEY.to_vec(), ben_int!(creation_date).encode()));
+        entries.insert(parse::INFO_KEY.to_vec(), self.info.raw_bytes().to_vec());
+
+        encode_dict_entries(&entries)
+    }
+
+    /// Verify that the given metainfo file bytes survive a round trip through `Metainfo`.
+    ///
+    /// The info dictionary of the re-encoded file must always match the original exactly. If
+    /// `full_file` is true, the entire re-encoded file must match the original byte-for-byte,
+    /// which generally only holds for canonically encoded files when unknown keys are preserved.
+    pub fn verify_round_trip<B>(bytes: B, opt: EncodeOpt, full_file: bool) -> ParseResult<()>
+        where B: AsRef<[u8]>
+    {
+        let bytes_slice = bytes.as_ref();
+
+        let metainfo = try!(Metainfo::from_bytes(bytes_slice));
+        let re_encoded = metainfo.to_bytes_with_opt(opt);
+        let re_parsed = try!(Metainfo::from_bytes(&re_encoded));
+
+        if metainfo.info().raw_bytes() != re_parsed.info().raw_bytes() {
+            let error_msg = format!("Info Dictionary Changed From {} To {} Bytes",
+                                    metainfo.info().raw_bytes().len(), re_parsed.info().raw_bytes().len());
+            Err(ParseError::from_kind(ParseErrorKind::RoundTripMismatch { details: error_msg }))
+        } else if full_file && bytes_slice != &re_encoded[..] {
+            let mismatch_index = bytes_slice.iter()
+                .zip(re_encoded.iter())
+                .position(|(a, b)| a != b)
+                .unwrap_or(cmp::min(bytes_slice.len(), re_encoded.len()));
+
+            let error_msg = format!("File Bytes Differ Starting At Index {}", mismatch_index);
+            Err(ParseError::from_kind(ParseErrorKind::RoundTripMismatch { details: error_msg }))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Options for encoding a `Metainfo` file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct EncodeOpt {
+    preserve_unknown: bool
+}
+
+impl EncodeOpt {
+    /// Create a new `EncodeOpt` object.
+    pub fn new(preserve_unknown: bool) -> EncodeOpt {
+        EncodeOpt{ preserve_unknown: preserve_unknown }
+    }
+
+    /// Whether or not root dictionary keys that we do not recognize should be encoded as they were parsed.
+    ///
+    /// Indexers may want to enable this so that fields such as web seeds or source tags are
+    /// not stripped from the files they serve.
+    pub fn preserve_unknown(&self) -> bool {
+        self.preserve_unknown
+    }
+}
+
+impl Default for EncodeOpt {
+    fn default() -> EncodeOpt {
+        EncodeOpt::new(false)
     }
 }
 
@@ -94,6 +167,7 @@ impl From<Info> for Metainfo {
             encoding: None,
             created_by: None,
             creation_date: None,
+            unknown: BTreeMap::new(),
             info: info
         }
     }
@@ -120,6 +194,11 @@ fn parse_meta_bytes(bytes: &[u8]) -> ParseResult<Metainfo> {
     let info_bencode = try!(parse::parse_info_bencode(root_dict));
     let info = try!(parse_info_dictionary(info_bencode));
 
+    let unknown = root_dict.to_list().into_iter()
+        .filter(|&(key, _)| !is_known_root_key(key))
+        .map(|(key, value)| (key.to_vec(), value.buffer().to_vec()))
+        .collect();
+
     Ok(Metainfo {
         comment: opt_comment,
         announce: announce,
@@ -127,22 +206,75 @@ fn parse_meta_bytes(bytes: &[u8]) -> ParseResult<Metainfo> {
         encoding: opt_encoding,
         created_by: opt_created_by,
         creation_date: opt_creation_date,
+        unknown: unknown,
         info: info
     })
 }
 
+/// Returns whether or not the root dictionary key is one that we parse into a `Metainfo`.
+fn is_known_root_key(key: &[u8]) -> bool {
+    [parse::ANNOUNCE_URL_KEY, parse::ANNOUNCE_LIST_KEY, parse::COMMENT_KEY, parse::ENCODING_KEY,
+     parse::CREATED_BY_KEY, parse::CREATION_DATE_KEY, parse::INFO_KEY].contains(&key)
+}
+
+/// Encode the given announce list as a bencoded list of lists.
+fn encode_announce_list(announce_list: &[Vec<String>]) -> Vec<u8> {
+    let mut list = BencodeMut::new_list();
+
+    {
+        let list_access = list.list_mut().unwrap();
+
+        for tier in announce_list.iter() {
+            let mut tier_list = BencodeMut::new_list();
+
+            {
+                let tier_access = tier_list.list_mut().unwrap();
+
+                for url in tier.iter() {
+                    tier_access.push(ben_bytes!(&url[..]));
+                }
+            }
+
+            list_access.push(tier_list);
+        }
+    }
+
+    list.encode()
+}
+
+/// Encode the given (sorted) keys and already encoded values as a bencoded dictionary.
+pub fn encode_dict_entries(entries: &BTreeMap<Vec<u8>, Vec<u8>>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    bytes.push(b'd');
+    for (key, value) in entries.iter() {
+        bytes.extend_from_slice(key.len().to_string().as_bytes());
+        bytes.push(b':');
+        bytes.extend_from_slice(key);
+
+        bytes.extend_from_slice(value);
+    }
+    bytes.push(b'e');
+
+    bytes
+}
+
 // ----------------------------------------------------------------------------//
 
 /// Contains directory and checksum data for a torrent file.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Info {
     info_hash:      InfoHash,
+    // Exact bytes of the info dictionary, as it was parsed.
+    raw_bytes:      Vec<u8>,
     files:          Vec<File>,
     pieces:         Vec<[u8; sha::SHA_HASH_LEN]>,
     piece_len:      u64,
     is_private:     Option<bool>,
     // Present only for multi file torrents.
     file_directory: Option<PathBuf>,
+    // Encoded values of info dictionary entries that we do not recognize, keyed by their raw key.
+    unknown:        BTreeMap<Vec<u8>, Vec<u8>>,
 }
 
 impl Info {
@@ -152,7 +284,19 @@ impl Info {
     {
         let bytes_slice = bytes.as_ref();
 
-        parse_info_bytes(bytes_slice)
+        parse_info_bytes(bytes_slice, BDecodeOpt::default())
+    }
+
+    /// Read an `Info` from info dictionary bytes, rejecting non canonical bencode.
+    ///
+    /// Use this for info dictionaries received from untrusted sources, such as peers,
+    /// so that the info hash is always computed over the canonical encoding.
+    pub fn from_bytes_strict<B>(bytes: B) -> ParseResult<Info>
+        where B: AsRef<[u8]>
+    {
+        let bytes_slice = bytes.as_ref();
+
+        parse_info_bytes(bytes_slice, BDecodeOpt::strict())
     }
 
     /// Hash to uniquely identify this torrent.
@@ -197,15 +341,44 @@ impl Info {
         Files::new(&self.files)
     }
 
+    /// Iterator over each region of a file that makes up the piece at the given index.
+    ///
+    /// The last piece may be shorter than the piece length, and pieces past the end of the
+    /// torrent yield no segments.
+    pub fn piece_segments<'a>(&'a self, piece_index: u64) -> FileSegments<'a> {
+        let offset = piece_index.saturating_mul(self.piece_len);
+
+        FileSegments::new(&self.files, offset, self.piece_len)
+    }
+
+    /// Iterator over each region of a file that makes up the given byte range of the torrent.
+    ///
+    /// Offsets are relative to the start of the first file, with files laid out back to
+    /// back in the order yielded by `Info::files`. Bytes past the end of the torrent are ignored.
+    pub fn range_segments<'a>(&'a self, offset: u64, length: u64) -> FileSegments<'a> {
+        FileSegments::new(&self.files, offset, length)
+    }
+
+    /// Iterator over each key, and its bencoded value, within the info dictionary that we do not recognize.
+    ///
+    /// Unknown keys are always re-emitted as part of `Info::raw_bytes`, these are provided for diagnostics.
+    pub fn unknown_keys<'a>(&'a self) -> UnknownKeys<'a> {
+        UnknownKeys::new(&self.unknown)
+    }
+
+    /// Exact bencoded bytes of the `Info` dictionary, as it was parsed.
+    ///
+    /// These are the bytes that the info hash was computed from, which makes
+    /// them suitable for serving to peers requesting the metadata (BEP 9).
+    pub fn raw_bytes(&self) -> &[u8] {
+        &self.raw_bytes
+    }
+
     /// Retrieve the bencoded bytes for the `Info` dictionary.
+    ///
+    /// Since we keep around the original bytes, this is a copy of `Info::raw_bytes`.
     pub fn to_bytes(&self) -> Vec<u8> {
-        // Since there are no file system accesses here, should be fine to unwrap
-        InfoBuilder::new()
-            .set_private_flag(self.is_private())
-            // TODO: Revisit this cast...
-            .set_piece_length(PieceLength::Custom(self.piece_length() as usize))
-            .build(1, self, |_| ())
-            .unwrap()
+        self.raw_bytes.clone()
     }
 }
 
@@ -250,15 +423,17 @@ impl Accessor for Info {
 }
 
 /// Parses the given info dictionary bytes and builds a Metainfo from them.
-fn parse_info_bytes(bytes: &[u8]) -> ParseResult<Info> {
-    let info_bencode = try!(BencodeRef::decode(bytes, BDecodeOpt::default()));
+fn parse_info_bytes(bytes: &[u8], opts: BDecodeOpt) -> ParseResult<Info> {
+    let info_bencode = try!(BencodeRef::decode(bytes, opts));
 
     parse_info_dictionary(&info_bencode)
 }
 
 /// Parses the given info dictionary and builds an Info from it.
 fn parse_info_dictionary<'a>(info_bencode: &BencodeRef<'a>) -> ParseResult<Info> {
-    let info_hash = InfoHash::from_bytes(info_bencode.buffer());
+    // Hash the original byte span of the dictionary, so we dont have to re-encode it
+    let raw_bytes = info_bencode.buffer().to_vec();
+    let info_hash = InfoHash::from_bytes(&raw_bytes);
 
     let info_dict = try!(parse::parse_root_dict(info_bencode));
     let piece_len = try!(parse::parse_piece_length(info_dict));
@@ -267,6 +442,11 @@ fn parse_info_dictionary<'a>(info_bencode: &BencodeRef<'a>) -> ParseResult<Info>
     let pieces = try!(parse::parse_pieces(info_dict));
     let piece_buffers = try!(allocate_pieces(pieces));
 
+    let unknown = info_dict.to_list().into_iter()
+        .filter(|&(key, _)| !is_known_info_key(key))
+        .map(|(key, value)| (key.to_vec(), value.buffer().to_vec()))
+        .collect();
+
     if is_multi_file_torrent(info_dict) {
         let file_directory = try!(parse::parse_name(info_dict));
         let mut file_directory_path = PathBuf::new();
@@ -284,26 +464,36 @@ fn parse_info_dictionary<'a>(info_bencode: &BencodeRef<'a>) -> ParseResult<Info>
 
         Ok(Info {
             info_hash: info_hash,
+            raw_bytes: raw_bytes,
             files: files_list,
             pieces: piece_buffers,
             piece_len: piece_len,
             is_private: is_private,
             file_directory: Some(file_directory_path),
+            unknown: unknown,
         })
     } else {
         let file = try!(File::as_single_file(info_dict));
 
         Ok(Info {
             info_hash: info_hash,
+            raw_bytes: raw_bytes,
             files: vec![file],
             pieces: piece_buffers,
             piece_len: piece_len,
             is_private: is_private,
             file_directory: None,
+            unknown: unknown,
         })
     }
 }
 
+/// Returns whether or not the info dictionary key is one that we parse into an `Info`.
+fn is_known_info_key(key: &[u8]) -> bool {
+    [parse::PIECE_LENGTH_KEY, parse::PIECES_KEY, parse::PRIVATE_KEY, parse::NAME_KEY, parse::FILES_KEY,
+     parse::LENGTH_KEY, parse::MD5SUM_KEY].contains(&key)
+}
+
 /// Returns whether or not this is a multi file torrent.
 fn is_multi_file_torrent<B>(info_dict: &BDictAccess<B::BKey, B>) -> bool
     where B: BRefAccess {
@@ -400,11 +590,14 @@ impl File {
 mod tests {
     use std::path::{Path, PathBuf};
 
-    use bip_bencode::{BencodeMut, BMutAccess};
+    use bip_bencode::{BencodeMut, BMutAccess, BencodeConvertErrorKind};
     use bip_util::sha;
     use bip_util::bt::InfoHash;
 
-    use metainfo::Metainfo;
+    use accessor::DirectAccessor;
+    use builder::MetainfoBuilder;
+    use error::ParseErrorKind;
+    use metainfo::{Info, Metainfo, EncodeOpt};
     use parse;
 
     /// Helper function for manually constructing a metainfo file based on the parameters given.
@@ -507,6 +700,7 @@ mod tests {
         let metainfo_file = Metainfo::from_bytes(root_dict.encode()).unwrap();
 
         assert_eq!(metainfo_file.info().info_hash(), info_hash);
+        assert_eq!(InfoHash::from_bytes(metainfo_file.info().raw_bytes()), info_hash);
         assert_eq!(metainfo_file.comment(), comment);
         assert_eq!(metainfo_file.created_by(), create_by);
         assert_eq!(metainfo_file.encoding(), encoding);
@@ -515,6 +709,7 @@ mod tests {
         assert_eq!(metainfo_file.info().directory(), directory.map(|d| d.as_ref()));
         assert_eq!(metainfo_file.info().piece_length(), piece_length.unwrap() as u64);
         assert_eq!(metainfo_file.info().is_private(), private.map(|private| private == 1));
+        assert_eq!(metainfo_file.info().unknown_keys().count(), 0);
 
         let pieces = pieces.unwrap();
         assert_eq!(pieces.chunks(sha::SHA_HASH_LEN).count(),
@@ -818,12 +1013,143 @@ mod tests {
                                    Some(vec![(Some(file_len), None, Some(file_paths))]));
     }
 
+    /// Build metainfo file bytes with an unknown key in both the root and info dictionaries.
+    fn build_bytes_with_unknown_keys() -> Vec<u8> {
+        let accessor = DirectAccessor::new("FileName.txt", b"This is our file data");
+        let metainfo_bytes = MetainfoBuilder::new()
+            .set_main_tracker(Some("udp://tracker.example.com:6969"))
+            .build(1, accessor, |_| ())
+            .unwrap();
+        let metainfo = Metainfo::from_bytes(metainfo_bytes).unwrap();
+
+        // Splice an unknown (but sorted) key onto the end of the info dictionary
+        let raw_info = metainfo.info().raw_bytes();
+        let info_bytes = [&raw_info[..raw_info.len() - 1], &b"6:sourcei1ee"[..]].concat();
+
+        [&b"d8:announce30:udp://tracker.example.com:69694:info"[..], &info_bytes[..],
+         &b"8:url-list24:http://seed.example.com/e"[..]].concat()
+    }
+
+    /// Info dictionary with files of length 5, 0, and 7, split into pieces of length 4.
+    fn build_multi_file_info() -> Info {
+        let mut bytes = b"d5:filesld6:lengthi5e4:pathl1:aeed6:lengthi0e4:pathl1:beed6:lengthi7e4:pathl1:ceee\
+                          4:name3:dir12:piece lengthi4e6:pieces60:".to_vec();
+        bytes.extend_from_slice(&[0u8; 3 * sha::SHA_HASH_LEN]);
+        bytes.push(b'e');
+
+        Info::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn positive_piece_segments_span_files() {
+        let info = build_multi_file_info();
+
+        let segments: Vec<(&Path, u64, u64)> = info.piece_segments(1)
+            .map(|segment| (segment.file().path(), segment.offset(), segment.length()))
+            .collect();
+        assert_eq!(segments, vec![(Path::new("a"), 4, 1), (Path::new("c"), 0, 3)]);
+    }
+
+    #[test]
+    fn positive_piece_segments_last_piece() {
+        let info = build_multi_file_info();
+
+        let segments: Vec<(&Path, u64, u64)> = info.piece_segments(2)
+            .map(|segment| (segment.file().path(), segment.offset(), segment.length()))
+            .collect();
+        assert_eq!(segments, vec![(Path::new("c"), 3, 4)]);
+        assert_eq!(info.piece_segments(3).count(), 0);
+    }
+
+    #[test]
+    fn positive_range_segments_past_end() {
+        let info = build_multi_file_info();
+
+        let segments: Vec<(&Path, u64, u64)> = info.range_segments(3, 100)
+            .map(|segment| (segment.file().path(), segment.offset(), segment.length()))
+            .collect();
+        assert_eq!(segments, vec![(Path::new("a"), 3, 2), (Path::new("c"), 0, 7)]);
+    }
+
+    #[test]
+    fn positive_round_trip_preserves_info() {
+        let bytes = build_bytes_with_unknown_keys();
+
+        Metainfo::verify_round_trip(&bytes, EncodeOpt::default(), false).unwrap();
+    }
+
+    #[test]
+    fn positive_round_trip_preserve_unknown_full_file() {
+        let bytes = build_bytes_with_unknown_keys();
+
+        Metainfo::verify_round_trip(&bytes, EncodeOpt::new(true), true).unwrap();
+        assert_eq!(bytes, Metainfo::from_bytes(&bytes).unwrap().to_bytes_with_opt(EncodeOpt::new(true)));
+    }
+
+    #[test]
+    fn positive_info_retains_unknown_keys() {
+        let bytes = build_bytes_with_unknown_keys();
+        let metainfo = Metainfo::from_bytes(&bytes).unwrap();
+
+        let unknown_keys: Vec<(&[u8], &[u8])> = metainfo.info().unknown_keys().collect();
+        assert_eq!(unknown_keys, vec![(&b"source"[..], &b"i1e"[..])]);
+
+        let info = Info::from_bytes(metainfo.info().to_bytes()).unwrap();
+        assert_eq!(info.info_hash(), metainfo.info().info_hash());
+        assert_eq!(info.unknown_keys().collect::<Vec<_>>(), unknown_keys);
+    }
+
+    #[test]
+    fn positive_info_from_bytes_strict() {
+        let bytes = build_bytes_with_unknown_keys();
+        let metainfo = Metainfo::from_bytes(&bytes).unwrap();
+
+        let info = Info::from_bytes_strict(metainfo.info().to_bytes()).unwrap();
+        assert_eq!(info.info_hash(), metainfo.info().info_hash());
+    }
+
+    #[test]
+    fn negative_info_from_bytes_strict_non_canonical() {
+        let mut bytes = b"d6:lengthi+5e4:name1:a12:piece lengthi1024e6:pieces20:".to_vec();
+        bytes.extend_from_slice(&[0u8; sha::SHA_HASH_LEN]);
+        bytes.push(b'e');
+
+        Info::from_bytes(&bytes).unwrap();
+        assert!(Info::from_bytes_strict(&bytes).is_err());
+    }
+
+    #[test]
+    fn negative_round_trip_strips_unknown_full_file() {
+        let bytes = build_bytes_with_unknown_keys();
+
+        let error = Metainfo::verify_round_trip(&bytes, EncodeOpt::default(), true).unwrap_err();
+        match error.kind() {
+            &ParseErrorKind::RoundTripMismatch{ .. } => (),
+            unexpected @ _ => panic!("Unexpected Error: {:?}", unexpected)
+        }
+    }
+
     #[test]
     #[should_panic]
     fn negative_parse_from_empty_bytes() {
         Metainfo::from_bytes(b"").unwrap();
     }
 
+    #[test]
+    fn negative_parse_missing_info_key_names_path() {
+        let error = Metainfo::from_bytes(&b"d4:infod6:lengthi0e4:name1:a6:pieces0:ee"[..]).unwrap_err();
+
+        match error.kind() {
+            &ParseErrorKind::BencodeConvert(ref error) => {
+                match error.kind() {
+                    &BencodeConvertErrorKind::MissingKey{ ref key } => assert_eq!(&b"info.piece length"[..], &key[..]),
+                    _                                               => panic!("Expected MissingKey Error")
+                }
+            },
+            _ => panic!("Expected BencodeConvert Error")
+        }
+    }
+
     #[test]
     #[should_panic]
     fn negative_parse_with_no_piece_length() {
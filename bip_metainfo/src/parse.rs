@@ -1,5 +1,5 @@
 use bip_bencode::BRefAccess;
-use bip_bencode::{BDictAccess, BConvert, BencodeConvertError, BListAccess};
+use bip_bencode::{BDictAccess, BDictDecoder, BConvert, BencodeConvertError, BListAccess};
 
 use error::{ParseError, ParseResult};
 
@@ -28,6 +28,7 @@ pub const COMMENT_KEY:       &'static [u8] = b"comment";
 pub const CREATED_BY_KEY:    &'static [u8] = b"created by";
 pub const ENCODING_KEY:      &'static [u8] = b"encoding";
 pub const INFO_KEY:          &'static [u8] = b"info";
+pub const URL_LIST_KEY:      &'static [u8] = b"url-list";
 
 /// Keys found within the info dictionary of a metainfo file.
 pub const PIECE_LENGTH_KEY: &'static [u8] = b"piece length";
@@ -47,10 +48,22 @@ pub fn parse_root_dict<B>(root_bencode: &B) -> ParseResult<&BDictAccess<B::BKey,
     CONVERT.convert_dict(root_bencode, ROOT_ERROR_KEY)
 }
 
+/// Decoder for the root dictionary.
+fn root_decoder<B>(root_dict: &BDictAccess<B::BKey, B>) -> BDictDecoder<MetainfoConverter, B::BKey, B>
+    where B: BRefAccess {
+    BDictDecoder::new(&CONVERT, root_dict)
+}
+
+/// Decoder for the info dictionary, so errors name keys within it as `info.<key>`.
+fn info_decoder<B>(info_dict: &BDictAccess<B::BKey, B>) -> BDictDecoder<MetainfoConverter, B::BKey, B>
+    where B: BRefAccess {
+    BDictDecoder::with_path(&CONVERT, info_dict, INFO_KEY)
+}
+
 /// Parses the announce list from the root dictionary.
 pub fn parse_announce_list<B>(root_dict: &BDictAccess<B::BKey, B>) -> Option<&BListAccess<B>>
     where B: BRefAccess<BType=B> {
-    CONVERT.lookup_and_convert_list(root_dict, ANNOUNCE_LIST_KEY).ok()
+    root_decoder(root_dict).optional_list(ANNOUNCE_LIST_KEY).ok().and_then(|opt| opt)
 }
 
 /// Converts list of lists to vec of vecs
@@ -70,31 +83,31 @@ pub fn convert_announce_list<B>(list: &BListAccess<B>) -> Vec<Vec<String>>
 /// Parses the announce url from the root dictionary.
 pub fn parse_announce_url<'a, B>(root_dict: &'a BDictAccess<B::BKey, B>) -> Option<&'a str>
     where B: BRefAccess + 'a {
-    CONVERT.lookup_and_convert_str(root_dict, ANNOUNCE_URL_KEY).ok()
+    root_decoder(root_dict).optional_str(ANNOUNCE_URL_KEY).ok().and_then(|opt| opt)
 }
 
 /// Parses the creation date from the root dictionary.
 pub fn parse_creation_date<B>(root_dict: &BDictAccess<B::BKey, B>) -> Option<i64>
     where B: BRefAccess {
-    CONVERT.lookup_and_convert_int(root_dict, CREATION_DATE_KEY).ok()
+    root_decoder(root_dict).optional_int(CREATION_DATE_KEY).ok().and_then(|opt| opt)
 }
 
 /// Parses the comment from the root dictionary.
 pub fn parse_comment<'a, B>(root_dict: &'a BDictAccess<B::BKey, B>) -> Option<&'a str>
     where B: BRefAccess + 'a {
-    CONVERT.lookup_and_convert_str(root_dict, COMMENT_KEY).ok()
+    root_decoder(root_dict).optional_str(COMMENT_KEY).ok().and_then(|opt| opt)
 }
 
 /// Parses the created by from the root dictionary.
 pub fn parse_created_by<'a, B>(root_dict: &'a BDictAccess<B::BKey, B>) -> Option<&'a str>
     where B: BRefAccess + 'a {
-    CONVERT.lookup_and_convert_str(root_dict, CREATED_BY_KEY).ok()
+    root_decoder(root_dict).optional_str(CREATED_BY_KEY).ok().and_then(|opt| opt)
 }
 
 /// Parses the encoding from the root dictionary.
 pub fn parse_encoding<'a, B>(root_dict: &'a BDictAccess<B::BKey, B>) -> Option<&'a str>
     where B: BRefAccess + 'a {
-    CONVERT.lookup_and_convert_str(root_dict, ENCODING_KEY).ok()
+    root_decoder(root_dict).optional_str(ENCODING_KEY).ok().and_then(|opt| opt)
 }
 
 /// Parses the info dictionary from the root dictionary.
@@ -108,31 +121,31 @@ pub fn parse_info_bencode<'a, B>(root_dict: &'a BDictAccess<B::BKey, B>) -> Pars
 /// Parses the piece length from the info dictionary.
 pub fn parse_piece_length<B>(info_dict: &BDictAccess<B::BKey, B>) -> ParseResult<u64>
     where B: BRefAccess {
-    CONVERT.lookup_and_convert_int(info_dict, PIECE_LENGTH_KEY).map(|len| len as u64)
+    info_decoder(info_dict).required_int(PIECE_LENGTH_KEY).map(|len| len as u64)
 }
 
 /// Parses the pieces from the info dictionary.
 pub fn parse_pieces<'a, B>(info_dict: &'a BDictAccess<B::BKey, B>) -> ParseResult<&'a [u8]>
     where B: BRefAccess + 'a {
-    CONVERT.lookup_and_convert_bytes(info_dict, PIECES_KEY)
+    info_decoder(info_dict).required_bytes(PIECES_KEY)
 }
 
 /// Parses the private flag from the info dictionary.
 pub fn parse_private<B>(info_dict: &BDictAccess<B::BKey, B>) -> Option<bool>
     where B: BRefAccess {
-    CONVERT.lookup_and_convert_int(info_dict, PRIVATE_KEY).ok().map(|p| p == 1)
+    info_decoder(info_dict).optional_int(PRIVATE_KEY).ok().and_then(|opt| opt).map(|p| p == 1)
 }
 
 /// Parses the name from the info dictionary.
 pub fn parse_name<'a, B>(info_dict: &'a BDictAccess<B::BKey, B>) -> ParseResult<&'a str>
     where B: BRefAccess + 'a {
-    CONVERT.lookup_and_convert_str(info_dict, NAME_KEY)
+    info_decoder(info_dict).required_str(NAME_KEY)
 }
 
 /// Parses the files list from the info dictionary.
 pub fn parse_files_list<B>(info_dict: &BDictAccess<B::BKey, B>) -> ParseResult<&BListAccess<B>>
     where B: BRefAccess<BType=B> {
-    CONVERT.lookup_and_convert_list(info_dict, FILES_KEY)
+    info_decoder(info_dict).required_list(FILES_KEY)
 }
 
 // ----------------------------------------------------------------------------//
@@ -1,6 +1,9 @@
 extern crate bip_metainfo;
+extern crate bip_util;
 
-use bip_metainfo::MetainfoBuilder;
+use bip_metainfo::{DirectAccessor, Metainfo, MetainfoBuilder, PieceLength};
+use bip_metainfo::error::ParseErrorKind;
+use bip_util::sha::ShaHash;
 
 const TRACKER: &'static str = "udp://foo.bar.baz:6969";
 const DATE: i64 = 1517651523851;
@@ -50,3 +53,42 @@ fn positive_set_created_by() {
 
     assert_eq!(builder.get_created_by(), Some(CREATED_BY.to_string()));
 }
+
+#[test]
+fn positive_build_from_hashes_matches_hashed_build() {
+    let contents = vec![55u8; 5000];
+
+    let hashed_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, DirectAccessor::new("foo", &contents), |_| ())
+        .unwrap();
+    let pieces: Vec<ShaHash> = Metainfo::from_bytes(&hashed_bytes).unwrap()
+        .info().pieces()
+        .map(|piece| ShaHash::from_hash(piece).unwrap())
+        .collect();
+
+    // Contents are never read, so hashes are taken as given
+    let unread_contents = vec![0u8; 5000];
+    let built_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build_from_hashes(DirectAccessor::new("foo", &unread_contents), &pieces)
+        .unwrap();
+
+    assert_eq!(hashed_bytes, built_bytes);
+}
+
+#[test]
+fn negative_build_from_hashes_wrong_piece_count() {
+    let contents = vec![55u8; 5000];
+    let pieces = vec![ShaHash::from_bytes(&contents); 4];
+
+    let error = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build_from_hashes(DirectAccessor::new("foo", &contents), &pieces)
+        .unwrap_err();
+
+    match error.kind() {
+        &ParseErrorKind::PieceCountMismatch{ expected: 5, found: 4 } => (),
+        other => panic!("Unexpected Error Kind: {:?}", other)
+    }
+}
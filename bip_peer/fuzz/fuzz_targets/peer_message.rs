@@ -0,0 +1,23 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate bip_peer;
+extern crate bytes;
+extern crate tokio_io;
+
+use bip_peer::{PeerProtocol, PeerProtocolCodec};
+use bip_peer::protocols::{NullProtocol, PeerWireProtocol};
+use bytes::{Bytes, BytesMut};
+use tokio_io::codec::Decoder;
+
+fuzz_target!(|data: &[u8]| {
+    // Parsing a single message directly should never panic, regardless of the length prefix
+    let mut protocol = PeerWireProtocol::new(NullProtocol::new());
+    let _ = protocol.parse_bytes(Bytes::from(data));
+
+    // Decoding a stream of messages should never panic, and should always make progress
+    let mut codec = PeerProtocolCodec::new(PeerWireProtocol::new(NullProtocol::new()));
+    let mut bytes = BytesMut::from(data);
+
+    while let Ok(Some(_)) = codec.decode(&mut bytes) {}
+});
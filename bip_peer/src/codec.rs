@@ -1,32 +1,207 @@
 //! Codecs operating over `PeerProtocol`s.
 
+use std::cmp;
 use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
+use manager::metrics::CongestionMetrics;
 use protocol::PeerProtocol;
 
 use bytes::{BytesMut, BufMut};
 use tokio_io::codec::{Decoder, Encoder};
 
+/// Default maximum payload enforced by a `PeerProtocolCodec`.
+///
+/// Large enough for the bitfield of any reasonable torrent, while keeping a peer
+/// from making us buffer an arbitrarily large message.
+pub const DEFAULT_MAX_PAYLOAD: usize = 16 * 1024 * 1024;
+
+/// Length of a message header, the length prefix followed by the message id.
+const MESSAGE_HEADER_LEN: usize = 5;
+
+/// Statistics on the messages exchanged with a single peer.
+///
+/// Useful for identifying peers running buggy client implementations, as well
+/// as accounting for the bytes transferred with a peer. Clones
+/// of a `PeerProtocolStats` share the same counters.
+#[derive(Clone, Debug)]
+pub struct PeerProtocolStats {
+    unknown_messages: Arc<AtomicUsize>,
+    parse_failures:   Arc<AtomicUsize>,
+    parse_retries:    Arc<AtomicUsize>,
+    oversized_frames: Arc<AtomicUsize>,
+    bytes_received:   Arc<AtomicUsize>,
+    bytes_sent:       Arc<AtomicUsize>,
+    header_received:  Arc<AtomicUsize>,
+    header_sent:      Arc<AtomicUsize>,
+    snubbed:          Arc<AtomicBool>,
+    congestion:       Arc<Mutex<Option<CongestionMetrics>>>
+}
+
+impl PeerProtocolStats {
+    /// Create a new `PeerProtocolStats` with all counters at zero.
+    pub fn new() -> PeerProtocolStats {
+        PeerProtocolStats{ unknown_messages: Arc::new(AtomicUsize::new(0)), parse_failures: Arc::new(AtomicUsize::new(0)),
+                           parse_retries: Arc::new(AtomicUsize::new(0)), oversized_frames: Arc::new(AtomicUsize::new(0)),
+                           bytes_received: Arc::new(AtomicUsize::new(0)), bytes_sent: Arc::new(AtomicUsize::new(0)),
+                           header_received: Arc::new(AtomicUsize::new(0)), header_sent: Arc::new(AtomicUsize::new(0)),
+                           snubbed: Arc::new(AtomicBool::new(false)), congestion: Arc::new(Mutex::new(None)) }
+    }
+
+    /// Number of messages with an unknown id that were ignored.
+    pub fn unknown_messages(&self) -> usize {
+        self.unknown_messages.load(Ordering::Relaxed)
+    }
+
+    /// Number of messages that failed to parse.
+    pub fn parse_failures(&self) -> usize {
+        self.parse_failures.load(Ordering::Relaxed)
+    }
+
+    /// Number of times a partially received message had to wait for more bytes before parsing.
+    pub fn parse_retries(&self) -> usize {
+        self.parse_retries.load(Ordering::Relaxed)
+    }
+
+    /// Number of messages that were larger than the maximum payload.
+    pub fn oversized_frames(&self) -> usize {
+        self.oversized_frames.load(Ordering::Relaxed)
+    }
+
+    /// Number of message payload bytes received from the peer.
+    ///
+    /// Does not include message headers, see `PeerProtocolStats::header_bytes_received`.
+    pub fn bytes_received(&self) -> usize {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    /// Number of message payload bytes sent to the peer.
+    ///
+    /// Does not include message headers, see `PeerProtocolStats::header_bytes_sent`.
+    pub fn bytes_sent(&self) -> usize {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    /// Number of message header bytes (length prefixes and message ids) received from the peer.
+    pub fn header_bytes_received(&self) -> usize {
+        self.header_received.load(Ordering::Relaxed)
+    }
+
+    /// Number of message header bytes (length prefixes and message ids) sent to the peer.
+    pub fn header_bytes_sent(&self) -> usize {
+        self.header_sent.load(Ordering::Relaxed)
+    }
+
+    /// Whether or not the peer is currently snubbing us (not sending blocks we requested).
+    pub fn is_snubbed(&self) -> bool {
+        self.snubbed.load(Ordering::Relaxed)
+    }
+
+    /// Record whether or not the peer is currently snubbing us.
+    pub fn set_snubbed(&self, snubbed: bool) {
+        self.snubbed.store(snubbed, Ordering::Relaxed);
+    }
+
+    /// Most recent congestion metrics recorded by the transport for the peer, if any.
+    pub fn congestion_metrics(&self) -> Option<CongestionMetrics> {
+        *self.congestion.lock().expect("bip_peer: PeerProtocolStats Failed To Lock Congestion Metrics")
+    }
+
+    /// Record the current congestion metrics of the transport for the peer.
+    pub fn set_congestion_metrics(&self, metrics: CongestionMetrics) {
+        *self.congestion.lock().expect("bip_peer: PeerProtocolStats Failed To Lock Congestion Metrics") = Some(metrics);
+    }
+
+    /// Record a message with an unknown id.
+    pub fn record_unknown_message(&self) {
+        self.unknown_messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a message that failed to parse.
+    pub fn record_parse_failure(&self) {
+        self.parse_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a partially received message.
+    pub fn record_parse_retry(&self) {
+        self.parse_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a message larger than the maximum payload.
+    pub fn record_oversized_frame(&self) {
+        self.oversized_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a message of the given size received from the peer, splitting off the header.
+    pub fn record_bytes_received(&self, bytes: usize) {
+        let header = cmp::min(bytes, MESSAGE_HEADER_LEN);
+
+        self.header_received.fetch_add(header, Ordering::Relaxed);
+        self.bytes_received.fetch_add(bytes - header, Ordering::Relaxed);
+    }
+
+    /// Record a message of the given size sent to the peer, splitting off the header.
+    pub fn record_bytes_sent(&self, bytes: usize) {
+        let header = cmp::min(bytes, MESSAGE_HEADER_LEN);
+
+        self.header_sent.fetch_add(header, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes - header, Ordering::Relaxed);
+    }
+}
+
+impl PartialEq for PeerProtocolStats {
+    fn eq(&self, other: &PeerProtocolStats) -> bool {
+        // Stats are equal if they share the same counters
+        Arc::ptr_eq(&self.unknown_messages, &other.unknown_messages)
+    }
+}
+
+impl Eq for PeerProtocolStats {}
+
+//----------------------------------------------------------------------------//
+
 /// Codec operating over some `PeerProtocol`.
+///
+/// Messages which the protocol fails to parse with an `io::ErrorKind::InvalidData`
+/// error are counted as messages with an unknown id, before the error is returned.
 pub struct PeerProtocolCodec<P> {
     protocol:    P,
-    max_payload: Option<usize>
+    max_payload: usize,
+    stats:       PeerProtocolStats
 }
 
 impl<P> PeerProtocolCodec<P> {
     /// Create a new `PeerProtocolCodec`.
     ///
-    /// It is strongly recommended to use `PeerProtocolCodec::with_max_payload`
-    /// instead of this function, as this function will not enforce a limit on
-    /// received payload length.
+    /// Received payloads larger than `DEFAULT_MAX_PAYLOAD` will yield an error, it is
+    /// recommended to use `PeerProtocolCodec::with_max_payload` to enforce a limit
+    /// suited to the messages you expect to receive.
     pub fn new(protocol: P) -> PeerProtocolCodec<P> {
-        PeerProtocolCodec{ protocol: protocol, max_payload: None }
+        PeerProtocolCodec::with_max_payload(protocol, DEFAULT_MAX_PAYLOAD)
     }
 
     /// Create a new `PeerProtocolCodec` which will yield an error if 
     /// receiving a payload larger than the specified `max_payload`.
     pub fn with_max_payload(protocol: P, max_payload: usize) -> PeerProtocolCodec<P> {
-        PeerProtocolCodec{ protocol: protocol, max_payload: Some(max_payload) }
+        PeerProtocolCodec{ protocol: protocol, max_payload: max_payload, stats: PeerProtocolStats::new() }
+    }
+
+    /// Set the maximum payload that will be accepted by the codec.
+    pub fn set_max_payload(&mut self, max_payload: usize) {
+        self.max_payload = max_payload;
+    }
+
+    /// Maximum payload that will be accepted by the codec.
+    pub fn max_payload(&self) -> usize {
+        self.max_payload
+    }
+
+    /// Statistics for the messages decoded by this codec.
+    ///
+    /// These can be given to the `PeerManager` through `PeerConfig::with_protocol_stats`.
+    pub fn stats(&self) -> PeerProtocolStats {
+        self.stats.clone()
     }
 }
 
@@ -35,19 +210,42 @@ impl<P> Decoder for PeerProtocolCodec<P> where P: PeerProtocol {
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
-        let src_len = src.len();
-        
-        let bytes = match try!(self.protocol.bytes_needed(src.as_ref())) {
-            Some(needed) if self.max_payload
-                                .map(|max_payload| needed > max_payload)
-                                .unwrap_or(false) => {
-                return Err(io::Error::new(io::ErrorKind::Other, "PeerProtocolCodec Enforced Maximum Payload Check For Peer"))
-            }
-            Some(needed) if needed <= src_len => src.split_to(needed).freeze(),
-            Some(_) | None                    => { return Ok(None) }
-        };
+        loop {
+            let src_len = src.len();
+
+            let bytes = match try!(self.protocol.bytes_needed(src.as_ref())) {
+                Some(needed) if needed > self.max_payload => {
+                    self.stats.record_oversized_frame();
+
+                    return Err(io::Error::new(io::ErrorKind::Other, "PeerProtocolCodec Enforced Maximum Payload Check For Peer"))
+                }
+                Some(needed) if needed <= src_len => {
+                    self.stats.record_bytes_received(needed);
 
-        self.protocol.parse_bytes(bytes).map(|message| Some(message))
+                    src.split_to(needed).freeze()
+                },
+                Some(_) | None                    => {
+                    if src_len != 0 {
+                        self.stats.record_parse_retry();
+                    }
+
+                    return Ok(None)
+                }
+            };
+
+            match self.protocol.parse_bytes(bytes) {
+                Ok(message) => return Ok(Some(message)),
+                Err(err)    => {
+                    if err.kind() == io::ErrorKind::InvalidData {
+                        self.stats.record_unknown_message();
+                    } else {
+                        self.stats.record_parse_failure();
+                    }
+
+                    return Err(err)
+                }
+            }
+        }
     }
 }
 
@@ -56,21 +254,30 @@ impl<P> Encoder for PeerProtocolCodec<P> where P: PeerProtocol {
     type Error = io::Error;
 
     fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> io::Result<()> {
-        dst.reserve(self.protocol.message_size(&item));
+        let message_size = self.protocol.message_size(&item);
+        dst.reserve(message_size);
         
-        self.protocol.write_bytes(&item, dst.writer())
+        try!(self.protocol.write_bytes(&item, dst.writer()));
+        self.stats.record_bytes_sent(message_size);
+
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use std::io::{self, Write};
+    use std::time::Duration;
 
     use super::PeerProtocolCodec;
+    use manager::metrics::CongestionMetrics;
     use protocol::PeerProtocol;
+    use message::{PeerWireProtocolMessage};
+    use protocol::null::NullProtocol;
+    use protocol::wire::PeerWireProtocol;
 
     use bytes::{Bytes, BytesMut};
-    use tokio_io::codec::{Decoder};
+    use tokio_io::codec::{Decoder, Encoder};
 
     struct ConsumeProtocol;
 
@@ -115,5 +322,106 @@ mod tests {
 
         assert!(codec.decode(&mut bytes).is_err());
         assert_eq!(bytes.len(), 200);
+        assert_eq!(1, codec.stats().oversized_frames());
+    }
+
+    #[test]
+    fn negative_parse_above_default_max_payload() {
+        let mut codec = PeerProtocolCodec::new(PeerWireProtocol::new(NullProtocol::new()));
+        let mut bytes = BytesMut::with_capacity(100);
+
+        // Piece message claiming a length of u32::MAX
+        bytes.extend_from_slice(&[255, 255, 255, 255, 7]);
+
+        assert!(codec.decode(&mut bytes).is_err());
+        assert_eq!(1, codec.stats().oversized_frames());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn negative_parse_piece_shorter_than_header() {
+        let mut codec = PeerProtocolCodec::new(PeerWireProtocol::new(NullProtocol::new()));
+        let mut bytes = BytesMut::with_capacity(100);
+
+        // Piece message with a length that does not cover the piece index and block offset
+        bytes.extend_from_slice(&[0, 0, 0, 2, 7, 0]);
+
+        assert!(codec.decode(&mut bytes).is_err());
+        assert_eq!(1, codec.stats().parse_failures());
+    }
+
+    #[test]
+    fn negative_unknown_message_id() {
+        let mut codec = PeerProtocolCodec::new(PeerWireProtocol::new(NullProtocol::new()));
+        let mut bytes = BytesMut::with_capacity(100);
+
+        // Unknown message with id 100
+        bytes.extend_from_slice(&[0, 0, 0, 2, 100, 0]);
+
+        match codec.decode(&mut bytes) {
+            Err(ref err) if err.kind() == io::ErrorKind::InvalidData => (),
+            _                                                        => panic!("bip_peer: Expected InvalidData Error For Unknown Message")
+        }
+        assert_eq!(1, codec.stats().unknown_messages());
+        assert_eq!(0, codec.stats().parse_failures());
+    }
+
+    #[test]
+    fn negative_parse_failure_known_message_id() {
+        let mut codec = PeerProtocolCodec::new(PeerWireProtocol::new(NullProtocol::new()));
+        let mut bytes = BytesMut::with_capacity(100);
+
+        // Have message with a missing piece index
+        bytes.extend_from_slice(&[0, 0, 0, 1, 4]);
+
+        assert!(codec.decode(&mut bytes).is_err());
+        assert_eq!(1, codec.stats().parse_failures());
+    }
+
+    #[test]
+    fn positive_record_parse_retry() {
+        let mut codec = PeerProtocolCodec::new(PeerWireProtocol::new(NullProtocol::new()));
+        let mut bytes = BytesMut::with_capacity(100);
+
+        bytes.extend_from_slice(&[0, 0, 0, 1]);
+        assert!(codec.decode(&mut bytes).unwrap().is_none());
+
+        bytes.extend_from_slice(&[0]);
+        assert!(codec.decode(&mut bytes).unwrap().is_some());
+
+        assert_eq!(1, codec.stats().parse_retries());
+    }
+
+    #[test]
+    fn positive_record_bytes_transferred() {
+        let mut codec = PeerProtocolCodec::new(PeerWireProtocol::new(NullProtocol::new()));
+        let mut bytes = BytesMut::with_capacity(100);
+
+        // Unknown message with id 100, followed by a have message
+        bytes.extend_from_slice(&[0, 0, 0, 2, 100, 0]);
+        bytes.extend_from_slice(&[0, 0, 0, 5, 4, 0, 0, 0, 1]);
+        assert!(codec.decode(&mut bytes).is_err());
+        codec.decode(&mut bytes).unwrap();
+
+        let mut out_bytes = BytesMut::new();
+        codec.encode(PeerWireProtocolMessage::UnChoke, &mut out_bytes).unwrap();
+        codec.encode(PeerWireProtocolMessage::KeepAlive, &mut out_bytes).unwrap();
+
+        // Headers are counted separately from payloads
+        assert_eq!(5, codec.stats().bytes_received());
+        assert_eq!(10, codec.stats().header_bytes_received());
+        assert_eq!(0, codec.stats().bytes_sent());
+        assert_eq!(9, codec.stats().header_bytes_sent());
+    }
+
+    #[test]
+    fn positive_record_congestion_metrics() {
+        let codec = PeerProtocolCodec::new(PeerWireProtocol::new(NullProtocol::new()));
+        let stats = codec.stats();
+        assert_eq!(None, stats.congestion_metrics());
+
+        let metrics = CongestionMetrics::new(Duration::from_millis(100), Duration::from_millis(10), 64 * 1024, 0);
+        stats.set_congestion_metrics(metrics);
+
+        assert_eq!(Some(metrics), codec.stats().congestion_metrics());
+    }
+}
@@ -0,0 +1,41 @@
+use std::io;
+
+use codec::PeerProtocolCodec;
+use manager::peer_info::PeerInfo;
+use message::PeerWireProtocolMessage;
+use protocol::PeerProtocolFactory;
+use protocol::negotiated::NegotiatedProtocol;
+use protocol::wire::PeerWireProtocol;
+
+use bip_handshake::CompleteMessage;
+use futures::sink::Sink;
+use futures::stream::Stream;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+/// Trait for a peer that messages of type `M` can be sent to and received from.
+///
+/// This is implemented for any `Sink` and `Stream` of `M`, and allows peers
+/// with different underlying sockets to be boxed up and given to the same `PeerManager`.
+pub trait FramedPeer<M>: Sink<SinkItem=M, SinkError=io::Error> + Stream<Item=M, Error=io::Error> { }
+
+impl<T, M> FramedPeer<M> for T where T: Sink<SinkItem=M, SinkError=io::Error> + Stream<Item=M, Error=io::Error> { }
+
+/// Boxed peer speaking the peer wire protocol, with extension protocol messages created by `F`.
+pub type BoxedPeer<F> = Box<FramedPeer<PeerWireProtocolMessage<<F as PeerProtocolFactory>::Protocol>>>;
+
+/// Frame the socket from a completed handshake with a protocol stack matching the negotiated extensions.
+///
+/// Returns the `PeerInfo` for the peer, as well as the framed peer, ready to be added to a `PeerManager`.
+/// Messages for extensions that were not negotiated (such as extension protocol messages for a peer that
+/// does not support it) will fail to send, and will result in an error if the peer sends them anyway.
+pub fn frame_peer<S, F>(complete: CompleteMessage<S>, ext_factory: F, max_payload: usize) -> (PeerInfo, BoxedPeer<F>)
+    where S: AsyncRead + AsyncWrite + 'static,
+          F: PeerProtocolFactory + 'static,
+          F::Protocol: 'static {
+    let parts = complete.into_parts();
+
+    let protocol = NegotiatedProtocol::new(PeerWireProtocol::new(ext_factory), parts.extensions);
+    let peer: BoxedPeer<F> = Box::new(parts.socket.framed(PeerProtocolCodec::with_max_payload(protocol, max_payload)));
+
+    (PeerInfo::new(parts.address, parts.peer_id, parts.hash, parts.extensions, parts.direction, parts.transport), peer)
+}
@@ -5,6 +5,7 @@ extern crate bip_util;
 extern crate bytes;
 extern crate byteorder;
 extern crate crossbeam;
+extern crate crypto;
 #[macro_use]
 extern crate error_chain;
 extern crate futures;
@@ -18,15 +19,24 @@ extern crate nom;
 mod macros;
 
 mod codec;
+mod framed;
 mod manager;
 mod message;
 mod protocol;
 
-pub use codec::PeerProtocolCodec;
-pub use protocol::{PeerProtocol, NestedPeerProtocol};
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub use codec::{PeerProtocolCodec, PeerProtocolStats, DEFAULT_MAX_PAYLOAD};
+pub use framed::{FramedPeer, BoxedPeer, frame_peer};
+pub use protocol::{PeerProtocol, PeerProtocolFactory, ExtendedState};
 pub use manager::{ManagedMessage, PeerManager, PeerManagerSink, PeerManagerStream, IPeerManagerMessage, OPeerManagerMessage, MessageId};
-pub use manager::builder::PeerManagerBuilder;
-pub use manager::peer_info::PeerInfo;
+pub use manager::builder::{PeerManagerBuilder, PeerConfig};
+pub use manager::peer_info::{PeerInfo, PeerIdentity};
+pub use manager::hash_stream::{PeerManagerHashStreams, PeerManagerHashStream};
+pub use manager::metrics::CongestionMetrics;
+pub use manager::suggest::SuggestPieces;
+pub use manager::requests::{PeerRequests, DEFAULT_MAX_PEER_REQUESTS};
 
 /// Serializable and deserializable protocol messages.
 pub mod messages {
@@ -35,9 +45,10 @@ pub mod messages {
         pub use message::{ExtendedMessageBuilder};
     }
 
-    pub use message::{BitFieldIter, BitFieldMessage, CancelMessage, ExtendedMessage, HaveMessage, PieceMessage, PortMessage,
+    pub use message::{BitFieldIter, BitFieldMessage, CancelMessage, ExtendedMessage, HaveMessage, PieceMessage, PortMessage, SuggestPieceMessage,
         RequestMessage, UtMetadataRequestMessage, UtMetadataDataMessage, UtMetadataRejectMessage, BitsExtensionMessage, ExtendedType,
-        NullProtocolMessage, PeerExtensionProtocolMessage, PeerWireProtocolMessage, UtMetadataMessage};
+        NullProtocolMessage, PeerExtensionProtocolMessage, PeerWireProtocolMessage, UtMetadataMessage, HashRequestMessage, HashesMessage,
+        HashRejectMessage, HashIter, MerkleHash, MERKLE_HASH_LEN};
 }
 
 /// `PeerManager` error types.
@@ -50,5 +61,6 @@ pub mod protocols {
     pub use protocol::unit::UnitProtocol;
     pub use protocol::null::NullProtocol;
     pub use protocol::wire::PeerWireProtocol;
-    pub use protocol::extension::PeerExtensionProtocol;
+    pub use protocol::extension::{PeerExtensionProtocol, PeerExtensionProtocolFactory};
+    pub use protocol::negotiated::NegotiatedProtocol;
 }
\ No newline at end of file
@@ -1,8 +1,13 @@
 use std::time::Duration;
 use std::io;
+use std::cmp;
+use std::sync::Arc;
 
+use codec::PeerProtocolStats;
 use manager::{PeerManager, ManagedMessage};
 
+use bip_util::metrics::{self, Metrics};
+use bip_util::pool::BufferPool;
 use futures::sink::Sink;
 use futures::stream::Stream;
 use tokio_core::reactor::Handle;
@@ -14,13 +19,19 @@ const DEFAULT_HEARTBEAT_INTERVAL_MILLIS: u64   = 1 * 60 * 1000;
 const DEFAULT_HEARTBEAT_TIMEOUT_MILLIS:  u64   = 2 * 60 * 1000;
 
 /// Builder for configuring a `PeerManager`.
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct PeerManagerBuilder {
     peer:               usize,
     sink_buffer:        usize,
     stream_buffer:      usize,
     heartbeat_interval: Duration,
-    heartbeat_timeout:  Duration
+    heartbeat_timeout:  Duration,
+    heartbeat_adaptive: Option<(Duration, Duration)>,
+    heartbeat_max:      Option<Duration>,
+    keep_alive_limit:   Option<(usize, Duration)>,
+    ack_window:         Option<usize>,
+    buffer_pool:        Option<BufferPool>,
+    metrics:            Arc<Metrics>
 }
 
 impl PeerManagerBuilder {
@@ -31,7 +42,13 @@ impl PeerManagerBuilder {
             sink_buffer:        DEFAULT_SINK_BUFFER_CAPACITY,
             stream_buffer:      DEFAULT_STREAM_BUFFER_CAPACITY,
             heartbeat_interval: Duration::from_millis(DEFAULT_HEARTBEAT_INTERVAL_MILLIS),
-            heartbeat_timeout:  Duration::from_millis(DEFAULT_HEARTBEAT_TIMEOUT_MILLIS)
+            heartbeat_timeout:  Duration::from_millis(DEFAULT_HEARTBEAT_TIMEOUT_MILLIS),
+            heartbeat_adaptive: None,
+            heartbeat_max:      None,
+            keep_alive_limit:   None,
+            ack_window:         None,
+            buffer_pool:        None,
+            metrics:            metrics::noop()
         }
     }
 
@@ -65,6 +82,65 @@ impl PeerManagerBuilder {
         self
     }
 
+    /// Scale the heartbeat timeout of each peer based on how often we receive messages from it.
+    ///
+    /// Instead of a fixed timeout, the mean and deviation of the time between messages from
+    /// the peer is tracked, and the timeout is derived from those, bounded by the given min
+    /// and max. Until we have received a message, the heartbeat timeout is used (also bounded).
+    pub fn with_adaptive_heartbeat_timeout(mut self, min: Duration, max: Duration) -> PeerManagerBuilder {
+        self.heartbeat_adaptive = Some((min, cmp::max(min, max)));
+        self
+    }
+
+    /// Largest heartbeat interval or timeout that can be given to a peer through a `PeerConfig`.
+    ///
+    /// Durations in a `PeerConfig` larger than this will be capped at this value. By
+    /// default, this is the larger of the heartbeat interval, heartbeat timeout, and
+    /// adaptive heartbeat timeout max.
+    pub fn with_heartbeat_max(mut self, max: Duration) -> PeerManagerBuilder {
+        self.heartbeat_max = Some(max);
+        self
+    }
+
+    /// Maximum number of keep-alive messages a peer can send us within the given window.
+    ///
+    /// Peers exceeding this limit are removed with a `PeerError`, since a flood of zero
+    /// length frames is cheap to send, but not cheap for us to process. Passing `None`
+    /// disables the limit. By default, there is no limit.
+    pub fn with_keep_alive_limit(mut self, limit: Option<(usize, Duration)>) -> PeerManagerBuilder {
+        self.keep_alive_limit = limit;
+        self
+    }
+
+    /// Maximum number of sent messages that can be acknowledged together.
+    ///
+    /// When set, messages that were queued up together for a peer (such as the messages of an
+    /// `IPeerManagerMessage::SendMessages` batch) are acknowledged with a single
+    /// `OPeerManagerMessage::SentMessages`, instead of a `SentMessage` for each message. Useful
+    /// when queueing hundreds of requests at once. By default, every message is acknowledged.
+    pub fn with_ack_window(mut self, window: Option<usize>) -> PeerManagerBuilder {
+        self.ack_window = window;
+        self
+    }
+
+    /// Pool that the block payloads of sent messages will be recycled into.
+    ///
+    /// Payloads are reclaimed by the pool once the message has been written to the peer, and every
+    /// other handle to the payload has been dropped. Useful when block buffers for outgoing pieces
+    /// are allocated from the same pool. By default, payloads are not recycled.
+    pub fn with_buffer_pool(mut self, pool: Option<BufferPool>) -> PeerManagerBuilder {
+        self.buffer_pool = pool;
+        self
+    }
+
+    /// Metrics that the number of connected peers will be reported to.
+    ///
+    /// Reports a `bip_peer_peers_connected` gauge. By default, all metrics are discarded.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> PeerManagerBuilder {
+        self.metrics = metrics;
+        self
+    }
+
     /// Retrieve the peer capacity.
     pub fn peer_capacity(&self) -> usize {
         self.peer
@@ -90,6 +166,39 @@ impl PeerManagerBuilder {
         self.heartbeat_timeout
     }
 
+    /// Retrieve the adaptive heartbeat timeout bounds, if enabled.
+    pub fn adaptive_heartbeat_timeout(&self) -> Option<(Duration, Duration)> {
+        self.heartbeat_adaptive
+    }
+
+    /// Retrieve the keep-alive limit, if enabled.
+    pub fn keep_alive_limit(&self) -> Option<(usize, Duration)> {
+        self.keep_alive_limit
+    }
+
+    /// Retrieve the acknowledgement window, if enabled.
+    pub fn ack_window(&self) -> Option<usize> {
+        self.ack_window
+    }
+
+    /// Retrieve the buffer pool, if enabled.
+    pub fn buffer_pool(&self) -> Option<BufferPool> {
+        self.buffer_pool.clone()
+    }
+
+    /// Retrieve the heartbeat max `Duration`.
+    pub fn heartbeat_max(&self) -> Duration {
+        let default_max = cmp::max(self.heartbeat_interval, self.heartbeat_timeout);
+        let default_max = self.heartbeat_adaptive.map(|(_, max)| cmp::max(max, default_max)).unwrap_or(default_max);
+
+        self.heartbeat_max.map(|max| cmp::max(max, default_max)).unwrap_or(default_max)
+    }
+
+    /// Retrieve the `Metrics`.
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
     /// Build a `PeerManager` from the current `PeerManagerBuilder`.
     pub fn build<P>(self, handle: Handle) -> PeerManager<P>
         where P: Sink<SinkError=io::Error> +
@@ -98,4 +207,93 @@ impl PeerManagerBuilder {
               P::Item:     ManagedMessage {
         PeerManager::from_builder(self, handle)
     }
-}
\ No newline at end of file
+}
+
+//----------------------------------------------------------------------------//
+
+/// Configuration for an individual peer in a `PeerManager`.
+///
+/// Useful for giving high latency peers (tor, proxies) a longer heartbeat
+/// timeout, while pruning unresponsive local peers more aggressively.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerConfig {
+    heartbeat_interval: Duration,
+    heartbeat_timeout:  Duration,
+    heartbeat_adaptive: Option<(Duration, Duration)>,
+    keep_alive_limit:   Option<(usize, Duration)>,
+    protocol_stats:     PeerProtocolStats
+}
+
+impl PeerConfig {
+    /// Create a new `PeerConfig` with the defaults from the given `PeerManagerBuilder`.
+    pub fn from_builder(builder: &PeerManagerBuilder) -> PeerConfig {
+        PeerConfig {
+            heartbeat_interval: builder.heartbeat_interval(),
+            heartbeat_timeout:  builder.heartbeat_timeout(),
+            heartbeat_adaptive: builder.adaptive_heartbeat_timeout(),
+            keep_alive_limit:   builder.keep_alive_limit(),
+            protocol_stats:     PeerProtocolStats::new()
+        }
+    }
+
+    /// Interval at which we send keep-alive messages.
+    pub fn with_heartbeat_interval(mut self, interval: Duration) -> PeerConfig {
+        self.heartbeat_interval = interval;
+        self
+    }
+
+    /// Timeout at which we disconnect from the peer without seeing a keep-alive message.
+    pub fn with_heartbeat_timeout(mut self, timeout: Duration) -> PeerConfig {
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Scale the heartbeat timeout based on how often we receive messages from the peer.
+    ///
+    /// Passing `None` will use a fixed heartbeat timeout for the peer.
+    pub fn with_adaptive_heartbeat_timeout(mut self, bounds: Option<(Duration, Duration)>) -> PeerConfig {
+        self.heartbeat_adaptive = bounds.map(|(min, max)| (min, cmp::max(min, max)));
+        self
+    }
+
+    /// Maximum number of keep-alive messages the peer can send us within the given window.
+    ///
+    /// Passing `None` disables the limit for the peer.
+    pub fn with_keep_alive_limit(mut self, limit: Option<(usize, Duration)>) -> PeerConfig {
+        self.keep_alive_limit = limit;
+        self
+    }
+
+    /// Protocol statistics that will be returned when querying the peer with `IPeerManagerMessage::QueryStats`.
+    ///
+    /// Typically retrieved from the codec for the peer, via `PeerProtocolCodec::stats`.
+    pub fn with_protocol_stats(mut self, stats: PeerProtocolStats) -> PeerConfig {
+        self.protocol_stats = stats;
+        self
+    }
+
+    /// Retrieve the hearbeat interval `Duration`.
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.heartbeat_interval
+    }
+
+    /// Retrieve the heartbeat timeout `Duration`.
+    pub fn heartbeat_timeout(&self) -> Duration {
+        self.heartbeat_timeout
+    }
+
+    /// Retrieve the adaptive heartbeat timeout bounds, if enabled.
+    pub fn adaptive_heartbeat_timeout(&self) -> Option<(Duration, Duration)> {
+        self.heartbeat_adaptive
+    }
+
+    /// Retrieve the keep-alive limit for the peer, if enabled.
+    pub fn keep_alive_limit(&self) -> Option<(usize, Duration)> {
+        self.keep_alive_limit
+    }
+
+    /// Retrieve the protocol statistics for the peer.
+    pub fn protocol_stats(&self) -> &PeerProtocolStats {
+        &self.protocol_stats
+    }
+}
@@ -1,5 +1,8 @@
 use std::io;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use std::cmp;
+use std::rc::Rc;
+use std::cell::RefCell;
 
 use tokio_timer::{Timer, TimeoutError, Sleep};
 use futures::{Poll, Async, Future};
@@ -54,6 +57,32 @@ impl<S> Stream for PersistentStream<S>
 
 //----------------------------------------------------------------------------//
 
+/// Stream which polls an underlying stream that is shared with its owner, so that
+/// the owner can swap out the underlying stream while we are being polled as part
+/// of some larger stream (since we dont get ownership of that stream back).
+pub struct SharedStream<S> {
+    stream: Rc<RefCell<S>>
+}
+
+impl<S> SharedStream<S> {
+    /// Create a new `SharedStream`.
+    pub fn new(stream: Rc<RefCell<S>>) -> SharedStream<S> {
+        SharedStream{ stream: stream }
+    }
+}
+
+impl<S> Stream for SharedStream<S>
+    where S: Stream {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        self.stream.borrow_mut().poll()
+    }
+}
+
+//----------------------------------------------------------------------------//
+
 /// Error type for `RecurringTimeoutStream`.
 pub enum RecurringTimeoutError {
     /// None and any errors are mapped to this type...
@@ -119,4 +148,172 @@ impl<S> Stream for RecurringTimeoutStream<S>
             Err(_) => panic!("bip_peer: Timer Error In Manager Stream, Timer Capacity Is Probably Too Small...")
         }
     }
-}
\ No newline at end of file
+}
+
+//----------------------------------------------------------------------------//
+
+// Gains for the mean and deviation estimates, same as those used for tcp rtt estimation
+const MEAN_GAIN_DENOM:      u64 = 8;
+const DEVIATION_GAIN_DENOM: u64 = 4;
+// Number of deviations to add to the mean to get our timeout
+const DEVIATION_MULTIPLIER: u64 = 4;
+
+/// Tracks the mean and deviation of the time between messages from a peer.
+struct ArrivalStats {
+    opt_stats: Option<(u64, u64)>,
+    base:      Duration,
+    bounds:    Option<(Duration, Duration)>
+}
+
+impl ArrivalStats {
+    fn new(base: Duration, bounds: Option<(Duration, Duration)>) -> ArrivalStats {
+        ArrivalStats{ opt_stats: None, base: base, bounds: bounds }
+    }
+
+    /// Record the time between the last two messages.
+    fn record(&mut self, gap: Duration) {
+        let gap = duration_to_millis(gap);
+
+        self.opt_stats = Some(match self.opt_stats {
+            None                    => (gap, gap / 2),
+            Some((mean, deviation)) => {
+                let diff = if mean > gap { mean - gap } else { gap - mean };
+
+                let new_deviation = (deviation * (DEVIATION_GAIN_DENOM - 1) + diff) / DEVIATION_GAIN_DENOM;
+                let new_mean = (mean * (MEAN_GAIN_DENOM - 1) + gap) / MEAN_GAIN_DENOM;
+
+                (new_mean, new_deviation)
+            }
+        });
+    }
+
+    /// Current timeout to use for the peer.
+    fn timeout(&self) -> Duration {
+        match (self.bounds, self.opt_stats) {
+            (None, _)                                   => self.base,
+            (Some((min, max)), None)                    => cmp::min(cmp::max(self.base, min), max),
+            (Some((min, max)), Some((mean, deviation))) => {
+                let timeout = Duration::from_millis(mean.saturating_add(deviation.saturating_mul(DEVIATION_MULTIPLIER)));
+
+                cmp::min(cmp::max(timeout, min), max)
+            }
+        }
+    }
+}
+
+fn duration_to_millis(dur: Duration) -> u64 {
+    dur.as_secs().saturating_mul(1000).saturating_add((dur.subsec_nanos() / 1_000_000) as u64)
+}
+
+/// Stream similar to `tokio_timer::TimeoutStream`, but where the timeout is
+/// (optionally) scaled based on the observed time between items from the
+/// underlying stream, bounded by some min and max.
+///
+/// Useful for not disconnecting slow, but alive, peers on congested links,
+/// while still pruning peers that have actually gone away.
+pub struct AdaptiveTimeoutStream<S> {
+    timer:  Timer,
+    sleep:  Sleep,
+    last:   Instant,
+    stats:  ArrivalStats,
+    stream: S
+}
+
+impl<S> AdaptiveTimeoutStream<S> {
+    /// Create a new `AdaptiveTimeoutStream`.
+    ///
+    /// If `bounds` is `None`, a fixed timeout of `dur` will be used.
+    pub fn new(stream: S, timer: Timer, dur: Duration, bounds: Option<(Duration, Duration)>) -> AdaptiveTimeoutStream<S> {
+        let stats = ArrivalStats::new(dur, bounds);
+        let sleep = timer.sleep(stats.timeout());
+
+        AdaptiveTimeoutStream{ timer: timer, sleep: sleep, last: Instant::now(), stats: stats, stream: stream }
+    }
+}
+
+impl<S> Stream for AdaptiveTimeoutStream<S>
+    where S: Stream<Error=PersistentError> {
+    type Item = S::Item;
+    type Error = PersistentError;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, PersistentError> {
+        match try!(self.stream.poll()) {
+            Async::NotReady => {},
+            Async::Ready(Some(v)) => {
+                let now = Instant::now();
+                self.stats.record(now.duration_since(self.last));
+                self.last = now;
+
+                // Reset the timeout
+                self.sleep = self.timer.sleep(self.stats.timeout());
+
+                return Ok(Async::Ready(Some(v)));
+            },
+            Async::Ready(None) => { return Ok(Async::Ready(None)) }
+        }
+
+        match self.sleep.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(_)) => Err(PersistentError::Timeout),
+            Err(err)            => panic!("bip_peer: Timer Error In Peer Stream, Timer Capacity Is Probably Too Small: {}", err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ArrivalStats;
+
+    #[test]
+    fn positive_fixed_timeout_ignores_samples() {
+        let mut stats = ArrivalStats::new(Duration::from_secs(120), None);
+
+        stats.record(Duration::from_secs(1));
+        stats.record(Duration::from_secs(300));
+
+        assert_eq!(Duration::from_secs(120), stats.timeout());
+    }
+
+    #[test]
+    fn positive_adaptive_timeout_bounds_base_without_samples() {
+        let stats = ArrivalStats::new(Duration::from_secs(120), Some((Duration::from_secs(30), Duration::from_secs(60))));
+
+        assert_eq!(Duration::from_secs(60), stats.timeout());
+    }
+
+    #[test]
+    fn positive_adaptive_timeout_first_sample() {
+        let mut stats = ArrivalStats::new(Duration::from_secs(120), Some((Duration::from_secs(1), Duration::from_secs(600))));
+
+        stats.record(Duration::from_secs(10));
+
+        // Mean of 10 plus 4 deviations of 5
+        assert_eq!(Duration::from_secs(30), stats.timeout());
+    }
+
+    #[test]
+    fn positive_adaptive_timeout_grows_with_jitter() {
+        let mut stats = ArrivalStats::new(Duration::from_secs(120), Some((Duration::from_secs(1), Duration::from_secs(600))));
+
+        stats.record(Duration::from_secs(10));
+        stats.record(Duration::from_secs(10));
+        let steady_timeout = stats.timeout();
+
+        stats.record(Duration::from_secs(50));
+
+        assert!(stats.timeout() > steady_timeout);
+    }
+
+    #[test]
+    fn positive_adaptive_timeout_clamped_to_bounds() {
+        let mut stats = ArrivalStats::new(Duration::from_secs(120), Some((Duration::from_secs(90), Duration::from_secs(180))));
+
+        stats.record(Duration::from_millis(10));
+        assert_eq!(Duration::from_secs(90), stats.timeout());
+
+        stats.record(Duration::from_secs(1000));
+        assert_eq!(Duration::from_secs(180), stats.timeout());
+    }
+}
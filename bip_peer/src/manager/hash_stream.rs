@@ -0,0 +1,220 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use manager::{PeerManagerStream, OPeerManagerMessage};
+
+use bip_util::bt::InfoHash;
+use futures::{Poll, Async};
+use futures::executor::{self, Notify, NotifyHandle, Spawn};
+use futures::sink::Sink;
+use futures::stream::Stream;
+use futures::task::{self, Task};
+
+/// Splits a `PeerManagerStream` into one stream per `InfoHash`.
+///
+/// Streams are created by calling `add_torrent`. Messages for an `InfoHash` that
+/// does not have a stream yet (such as a `PeerAdded` received before `add_torrent`
+/// was called) are buffered, and will be yielded once its stream is added. Messages
+/// for an `InfoHash` whose stream was dropped are discarded.
+///
+/// Every `PeerManagerHashStream` drives the underlying `PeerManagerStream`, and
+/// all of the hash streams waiting on it will be woken up when it is ready.
+pub struct PeerManagerHashStreams<P> where P: Sink + Stream {
+    state:   Arc<Mutex<HashStreamState<P>>>,
+    waiters: Arc<HashWaiters>
+}
+
+impl<P> PeerManagerHashStreams<P> where P: Sink + Stream {
+    /// Create a new `PeerManagerHashStreams` from the given `PeerManagerStream`.
+    pub fn new(stream: PeerManagerStream<P>) -> PeerManagerHashStreams<P> {
+        PeerManagerHashStreams{ state: Arc::new(Mutex::new(HashStreamState::new(stream))), waiters: Arc::new(HashWaiters::new()) }
+    }
+
+    /// Add a stream for the given `InfoHash`.
+    ///
+    /// Returns `None` if a stream for the `InfoHash` already exists.
+    pub fn add_torrent(&mut self, hash: InfoHash) -> Option<PeerManagerHashStream<P>> {
+        let mut state = self.state.lock()
+            .expect("bip_peer: PeerManagerHashStreams::add_torrent Failed To Lock State");
+
+        if state.claimed.insert(hash) {
+            state.dropped.remove(&hash);
+            state.queues.entry(hash).or_insert_with(VecDeque::new);
+
+            Some(PeerManagerHashStream{ hash: hash, state: self.state.clone(), waiters: self.waiters.clone() })
+        } else {
+            None
+        }
+    }
+
+    /// Whether or not a stream for the given `InfoHash` exists.
+    pub fn contains_torrent(&self, hash: &InfoHash) -> bool {
+        self.state.lock()
+            .expect("bip_peer: PeerManagerHashStreams::contains_torrent Failed To Lock State")
+            .claimed.contains(hash)
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// Stream of `OPeerManagerMessage`s for a single `InfoHash`.
+///
+/// Dropping the stream will remove the `InfoHash` from the
+/// `PeerManagerHashStreams` it was created from.
+pub struct PeerManagerHashStream<P> where P: Sink + Stream {
+    hash:    InfoHash,
+    state:   Arc<Mutex<HashStreamState<P>>>,
+    waiters: Arc<HashWaiters>
+}
+
+impl<P> PeerManagerHashStream<P> where P: Sink + Stream {
+    /// `InfoHash` that this stream is receiving messages for.
+    pub fn hash(&self) -> &InfoHash {
+        &self.hash
+    }
+}
+
+impl<P> Stream for PeerManagerHashStream<P> where P: Sink + Stream {
+    type Item = OPeerManagerMessage<P::Item>;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        // Register before routing, so a wake up while we are routing is not missed
+        self.waiters.register(self.hash, task::current());
+
+        let mut state = self.state.lock()
+            .expect("bip_peer: PeerManagerHashStream::poll Failed To Lock State");
+
+        try!(state.route_messages(&self.waiters));
+
+        let finished = state.finished;
+        let queue = state.queues.get_mut(&self.hash)
+            .expect("bip_peer: PeerManagerHashStream::poll Failed To Find Hash Queue");
+
+        match queue.pop_front() {
+            Some(message) => Ok(Async::Ready(Some(message))),
+            None if finished => Ok(Async::Ready(None)),
+            None => Ok(Async::NotReady)
+        }
+    }
+}
+
+impl<P> Drop for PeerManagerHashStream<P> where P: Sink + Stream {
+    fn drop(&mut self) {
+        self.waiters.remove(&self.hash);
+
+        if let Ok(mut state) = self.state.lock() {
+            state.claimed.remove(&self.hash);
+            state.queues.remove(&self.hash);
+            state.dropped.insert(self.hash);
+        }
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// Tasks for each hash stream waiting on the underlying stream.
+///
+/// The underlying stream only holds on to a single task, so we poll it with
+/// ourselves as the notifier, and wake up every waiting hash stream.
+struct HashWaiters {
+    tasks: Mutex<HashMap<InfoHash, Task>>
+}
+
+impl HashWaiters {
+    fn new() -> HashWaiters {
+        HashWaiters{ tasks: Mutex::new(HashMap::new()) }
+    }
+
+    fn register(&self, hash: InfoHash, task: Task) {
+        self.tasks.lock()
+            .expect("bip_peer: HashWaiters::register Failed To Lock Tasks")
+            .insert(hash, task);
+    }
+
+    fn remove(&self, hash: &InfoHash) {
+        if let Ok(mut tasks) = self.tasks.lock() {
+            tasks.remove(hash);
+        }
+    }
+
+    fn notify_hash(&self, hash: &InfoHash) {
+        let opt_task = self.tasks.lock()
+            .expect("bip_peer: HashWaiters::notify_hash Failed To Lock Tasks")
+            .remove(hash);
+
+        opt_task.map(|task| task.notify());
+    }
+}
+
+impl Notify for HashWaiters {
+    fn notify(&self, _id: usize) {
+        let tasks: Vec<Task> = self.tasks.lock()
+            .expect("bip_peer: HashWaiters::notify Failed To Lock Tasks")
+            .drain()
+            .map(|(_, task)| task)
+            .collect();
+
+        for task in tasks {
+            task.notify();
+        }
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+struct HashStreamState<P> where P: Sink + Stream {
+    stream:   Spawn<PeerManagerStream<P>>,
+    queues:   HashMap<InfoHash, VecDeque<OPeerManagerMessage<P::Item>>>,
+    claimed:  HashSet<InfoHash>,
+    dropped:  HashSet<InfoHash>,
+    finished: bool
+}
+
+impl<P> HashStreamState<P> where P: Sink + Stream {
+    fn new(stream: PeerManagerStream<P>) -> HashStreamState<P> {
+        HashStreamState{ stream: executor::spawn(stream), queues: HashMap::new(), claimed: HashSet::new(),
+                         dropped: HashSet::new(), finished: false }
+    }
+
+    /// Route all available messages from the underlying stream to their hash queues.
+    fn route_messages(&mut self, waiters: &Arc<HashWaiters>) -> Result<(), ()> {
+        let notify = NotifyHandle::from(waiters.clone());
+
+        while !self.finished {
+            match try!(self.stream.poll_stream_notify(&notify, 0)) {
+                Async::Ready(Some(message)) => {
+                    let hash = *message_info_hash(&message);
+
+                    if !self.dropped.contains(&hash) {
+                        self.queues.entry(hash).or_insert_with(VecDeque::new).push_back(message);
+                        waiters.notify_hash(&hash);
+                    }
+                },
+                Async::Ready(None) => {
+                    self.finished = true;
+
+                    waiters.notify(0);
+                },
+                Async::NotReady => break
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn message_info_hash<M>(message: &OPeerManagerMessage<M>) -> &InfoHash {
+    match message {
+        &OPeerManagerMessage::PeerAdded(ref info)          => info.hash(),
+        &OPeerManagerMessage::PeerRemoved(ref info)        => info.hash(),
+        &OPeerManagerMessage::SentMessage(ref info, _)     => info.hash(),
+        &OPeerManagerMessage::SentMessages(ref info, _)    => info.hash(),
+        &OPeerManagerMessage::ReceivedMessage(ref info, _) => info.hash(),
+        &OPeerManagerMessage::PeerDisconnect(ref info)     => info.hash(),
+        &OPeerManagerMessage::PeerError(ref info, _)       => info.hash(),
+        &OPeerManagerMessage::PeerStats(ref info, _)       => info.hash(),
+        &OPeerManagerMessage::PurgedMessages(ref info, _)  => info.hash(),
+        &OPeerManagerMessage::PeerQueued(ref info, _)      => info.hash()
+    }
+}
@@ -0,0 +1,97 @@
+use std::cmp;
+use std::time::Duration;
+
+/// Congestion metrics for the transport of a single peer.
+///
+/// Transports that track congestion (such as uTP) can record this with
+/// `PeerProtocolStats::set_congestion_metrics`, it is then returned in
+/// `OPeerManagerMessage::PeerStats` so that the selection layer can adapt
+/// how many block requests it pipelines to a peer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CongestionMetrics {
+    rtt:               Duration,
+    rtt_variance:      Duration,
+    congestion_window: usize,
+    bytes_in_flight:   usize
+}
+
+impl CongestionMetrics {
+    /// Create a new `CongestionMetrics`.
+    ///
+    /// The congestion window and bytes in flight are measured in bytes.
+    pub fn new(rtt: Duration, rtt_variance: Duration, congestion_window: usize, bytes_in_flight: usize) -> CongestionMetrics {
+        CongestionMetrics{ rtt: rtt, rtt_variance: rtt_variance, congestion_window: congestion_window, bytes_in_flight: bytes_in_flight }
+    }
+
+    /// Smoothed round trip time to the peer.
+    pub fn rtt(&self) -> Duration {
+        self.rtt
+    }
+
+    /// Variance of the round trip time to the peer.
+    pub fn rtt_variance(&self) -> Duration {
+        self.rtt_variance
+    }
+
+    /// Number of bytes the transport will allow to be unacknowledged.
+    pub fn congestion_window(&self) -> usize {
+        self.congestion_window
+    }
+
+    /// Number of bytes currently unacknowledged.
+    pub fn bytes_in_flight(&self) -> usize {
+        self.bytes_in_flight
+    }
+
+    /// Number of bytes that can be sent before the congestion window is full.
+    pub fn available_window(&self) -> usize {
+        self.congestion_window.saturating_sub(self.bytes_in_flight)
+    }
+
+    /// Number of requests for blocks of the given length that would fill the congestion window.
+    ///
+    /// Always returns at least one, so that a peer with a small window is not starved.
+    pub fn pipeline_depth(&self, block_len: usize) -> usize {
+        if block_len == 0 {
+            1
+        } else {
+            cmp::max(1, self.congestion_window / block_len)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::CongestionMetrics;
+
+    #[test]
+    fn positive_available_window() {
+        let metrics = CongestionMetrics::new(Duration::from_millis(100), Duration::from_millis(10), 64 * 1024, 16 * 1024);
+
+        assert_eq!(48 * 1024, metrics.available_window());
+    }
+
+    #[test]
+    fn positive_available_window_over_full() {
+        let metrics = CongestionMetrics::new(Duration::from_millis(100), Duration::from_millis(10), 16 * 1024, 32 * 1024);
+
+        assert_eq!(0, metrics.available_window());
+    }
+
+    #[test]
+    fn positive_pipeline_depth() {
+        let metrics = CongestionMetrics::new(Duration::from_millis(100), Duration::from_millis(10), 64 * 1024, 0);
+
+        assert_eq!(4, metrics.pipeline_depth(16 * 1024));
+    }
+
+    #[test]
+    fn positive_pipeline_depth_small_window() {
+        let metrics = CongestionMetrics::new(Duration::from_millis(100), Duration::from_millis(10), 1024, 0);
+
+        assert_eq!(1, metrics.pipeline_depth(16 * 1024));
+        assert_eq!(1, metrics.pipeline_depth(0));
+    }
+}
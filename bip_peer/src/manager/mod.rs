@@ -5,10 +5,14 @@ use std::cmp;
 use std::time::Duration;
 use std::sync::{Arc, Mutex};
 
-use manager::builder::PeerManagerBuilder;
+use manager::builder::{PeerManagerBuilder, PeerConfig};
 use manager::peer_info::PeerInfo;
 use manager::error::{PeerManagerError, PeerManagerErrorKind};
+use manager::hash_stream::PeerManagerHashStreams;
+use manager::task::PeerSender;
+use codec::PeerProtocolStats;
 
+use bytes::Bytes;
 use crossbeam::sync::MsQueue;
 use futures::{StartSend, Poll, AsyncSink, Async};
 use futures::sink::Sink;
@@ -21,6 +25,10 @@ use tokio_timer::{self, Timer};
 pub mod builder;
 pub mod peer_info;
 pub mod error;
+pub mod hash_stream;
+pub mod metrics;
+pub mod suggest;
+pub mod requests;
 
 mod future;
 mod task;
@@ -51,11 +59,13 @@ impl<P> PeerManager<P>
 
         // Figure out the right tick duration to get num slots of 2048.
         // TODO: We could probably let users change this in the future...
-        let max_duration = cmp::max(builder.heartbeat_interval(), builder.heartbeat_timeout());
+        let max_duration = builder.heartbeat_max();
         let tick_duration = Duration::from_millis(max_duration.as_secs() * 1000 / (DEFAULT_TIMER_SLOTS as u64) + 1);
+        // Timeouts waiting in the channel reserve their wheel slot up front, so the wheel needs room for both,
+        // otherwise, the timer thread only moves one timeout out of the channel each time it wakes up
         let timer = tokio_timer::wheel()
             .tick_duration(tick_duration)
-            .max_capacity(pow_maximum_timers + 1)
+            .max_capacity(pow_maximum_timers * 2)
             .channel_capacity(pow_maximum_timers)
             .num_slots(DEFAULT_TIMER_SLOTS)
             .build();
@@ -115,13 +125,13 @@ pub struct PeerManagerSink<P> where P: Sink + Stream {
     timer:      Timer,
     build:      PeerManagerBuilder,
     send:       Sender<OPeerManagerMessage<P::Item>>,
-    peers:      Arc<Mutex<HashMap<PeerInfo, Sender<IPeerManagerMessage<P>>>>>,
+    peers:      Arc<Mutex<HashMap<PeerInfo, PeerSender<P>>>>,
     task_queue: Arc<MsQueue<Task>>
 }
 
 impl<P> Clone for PeerManagerSink<P> where P: Sink + Stream {
     fn clone(&self) -> PeerManagerSink<P> {
-        PeerManagerSink{ handle: self.handle.clone(), timer: self.timer.clone(), build: self.build,
+        PeerManagerSink{ handle: self.handle.clone(), timer: self.timer.clone(), build: self.build.clone(),
                          send: self.send.clone(), peers: self.peers.clone(), task_queue: self.task_queue.clone() }
     }
 }
@@ -129,7 +139,7 @@ impl<P> Clone for PeerManagerSink<P> where P: Sink + Stream {
 impl<P> PeerManagerSink<P> where P: Sink + Stream {
     fn new(handle: Handle, timer: Timer, build: PeerManagerBuilder,
            send: Sender<OPeerManagerMessage<P::Item>>,
-           peers: Arc<Mutex<HashMap<PeerInfo, Sender<IPeerManagerMessage<P>>>>>,
+           peers: Arc<Mutex<HashMap<PeerInfo, PeerSender<P>>>>,
            task_queue: Arc<MsQueue<Task>>) -> PeerManagerSink<P> {
         PeerManagerSink{ handle: handle, timer: timer, build: build, send: send, peers: peers, task_queue: task_queue}
     }
@@ -137,7 +147,7 @@ impl<P> PeerManagerSink<P> where P: Sink + Stream {
     fn run_with_lock_sink<F, T, E, G, I>(&mut self, item: I, call: F, not: G) -> StartSend<T, E>
         where F: FnOnce(I, &mut Handle, &mut Timer, &mut PeerManagerBuilder,
                         &mut Sender<OPeerManagerMessage<P::Item>>,
-                        &mut HashMap<PeerInfo, Sender<IPeerManagerMessage<P>>>) -> StartSend<T, E>,
+                        &mut HashMap<PeerInfo, PeerSender<P>>) -> StartSend<T, E>,
               G: FnOnce(I) -> T {
         let (result, took_lock) = if let Ok(mut guard) = self.peers.try_lock() {
             let result = call(item, &mut self.handle, &mut self.timer, &mut self.build, &mut self.send, &mut *guard);
@@ -173,10 +183,44 @@ impl<P> PeerManagerSink<P> where P: Sink + Stream {
         result
     }
 
+    fn start_add_peer(&mut self, info: PeerInfo, peer: P, config: PeerConfig) -> StartSend<(PeerInfo, P, PeerConfig), PeerManagerError>
+        where P: Sink<SinkError=io::Error> +
+                 Stream<Error=io::Error> +
+                 'static,
+              P::SinkItem: ManagedMessage,
+              P::Item:     ManagedMessage {
+        self.run_with_lock_sink((info, peer, config), |(info, peer, config), handle, timer, builder, send, peers| {
+            if peers.len() >= builder.peer_capacity() {
+                Ok(AsyncSink::NotReady((info, peer, config)))
+            } else {
+                match peers.entry(info) {
+                    Entry::Occupied(_) => Err(PeerManagerError::from_kind(PeerManagerErrorKind::PeerNotFound{ info: info })),
+                    Entry::Vacant(vac) => {
+                        // Timer wheel cannot handle timeouts longer than our max
+                        let max_duration = builder.heartbeat_max();
+                        let interval = cmp::min(config.heartbeat_interval(), max_duration);
+                        let timeout = cmp::min(config.heartbeat_timeout(), max_duration);
+                        let adaptive = config.adaptive_heartbeat_timeout().map(|(min, max)| {
+                            (cmp::min(min, max_duration), cmp::min(max, max_duration))
+                        });
+                        let config = config
+                            .with_heartbeat_interval(interval)
+                            .with_heartbeat_timeout(timeout)
+                            .with_adaptive_heartbeat_timeout(adaptive);
+                        vac.insert(task::run_peer(peer, info, send.clone(), timer.clone(), builder, config, handle));
+
+                        Ok(AsyncSink::Ready)
+                    }
+                }
+            }
+        },
+        |(info, peer, config)| (info, peer, config))
+    }
+
     fn run_with_lock_poll<F, T, E>(&mut self, call: F) -> Poll<T, E>
         where F: FnOnce(&mut Handle, &mut Timer, &mut PeerManagerBuilder,
                         &mut Sender<OPeerManagerMessage<P::Item>>,
-                        &mut HashMap<PeerInfo, Sender<IPeerManagerMessage<P>>>) -> Poll<T, E> {
+                        &mut HashMap<PeerInfo, PeerSender<P>>) -> Poll<T, E> {
         let (result, took_lock) = if let Ok(mut guard) = self.peers.try_lock() {
             let result = call(&mut self.handle, &mut self.timer, &mut self.build, &mut self.send, &mut *guard);
 
@@ -204,6 +248,29 @@ impl<P> PeerManagerSink<P> where P: Sink + Stream {
     }
 }
 
+/// Send each message in the batch to the peer individually, so that every message takes up a slot in the peer buffer.
+///
+/// If the peer buffer fills up part way through the batch, the messages that were not accepted are handed back.
+fn start_send_messages<P>(send: &mut PeerSender<P>, info: PeerInfo, peer_messages: Vec<(MessageId, P::SinkItem)>)
+    -> StartSend<IPeerManagerMessage<P>, PeerManagerError>
+    where P: Sink {
+    let mut peer_messages = peer_messages.into_iter();
+
+    while let Some((mid, peer_message)) = peer_messages.next() {
+        // Needs type hint in case send fails (so that error type matches)
+        let result: StartSend<_, PeerManagerError> = send.start_send(IPeerManagerMessage::SendMessage(info, mid, peer_message))
+            .map_err(|_| panic!("bip_peer: PeerManager Failed to Send SendMessages"));
+
+        if let AsyncSink::NotReady(IPeerManagerMessage::SendMessage(info, mid, peer_message)) = try!(result) {
+            let remaining = Some((mid, peer_message)).into_iter().chain(peer_messages).collect();
+
+            return Ok(AsyncSink::NotReady(IPeerManagerMessage::SendMessages(info, remaining)))
+        }
+    }
+
+    Ok(AsyncSink::Ready)
+}
+
 impl<P> Sink for PeerManagerSink<P>
     where P: Sink<SinkError=io::Error> +
              Stream<Error=io::Error> +
@@ -216,21 +283,24 @@ impl<P> Sink for PeerManagerSink<P>
     fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
         match item {
             IPeerManagerMessage::AddPeer(info, peer) => {
-                self.run_with_lock_sink((info, peer), |(info, peer), handle, timer, builder, send, peers| {
-                    if peers.len() >= builder.peer_capacity() {
-                        Ok(AsyncSink::NotReady(IPeerManagerMessage::AddPeer(info, peer)))
-                    } else {
-                        match peers.entry(info) {
-                            Entry::Occupied(_) => Err(PeerManagerError::from_kind(PeerManagerErrorKind::PeerNotFound{ info: info })),
-                            Entry::Vacant(vac) => {
-                                vac.insert(task::run_peer(peer, info, send.clone(), timer.clone(), builder, handle));
-
-                                Ok(AsyncSink::Ready)
-                            }
-                        }
-                    }
+                let config = PeerConfig::from_builder(&self.build);
+
+                self.start_add_peer(info, peer, config)
+                    .map(|async| async.map(|(info, peer, _)| IPeerManagerMessage::AddPeer(info, peer)))
+            },
+            IPeerManagerMessage::AddPeerWithConfig(info, peer, config) => {
+                self.start_add_peer(info, peer, config)
+                    .map(|async| async.map(|(info, peer, config)| IPeerManagerMessage::AddPeerWithConfig(info, peer, config)))
+            },
+            IPeerManagerMessage::ReplacePeer(info, peer) => {
+                self.run_with_lock_sink((info, peer), |(info, peer), _, _, _, _, peers| {
+                    peers.get_mut(&info)
+                        .ok_or_else(|| PeerManagerError::from_kind(PeerManagerErrorKind::PeerNotFound{ info: info }))
+                        .and_then(|send| send.start_send(IPeerManagerMessage::ReplacePeer(info, peer))
+                                             .map_err(|_| panic!("bip_peer: PeerManager Failed To Send ReplacePeer"))
+                        )
                 },
-                |(info, peer)| IPeerManagerMessage::AddPeer(info, peer))
+                |(info, peer)| IPeerManagerMessage::ReplacePeer(info, peer))
             },
             IPeerManagerMessage::RemovePeer(info) => {
                 self.run_with_lock_sink(info, |info, _, _, _, _, peers| {
@@ -251,6 +321,44 @@ impl<P> Sink for PeerManagerSink<P>
                         )
                 },
                 |(info, mid, peer_message)| IPeerManagerMessage::SendMessage(info, mid, peer_message))
+            },
+            IPeerManagerMessage::SendMessages(info, peer_messages) => {
+                self.run_with_lock_sink((info, peer_messages), |(info, peer_messages), _, _, _, _, peers| {
+                    peers.get_mut(&info)
+                        .ok_or_else(|| PeerManagerError::from_kind(PeerManagerErrorKind::PeerNotFound{ info: info }))
+                        .and_then(|send| start_send_messages(send, info, peer_messages))
+                },
+                |(info, peer_messages)| IPeerManagerMessage::SendMessages(info, peer_messages))
+            },
+            IPeerManagerMessage::QueryStats(info) => {
+                self.run_with_lock_sink(info, |info, _, _, _, _, peers| {
+                    peers.get_mut(&info)
+                        .ok_or_else(|| PeerManagerError::from_kind(PeerManagerErrorKind::PeerNotFound{ info: info }))
+                        .and_then(|send| send.start_send(IPeerManagerMessage::QueryStats(info))
+                                             .map_err(|_| panic!("bip_peer: PeerManager Failed To Send QueryStats"))
+                        )
+                },
+                |info| IPeerManagerMessage::QueryStats(info))
+            },
+            IPeerManagerMessage::PurgeQueued(info, predicate) => {
+                self.run_with_lock_sink((info, predicate), |(info, predicate), _, _, _, _, peers| {
+                    peers.get_mut(&info)
+                        .ok_or_else(|| PeerManagerError::from_kind(PeerManagerErrorKind::PeerNotFound{ info: info }))
+                        .and_then(|send| send.start_send(IPeerManagerMessage::PurgeQueued(info, predicate))
+                                             .map_err(|_| panic!("bip_peer: PeerManager Failed To Send PurgeQueued"))
+                        )
+                },
+                |(info, predicate)| IPeerManagerMessage::PurgeQueued(info, predicate))
+            },
+            IPeerManagerMessage::QueryQueued(info) => {
+                self.run_with_lock_sink(info, |info, _, _, _, _, peers| {
+                    peers.get_mut(&info)
+                        .ok_or_else(|| PeerManagerError::from_kind(PeerManagerErrorKind::PeerNotFound{ info: info }))
+                        .and_then(|send| send.start_send(IPeerManagerMessage::QueryQueued(info))
+                                             .map_err(|_| panic!("bip_peer: PeerManager Failed To Send QueryQueued"))
+                        )
+                },
+                |info| IPeerManagerMessage::QueryQueued(info))
             }
         }
     }
@@ -276,20 +384,28 @@ impl<P> Sink for PeerManagerSink<P>
 /// Stream half of a `PeerManager`.
 pub struct PeerManagerStream<P> where P: Sink + Stream {
     recv:        Receiver<OPeerManagerMessage<P::Item>>,
-    peers:       Arc<Mutex<HashMap<PeerInfo, Sender<IPeerManagerMessage<P>>>>>,
+    peers:       Arc<Mutex<HashMap<PeerInfo, PeerSender<P>>>>,
     task_queue:  Arc<MsQueue<Task>>,
     opt_pending: Option<Option<OPeerManagerMessage<P::Item>>>
 }
 
 impl<P> PeerManagerStream<P> where P: Sink + Stream {
     fn new(recv: Receiver<OPeerManagerMessage<P::Item>>,
-           peers: Arc<Mutex<HashMap<PeerInfo, Sender<IPeerManagerMessage<P>>>>>,
+           peers: Arc<Mutex<HashMap<PeerInfo, PeerSender<P>>>>,
            task_queue: Arc<MsQueue<Task>>) -> PeerManagerStream<P> {
         PeerManagerStream{ recv: recv, peers: peers, task_queue: task_queue, opt_pending: None }
     }
 
+    /// Split this stream into separate streams for each `InfoHash`.
+    ///
+    /// Useful for clients managing multiple torrents, which would otherwise
+    /// have to demultiplex the messages for each torrent themselves.
+    pub fn streams_by_hash(self) -> PeerManagerHashStreams<P> {
+        PeerManagerHashStreams::new(self)
+    }
+
     fn run_with_lock_poll<F, T, E, I, G>(&mut self, item: I, call: F, not: G) -> Poll<T, E>
-        where F: FnOnce(I, &mut HashMap<PeerInfo, Sender<IPeerManagerMessage<P>>>) -> Poll<T, E>,
+        where F: FnOnce(I, &mut HashMap<PeerInfo, PeerSender<P>>) -> Poll<T, E>,
               G: FnOnce(I) -> Option<OPeerManagerMessage<P::Item>> {
         let (result, took_lock) = if let Ok(mut guard) = self.peers.try_lock() {
             let result = call(item, &mut *guard);
@@ -380,6 +496,14 @@ pub trait ManagedMessage {
 
     /// Whether or not this message is a keep alive message.
     fn is_keep_alive(&self) -> bool;
+
+    /// Block payload carried by this message, if any.
+    ///
+    /// Recycled into the buffer pool of the `PeerManager` once the message has been
+    /// sent, see `PeerManagerBuilder::with_buffer_pool`.
+    fn block_payload(&self) -> Option<Bytes> {
+        None
+    }
 }
 
 //----------------------------------------------------------------------------//
@@ -392,11 +516,40 @@ pub enum IPeerManagerMessage<P>
     where P: Sink {
     /// Add a peer to the peer manager.
     AddPeer(PeerInfo, P),
+    /// Add a peer to the peer manager, overriding the builder configuration for this peer.
+    AddPeerWithConfig(PeerInfo, P, PeerConfig),
+    /// Replace the sink and stream of an existing peer, without removing it from the peer manager.
+    ///
+    /// Messages sent before this message will go to the old peer, and messages sent after
+    /// will go to the new peer. Useful for transports that negotiate some form of encryption
+    /// after the handshake, where the new peer would wrap the connection of the old peer.
+    ReplacePeer(PeerInfo, P),
     /// Remove a peer from the peer manager.
     RemovePeer(PeerInfo),
     /// Send a message to a peer.
-    SendMessage(PeerInfo, MessageId, P::SinkItem)
-    // TODO: Support querying for statistics
+    SendMessage(PeerInfo, MessageId, P::SinkItem),
+    /// Send a batch of messages to a peer.
+    ///
+    /// Equivalent to sending each message with `SendMessage`, in order, but only takes the peers lock
+    /// once. Each message takes up its own slot in the peer buffer; if the buffer fills up part way through
+    /// the batch, the messages that were not accepted are handed back in `AsyncSink::NotReady`.
+    ///
+    /// See `PeerManagerBuilder::with_ack_window` for acknowledging the whole batch at once.
+    SendMessages(PeerInfo, Vec<(MessageId, P::SinkItem)>),
+    /// Query the protocol statistics of a peer.
+    ///
+    /// Statistics are returned through `OPeerManagerMessage::PeerStats`.
+    QueryStats(PeerInfo),
+    /// Drop any messages queued up for a peer, that have not been written yet, which match the given predicate.
+    ///
+    /// This message skips ahead of any queued messages, so matching messages are dropped instead of written, for
+    /// example, when choking a peer with piece messages still queued. Message ids of dropped messages are returned
+    /// through `OPeerManagerMessage::PurgedMessages`.
+    PurgeQueued(PeerInfo, Box<Fn(&P::SinkItem) -> bool + Send>),
+    /// Query the number of messages queued up for a peer, that have not been written yet.
+    ///
+    /// This message skips ahead of any queued messages, the count is returned through `OPeerManagerMessage::PeerQueued`.
+    QueryQueued(PeerInfo)
 }
 
 /// Message that can be received from the `PeerManager`.
@@ -407,6 +560,10 @@ pub enum OPeerManagerMessage<M> {
     PeerRemoved(PeerInfo),
     /// Message indicating a message has been sent to the given peer.
     SentMessage(PeerInfo, MessageId),
+    /// Message indicating the messages with the given ids have been sent to the given peer, in order.
+    ///
+    /// Only sent when an acknowledgement window is set through `PeerManagerBuilder::with_ack_window`.
+    SentMessages(PeerInfo, Vec<MessageId>),
     /// Message indicating we have received a message from a peer.
     ReceivedMessage(PeerInfo, M),
     /// Message indicating a peer has disconnected from us.
@@ -416,5 +573,16 @@ pub enum OPeerManagerMessage<M> {
     /// Message indicating a peer errored out.
     ///
     /// Same semantics as `PeerRemoved`, but the peer is not returned.
-    PeerError(PeerInfo, io::Error)
+    PeerError(PeerInfo, io::Error),
+    /// Message containing the protocol statistics for a peer, in response to `IPeerManagerMessage::QueryStats`.
+    ///
+    /// Also sent right before every `PeerRemoved`, `PeerDisconnect`, or `PeerError`, so the final statistics
+    /// for a peer are never lost.
+    PeerStats(PeerInfo, PeerProtocolStats),
+    /// Message containing the ids of messages that were dropped, in response to `IPeerManagerMessage::PurgeQueued`.
+    ///
+    /// Dropped messages will not be acknowledged through `SentMessage` or `SentMessages`.
+    PurgedMessages(PeerInfo, Vec<MessageId>),
+    /// Message containing the number of queued messages for a peer, in response to `IPeerManagerMessage::QueryQueued`.
+    PeerQueued(PeerInfo, usize)
 }
\ No newline at end of file
@@ -1,60 +1,201 @@
+use std::borrow::Borrow;
 use std::hash::Hash;
 use std::hash::Hasher;
 use std::net::SocketAddr;
 
-use bip_handshake::Extensions;
+use bip_handshake::{Extensions, Direction, TransportKind};
 use bip_util::bt::{InfoHash, PeerId};
 
-/// Information that uniquely identifies a peer.
+/// Identity of a peer, the `(address, peer_id, hash)` tuple.
+///
+/// This is the only part of a `PeerInfo` that takes part in equality and hashing.
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub struct PeerIdentity {
+    addr: SocketAddr,
+    pid:  PeerId,
+    hash: InfoHash
+}
+
+impl PeerIdentity {
+    /// Create a new `PeerIdentity` object.
+    pub fn new(addr: SocketAddr, pid: PeerId, hash: InfoHash) -> PeerIdentity {
+        PeerIdentity{ addr: addr, pid: pid, hash: hash }
+    }
+
+    /// Retrieve the peer address.
+    pub fn addr(&self) -> &SocketAddr {
+        &self.addr
+    }
+
+    /// Retrieve the peer id.
+    pub fn peer_id(&self) -> &PeerId {
+        &self.pid
+    }
+
+    /// Retrieve the peer info hash.
+    pub fn hash(&self) -> &InfoHash {
+        &self.hash
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// Information that uniquely identifies a peer, along with metadata about the peer.
 /// 
-/// Equality oprations DO NOT INCLUDE `Extensions` as we define a
-/// unique peer as `(address, peer_id, hash)`, so equality will be
-/// based on that tuple.
+/// Equality and hashing operations DO NOT INCLUDE `Extensions`, `Direction`, or
+/// `TransportKind` as we define a unique peer as its `PeerIdentity`, so changing
+/// metadata (for example, with `PeerInfo::with_extensions`) never changes which
+/// peer a `PeerInfo` refers to. Since `PeerInfo` implements `Borrow<PeerIdentity>`,
+/// maps keyed by `PeerInfo` can be queried with just a `PeerIdentity`.
 #[derive(Eq, Debug, Copy, Clone)]
 pub struct PeerInfo {
-    addr: SocketAddr,
-    pid:  PeerId,
-    hash: InfoHash,
-    ext:  Extensions
+    id:   PeerIdentity,
+    ext:  Extensions,
+    dir:  Direction,
+    kind: TransportKind
 }
 
 impl PeerInfo {
     /// Create a new `PeerInfo` object.
-    pub fn new(addr: SocketAddr, pid: PeerId, hash: InfoHash, extensions: Extensions) -> PeerInfo {
-        PeerInfo{ addr: addr, pid: pid, hash: hash, ext: extensions }
+    pub fn new(addr: SocketAddr, pid: PeerId, hash: InfoHash, extensions: Extensions, dir: Direction,
+               kind: TransportKind) -> PeerInfo {
+        PeerInfo::from_identity(PeerIdentity::new(addr, pid, hash), extensions, dir, kind)
+    }
+
+    /// Create a new `PeerInfo` object from the given `PeerIdentity`.
+    pub fn from_identity(id: PeerIdentity, extensions: Extensions, dir: Direction, kind: TransportKind) -> PeerInfo {
+        PeerInfo{ id: id, ext: extensions, dir: dir, kind: kind }
+    }
+
+    /// Set the extensions supported by the peer.
+    ///
+    /// The returned `PeerInfo` is still equal to this one.
+    pub fn with_extensions(mut self, extensions: Extensions) -> PeerInfo {
+        self.ext = extensions;
+
+        self
+    }
+
+    /// Set the direction that the connection to the peer was established in.
+    pub fn with_direction(mut self, dir: Direction) -> PeerInfo {
+        self.dir = dir;
+
+        self
+    }
+
+    /// Set the kind of transport that the connection to the peer was established over.
+    pub fn with_transport(mut self, kind: TransportKind) -> PeerInfo {
+        self.kind = kind;
+
+        self
+    }
+
+    /// Retrieve the identity of the peer.
+    pub fn identity(&self) -> &PeerIdentity {
+        &self.id
     }
 
     /// Retrieve the peer address.
     pub fn addr(&self) -> &SocketAddr {
-        &self.addr
+        self.id.addr()
     }
 
     /// Retrieve the peer id.
     pub fn peer_id(&self) -> &PeerId {
-        &self.pid
+        self.id.peer_id()
     }
     
     /// Retrieve the peer info hash.
     pub fn hash(&self) -> &InfoHash {
-        &self.hash
+        self.id.hash()
     }
 
     /// Retrieve the extensions supported by this peer.
     pub fn extensions(&self) -> &Extensions {
         &self.ext
     }
+
+    /// Retrieve the direction that the connection to the peer was established in.
+    pub fn direction(&self) -> Direction {
+        self.dir
+    }
+
+    /// Retrieve the kind of transport that the connection to the peer was established over.
+    pub fn transport(&self) -> TransportKind {
+        self.kind
+    }
 }
 
 impl PartialEq for PeerInfo {
     fn eq(&self, other: &PeerInfo) -> bool {
-        self.addr.eq(&other.addr) && self.pid.eq(&other.pid) && self.hash.eq(&other.hash)
+        self.id.eq(&other.id)
     }
 }
 
+// Has to hash the same as our identity, to uphold the contract of Borrow
 impl Hash for PeerInfo {
     fn hash<H>(&self, state: &mut H) where H: Hasher {
-        self.addr.hash(state);
-        self.pid.hash(state);
-        self.hash.hash(state);
+        Hash::hash(&self.id, state);
+    }
+}
+
+impl Borrow<PeerIdentity> for PeerInfo {
+    fn borrow(&self) -> &PeerIdentity {
+        &self.id
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{PeerInfo, PeerIdentity};
+
+    use bip_handshake::{Extension, Extensions, Direction, TransportKind};
+    use bip_util::bt;
+
+    #[test]
+    fn positive_peer_info_connection_details() {
+        let info = PeerInfo::new("1.2.3.4:5".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(),
+                                 Direction::Inbound, TransportKind::Utp);
+
+        assert_eq!(Direction::Inbound, info.direction());
+        assert_eq!(TransportKind::Utp, info.transport());
+    }
+
+    #[test]
+    fn positive_peer_info_equality_ignores_connection_details() {
+        let info = PeerInfo::new("1.2.3.4:5".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(),
+                                 Direction::Outbound, TransportKind::Tcp);
+        let other = info.with_direction(Direction::Inbound).with_transport(TransportKind::Utp);
+
+        assert_eq!(Direction::Inbound, other.direction());
+        assert_eq!(TransportKind::Utp, other.transport());
+        assert_eq!(info, other);
+    }
+
+    #[test]
+    fn positive_peer_info_equality_ignores_extensions() {
+        let info = PeerInfo::new("1.2.3.4:5".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(),
+                                 Direction::Outbound, TransportKind::Tcp);
+
+        let mut extensions = Extensions::new();
+        extensions.add(Extension::ExtensionProtocol);
+        let other = info.with_extensions(extensions);
+
+        assert_eq!(&extensions, other.extensions());
+        assert_eq!(info, other);
+    }
+
+    #[test]
+    fn positive_peer_info_lookup_by_identity() {
+        let identity = PeerIdentity::new("1.2.3.4:5".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into());
+        let info = PeerInfo::from_identity(identity, Extensions::new(), Direction::Inbound, TransportKind::Tcp);
+
+        let mut peers = HashMap::new();
+        peers.insert(info, 5);
+
+        assert_eq!(Some(&5), peers.get(&identity));
+        assert_eq!(&identity, info.identity());
+    }
+}
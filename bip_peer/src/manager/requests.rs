@@ -0,0 +1,334 @@
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+
+use manager::peer_info::PeerInfo;
+use message::{CancelMessage, RequestMessage};
+
+use bip_util::bt::InfoHash;
+
+/// Default maximum number of requests a single peer can have outstanding.
+pub const DEFAULT_MAX_PEER_REQUESTS: usize = 250;
+
+/// Block requested by one or more peers, along with whether it is being loaded.
+struct PendingBlock {
+    loading: bool,
+    peers:   VecDeque<PeerInfo>
+}
+
+impl PendingBlock {
+    fn new() -> PendingBlock {
+        PendingBlock{ loading: false, peers: VecDeque::new() }
+    }
+}
+
+/// Tracks `RequestMessage`s received from peers that we have yet to serve.
+///
+/// Incoming requests are recorded with `request_received`, and the blocks
+/// that need to be loaded from disk are handed out by `next_load`. If every
+/// peer waiting on a block cancels (or disconnects) before its load is handed
+/// out, the load is dropped and no disk IO takes place. Once a block has been
+/// loaded, `block_loaded` returns the peers that are still waiting for it, in
+/// the order that they requested it (or `load_failed`, if the load failed).
+///
+/// Each peer can only have a limited number of requests outstanding, similar
+/// to the `reqq` value advertised in the extended handshake.
+pub struct PeerRequests {
+    pending:      HashMap<(InfoHash, RequestMessage), PendingBlock>,
+    loads:        VecDeque<(InfoHash, RequestMessage)>,
+    outstanding:  HashMap<PeerInfo, usize>,
+    max_requests: usize
+}
+
+impl PeerRequests {
+    /// Create a new, empty `PeerRequests`.
+    ///
+    /// Peers can have up to `DEFAULT_MAX_PEER_REQUESTS` requests outstanding.
+    pub fn new() -> PeerRequests {
+        PeerRequests::with_max_requests(DEFAULT_MAX_PEER_REQUESTS)
+    }
+
+    /// Create a new, empty `PeerRequests` where each peer can have up to
+    /// `max_requests` requests outstanding.
+    pub fn with_max_requests(max_requests: usize) -> PeerRequests {
+        PeerRequests{ pending: HashMap::new(), loads: VecDeque::new(), outstanding: HashMap::new(), max_requests: max_requests }
+    }
+
+    /// Record a request received from the given peer.
+    ///
+    /// Returns false if the peer already has the same request outstanding, or
+    /// if the peer already has the maximum number of requests outstanding.
+    pub fn request_received(&mut self, info: PeerInfo, request: RequestMessage) -> bool {
+        if self.outstanding.get(&info).cloned().unwrap_or(0) >= self.max_requests {
+            return false
+        }
+
+        let key = (*info.hash(), request);
+        let block = self.pending.entry(key).or_insert_with(PendingBlock::new);
+
+        if block.peers.contains(&info) {
+            return false
+        }
+
+        // Only queue a load for the first peer, later peers piggy back off of it
+        if block.peers.is_empty() && !block.loading {
+            self.loads.push_back(key);
+        }
+        block.peers.push_back(info);
+        *self.outstanding.entry(info).or_insert(0) += 1;
+
+        true
+    }
+
+    /// Process a cancel received from the given peer.
+    ///
+    /// Returns false if the peer had no matching request outstanding.
+    pub fn cancel_received(&mut self, info: &PeerInfo, cancel: CancelMessage) -> bool {
+        let request = RequestMessage::new(cancel.piece_index(), cancel.block_offset(), cancel.block_length());
+
+        let removed = match self.pending.entry((*info.hash(), request)) {
+            Entry::Occupied(mut occupied) => {
+                let opt_position = occupied.get().peers.iter().position(|peer| peer == info);
+                let removed = opt_position.map(|position| occupied.get_mut().peers.remove(position)).is_some();
+
+                if occupied.get().peers.is_empty() && !occupied.get().loading {
+                    occupied.remove();
+                }
+
+                removed
+            },
+            Entry::Vacant(_) => false
+        };
+
+        if removed {
+            self.request_finished(info);
+        }
+
+        removed
+    }
+
+    /// Drop all outstanding requests for the given peer.
+    pub fn remove_peer(&mut self, info: &PeerInfo) {
+        for block in self.pending.values_mut() {
+            block.peers.retain(|peer| peer != info);
+        }
+
+        self.pending.retain(|_, block| !block.peers.is_empty() || block.loading);
+        self.outstanding.remove(info);
+    }
+
+    /// Retrieve the next block that should be loaded from disk.
+    ///
+    /// Loads for blocks that no peer is waiting on anymore are skipped.
+    pub fn next_load(&mut self) -> Option<(InfoHash, RequestMessage)> {
+        while let Some(key) = self.loads.pop_front() {
+            if let Some(block) = self.pending.get_mut(&key) {
+                if !block.loading {
+                    block.loading = true;
+
+                    return Some(key)
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Notify that a block was loaded from disk.
+    ///
+    /// Returns the peers that should be sent the block.
+    pub fn block_loaded(&mut self, hash: &InfoHash, request: &RequestMessage) -> Vec<PeerInfo> {
+        self.remove_block(hash, request)
+    }
+
+    /// Notify that a block could not be loaded from disk.
+    ///
+    /// Requests for the block are dropped, returns the peers that were waiting on it.
+    pub fn load_failed(&mut self, hash: &InfoHash, request: &RequestMessage) -> Vec<PeerInfo> {
+        self.remove_block(hash, request)
+    }
+
+    /// Whether or not any peer is still waiting on the given block.
+    pub fn is_requested(&self, hash: &InfoHash, request: &RequestMessage) -> bool {
+        self.pending.get(&(*hash, *request))
+            .map(|block| !block.peers.is_empty())
+            .unwrap_or(false)
+    }
+
+    fn remove_block(&mut self, hash: &InfoHash, request: &RequestMessage) -> Vec<PeerInfo> {
+        let peers: Vec<PeerInfo> = self.pending.remove(&(*hash, *request))
+            .map(|block| block.peers.into_iter().collect())
+            .unwrap_or(Vec::new());
+
+        for peer in peers.iter() {
+            self.request_finished(peer);
+        }
+
+        peers
+    }
+
+    fn request_finished(&mut self, info: &PeerInfo) {
+        let remaining = self.outstanding.get_mut(info).map(|count| { *count -= 1; *count });
+
+        if remaining == Some(0) {
+            self.outstanding.remove(info);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::PeerRequests;
+    use manager::peer_info::PeerInfo;
+    use message::{CancelMessage, RequestMessage};
+
+    use bip_handshake::{Direction, Extensions, TransportKind};
+    use bip_util::bt::{self, InfoHash, PeerId};
+
+    fn peer_info(port: u16) -> PeerInfo {
+        let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+        let pid: PeerId = [port as u8; bt::PEER_ID_LEN].into();
+        let hash: InfoHash = [0u8; bt::INFO_HASH_LEN].into();
+
+        PeerInfo::new(addr, pid, hash, Extensions::new(), Direction::Outbound, TransportKind::Tcp)
+    }
+
+    #[test]
+    fn positive_single_load_for_multiple_peers() {
+        let (peer_one, peer_two) = (peer_info(1), peer_info(2));
+        let request = RequestMessage::new(0, 0, 16 * 1024);
+        let mut requests = PeerRequests::new();
+
+        assert!(requests.request_received(peer_one, request));
+        assert!(requests.request_received(peer_two, request));
+
+        assert_eq!(Some((*peer_one.hash(), request)), requests.next_load());
+        assert_eq!(None, requests.next_load());
+
+        assert_eq!(vec![peer_one, peer_two], requests.block_loaded(peer_one.hash(), &request));
+        assert!(!requests.is_requested(peer_one.hash(), &request));
+    }
+
+    #[test]
+    fn positive_cancel_drops_queued_load() {
+        let peer = peer_info(1);
+        let request = RequestMessage::new(0, 0, 16 * 1024);
+        let mut requests = PeerRequests::new();
+
+        requests.request_received(peer, request);
+        assert!(requests.cancel_received(&peer, CancelMessage::new(0, 0, 16 * 1024)));
+
+        assert_eq!(None, requests.next_load());
+    }
+
+    #[test]
+    fn positive_cancel_after_load_started() {
+        let (peer_one, peer_two) = (peer_info(1), peer_info(2));
+        let request = RequestMessage::new(1, 0, 16 * 1024);
+        let mut requests = PeerRequests::new();
+
+        requests.request_received(peer_one, request);
+        requests.request_received(peer_two, request);
+        requests.next_load().unwrap();
+
+        assert!(requests.cancel_received(&peer_one, CancelMessage::new(1, 0, 16 * 1024)));
+
+        assert_eq!(vec![peer_two], requests.block_loaded(peer_one.hash(), &request));
+    }
+
+    #[test]
+    fn positive_remove_peer_drops_queued_load() {
+        let peer = peer_info(1);
+        let mut requests = PeerRequests::new();
+
+        requests.request_received(peer, RequestMessage::new(0, 0, 16 * 1024));
+        requests.request_received(peer, RequestMessage::new(0, 16 * 1024, 16 * 1024));
+        requests.remove_peer(&peer);
+
+        assert_eq!(None, requests.next_load());
+    }
+
+    #[test]
+    fn positive_rerequest_after_cancel_loads_once() {
+        let peer = peer_info(1);
+        let request = RequestMessage::new(0, 0, 16 * 1024);
+        let mut requests = PeerRequests::new();
+
+        requests.request_received(peer, request);
+        requests.cancel_received(&peer, CancelMessage::new(0, 0, 16 * 1024));
+        requests.request_received(peer, request);
+
+        assert_eq!(Some((*peer.hash(), request)), requests.next_load());
+        assert_eq!(None, requests.next_load());
+    }
+
+    #[test]
+    fn positive_load_failed_drops_waiters() {
+        let (peer_one, peer_two) = (peer_info(1), peer_info(2));
+        let request = RequestMessage::new(0, 0, 16 * 1024);
+        let mut requests = PeerRequests::new();
+
+        requests.request_received(peer_one, request);
+        requests.request_received(peer_two, request);
+        requests.next_load().unwrap();
+
+        assert_eq!(vec![peer_one, peer_two], requests.load_failed(peer_one.hash(), &request));
+        assert!(!requests.is_requested(peer_one.hash(), &request));
+
+        // Block is no longer considered loading, so a new request queues another load
+        assert!(requests.request_received(peer_one, request));
+        assert_eq!(Some((*peer_one.hash(), request)), requests.next_load());
+    }
+
+    #[test]
+    fn positive_finished_request_frees_peer_limit() {
+        let peer = peer_info(1);
+        let (request_one, request_two) = (RequestMessage::new(0, 0, 16 * 1024), RequestMessage::new(0, 16 * 1024, 16 * 1024));
+        let mut requests = PeerRequests::with_max_requests(1);
+
+        assert!(requests.request_received(peer, request_one));
+        requests.next_load().unwrap();
+        requests.block_loaded(peer.hash(), &request_one);
+
+        assert!(requests.request_received(peer, request_two));
+        assert!(requests.cancel_received(&peer, CancelMessage::new(0, 16 * 1024, 16 * 1024)));
+
+        assert!(requests.request_received(peer, request_one));
+    }
+
+    #[test]
+    fn negative_request_over_peer_limit() {
+        let (peer_one, peer_two) = (peer_info(1), peer_info(2));
+        let (request_one, request_two) = (RequestMessage::new(0, 0, 16 * 1024), RequestMessage::new(0, 16 * 1024, 16 * 1024));
+        let mut requests = PeerRequests::with_max_requests(1);
+
+        assert!(requests.request_received(peer_one, request_one));
+        assert!(!requests.request_received(peer_one, request_two));
+        assert!(!requests.is_requested(peer_one.hash(), &request_two));
+
+        // Limit is per peer
+        assert!(requests.request_received(peer_two, request_two));
+    }
+
+    #[test]
+    fn negative_duplicate_request_ignored() {
+        let peer = peer_info(1);
+        let request = RequestMessage::new(0, 0, 16 * 1024);
+        let mut requests = PeerRequests::new();
+
+        assert!(requests.request_received(peer, request));
+        assert!(!requests.request_received(peer, request));
+
+        requests.next_load().unwrap();
+        assert_eq!(vec![peer], requests.block_loaded(peer.hash(), &request));
+    }
+
+    #[test]
+    fn negative_cancel_without_request() {
+        let peer = peer_info(1);
+        let mut requests = PeerRequests::new();
+
+        assert!(!requests.cancel_received(&peer, CancelMessage::new(0, 0, 16 * 1024)));
+    }
+}
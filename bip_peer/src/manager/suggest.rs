@@ -0,0 +1,120 @@
+use std::collections::{HashMap, VecDeque};
+
+use message::SuggestPieceMessage;
+
+use bip_util::bt::InfoHash;
+
+/// Default number of hot pieces tracked for each torrent.
+const DEFAULT_HOT_PIECES: usize = 16;
+
+/// Strategy for emitting `SuggestPieceMessage`s for pieces we can serve cheaply.
+///
+/// Pieces that were recently loaded from disk are likely still sitting in a
+/// cache, so suggesting them to peers nudges those peers towards requests
+/// that we can satisfy with minimal IO. Clients should call `piece_loaded`
+/// whenever a block is loaded for a peer, and `suggestions` when deciding
+/// what to send to a peer that supports the Fast Extension.
+pub struct SuggestPieces {
+    max_pieces: usize,
+    hot_pieces: HashMap<InfoHash, VecDeque<u32>>
+}
+
+impl SuggestPieces {
+    /// Create a new `SuggestPieces` tracking a default number of hot pieces per torrent.
+    pub fn new() -> SuggestPieces {
+        SuggestPieces::with_max_pieces(DEFAULT_HOT_PIECES)
+    }
+
+    /// Create a new `SuggestPieces` tracking up to `max_pieces` hot pieces per torrent.
+    pub fn with_max_pieces(max_pieces: usize) -> SuggestPieces {
+        SuggestPieces{ max_pieces: max_pieces, hot_pieces: HashMap::new() }
+    }
+
+    /// Mark the given piece as recently loaded, making it the hottest piece for the torrent.
+    pub fn piece_loaded(&mut self, hash: InfoHash, piece_index: u32) {
+        if self.max_pieces == 0 {
+            return
+        }
+
+        let pieces = self.hot_pieces.entry(hash).or_insert_with(VecDeque::new);
+
+        if let Some(position) = pieces.iter().position(|&index| index == piece_index) {
+            pieces.remove(position);
+        }
+        pieces.push_front(piece_index);
+
+        pieces.truncate(self.max_pieces);
+    }
+
+    /// Stop tracking hot pieces for the given torrent.
+    pub fn remove_torrent(&mut self, hash: &InfoHash) {
+        self.hot_pieces.remove(hash);
+    }
+
+    /// Suggestions for a peer, hottest first, skipping pieces the peer already has.
+    pub fn suggestions<F>(&self, hash: &InfoHash, mut peer_has: F) -> Vec<SuggestPieceMessage>
+        where F: FnMut(u32) -> bool {
+        self.hot_pieces.get(hash)
+            .map(|pieces| {
+                pieces.iter()
+                    .filter(|&&index| !peer_has(index))
+                    .map(|&index| SuggestPieceMessage::new(index))
+                    .collect()
+            })
+            .unwrap_or(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SuggestPieces;
+    use message::SuggestPieceMessage;
+
+    use bip_util::bt::{self, InfoHash};
+
+    #[test]
+    fn positive_suggestions_hottest_first() {
+        let hash: InfoHash = [1u8; bt::INFO_HASH_LEN].into();
+        let mut suggest = SuggestPieces::new();
+
+        suggest.piece_loaded(hash, 3);
+        suggest.piece_loaded(hash, 5);
+        suggest.piece_loaded(hash, 3);
+
+        assert_eq!(vec![SuggestPieceMessage::new(3), SuggestPieceMessage::new(5)], suggest.suggestions(&hash, |_| false));
+    }
+
+    #[test]
+    fn positive_suggestions_skip_pieces_peer_has() {
+        let hash: InfoHash = [1u8; bt::INFO_HASH_LEN].into();
+        let mut suggest = SuggestPieces::new();
+
+        suggest.piece_loaded(hash, 3);
+        suggest.piece_loaded(hash, 5);
+
+        assert_eq!(vec![SuggestPieceMessage::new(3)], suggest.suggestions(&hash, |index| index == 5));
+    }
+
+    #[test]
+    fn positive_hot_pieces_bounded() {
+        let hash: InfoHash = [1u8; bt::INFO_HASH_LEN].into();
+        let mut suggest = SuggestPieces::with_max_pieces(2);
+
+        suggest.piece_loaded(hash, 1);
+        suggest.piece_loaded(hash, 2);
+        suggest.piece_loaded(hash, 3);
+
+        assert_eq!(vec![SuggestPieceMessage::new(3), SuggestPieceMessage::new(2)], suggest.suggestions(&hash, |_| false));
+    }
+
+    #[test]
+    fn negative_suggestions_unknown_torrent() {
+        let hash: InfoHash = [1u8; bt::INFO_HASH_LEN].into();
+        let mut suggest = SuggestPieces::new();
+
+        suggest.piece_loaded(hash, 1);
+        suggest.remove_torrent(&hash);
+
+        assert!(suggest.suggestions(&hash, |_| false).is_empty());
+    }
+}
@@ -1,19 +1,29 @@
 #![allow(deprecated)]
 
+use std::cmp;
 use std::io;
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
-use manager::builder::PeerManagerBuilder;
+use manager::builder::{PeerManagerBuilder, PeerConfig};
 use manager::peer_info::PeerInfo;
-use manager::future::{PersistentError, PersistentStream, RecurringTimeoutStream, RecurringTimeoutError};
-use manager::{IPeerManagerMessage, OPeerManagerMessage, ManagedMessage};
+use manager::future::{AdaptiveTimeoutStream, PersistentError, PersistentStream, RecurringTimeoutStream, RecurringTimeoutError, SharedStream};
+use manager::{IPeerManagerMessage, OPeerManagerMessage, ManagedMessage, MessageId};
 
 use tokio_core::reactor::Handle;
 use tokio_timer::{Timer};
-use futures::sync::mpsc::{self, Sender};
-use futures::stream::{Stream, MergedItem};
+use futures::sync::mpsc::{self, Sender, SendError};
+use futures::{Poll, Async, StartSend};
+use futures::stream::{Stream, Fuse, MergedItem, SplitSink, SplitStream};
 use futures::sink::Sink;
 use futures::future::{self, Loop, Future};
 
+const PEERS_CONNECTED_METRIC: &'static str = "bip_peer_peers_connected";
+// Control messages are handled as soon as the peer task is polled, so they dont need much room
+const CONTROL_BUFFER_CAPACITY: usize = 1;
+
 // Separated from MergedError to 
 enum PeerError {
     // We need to send a heartbeat (no messages sent from manager for a while)
@@ -38,15 +48,31 @@ enum MergedError<A, B, C> {
 //----------------------------------------------------------------------------//
 
 pub fn run_peer<P>(peer: P, info: PeerInfo, o_send: Sender<OPeerManagerMessage<P::Item>>,
-                   timer: Timer, builder: &PeerManagerBuilder, handle: &Handle) -> Sender<IPeerManagerMessage<P>>
+                   timer: Timer, builder: &PeerManagerBuilder, config: PeerConfig, handle: &Handle) -> PeerSender<P>
     where P: Stream<Error=io::Error> + Sink<SinkError=io::Error> + 'static,
           P::SinkItem: ManagedMessage,
           P::Item:     ManagedMessage {
-    let (m_send, m_recv) = mpsc::channel(builder.sink_buffer_capacity());
+    // Split the sink buffer between the manager channel and our local queue, so together they hold the configured capacity
+    let queue_capacity = builder.sink_buffer_capacity() - builder.sink_buffer_capacity() / 2;
+    let (m_send, m_recv) = mpsc::channel(builder.sink_buffer_capacity() / 2);
+    let (c_send, c_recv) = mpsc::channel(CONTROL_BUFFER_CAPACITY);
     let (p_send, p_recv) = peer.split();
 
-    // Build a stream that will timeout if no message is sent for heartbeat_timeout and teardown (dont preserve) the underlying stream
-    let p_stream = timer.timeout_stream(PersistentStream::new(p_recv), builder.heartbeat_timeout())
+    // Queue up messages from the manager locally, so that they can be purged before being written
+    let purged = Rc::new(RefCell::new(VecDeque::new()));
+    let queued = Rc::new(Cell::new(0));
+    // Ids of sent messages waiting to be acknowledged together
+    let unacked = Rc::new(RefCell::new(Vec::new()));
+    let ack_window = builder.ack_window();
+    let buffer_pool = builder.buffer_pool();
+    let m_recv = PurgeableQueue::new(m_recv, c_recv, queue_capacity, purged.clone(), queued.clone());
+
+    // Shared so that we can swap out the peer, after our stream has been merged
+    let p_recv_slot = Rc::new(RefCell::new(p_recv));
+
+    // Build a stream that will timeout if no message is sent for heartbeat_timeout (possibly adapted to the peer) and teardown (dont preserve) the underlying stream
+    let p_stream = AdaptiveTimeoutStream::new(PersistentStream::new(SharedStream::new(p_recv_slot.clone())), timer.clone(), config.heartbeat_timeout(),
+                                              config.adaptive_heartbeat_timeout())
         .map_err(|error| {
             match error {
                 PersistentError::Disconnect   => PeerError::PeerDisconnect,
@@ -54,8 +80,22 @@ pub fn run_peer<P>(peer: P, info: PeerInfo, o_send: Sender<OPeerManagerMessage<P
                 PersistentError::IoError(err) => PeerError::PeerError(err)
             }   
         });
+
+    // Disconnect peers that flood us with keep alive messages
+    let mut keep_alive_limiter = config.keep_alive_limit().map(|(max, window)| KeepAliveLimiter::new(max, window));
+    let p_stream = p_stream.and_then(move |message| {
+        let exceeded = message.is_keep_alive() && keep_alive_limiter.as_mut()
+            .map(|limiter| limiter.record(Instant::now()))
+            .unwrap_or(false);
+
+        if exceeded {
+            Err(PeerError::PeerError(io::Error::new(io::ErrorKind::InvalidData, "Peer Exceeded Keep Alive Limit")))
+        } else {
+            Ok(message)
+        }
+    });
     // Build a stream that will notify us of no message is sent for heartbeat_interval and done teartdown (preserve) the underlying stream
-    let m_stream = RecurringTimeoutStream::new(m_recv, timer, builder.heartbeat_interval())
+    let m_stream = RecurringTimeoutStream::new(m_recv, timer, config.heartbeat_interval())
         .map_err(|error| {
             match error {
                 RecurringTimeoutError::Disconnect => PeerError::ManagerDisconnect,
@@ -64,14 +104,27 @@ pub fn run_peer<P>(peer: P, info: PeerInfo, o_send: Sender<OPeerManagerMessage<P
         });
 
     let merged_stream = m_stream.merge(p_stream);
+    let metrics = builder.metrics();
+    let protocol_stats = config.protocol_stats().clone();
 
     handle.spawn(o_send.send(OPeerManagerMessage::PeerAdded(info)).map_err(|_| ()).and_then(move |o_send| {
-        future::loop_fn((merged_stream, o_send, p_send, info), |(merged_stream, o_send, p_send, info)| {
+        metrics.gauge(PEERS_CONNECTED_METRIC, 1);
+
+        future::loop_fn((merged_stream, o_send, p_send, info), move |(merged_stream, o_send, p_send, info)| {
+            let p_recv_slot = p_recv_slot.clone();
+            let protocol_stats = protocol_stats.clone();
+            let final_stats = protocol_stats.clone();
+            let (purged, queued) = (purged.clone(), queued.clone());
+            let (unacked, still_queued) = (unacked.clone(), queued.clone());
+            let buffer_pool = buffer_pool.clone();
+
             // Our return tuple takes the form (merged_stream, Option<Send Message>, Option<Recv Message>, Option<Send To Manager Message>, is_good) where each stage (A, B, C),
             // will execute one of those options (if present), since each future transform can only execute a single future and we have 2^3 possible combintations
             // (Some or None = 2)^(3 Options = 3)
             merged_stream.into_future()
                 .then(move |result| {
+                    let mut p_send = p_send;
+
                     let result = match result {
                         Ok((Some(MergedItem::First(
                             IPeerManagerMessage::SendMessage(p_info, mid, p_message))),
@@ -81,6 +134,30 @@ pub fn run_peer<P>(peer: P, info: PeerInfo, o_send: Sender<OPeerManagerMessage<P
                             IPeerManagerMessage::RemovePeer(p_info))),
                             merged_stream
                         ))                                                              => Ok((merged_stream, None, None, Some(OPeerManagerMessage::PeerRemoved(p_info)), false)),
+                        Ok((Some(MergedItem::First(
+                            IPeerManagerMessage::ReplacePeer(_, peer))),
+                            merged_stream
+                        ))                                                              => {
+                            p_send = replace_peer(peer, &p_recv_slot);
+
+                            Ok((merged_stream, None, None, None, true))
+                        },
+                        Ok((Some(MergedItem::First(
+                            IPeerManagerMessage::QueryStats(p_info))),
+                            merged_stream
+                        ))                                                              => Ok((merged_stream, None, None, Some(OPeerManagerMessage::PeerStats(p_info, protocol_stats)), true)),
+                        Ok((Some(MergedItem::First(
+                            IPeerManagerMessage::PurgeQueued(p_info, _))),
+                            merged_stream
+                        ))                                                              => {
+                            let mids = purged.borrow_mut().pop_front().unwrap_or_else(Vec::new);
+
+                            Ok((merged_stream, None, None, Some(OPeerManagerMessage::PurgedMessages(p_info, mids)), true))
+                        },
+                        Ok((Some(MergedItem::First(
+                            IPeerManagerMessage::QueryQueued(p_info))),
+                            merged_stream
+                        ))                                                              => Ok((merged_stream, None, None, Some(OPeerManagerMessage::PeerQueued(p_info, queued.get())), true)),
                         Ok((Some(MergedItem::Second(
                             peer_message)),
                             merged_stream
@@ -95,6 +172,34 @@ pub fn run_peer<P>(peer: P, info: PeerInfo, o_send: Sender<OPeerManagerMessage<P
                             peer_message)),
                             merged_stream
                         ))                                                               => Ok((merged_stream, None, Some(peer_message), Some(OPeerManagerMessage::PeerRemoved(p_info)), false)),
+                        Ok((Some(MergedItem::Both(
+                            IPeerManagerMessage::ReplacePeer(_, peer),
+                            peer_message)),
+                            merged_stream
+                        ))                                                               => {
+                            p_send = replace_peer(peer, &p_recv_slot);
+
+                            Ok((merged_stream, None, Some(peer_message), None, true))
+                        },
+                        Ok((Some(MergedItem::Both(
+                            IPeerManagerMessage::QueryStats(p_info),
+                            peer_message)),
+                            merged_stream
+                        ))                                                               => Ok((merged_stream, None, Some(peer_message), Some(OPeerManagerMessage::PeerStats(p_info, protocol_stats)), true)),
+                        Ok((Some(MergedItem::Both(
+                            IPeerManagerMessage::PurgeQueued(p_info, _),
+                            peer_message)),
+                            merged_stream
+                        ))                                                               => {
+                            let mids = purged.borrow_mut().pop_front().unwrap_or_else(Vec::new);
+
+                            Ok((merged_stream, None, Some(peer_message), Some(OPeerManagerMessage::PurgedMessages(p_info, mids)), true))
+                        },
+                        Ok((Some(MergedItem::Both(
+                            IPeerManagerMessage::QueryQueued(p_info),
+                            peer_message)),
+                            merged_stream
+                        ))                                                               => Ok((merged_stream, None, Some(peer_message), Some(OPeerManagerMessage::PeerQueued(p_info, queued.get())), true)),
                         Ok((Some(_), _))                                                 => panic!("bip_peer: Peer Future Received Invalid Message From Peer Manager"),
                         Err((PeerError::ManagerHeartbeatInterval, merged_stream))        => Ok((merged_stream, Some(P::SinkItem::keep_alive()), None, None, true)),
                         // In this case, the manager and peer probably both disconnected at the same time? Treat as a manager disconnect.
@@ -107,7 +212,13 @@ pub fn run_peer<P>(peer: P, info: PeerInfo, o_send: Sender<OPeerManagerMessage<P
 
                     match result {
                         Ok((merged_stream, opt_send, opt_recv, opt_ack, is_good)) => {
+                            // Unlike send_all, send flushes the message without closing the peer sink
                             if let Some(send) = opt_send {
+                                // Pool holds on to the payload until the codec (and everyone else) is done with it
+                                if let (Some(pool), Some(payload)) = (buffer_pool.as_ref(), send.block_payload()) {
+                                    pool.recycle(payload);
+                                }
+
                                 Ok(p_send.send(send)
                                     .map_err(|_| MergedError::Peer(PeerError::PeerDisconnect))
                                     .and_then(move |p_send| Err(MergedError::StageOne((merged_stream, o_send, p_send, info, opt_recv, opt_ack, is_good)))))
@@ -137,16 +248,45 @@ pub fn run_peer<P>(peer: P, info: PeerInfo, o_send: Sender<OPeerManagerMessage<P
                     }
                 })
                 .flatten()
-                .or_else(|error| {
+                .or_else(move |error| {
                     match error {
                         MergedError::StageTwo((merged_stream, o_send, p_send, info, opt_ack, is_good)) => {
-                            if let Some(ack) = opt_ack {
-                                Ok(o_send.send(ack)
-                                    .map_err(|_| MergedError::Peer(PeerError::ManagerDisconnect))
-                                    .and_then(move |o_send| Err(MergedError::StageThree((merged_stream, o_send, p_send, info, is_good)))))
-                            } else {
-                                Err(MergedError::StageThree((merged_stream, o_send, p_send, info, is_good)))
+                            let mut acks = Vec::new();
+                            {
+                                let mut unacked = unacked.borrow_mut();
+
+                                match (opt_ack, ack_window) {
+                                    (Some(OPeerManagerMessage::SentMessage(_, mid)), Some(window)) => {
+                                        unacked.push(mid);
+
+                                        // Hold off while more messages are queued up, so they are acknowledged together
+                                        if still_queued.get() == 0 || unacked.len() >= window {
+                                            acks.push(OPeerManagerMessage::SentMessages(info, unacked.drain(..).collect()));
+                                        }
+                                    },
+                                    (opt_ack, _) => {
+                                        // Anything reported after messages were sent should be received after their acknowledgement
+                                        if !unacked.is_empty() {
+                                            acks.push(OPeerManagerMessage::SentMessages(info, unacked.drain(..).collect()));
+                                        }
+
+                                        // Final statistics go out right before the peer is removed, however it was removed, so they are never lost
+                                        if !is_good {
+                                            acks.push(OPeerManagerMessage::PeerStats(info, final_stats));
+                                        }
+                                        acks.extend(opt_ack);
+                                    }
+                                }
                             }
+
+                            Ok(future::loop_fn((o_send, acks.into_iter()), |(o_send, mut acks)| {
+                                match acks.next() {
+                                    Some(ack) => future::Either::A(o_send.send(ack).map(move |o_send| Loop::Continue((o_send, acks)))),
+                                    None      => future::Either::B(future::ok(Loop::Break(o_send)))
+                                }
+                            })
+                            .map_err(|_| MergedError::Peer(PeerError::ManagerDisconnect))
+                            .and_then(move |o_send| Err(MergedError::StageThree((merged_stream, o_send, p_send, info, is_good)))))
                         },
                         err => Err(err)
                     }
@@ -168,7 +308,193 @@ pub fn run_peer<P>(peer: P, info: PeerInfo, o_send: Sender<OPeerManagerMessage<P
                     }
                 })
         })
+        .then(move |result| {
+            metrics.gauge(PEERS_CONNECTED_METRIC, -1);
+
+            result
+        })
     }));
 
-    m_send
-}
\ No newline at end of file
+    PeerSender{ data: m_send, control: c_send }
+}
+
+//----------------------------------------------------------------------------//
+
+/// Sink for sending messages to a peer task.
+///
+/// Purge and query messages are sent over their own channel, so that they are not
+/// stuck behind messages waiting to get in to the (full) local queue of the peer.
+pub struct PeerSender<P> where P: Sink {
+    data:    Sender<IPeerManagerMessage<P>>,
+    control: Sender<IPeerManagerMessage<P>>
+}
+
+impl<P> Sink for PeerSender<P> where P: Sink {
+    type SinkItem = IPeerManagerMessage<P>;
+    type SinkError = SendError<IPeerManagerMessage<P>>;
+
+    fn start_send(&mut self, item: IPeerManagerMessage<P>) -> StartSend<IPeerManagerMessage<P>, Self::SinkError> {
+        match item {
+            IPeerManagerMessage::PurgeQueued(..) |
+            IPeerManagerMessage::QueryQueued(..) => self.control.start_send(item),
+            _                                    => self.data.start_send(item)
+        }
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        let control = try!(self.control.poll_complete());
+        let data = try!(self.data.poll_complete());
+
+        match (control, data) {
+            (Async::Ready(()), Async::Ready(())) => Ok(Async::Ready(())),
+            _                                    => Ok(Async::NotReady)
+        }
+    }
+}
+
+/// Counts keep alive messages received from a peer within a fixed window.
+struct KeepAliveLimiter {
+    max:          usize,
+    window:       Duration,
+    window_start: Option<Instant>,
+    count:        usize
+}
+
+impl KeepAliveLimiter {
+    fn new(max: usize, window: Duration) -> KeepAliveLimiter {
+        KeepAliveLimiter{ max: max, window: window, window_start: None, count: 0 }
+    }
+
+    /// Record a keep alive received at the given time, returning true if the limit was exceeded.
+    fn record(&mut self, now: Instant) -> bool {
+        let window_expired = self.window_start
+            .map(|start| now.duration_since(start) >= self.window)
+            .unwrap_or(true);
+
+        if window_expired {
+            self.window_start = Some(now);
+            self.count = 0;
+        }
+        self.count += 1;
+
+        self.count > self.max
+    }
+}
+
+/// Stream of messages from the manager, which drains messages that are ready (up to capacity) into a local
+/// queue, so that queued messages can be purged before they are written to the peer.
+///
+/// Purge and query messages come from a separate control stream, which is always drained, so they are
+/// never stuck behind a full local queue. They skip ahead of the local queue, since they are about the queue itself.
+struct PurgeableQueue<S, C, P> where P: Sink {
+    stream:   Fuse<S>,
+    controls: Fuse<C>,
+    capacity: usize,
+    queue:    VecDeque<IPeerManagerMessage<P>>,
+    control:  VecDeque<IPeerManagerMessage<P>>,
+    purged:   Rc<RefCell<VecDeque<Vec<MessageId>>>>,
+    queued:   Rc<Cell<usize>>
+}
+
+impl<S, C, P> PurgeableQueue<S, C, P> where S: Stream, C: Stream, P: Sink {
+    fn new(stream: S, controls: C, capacity: usize, purged: Rc<RefCell<VecDeque<Vec<MessageId>>>>, queued: Rc<Cell<usize>>) -> PurgeableQueue<S, C, P> {
+        // Always need room for at least one message, otherwise we would never poll the manager
+        PurgeableQueue{ stream: stream.fuse(), controls: controls.fuse(), capacity: cmp::max(capacity, 1), queue: VecDeque::new(),
+                        control: VecDeque::new(), purged: purged, queued: queued }
+    }
+
+    fn push_message(&mut self, message: IPeerManagerMessage<P>) {
+        match message {
+            IPeerManagerMessage::PurgeQueued(info, predicate) => {
+                let mids = purge_queue(&mut self.queue, &*predicate);
+                self.purged.borrow_mut().push_back(mids);
+
+                self.control.push_back(IPeerManagerMessage::PurgeQueued(info, predicate));
+            },
+            IPeerManagerMessage::QueryQueued(info) => self.control.push_back(IPeerManagerMessage::QueryQueued(info)),
+            message                                => self.queue.push_back(message)
+        }
+    }
+}
+
+impl<S, C, P> Stream for PurgeableQueue<S, C, P>
+    where S: Stream<Item=IPeerManagerMessage<P>>,
+          C: Stream<Item=IPeerManagerMessage<P>, Error=S::Error>,
+          P: Sink {
+    type Item = IPeerManagerMessage<P>;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<IPeerManagerMessage<P>>, S::Error> {
+        // Leave anything past our capacity in the manager channel, so it can apply backpressure
+        while self.queue.len() < self.capacity {
+            match try!(self.stream.poll()) {
+                Async::Ready(Some(message))          => self.push_message(message),
+                Async::Ready(None) | Async::NotReady => break
+            }
+        }
+
+        // Control messages never wait on the capacity of the local queue, but they do apply to anything
+        // still waiting in the manager channel (which is bounded), so pull that in first
+        while let Async::Ready(Some(message)) = try!(self.controls.poll()) {
+            while let Async::Ready(Some(message)) = try!(self.stream.poll()) {
+                self.push_message(message);
+            }
+
+            self.push_message(message);
+        }
+
+        let opt_message = self.control.pop_front().or_else(|| self.queue.pop_front());
+        self.queued.set(queued_messages(&self.queue));
+
+        match opt_message {
+            Some(message)                 => Ok(Async::Ready(Some(message))),
+            None if self.stream.is_done() => Ok(Async::Ready(None)),
+            None                          => Ok(Async::NotReady)
+        }
+    }
+}
+
+/// Drop messages in the queue matching the given predicate, returning the ids of messages that were dropped.
+fn purge_queue<P>(queue: &mut VecDeque<IPeerManagerMessage<P>>, predicate: &Fn(&P::SinkItem) -> bool) -> Vec<MessageId>
+    where P: Sink {
+    let mut mids = Vec::new();
+    let mut kept = VecDeque::with_capacity(queue.len());
+
+    for message in queue.drain(..) {
+        match message {
+            IPeerManagerMessage::SendMessage(info, mid, peer_message) => {
+                if predicate(&peer_message) {
+                    mids.push(mid);
+                } else {
+                    kept.push_back(IPeerManagerMessage::SendMessage(info, mid, peer_message));
+                }
+            },
+            other => kept.push_back(other)
+        }
+    }
+    *queue = kept;
+
+    mids
+}
+
+/// Number of peer messages waiting to be written in the queue.
+fn queued_messages<P>(queue: &VecDeque<IPeerManagerMessage<P>>) -> usize
+    where P: Sink {
+    queue.iter()
+        .map(|message| {
+            match message {
+                &IPeerManagerMessage::SendMessage(..) => 1,
+                _                                     => 0
+            }
+        })
+        .sum()
+}
+
+/// Split the given peer, swapping in its stream and returning its sink.
+fn replace_peer<P>(peer: P, p_recv_slot: &Rc<RefCell<SplitStream<P>>>) -> SplitSink<P>
+    where P: Stream + Sink {
+    let (p_send, p_recv) = peer.split();
+    *p_recv_slot.borrow_mut() = p_recv;
+
+    p_send
+}
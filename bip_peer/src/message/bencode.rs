@@ -30,6 +30,7 @@ pub const CLIENT_IPV6_ADDR_KEY:    &'static [u8] = b"ipv6";
 pub const CLIENT_IPV4_ADDR_KEY:    &'static [u8] = b"ipv4";
 pub const CLIENT_MAX_REQUESTS_KEY: &'static [u8] = b"reqq";
 pub const METADATA_SIZE_KEY:       &'static [u8] = b"metadata_size";
+pub const UPLOAD_ONLY_KEY:         &'static [u8] = b"upload_only";
 
 pub fn parse_id_map<K, V>(root: &BDictAccess<K, V>) -> HashMap<ExtendedType, u8>
     where V: BRefAccess, V::BKey: AsRef<[u8]> {
@@ -120,6 +121,13 @@ pub fn parse_metadata_size<K, V>(root: &BDictAccess<K, V>) -> Option<i64>
         .ok()
 }
 
+pub fn parse_upload_only<K, V>(root: &BDictAccess<K, V>) -> Option<bool>
+    where V: BRefAccess {
+    CONVERT.lookup_and_convert_int(root, UPLOAD_ONLY_KEY)
+        .ok()
+        .map(|upload_only| upload_only != 0)
+}
+
 fn parse_ipv4_addr(ipv4_bytes: &[u8]) -> Ipv4Addr {
     convert::bytes_be_to_ipv4([ipv4_bytes[0], ipv4_bytes[1], ipv4_bytes[2], ipv4_bytes[3]])
 }
@@ -24,6 +24,7 @@ pub struct ExtendedMessageBuilder {
     our_ipv4_addr:    Option<Ipv4Addr>,
     our_max_requests: Option<i64>,
     metadata_size:    Option<i64>,
+    upload_only:      Option<bool>,
     custom_entries:   HashMap<String, BencodeMut<'static>>
 }
 
@@ -31,7 +32,7 @@ impl ExtendedMessageBuilder {
     /// Create a new `ExtendedMessageBuilder`.
     pub fn new() -> ExtendedMessageBuilder {
         ExtendedMessageBuilder{ id_map: HashMap::new(), our_id: None, our_tcp_port: None, their_ip: None, our_ipv6_addr: None,
-            our_ipv4_addr: None, our_max_requests: None, metadata_size: None, custom_entries: HashMap::new() }
+            our_ipv4_addr: None, our_max_requests: None, metadata_size: None, upload_only: None, custom_entries: HashMap::new() }
     }
 
     /// Set our client identification in the message.
@@ -86,6 +87,12 @@ impl ExtendedMessageBuilder {
         self
     }
 
+    /// Set whether or not we are only uploading (see `http://www.bittorrent.org/beps/bep_0021.html`).
+    pub fn with_upload_only(mut self, upload_only: Option<bool>) -> ExtendedMessageBuilder {
+        self.upload_only = upload_only;
+        self
+    }
+
     /// Set a custom entry in the message with the given dictionary key.
     pub fn with_custom_entry(mut self, key: String, opt_value: Option<BencodeMut<'static>>) -> ExtendedMessageBuilder {
         if let Some(value) = opt_value {
@@ -151,6 +158,8 @@ fn bencode_from_builder(builder: &ExtendedMessageBuilder, mut custom_entries: Ha
             .map(|client_max_requests| root_map_access.insert(bencode::CLIENT_MAX_REQUESTS_KEY.into(), ben_int!(client_max_requests)));
         builder.metadata_size
             .map(|metadata_size| root_map_access.insert(bencode::METADATA_SIZE_KEY.into(), ben_int!(metadata_size)));
+        builder.upload_only
+            .map(|upload_only| root_map_access.insert(bencode::UPLOAD_ONLY_KEY.into(), ben_int!(upload_only as i64)));
     }
     
     root_map.encode()
@@ -208,6 +217,7 @@ pub struct ExtendedMessage {
     our_ipv4_addr:    Option<Ipv4Addr>,
     our_max_requests: Option<i64>,
     metadata_size:    Option<i64>,
+    upload_only:      Option<bool>,
     raw_bencode:      Bytes
 }
 
@@ -223,12 +233,15 @@ impl ExtendedMessage {
 
         ExtendedMessage{ id_map: builder.id_map, our_id: builder.our_id, our_tcp_port: builder.our_tcp_port, their_ip: builder.their_ip,
             our_ipv6_addr: builder.our_ipv6_addr, our_ipv4_addr: builder.our_ipv4_addr, our_max_requests: builder.our_max_requests,
-            metadata_size: builder.metadata_size, raw_bencode: raw_bencode.freeze() }
+            metadata_size: builder.metadata_size, upload_only: builder.upload_only, raw_bencode: raw_bencode.freeze() }
     }
     
     /// Parse an `ExtendedMessage` from some raw bencode of the given length.
     pub fn parse_bytes(_input: (), mut bytes: Bytes, len: u32) -> IResult<(), io::Result<ExtendedMessage>> {
-        let cast_len = message::u32_to_usize(len);
+        let cast_len = match message::u32_to_usize(len) {
+            Ok(cast_len) => cast_len,
+            Err(err)     => return IResult::Done((), Err(err))
+        };
         
         if bytes.len() >= cast_len {
             let raw_bencode = bytes.split_to(cast_len);
@@ -247,10 +260,11 @@ impl ExtendedMessage {
                     let our_ipv4_addr = bencode::parse_client_ipv4_addr(ben_dict);
                     let our_max_requests = bencode::parse_client_max_requests(ben_dict);
                     let metadata_size = bencode::parse_metadata_size(ben_dict);
+                    let upload_only = bencode::parse_upload_only(ben_dict);
 
                     Ok(ExtendedMessage{ id_map: id_map, our_id: our_id, our_tcp_port: our_tcp_port, their_ip: their_ip,
                         our_ipv6_addr: our_ipv6_addr, our_ipv4_addr: our_ipv4_addr, our_max_requests: our_max_requests,
-                        metadata_size: metadata_size, raw_bencode: clone_raw_bencode })
+                        metadata_size: metadata_size, upload_only: upload_only, raw_bencode: clone_raw_bencode })
                 });
                 
             IResult::Done((), res_extended_message)
@@ -295,6 +309,13 @@ impl ExtendedMessage {
         self.their_ip
     }
 
+    /// Retrieve the ip address that the sender sees us as (the `yourip` entry) from the message.
+    ///
+    /// Same as `their_ip`, but named from the perspective of the receiver of the message.
+    pub fn your_ip(&self) -> Option<IpAddr> {
+        self.their_ip
+    }
+
     /// Retrieve our ipv6 address from the message.
     pub fn our_ipv6_addr(&self) -> Option<Ipv6Addr> {
         self.our_ipv6_addr
@@ -315,9 +336,48 @@ impl ExtendedMessage {
         self.metadata_size
     }
 
+    /// Retrieve whether or not the sender is only uploading from the message.
+    pub fn upload_only(&self) -> Option<bool> {
+        self.upload_only
+    }
+
     /// Retrieve a raw `BencodeRef` representing the current message.
     pub fn bencode_ref<'a>(&'a self) -> BencodeRef<'a> {
         // We already verified that this is valid bencode
         BencodeRef::decode(&*self.raw_bencode, BDecodeOpt::default()).unwrap()
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExtendedMessage, ExtendedMessageBuilder};
+
+    use bytes::Bytes;
+    use nom::IResult;
+
+    fn round_trip(message: ExtendedMessage) -> ExtendedMessage {
+        let bytes = Bytes::from(message.bencode_ref().buffer());
+        let len = bytes.len() as u32;
+
+        match ExtendedMessage::parse_bytes((), bytes, len) {
+            IResult::Done(_, Ok(parsed)) => parsed,
+            _                            => panic!("Failed To Parse ExtendedMessage")
+        }
+    }
+
+    #[test]
+    fn positive_upload_only_round_trip() {
+        let message = ExtendedMessageBuilder::new()
+            .with_upload_only(Some(true))
+            .build();
+
+        assert_eq!(Some(true), round_trip(message).upload_only());
+    }
+
+    #[test]
+    fn positive_upload_only_not_set() {
+        let message = ExtendedMessageBuilder::new().build();
+
+        assert_eq!(None, round_trip(message).upload_only());
+    }
 }
\ No newline at end of file
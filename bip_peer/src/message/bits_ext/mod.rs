@@ -12,18 +12,22 @@ use message;
 use message::bencode;
 
 const PORT_MESSAGE_LEN:          u32 = 3;
+const SUGGEST_PIECE_MESSAGE_LEN: u32 = 5;
 const BASE_EXTENDED_MESSAGE_LEN: u32 = 6;
 
-const PORT_MESSAGE_ID:         u8 = 9;
+pub const PORT_MESSAGE_ID:          u8 = 9;
+pub const SUGGEST_PIECE_MESSAGE_ID: u8 = 13;
 pub const EXTENDED_MESSAGE_ID: u8 = 20;
 
 const EXTENDED_MESSAGE_HANDSHAKE_ID: u8 = 0;
 
 mod handshake;
 mod port;
+mod suggest;
 
 pub use self::handshake::{ExtendedType, ExtendedMessage, ExtendedMessageBuilder};
 pub use self::port::PortMessage;
+pub use self::suggest::SuggestPieceMessage;
 
 /// Enumeration of messages for `PeerWireProtocolMessage`, activated via `Extensions` bits.
 ///
@@ -32,6 +36,8 @@ pub use self::port::PortMessage;
 pub enum BitsExtensionMessage {
     /// Messsage for determining the port a peer's DHT is listening on.
     Port(PortMessage),
+    /// Message for suggesting a piece that we can cheaply serve (Fast Extension).
+    SuggestPiece(SuggestPieceMessage),
     /// Message for sending a peer the map of extensions we support.
     Extended(ExtendedMessage)
 
@@ -47,6 +53,7 @@ impl BitsExtensionMessage {
     {
         match self {
             &BitsExtensionMessage::Port(msg)         => msg.write_bytes(writer),
+            &BitsExtensionMessage::SuggestPiece(msg) => msg.write_bytes(writer),
             &BitsExtensionMessage::Extended(ref msg) => msg.write_bytes(writer)
         }
     }
@@ -54,6 +61,7 @@ impl BitsExtensionMessage {
     pub fn message_size(&self) -> usize {
         match self {
             &BitsExtensionMessage::Port(_)           => PORT_MESSAGE_LEN as usize,
+            &BitsExtensionMessage::SuggestPiece(_)   => SUGGEST_PIECE_MESSAGE_LEN as usize,
             &BitsExtensionMessage::Extended(ref msg) => BASE_EXTENDED_MESSAGE_LEN as usize + msg.bencode_size()
         }
     }
@@ -71,10 +79,18 @@ fn parse_extension(mut bytes: Bytes) -> IResult<(), io::Result<BitsExtensionMess
                 )
             )
         ) |
+        ignore_input!(
+            switch!(header_bytes.as_ref(), throwaway_input!(tuple!(be_u32, be_u8)),
+                (SUGGEST_PIECE_MESSAGE_LEN, SUGGEST_PIECE_MESSAGE_ID) => map!(
+                    call!(SuggestPieceMessage::parse_bytes, bytes.split_off(message::HEADER_LEN)),
+                    |res_suggest| res_suggest.map(|suggest| BitsExtensionMessage::SuggestPiece(suggest))
+                )
+            )
+        ) |
         ignore_input!(
             switch!(header_bytes.as_ref(), throwaway_input!(tuple!(be_u32, be_u8, be_u8)),
                 (message_len, EXTENDED_MESSAGE_ID, EXTENDED_MESSAGE_HANDSHAKE_ID) => map!(
-                    call!(ExtendedMessage::parse_bytes, bytes.split_off(message::HEADER_LEN + 1), message_len - 2),
+                    call!(ExtendedMessage::parse_bytes, bytes.split_off(message::HEADER_LEN + 1), message_len.saturating_sub(2)),
                     |res_extended| res_extended.map(|extended| BitsExtensionMessage::Extended(extended))
                 )
             )
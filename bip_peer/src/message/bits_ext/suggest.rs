@@ -0,0 +1,77 @@
+use bytes::Bytes;
+use std::io::Write;
+use std::io;
+use nom::be_u32;
+use bytes::BigEndian;
+use nom::IResult;
+use message::bits_ext;
+use message;
+use byteorder::WriteBytesExt;
+
+/// Message for suggesting a piece that a peer should request from us.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct SuggestPieceMessage {
+    piece_index: u32,
+}
+
+impl SuggestPieceMessage {
+    pub fn new(piece_index: u32) -> SuggestPieceMessage {
+        SuggestPieceMessage { piece_index: piece_index }
+    }
+
+    pub fn parse_bytes(_input: (), bytes: Bytes) -> IResult<(), io::Result<SuggestPieceMessage>> {
+        match parse_suggest(bytes.as_ref()) {
+            IResult::Done(_, result)  => IResult::Done((), Ok(result)),
+            IResult::Error(err)       => IResult::Error(err),
+            IResult::Incomplete(need) => IResult::Incomplete(need)
+        }
+    }
+
+    pub fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
+        where W: Write
+    {
+        try!(message::write_length_id_pair(&mut writer, bits_ext::SUGGEST_PIECE_MESSAGE_LEN, Some(bits_ext::SUGGEST_PIECE_MESSAGE_ID)));
+
+        writer.write_u32::<BigEndian>(self.piece_index)
+    }
+
+    pub fn piece_index(&self) -> u32 {
+        self.piece_index
+    }
+}
+
+fn parse_suggest(bytes: &[u8]) -> IResult<&[u8], SuggestPieceMessage> {
+    map!(bytes, be_u32, |piece_index| SuggestPieceMessage::new(piece_index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SuggestPieceMessage;
+    use message::bits_ext::BitsExtensionMessage;
+
+    use bytes::Bytes;
+    use nom::IResult;
+
+    #[test]
+    fn positive_parse_suggest_piece() {
+        let bytes = Bytes::from(&[0, 0, 0, 5, 13, 0, 0, 1, 0][..]);
+
+        match BitsExtensionMessage::parse_bytes((), bytes) {
+            IResult::Done(_, Ok(BitsExtensionMessage::SuggestPiece(msg))) => assert_eq!(SuggestPieceMessage::new(256), msg),
+            _                                                            => panic!("bip_peer: Failed To Parse SuggestPiece Message")
+        }
+    }
+
+    #[test]
+    fn positive_suggest_piece_round_trip() {
+        let message = BitsExtensionMessage::SuggestPiece(SuggestPieceMessage::new(5));
+        let mut bytes = Vec::new();
+        message.write_bytes(&mut bytes).unwrap();
+
+        assert_eq!(message.message_size() + 4, bytes.len());
+        match BitsExtensionMessage::parse_bytes((), Bytes::from(bytes)) {
+            IResult::Done(_, Ok(parsed)) => assert_eq!(message, parsed),
+            _                            => panic!("bip_peer: Failed To Parse SuggestPiece Message")
+        }
+    }
+}
@@ -0,0 +1,483 @@
+use std::cmp;
+use std::io::{self, Write};
+
+use bytes::Bytes;
+use byteorder::{WriteBytesExt, BigEndian};
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use nom::{IResult, be_u32, Needed};
+
+use message;
+
+/// Length of a merkle tree hash (SHA-256).
+pub const MERKLE_HASH_LEN: usize = 32;
+
+/// Hash of a node in the merkle tree of a file.
+pub type MerkleHash = [u8; MERKLE_HASH_LEN];
+
+/// Fields shared by all hash transfer messages.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+struct HashHeader {
+    pieces_root:  MerkleHash,
+    base_layer:   u32,
+    index:        u32,
+    length:       u32,
+    proof_layers: u32
+}
+
+impl HashHeader {
+    fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
+        where W: Write
+    {
+        try!(writer.write_all(&self.pieces_root));
+        try!(writer.write_u32::<BigEndian>(self.base_layer));
+        try!(writer.write_u32::<BigEndian>(self.index));
+        try!(writer.write_u32::<BigEndian>(self.length));
+        writer.write_u32::<BigEndian>(self.proof_layers)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.length >= 2 && self.length.is_power_of_two() && self.index % self.length == 0
+    }
+}
+
+fn parse_hash_header(bytes: &[u8]) -> IResult<&[u8], HashHeader> {
+    do_parse!(bytes,
+        pieces_root:  take!(MERKLE_HASH_LEN) >>
+        base_layer:   be_u32                 >>
+        index:        be_u32                 >>
+        length:       be_u32                 >>
+        proof_layers: be_u32                 >>
+        (HashHeader{ pieces_root: slice_to_hash(pieces_root), base_layer: base_layer, index: index,
+                     length: length, proof_layers: proof_layers })
+    )
+}
+
+fn slice_to_hash(bytes: &[u8]) -> MerkleHash {
+    let mut hash = [0u8; MERKLE_HASH_LEN];
+    hash.copy_from_slice(bytes);
+
+    hash
+}
+
+/// Hash two child nodes in the merkle tree together to get the parent node.
+fn hash_pair(left: &MerkleHash, right: &MerkleHash) -> MerkleHash {
+    let mut hasher = Sha256::new();
+    let mut parent = [0u8; MERKLE_HASH_LEN];
+
+    hasher.input(left);
+    hasher.input(right);
+    hasher.result(&mut parent);
+
+    parent
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Message for requesting a range of hashes from a layer of the merkle tree for a file.
+///
+/// The `base_layer` is the layer of the tree (where 0 is the leaf layer of 16 KiB blocks) that
+/// the `length` hashes, starting at `index`, are requested from. The `proof_layers` are the number
+/// of ancestor layers for which the uncle hashes should be included so that the hashes can be
+/// verified against the `pieces_root`.
+///
+/// See `http://www.bittorrent.org/beps/bep_0052.html`.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct HashRequestMessage {
+    header: HashHeader
+}
+
+impl HashRequestMessage {
+    pub fn new(pieces_root: MerkleHash, base_layer: u32, index: u32, length: u32, proof_layers: u32) -> HashRequestMessage {
+        HashRequestMessage{ header: HashHeader{ pieces_root: pieces_root, base_layer: base_layer, index: index,
+                                                length: length, proof_layers: proof_layers } }
+    }
+
+    pub fn parse_bytes(_input: (), bytes: Bytes) -> IResult<(), io::Result<HashRequestMessage>> {
+        throwaway_input!(map!(bytes.as_ref(), parse_hash_header, |header| Ok(HashRequestMessage{ header: header })))
+    }
+
+    pub fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
+        where W: Write
+    {
+        try!(message::write_length_id_pair(&mut writer, message::HASH_REQUEST_MESSAGE_LEN, Some(message::HASH_REQUEST_MESSAGE_ID)));
+
+        self.header.write_bytes(writer)
+    }
+
+    pub fn pieces_root(&self) -> &MerkleHash {
+        &self.header.pieces_root
+    }
+
+    pub fn base_layer(&self) -> u32 {
+        self.header.base_layer
+    }
+
+    pub fn index(&self) -> u32 {
+        self.header.index
+    }
+
+    pub fn length(&self) -> u32 {
+        self.header.length
+    }
+
+    pub fn proof_layers(&self) -> u32 {
+        self.header.proof_layers
+    }
+
+    /// Whether or not the request is well formed.
+    ///
+    /// The length must be a power of two (at least two), and the index must be a multiple of the length.
+    pub fn is_valid(&self) -> bool {
+        self.header.is_valid()
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Message for sending a range of hashes from a layer of the merkle tree for a file.
+///
+/// The hashes consist of the `length` requested hashes, followed by the uncle hashes
+/// (from the bottom of the tree up) needed to verify them against the `pieces_root`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct HashesMessage {
+    header: HashHeader,
+    hashes: Bytes
+}
+
+impl HashesMessage {
+    pub fn new(request: HashRequestMessage, hashes: Bytes) -> HashesMessage {
+        HashesMessage{ header: request.header, hashes: hashes }
+    }
+
+    pub fn parse_bytes(_input: (), bytes: Bytes, len: u32) -> IResult<(), io::Result<HashesMessage>> {
+        let cast_len = match message::u32_to_usize(len) {
+            Ok(cast_len) => cast_len,
+            Err(err)     => return IResult::Done((), Err(err))
+        };
+        let header_len = message::BASE_HASHES_MESSAGE_LEN as usize - 1;
+
+        if bytes.len() < cast_len {
+            IResult::Incomplete(Needed::Size(cast_len - bytes.len()))
+        } else if cast_len < header_len || (cast_len - header_len) % MERKLE_HASH_LEN != 0 {
+            IResult::Done((), Err(io::Error::new(io::ErrorKind::Other, "Failed To Parse HashesMessage, Invalid Hashes Length")))
+        } else {
+            throwaway_input!(map!(bytes.as_ref(), parse_hash_header,
+                |header| Ok(HashesMessage{ header: header, hashes: bytes.slice(header_len, cast_len) })))
+        }
+    }
+
+    pub fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
+        where W: Write
+    {
+        let actual_length = message::BASE_HASHES_MESSAGE_LEN + self.hashes.len() as u32;
+        try!(message::write_length_id_pair(&mut writer, actual_length, Some(message::HASHES_MESSAGE_ID)));
+
+        try!(self.header.write_bytes(&mut writer));
+        writer.write_all(&self.hashes)
+    }
+
+    pub fn pieces_root(&self) -> &MerkleHash {
+        &self.header.pieces_root
+    }
+
+    pub fn base_layer(&self) -> u32 {
+        self.header.base_layer
+    }
+
+    pub fn index(&self) -> u32 {
+        self.header.index
+    }
+
+    pub fn length(&self) -> u32 {
+        self.header.length
+    }
+
+    pub fn proof_layers(&self) -> u32 {
+        self.header.proof_layers
+    }
+
+    /// Raw bytes of all hashes in the message (requested hashes followed by uncle hashes).
+    pub fn hashes(&self) -> &[u8] {
+        &self.hashes
+    }
+
+    /// Iterator over the requested hashes.
+    pub fn layer_hashes(&self) -> HashIter {
+        let layer_len = cmp::min(self.layer_bytes_len(), self.hashes.len());
+
+        HashIter::new(self.hashes.slice(0, layer_len))
+    }
+
+    /// Iterator over the uncle hashes, from the bottom of the tree up.
+    pub fn proof_hashes(&self) -> HashIter {
+        let layer_len = cmp::min(self.layer_bytes_len(), self.hashes.len());
+
+        HashIter::new(self.hashes.slice_from(layer_len))
+    }
+
+    /// Verify the requested hashes (and uncle hashes) against the `pieces_root`.
+    ///
+    /// This requires that the uncle hashes go all the way up to the root of the tree,
+    /// if fewer proof layers were requested, use `verify_against` with the already
+    /// known hash of the ancestor node that the uncle hashes stop at.
+    pub fn verify(&self) -> bool {
+        let pieces_root = self.header.pieces_root;
+
+        self.verify_against(&pieces_root)
+    }
+
+    /// Verify the requested hashes (and uncle hashes) against the given ancestor hash.
+    pub fn verify_against(&self, ancestor: &MerkleHash) -> bool {
+        if !self.header.is_valid() || self.hashes.len() < self.layer_bytes_len() {
+            return false
+        }
+
+        // Compute the root of the subtree for the requested hashes
+        let mut layer: Vec<MerkleHash> = self.layer_hashes().collect();
+        while layer.len() > 1 {
+            layer = layer.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+        }
+
+        // Walk up the tree using the uncle hashes
+        let mut node = layer[0];
+        let mut node_index = self.header.index / self.header.length;
+        for uncle in self.proof_hashes() {
+            node = if node_index % 2 == 0 {
+                hash_pair(&node, &uncle)
+            } else {
+                hash_pair(&uncle, &node)
+            };
+            node_index /= 2;
+        }
+
+        node == *ancestor
+    }
+
+    fn layer_bytes_len(&self) -> usize {
+        (self.header.length as usize).saturating_mul(MERKLE_HASH_LEN)
+    }
+}
+
+/// Iterator over the `MerkleHash`es in a `HashesMessage`.
+pub struct HashIter {
+    bytes:  Bytes,
+    offset: usize
+}
+
+impl HashIter {
+    fn new(bytes: Bytes) -> HashIter {
+        HashIter{ bytes: bytes, offset: 0 }
+    }
+}
+
+impl Iterator for HashIter {
+    type Item = MerkleHash;
+
+    fn next(&mut self) -> Option<MerkleHash> {
+        if self.offset + MERKLE_HASH_LEN <= self.bytes.len() {
+            let hash = slice_to_hash(&self.bytes[self.offset..self.offset + MERKLE_HASH_LEN]);
+            self.offset += MERKLE_HASH_LEN;
+
+            Some(hash)
+        } else {
+            None
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Message for rejecting a `HashRequestMessage` from a peer.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct HashRejectMessage {
+    header: HashHeader
+}
+
+impl HashRejectMessage {
+    pub fn new(request: HashRequestMessage) -> HashRejectMessage {
+        HashRejectMessage{ header: request.header }
+    }
+
+    pub fn parse_bytes(_input: (), bytes: Bytes) -> IResult<(), io::Result<HashRejectMessage>> {
+        throwaway_input!(map!(bytes.as_ref(), parse_hash_header, |header| Ok(HashRejectMessage{ header: header })))
+    }
+
+    pub fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
+        where W: Write
+    {
+        try!(message::write_length_id_pair(&mut writer, message::HASH_REJECT_MESSAGE_LEN, Some(message::HASH_REJECT_MESSAGE_ID)));
+
+        self.header.write_bytes(writer)
+    }
+
+    pub fn pieces_root(&self) -> &MerkleHash {
+        &self.header.pieces_root
+    }
+
+    pub fn base_layer(&self) -> u32 {
+        self.header.base_layer
+    }
+
+    pub fn index(&self) -> u32 {
+        self.header.index
+    }
+
+    pub fn length(&self) -> u32 {
+        self.header.length
+    }
+
+    pub fn proof_layers(&self) -> u32 {
+        self.header.proof_layers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HashRequestMessage, HashesMessage, HashRejectMessage, MerkleHash, MERKLE_HASH_LEN};
+
+    use message::PeerWireProtocolMessage;
+    use protocol::null::NullProtocol;
+
+    use bytes::{Bytes, BytesMut};
+
+    fn round_trip(message: PeerWireProtocolMessage<NullProtocol>) -> PeerWireProtocolMessage<NullProtocol> {
+        let mut protocol = NullProtocol::new();
+        let mut bytes = Vec::new();
+        message.write_bytes(&mut bytes, &mut protocol).unwrap();
+
+        assert_eq!(message.message_size(&mut protocol), bytes.len());
+
+        PeerWireProtocolMessage::parse_bytes(Bytes::from(bytes), &mut protocol).unwrap()
+    }
+
+    fn leaf(value: u8) -> MerkleHash {
+        [value; MERKLE_HASH_LEN]
+    }
+
+    fn hashes_bytes(hashes: &[MerkleHash]) -> Bytes {
+        let mut bytes = BytesMut::with_capacity(hashes.len() * MERKLE_HASH_LEN);
+        for hash in hashes {
+            bytes.extend_from_slice(hash);
+        }
+
+        bytes.freeze()
+    }
+
+    /// Tree with four leaves, returns (leaves, left parent, right parent, root).
+    fn four_leaf_tree() -> ([MerkleHash; 4], MerkleHash, MerkleHash, MerkleHash) {
+        let leaves = [leaf(1), leaf(2), leaf(3), leaf(4)];
+        let left = super::hash_pair(&leaves[0], &leaves[1]);
+        let right = super::hash_pair(&leaves[2], &leaves[3]);
+        let root = super::hash_pair(&left, &right);
+
+        (leaves, left, right, root)
+    }
+
+    #[test]
+    fn positive_verify_all_leaves() {
+        let (leaves, _, _, root) = four_leaf_tree();
+        let request = HashRequestMessage::new(root, 0, 0, 4, 2);
+
+        let hashes = HashesMessage::new(request, hashes_bytes(&leaves));
+
+        assert!(hashes.verify());
+    }
+
+    #[test]
+    fn positive_verify_with_uncle() {
+        let (leaves, left, _, root) = four_leaf_tree();
+        let request = HashRequestMessage::new(root, 0, 2, 2, 2);
+
+        let hashes = HashesMessage::new(request, hashes_bytes(&[leaves[2], leaves[3], left]));
+
+        assert!(hashes.verify());
+    }
+
+    #[test]
+    fn positive_verify_against_ancestor() {
+        let (leaves, _, right, root) = four_leaf_tree();
+        let request = HashRequestMessage::new(root, 0, 2, 2, 1);
+
+        let hashes = HashesMessage::new(request, hashes_bytes(&[leaves[2], leaves[3]]));
+
+        assert!(!hashes.verify());
+        assert!(hashes.verify_against(&right));
+    }
+
+    #[test]
+    fn negative_verify_tampered_hash() {
+        let (leaves, left, _, root) = four_leaf_tree();
+        let request = HashRequestMessage::new(root, 0, 2, 2, 2);
+
+        let hashes = HashesMessage::new(request, hashes_bytes(&[leaves[2], leaf(5), left]));
+
+        assert!(!hashes.verify());
+    }
+
+    #[test]
+    fn negative_invalid_request_length() {
+        let request = HashRequestMessage::new(leaf(0), 0, 0, 3, 0);
+
+        assert!(!request.is_valid());
+    }
+
+    #[test]
+    fn negative_invalid_request_index() {
+        let request = HashRequestMessage::new(leaf(0), 0, 1, 2, 0);
+
+        assert!(!request.is_valid());
+    }
+
+    #[test]
+    fn positive_hash_request_round_trip() {
+        let request = HashRequestMessage::new(leaf(7), 1, 4, 4, 3);
+
+        match round_trip(PeerWireProtocolMessage::HashRequest(request)) {
+            PeerWireProtocolMessage::HashRequest(parsed) => assert_eq!(request, parsed),
+            _                                            => panic!("bip_peer: Expected A HashRequestMessage")
+        }
+    }
+
+    #[test]
+    fn positive_hashes_round_trip() {
+        let (leaves, left, _, root) = four_leaf_tree();
+        let request = HashRequestMessage::new(root, 0, 2, 2, 2);
+        let hashes = HashesMessage::new(request, hashes_bytes(&[leaves[2], leaves[3], left]));
+
+        match round_trip(PeerWireProtocolMessage::Hashes(hashes.clone())) {
+            PeerWireProtocolMessage::Hashes(parsed) => {
+                assert_eq!(hashes, parsed);
+                assert!(parsed.verify());
+            },
+            _                                       => panic!("bip_peer: Expected A HashesMessage")
+        }
+    }
+
+    #[test]
+    fn positive_hash_reject_round_trip() {
+        let reject = HashRejectMessage::new(HashRequestMessage::new(leaf(7), 1, 4, 4, 3));
+
+        match round_trip(PeerWireProtocolMessage::HashReject(reject)) {
+            PeerWireProtocolMessage::HashReject(parsed) => assert_eq!(reject, parsed),
+            _                                           => panic!("bip_peer: Expected A HashRejectMessage")
+        }
+    }
+
+    #[test]
+    fn negative_hashes_partial_hash() {
+        let request = HashRequestMessage::new(leaf(0), 0, 0, 2, 0);
+        let hashes = HashesMessage::new(request, hashes_bytes(&[leaf(1), leaf(2)]));
+
+        let mut protocol = NullProtocol::new();
+        let mut bytes = Vec::new();
+        PeerWireProtocolMessage::Hashes(hashes).write_bytes(&mut bytes, &mut protocol).unwrap();
+
+        // Drop the last byte of the hashes, and fix up the length prefix to match
+        bytes.pop();
+        let length = (bytes.len() - 4) as u32;
+        bytes[0..4].copy_from_slice(&[(length >> 24) as u8, (length >> 16) as u8, (length >> 8) as u8, length as u8]);
+
+        assert!(PeerWireProtocolMessage::parse_bytes(Bytes::from(bytes), &mut protocol).is_err());
+    }
+}
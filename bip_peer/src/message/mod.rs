@@ -13,8 +13,6 @@ use bytes::Bytes;
 use byteorder::{WriteBytesExt, BigEndian};
 use nom::{IResult, be_u32, be_u8};
 
-// TODO: Propogate failures to cast values to/from usize
-
 const KEEP_ALIVE_MESSAGE_LEN:    u32 = 0;
 const CHOKE_MESSAGE_LEN:         u32 = 1;
 const UNCHOKE_MESSAGE_LEN:       u32 = 1;
@@ -25,6 +23,9 @@ const BASE_BITFIELD_MESSAGE_LEN: u32 = 1;
 const REQUEST_MESSAGE_LEN:       u32 = 13;
 const BASE_PIECE_MESSAGE_LEN:    u32 = 9;
 const CANCEL_MESSAGE_LEN:        u32 = 13;
+const HASH_REQUEST_MESSAGE_LEN:  u32 = 49;
+const BASE_HASHES_MESSAGE_LEN:   u32 = 49;
+const HASH_REJECT_MESSAGE_LEN:   u32 = 49;
 
 const CHOKE_MESSAGE_ID:        u8 = 0;
 const UNCHOKE_MESSAGE_ID:      u8 = 1;
@@ -35,6 +36,9 @@ const BITFIELD_MESSAGE_ID:     u8 = 5;
 const REQUEST_MESSAGE_ID:      u8 = 6;
 const PIECE_MESSAGE_ID:        u8 = 7;
 const CANCEL_MESSAGE_ID:       u8 = 8;
+const HASH_REQUEST_MESSAGE_ID: u8 = 21;
+const HASHES_MESSAGE_ID:       u8 = 22;
+const HASH_REJECT_MESSAGE_ID:  u8 = 23;
 
 const MESSAGE_LENGTH_LEN_BYTES: usize = 4;
 const MESSAGE_ID_LEN_BYTES:     usize = 1;
@@ -42,12 +46,14 @@ const HEADER_LEN:               usize = MESSAGE_LENGTH_LEN_BYTES + MESSAGE_ID_LE
 
 mod bencode;
 mod bits_ext;
+mod hash;
 mod prot_ext;
 mod standard;
 mod null;
 
-pub use message::bits_ext::{BitsExtensionMessage, PortMessage, ExtendedMessage, ExtendedMessageBuilder, ExtendedType};
+pub use message::bits_ext::{BitsExtensionMessage, PortMessage, SuggestPieceMessage, ExtendedMessage, ExtendedMessageBuilder, ExtendedType};
 pub use message::standard::{HaveMessage, BitFieldMessage, BitFieldIter, RequestMessage, PieceMessage, CancelMessage};
+pub use message::hash::{HashRequestMessage, HashesMessage, HashRejectMessage, HashIter, MerkleHash, MERKLE_HASH_LEN};
 pub use message::null::NullProtocolMessage;
 pub use message::prot_ext::{PeerExtensionProtocolMessage, UtMetadataMessage, UtMetadataRequestMessage, UtMetadataDataMessage, UtMetadataRejectMessage};
 
@@ -77,6 +83,12 @@ pub enum PeerWireProtocolMessage<P> where P: PeerProtocol {
     Piece(PieceMessage),
     /// Message to cancel a block request from a peer.
     Cancel(CancelMessage),
+    /// Message to request a range of merkle tree hashes from a peer (BitTorrent v2).
+    HashRequest(HashRequestMessage),
+    /// Message from a peer containing a range of merkle tree hashes (BitTorrent v2).
+    Hashes(HashesMessage),
+    /// Message to reject a hash request from a peer (BitTorrent v2).
+    HashReject(HashRejectMessage),
     /// Extension messages which are activated via the `ExtensionBits` as part of the handshake.
     BitsExtension(BitsExtensionMessage),
     /// Extension messages which are activated via the Extension Protocol.
@@ -98,6 +110,13 @@ impl<P> ManagedMessage for PeerWireProtocolMessage<P>
             _                                   => false
         }
     }
+
+    fn block_payload(&self) -> Option<Bytes> {
+        match self {
+            &PeerWireProtocolMessage::Piece(ref msg) => Some(msg.block()),
+            _                                        => None
+        }
+    }
 }
 
 impl<P> PeerWireProtocolMessage<P>
@@ -105,15 +124,41 @@ impl<P> PeerWireProtocolMessage<P>
     pub fn bytes_needed(bytes: &[u8]) -> io::Result<Option<usize>> {
         match be_u32(bytes) {
             // We need 4 bytes for the length, plus whatever the length is...
-            IResult::Done(_, length) => Ok(Some(MESSAGE_LENGTH_LEN_BYTES + u32_to_usize(length))),
+            IResult::Done(_, length) => {
+                let total_len = try!(u32_to_usize(length)).checked_add(MESSAGE_LENGTH_LEN_BYTES);
+
+                total_len.map(Some)
+                    .ok_or(io::Error::new(io::ErrorKind::Other, "PeerWireProtocolMessage Length Overflowed A usize"))
+            },
             _                        => Ok(None)
         }
     }
 
+    /// Parse a `PeerWireProtocolMessage` from the given bytes.
+    ///
+    /// Messages that fail to parse and have an id that we dont know about will
+    /// return an `io::ErrorKind::InvalidData` error, so that they can be told apart from malformed messages.
     pub fn parse_bytes(bytes: Bytes, ext_protocol: &mut P) -> io::Result<PeerWireProtocolMessage<P>> {
-        match parse_message(bytes, ext_protocol) {
+        // Parsers assume that the whole message is present, so dont hand them anything shorter
+        match try!(PeerWireProtocolMessage::<P>::bytes_needed(bytes.as_ref())) {
+            Some(needed) if needed <= bytes.len() => (),
+            Some(_) | None                        => {
+                return Err(io::Error::new(io::ErrorKind::Other, "PeerWireProtocolMessage Is Shorter Than Its Length Prefix"))
+            }
+        }
+
+        let opt_id = bytes.get(MESSAGE_LENGTH_LEN_BYTES).cloned();
+
+        let result = match parse_message(bytes, ext_protocol) {
             IResult::Done(_, result) => result,
             _                        => Err(io::Error::new(io::ErrorKind::Other, "Failed To Parse PeerWireProtocolMessage"))
+        };
+
+        match (result, opt_id) {
+            (Err(_), Some(id)) if !is_known_message_id(id) => {
+                Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unknown Id For PeerWireProtocolMessage: {}", id)))
+            },
+            (other, _)                                     => other
         }
     }
 
@@ -131,6 +176,9 @@ impl<P> PeerWireProtocolMessage<P>
             &PeerWireProtocolMessage::Request(ref msg)       => msg.write_bytes(writer),
             &PeerWireProtocolMessage::Piece(ref msg)         => msg.write_bytes(writer),
             &PeerWireProtocolMessage::Cancel(ref msg)        => msg.write_bytes(writer),
+            &PeerWireProtocolMessage::HashRequest(ref msg)   => msg.write_bytes(writer),
+            &PeerWireProtocolMessage::Hashes(ref msg)        => msg.write_bytes(writer),
+            &PeerWireProtocolMessage::HashReject(ref msg)    => msg.write_bytes(writer),
             &PeerWireProtocolMessage::BitsExtension(ref ext) => ext.write_bytes(writer),
             &PeerWireProtocolMessage::ProtExtension(ref ext) => ext_protocol.write_bytes(ext, writer)
         }
@@ -148,6 +196,9 @@ impl<P> PeerWireProtocolMessage<P>
             &PeerWireProtocolMessage::Request(_)             => REQUEST_MESSAGE_LEN as usize,
             &PeerWireProtocolMessage::Piece(ref msg)         => BASE_PIECE_MESSAGE_LEN as usize + msg.block().len(),
             &PeerWireProtocolMessage::Cancel(_)              => CANCEL_MESSAGE_LEN as usize,
+            &PeerWireProtocolMessage::HashRequest(_)         => HASH_REQUEST_MESSAGE_LEN as usize,
+            &PeerWireProtocolMessage::Hashes(ref msg)        => BASE_HASHES_MESSAGE_LEN as usize + msg.hashes().len(),
+            &PeerWireProtocolMessage::HashReject(_)          => HASH_REJECT_MESSAGE_LEN as usize,
             &PeerWireProtocolMessage::BitsExtension(ref ext) => ext.message_size(),
             &PeerWireProtocolMessage::ProtExtension(ref ext) => ext_protocol.message_size(ext)
         };
@@ -156,6 +207,17 @@ impl<P> PeerWireProtocolMessage<P>
     }
 }
 
+/// Whether or not the given id is used by any message we know about.
+fn is_known_message_id(id: u8) -> bool {
+    match id {
+        CHOKE_MESSAGE_ID ... CANCEL_MESSAGE_ID                            => true,
+        HASH_REQUEST_MESSAGE_ID ... HASH_REJECT_MESSAGE_ID                => true,
+        bits_ext::PORT_MESSAGE_ID | bits_ext::SUGGEST_PIECE_MESSAGE_ID |
+        bits_ext::EXTENDED_MESSAGE_ID                                     => true,
+        _                                                                 => false
+    }
+}
+
 /// Write a length and optional id out to the given writer.
 fn write_length_id_pair<W>(mut writer: W, length: u32, opt_id: Option<u8>) -> io::Result<()>
     where W: Write
@@ -169,24 +231,20 @@ fn write_length_id_pair<W>(mut writer: W, length: u32, opt_id: Option<u8>) -> io
     }
 }
 
-/// Parse the length portion of a message.
-///
-/// Panics if parsing failed for any reason.
-fn parse_message_length(bytes: &[u8]) -> usize {
-    if let IResult::Done(_, len) = be_u32(bytes) {
-        u32_to_usize(len)
+/// Convert a u32 to a usize, failing if the conversion is not valid.
+fn u32_to_usize(value: u32) -> io::Result<usize> {
+    if value as usize as u32 != value {
+        Err(io::Error::new(io::ErrorKind::Other, "Cannot Convert u32 To usize, usize Is Less Than 32-Bits"))
     } else {
-        panic!("bip_peer: Message Length Was Less Than 4 Bytes")
+        Ok(value as usize)
     }
 }
 
-/// Panics if the conversion from a u32 to usize is not valid.
-fn u32_to_usize(value: u32) -> usize {
-    if value as usize as u32 != value {
-        panic!("bip_peer: Cannot Convert u32 To usize, usize Is Less Than 32-Bits")
-    }
-
-    value as usize
+/// Length of the payload following the given number of header bytes, failing if the message length is too short.
+fn payload_len(message_len: u32, header_len: u32) -> io::Result<usize> {
+    message_len.checked_sub(header_len)
+        .ok_or(io::Error::new(io::ErrorKind::Other, "Message Length Is Shorter Than Its Header"))
+        .and_then(u32_to_usize)
 }
 
 // Since these messages may come over a stream oriented protocol, if a message is incomplete
@@ -221,7 +279,7 @@ fn parse_message<P>(mut bytes: Bytes, ext_protocol: &mut P) -> IResult<(), io::R
                     |res_have| res_have.map(|have| PeerWireProtocolMessage::Have(have))
                 ) |
                 (message_len, Some(BITFIELD_MESSAGE_ID)) => map!(
-                    call!(BitFieldMessage::parse_bytes, bytes.split_off(HEADER_LEN), message_len - 1),
+                    call!(BitFieldMessage::parse_bytes, bytes.split_off(HEADER_LEN), message_len.saturating_sub(1)),
                     |res_bitfield| res_bitfield.map(|bitfield| PeerWireProtocolMessage::BitField(bitfield))
                 ) |
                 (REQUEST_MESSAGE_LEN, Some(REQUEST_MESSAGE_ID)) => map!(
@@ -229,12 +287,24 @@ fn parse_message<P>(mut bytes: Bytes, ext_protocol: &mut P) -> IResult<(), io::R
                     |res_request| res_request.map(|request| PeerWireProtocolMessage::Request(request))
                 ) |
                 (message_len, Some(PIECE_MESSAGE_ID)) => map!(
-                    call!(PieceMessage::parse_bytes, bytes.split_off(HEADER_LEN), message_len - 1),
+                    call!(PieceMessage::parse_bytes, bytes.split_off(HEADER_LEN), message_len.saturating_sub(1)),
                     |res_piece| res_piece.map(|piece| PeerWireProtocolMessage::Piece(piece))
                 ) |
                 (CANCEL_MESSAGE_LEN, Some(CANCEL_MESSAGE_ID)) => map!(
                     call!(CancelMessage::parse_bytes, bytes.split_off(HEADER_LEN)),
                     |res_cancel| res_cancel.map(|cancel| PeerWireProtocolMessage::Cancel(cancel))
+                ) |
+                (HASH_REQUEST_MESSAGE_LEN, Some(HASH_REQUEST_MESSAGE_ID)) => map!(
+                    call!(HashRequestMessage::parse_bytes, bytes.split_off(HEADER_LEN)),
+                    |res_request| res_request.map(|request| PeerWireProtocolMessage::HashRequest(request))
+                ) |
+                (message_len, Some(HASHES_MESSAGE_ID)) => map!(
+                    call!(HashesMessage::parse_bytes, bytes.split_off(HEADER_LEN), message_len.saturating_sub(1)),
+                    |res_hashes| res_hashes.map(|hashes| PeerWireProtocolMessage::Hashes(hashes))
+                ) |
+                (HASH_REJECT_MESSAGE_LEN, Some(HASH_REJECT_MESSAGE_ID)) => map!(
+                    call!(HashRejectMessage::parse_bytes, bytes.split_off(HEADER_LEN)),
+                    |res_reject| res_reject.map(|reject| PeerWireProtocolMessage::HashReject(reject))
                 )
             )
          ) | map!(call!(BitsExtensionMessage::parse_bytes, bytes.clone()),
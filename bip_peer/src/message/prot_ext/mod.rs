@@ -73,15 +73,25 @@ fn parse_extensions<P>(mut bytes: Bytes, extended: &ExtendedMessage, custom_prot
          ignore_input!(
              switch!(header_bytes.as_ref(), throwaway_input!(tuple!(be_u32, be_u8, be_u8)),
                 (message_len, bits_ext::EXTENDED_MESSAGE_ID, message_id) =>
-                    call!(parse_extensions_with_id, bytes.split_off(EXTENSION_HEADER_LEN).split_to(message_len as usize - 2), extended, message_id)
+                    call!(parse_extensions_with_id, bytes.split_off(EXTENSION_HEADER_LEN), message_len, extended, message_id)
             )
          ) | map!(value!(custom_prot.parse_bytes(bytes)),
                |res_cust_ext| res_cust_ext.map(|cust_ext| PeerExtensionProtocolMessage::Custom(cust_ext)))
     )
 }
 
-fn parse_extensions_with_id<P>(_input: (), bytes: Bytes, extended: &ExtendedMessage, id: u8) -> IResult<(), io::Result<PeerExtensionProtocolMessage<P>>>
-    where P: PeerProtocol {
+fn parse_extensions_with_id<P>(_input: (), mut bytes: Bytes, message_len: u32, extended: &ExtendedMessage, id: u8)
+    -> IResult<(), io::Result<PeerExtensionProtocolMessage<P>>> where P: PeerProtocol {
+    // Length covers the extended message id and the extension id
+    let payload_len = match message::payload_len(message_len, 2) {
+        Ok(payload_len) if payload_len <= bytes.len() => payload_len,
+        Ok(_)                                         => {
+            return IResult::Done((), Err(io::Error::new(io::ErrorKind::Other, "PeerExtensionProtocolMessage Is Shorter Than Its Length Prefix")))
+        },
+        Err(err)                                      => return IResult::Done((), Err(err))
+    };
+    let bytes = bytes.split_to(payload_len);
+
     let lt_metadata_id = extended.query_id(&ExtendedType::UtMetadata);
     //let ut_pex_id = extended.query_id(&ExtendedType::UtPex);
 
@@ -89,7 +99,7 @@ fn parse_extensions_with_id<P>(_input: (), bytes: Bytes, extended: &ExtendedMess
         UtMetadataMessage::parse_bytes(bytes)
                 .map(|lt_metadata_msg| PeerExtensionProtocolMessage::UtMetadata(lt_metadata_msg))
     } else {
-        Err(io::Error::new(io::ErrorKind::Other, format!("Unknown Id For PeerExtensionProtocolMessage: {}", id)))
+        Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unknown Id For PeerExtensionProtocolMessage: {}", id)))
     };
 
     IResult::Done((), result)
@@ -54,7 +54,10 @@ impl BitFieldMessage {
     }
 
     pub fn parse_bytes(_input: (), mut bytes: Bytes, len: u32) -> IResult<(), io::Result<BitFieldMessage>> {
-        let cast_len = message::u32_to_usize(len);
+        let cast_len = match message::u32_to_usize(len) {
+            Ok(cast_len) => cast_len,
+            Err(err)     => return IResult::Done((), Err(err))
+        };
 
         if bytes.len() >= cast_len {
             IResult::Done((), Ok(BitFieldMessage{ bytes: bytes.split_to(cast_len) }))
@@ -164,7 +167,7 @@ impl RequestMessage {
 fn parse_request(bytes: &[u8]) -> IResult<&[u8], io::Result<RequestMessage>> {
     map!(bytes,
          tuple!(be_u32, be_u32, be_u32),
-         |(index, offset, length)| Ok(RequestMessage::new(index, offset, message::u32_to_usize(length)))
+         |(index, offset, length)| message::u32_to_usize(length).map(|length| RequestMessage::new(index, offset, length))
     )
 }
 
@@ -225,10 +228,15 @@ impl PieceMessage {
 }
 
 fn parse_piece(bytes: &Bytes, len: u32) -> IResult<&[u8], io::Result<PieceMessage>> {
+    // Length has to at least cover the piece index and block offset
+    let block_len = match message::payload_len(len, 8) {
+        Ok(block_len) => block_len,
+        Err(err)      => return IResult::Done(bytes.as_ref(), Err(err))
+    };
+
     do_parse!(bytes.as_ref(),
         piece_index:  be_u32                                                    >>
         block_offset: be_u32                                                    >>
-        block_len:    value!(message::u32_to_usize(len - 8))                    >>
         block:        map!(take!(block_len), |_| bytes.slice(8, 8 + block_len)) >>
         (Ok(PieceMessage::new(piece_index, block_offset, block)))
     )
@@ -283,7 +291,7 @@ impl CancelMessage {
 fn parse_cancel(bytes: &[u8]) -> IResult<&[u8], io::Result<CancelMessage>> {
     map!(bytes,
          tuple!(be_u32, be_u32, be_u32),
-         |(index, offset, length)| Ok(CancelMessage::new(index, offset, message::u32_to_usize(length)))
+         |(index, offset, length)| message::u32_to_usize(length).map(|length| CancelMessage::new(index, offset, length))
     )
 }
 
@@ -2,22 +2,22 @@ use std::io::{self, Write};
 
 use bytes::Bytes;
 
-use message::{ExtendedMessage, PeerExtensionProtocolMessage};
-use protocol::{PeerProtocol, NestedPeerProtocol};
+use message::PeerExtensionProtocolMessage;
+use protocol::{PeerProtocol, PeerProtocolFactory, ExtendedState};
 
 /// Protocol for `BEP 10` peer extensions.
 pub struct PeerExtensionProtocol<P> {
-    our_extended_msg:   Option<ExtendedMessage>,
-    their_extended_msg: Option<ExtendedMessage>,
-    custom_protocol:    P
+    extended:        ExtendedState,
+    custom_protocol: P
 }
 
 impl<P> PeerExtensionProtocol<P> {
-    /// Create a new `PeerExtensionProtocol` with the given (nested) custom extension protocol.
+    /// Create a new `PeerExtensionProtocol` for a peer with the given extended
+    /// handshake state and (nested) custom extension protocol.
     ///
     /// Notes for `PeerWireProtocol` apply to this custom extension protocol.
-    pub fn new(custom_protocol: P) -> PeerExtensionProtocol<P> {
-        PeerExtensionProtocol{ our_extended_msg: None, their_extended_msg: None, custom_protocol: custom_protocol }
+    pub fn new(extended: ExtendedState, custom_protocol: P) -> PeerExtensionProtocol<P> {
+        PeerExtensionProtocol{ extended: extended, custom_protocol: custom_protocol }
     }
 }
 
@@ -29,7 +29,7 @@ impl<P> PeerProtocol for PeerExtensionProtocol<P> where P: PeerProtocol {
     }
 
     fn parse_bytes(&mut self, bytes: Bytes) -> io::Result<Self::ProtocolMessage> {
-        match self.our_extended_msg {
+        match self.extended.ours {
             Some(ref extended_msg) => PeerExtensionProtocolMessage::parse_bytes(bytes, extended_msg, &mut self.custom_protocol),
             None                   => Err(io::Error::new(io::ErrorKind::Other, "Extension Message Received From Peer Before Extended Message..."))
         }
@@ -37,7 +37,7 @@ impl<P> PeerProtocol for PeerExtensionProtocol<P> where P: PeerProtocol {
 
     fn write_bytes<W>(&mut self, message: &Self::ProtocolMessage, writer: W) -> io::Result<()>
         where W: Write {
-        match self.their_extended_msg {
+        match self.extended.theirs {
             Some(ref extended_msg) => PeerExtensionProtocolMessage::write_bytes(message, writer, extended_msg, &mut self.custom_protocol),
             None                   => Err(io::Error::new(io::ErrorKind::Other, "Extension Message Sent From Us Before Extended Message..."))
         }
@@ -48,16 +48,24 @@ impl<P> PeerProtocol for PeerExtensionProtocol<P> where P: PeerProtocol {
     }
 }
 
-impl<P> NestedPeerProtocol<ExtendedMessage> for PeerExtensionProtocol<P> where P: NestedPeerProtocol<ExtendedMessage> {
-    fn received_message(&mut self, message: &ExtendedMessage) {
-        self.custom_protocol.received_message(message);
+//----------------------------------------------------------------------------//
 
-        self.their_extended_msg = Some(message.clone());
+/// Factory for creating a `PeerExtensionProtocol` for each peer.
+pub struct PeerExtensionProtocolFactory<F> {
+    custom_factory: F
+}
+
+impl<F> PeerExtensionProtocolFactory<F> {
+    /// Create a new `PeerExtensionProtocolFactory` with the given (nested) custom extension protocol factory.
+    pub fn new(custom_factory: F) -> PeerExtensionProtocolFactory<F> {
+        PeerExtensionProtocolFactory{ custom_factory: custom_factory }
     }
+}
 
-    fn sent_message(&mut self, message: &ExtendedMessage) {
-        self.custom_protocol.sent_message(message);
+impl<F> PeerProtocolFactory for PeerExtensionProtocolFactory<F> where F: PeerProtocolFactory {
+    type Protocol = PeerExtensionProtocol<F::Protocol>;
 
-        self.our_extended_msg = Some(message.clone());
+    fn new_protocol(&self, extended: &ExtendedState) -> Self::Protocol {
+        PeerExtensionProtocol::new(extended.clone(), self.custom_factory.new_protocol(extended))
     }
-}
\ No newline at end of file
+}
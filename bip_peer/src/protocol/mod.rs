@@ -4,7 +4,10 @@ use std::io::{self, Write};
 
 use bytes::Bytes;
 
+use message::ExtendedMessage;
+
 pub mod extension;
+pub mod negotiated;
 pub mod unit;
 pub mod null;
 pub mod wire;
@@ -34,23 +37,41 @@ pub trait PeerProtocol {
     fn message_size(&mut self, message: &Self::ProtocolMessage) -> usize;
 }
 
-/// Trait for nested peer protocols to see higher level peer protocol messages.
-///
-/// This is useful when tracking certain states of a connection that happen at a higher
-/// level peer protocol, but which nested peer protocols need to know about atomically
-/// (before other messages dependent on that message start coming in, and the nested
-/// protocol is expected to handle those).
+/// Trait for creating the `PeerProtocol` state of a single peer connection.
 ///
-/// Example: We handle `ExtensionMessage`s at the `PeerWireProtocol` layer, but the
-/// `ExtensionMessage` contains mappings of id to message type that nested extensions
-/// need to know about so they can determine what type of message a given id maps to.
-/// We need to pass the `ExtensionMessage` down to them before we start receiving
-/// messages with those ids (otherwise we will receive unrecognized messages and
-/// kill the connection).
-pub trait NestedPeerProtocol<M> {
-    /// Notify a nested protocol that we have received the given message.
-    fn received_message(&mut self, message: &M);
-
-    /// Notify a nested protocol that we have sent the given message.
-    fn sent_message(&mut self, message: &M);
-}
\ No newline at end of file
+/// Nested protocols (such as extension protocols) often need state that is negotiated
+/// per peer, like the ids each side assigned to extensions in their `ExtendedMessage`.
+/// A factory is given the extended handshake state for a peer, and creates a protocol
+/// that is initialized from it. The protocol is re-created any time the extended
+/// handshake state for the peer changes, before any dependent messages are processed.
+pub trait PeerProtocolFactory {
+    /// Type of protocol created for each peer.
+    type Protocol: PeerProtocol;
+
+    /// Create a new protocol for a peer with the given extended handshake state.
+    fn new_protocol(&self, extended: &ExtendedState) -> Self::Protocol;
+}
+
+/// Extended handshake state for a single peer connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtendedState {
+    ours:   Option<ExtendedMessage>,
+    theirs: Option<ExtendedMessage>
+}
+
+impl ExtendedState {
+    /// Create a new `ExtendedState` where neither side has sent an `ExtendedMessage`.
+    pub fn new() -> ExtendedState {
+        ExtendedState{ ours: None, theirs: None }
+    }
+
+    /// `ExtendedMessage` that we sent to the peer.
+    pub fn ours(&self) -> Option<&ExtendedMessage> {
+        self.ours.as_ref()
+    }
+
+    /// `ExtendedMessage` that the peer sent to us.
+    pub fn theirs(&self) -> Option<&ExtendedMessage> {
+        self.theirs.as_ref()
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::{self, Write};
+
+use message::{PeerWireProtocolMessage, BitsExtensionMessage};
+use protocol::{PeerProtocol, PeerProtocolFactory};
+use protocol::wire::PeerWireProtocol;
+
+use bip_handshake::{Extension, Extensions};
+use bytes::Bytes;
+
+/// Protocol for peer wire messages, restricted to the extensions negotiated in the handshake.
+///
+/// Messages belonging to an extension that was not negotiated will fail to be written, and
+/// will be rejected as invalid data if received (which the `PeerProtocolCodec` counts as an unknown message).
+pub struct NegotiatedProtocol<F> where F: PeerProtocolFactory {
+    protocol:   PeerWireProtocol<F>,
+    extensions: Extensions
+}
+
+impl<F> NegotiatedProtocol<F> where F: PeerProtocolFactory {
+    /// Create a new `NegotiatedProtocol` with the given `Extensions` that both sides support.
+    pub fn new(protocol: PeerWireProtocol<F>, extensions: Extensions) -> NegotiatedProtocol<F> {
+        NegotiatedProtocol{ protocol: protocol, extensions: extensions }
+    }
+
+    /// `Extensions` that both sides of the connection support.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Whether or not the message is allowed to be sent over the connection.
+    pub fn is_negotiated(&self, message: &PeerWireProtocolMessage<F::Protocol>) -> bool {
+        match message {
+            &PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::Extended(_))     => self.extensions.contains(Extension::ExtensionProtocol),
+            &PeerWireProtocolMessage::ProtExtension(_)                                     => self.extensions.contains(Extension::ExtensionProtocol),
+            &PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::SuggestPiece(_)) => self.extensions.contains(Extension::FastExtension),
+            _                                                                              => true
+        }
+    }
+}
+
+impl<F> PeerProtocol for NegotiatedProtocol<F> where F: PeerProtocolFactory {
+    type ProtocolMessage = PeerWireProtocolMessage<F::Protocol>;
+
+    fn bytes_needed(&mut self, bytes: &[u8]) -> io::Result<Option<usize>> {
+        self.protocol.bytes_needed(bytes)
+    }
+
+    fn parse_bytes(&mut self, bytes: Bytes) -> io::Result<Self::ProtocolMessage> {
+        let message = try!(self.protocol.parse_bytes(bytes));
+
+        if self.is_negotiated(&message) {
+            Ok(message)
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "Received Message For Extension That Was Not Negotiated"))
+        }
+    }
+
+    fn write_bytes<W>(&mut self, message: &Self::ProtocolMessage, writer: W) -> io::Result<()>
+        where W: Write {
+        if self.is_negotiated(message) {
+            self.protocol.write_bytes(message, writer)
+        } else {
+            Err(io::Error::new(io::ErrorKind::Other, "Attempted To Send Message For Extension That Was Not Negotiated"))
+        }
+    }
+
+    fn message_size(&mut self, message: &Self::ProtocolMessage) -> usize {
+        self.protocol.message_size(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NegotiatedProtocol;
+    use message::{BitsExtensionMessage, ExtendedMessageBuilder, PeerWireProtocolMessage, SuggestPieceMessage};
+    use protocol::PeerProtocol;
+    use protocol::extension::PeerExtensionProtocolFactory;
+    use protocol::null::NullProtocol;
+    use protocol::wire::PeerWireProtocol;
+
+    use bip_handshake::{Extension, Extensions};
+    use bytes::Bytes;
+    use std::io;
+
+    fn negotiated_protocol(extensions: Extensions) -> NegotiatedProtocol<PeerExtensionProtocolFactory<NullProtocol>> {
+        NegotiatedProtocol::new(PeerWireProtocol::new(PeerExtensionProtocolFactory::new(NullProtocol::new())), extensions)
+    }
+
+    #[test]
+    fn positive_write_negotiated_extended_message() {
+        let mut extensions = Extensions::new();
+        extensions.add(Extension::ExtensionProtocol);
+        let mut protocol = negotiated_protocol(extensions);
+
+        let message = PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::Extended(ExtendedMessageBuilder::new().build()));
+
+        assert!(protocol.write_bytes(&message, &mut Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn negative_write_extended_message_not_negotiated() {
+        let mut protocol = negotiated_protocol(Extensions::new());
+
+        let message = PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::Extended(ExtendedMessageBuilder::new().build()));
+
+        assert!(protocol.write_bytes(&message, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn negative_parse_suggest_piece_not_negotiated() {
+        let message = PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::SuggestPiece(SuggestPieceMessage::new(5)));
+
+        let mut fast_extensions = Extensions::new();
+        fast_extensions.add(Extension::FastExtension);
+
+        let mut bytes = Vec::new();
+        negotiated_protocol(fast_extensions).write_bytes(&message, &mut bytes).unwrap();
+
+        match negotiated_protocol(Extensions::new()).parse_bytes(Bytes::from(bytes)) {
+            Err(error) => assert_eq!(io::ErrorKind::InvalidData, error.kind()),
+            Ok(_)      => panic!("bip_peer: Parsed SuggestPiece Message That Was Not Negotiated")
+        }
+    }
+
+    #[test]
+    fn positive_standard_messages_always_negotiated() {
+        let protocol = negotiated_protocol(Extensions::new());
+
+        assert!(protocol.is_negotiated(&PeerWireProtocolMessage::Interested));
+        assert!(protocol.is_negotiated(&PeerWireProtocolMessage::KeepAlive));
+    }
+}
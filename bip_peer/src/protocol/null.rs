@@ -1,7 +1,7 @@
 use std::io::{self, Write};
 
 use message::NullProtocolMessage;
-use protocol::{PeerProtocol, NestedPeerProtocol};
+use protocol::{PeerProtocol, PeerProtocolFactory, ExtendedState};
 
 use bytes::Bytes;
 
@@ -43,8 +43,10 @@ impl PeerProtocol for NullProtocol {
     }
 }
 
-impl<M> NestedPeerProtocol<M> for NullProtocol {
-    fn received_message(&mut self, _message: &M) { }
+impl PeerProtocolFactory for NullProtocol {
+    type Protocol = NullProtocol;
 
-    fn sent_message(&mut self, _message: &M) { }
+    fn new_protocol(&self, _extended: &ExtendedState) -> NullProtocol {
+        NullProtocol
+    }
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
 use std::io::{self, Write};
 
-use protocol::{PeerProtocol, NestedPeerProtocol};
+use protocol::{PeerProtocol, PeerProtocolFactory, ExtendedState};
 
 use bytes::Bytes;
 
@@ -35,8 +35,10 @@ impl PeerProtocol for UnitProtocol {
     }
 }
 
-impl<M> NestedPeerProtocol<M> for UnitProtocol {
-    fn received_message(&mut self, _message: &M) { }
+impl PeerProtocolFactory for UnitProtocol {
+    type Protocol = UnitProtocol;
 
-    fn sent_message(&mut self, _message: &M) { }
+    fn new_protocol(&self, _extended: &ExtendedState) -> UnitProtocol {
+        UnitProtocol
+    }
 }
\ No newline at end of file
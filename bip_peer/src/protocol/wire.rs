@@ -1,37 +1,55 @@
 use std::io::{self, Write};
 
-use message::{PeerWireProtocolMessage, ExtendedMessage, BitsExtensionMessage};
-use protocol::{PeerProtocol, NestedPeerProtocol};
+use message::{PeerWireProtocolMessage, BitsExtensionMessage};
+use protocol::{PeerProtocol, PeerProtocolFactory, ExtendedState};
 
 use bytes::Bytes;
 
 /// Protocol for peer wire messages.
-pub struct PeerWireProtocol<P> {
-    ext_protocol: P
+pub struct PeerWireProtocol<F> where F: PeerProtocolFactory {
+    ext_factory:  F,
+    ext_protocol: F::Protocol,
+    extended:     ExtendedState
 }
 
-impl<P> PeerWireProtocol<P> {
-    /// Create a new `PeerWireProtocol` with the given extension protocol.
+impl<F> PeerWireProtocol<F> where F: PeerProtocolFactory {
+    /// Create a new `PeerWireProtocol` with the given extension protocol factory.
     ///
     /// Important to note that nested protocol should follow the same message length format
     /// as the peer wire protocol. This means it should expect a 4 byte (`u32`) message
     /// length prefix. Nested protocols will NOT have their `bytes_needed` method called.
-    pub fn new(ext_protocol: P) -> PeerWireProtocol<P> {
-        PeerWireProtocol{ ext_protocol: ext_protocol }
+    ///
+    /// The nested protocol is created from the extended handshake state of the peer, and is
+    /// re-created every time an `ExtendedMessage` is sent to, or received from, the peer.
+    pub fn new(ext_factory: F) -> PeerWireProtocol<F> {
+        let extended = ExtendedState::new();
+        let ext_protocol = ext_factory.new_protocol(&extended);
+
+        PeerWireProtocol{ ext_factory: ext_factory, ext_protocol: ext_protocol, extended: extended }
+    }
+
+    /// Extended handshake state for the peer.
+    pub fn extended_state(&self) -> &ExtendedState {
+        &self.extended
+    }
+
+    fn renew_protocol(&mut self) {
+        self.ext_protocol = self.ext_factory.new_protocol(&self.extended);
     }
 }
 
-impl<P> PeerProtocol for PeerWireProtocol<P> where P: PeerProtocol + NestedPeerProtocol<ExtendedMessage> {
-    type ProtocolMessage = PeerWireProtocolMessage<P>;
+impl<F> PeerProtocol for PeerWireProtocol<F> where F: PeerProtocolFactory {
+    type ProtocolMessage = PeerWireProtocolMessage<F::Protocol>;
 
     fn bytes_needed(&mut self, bytes: &[u8]) -> io::Result<Option<usize>> {
-        PeerWireProtocolMessage::<P>::bytes_needed(bytes)
+        PeerWireProtocolMessage::<F::Protocol>::bytes_needed(bytes)
     }
 
     fn parse_bytes(&mut self, bytes: Bytes) -> io::Result<Self::ProtocolMessage> {
         match PeerWireProtocolMessage::parse_bytes(bytes, &mut self.ext_protocol) {
             Ok(PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::Extended(msg))) => {
-                self.ext_protocol.received_message(&msg);
+                self.extended.theirs = Some(msg.clone());
+                self.renew_protocol();
 
                 Ok(PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::Extended(msg)))
             },
@@ -43,8 +61,9 @@ impl<P> PeerProtocol for PeerWireProtocol<P> where P: PeerProtocol + NestedPeerP
         where W: Write {
         match (message.write_bytes(writer, &mut self.ext_protocol), message) {
             (Ok(()), &PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::Extended(ref msg))) => {
-                self.ext_protocol.sent_message(msg);
-                
+                self.extended.ours = Some(msg.clone());
+                self.renew_protocol();
+
                 Ok(())
             },
             (other, _)                                                                                 => other
@@ -54,4 +73,63 @@ impl<P> PeerProtocol for PeerWireProtocol<P> where P: PeerProtocol + NestedPeerP
     fn message_size(&mut self, message: &Self::ProtocolMessage) -> usize {
         message.message_size(&mut self.ext_protocol)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PeerWireProtocol;
+    use message::{BitsExtensionMessage, ExtendedMessageBuilder, ExtendedType, PeerExtensionProtocolMessage, PeerWireProtocolMessage,
+                  UtMetadataMessage, UtMetadataRequestMessage};
+    use protocol::PeerProtocol;
+    use protocol::extension::PeerExtensionProtocolFactory;
+    use protocol::null::NullProtocol;
+
+    use bytes::Bytes;
+
+    fn ut_metadata_request(protocol: &mut PeerWireProtocol<PeerExtensionProtocolFactory<NullProtocol>>) -> Vec<u8> {
+        let message = PeerWireProtocolMessage::ProtExtension(PeerExtensionProtocolMessage::UtMetadata(
+            UtMetadataMessage::Request(UtMetadataRequestMessage::new(0))));
+
+        let mut bytes = Vec::new();
+        protocol.write_bytes(&message, &mut bytes).unwrap();
+
+        bytes
+    }
+
+    #[test]
+    fn positive_extended_state_initializes_nested_protocol() {
+        let extended = ExtendedMessageBuilder::new()
+            .with_extended_type(ExtendedType::UtMetadata, Some(5))
+            .build();
+        let extended_message = PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::Extended(extended));
+
+        let mut extended_bytes = Vec::new();
+        let mut ours = PeerWireProtocol::new(PeerExtensionProtocolFactory::new(NullProtocol::new()));
+        let mut theirs = PeerWireProtocol::new(PeerExtensionProtocolFactory::new(NullProtocol::new()));
+
+        // Both peers send each other the same extended message
+        ours.write_bytes(&extended_message, &mut extended_bytes).unwrap();
+        theirs.write_bytes(&extended_message, &mut Vec::new()).unwrap();
+        theirs.parse_bytes(Bytes::from(extended_bytes)).unwrap();
+
+        let request_bytes = ut_metadata_request(&mut theirs);
+
+        match ours.parse_bytes(Bytes::from(request_bytes)).unwrap() {
+            PeerWireProtocolMessage::ProtExtension(PeerExtensionProtocolMessage::UtMetadata(UtMetadataMessage::Request(request))) => {
+                assert_eq!(0, request.piece());
+            },
+            _ => panic!("bip_peer: Expected UtMetadata Request Message")
+        }
+    }
+
+    #[test]
+    fn negative_send_extension_message_before_extended_message() {
+        let mut protocol = PeerWireProtocol::new(PeerExtensionProtocolFactory::new(NullProtocol::new()));
+
+        let message = PeerWireProtocolMessage::ProtExtension(PeerExtensionProtocolMessage::UtMetadata(
+            UtMetadataMessage::Request(UtMetadataRequestMessage::new(0))));
+
+        assert!(protocol.write_bytes(&message, &mut Vec::new()).is_err());
+        assert!(protocol.extended_state().theirs().is_none());
+    }
+}
@@ -0,0 +1,261 @@
+//! In memory peers for testing code built on top of a `PeerManager`, without any sockets.
+//!
+//! A `MemoryPeer` is added to a `PeerManager` like any other peer, while its `RemotePeer`
+//! scripts what the peer sends, and receives what the `PeerManager` sent to the peer.
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::{StartSend, Poll, Async, AsyncSink, Future};
+use futures::sink::Sink;
+use futures::stream::Stream;
+use futures::sync::mpsc::{self, UnboundedSender, UnboundedReceiver};
+use tokio_timer::{Timer, Sleep};
+
+/// Create a connected `MemoryPeer` and `RemotePeer`.
+///
+/// The `MemoryPeer` accepts messages of type `I`, and yields messages of type `O`.
+pub fn memory_peer<I, O>() -> (MemoryPeer<I, O>, RemotePeer<I, O>) {
+    let (action_send, action_recv) = mpsc::unbounded();
+    let (incoming_send, incoming_recv) = mpsc::unbounded();
+    let behavior = Arc::new(Mutex::new(IncomingBehavior::Deliver));
+
+    let peer = MemoryPeer{ actions: action_recv, incoming: incoming_send, behavior: behavior.clone(),
+                           opt_timer: None, opt_sleep: None, closed: false };
+    let remote = RemotePeer{ actions: action_send, incoming: incoming_recv, behavior: behavior };
+
+    (peer, remote)
+}
+
+/// Scripted action taken by a `MemoryPeer`, in the order given to its `RemotePeer`.
+#[derive(Debug)]
+pub enum PeerAction<O> {
+    /// Yield the message from the peer.
+    Send(O),
+    /// Wait for the duration before taking the next action.
+    Delay(Duration),
+    /// End the stream of the peer, as if it disconnected.
+    Disconnect,
+    /// Error out the stream of the peer, with the given kind.
+    Error(io::ErrorKind)
+}
+
+/// Behavior of a `MemoryPeer` when messages are sent to it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IncomingBehavior {
+    /// Deliver messages to the `RemotePeer`.
+    Deliver,
+    /// Accept messages, but silently drop them.
+    Drop,
+    /// Fail to send messages, with the given kind.
+    Error(io::ErrorKind)
+}
+
+//----------------------------------------------------------------------------//
+
+/// Peer which can be added to a `PeerManager`, and is driven by a `RemotePeer`.
+pub struct MemoryPeer<I, O> {
+    actions:   UnboundedReceiver<PeerAction<O>>,
+    incoming:  UnboundedSender<I>,
+    behavior:  Arc<Mutex<IncomingBehavior>>,
+    opt_timer: Option<Timer>,
+    opt_sleep: Option<Sleep>,
+    closed:    bool
+}
+
+impl<I, O> MemoryPeer<I, O> {
+    fn start_delay(&mut self, duration: Duration) {
+        // Only spin up a timer for peers that actually delay
+        let sleep = self.opt_timer.get_or_insert_with(Timer::default).sleep(duration);
+
+        self.opt_sleep = Some(sleep);
+    }
+}
+
+impl<I, O> Sink for MemoryPeer<I, O> {
+    type SinkItem = I;
+    type SinkError = io::Error;
+
+    fn start_send(&mut self, item: I) -> StartSend<I, io::Error> {
+        if self.closed {
+            return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "Memory Peer Disconnected"))
+        }
+
+        let behavior = *self.behavior.lock()
+            .expect("bip_peer: MemoryPeer Failed To Lock Behavior");
+
+        match behavior {
+            IncomingBehavior::Deliver     => {
+                // Remote may have been dropped, in which case no one is interested in the message
+                let _ = self.incoming.unbounded_send(item);
+
+                Ok(AsyncSink::Ready)
+            },
+            IncomingBehavior::Drop        => Ok(AsyncSink::Ready),
+            IncomingBehavior::Error(kind) => Err(io::Error::new(kind, "Memory Peer Failed To Receive"))
+        }
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), io::Error> {
+        Ok(Async::Ready(()))
+    }
+}
+
+impl<I, O> Stream for MemoryPeer<I, O> {
+    type Item = O;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<O>, io::Error> {
+        loop {
+            if self.closed {
+                return Ok(Async::Ready(None))
+            }
+
+            if let Some(mut sleep) = self.opt_sleep.take() {
+                match sleep.poll() {
+                    Ok(Async::NotReady) => {
+                        self.opt_sleep = Some(sleep);
+
+                        return Ok(Async::NotReady)
+                    },
+                    Ok(Async::Ready(())) => (),
+                    Err(err)             => return Err(io::Error::new(io::ErrorKind::Other, err))
+                }
+            }
+
+            // Dropping the remote is the same as disconnecting
+            match self.actions.poll().expect("bip_peer: MemoryPeer Failed To Poll Actions") {
+                Async::Ready(Some(PeerAction::Send(message)))    => return Ok(Async::Ready(Some(message))),
+                Async::Ready(Some(PeerAction::Delay(duration)))  => self.start_delay(duration),
+                Async::Ready(Some(PeerAction::Error(kind)))      => return Err(io::Error::new(kind, "Memory Peer Failed To Send")),
+                Async::Ready(Some(PeerAction::Disconnect))       |
+                Async::Ready(None)                               => self.closed = true,
+                Async::NotReady                                  => return Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// Remote end of a `MemoryPeer`, used to script the peer.
+///
+/// Yields the messages that were delivered to the `MemoryPeer`.
+pub struct RemotePeer<I, O> {
+    actions:  UnboundedSender<PeerAction<O>>,
+    incoming: UnboundedReceiver<I>,
+    behavior: Arc<Mutex<IncomingBehavior>>
+}
+
+impl<I, O> RemotePeer<I, O> {
+    /// Queue the given action for the peer.
+    pub fn action(&self, action: PeerAction<O>) {
+        // Peer may have been dropped, in which case there is no one to take the action
+        let _ = self.actions.unbounded_send(action);
+    }
+
+    /// Queue the message to be sent from the peer.
+    pub fn send(&self, message: O) {
+        self.action(PeerAction::Send(message))
+    }
+
+    /// Queue a delay before the peer takes any further actions.
+    pub fn delay(&self, duration: Duration) {
+        self.action(PeerAction::Delay(duration))
+    }
+
+    /// Queue a disconnect of the peer.
+    pub fn disconnect(&self) {
+        self.action(PeerAction::Disconnect)
+    }
+
+    /// Queue an error for the peer.
+    pub fn error(&self, kind: io::ErrorKind) {
+        self.action(PeerAction::Error(kind))
+    }
+
+    /// Set the behavior of the peer for messages sent to it from now on.
+    ///
+    /// Defaults to `IncomingBehavior::Deliver`.
+    pub fn set_incoming(&self, behavior: IncomingBehavior) {
+        *self.behavior.lock()
+            .expect("bip_peer: RemotePeer Failed To Lock Behavior") = behavior;
+    }
+}
+
+impl<I, O> Stream for RemotePeer<I, O> {
+    type Item = I;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<I>, ()> {
+        self.incoming.poll()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::Duration;
+
+    use super::{MemoryPeer, IncomingBehavior};
+
+    use futures::{Future, Sink, Stream};
+
+    #[test]
+    fn positive_scripted_messages_in_order() {
+        let (peer, remote): (MemoryPeer<(), u32>, _) = super::memory_peer();
+
+        remote.send(1);
+        remote.delay(Duration::from_millis(10));
+        remote.send(2);
+        remote.disconnect();
+        remote.send(3);
+
+        assert_eq!(vec![1, 2], peer.collect().wait().unwrap());
+    }
+
+    #[test]
+    fn positive_dropping_remote_disconnects() {
+        let (peer, remote): (MemoryPeer<(), u32>, _) = super::memory_peer();
+
+        remote.send(1);
+        drop(remote);
+
+        assert_eq!(vec![1], peer.collect().wait().unwrap());
+    }
+
+    #[test]
+    fn negative_scripted_error() {
+        let (peer, remote): (MemoryPeer<(), u32>, _) = super::memory_peer();
+
+        remote.error(io::ErrorKind::ConnectionReset);
+
+        let error = peer.collect().wait().unwrap_err();
+        assert_eq!(io::ErrorKind::ConnectionReset, error.kind());
+    }
+
+    #[test]
+    fn positive_incoming_deliver_and_drop() {
+        let (peer, remote): (MemoryPeer<u32, ()>, _) = super::memory_peer();
+
+        let peer = peer.send(1).wait().unwrap();
+        remote.set_incoming(IncomingBehavior::Drop);
+        let peer = peer.send(2).wait().unwrap();
+        drop(peer);
+
+        assert_eq!(vec![1], remote.collect().wait().unwrap());
+    }
+
+    #[test]
+    fn negative_incoming_error() {
+        let (peer, remote): (MemoryPeer<u32, ()>, _) = super::memory_peer();
+
+        remote.set_incoming(IncomingBehavior::Error(io::ErrorKind::BrokenPipe));
+
+        match peer.send(1).wait() {
+            Err(error) => assert_eq!(io::ErrorKind::BrokenPipe, error.kind()),
+            Ok(_)      => panic!("Expected Send To Fail")
+        }
+    }
+}
@@ -12,7 +12,17 @@ use futures::sink::{Sink};
 use futures::stream::{Stream};
 use futures::sync::mpsc::{self, Sender, Receiver};
 
+mod peer_manager_ack_window;
+mod peer_manager_buffer_pool;
+mod peer_manager_keep_alive_limit;
+#[cfg(feature = "testing")]
+mod peer_manager_memory_peer;
+mod peer_manager_peer_config;
+mod peer_manager_purge_queued;
+mod peer_manager_replace_peer;
 mod peer_manager_send_backpressure;
+mod peer_manager_send_messages;
+mod peer_manager_streams_by_hash;
 
 pub struct ConnectedChannel<I, O> {
     send: Sender<I>,
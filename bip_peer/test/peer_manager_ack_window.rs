@@ -0,0 +1,57 @@
+use {ConnectedChannel};
+
+use bip_peer::{PeerManagerBuilder, PeerInfo, IPeerManagerMessage, OPeerManagerMessage};
+use bip_peer::protocols::{NullProtocol};
+use bip_peer::messages::PeerWireProtocolMessage;
+use bip_handshake::{Direction, Extensions, TransportKind};
+use bip_util::bt;
+use futures::Future;
+use futures::sink::Sink;
+use futures::stream::Stream;
+use tokio_core::reactor::Core;
+
+#[test]
+fn positive_peer_manager_ack_window() {
+    let mut core = Core::new().unwrap();
+    let manager = PeerManagerBuilder::new()
+        .with_ack_window(Some(5))
+        .build(core.handle());
+
+    let (peer, remote): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
+                         ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(5);
+    let peer_info = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    // Add the peer to the manager
+    let manager = core.run(manager.send(IPeerManagerMessage::AddPeer(peer_info, peer))).unwrap();
+
+    let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(peer_info, info),
+        _                                    => panic!("Unexpected First Peer Manager Response")
+    };
+
+    // Send a batch of messages, which should be acked once for the whole batch
+    let batch = vec![(7, PeerWireProtocolMessage::Interested), (8, PeerWireProtocolMessage::UnChoke)];
+    let manager = core.run(manager.send(IPeerManagerMessage::SendMessages(peer_info, batch))).unwrap();
+
+    let (response, _manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::SentMessages(info, mids) => {
+            assert_eq!(peer_info, info);
+            assert_eq!(vec![7, 8], mids);
+        },
+        _ => panic!("Unexpected Second Peer Manager Response")
+    };
+
+    let (opt_message, remote) = core.run(remote.into_future().map_err(|_| ())).unwrap();
+    match opt_message {
+        Some(PeerWireProtocolMessage::Interested) => (),
+        _                                         => panic!("Expected Interested Message On Peer")
+    };
+
+    let (opt_message, _remote) = core.run(remote.into_future().map_err(|_| ())).unwrap();
+    match opt_message {
+        Some(PeerWireProtocolMessage::UnChoke) => (),
+        _                                      => panic!("Expected UnChoke Message On Peer")
+    };
+}
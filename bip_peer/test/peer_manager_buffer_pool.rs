@@ -0,0 +1,58 @@
+use {ConnectedChannel};
+
+use bip_peer::{PeerManagerBuilder, PeerInfo, IPeerManagerMessage, OPeerManagerMessage};
+use bip_peer::protocols::{NullProtocol};
+use bip_peer::messages::{PeerWireProtocolMessage, PieceMessage};
+use bip_handshake::{Direction, Extensions, TransportKind};
+use bip_util::bt;
+use bip_util::pool::BufferPoolBuilder;
+use futures::Future;
+use futures::sink::Sink;
+use futures::stream::Stream;
+use tokio_core::reactor::Core;
+
+#[test]
+fn positive_peer_manager_recycles_sent_piece() {
+    let mut core = Core::new().unwrap();
+    let pool = BufferPoolBuilder::new().with_buffer_size(64).build();
+    let manager = PeerManagerBuilder::new()
+        .with_buffer_pool(Some(pool.clone()))
+        .build(core.handle());
+
+    let (peer, remote): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
+                         ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(5);
+    let peer_info = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    let manager = core.run(manager.send(IPeerManagerMessage::AddPeer(peer_info, peer))).unwrap();
+
+    let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(peer_info, info),
+        _                                    => panic!("Unexpected First Peer Manager Response")
+    };
+
+    let block = pool.alloc(64).freeze();
+    let piece = PeerWireProtocolMessage::Piece(PieceMessage::new(0, 0, block));
+    let manager = core.run(manager.send(IPeerManagerMessage::SendMessage(peer_info, 0, piece))).unwrap();
+
+    let (response, _manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::SentMessage(info, 0) => assert_eq!(peer_info, info),
+        _                                         => panic!("Unexpected Second Peer Manager Response")
+    };
+
+    // Payload is waiting on the remote end to drop its handle
+    assert_eq!(64, pool.stats().pending_bytes);
+
+    let (opt_message, _remote) = core.run(remote.into_future().map_err(|_| ())).unwrap();
+    match opt_message {
+        Some(PeerWireProtocolMessage::Piece(_)) => (),
+        _                                       => panic!("Expected Piece Message On Peer")
+    };
+    drop(opt_message);
+
+    pool.alloc(64);
+    let stats = pool.stats();
+    assert_eq!(1, stats.reused);
+    assert_eq!(0, stats.pending_bytes);
+}
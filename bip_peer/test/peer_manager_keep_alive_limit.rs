@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use {ConnectedChannel};
+
+use bip_peer::{PeerManagerBuilder, PeerInfo, IPeerManagerMessage, OPeerManagerMessage};
+use bip_peer::protocols::{NullProtocol};
+use bip_peer::messages::PeerWireProtocolMessage;
+use bip_handshake::{Direction, Extensions, TransportKind};
+use bip_util::bt;
+use futures::Future;
+use futures::sink::Sink;
+use futures::stream::Stream;
+use tokio_core::reactor::Core;
+
+#[test]
+fn positive_peer_manager_keep_alive_limit() {
+    let mut core = Core::new().unwrap();
+    let manager = PeerManagerBuilder::new()
+        .with_keep_alive_limit(Some((1, Duration::from_secs(60))))
+        .build(core.handle());
+
+    let (peer, remote): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
+                         ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(5);
+    let peer_info = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    // Add the peer to the manager
+    let manager = core.run(manager.send(IPeerManagerMessage::AddPeer(peer_info, peer))).unwrap();
+
+    let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(peer_info, info),
+        _                                    => panic!("Unexpected First Peer Manager Response")
+    };
+
+    // First keep alive is within the limit, second one exceeds it
+    let remote = core.run(remote.send(PeerWireProtocolMessage::KeepAlive)).unwrap();
+    let _remote = core.run(remote.send(PeerWireProtocolMessage::KeepAlive)).unwrap();
+
+    // Final statistics for the peer come right before it is removed
+    let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerStats(info, _) => assert_eq!(peer_info, info),
+        _                                       => panic!("Unexpected Peer Manager Stats Response")
+    };
+
+    let (response, _manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerError(info, _) => assert_eq!(peer_info, info),
+        _                                       => panic!("Unexpected Second Peer Manager Response")
+    };
+}
+
+#[test]
+fn positive_peer_manager_keep_alive_limit_opt_in() {
+    assert_eq!(None, PeerManagerBuilder::new().keep_alive_limit());
+}
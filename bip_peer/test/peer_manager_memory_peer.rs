@@ -0,0 +1,59 @@
+use std::io;
+use std::time::Duration;
+
+use bip_peer::{PeerManagerBuilder, PeerInfo, IPeerManagerMessage, OPeerManagerMessage};
+use bip_peer::protocols::{NullProtocol};
+use bip_peer::messages::PeerWireProtocolMessage;
+use bip_peer::testing::{self, MemoryPeer, RemotePeer};
+use bip_handshake::{Direction, Extensions, TransportKind};
+use bip_util::bt;
+use futures::sink::Sink;
+use futures::stream::Stream;
+use tokio_core::reactor::Core;
+
+type WireMessage = PeerWireProtocolMessage<NullProtocol>;
+
+#[test]
+fn positive_peer_manager_memory_peer() {
+    let mut core = Core::new().unwrap();
+    let manager = PeerManagerBuilder::new()
+        .build(core.handle());
+
+    let (peer, remote): (MemoryPeer<WireMessage, WireMessage>, RemotePeer<WireMessage, WireMessage>) = testing::memory_peer();
+    let peer_info = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    // Script the peer before it is added, so the manager sees the messages as soon as it polls the peer
+    remote.send(PeerWireProtocolMessage::Interested);
+    remote.delay(Duration::from_millis(50));
+    remote.send(PeerWireProtocolMessage::UnChoke);
+    remote.error(io::ErrorKind::ConnectionReset);
+
+    let manager = core.run(manager.send(IPeerManagerMessage::AddPeer(peer_info, peer))).unwrap();
+
+    let responses = core.run(manager.take(5).collect()).unwrap();
+    let mut responses = responses.into_iter();
+
+    match responses.next() {
+        Some(OPeerManagerMessage::PeerAdded(info)) => assert_eq!(peer_info, info),
+        _                                          => panic!("Expected PeerAdded")
+    };
+    match responses.next() {
+        Some(OPeerManagerMessage::ReceivedMessage(info, PeerWireProtocolMessage::Interested)) => assert_eq!(peer_info, info),
+        _                                                                                     => panic!("Expected Interested Message")
+    };
+    match responses.next() {
+        Some(OPeerManagerMessage::ReceivedMessage(info, PeerWireProtocolMessage::UnChoke)) => assert_eq!(peer_info, info),
+        _                                                                                  => panic!("Expected UnChoke Message")
+    };
+    match responses.next() {
+        Some(OPeerManagerMessage::PeerStats(info, _)) => assert_eq!(peer_info, info),
+        _                                             => panic!("Expected PeerStats")
+    };
+    match responses.next() {
+        Some(OPeerManagerMessage::PeerError(info, error)) => {
+            assert_eq!(peer_info, info);
+            assert_eq!(io::ErrorKind::ConnectionReset, error.kind());
+        },
+        _                                                 => panic!("Expected PeerError")
+    };
+}
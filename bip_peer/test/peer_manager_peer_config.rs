@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use {ConnectedChannel};
+
+use bip_peer::{PeerManagerBuilder, PeerInfo, PeerConfig, IPeerManagerMessage, OPeerManagerMessage};
+use bip_peer::protocols::{NullProtocol};
+use bip_peer::messages::PeerWireProtocolMessage;
+use bip_handshake::{Direction, Extensions, TransportKind};
+use bip_util::bt;
+use futures::Future;
+use futures::sink::Sink;
+use futures::stream::Stream;
+use tokio_core::reactor::Core;
+
+#[test]
+fn positive_peer_config_heartbeat_timeout() {
+    let mut core = Core::new().unwrap();
+    let builder = PeerManagerBuilder::new();
+    // Peer specific timeout, much shorter than the two minute default of the builder
+    let config = PeerConfig::from_builder(&builder)
+        .with_heartbeat_timeout(Duration::from_millis(200));
+    let manager = builder.build(core.handle());
+
+    let (peer, _remote): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
+                          ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(5);
+    let peer_info = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    let manager = core.run(manager.send(IPeerManagerMessage::AddPeerWithConfig(peer_info, peer, config))).unwrap();
+
+    let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(peer_info, info),
+        _                                    => panic!("Unexpected First Peer Manager Response")
+    };
+
+    // Remote never sends us anything, so the peer should time out
+    // Final statistics for the peer come right before it is removed
+    let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerStats(info, _) => assert_eq!(peer_info, info),
+        _                                       => panic!("Unexpected Peer Manager Stats Response")
+    };
+
+    let (response, _manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerDisconnect(info) => assert_eq!(peer_info, info),
+        _                                         => panic!("Unexpected Second Peer Manager Response")
+    };
+}
+
+#[test]
+fn positive_peer_config_heartbeat_interval() {
+    let mut core = Core::new().unwrap();
+    let builder = PeerManagerBuilder::new();
+    // Peer specific interval, much shorter than the one minute default of the builder
+    let config = PeerConfig::from_builder(&builder)
+        .with_heartbeat_interval(Duration::from_millis(200));
+    let manager = builder.build(core.handle());
+
+    let (peer, remote): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
+                         ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(5);
+    let peer_info = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    let manager = core.run(manager.send(IPeerManagerMessage::AddPeerWithConfig(peer_info, peer, config))).unwrap();
+
+    let (response, _manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(peer_info, info),
+        _                                    => panic!("Unexpected First Peer Manager Response")
+    };
+
+    // We never send the peer anything, so the manager should send it a keep alive
+    let (message, _remote) = core.run(remote.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match message {
+        PeerWireProtocolMessage::KeepAlive => (),
+        _                                  => panic!("Unexpected Remote Message")
+    };
+}
@@ -0,0 +1,141 @@
+use {ConnectedChannel};
+
+use bip_peer::{PeerManagerBuilder, PeerInfo, IPeerManagerMessage, OPeerManagerMessage};
+use bip_peer::protocols::{NullProtocol};
+use bip_peer::messages::{HaveMessage, PeerWireProtocolMessage};
+use bip_handshake::{Direction, Extensions, TransportKind};
+use bip_util::bt;
+use futures::{Future, AsyncSink};
+use futures::future;
+use futures::sink::Sink;
+use futures::stream::Stream;
+use tokio_core::reactor::Core;
+
+#[test]
+fn positive_peer_manager_purge_queued() {
+    let mut core = Core::new().unwrap();
+    let manager = PeerManagerBuilder::new()
+        .build(core.handle());
+
+    // Peer can only hold two messages before we have to read from the remote
+    let (peer, remote): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
+                         ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(1);
+    let peer_info = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    // Add the peer to the manager
+    let manager = core.run(manager.send(IPeerManagerMessage::AddPeer(peer_info, peer))).unwrap();
+
+    let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(peer_info, info),
+        _                                    => panic!("Unexpected First Peer Manager Response")
+    };
+
+    // Peer task hasnt run yet, so all of these messages are queued up
+    let mut manager = manager;
+    for mid in 0..3 {
+        manager = core.run(manager.send(IPeerManagerMessage::SendMessage(peer_info, mid, PeerWireProtocolMessage::Interested))).unwrap();
+    }
+    for mid in 3..5 {
+        let have = PeerWireProtocolMessage::Have(HaveMessage::new(mid as u32));
+        manager = core.run(manager.send(IPeerManagerMessage::SendMessage(peer_info, mid, have))).unwrap();
+    }
+
+    let purge_have = Box::new(|message: &PeerWireProtocolMessage<NullProtocol>| {
+        match message {
+            &PeerWireProtocolMessage::Have(_) => true,
+            _                                 => false
+        }
+    });
+    let manager = core.run(manager.send(IPeerManagerMessage::PurgeQueued(peer_info, purge_have))).unwrap();
+    let manager = core.run(manager.send(IPeerManagerMessage::SendMessage(peer_info, 5, PeerWireProtocolMessage::UnChoke))).unwrap();
+
+    // Have messages should never make it to the remote
+    let messages = core.run(remote.take(4).collect()).unwrap();
+    match &messages[..] {
+        &[PeerWireProtocolMessage::Interested, PeerWireProtocolMessage::Interested,
+          PeerWireProtocolMessage::Interested, PeerWireProtocolMessage::UnChoke] => (),
+        _                                                                        => panic!("Unexpected Messages On Peer")
+    };
+
+    let responses = core.run(manager.take(5).collect()).unwrap();
+    match &responses[..] {
+        &[OPeerManagerMessage::PurgedMessages(_, ref mids), OPeerManagerMessage::SentMessage(_, 0), OPeerManagerMessage::SentMessage(_, 1),
+          OPeerManagerMessage::SentMessage(_, 2), OPeerManagerMessage::SentMessage(_, 5)] => assert_eq!(&vec![3, 4], mids),
+        _                                                                                => panic!("Unexpected Peer Manager Responses")
+    };
+}
+
+#[test]
+fn positive_peer_manager_purge_queued_full_queue() {
+    let mut core = Core::new().unwrap();
+    let manager = PeerManagerBuilder::new()
+        .with_sink_buffer_capacity(2)
+        .build(core.handle());
+
+    let (peer, remote): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
+                         ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(5);
+    let peer_info = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    // Add the peer to the manager
+    let manager = core.run(manager.send(IPeerManagerMessage::AddPeer(peer_info, peer))).unwrap();
+
+    let (response, mut manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(peer_info, info),
+        _                                    => panic!("Unexpected First Peer Manager Response")
+    };
+
+    // Queue up have messages until the manager applies backpressure, then purge them without waiting on the peer
+    let (mids, manager) = future::lazy(move || {
+        let mut mids = Vec::new();
+
+        loop {
+            let mid = mids.len() as u64;
+            let have = PeerWireProtocolMessage::Have(HaveMessage::new(mid as u32));
+
+            match manager.start_send(IPeerManagerMessage::SendMessage(peer_info, mid, have)).unwrap() {
+                AsyncSink::Ready       => mids.push(mid),
+                AsyncSink::NotReady(_) => break
+            }
+        }
+
+        let purge_have = Box::new(|message: &PeerWireProtocolMessage<NullProtocol>| {
+            match message {
+                &PeerWireProtocolMessage::Have(_) => true,
+                _                                 => false
+            }
+        });
+        match manager.start_send(IPeerManagerMessage::PurgeQueued(peer_info, purge_have)).unwrap() {
+            AsyncSink::Ready       => (),
+            AsyncSink::NotReady(_) => panic!("Purge Stuck Behind Full Queue")
+        }
+
+        Ok::<_, ()>((mids, manager))
+    }).wait().unwrap();
+    assert!(!mids.is_empty());
+
+    let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PurgedMessages(info, purged) => {
+            assert_eq!(peer_info, info);
+            assert_eq!(mids, purged);
+        },
+        _ => panic!("Unexpected Second Peer Manager Response")
+    };
+
+    // Nothing was left to write to the peer
+    let manager = core.run(manager.send(IPeerManagerMessage::SendMessage(peer_info, 100, PeerWireProtocolMessage::UnChoke))).unwrap();
+
+    let (opt_message, _remote) = core.run(remote.into_future().map_err(|_| ())).unwrap();
+    match opt_message {
+        Some(PeerWireProtocolMessage::UnChoke) => (),
+        _                                      => panic!("Expected UnChoke Message On Peer")
+    };
+
+    let (response, _manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::SentMessage(_, 100) => (),
+        _                                        => panic!("Unexpected Third Peer Manager Response")
+    };
+}
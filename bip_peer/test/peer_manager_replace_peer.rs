@@ -0,0 +1,59 @@
+use {ConnectedChannel};
+
+use bip_peer::{PeerManagerBuilder, PeerInfo, IPeerManagerMessage, OPeerManagerMessage};
+use bip_peer::protocols::{NullProtocol};
+use bip_peer::messages::PeerWireProtocolMessage;
+use bip_handshake::{Direction, Extensions, TransportKind};
+use bip_util::bt;
+use futures::Future;
+use futures::sink::Sink;
+use futures::stream::Stream;
+use tokio_core::reactor::Core;
+
+#[test]
+fn positive_peer_manager_replace_peer() {
+    let mut core = Core::new().unwrap();
+    let manager = PeerManagerBuilder::new()
+        .build(core.handle());
+
+    // Create two connections, the second will replace the first
+    let (peer_one, _remote_one): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
+                                  ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(5);
+    let (peer_two, remote_two): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
+                                 ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(5);
+    let peer_info = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    // Add the peer to the manager
+    let manager = core.run(manager.send(IPeerManagerMessage::AddPeer(peer_info, peer_one))).unwrap();
+
+    let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(peer_info, info),
+        _                                    => panic!("Unexpected First Peer Manager Response")
+    };
+
+    // Replace the peer, then send a message which should go out on the new connection
+    let manager = core.run(manager.send(IPeerManagerMessage::ReplacePeer(peer_info, peer_two))).unwrap();
+    let manager = core.run(manager.send(IPeerManagerMessage::SendMessage(peer_info, 0, PeerWireProtocolMessage::Interested))).unwrap();
+
+    let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::SentMessage(info, 0) => assert_eq!(peer_info, info),
+        _                                         => panic!("Unexpected Second Peer Manager Response")
+    };
+
+    let (opt_message, remote_two) = core.run(remote_two.into_future().map_err(|_| ())).unwrap();
+    match opt_message {
+        Some(PeerWireProtocolMessage::Interested) => (),
+        _                                         => panic!("Expected Interested Message On Replaced Peer")
+    };
+
+    // Messages from the new connection should be received by the manager
+    core.run(remote_two.send(PeerWireProtocolMessage::UnChoke)).unwrap();
+
+    let (response, _manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::ReceivedMessage(info, PeerWireProtocolMessage::UnChoke) => assert_eq!(peer_info, info),
+        _                                                                            => panic!("Unexpected Third Peer Manager Response")
+    };
+}
@@ -3,7 +3,7 @@ use {ConnectedChannel};
 use bip_peer::{PeerManagerBuilder, PeerInfo, IPeerManagerMessage, OPeerManagerMessage};
 use bip_peer::protocols::{NullProtocol};
 use bip_peer::messages::PeerWireProtocolMessage;
-use bip_handshake::Extensions;
+use bip_handshake::{Direction, Extensions, TransportKind};
 use bip_util::bt;
 use futures::{future, Future, AsyncSink};
 use futures::sink::Sink;
@@ -20,8 +20,8 @@ fn positive_peer_manager_send_backpressure() {
     // Create two peers
     let (peer_one, peer_two): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
                                ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(5);
-    let peer_one_info = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new());
-    let peer_two_info = PeerInfo::new("127.0.0.1:1".parse().unwrap(), [1u8; bt::PEER_ID_LEN].into(), [1u8; bt::INFO_HASH_LEN].into(), Extensions::new());
+    let peer_one_info = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+    let peer_two_info = PeerInfo::new("127.0.0.1:1".parse().unwrap(), [1u8; bt::PEER_ID_LEN].into(), [1u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp);
 
     // Add peer one to the manager
     let manager = core.run(manager.send(IPeerManagerMessage::AddPeer(peer_one_info, peer_one))).unwrap();
@@ -45,6 +45,13 @@ fn positive_peer_manager_send_backpressure() {
     // Remove peer one from the manager
     let manager = core.run(manager.send(IPeerManagerMessage::RemovePeer(peer_one_info))).unwrap();
 
+    // Final statistics for the peer come right before it is removed
+    let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerStats(info, _) => assert_eq!(peer_one_info, info),
+        _                                       => panic!("Unexpected Peer Manager Stats Response")
+    };
+
     // Check that peer one was removed
     let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
     match response {
@@ -0,0 +1,93 @@
+use {ConnectedChannel};
+
+use bip_peer::{PeerManagerBuilder, PeerInfo, IPeerManagerMessage, OPeerManagerMessage};
+use bip_peer::protocols::{NullProtocol};
+use bip_peer::messages::PeerWireProtocolMessage;
+use bip_handshake::{Direction, Extensions, TransportKind};
+use bip_util::bt;
+use futures::{future, Future, AsyncSink};
+use futures::sink::Sink;
+use futures::stream::Stream;
+use tokio_core::reactor::Core;
+
+#[test]
+fn positive_peer_manager_send_messages() {
+    let mut core = Core::new().unwrap();
+    let manager = PeerManagerBuilder::new()
+        .build(core.handle());
+
+    let (peer, remote): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
+                         ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(5);
+    let peer_info = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    // Add the peer to the manager
+    let manager = core.run(manager.send(IPeerManagerMessage::AddPeer(peer_info, peer))).unwrap();
+
+    let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(peer_info, info),
+        _                                    => panic!("Unexpected First Peer Manager Response")
+    };
+
+    // Send a batch of messages, each of which should be acked individually, in order
+    let batch = vec![(0, PeerWireProtocolMessage::Interested), (1, PeerWireProtocolMessage::UnChoke)];
+    let manager = core.run(manager.send(IPeerManagerMessage::SendMessages(peer_info, batch))).unwrap();
+
+    let (response, manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::SentMessage(info, 0) => assert_eq!(peer_info, info),
+        _                                         => panic!("Unexpected Second Peer Manager Response")
+    };
+
+    let (response, _manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::SentMessage(info, 1) => assert_eq!(peer_info, info),
+        _                                         => panic!("Unexpected Third Peer Manager Response")
+    };
+
+    let (opt_message, remote) = core.run(remote.into_future().map_err(|_| ())).unwrap();
+    match opt_message {
+        Some(PeerWireProtocolMessage::Interested) => (),
+        _                                         => panic!("Expected Interested Message On Peer")
+    };
+
+    let (opt_message, _remote) = core.run(remote.into_future().map_err(|_| ())).unwrap();
+    match opt_message {
+        Some(PeerWireProtocolMessage::UnChoke) => (),
+        _                                      => panic!("Expected UnChoke Message On Peer")
+    };
+}
+
+#[test]
+fn positive_peer_manager_send_messages_backpressure() {
+    let mut core = Core::new().unwrap();
+    let manager = PeerManagerBuilder::new()
+        .with_sink_buffer_capacity(1)
+        .build(core.handle());
+
+    let (peer, _remote): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
+                          ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(5);
+    let peer_info = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    let manager = core.run(manager.send(IPeerManagerMessage::AddPeer(peer_info, peer))).unwrap();
+
+    let (response, mut manager) = core.run(manager.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(peer_info, info),
+        _                                    => panic!("Unexpected First Peer Manager Response")
+    };
+
+    // Batch is larger than the peer buffer, so only part of it should be accepted
+    let batch = (0..10).map(|mid| (mid, PeerWireProtocolMessage::Interested)).collect();
+    let response = core.run(future::lazy(|| {
+        future::ok::<_, ()>(manager.start_send(IPeerManagerMessage::SendMessages(peer_info, batch)))
+    })).unwrap();
+    match response {
+        Ok(AsyncSink::NotReady(IPeerManagerMessage::SendMessages(info, remaining))) => {
+            assert_eq!(peer_info, info);
+            assert!(!remaining.is_empty() && remaining.len() < 10);
+            assert_eq!(10 - remaining.len() as u64, remaining[0].0);
+        },
+        _ => panic!("Unexpected Send Messages Response")
+    };
+}
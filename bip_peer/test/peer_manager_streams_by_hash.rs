@@ -0,0 +1,150 @@
+use {ConnectedChannel};
+
+use bip_peer::{PeerManagerBuilder, PeerInfo, IPeerManagerMessage, OPeerManagerMessage};
+use bip_peer::protocols::{NullProtocol};
+use bip_peer::messages::PeerWireProtocolMessage;
+use bip_handshake::{Direction, Extensions, TransportKind};
+use bip_util::bt;
+use futures::{Async, Future};
+use futures::future;
+use futures::sink::Sink;
+use futures::stream::Stream;
+use futures::sync::oneshot;
+use tokio_core::reactor::Core;
+
+type PeerChannel = ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>;
+
+#[test]
+fn positive_peer_manager_streams_by_hash() {
+    let mut core = Core::new().unwrap();
+    let manager = PeerManagerBuilder::new()
+        .build(core.handle());
+    let (manager_send, manager_recv) = manager.into_parts();
+
+    let hash_one = [1u8; bt::INFO_HASH_LEN].into();
+    let hash_two = [2u8; bt::INFO_HASH_LEN].into();
+
+    let mut streams = manager_recv.streams_by_hash();
+    let stream_one = streams.add_torrent(hash_one).unwrap();
+    let stream_two = streams.add_torrent(hash_two).unwrap();
+    assert!(streams.add_torrent(hash_one).is_none());
+
+    let (peer_one, _remote_one): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
+                                  ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(5);
+    let (peer_two, _remote_two): (ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>,
+                                  ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>) = ::connected_channel(5);
+    let info_one = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), hash_one, Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+    let info_two = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), hash_two, Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    // Add a peer for the second torrent first, then the first torrent
+    let manager_send = core.run(manager_send.send(IPeerManagerMessage::AddPeer(info_two, peer_two))).unwrap();
+    core.run(manager_send.send(IPeerManagerMessage::AddPeer(info_one, peer_one))).unwrap();
+
+    // Each stream should only see the peer for its own torrent
+    let (response, _stream_one) = core.run(stream_one.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(info_one, info),
+        _                                    => panic!("Unexpected First Stream Response")
+    };
+
+    let (response, _stream_two) = core.run(stream_two.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(info_two, info),
+        _                                    => panic!("Unexpected Second Stream Response")
+    };
+}
+
+#[test]
+fn positive_peer_manager_streams_by_hash_drop_removes_torrent() {
+    let core = Core::new().unwrap();
+    let manager = PeerManagerBuilder::new()
+        .build::<ConnectedChannel<PeerWireProtocolMessage<NullProtocol>, PeerWireProtocolMessage<NullProtocol>>>(core.handle());
+    let (_manager_send, manager_recv) = manager.into_parts();
+
+    let hash = [1u8; bt::INFO_HASH_LEN].into();
+
+    let mut streams = manager_recv.streams_by_hash();
+    let stream = streams.add_torrent(hash).unwrap();
+    assert!(streams.contains_torrent(&hash));
+
+    drop(stream);
+    assert!(!streams.contains_torrent(&hash));
+    assert!(streams.add_torrent(hash).is_some());
+}
+
+#[test]
+fn positive_peer_manager_streams_by_hash_buffer_before_add() {
+    let mut core = Core::new().unwrap();
+    let manager = PeerManagerBuilder::new()
+        .build(core.handle());
+    let (manager_send, manager_recv) = manager.into_parts();
+
+    let hash_one = [1u8; bt::INFO_HASH_LEN].into();
+    let hash_two = [2u8; bt::INFO_HASH_LEN].into();
+
+    let mut streams = manager_recv.streams_by_hash();
+    let stream_two = streams.add_torrent(hash_two).unwrap();
+
+    let (peer_one, _remote_one): (PeerChannel, PeerChannel) = ::connected_channel(5);
+    let (peer_two, _remote_two): (PeerChannel, PeerChannel) = ::connected_channel(5);
+    let info_one = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), hash_one, Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+    let info_two = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), hash_two, Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    // Add a peer for the first torrent before it has a stream, then the second torrent
+    let manager_send = core.run(manager_send.send(IPeerManagerMessage::AddPeer(info_one, peer_one))).unwrap();
+    core.run(manager_send.send(IPeerManagerMessage::AddPeer(info_two, peer_two))).unwrap();
+
+    // Receiving on the second stream routes (and buffers) the message for the first torrent
+    let (response, _stream_two) = core.run(stream_two.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(info_two, info),
+        _                                    => panic!("Unexpected Second Stream Response")
+    };
+
+    let stream_one = streams.add_torrent(hash_one).unwrap();
+    let (response, _stream_one) = core.run(stream_one.into_future().map(|(opt_item, stream)| (opt_item.unwrap(), stream)).map_err(|_| ())).unwrap();
+    match response {
+        OPeerManagerMessage::PeerAdded(info) => assert_eq!(info_one, info),
+        _                                    => panic!("Unexpected First Stream Response")
+    };
+}
+
+#[test]
+fn positive_peer_manager_streams_by_hash_wake_other_task() {
+    let mut core = Core::new().unwrap();
+    let manager = PeerManagerBuilder::new()
+        .build(core.handle());
+    let (manager_send, manager_recv) = manager.into_parts();
+
+    let hash_one = [1u8; bt::INFO_HASH_LEN].into();
+    let hash_two = [2u8; bt::INFO_HASH_LEN].into();
+
+    let mut streams = manager_recv.streams_by_hash();
+    let stream_one = streams.add_torrent(hash_one).unwrap();
+    let mut stream_two = streams.add_torrent(hash_two).unwrap();
+
+    // Wait on the first stream from its own task
+    let (result_send, result_recv) = oneshot::channel();
+    core.handle().spawn(stream_one.into_future()
+        .map(|(opt_item, _)| { let _ = result_send.send(opt_item); })
+        .map_err(|_| ()));
+    core.turn(None);
+
+    // Second stream is the last one to wait on the underlying stream, but then goes away
+    core.run(future::poll_fn(|| {
+        assert!(stream_two.poll().unwrap().is_not_ready());
+
+        Ok::<_, ()>(Async::Ready(()))
+    })).unwrap();
+    drop(stream_two);
+
+    let (peer_one, _remote_one): (PeerChannel, PeerChannel) = ::connected_channel(5);
+    let info_one = PeerInfo::new("127.0.0.1:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), hash_one, Extensions::new(), Direction::Outbound, TransportKind::Tcp);
+
+    core.run(manager_send.send(IPeerManagerMessage::AddPeer(info_one, peer_one))).unwrap();
+
+    match core.run(result_recv).unwrap() {
+        Some(OPeerManagerMessage::PeerAdded(info)) => assert_eq!(info_one, info),
+        _                                          => panic!("Unexpected First Stream Response")
+    };
+}
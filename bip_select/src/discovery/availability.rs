@@ -0,0 +1,462 @@
+use ControlMessage;
+use bip_handshake::InfoHash;
+use bip_metainfo::Metainfo;
+use bip_peer::PeerInfo;
+use bip_peer::messages::{BitFieldMessage, HaveMessage};
+use bit_set::BitSet;
+use discovery::IDiscoveryMessage;
+use discovery::ODiscoveryMessage;
+use discovery::error::{DiscoveryError, DiscoveryErrorKind};
+use extended::ExtendedListener;
+use futures::Async;
+use futures::AsyncSink;
+use futures::Poll;
+use futures::Sink;
+use futures::StartSend;
+use futures::Stream;
+use futures::task;
+use futures::task::Task;
+use std::cmp;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::collections::hash_map::Entry;
+
+const DEFAULT_THRESHOLD: f64 = 1.0;
+const DEFAULT_LOW_TICKS: usize = 5;
+const DEFAULT_MAX_LOW_TICKS: usize = 80;
+
+struct TorrentAvailability {
+    piece_counts: Vec<usize>,
+    peers: HashMap<PeerInfo, BitSet>,
+    // Number of consecutive ticks availability has been below the threshold
+    ticks_below: usize,
+    // Number of consecutive ticks needed before we ask for more peers
+    ticks_needed: usize,
+}
+
+impl TorrentAvailability {
+    fn new(num_pieces: usize, ticks_needed: usize) -> TorrentAvailability {
+        TorrentAvailability {
+            piece_counts: vec![0; num_pieces],
+            peers: HashMap::new(),
+            ticks_below: 0,
+            ticks_needed: ticks_needed,
+        }
+    }
+
+    fn add_piece(&mut self, info: PeerInfo, index: usize) {
+        if index >= self.piece_counts.len() {
+            return;
+        }
+
+        if self.peers.entry(info).or_insert_with(BitSet::new).insert(index) {
+            self.piece_counts[index] += 1;
+        }
+    }
+
+    fn remove_peer(&mut self, info: &PeerInfo) {
+        if let Some(pieces) = self.peers.remove(info) {
+            for index in pieces.iter() {
+                self.piece_counts[index] -= 1;
+            }
+        }
+    }
+
+    fn distributed_copies(&self) -> Option<f64> {
+        distributed_copies(&self.piece_counts[..])
+    }
+}
+
+/// Module for requesting more peers when the availability of a torrent is poor.
+///
+/// Availability is measured as the number of distributed copies of a torrent
+/// across all connected peers (not including ourselves), as learned through
+/// `IDiscoveryMessage::ReceivedBitField` and `IDiscoveryMessage::ReceivedHave`.
+///
+/// When the availability stays below the threshold for a number of consecutive
+/// `ControlMessage::Tick`s, an `ODiscoveryMessage::NeedPeers` is emitted. Each time
+/// this happens while availability remains poor, the number of ticks we wait before
+/// asking again is doubled (up to a maximum). Once availability recovers, the number
+/// of ticks is reset.
+pub struct AvailabilityModule {
+    threshold: f64,
+    low_ticks: usize,
+    max_low_ticks: usize,
+    torrents: HashMap<InfoHash, TorrentAvailability>,
+    out_queue: VecDeque<ODiscoveryMessage>,
+    opt_stream: Option<Task>,
+}
+
+impl AvailabilityModule {
+    /// Create a new `AvailabilityModule`.
+    pub fn new() -> AvailabilityModule {
+        AvailabilityModule {
+            threshold: DEFAULT_THRESHOLD,
+            low_ticks: DEFAULT_LOW_TICKS,
+            max_low_ticks: DEFAULT_MAX_LOW_TICKS,
+            torrents: HashMap::new(),
+            out_queue: VecDeque::new(),
+            opt_stream: None,
+        }
+    }
+
+    /// Distributed copies below which a torrent is considered to have poor availability.
+    ///
+    /// Defaults to 1.0, meaning that our peers do not have a full copy of the torrent between them.
+    pub fn with_threshold(mut self, threshold: f64) -> AvailabilityModule {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Number of consecutive ticks availability has to be poor before we ask for more peers.
+    ///
+    /// Defaults to 5 ticks.
+    pub fn with_low_ticks(mut self, ticks: usize) -> AvailabilityModule {
+        self.low_ticks = cmp::max(1, ticks);
+        self
+    }
+
+    /// Maximum number of ticks that we will back off to between asking for more peers.
+    ///
+    /// Defaults to 80 ticks.
+    pub fn with_max_low_ticks(mut self, ticks: usize) -> AvailabilityModule {
+        self.max_low_ticks = ticks;
+        self
+    }
+
+    /// Current number of distributed copies for the given torrent.
+    ///
+    /// Returns `None` if the torrent has not been added, or if it has no pieces.
+    pub fn distributed_copies(&self, hash: &InfoHash) -> Option<f64> {
+        self.torrents
+            .get(hash)
+            .and_then(|torrent| torrent.distributed_copies())
+    }
+
+    fn add_torrent(&mut self, metainfo: Metainfo) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        let info_hash = metainfo.info().info_hash();
+        let low_ticks = self.low_ticks;
+
+        match self.torrents.entry(info_hash) {
+            Entry::Occupied(_) => {
+                Err(DiscoveryError::from_kind(DiscoveryErrorKind::InvalidMetainfoExists { hash: info_hash }))
+            },
+            Entry::Vacant(vac) => {
+                vac.insert(TorrentAvailability::new(metainfo.info().pieces().count(), low_ticks));
+
+                Ok(AsyncSink::Ready)
+            },
+        }
+    }
+
+    fn remove_torrent(&mut self, metainfo: Metainfo) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        let info_hash = metainfo.info().info_hash();
+
+        if self.torrents.remove(&info_hash).is_none() {
+            Err(DiscoveryError::from_kind(DiscoveryErrorKind::InvalidMetainfoNotExists { hash: info_hash }))
+        } else {
+            Ok(AsyncSink::Ready)
+        }
+    }
+
+    fn remove_peer(&mut self, info: PeerInfo) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        self.torrents
+            .get_mut(info.hash())
+            .map(|torrent| torrent.remove_peer(&info));
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn recv_bitfield(&mut self, info: PeerInfo, bitfield: BitFieldMessage) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        let info_hash = *info.hash();
+
+        self.torrents
+            .get_mut(&info_hash)
+            .map(|torrent| {
+                // A bitfield replaces anything we knew about the peer
+                torrent.remove_peer(&info);
+
+                for have in bitfield.iter() {
+                    torrent.add_piece(info, have.piece_index() as usize);
+                }
+
+                Ok(AsyncSink::Ready)
+            })
+            .unwrap_or_else(|| Err(DiscoveryError::from_kind(DiscoveryErrorKind::InvalidMetainfoNotExists { hash: info_hash })))
+    }
+
+    fn recv_have(&mut self, info: PeerInfo, have: HaveMessage) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        let info_hash = *info.hash();
+
+        self.torrents
+            .get_mut(&info_hash)
+            .map(|torrent| {
+                torrent.add_piece(info, have.piece_index() as usize);
+
+                Ok(AsyncSink::Ready)
+            })
+            .unwrap_or_else(|| Err(DiscoveryError::from_kind(DiscoveryErrorKind::InvalidMetainfoNotExists { hash: info_hash })))
+    }
+
+    fn apply_tick(&mut self) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        let (threshold, low_ticks, max_low_ticks) = (self.threshold, self.low_ticks, self.max_low_ticks);
+
+        for (hash, torrent) in self.torrents.iter_mut() {
+            let is_poor = torrent
+                .distributed_copies()
+                .map(|copies| copies < threshold)
+                .unwrap_or(false);
+
+            if is_poor {
+                torrent.ticks_below += 1;
+
+                if torrent.ticks_below >= torrent.ticks_needed {
+                    self.out_queue.push_back(ODiscoveryMessage::NeedPeers(*hash));
+
+                    // Back off from asking again if this doesnt help
+                    torrent.ticks_below = 0;
+                    torrent.ticks_needed = cmp::max(low_ticks, cmp::min(torrent.ticks_needed * 2, max_low_ticks));
+                }
+            } else {
+                torrent.ticks_below = 0;
+                torrent.ticks_needed = low_ticks;
+            }
+        }
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn check_stream_unblock(&mut self) {
+        // Check if stream is currently blocked AND we have messages to give it
+        let should_unblock = self.opt_stream.is_some() && !self.out_queue.is_empty();
+
+        if should_unblock {
+            self.opt_stream.take().unwrap().notify();
+        }
+    }
+}
+
+/// Distributed copies for the given piece counts.
+///
+/// This is the number of copies of the rarest piece, plus the fraction of
+/// pieces which have more copies than the rarest piece.
+fn distributed_copies(piece_counts: &[usize]) -> Option<f64> {
+    piece_counts.iter().cloned().min().map(|min_count| {
+        let num_above = piece_counts.iter().filter(|&&count| count > min_count).count();
+
+        min_count as f64 + num_above as f64 / piece_counts.len() as f64
+    })
+}
+
+//-------------------------------------------------------------------------------//
+
+impl ExtendedListener for AvailabilityModule {}
+
+//-------------------------------------------------------------------------------//
+
+impl Sink for AvailabilityModule {
+    type SinkItem = IDiscoveryMessage;
+    type SinkError = DiscoveryError;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        let start_send = match item {
+            IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo)) => {
+                self.add_torrent(metainfo)
+            },
+            IDiscoveryMessage::Control(ControlMessage::RemoveTorrent(metainfo)) => {
+                self.remove_torrent(metainfo)
+            },
+            IDiscoveryMessage::Control(ControlMessage::PeerDisconnected(info)) => {
+                self.remove_peer(info)
+            },
+            IDiscoveryMessage::Control(ControlMessage::Tick(_)) => {
+                self.apply_tick()
+            },
+            IDiscoveryMessage::ReceivedBitField(info, bitfield) => {
+                self.recv_bitfield(info, bitfield)
+            },
+            IDiscoveryMessage::ReceivedHave(info, have) => {
+                self.recv_have(info, have)
+            },
+            _ => {
+                Ok(AsyncSink::Ready)
+            },
+        };
+
+        // Check if we need to unblock the stream after performing our work
+        self.check_stream_unblock();
+
+        start_send
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        Ok(Async::Ready(()))
+    }
+}
+
+impl Stream for AvailabilityModule {
+    type Item = ODiscoveryMessage;
+    type Error = DiscoveryError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.out_queue.pop_front() {
+            Some(message) => {
+                Ok(Async::Ready(Some(message)))
+            },
+            None => {
+                self.opt_stream = Some(task::current());
+                Ok(Async::NotReady)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AvailabilityModule;
+    use ControlMessage;
+    use bip_handshake::{Direction, Extensions, TransportKind};
+    use bip_metainfo::{DirectAccessor, Metainfo, MetainfoBuilder, PieceLength};
+    use bip_peer::PeerInfo;
+    use bip_peer::messages::{BitFieldMessage, HaveMessage};
+    use bip_util::bt;
+    use bip_util::bt::InfoHash;
+    use bytes::Bytes;
+    use discovery::{IDiscoveryMessage, ODiscoveryMessage};
+    use futures::{Async, Sink, Stream};
+    use futures_test::harness::Harness;
+    use std::time::Duration;
+
+    fn metainfo(num_pieces: usize) -> Metainfo {
+        let data = vec![0u8; num_pieces];
+
+        let accessor = DirectAccessor::new("MyFile.txt", &data);
+        let bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(1))
+            .build(1, accessor, |_| ())
+            .unwrap();
+
+        Metainfo::from_bytes(bytes).unwrap()
+    }
+
+    fn peer_info(hash: InfoHash, port: u16) -> PeerInfo {
+        PeerInfo::new(
+            format!("127.0.0.1:{}", port).parse().unwrap(),
+            [0u8; bt::PEER_ID_LEN].into(),
+            hash,
+            Extensions::new(),
+            Direction::Outbound,
+            TransportKind::Tcp,
+        )
+    }
+
+    fn tick() -> IDiscoveryMessage {
+        IDiscoveryMessage::Control(ControlMessage::Tick(Duration::from_millis(100)))
+    }
+
+    #[test]
+    fn positive_distributed_copies() {
+        let mut module = AvailabilityModule::new();
+        let metainfo = metainfo(8);
+        let info_hash = metainfo.info().info_hash();
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+        module
+            .start_send(IDiscoveryMessage::ReceivedBitField(peer_info(info_hash, 1), BitFieldMessage::new(Bytes::from(vec![0xFF]))))
+            .unwrap();
+        module
+            .start_send(IDiscoveryMessage::ReceivedHave(peer_info(info_hash, 2), HaveMessage::new(0)))
+            .unwrap();
+        module
+            .start_send(IDiscoveryMessage::ReceivedHave(peer_info(info_hash, 2), HaveMessage::new(1)))
+            .unwrap();
+
+        assert_eq!(Some(1.25), module.distributed_copies(&info_hash));
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::PeerDisconnected(peer_info(info_hash, 1))))
+            .unwrap();
+
+        assert_eq!(Some(0.25), module.distributed_copies(&info_hash));
+    }
+
+    #[test]
+    fn positive_need_peers_after_low_ticks() {
+        let (send, recv) = AvailabilityModule::new().with_low_ticks(2).split();
+        let metainfo = metainfo(8);
+        let info_hash = metainfo.info().info_hash();
+
+        let mut block_send = send.wait();
+        let mut non_block_recv = Harness::new(recv);
+
+        block_send
+            .send(IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+
+        block_send.send(tick()).unwrap();
+        assert!(
+            non_block_recv
+                .poll_next()
+                .as_ref()
+                .map(Async::is_not_ready)
+                .unwrap_or(false)
+        );
+
+        block_send.send(tick()).unwrap();
+        assert_eq!(
+            Async::Ready(Some(ODiscoveryMessage::NeedPeers(info_hash))),
+            non_block_recv.poll_next().unwrap()
+        );
+    }
+
+    #[test]
+    fn positive_need_peers_backs_off() {
+        let mut module = AvailabilityModule::new().with_low_ticks(1).with_max_low_ticks(2);
+        let metainfo = metainfo(8);
+        let info_hash = metainfo.info().info_hash();
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+
+        // First request after one tick, then every two ticks
+        let mut num_requests = 0;
+        for _ in 0..5 {
+            module.start_send(tick()).unwrap();
+        }
+        while let Ok(Async::Ready(Some(ODiscoveryMessage::NeedPeers(hash)))) = Harness::new(&mut module).poll_next() {
+            assert_eq!(info_hash, hash);
+            num_requests += 1;
+        }
+
+        assert_eq!(3, num_requests);
+    }
+
+    #[test]
+    fn positive_no_need_peers_when_available() {
+        let (send, recv) = AvailabilityModule::new().with_low_ticks(1).split();
+        let metainfo = metainfo(8);
+        let info_hash = metainfo.info().info_hash();
+
+        let mut block_send = send.wait();
+        let mut non_block_recv = Harness::new(recv);
+
+        block_send
+            .send(IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+        block_send
+            .send(IDiscoveryMessage::ReceivedBitField(peer_info(info_hash, 1), BitFieldMessage::new(Bytes::from(vec![0xFF]))))
+            .unwrap();
+        block_send.send(tick()).unwrap();
+
+        assert!(
+            non_block_recv
+                .poll_next()
+                .as_ref()
+                .map(Async::is_not_ready)
+                .unwrap_or(false)
+        );
+    }
+}
@@ -1,3 +1,290 @@
-struct DhtModule {
-    
-}
\ No newline at end of file
+use ControlMessage;
+use bip_handshake::InfoHash;
+use bip_metainfo::Metainfo;
+use discovery::IDiscoveryMessage;
+use discovery::ODiscoveryMessage;
+use discovery::error::{DiscoveryError, DiscoveryErrorKind};
+use extended::ExtendedListener;
+use futures::Async;
+use futures::AsyncSink;
+use futures::Poll;
+use futures::Sink;
+use futures::StartSend;
+use futures::Stream;
+use futures::task;
+use futures::task::Task;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::collections::hash_map::Entry;
+use std::mem;
+use std::time::Duration;
+
+const DEFAULT_ANNOUNCE_INTERVAL_SECS: u64 = 15 * 60;
+
+/// Module for announcing torrents to the dht.
+///
+/// When a torrent is added, an announce is sent as a leecher. Once the torrent has been
+/// downloaded (`IDiscoveryMessage::TorrentCompleted`), an announce is sent as a seed, so
+/// that the dht can balance the seeds and leechers it gives out (BEP 33). Torrents are
+/// re-announced every announce interval, as measured by `ControlMessage::Tick`s.
+///
+/// Announces are yielded as `ODiscoveryMessage::SendDhtAnnounce`, which should be mapped
+/// to `MainlineDht::search` or `MainlineDht::search_as_seed`, depending on the seed flag.
+pub struct DhtModule {
+    interval: Duration,
+    elapsed: Duration,
+    // Whether or not we are seeding each torrent
+    torrents: HashMap<InfoHash, bool>,
+    out_queue: VecDeque<ODiscoveryMessage>,
+    opt_stream: Option<Task>,
+}
+
+impl DhtModule {
+    /// Create a new `DhtModule`.
+    pub fn new() -> DhtModule {
+        DhtModule {
+            interval: Duration::from_secs(DEFAULT_ANNOUNCE_INTERVAL_SECS),
+            elapsed: Duration::from_secs(0),
+            torrents: HashMap::new(),
+            out_queue: VecDeque::new(),
+            opt_stream: None,
+        }
+    }
+
+    /// Time between announces for each torrent.
+    ///
+    /// Defaults to 15 minutes.
+    pub fn with_announce_interval(mut self, interval: Duration) -> DhtModule {
+        self.interval = interval;
+        self
+    }
+
+    fn add_torrent(&mut self, metainfo: Metainfo) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        let info_hash = metainfo.info().info_hash();
+
+        match self.torrents.entry(info_hash) {
+            Entry::Occupied(_) => {
+                return Err(DiscoveryError::from_kind(DiscoveryErrorKind::InvalidMetainfoExists { hash: info_hash }))
+            },
+            Entry::Vacant(vac) => {
+                vac.insert(false);
+            },
+        }
+        self.out_queue.push_back(ODiscoveryMessage::SendDhtAnnounce(info_hash, false));
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn remove_torrent(&mut self, metainfo: Metainfo) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        let info_hash = metainfo.info().info_hash();
+
+        if self.torrents.remove(&info_hash).is_none() {
+            return Err(DiscoveryError::from_kind(DiscoveryErrorKind::InvalidMetainfoNotExists { hash: info_hash }))
+        }
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn torrent_completed(&mut self, hash: InfoHash) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        let newly_seeding = self.torrents
+            .get_mut(&hash)
+            .map(|is_seed| !mem::replace(is_seed, true))
+            .unwrap_or(false);
+
+        if newly_seeding {
+            self.out_queue.push_back(ODiscoveryMessage::SendDhtAnnounce(hash, true));
+        }
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn apply_tick(&mut self, duration: Duration) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        self.elapsed += duration;
+
+        if self.elapsed >= self.interval {
+            self.elapsed = Duration::from_secs(0);
+
+            for (&hash, &is_seed) in self.torrents.iter() {
+                self.out_queue.push_back(ODiscoveryMessage::SendDhtAnnounce(hash, is_seed));
+            }
+        }
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn check_stream_unblock(&mut self) {
+        // Check if stream is currently blocked AND we have messages to give it
+        let should_unblock = self.opt_stream.is_some() && !self.out_queue.is_empty();
+
+        if should_unblock {
+            self.opt_stream.take().unwrap().notify();
+        }
+    }
+}
+
+//-------------------------------------------------------------------------------//
+
+impl ExtendedListener for DhtModule {}
+
+//-------------------------------------------------------------------------------//
+
+impl Sink for DhtModule {
+    type SinkItem = IDiscoveryMessage;
+    type SinkError = DiscoveryError;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        let start_send = match item {
+            IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo)) => {
+                self.add_torrent(metainfo)
+            },
+            IDiscoveryMessage::Control(ControlMessage::RemoveTorrent(metainfo)) => {
+                self.remove_torrent(metainfo)
+            },
+            IDiscoveryMessage::Control(ControlMessage::Tick(duration)) => {
+                self.apply_tick(duration)
+            },
+            IDiscoveryMessage::TorrentCompleted(hash) => {
+                self.torrent_completed(hash)
+            },
+            _ => {
+                Ok(AsyncSink::Ready)
+            },
+        };
+
+        // Check if we need to unblock the stream after performing our work
+        self.check_stream_unblock();
+
+        start_send
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        Ok(Async::Ready(()))
+    }
+}
+
+impl Stream for DhtModule {
+    type Item = ODiscoveryMessage;
+    type Error = DiscoveryError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.out_queue.pop_front() {
+            Some(message) => {
+                Ok(Async::Ready(Some(message)))
+            },
+            None => {
+                self.opt_stream = Some(task::current());
+                Ok(Async::NotReady)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DhtModule;
+    use ControlMessage;
+    use bip_metainfo::{DirectAccessor, Metainfo, MetainfoBuilder, PieceLength};
+    use discovery::{IDiscoveryMessage, ODiscoveryMessage};
+    use futures::{Async, Sink};
+    use futures_test::harness::Harness;
+    use std::time::Duration;
+
+    fn metainfo() -> Metainfo {
+        let data = vec![0u8; 8];
+
+        let accessor = DirectAccessor::new("MyFile.txt", &data);
+        let bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(1))
+            .build(1, accessor, |_| ())
+            .unwrap();
+
+        Metainfo::from_bytes(bytes).unwrap()
+    }
+
+    fn next_announce(module: &mut DhtModule) -> Option<ODiscoveryMessage> {
+        match Harness::new(module).poll_next() {
+            Ok(Async::Ready(Some(message))) => Some(message),
+            Ok(Async::NotReady) => None,
+            other => panic!("Unexpected Poll: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn positive_announce_as_leecher_on_add() {
+        let mut module = DhtModule::new();
+        let metainfo = metainfo();
+        let info_hash = metainfo.info().info_hash();
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+
+        assert_eq!(Some(ODiscoveryMessage::SendDhtAnnounce(info_hash, false)), next_announce(&mut module));
+        assert_eq!(None, next_announce(&mut module));
+    }
+
+    #[test]
+    fn positive_announce_as_seed_once_completed() {
+        let mut module = DhtModule::new();
+        let metainfo = metainfo();
+        let info_hash = metainfo.info().info_hash();
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+        next_announce(&mut module);
+
+        module.start_send(IDiscoveryMessage::TorrentCompleted(info_hash)).unwrap();
+        assert_eq!(Some(ODiscoveryMessage::SendDhtAnnounce(info_hash, true)), next_announce(&mut module));
+
+        // Completing a torrent that is already seeding does not announce again
+        module.start_send(IDiscoveryMessage::TorrentCompleted(info_hash)).unwrap();
+        assert_eq!(None, next_announce(&mut module));
+    }
+
+    #[test]
+    fn positive_reannounce_on_interval() {
+        let mut module = DhtModule::new().with_announce_interval(Duration::from_secs(10));
+        let metainfo = metainfo();
+        let info_hash = metainfo.info().info_hash();
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+        module.start_send(IDiscoveryMessage::TorrentCompleted(info_hash)).unwrap();
+        next_announce(&mut module);
+        next_announce(&mut module);
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::Tick(Duration::from_secs(5))))
+            .unwrap();
+        assert_eq!(None, next_announce(&mut module));
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::Tick(Duration::from_secs(5))))
+            .unwrap();
+        assert_eq!(Some(ODiscoveryMessage::SendDhtAnnounce(info_hash, true)), next_announce(&mut module));
+    }
+
+    #[test]
+    fn negative_no_announce_after_remove() {
+        let mut module = DhtModule::new().with_announce_interval(Duration::from_secs(10));
+        let metainfo = metainfo();
+        let info_hash = metainfo.info().info_hash();
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo.clone())))
+            .unwrap();
+        next_announce(&mut module);
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::RemoveTorrent(metainfo)))
+            .unwrap();
+        module.start_send(IDiscoveryMessage::TorrentCompleted(info_hash)).unwrap();
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::Tick(Duration::from_secs(10))))
+            .unwrap();
+
+        assert_eq!(None, next_announce(&mut module));
+    }
+}
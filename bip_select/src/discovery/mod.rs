@@ -4,14 +4,24 @@ use ControlMessage;
 use bip_handshake::InfoHash;
 use bip_metainfo::Metainfo;
 use bip_peer::PeerInfo;
+use bip_peer::messages::{BitFieldMessage, HaveMessage};
 use bip_peer::messages::UtMetadataMessage;
 use bip_utracker::announce::ClientState;
+use bip_utracker::option::AnnounceOptions;
 use std::net::SocketAddr;
 
 pub mod error;
 
+mod availability;
+mod dht;
+mod tiers;
+mod udp_tracker;
 mod ut_metadata;
 
+pub use self::availability::AvailabilityModule;
+pub use self::dht::DhtModule;
+pub use self::tiers::TrackerTiers;
+pub use self::udp_tracker::UdpTrackerModule;
 pub use self::ut_metadata::UtMetadataModule;
 
 /// Enumeration of discovery messages that can be sent to a discovery module.
@@ -23,17 +33,41 @@ pub enum IDiscoveryMessage {
     DownloadMetainfo(InfoHash),
     /// Received a UtMetadata message.
     ReceivedUtMetadataMessage(PeerInfo, UtMetadataMessage),
+    /// Received a `BitFieldMessage`.
+    ReceivedBitField(PeerInfo, BitFieldMessage),
+    /// Received a `HaveMessage`.
+    ReceivedHave(PeerInfo, HaveMessage),
+    /// Udp tracker at the given address responded to an announce for the `InfoHash`.
+    UdpTrackerResponded(InfoHash, SocketAddr),
+    /// Udp tracker at the given address failed to respond to an announce for the `InfoHash`.
+    UdpTrackerFailed(InfoHash, SocketAddr),
+    /// We have finished downloading the torrent for the `InfoHash`, and are now seeding it.
+    TorrentCompleted(InfoHash),
 }
 
 /// Enumeration of discovery messages that can be received from a discovery module.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ODiscoveryMessage {
-    /// Send a dht announce for the `InfoHash`.
-    SendDhtAnnounce(InfoHash),
-    /// Send a udp tracker announce for the `InfoHash`.
-    SendUdpTrackerAnnounce(InfoHash, SocketAddr, ClientState),
+    /// Send a dht announce for the `InfoHash`, as a seed if the flag is set.
+    ///
+    /// The flag should be set once the torrent has completed, so that the dht can balance
+    /// the seeds and leechers it gives out (see `MainlineDht::search_as_seed`).
+    SendDhtAnnounce(InfoHash, bool),
+    /// Send a udp tracker announce for the `InfoHash`, with the given options attached.
+    ///
+    /// The options carry the URL data (BEP 41) of the tracker, if it had any.
+    SendUdpTrackerAnnounce(InfoHash, SocketAddr, ClientState, AnnounceOptions<'static>),
     /// Send a UtMetadata message.
     SendUtMetadataMessage(PeerInfo, UtMetadataMessage),
     /// We have finished downloading the given `Metainfo`.
     DownloadedMetainfo(Metainfo),
+    /// We need more peers for the `InfoHash`.
+    ///
+    /// This is a hint that trackers should be re-announced to, and the dht re-searched.
+    NeedPeers(InfoHash),
+    /// Peer sent us invalid or malicious data, and should be banned.
+    ///
+    /// The peer should be removed from the `PeerManager` (and optionally reported
+    /// to a `ReputationModule`), the module will ignore any further messages from it.
+    BanPeer(PeerInfo),
 }
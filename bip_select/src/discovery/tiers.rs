@@ -0,0 +1,190 @@
+use bip_metainfo::Metainfo;
+use rand::{self, Rng};
+
+/// Ordering of the trackers for a torrent, following the announce-list semantics of BEP 12.
+///
+/// Trackers within each tier are shuffled on creation. Announces should be sent to the
+/// current tracker; if it fails, the next tracker in the tier is tried, falling through
+/// to the next tier only once every tracker in the current tier has failed. When a tracker
+/// responds, it is promoted to the front of its tier, and the next announce starts over
+/// from the first tier.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackerTiers {
+    tiers: Vec<Vec<String>>,
+    // Position of the current tracker as (tier index, tracker index)
+    current: (usize, usize),
+}
+
+impl TrackerTiers {
+    /// Create a new `TrackerTiers` from the given announce-list, shuffling each tier.
+    ///
+    /// Empty tiers are discarded.
+    pub fn new(announce_list: &[Vec<String>]) -> TrackerTiers {
+        let mut rng = rand::thread_rng();
+
+        let tiers = announce_list
+            .iter()
+            .filter(|tier| !tier.is_empty())
+            .map(|tier| {
+                let mut tier = tier.clone();
+                rng.shuffle(&mut tier);
+
+                tier
+            })
+            .collect();
+
+        TrackerTiers::with_tiers(tiers)
+    }
+
+    /// Create a new `TrackerTiers` from the given `Metainfo`.
+    ///
+    /// If the `Metainfo` does not have an announce-list, the main tracker is used as the only tier.
+    pub fn from_metainfo(metainfo: &Metainfo) -> TrackerTiers {
+        match (metainfo.trackers(), metainfo.main_tracker()) {
+            (Some(announce_list), _) if announce_list.iter().any(|tier| !tier.is_empty()) => TrackerTiers::new(announce_list),
+            (_, Some(main_tracker)) => TrackerTiers::with_tiers(vec![vec![main_tracker.to_owned()]]),
+            (_, None) => TrackerTiers::with_tiers(Vec::new()),
+        }
+    }
+
+    fn with_tiers(tiers: Vec<Vec<String>>) -> TrackerTiers {
+        TrackerTiers {
+            tiers: tiers,
+            current: (0, 0),
+        }
+    }
+
+    /// Tracker that the next announce should be sent to, if we have any trackers.
+    pub fn current(&self) -> Option<&str> {
+        let (tier_index, tracker_index) = self.current;
+
+        self.tiers
+            .get(tier_index)
+            .and_then(|tier| tier.get(tracker_index))
+            .map(|tracker| &tracker[..])
+    }
+
+    /// Resolved ordering of the trackers, grouped by tier.
+    ///
+    /// This reflects both the initial shuffle and any promotions that have occurred.
+    pub fn tiers(&self) -> &[Vec<String>] {
+        &self.tiers
+    }
+
+    /// Resolved ordering of the trackers, flattened across tiers.
+    pub fn ordering(&self) -> Vec<&str> {
+        self.tiers
+            .iter()
+            .flat_map(|tier| tier.iter())
+            .map(|tracker| &tracker[..])
+            .collect()
+    }
+
+    /// The given tracker responded to an announce.
+    ///
+    /// The tracker is moved to the front of its tier, and the next announce will be sent to
+    /// the first tracker of the first tier. Returns false if the tracker is unknown.
+    pub fn tracker_succeeded(&mut self, tracker: &str) -> bool {
+        match self.position(tracker) {
+            Some((tier_index, tracker_index)) => {
+                let tracker = self.tiers[tier_index].remove(tracker_index);
+                self.tiers[tier_index].insert(0, tracker);
+
+                self.current = (0, 0);
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The given tracker failed to respond to an announce.
+    ///
+    /// If it was the current tracker, we advance to the next tracker in the tier, or the first
+    /// tracker of the next tier if the tier has been exhausted. Once every tier has been exhausted,
+    /// we wrap back around to the first tier. Returns false if the tracker is unknown.
+    pub fn tracker_failed(&mut self, tracker: &str) -> bool {
+        if self.current() == Some(tracker) {
+            let (tier_index, tracker_index) = self.current;
+
+            self.current = if tracker_index + 1 < self.tiers[tier_index].len() {
+                (tier_index, tracker_index + 1)
+            } else if tier_index + 1 < self.tiers.len() {
+                (tier_index + 1, 0)
+            } else {
+                (0, 0)
+            };
+
+            true
+        } else {
+            self.position(tracker).is_some()
+        }
+    }
+
+    fn position(&self, tracker: &str) -> Option<(usize, usize)> {
+        self.tiers.iter().enumerate().filter_map(|(tier_index, tier)| {
+            tier.iter()
+                .position(|tier_tracker| tier_tracker == tracker)
+                .map(|tracker_index| (tier_index, tracker_index))
+        }).next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrackerTiers;
+
+    fn announce_list() -> Vec<Vec<String>> {
+        vec![
+            vec!["udp://a.example.com:6969".to_owned(), "udp://b.example.com:6969".to_owned()],
+            vec![],
+            vec!["udp://c.example.com:6969".to_owned()],
+        ]
+    }
+
+    #[test]
+    fn positive_shuffle_keeps_tiers() {
+        let tiers = TrackerTiers::new(&announce_list());
+
+        assert_eq!(2, tiers.tiers().len());
+        assert_eq!(2, tiers.tiers()[0].len());
+        assert!(tiers.tiers()[0].contains(&"udp://a.example.com:6969".to_owned()));
+        assert!(tiers.tiers()[0].contains(&"udp://b.example.com:6969".to_owned()));
+        assert_eq!(vec!["udp://c.example.com:6969".to_owned()], tiers.tiers()[1]);
+    }
+
+    #[test]
+    fn positive_fall_through_tiers_on_failure() {
+        let mut tiers = TrackerTiers::new(&announce_list());
+        let ordering: Vec<String> = tiers.ordering().into_iter().map(String::from).collect();
+
+        for tracker in ordering.iter() {
+            assert_eq!(Some(&tracker[..]), tiers.current());
+            assert!(tiers.tracker_failed(tracker));
+        }
+
+        // Wrapped back around to the first tier
+        assert_eq!(Some(&ordering[0][..]), tiers.current());
+    }
+
+    #[test]
+    fn positive_promote_responding_tracker() {
+        let mut tiers = TrackerTiers::new(&announce_list());
+
+        let first = tiers.current().unwrap().to_owned();
+        tiers.tracker_failed(&first);
+        let second = tiers.current().unwrap().to_owned();
+        assert!(tiers.tracker_succeeded(&second));
+
+        assert_eq!(Some(&second[..]), tiers.current());
+        assert_eq!(vec![second.clone(), first.clone()], tiers.tiers()[0]);
+    }
+
+    #[test]
+    fn negative_unknown_tracker() {
+        let mut tiers = TrackerTiers::new(&announce_list());
+
+        assert!(!tiers.tracker_succeeded("udp://unknown.example.com:6969"));
+        assert!(!tiers.tracker_failed("udp://unknown.example.com:6969"));
+    }
+}
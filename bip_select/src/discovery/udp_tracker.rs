@@ -1,3 +1,464 @@
-struct UdpTrackerModule {
-    
-}
\ No newline at end of file
+use ControlMessage;
+use bip_handshake::InfoHash;
+use bip_metainfo::Metainfo;
+use bip_utracker::announce::{AnnounceEvent, ClientState};
+use bip_utracker::option::{AnnounceOptions, OptionsBuilder};
+use discovery::IDiscoveryMessage;
+use discovery::ODiscoveryMessage;
+use discovery::TrackerTiers;
+use discovery::error::{DiscoveryError, DiscoveryErrorKind};
+use extended::ExtendedListener;
+use futures::Async;
+use futures::AsyncSink;
+use futures::Poll;
+use futures::Sink;
+use futures::StartSend;
+use futures::Stream;
+use futures::task;
+use futures::task::Task;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::collections::hash_map::Entry;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+const DEFAULT_ANNOUNCE_INTERVAL_SECS: u64 = 30 * 60;
+
+/// Resolved address of a udp tracker, along with the URL data (BEP 41) to attach to announces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct AnnounceTarget {
+    addr: SocketAddr,
+    options: AnnounceOptions<'static>,
+}
+
+struct TorrentTrackers {
+    tiers: TrackerTiers,
+    // Trackers we have resolved, so responses (which only carry an address) can be mapped back to them
+    targets: HashMap<String, Option<AnnounceTarget>>,
+    // Event of the announce that is currently in progress
+    event: AnnounceEvent,
+}
+
+impl TorrentTrackers {
+    fn new(tiers: TrackerTiers) -> TorrentTrackers {
+        TorrentTrackers {
+            tiers: tiers,
+            targets: HashMap::new(),
+            event: AnnounceEvent::Started,
+        }
+    }
+
+    fn target(&mut self, tracker: &str) -> Option<AnnounceTarget> {
+        self.targets
+            .entry(tracker.to_owned())
+            .or_insert_with(|| announce_target(tracker))
+            .clone()
+    }
+
+    /// Tracker which is currently being announced to, if it resolved to the given address.
+    fn current_at(&self, addr: SocketAddr) -> Option<String> {
+        self.tiers
+            .current()
+            .and_then(|tracker| {
+                match self.targets.get(tracker) {
+                    Some(&Some(ref target)) if target.addr == addr => Some(tracker.to_owned()),
+                    _ => None,
+                }
+            })
+    }
+}
+
+/// Module for announcing torrents to their udp trackers, following the announce-list semantics of BEP 12.
+///
+/// When a torrent is added, a started announce is sent to the first tracker of the (shuffled)
+/// first tier. Trackers are tried one at a time; when the tracker client reports that a tracker
+/// failed (`IDiscoveryMessage::UdpTrackerFailed`), the announce falls through to the next tracker,
+/// until every tier has been exhausted. Responding trackers (`IDiscoveryMessage::UdpTrackerResponded`)
+/// are promoted to the front of their tier. Torrents are re-announced every announce interval,
+/// as measured by `ControlMessage::Tick`s, and a stopped announce is sent when they are removed.
+///
+/// Trackers that are not udp trackers, or that could not be resolved, are skipped over. The path and
+/// query of each tracker URL are attached to its announces as URL data (BEP 41).
+pub struct UdpTrackerModule {
+    interval: Duration,
+    elapsed: Duration,
+    torrents: HashMap<InfoHash, TorrentTrackers>,
+    out_queue: VecDeque<ODiscoveryMessage>,
+    opt_stream: Option<Task>,
+}
+
+impl UdpTrackerModule {
+    /// Create a new `UdpTrackerModule`.
+    pub fn new() -> UdpTrackerModule {
+        UdpTrackerModule {
+            interval: Duration::from_secs(DEFAULT_ANNOUNCE_INTERVAL_SECS),
+            elapsed: Duration::from_secs(0),
+            torrents: HashMap::new(),
+            out_queue: VecDeque::new(),
+            opt_stream: None,
+        }
+    }
+
+    /// Time between announces for each torrent.
+    ///
+    /// Defaults to 30 minutes.
+    pub fn with_announce_interval(mut self, interval: Duration) -> UdpTrackerModule {
+        self.interval = interval;
+        self
+    }
+
+    /// Resolved ordering of the trackers for the given torrent, if it has been added.
+    pub fn tiers(&self, hash: &InfoHash) -> Option<&TrackerTiers> {
+        self.torrents.get(hash).map(|torrent| &torrent.tiers)
+    }
+
+    fn add_torrent(&mut self, metainfo: Metainfo) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        let info_hash = metainfo.info().info_hash();
+
+        match self.torrents.entry(info_hash) {
+            Entry::Occupied(_) => {
+                return Err(DiscoveryError::from_kind(DiscoveryErrorKind::InvalidMetainfoExists { hash: info_hash }))
+            },
+            Entry::Vacant(vac) => {
+                vac.insert(TorrentTrackers::new(TrackerTiers::from_metainfo(&metainfo)));
+            },
+        }
+        self.announce(info_hash, AnnounceEvent::Started);
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn remove_torrent(&mut self, metainfo: Metainfo) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        let info_hash = metainfo.info().info_hash();
+
+        if !self.torrents.contains_key(&info_hash) {
+            return Err(DiscoveryError::from_kind(DiscoveryErrorKind::InvalidMetainfoNotExists { hash: info_hash }))
+        }
+        self.announce(info_hash, AnnounceEvent::Stopped);
+        self.torrents.remove(&info_hash);
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn tracker_responded(&mut self, hash: InfoHash, addr: SocketAddr) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        if let Some(torrent) = self.torrents.get_mut(&hash) {
+            if let Some(tracker) = torrent.current_at(addr) {
+                torrent.tiers.tracker_succeeded(&tracker);
+            }
+        }
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn tracker_failed(&mut self, hash: InfoHash, addr: SocketAddr) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        let opt_event = self.torrents.get_mut(&hash).and_then(|torrent| {
+            torrent.current_at(addr).and_then(|tracker| {
+                torrent.tiers.tracker_failed(&tracker);
+
+                // Once every tier has been exhausted, wait until the next interval before trying again
+                if torrent.tiers.ordering().first() == torrent.tiers.current().as_ref() {
+                    None
+                } else {
+                    Some(torrent.event)
+                }
+            })
+        });
+
+        if let Some(event) = opt_event {
+            self.announce(hash, event);
+        }
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn apply_tick(&mut self, duration: Duration) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        self.elapsed += duration;
+
+        if self.elapsed >= self.interval {
+            self.elapsed = Duration::from_secs(0);
+
+            let hashes: Vec<InfoHash> = self.torrents.keys().cloned().collect();
+            for hash in hashes {
+                self.announce(hash, AnnounceEvent::None);
+            }
+        }
+
+        Ok(AsyncSink::Ready)
+    }
+
+    /// Queue an announce for the torrent to its current tracker, skipping over any trackers we cant announce to.
+    fn announce(&mut self, hash: InfoHash, event: AnnounceEvent) {
+        let torrent = match self.torrents.get_mut(&hash) {
+            Some(torrent) => torrent,
+            None => return,
+        };
+        torrent.event = event;
+
+        for _ in 0..torrent.tiers.ordering().len() {
+            let tracker = match torrent.tiers.current() {
+                Some(tracker) => tracker.to_owned(),
+                None => return,
+            };
+
+            match torrent.target(&tracker) {
+                Some(target) => {
+                    let state = ClientState::new(0, 0, 0, event);
+
+                    self.out_queue.push_back(ODiscoveryMessage::SendUdpTrackerAnnounce(hash, target.addr, state, target.options));
+                    return;
+                },
+                None => {
+                    torrent.tiers.tracker_failed(&tracker);
+                },
+            }
+        }
+    }
+
+    fn check_stream_unblock(&mut self) {
+        // Check if stream is currently blocked AND we have messages to give it
+        let should_unblock = self.opt_stream.is_some() && !self.out_queue.is_empty();
+
+        if should_unblock {
+            self.opt_stream.take().unwrap().notify();
+        }
+    }
+}
+
+/// Resolve the given tracker, if it is a udp tracker, along with the URL data to attach to its announces.
+fn announce_target(tracker: &str) -> Option<AnnounceTarget> {
+    if !tracker.starts_with("udp://") {
+        return None;
+    }
+    let rest = &tracker["udp://".len()..];
+    let (host_port, url_data) = match rest.find(|c| c == '/' || c == '?') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, ""),
+    };
+
+    let opt_addr = host_port.to_socket_addrs().ok().and_then(|mut addrs| addrs.next());
+    opt_addr.map(|addr| {
+        let options = if url_data.is_empty() {
+            AnnounceOptions::new()
+        } else {
+            OptionsBuilder::new().with_url_data(url_data.as_bytes()).build()
+        };
+
+        AnnounceTarget {
+            addr: addr,
+            options: options,
+        }
+    })
+}
+
+//-------------------------------------------------------------------------------//
+
+impl ExtendedListener for UdpTrackerModule {}
+
+//-------------------------------------------------------------------------------//
+
+impl Sink for UdpTrackerModule {
+    type SinkItem = IDiscoveryMessage;
+    type SinkError = DiscoveryError;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        let start_send = match item {
+            IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo)) => {
+                self.add_torrent(metainfo)
+            },
+            IDiscoveryMessage::Control(ControlMessage::RemoveTorrent(metainfo)) => {
+                self.remove_torrent(metainfo)
+            },
+            IDiscoveryMessage::Control(ControlMessage::Tick(duration)) => {
+                self.apply_tick(duration)
+            },
+            IDiscoveryMessage::UdpTrackerResponded(hash, addr) => {
+                self.tracker_responded(hash, addr)
+            },
+            IDiscoveryMessage::UdpTrackerFailed(hash, addr) => {
+                self.tracker_failed(hash, addr)
+            },
+            _ => {
+                Ok(AsyncSink::Ready)
+            },
+        };
+
+        // Check if we need to unblock the stream after performing our work
+        self.check_stream_unblock();
+
+        start_send
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        Ok(Async::Ready(()))
+    }
+}
+
+impl Stream for UdpTrackerModule {
+    type Item = ODiscoveryMessage;
+    type Error = DiscoveryError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.out_queue.pop_front() {
+            Some(message) => {
+                Ok(Async::Ready(Some(message)))
+            },
+            None => {
+                self.opt_stream = Some(task::current());
+                Ok(Async::NotReady)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{UdpTrackerModule, announce_target};
+    use ControlMessage;
+    use bip_metainfo::{DirectAccessor, Metainfo, MetainfoBuilder, PieceLength};
+    use bip_utracker::announce::{AnnounceEvent, ClientState};
+    use bip_utracker::option::{AnnounceOptions, OptionsBuilder};
+    use discovery::{IDiscoveryMessage, ODiscoveryMessage};
+    use futures::{Async, Sink, Stream};
+    use futures_test::harness::Harness;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    fn metainfo(trackers: &Vec<Vec<String>>) -> Metainfo {
+        let data = vec![0u8; 8];
+
+        let accessor = DirectAccessor::new("MyFile.txt", &data);
+        let bytes = MetainfoBuilder::new()
+            .set_trackers(Some(trackers))
+            .set_piece_length(PieceLength::Custom(1))
+            .build(1, accessor, |_| ())
+            .unwrap();
+
+        Metainfo::from_bytes(bytes).unwrap()
+    }
+
+    fn trackers() -> Vec<Vec<String>> {
+        vec![
+            vec!["udp://127.0.0.1:6969/announce".to_owned()],
+            vec!["udp://127.0.0.1:6970".to_owned()],
+        ]
+    }
+
+    fn addr(port: u16) -> SocketAddr {
+        format!("127.0.0.1:{}", port).parse().unwrap()
+    }
+
+    fn next_announce(module: &mut UdpTrackerModule) -> Option<(SocketAddr, AnnounceEvent, AnnounceOptions<'static>)> {
+        match Harness::new(module).poll_next() {
+            Ok(Async::Ready(Some(ODiscoveryMessage::SendUdpTrackerAnnounce(_, addr, state, options)))) => Some((addr, state.event(), options)),
+            Ok(Async::NotReady) => None,
+            other => panic!("Unexpected Poll: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn positive_announce_target_url_data() {
+        let target = announce_target("udp://127.0.0.1:6969/announce?passkey=abc").unwrap();
+
+        assert_eq!(addr(6969), target.addr);
+        assert_eq!(OptionsBuilder::new().with_url_data(b"/announce?passkey=abc").build(), target.options);
+    }
+
+    #[test]
+    fn negative_announce_target_http_tracker() {
+        assert_eq!(None, announce_target("http://127.0.0.1:6969/announce"));
+    }
+
+    #[test]
+    fn positive_announce_started_to_first_tier() {
+        let mut module = UdpTrackerModule::new();
+        let metainfo = metainfo(&trackers());
+        let info_hash = metainfo.info().info_hash();
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+
+        assert_eq!(
+            Async::Ready(Some(ODiscoveryMessage::SendUdpTrackerAnnounce(
+                info_hash,
+                addr(6969),
+                ClientState::new(0, 0, 0, AnnounceEvent::Started),
+                OptionsBuilder::new().with_url_data(b"/announce").build()
+            ))),
+            Harness::new(&mut module).poll_next().unwrap()
+        );
+        assert_eq!(None, next_announce(&mut module));
+    }
+
+    #[test]
+    fn positive_fall_through_tiers_until_exhausted() {
+        let mut module = UdpTrackerModule::new();
+        let metainfo = metainfo(&trackers());
+        let info_hash = metainfo.info().info_hash();
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+        assert_eq!(Some(addr(6969)), next_announce(&mut module).map(|(addr, _, _)| addr));
+
+        module
+            .start_send(IDiscoveryMessage::UdpTrackerFailed(info_hash, addr(6969)))
+            .unwrap();
+        assert_eq!(
+            Some((addr(6970), AnnounceEvent::Started, AnnounceOptions::new())),
+            next_announce(&mut module)
+        );
+
+        // Every tier has failed, so we wait for the next interval
+        module
+            .start_send(IDiscoveryMessage::UdpTrackerFailed(info_hash, addr(6970)))
+            .unwrap();
+        assert_eq!(None, next_announce(&mut module));
+    }
+
+    #[test]
+    fn positive_reannounce_to_responding_tracker() {
+        let mut module = UdpTrackerModule::new().with_announce_interval(Duration::from_millis(200));
+        let metainfo = metainfo(&vec![vec!["udp://127.0.0.1:6969".to_owned(), "udp://127.0.0.1:6970".to_owned()]]);
+        let info_hash = metainfo.info().info_hash();
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+        let first = next_announce(&mut module).unwrap().0;
+
+        module
+            .start_send(IDiscoveryMessage::UdpTrackerFailed(info_hash, first))
+            .unwrap();
+        let second = next_announce(&mut module).unwrap().0;
+        assert!(first != second);
+
+        module
+            .start_send(IDiscoveryMessage::UdpTrackerResponded(info_hash, second))
+            .unwrap();
+        assert_eq!(vec![format!("udp://{}", second), format!("udp://{}", first)], module.tiers(&info_hash).unwrap().tiers()[0]);
+
+        for _ in 0..2 {
+            module
+                .start_send(IDiscoveryMessage::Control(ControlMessage::Tick(Duration::from_millis(100))))
+                .unwrap();
+        }
+        assert_eq!(Some((second, AnnounceEvent::None, AnnounceOptions::new())), next_announce(&mut module));
+    }
+
+    #[test]
+    fn positive_announce_stopped_on_remove() {
+        let mut module = UdpTrackerModule::new();
+        let metainfo = metainfo(&trackers());
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo.clone())))
+            .unwrap();
+        next_announce(&mut module).unwrap();
+
+        module
+            .start_send(IDiscoveryMessage::Control(ControlMessage::RemoveTorrent(metainfo)))
+            .unwrap();
+        assert_eq!(Some(AnnounceEvent::Stopped), next_announce(&mut module).map(|(_, event, _)| event));
+    }
+}
@@ -23,7 +23,7 @@ use futures::StartSend;
 use futures::Stream;
 use futures::task;
 use futures::task::Task;
-use rand::{self, Rng};
+use std::cmp;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
@@ -37,10 +37,19 @@ const MAX_REQUEST_SIZE: usize = 16 * 1024;
 const MAX_ACTIVE_REQUESTS: usize = 100;
 const MAX_PEER_REQUESTS: usize = 100;
 
+// Outstanding requests we will have with any single peer, so pieces are spread across the swarm
+const MAX_REQUESTS_PER_PEER: usize = 4;
+// Number of peers a piece may be requested from at once, once all pieces have been requested
+const MAX_ENDGAME_REQUESTS: usize = 2;
+
+const DEFAULT_MAX_METADATA_SIZE: i64 = 8 * 1024 * 1024;
+
 struct PendingInfo {
     messages: Vec<UtMetadataRequestMessage>,
+    received: Vec<bool>,
     left: usize,
     bytes: Vec<u8>,
+    sources: HashSet<PeerInfo>,
 }
 
 struct ActiveRequest {
@@ -55,8 +64,31 @@ struct PeerRequest {
 }
 
 struct ActivePeers {
-    peers: HashSet<PeerInfo>,
-    metadata_size: i64,
+    peers: HashMap<PeerInfo, i64>,
+}
+
+impl ActivePeers {
+    fn new() -> ActivePeers {
+        ActivePeers { peers: HashMap::new() }
+    }
+
+    /// Whether or not any peer advertised the given metadata size.
+    fn advertises(&self, metadata_size: i64) -> bool {
+        self.peers.values().any(|&size| size == metadata_size)
+    }
+
+    /// Metadata size advertised by the most peers, preferring the smaller size on ties.
+    fn majority_size(&self) -> Option<i64> {
+        let mut size_counts = HashMap::new();
+        for &size in self.peers.values() {
+            *size_counts.entry(size).or_insert(0) += 1;
+        }
+
+        size_counts
+            .into_iter()
+            .max_by_key(|&(size, count)| (count, cmp::Reverse(size)))
+            .map(|(size, _)| size)
+    }
 }
 
 /// Module for sending/receiving metadata from other peers.
@@ -68,12 +100,29 @@ struct ActivePeers {
 /// Metadata will be retrieved when `IDiscoveryMessage::DownloadMetadata`
 /// is received, and will be served when
 /// `IDiscoveryMessage::Control(ControlMessage::AddTorrent)` is received.
+///
+/// Metadata pieces are requested in parallel from all peers advertising
+/// `ut_metadata`, with each piece going to the peer with the fewest outstanding
+/// requests. Once every piece has been requested, outstanding pieces are also
+/// requested from other peers (endgame), and the first response wins.
+///
+/// Peers are grouped by the metadata size they advertise, and metadata is downloaded
+/// from the peers agreeing on the most common size. If the downloaded metadata fails
+/// the info hash check, peers that sent us pieces of it are no longer downloaded from,
+/// but are not banned, since we cant tell which of them sent the bad piece.
+///
+/// Peers advertising a metadata size larger than the configured maximum, or
+/// sending metadata pieces that dont match their advertised size, will be banned
+/// via `ODiscoveryMessage::BanPeer`.
 pub struct UtMetadataModule {
+    max_metadata_size: i64,
     completed_map: HashMap<InfoHash, Vec<u8>>,
     pending_map: HashMap<InfoHash, Option<PendingInfo>>,
     active_peers: HashMap<InfoHash, ActivePeers>,
     active_requests: Vec<ActiveRequest>,
     peer_requests: VecDeque<PeerRequest>,
+    banned_peers: HashSet<PeerInfo>,
+    ban_queue: VecDeque<PeerInfo>,
     opt_sink: Option<Task>,
     opt_stream: Option<Task>,
 }
@@ -82,16 +131,28 @@ impl UtMetadataModule {
     /// Create a new `UtMetadataModule`.
     pub fn new() -> UtMetadataModule {
         UtMetadataModule {
+            max_metadata_size: DEFAULT_MAX_METADATA_SIZE,
             completed_map: HashMap::new(),
             pending_map: HashMap::new(),
             active_peers: HashMap::new(),
             active_requests: Vec::new(),
             peer_requests: VecDeque::new(),
+            banned_peers: HashSet::new(),
+            ban_queue: VecDeque::new(),
             opt_sink: None,
             opt_stream: None,
         }
     }
 
+    /// Maximum metadata size, in bytes, that we will download from peers.
+    ///
+    /// Peers advertising a larger metadata size will be banned, since we would
+    /// have to allocate the advertised size up front. Defaults to 8 MiB.
+    pub fn with_max_metadata_size(mut self, max_size: i64) -> UtMetadataModule {
+        self.max_metadata_size = max_size;
+        self
+    }
+
     fn add_torrent(&mut self, metainfo: Metainfo) -> StartSend<IDiscoveryMessage, DiscoveryError> {
         let info_hash = metainfo.info().info_hash();
 
@@ -100,7 +161,7 @@ impl UtMetadataModule {
                 Err(DiscoveryError::from_kind(DiscoveryErrorKind::InvalidMetainfoExists { hash: info_hash }))
             },
             Entry::Vacant(vac) => {
-                let info_bytes = metainfo.info().to_bytes();
+                let info_bytes = metainfo.info().raw_bytes().to_vec();
                 vac.insert(info_bytes);
 
                 Ok(AsyncSink::Ready)
@@ -122,6 +183,10 @@ impl UtMetadataModule {
     }
 
     fn add_peer(&mut self, info: PeerInfo, ext_info: &ExtendedPeerInfo) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        if self.banned_peers.contains(&info) {
+            return Ok(AsyncSink::Ready);
+        }
+
         let our_support = ext_info
             .our_message()
             .and_then(|msg| msg.query_id(&ExtendedType::UtMetadata))
@@ -143,17 +208,17 @@ impl UtMetadataModule {
         );
         // If peer supports it, but they dont have the metadata size, then they probably dont have the file yet...
         match (our_support, they_support, opt_metadata_size) {
+            (true, true, Some(metadata_size)) if metadata_size <= 0 || metadata_size > self.max_metadata_size => {
+                info!("Banning Peer {:?} For Advertising Metadata Size {:?}", info.addr(), metadata_size);
+                self.ban_peer(info);
+            },
             (true, true, Some(metadata_size)) => {
+                // Peers disagreeing on the size cant all be right, but we dont know who is lying until we check the hash
                 self.active_peers
                     .entry(*info.hash())
-                    .or_insert_with(|| {
-                        ActivePeers {
-                            peers: HashSet::new(),
-                            metadata_size: metadata_size,
-                        }
-                    })
+                    .or_insert_with(ActivePeers::new)
                     .peers
-                    .insert(info);
+                    .insert(info, metadata_size);
             },
             _ => {
                 ()
@@ -164,6 +229,14 @@ impl UtMetadataModule {
     }
 
     fn remove_peer(&mut self, info: PeerInfo) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        // Peer may be re-added if it reconnects, future bans are up to the client (or a `ReputationModule`)
+        self.banned_peers.remove(&info);
+        self.remove_active_peer(info);
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn remove_active_peer(&mut self, info: PeerInfo) {
         let empty_peers = if let Some(active_peers) = self.active_peers.get_mut(info.hash()) {
             active_peers.peers.remove(&info);
 
@@ -175,8 +248,33 @@ impl UtMetadataModule {
         if empty_peers {
             self.active_peers.remove(&info.hash());
         }
+    }
 
-        Ok(AsyncSink::Ready)
+    fn ban_peer(&mut self, info: PeerInfo) {
+        if !self.banned_peers.insert(info) {
+            return;
+        }
+
+        self.remove_active_peer(info);
+        self.ban_queue.push_back(info);
+
+        // Any requests we made to the peer wont be fulfilled
+        self.requeue_peer_requests(info);
+    }
+
+    /// Push all requests we made to the peer back to pending.
+    fn requeue_peer_requests(&mut self, info: PeerInfo) {
+        let pending_map = &mut self.pending_map;
+
+        self.active_requests.retain(|request| {
+            let sent_to_peer = request.sent_to == info;
+
+            if sent_to_peer {
+                requeue_request(pending_map, request);
+            }
+
+            !sent_to_peer
+        });
     }
 
     fn apply_tick(&mut self, duration: Duration) -> StartSend<IDiscoveryMessage, DiscoveryError> {
@@ -195,13 +293,7 @@ impl UtMetadataModule {
                 }
 
                 // Push request back to pending
-                pending_map
-                    .get_mut(&request.sent_to.hash())
-                    .map(|opt_pending| {
-                        opt_pending.as_mut().map(|pending| {
-                            pending.messages.push(request.message);
-                        })
-                    });
+                requeue_request(pending_map, request);
             }
 
             !is_expired
@@ -244,30 +336,71 @@ impl UtMetadataModule {
             .iter()
             .position(|request| request.sent_to == info && request.message.piece() == data.piece());
 
-        // If so, go ahead and process it, if not, ignore it (could ban peer...)
+        // If so, go ahead and process it, if not, ignore it (may have been sent after our request timed out)
         if let Some(index) = opt_index {
-            self.active_requests.swap_remove(index);
-
-            if let Some(&mut Some(ref mut pending)) = self.pending_map.get_mut(&info.hash()) {
-                let data_offset = (data.piece() as usize) * MAX_REQUEST_SIZE;
+            let request = self.active_requests.swap_remove(index);
+
+            let is_valid = match self.pending_map.get_mut(&info.hash()) {
+                Some(&mut Some(ref mut pending)) => {
+                    let is_valid = is_valid_data(pending.bytes.len(), &data);
+                    let piece_index = data.piece() as usize;
+
+                    // Piece may have been requeued (or duplicated in the endgame), only count it once
+                    if is_valid && !pending.received[piece_index] {
+                        let data_offset = piece_index * MAX_REQUEST_SIZE;
+
+                        pending.left -= 1;
+                        pending.received[piece_index] = true;
+                        pending.sources.insert(info);
+                        pending.messages.retain(|message| message.piece() != data.piece());
+                        (&mut pending.bytes.as_mut_slice()[data_offset..])
+                            .write(data.data().as_ref())
+                            .unwrap();
+                    }
 
-                pending.left -= 1;
-                (&mut pending.bytes.as_mut_slice()[data_offset..])
-                    .write(data.data().as_ref())
-                    .unwrap();
+                    is_valid
+                },
+                _ => true,
+            };
+
+            if is_valid {
+                // Any duplicate requests for the piece are no longer needed
+                self.active_requests.retain(|request| {
+                    request.sent_to.hash() != info.hash() || request.message.piece() != data.piece()
+                });
+            } else {
+                info!("Banning Peer {:?} For Sending Invalid Metadata Piece {:?}", info.addr(), data.piece());
+                requeue_request(&mut self.pending_map, &request);
+                self.ban_peer(info);
             }
         }
 
         Ok(AsyncSink::Ready)
     }
 
-    fn recv_reject(&mut self, _info: PeerInfo, _reject: UtMetadataRejectMessage) -> StartSend<IDiscoveryMessage, DiscoveryError> {
-        // TODO: Remove any requests after receiving a reject, for now, we will just timeout
+    fn recv_reject(&mut self, info: PeerInfo, reject: UtMetadataRejectMessage) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        let requested = self.active_requests
+            .iter()
+            .any(|request| request.sent_to == info && request.message.piece() == reject.piece());
+
+        // Peer probably doesnt have the metadata, so give any pieces we asked it for to other
+        // peers, instead of waiting for the rest of our requests to it to time out
+        if requested {
+            self.requeue_peer_requests(info);
+            self.remove_active_peer(info);
+        }
+
         Ok(AsyncSink::Ready)
     }
 
     //-------------------------------------------------------------------------------//
 
+    fn retrieve_ban(&mut self) -> Option<Result<ODiscoveryMessage, DiscoveryError>> {
+        self.ban_queue
+            .pop_front()
+            .map(|info| Ok(ODiscoveryMessage::BanPeer(info)))
+    }
+
     fn retrieve_completed_download(&mut self) -> Option<Result<ODiscoveryMessage, DiscoveryError>> {
         let opt_completed_hash = self.pending_map
             .iter()
@@ -285,7 +418,7 @@ impl UtMetadataModule {
             // Clean up other structures since the download is complete
             self.active_peers.remove(&completed_hash);
 
-            match Info::from_bytes(&completed.bytes[..]) {
+            match Info::from_bytes_strict(&completed.bytes[..]) {
                 Ok(info) => {
                     Some(Ok(ODiscoveryMessage::DownloadedMetainfo(info.into())))
                 },
@@ -297,32 +430,26 @@ impl UtMetadataModule {
     }
 
     fn retrieve_piece_request(&mut self) -> Option<Result<ODiscoveryMessage, DiscoveryError>> {
-        for (hash, opt_pending) in self.pending_map.iter_mut() {
-            let has_ready_requests = opt_pending
-                .as_ref()
-                .map(|pending| !pending.messages.is_empty())
-                .unwrap_or(false);
-            let has_active_peers = self.active_peers
-                .get(hash)
-                .map(|peers| !peers.peers.is_empty())
-                .unwrap_or(false);
-
-            if has_ready_requests && has_active_peers {
-                let pending = opt_pending.as_mut().unwrap();
+        if self.active_requests.len() >= MAX_ACTIVE_REQUESTS {
+            return None;
+        }
 
-                let mut active_peers = self.active_peers.get(hash).unwrap().peers.iter();
-                let num_active_peers = active_peers.len();
-                let selected_peer_num = rand::thread_rng().next_u32() as usize % num_active_peers;
+        for (hash, opt_pending) in self.pending_map.iter_mut() {
+            let pending = match opt_pending.as_mut() {
+                Some(pending) => pending,
+                None => continue,
+            };
 
-                let selected_peer = active_peers.nth(selected_peer_num).unwrap();
-                let selected_message = pending.messages.pop().unwrap();
+            if let Some((selected_peer, selected_message)) = next_request(&self.active_requests, &self.active_peers, hash, pending) {
+                // Request was taken from the back of the pending messages, unless we are in the endgame
+                pending.messages.pop();
 
                 self.active_requests
-                    .push(generate_active_request(selected_message, *selected_peer));
+                    .push(generate_active_request(selected_message, selected_peer));
 
                 info!("Requesting Piece {:?} For Hash {:?}", selected_message.piece(), selected_peer.hash());
                 return Some(Ok(ODiscoveryMessage::SendUtMetadataMessage(
-                    *selected_peer,
+                    selected_peer,
                     UtMetadataMessage::Request(selected_message),
                 )));
             }
@@ -359,27 +486,44 @@ impl UtMetadataModule {
 
     //-------------------------------------------------------------------------------//
 
-    fn initialize_pending(&mut self) -> bool {
-        let mut pending_tasks_available = false;
-
+    fn initialize_pending(&mut self) {
         // Initialize PeningInfo once we get peers that have told us the metadata size
         for (hash, opt_pending) in self.pending_map.iter_mut() {
-            if opt_pending.is_none() {
-                let opt_pending_info = self.active_peers
-                    .get(hash)
-                    .map(|active_peers| pending_info_from_metadata_size(active_peers.metadata_size));
+            let opt_active_peers = self.active_peers.get(hash);
+            let opt_metadata_size = opt_active_peers.and_then(ActivePeers::majority_size);
 
-                *opt_pending = opt_pending_info;
-            }
-
-            // If pending is there, and the messages array is not empty
-            pending_tasks_available |= opt_pending
+            // Re-initialize if most peers now disagree with the size we are downloading, and we havent
+            // received any pieces yet, or if no peers are left that agree with the size at all
+            let needs_init = opt_pending
                 .as_ref()
-                .map(|pending| !pending.messages.is_empty())
-                .unwrap_or(false);
+                .map(|pending| {
+                    let pending_size = pending.bytes.len() as i64;
+                    let size_abandoned = !opt_active_peers
+                        .map(|active_peers| active_peers.advertises(pending_size))
+                        .unwrap_or(false);
+                    let size_outvoted = pending.sources.is_empty() && opt_metadata_size.map(|size| size != pending_size).unwrap_or(false);
+
+                    size_abandoned || size_outvoted
+                })
+                .unwrap_or(true);
+
+            if let (true, Some(metadata_size)) = (needs_init, opt_metadata_size) {
+                // Outstanding requests were for the old size, responses to them are no longer useful
+                self.active_requests.retain(|request| request.sent_to.hash() != hash);
+
+                *opt_pending = Some(pending_info_from_metadata_size(metadata_size));
+            }
         }
+    }
 
-        pending_tasks_available
+    fn requests_available(&self) -> bool {
+        // Pieces may be left, but if they are all in flight (and duplicated) there is nothing we can request
+        self.pending_map.iter().any(|(hash, opt_pending)| {
+            opt_pending
+                .as_ref()
+                .and_then(|pending| next_request(&self.active_requests, &self.active_peers, hash, pending))
+                .is_some()
+        })
     }
 
     fn validate_downloaded(&mut self) -> bool {
@@ -408,6 +552,13 @@ impl UtMetadataModule {
                 .unwrap_or(false);
 
             if should_reset {
+                // Cant tell which of the peers sent us a bad piece, so stop downloading from all of them
+                if let (Some(pending), Some(active_peers)) = (opt_pending.as_ref(), self.active_peers.get_mut(&expected_hash)) {
+                    for source in pending.sources.iter() {
+                        active_peers.peers.remove(source);
+                    }
+                }
+
                 *opt_pending = None;
             }
         }
@@ -421,14 +572,16 @@ impl UtMetadataModule {
         // Will invalidate downloads that dont pass hash check
         let downloads_available = self.validate_downloaded();
         // Will potentially re-initialize downloads that failed hash check
-        let tasks_available = self.initialize_pending();
+        self.initialize_pending();
+        let tasks_available = self.requests_available();
 
         let free_task_queue_space = self.active_requests.len() != MAX_ACTIVE_REQUESTS;
         let peer_requests_available = !self.peer_requests.is_empty();
+        let bans_available = !self.ban_queue.is_empty();
 
-        // Check if stream is currently blocked AND either we can queue more requests OR we can service some requests OR we have complete downloads
-        let should_unblock =
-            self.opt_stream.is_some() && ((free_task_queue_space && tasks_available) || peer_requests_available || downloads_available);
+        // Check if stream is currently blocked AND either we can queue more requests OR we can service some requests OR we have complete downloads OR bans
+        let should_unblock = self.opt_stream.is_some() &&
+            ((free_task_queue_space && tasks_available) || peer_requests_available || downloads_available || bans_available);
 
         if should_unblock {
             self.opt_stream.take().unwrap().notify();
@@ -453,17 +606,115 @@ fn generate_active_request(message: UtMetadataRequestMessage, peer: PeerInfo) ->
     }
 }
 
+fn requeue_request(pending_map: &mut HashMap<InfoHash, Option<PendingInfo>>, request: &ActiveRequest) {
+    pending_map
+        .get_mut(&request.sent_to.hash())
+        .map(|opt_pending| {
+            opt_pending.as_mut().map(|pending| {
+                let piece = request.message.piece();
+                let received = pending.received.get(piece as usize).cloned().unwrap_or(true);
+                let queued = pending.messages.iter().any(|message| message.piece() == piece);
+
+                if !received && !queued {
+                    pending.messages.push(request.message);
+                }
+            })
+        });
+}
+
+/// Next request to make for the pending download, along with the peer to make it to, if any.
+///
+/// Pieces that havent been requested yet are taken from the back of the pending messages.
+fn next_request(active_requests: &[ActiveRequest],
+                active_peers: &HashMap<InfoHash, ActivePeers>,
+                hash: &InfoHash,
+                pending: &PendingInfo)
+                -> Option<(PeerInfo, UtMetadataRequestMessage)> {
+    let active_peers = match (pending.left, active_peers.get(hash)) {
+        (0, _) | (_, None) => return None,
+        (_, Some(active_peers)) => active_peers,
+    };
+
+    // Only peers agreeing on the size we are downloading can give us valid pieces
+    let metadata_size = pending.bytes.len() as i64;
+    let candidate_peers = active_peers
+        .peers
+        .iter()
+        .filter(|&(_, &size)| size == metadata_size)
+        .map(|(peer, _)| (outstanding_requests(active_requests, peer), *peer))
+        .filter(|&(outstanding, _)| outstanding < MAX_REQUESTS_PER_PEER);
+
+    match pending.messages.last() {
+        // Give the piece to the peer with the fewest outstanding requests
+        Some(&message) => {
+            candidate_peers
+                .min_by_key(|&(outstanding, _)| outstanding)
+                .map(|(_, peer)| (peer, message))
+        },
+        // Once every piece has been requested, duplicate outstanding requests to idle peers
+        None => {
+            candidate_peers
+                .filter_map(|(outstanding, peer)| endgame_request(active_requests, &peer).map(|message| (outstanding, peer, message)))
+                .min_by_key(|&(outstanding, _, _)| outstanding)
+                .map(|(_, peer, message)| (peer, message))
+        },
+    }
+}
+
+/// Number of requests we have outstanding with the peer.
+fn outstanding_requests(active_requests: &[ActiveRequest], peer: &PeerInfo) -> usize {
+    active_requests.iter().filter(|request| request.sent_to == *peer).count()
+}
+
+/// Outstanding request for the peer's torrent that can be duplicated to the peer, if any.
+fn endgame_request(active_requests: &[ActiveRequest], peer: &PeerInfo) -> Option<UtMetadataRequestMessage> {
+    let piece_requests = |piece: i64| {
+        active_requests
+            .iter()
+            .filter(move |request| request.sent_to.hash() == peer.hash() && request.message.piece() == piece)
+    };
+
+    active_requests
+        .iter()
+        .filter(|request| request.sent_to.hash() == peer.hash())
+        .map(|request| request.message)
+        .find(|message| {
+            let already_sent = piece_requests(message.piece()).any(|request| request.sent_to == *peer);
+
+            !already_sent && piece_requests(message.piece()).count() < MAX_ENDGAME_REQUESTS
+        })
+}
+
+fn num_pieces_from_metadata_size(metadata_size: usize) -> usize {
+    if metadata_size % MAX_REQUEST_SIZE != 0 {
+        metadata_size / MAX_REQUEST_SIZE + 1
+    } else {
+        metadata_size / MAX_REQUEST_SIZE
+    }
+}
+
+/// Returns true if the data message is a valid piece of metadata with the given size.
+fn is_valid_data(metadata_size: usize, data: &UtMetadataDataMessage) -> bool {
+    let num_pieces = num_pieces_from_metadata_size(metadata_size);
+
+    if data.piece() < 0 || data.piece() as usize >= num_pieces || data.total_size() != metadata_size as i64 {
+        return false;
+    }
+
+    // Every piece except the last one should be a full piece
+    let data_offset = (data.piece() as usize) * MAX_REQUEST_SIZE;
+    let expected_len = cmp::min(MAX_REQUEST_SIZE, metadata_size - data_offset);
+
+    data.data().len() == expected_len
+}
+
 fn pending_info_from_metadata_size(metadata_size: i64) -> PendingInfo {
     let cast_metadata_size = metadata_size as usize;
 
     let bytes = vec![0u8; cast_metadata_size];
     let mut messages = Vec::new();
 
-    let num_pieces = if cast_metadata_size % MAX_REQUEST_SIZE != 0 {
-        cast_metadata_size / MAX_REQUEST_SIZE + 1
-    } else {
-        cast_metadata_size / MAX_REQUEST_SIZE
-    };
+    let num_pieces = num_pieces_from_metadata_size(cast_metadata_size);
 
     for index in 0..num_pieces {
         messages.push(UtMetadataRequestMessage::new((index) as i64));
@@ -471,8 +722,10 @@ fn pending_info_from_metadata_size(metadata_size: i64) -> PendingInfo {
 
     PendingInfo {
         messages: messages,
+        received: vec![false; num_pieces],
         left: num_pieces,
         bytes: bytes,
+        sources: HashSet::new(),
     }
 }
 
@@ -516,6 +769,11 @@ impl Sink for UtMetadataModule {
             IDiscoveryMessage::Control(ControlMessage::Tick(duration)) => {
                 self.apply_tick(duration)
             },
+            IDiscoveryMessage::Control(ControlMessage::SetDownloadStrategy(_, _)) |
+            IDiscoveryMessage::Control(ControlMessage::SetTorrentPriority(_, _)) |
+            IDiscoveryMessage::Control(ControlMessage::Shutdown) => {
+                Ok(AsyncSink::Ready)
+            },
             IDiscoveryMessage::DownloadMetainfo(hash) => {
                 self.download_metainfo(hash)
             },
@@ -528,6 +786,13 @@ impl Sink for UtMetadataModule {
             IDiscoveryMessage::ReceivedUtMetadataMessage(info, UtMetadataMessage::Reject(msg)) => {
                 self.recv_reject(info, msg)
             },
+            IDiscoveryMessage::ReceivedBitField(_, _) |
+            IDiscoveryMessage::ReceivedHave(_, _) |
+            IDiscoveryMessage::UdpTrackerResponded(_, _) |
+            IDiscoveryMessage::UdpTrackerFailed(_, _) |
+            IDiscoveryMessage::TorrentCompleted(_) => {
+                Ok(AsyncSink::Ready)
+            },
         };
 
         // Check if we need to unblock the stream after performing our work
@@ -554,10 +819,12 @@ impl Stream for UtMetadataModule {
     type Error = DiscoveryError;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        // Check if we completed any downloads
+        // Check if we banned any peers
+        // Or if we completed any downloads
         // Or if we can send any requests
         // Or if we can send any responses
-        let opt_result = self.retrieve_completed_download()
+        let opt_result = self.retrieve_ban()
+            .or_else(|| self.retrieve_completed_download())
             .or_else(|| self.retrieve_piece_request())
             .or_else(|| self.retrieve_piece_response());
 
@@ -576,3 +843,249 @@ impl Stream for UtMetadataModule {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ControlMessage;
+    use super::{MAX_REQUEST_SIZE, UtMetadataModule};
+    use bip_handshake::{Direction, Extensions, TransportKind};
+    use bip_peer::PeerInfo;
+    use bip_peer::messages::ExtendedType;
+    use bip_peer::messages::{UtMetadataDataMessage, UtMetadataMessage, UtMetadataRejectMessage};
+    use bip_peer::messages::builders::ExtendedMessageBuilder;
+    use bip_util::bt;
+    use bip_util::bt::InfoHash;
+    use bytes::Bytes;
+    use discovery::{IDiscoveryMessage, ODiscoveryMessage};
+    use extended::{ExtendedListener, ExtendedPeerInfo};
+    use futures::{Async, Sink};
+    use futures_test::harness::Harness;
+    use std::time::Duration;
+
+    fn peer_info(hash: InfoHash) -> PeerInfo {
+        peer_info_with_addr(hash, "127.0.0.1:6881")
+    }
+
+    fn peer_info_with_addr(hash: InfoHash, addr: &str) -> PeerInfo {
+        PeerInfo::new(
+            addr.parse().unwrap(),
+            [0u8; bt::PEER_ID_LEN].into(),
+            hash,
+            Extensions::new(),
+            Direction::Outbound,
+            TransportKind::Tcp,
+        )
+    }
+
+    fn extended_info(metadata_size: i64) -> ExtendedPeerInfo {
+        let ours = ExtendedMessageBuilder::new()
+            .with_extended_type(ExtendedType::UtMetadata, Some(5))
+            .build();
+        let theirs = ExtendedMessageBuilder::new()
+            .with_extended_type(ExtendedType::UtMetadata, Some(5))
+            .with_metadata_size(Some(metadata_size))
+            .build();
+
+        ExtendedPeerInfo::new(Some(ours), Some(theirs))
+    }
+
+    fn data_message(piece: i64, total_size: i64, len: usize) -> IDiscoveryMessage {
+        let message = UtMetadataDataMessage::new(piece, total_size, Bytes::from(vec![0u8; len]));
+
+        IDiscoveryMessage::ReceivedUtMetadataMessage(peer_info([0u8; bt::INFO_HASH_LEN].into()), UtMetadataMessage::Data(message))
+    }
+
+    #[test]
+    fn positive_valid_data_last_piece() {
+        let metadata_size = MAX_REQUEST_SIZE + 10;
+        let message = UtMetadataDataMessage::new(1, metadata_size as i64, Bytes::from(vec![0u8; 10]));
+
+        assert!(super::is_valid_data(metadata_size, &message));
+    }
+
+    #[test]
+    fn negative_invalid_data_piece_out_of_range() {
+        let metadata_size = MAX_REQUEST_SIZE;
+        let message = UtMetadataDataMessage::new(1, metadata_size as i64, Bytes::from(vec![0u8; 10]));
+
+        assert!(!super::is_valid_data(metadata_size, &message));
+    }
+
+    #[test]
+    fn negative_invalid_data_total_size_mismatch() {
+        let message = UtMetadataDataMessage::new(0, 20, Bytes::from(vec![0u8; 10]));
+
+        assert!(!super::is_valid_data(10, &message));
+    }
+
+    #[test]
+    fn negative_ban_peer_advertising_oversized_metadata() {
+        let mut module = UtMetadataModule::new().with_max_metadata_size(100);
+        let info = peer_info([0u8; bt::INFO_HASH_LEN].into());
+
+        module.on_update(&info, &extended_info(101));
+
+        assert_eq!(
+            Async::Ready(Some(ODiscoveryMessage::BanPeer(info))),
+            Harness::new(&mut module).poll_next().unwrap()
+        );
+    }
+
+    #[test]
+    fn negative_ban_peer_sending_oversized_piece() {
+        let mut module = UtMetadataModule::new();
+        let hash: InfoHash = [0u8; bt::INFO_HASH_LEN].into();
+        let info = peer_info(hash);
+
+        module.start_send(IDiscoveryMessage::DownloadMetainfo(hash)).unwrap();
+        module.on_update(&info, &extended_info(100));
+
+        match Harness::new(&mut module).poll_next().unwrap() {
+            Async::Ready(Some(ODiscoveryMessage::SendUtMetadataMessage(_, UtMetadataMessage::Request(request)))) => {
+                assert_eq!(0, request.piece());
+            },
+            _ => panic!("bip_select: Expected UtMetadata Request Message"),
+        }
+
+        module.start_send(data_message(0, 100, MAX_REQUEST_SIZE)).unwrap();
+
+        assert_eq!(
+            Async::Ready(Some(ODiscoveryMessage::BanPeer(info))),
+            Harness::new(&mut module).poll_next().unwrap()
+        );
+        // Banned peer was our only peer, so no more requests can be sent
+        assert!(Harness::new(&mut module).poll_next().unwrap().is_not_ready());
+    }
+
+    fn expect_request(module: &mut UtMetadataModule) -> (PeerInfo, i64) {
+        match Harness::new(module).poll_next().unwrap() {
+            Async::Ready(Some(ODiscoveryMessage::SendUtMetadataMessage(info, UtMetadataMessage::Request(request)))) => {
+                (info, request.piece())
+            },
+            _ => panic!("bip_select: Expected UtMetadata Request Message"),
+        }
+    }
+
+    #[test]
+    fn positive_requests_spread_across_peers() {
+        let mut module = UtMetadataModule::new();
+        let hash: InfoHash = [0u8; bt::INFO_HASH_LEN].into();
+        let metadata_size = (MAX_REQUEST_SIZE * 2) as i64;
+        let (info_one, info_two) = (peer_info_with_addr(hash, "127.0.0.1:6881"), peer_info_with_addr(hash, "127.0.0.1:6882"));
+
+        module.start_send(IDiscoveryMessage::DownloadMetainfo(hash)).unwrap();
+        module.on_update(&info_one, &extended_info(metadata_size));
+        module.on_update(&info_two, &extended_info(metadata_size));
+
+        let (sent_one, piece_one) = expect_request(&mut module);
+        let (sent_two, piece_two) = expect_request(&mut module);
+
+        assert!(sent_one != sent_two);
+        assert!(piece_one != piece_two);
+    }
+
+    #[test]
+    fn positive_endgame_duplicates_outstanding_piece() {
+        let mut module = UtMetadataModule::new();
+        let hash: InfoHash = [0u8; bt::INFO_HASH_LEN].into();
+        let (info_one, info_two) = (peer_info_with_addr(hash, "127.0.0.1:6881"), peer_info_with_addr(hash, "127.0.0.1:6882"));
+
+        module.start_send(IDiscoveryMessage::DownloadMetainfo(hash)).unwrap();
+        module.on_update(&info_one, &extended_info(100));
+        module.on_update(&info_two, &extended_info(100));
+
+        let (sent_one, piece_one) = expect_request(&mut module);
+        let (sent_two, piece_two) = expect_request(&mut module);
+
+        assert!(sent_one != sent_two);
+        assert_eq!((0, 0), (piece_one, piece_two));
+        // Piece has been requested from both peers, nothing left to duplicate
+        assert!(Harness::new(&mut module).poll_next().unwrap().is_not_ready());
+    }
+
+    #[test]
+    fn positive_no_wake_with_every_piece_in_flight() {
+        let mut module = UtMetadataModule::new();
+        let hash: InfoHash = [0u8; bt::INFO_HASH_LEN].into();
+        let info = peer_info(hash);
+
+        module.start_send(IDiscoveryMessage::DownloadMetainfo(hash)).unwrap();
+        module.on_update(&info, &extended_info(100));
+
+        assert_eq!((info, 0), expect_request(&mut module));
+        assert!(Harness::new(&mut module).poll_next().unwrap().is_not_ready());
+
+        // Piece is still left, but it is in flight with our only peer, so the stream should stay blocked
+        module.start_send(IDiscoveryMessage::Control(ControlMessage::Tick(Duration::from_millis(0)))).unwrap();
+        assert!(module.opt_stream.is_some());
+    }
+
+    #[test]
+    fn positive_reject_requeues_all_peer_requests() {
+        let mut module = UtMetadataModule::new();
+        let hash: InfoHash = [0u8; bt::INFO_HASH_LEN].into();
+        let metadata_size = (MAX_REQUEST_SIZE * 3) as i64;
+        let (info_one, info_two) = (peer_info_with_addr(hash, "127.0.0.1:6881"), peer_info_with_addr(hash, "127.0.0.1:6882"));
+
+        module.start_send(IDiscoveryMessage::DownloadMetainfo(hash)).unwrap();
+        module.on_update(&info_one, &extended_info(metadata_size));
+
+        for _ in 0..3 {
+            assert_eq!(info_one, expect_request(&mut module).0);
+        }
+
+        let reject = UtMetadataRejectMessage::new(0);
+        module.start_send(IDiscoveryMessage::ReceivedUtMetadataMessage(info_one, UtMetadataMessage::Reject(reject))).unwrap();
+        module.on_update(&info_two, &extended_info(metadata_size));
+
+        // Every piece should go to the other peer right away, instead of timing out with the first
+        let mut pieces = Vec::new();
+        for _ in 0..3 {
+            let (sent_to, piece) = expect_request(&mut module);
+
+            assert_eq!(info_two, sent_to);
+            pieces.push(piece);
+        }
+        pieces.sort();
+
+        assert_eq!(vec![0, 1, 2], pieces);
+    }
+
+    #[test]
+    fn positive_download_from_majority_metadata_size() {
+        let mut module = UtMetadataModule::new();
+        let hash: InfoHash = [0u8; bt::INFO_HASH_LEN].into();
+        let liar = peer_info_with_addr(hash, "127.0.0.1:6880");
+        let (info_one, info_two) = (peer_info_with_addr(hash, "127.0.0.1:6881"), peer_info_with_addr(hash, "127.0.0.1:6882"));
+
+        module.start_send(IDiscoveryMessage::DownloadMetainfo(hash)).unwrap();
+        module.on_update(&liar, &extended_info(50));
+        module.on_update(&info_one, &extended_info(100));
+        module.on_update(&info_two, &extended_info(100));
+
+        let (sent_one, _) = expect_request(&mut module);
+        let (sent_two, _) = expect_request(&mut module);
+
+        assert!(sent_one != liar && sent_two != liar);
+        assert!(Harness::new(&mut module).poll_next().unwrap().is_not_ready());
+    }
+
+    #[test]
+    fn negative_failed_hash_check_drops_sources_without_ban() {
+        let mut module = UtMetadataModule::new();
+        let hash: InfoHash = [0u8; bt::INFO_HASH_LEN].into();
+        let (info_one, info_two) = (peer_info_with_addr(hash, "127.0.0.1:6881"), peer_info_with_addr(hash, "127.0.0.1:6882"));
+
+        module.start_send(IDiscoveryMessage::DownloadMetainfo(hash)).unwrap();
+        module.on_update(&info_one, &extended_info(100));
+
+        assert_eq!((info_one, 0), expect_request(&mut module));
+        module.start_send(data_message(0, 100, 100)).unwrap();
+
+        // Metadata doesnt match the info hash, but we dont know who to blame
+        assert!(Harness::new(&mut module).poll_next().unwrap().is_not_ready());
+
+        module.on_update(&info_two, &extended_info(200));
+        assert_eq!((info_two, 0), expect_request(&mut module));
+    }
+}
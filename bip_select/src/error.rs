@@ -10,4 +10,8 @@ error_chain! {
     links {
         Discovery(DiscoveryError, DiscoveryErrorKind);
     }
+
+    foreign_links {
+        Io(::std::io::Error);
+    }
 }
@@ -0,0 +1,114 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// Tracks the external address that peers report seeing us as, and decides on a consensus.
+///
+/// Each reporter (identified by its own ip address) gets a single vote, so a single host
+/// can not sway the consensus by connecting to us multiple times.
+pub struct ExternalAddrConsensus {
+    min_votes: usize,
+    current: Option<IpAddr>,
+}
+
+impl ExternalAddrConsensus {
+    /// Create a new `ExternalAddrConsensus` requiring at least `min_votes` for an address to be chosen.
+    pub fn new(min_votes: usize) -> ExternalAddrConsensus {
+        ExternalAddrConsensus {
+            min_votes: min_votes,
+            current: None,
+        }
+    }
+
+    /// Current consensus, if any.
+    pub fn current(&self) -> Option<IpAddr> {
+        self.current
+    }
+
+    /// Update the consensus with the given (reporter, reported) pairs.
+    ///
+    /// Returns `Some` address if the consensus changed to that address.
+    pub fn update<I>(&mut self, reports: I) -> Option<IpAddr>
+    where
+        I: IntoIterator<Item = (IpAddr, IpAddr)>,
+    {
+        let votes: HashMap<IpAddr, IpAddr> = reports.into_iter().collect();
+
+        let mut tally: HashMap<IpAddr, usize> = HashMap::new();
+        for (_, reported) in votes {
+            *tally.entry(reported).or_insert(0) += 1;
+        }
+
+        let mut leader: Option<(IpAddr, usize)> = None;
+        let mut runner_up_votes = 0;
+        for (addr, count) in tally {
+            match leader {
+                Some((_, leader_votes)) if count <= leader_votes => {
+                    runner_up_votes = cmp::max(runner_up_votes, count);
+                },
+                Some((_, leader_votes)) => {
+                    runner_up_votes = leader_votes;
+                    leader = Some((addr, count));
+                },
+                None => leader = Some((addr, count)),
+            }
+        }
+
+        // Require a strict majority over the runner up, so ties never flip flop the consensus
+        let opt_consensus = leader
+            .and_then(|(addr, count)| if count >= self.min_votes && count > runner_up_votes { Some(addr) } else { None });
+
+        match opt_consensus {
+            Some(addr) if self.current != Some(addr) => {
+                self.current = Some(addr);
+
+                Some(addr)
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExternalAddrConsensus;
+    use std::net::IpAddr;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn positive_consensus_reached() {
+        let mut consensus = ExternalAddrConsensus::new(2);
+        let reports = vec![(ip("1.1.1.1"), ip("5.5.5.5")), (ip("2.2.2.2"), ip("5.5.5.5")), (ip("3.3.3.3"), ip("6.6.6.6"))];
+
+        assert_eq!(Some(ip("5.5.5.5")), consensus.update(reports.clone()));
+        assert_eq!(None, consensus.update(reports));
+        assert_eq!(Some(ip("5.5.5.5")), consensus.current());
+    }
+
+    #[test]
+    fn positive_consensus_changed() {
+        let mut consensus = ExternalAddrConsensus::new(1);
+
+        assert_eq!(Some(ip("5.5.5.5")), consensus.update(vec![(ip("1.1.1.1"), ip("5.5.5.5"))]));
+        assert_eq!(Some(ip("6.6.6.6")), consensus.update(vec![(ip("1.1.1.1"), ip("6.6.6.6"))]));
+    }
+
+    #[test]
+    fn negative_single_reporter_counts_once() {
+        let mut consensus = ExternalAddrConsensus::new(2);
+        let reports = vec![(ip("1.1.1.1"), ip("5.5.5.5")), (ip("1.1.1.1"), ip("5.5.5.5"))];
+
+        assert_eq!(None, consensus.update(reports));
+    }
+
+    #[test]
+    fn negative_tie_no_consensus() {
+        let mut consensus = ExternalAddrConsensus::new(1);
+        let reports = vec![(ip("1.1.1.1"), ip("5.5.5.5")), (ip("2.2.2.2"), ip("6.6.6.6"))];
+
+        assert_eq!(None, consensus.update(reports));
+    }
+}
@@ -9,6 +9,11 @@ use futures::Stream;
 use futures::task;
 use futures::task::Task;
 use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+
+mod consensus;
+
+use self::consensus::ExternalAddrConsensus;
 
 /// Enumeration of extended messages that can be sent to the extended module.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -21,6 +26,10 @@ pub enum IExtendedMessage {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum OExtendedMessage {
     SendExtendedMessage(PeerInfo, ExtendedMessage),
+    /// Peers have come to a consensus on our external ip address (via `yourip`).
+    ///
+    /// This can be used to generate a BEP 42 node id for the dht, or to announce our ip to trackers.
+    ExternalAddress(IpAddr),
 }
 
 /// Trait for a module to take part in constructing the extended message for a peer.
@@ -65,6 +74,24 @@ impl ExtendedPeerInfo {
     pub fn their_message(&self) -> Option<&ExtendedMessage> {
         self.theirs.as_ref()
     }
+
+    /// Whether or not we told the peer that we are only uploading (BEP 21).
+    pub fn our_upload_only(&self) -> bool {
+        upload_only(self.ours.as_ref())
+    }
+
+    /// Whether or not the peer told us that it is only uploading (BEP 21).
+    ///
+    /// If we are also only uploading, there is no point in expressing interest in the peer.
+    pub fn their_upload_only(&self) -> bool {
+        upload_only(self.theirs.as_ref())
+    }
+}
+
+fn upload_only(opt_message: Option<&ExtendedMessage>) -> bool {
+    opt_message
+        .and_then(|message| message.upload_only())
+        .unwrap_or(false)
 }
 
 //------------------------------------------------------------------------------//
@@ -74,19 +101,26 @@ pub struct ExtendedModule {
     peers: HashMap<PeerInfo, ExtendedPeerInfo>,
     out_queue: VecDeque<OExtendedMessage>,
     opt_task: Option<Task>,
+    consensus: ExternalAddrConsensus,
 }
 
 impl ExtendedModule {
-    pub fn new(builder: ExtendedMessageBuilder) -> ExtendedModule {
+    pub fn new(builder: ExtendedMessageBuilder, min_addr_votes: usize) -> ExtendedModule {
         ExtendedModule {
             builder: builder,
             peers: HashMap::new(),
             out_queue: VecDeque::new(),
             opt_task: None,
+            consensus: ExternalAddrConsensus::new(min_addr_votes),
         }
     }
 
-    pub fn process_message<D>(&mut self, message: IExtendedMessage, d_modules: &mut [Box<D>])
+    /// Local and remote `ExtendedMessage` for the given peer, if it is connected.
+    pub fn peer_info(&self, info: &PeerInfo) -> Option<&ExtendedPeerInfo> {
+        self.peers.get(info)
+    }
+
+    pub fn process_message<D>(&mut self, message: IExtendedMessage, d_modules: &mut [&mut Box<D>])
     where
         D: ExtendedListener + ?Sized,
     {
@@ -114,11 +148,19 @@ impl ExtendedModule {
                 self.peers.remove(&info);
             },
             IExtendedMessage::RecievedExtendedMessage(info, ext_message) => {
-                let ext_peer_info = self.peers.get_mut(&info).unwrap();
-                ext_peer_info.update_theirs(ext_message);
+                let has_your_ip = ext_message.your_ip().is_some();
 
-                for d_module in d_modules {
-                    d_module.on_update(&info, &ext_peer_info);
+                {
+                    let ext_peer_info = self.peers.get_mut(&info).unwrap();
+                    ext_peer_info.update_theirs(ext_message);
+
+                    for d_module in d_modules {
+                        d_module.on_update(&info, &ext_peer_info);
+                    }
+                }
+
+                if has_your_ip {
+                    self.check_external_addr();
                 }
             },
             _ => {
@@ -129,6 +171,19 @@ impl ExtendedModule {
         self.check_stream_unblock();
     }
 
+    fn check_external_addr(&mut self) {
+        let reports = self.peers.iter().filter_map(|(info, ext_peer_info)| {
+            ext_peer_info
+                .their_message()
+                .and_then(|message| message.your_ip())
+                .map(|your_ip| (info.addr().ip(), your_ip))
+        });
+
+        if let Some(addr) = self.consensus.update(reports) {
+            self.out_queue.push_back(OExtendedMessage::ExternalAddress(addr));
+        }
+    }
+
     fn check_stream_unblock(&mut self) {
         if !self.out_queue.is_empty() {
             if let Some(task) = self.opt_task.take() {
@@ -1,9 +1,14 @@
+#[cfg(feature = "magnet")]
+extern crate bip_dht;
 extern crate bip_handshake;
+#[cfg(feature = "magnet")]
+extern crate bip_magnet;
 extern crate bip_metainfo;
 extern crate bip_peer;
 extern crate bip_util;
 extern crate bip_utracker;
 extern crate bit_set;
+extern crate byteorder;
 extern crate bytes;
 #[macro_use]
 extern crate error_chain;
@@ -11,23 +16,35 @@ extern crate futures;
 #[macro_use]
 extern crate log;
 extern crate rand;
+#[cfg(feature = "magnet")]
+extern crate tokio_core;
 
 #[cfg(test)]
 extern crate futures_test;
 
+use bip_handshake::InfoHash;
 use bip_metainfo::Metainfo;
 use bip_peer::PeerInfo;
 use std::time::Duration;
 
 pub mod discovery;
 pub mod error;
+#[cfg(feature = "magnet")]
+pub mod magnet;
+pub mod policy;
+pub mod priority;
+pub mod reputation;
 pub mod revelation;
+pub mod selection;
+pub mod statistics;
 
 mod extended;
 mod uber;
 
 pub use extended::{ExtendedListener, ExtendedPeerInfo, IExtendedMessage, OExtendedMessage};
-pub use uber::{IUberMessage, OUberMessage, UberModule, UberModuleBuilder};
+pub use priority::TorrentPriority;
+pub use selection::{DownloadStrategy, ISelectMessage, OSelectMessage, PieceSelectionModule, UnsolicitedPiecePolicy};
+pub use uber::{IUberMessage, ModuleErrorPolicy, OUberMessage, UberModule, UberModuleBuilder};
 
 /// Enumeration of control messages most modules will be interested in.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -48,5 +65,24 @@ pub enum ControlMessage {
     /// This message is vital for certain modules
     /// to function correctly. Subsequent durations
     /// should not be spread too far apart.
+    ///
+    /// See `UberModuleBuilder::with_internal_ticks` to
+    /// have the `UberModule` generate these itself.
     Tick(Duration),
+    /// Set the `DownloadStrategy` used to select pieces for the given torrent.
+    ///
+    /// This can be sent at any time, for example, to move the window
+    /// of a streaming torrent along with the playback position.
+    SetDownloadStrategy(InfoHash, DownloadStrategy),
+    /// Set the `TorrentPriority` of the given torrent relative to other torrents.
+    ///
+    /// This can be sent at any time, paused torrents will not have any pieces selected.
+    SetTorrentPriority(InfoHash, TorrentPriority),
+    /// Shutdown all modules.
+    ///
+    /// Modules should queue up any final messages (such as stopped
+    /// announces) in response to this message. Once every module has been
+    /// flushed, and those messages have been yielded, the `UberModule` stream
+    /// will end.
+    Shutdown,
 }
@@ -0,0 +1,562 @@
+//! Module for downloading the metainfo of a magnet link.
+//!
+//! Only available with the `magnet` feature enabled.
+
+use {ControlMessage, IExtendedMessage, IUberMessage, OExtendedMessage, OUberMessage, UberModuleBuilder};
+use bip_dht::{DhtBuilder, Handshaker, Router};
+use bip_handshake::{DiscoveryInfo, Extension, Extensions, HandshakerBuilder, HandshakerConfig, InfoHash, InitiateMessage, LocalAddr, PeerId,
+                    Protocol, SocketOptions, Transport, TransportKind};
+use bip_handshake::transports::TcpTransport;
+use bip_magnet::MagnetLink;
+use bip_metainfo::Metainfo;
+use bip_peer::{self, IPeerManagerMessage, OPeerManagerMessage, PeerManagerBuilder};
+use bip_peer::messages::{BitsExtensionMessage, PeerExtensionProtocolMessage, PeerWireProtocolMessage};
+use bip_peer::messages::builders::ExtendedMessageBuilder;
+use bip_peer::protocols::{NullProtocol, PeerExtensionProtocol, PeerExtensionProtocolFactory};
+use bip_utracker::{ClientMetadata, ClientRequest, TrackerClient};
+use bip_utracker::announce::{AnnounceEvent, ClientState};
+use discovery::{IDiscoveryMessage, ODiscoveryMessage, UtMetadataModule};
+use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use futures::future::{self, Either, Loop, Shared};
+use futures::sink::Wait;
+use futures::sync::{mpsc, oneshot};
+use std::fmt::Debug;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+use tokio_core::reactor::{Handle, Interval};
+
+/// Default timeout for connecting to peers, low so we dont wait on peers that arent listening on tcp.
+const DEFAULT_CONNECT_TIMEOUT_MILLIS: u64 = 500;
+/// Default interval between ticks sent to the `UtMetadataModule`.
+const DEFAULT_TICK_MILLIS: u64 = 100;
+/// Maximum payload of a peer wire protocol message.
+const MAX_PAYLOAD_LEN: usize = 24 * 1024;
+
+/// Messages sent to and received from peers, with a nested null protocol for the extension protocol.
+type MetadataMessage = PeerWireProtocolMessage<PeerExtensionProtocol<NullProtocol>>;
+
+/// Signal that fires once the fetch future has completed, or has been dropped.
+type Shutdown = Shared<oneshot::Receiver<()>>;
+
+/// Builder for a future that downloads the `Metainfo` for a `MagnetLink`.
+///
+/// Peers are discovered through a dht search on the info hash, as well as announces
+/// to any udp trackers given in the link (`tr` params). The `Metainfo` is then
+/// downloaded from those peers through the ut_metadata extension.
+pub struct MetainfoFetcherBuilder {
+    dht: DhtBuilder,
+    handshaker_config: HandshakerConfig,
+    tracker_bind: SocketAddr,
+    tick: Duration,
+}
+
+impl MetainfoFetcherBuilder {
+    /// Create a new `MetainfoFetcherBuilder`.
+    pub fn new() -> MetainfoFetcherBuilder {
+        MetainfoFetcherBuilder {
+            dht: DhtBuilder::with_router(Router::uTorrent).set_read_only(false),
+            handshaker_config: HandshakerConfig::default().with_connect_timeout(Duration::from_millis(DEFAULT_CONNECT_TIMEOUT_MILLIS)),
+            tracker_bind: "0.0.0.0:0".parse().unwrap(),
+            tick: Duration::from_millis(DEFAULT_TICK_MILLIS),
+        }
+    }
+
+    /// `DhtBuilder` used to start the dht that will be searched for peers.
+    ///
+    /// Defaults to a (non read only) dht bootstrapped off of `Router::uTorrent`.
+    pub fn with_dht_builder(mut self, dht: DhtBuilder) -> MetainfoFetcherBuilder {
+        self.dht = dht;
+        self
+    }
+
+    /// `HandshakerConfig` used for connecting to peers.
+    ///
+    /// Defaults to a config with a connect timeout of 500 milliseconds.
+    pub fn with_handshaker_config(mut self, config: HandshakerConfig) -> MetainfoFetcherBuilder {
+        self.handshaker_config = config;
+        self
+    }
+
+    /// Address that the udp tracker client will bind to.
+    ///
+    /// Defaults to `0.0.0.0:0`.
+    pub fn with_tracker_bind(mut self, addr: SocketAddr) -> MetainfoFetcherBuilder {
+        self.tracker_bind = addr;
+        self
+    }
+
+    /// Start downloading the `Metainfo` for the given `MagnetLink`.
+    ///
+    /// Returns an error if the link does not have a bittorrent info hash, or if any of our
+    /// peer discovery services could not be started. Trackers that are not udp trackers, or
+    /// that could not be resolved, are ignored.
+    ///
+    /// Once the returned future completes, or is dropped, every task spawned on the `Handle`
+    /// is stopped, which closes our listener, drops our peers, and shuts down the dht and
+    /// tracker client.
+    pub fn fetch(self, link: &MagnetLink, handle: Handle) -> io::Result<Box<Future<Item = Metainfo, Error = ()>>> {
+        let info_hash = try!(link.get_info_hash()
+            .and_then(|hash| InfoHash::from_hash(hash.as_ref()).ok())
+            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "Magnet Link Has No BitTorrent Info Hash")));
+
+        // Activate the extension protocol via the handshake bits
+        let mut extensions = Extensions::new();
+        extensions.add(Extension::ExtensionProtocol);
+
+        let (shutdown_send, shutdown_recv) = oneshot::channel();
+        let shutdown = shutdown_recv.shared();
+
+        let (handshaker_send, handshaker_recv) = try!(HandshakerBuilder::new()
+            .with_extensions(extensions)
+            .with_config(self.handshaker_config)
+            .build(ShutdownTransport::new(TcpTransport, shutdown.clone()), handle.clone()))
+            .into_parts();
+        let (peer_manager_send, peer_manager_recv) = PeerManagerBuilder::new().build(handle.clone()).into_parts();
+
+        // Peers from trackers and the dht are connected to through the handshaker
+        let tracker_client = try!(TrackerClient::new(self.tracker_bind, TrackerHandshaker::new(handshaker_send.clone())));
+        let dht = Rc::new(try!(self.dht.start_mainline(DhtHandshaker::new(handshaker_send))));
+
+        // Resolving trackers blocks, so do that off of the reactor and announce as they come in
+        let (tracker_send, tracker_recv) = mpsc::unbounded();
+        let trackers = link.get_trackers().to_vec();
+        thread::spawn(move || {
+            for addr in trackers.iter().filter_map(|tracker| udp_tracker_addr(tracker)) {
+                if tracker_send.unbounded_send(addr).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // Announce that we have everything left, otherwise trackers may treat us as a seeder and hold back seeders
+        let state = ClientState::new(0, i64::max_value(), 0, AnnounceEvent::Started);
+        spawn_until_shutdown(
+            &handle,
+            tracker_recv
+                .fold(tracker_client, move |mut tracker_client, addr| {
+                    tracker_client.request(addr, ClientRequest::Announce(info_hash, state));
+
+                    Ok(tracker_client)
+                })
+                // Responses still have to come in, so the client lives until we shutdown
+                .and_then(|tracker_client| future::empty().map(move |()| drop(tracker_client))),
+            shutdown.clone(),
+        );
+        dht.search(info_hash, true);
+
+        // Feed handshaken peers over to the peer manager
+        spawn_until_shutdown(
+            &handle,
+            handshaker_recv
+                .map_err(|_| ())
+                .map(|complete_msg| {
+                    let (peer_info, peer) = bip_peer::frame_peer(
+                        complete_msg,
+                        PeerExtensionProtocolFactory::new(NullProtocol::new()),
+                        MAX_PAYLOAD_LEN,
+                    );
+
+                    IPeerManagerMessage::AddPeer(peer_info, peer)
+                })
+                .forward(peer_manager_send.clone().sink_map_err(|_| ()))
+                .map(|_| ()),
+            shutdown.clone(),
+        );
+
+        let (uber_send, uber_recv) = UberModuleBuilder::new()
+            .with_extended_builder(Some(ExtendedMessageBuilder::new()))
+            .with_discovery_module(UtMetadataModule::new())
+            .build()
+            .split();
+
+        // Feed peer manager messages, and ticks, over to the uber module
+        let tick = self.tick;
+        let tick_recv = try!(Interval::new(tick, &handle)).map(Either::B).map_err(|_| ());
+        let merged_recv = peer_manager_recv.map(Either::A).map_err(|_| ()).select(tick_recv);
+
+        spawn_until_shutdown(
+            &handle,
+            merged_recv
+                .filter_map(move |item| peer_manager_to_uber(item, tick))
+                .forward(uber_send.sink_map_err(|_| ()))
+                .map(|_| ()),
+            shutdown,
+        );
+
+        // Feed uber module messages over to the peer manager, until the metainfo is downloaded
+        let fetch = future::loop_fn(
+            (uber_recv, peer_manager_send.sink_map_err(|_| ())),
+            move |(uber_recv, peer_manager_send)| {
+                let dht = dht.clone();
+
+                uber_recv
+                    .into_future()
+                    .map_err(|_| ())
+                    .and_then(move |(opt_message, uber_recv)| {
+                        let message = match opt_message {
+                            Some(message) => message,
+                            None => return Either::B(future::err(())),
+                        };
+
+                        let opt_message = match message {
+                            OUberMessage::Discovery(ODiscoveryMessage::DownloadedMetainfo(metainfo)) => {
+                                return Either::B(future::ok(Loop::Break(metainfo)));
+                            },
+                            OUberMessage::Discovery(ODiscoveryMessage::NeedPeers(hash)) => {
+                                dht.search(hash, true);
+
+                                None
+                            },
+                            message => uber_to_peer_manager(message),
+                        };
+
+                        match opt_message {
+                            Some(message) => Either::A(
+                                peer_manager_send
+                                    .send(message)
+                                    .map(move |peer_manager_send| Loop::Continue((uber_recv, peer_manager_send))),
+                            ),
+                            None => Either::B(future::ok(Loop::Continue((uber_recv, peer_manager_send)))),
+                        }
+                    })
+            },
+        );
+
+        // Sender is dropped along with the future, so shutdown fires on completion or drop
+        Ok(Box::new(fetch.then(move |result| {
+            let _ = shutdown_send.send(());
+
+            result
+        })))
+    }
+}
+
+/// Spawn the given future on the reactor, stopping it early if the shutdown signal fires.
+fn spawn_until_shutdown<F>(handle: &Handle, future: F, shutdown: Shutdown)
+where
+    F: Future<Item = (), Error = ()> + 'static,
+{
+    handle.spawn(future.select(shutdown.then(|_| Ok(()))).then(|_| Ok(())));
+}
+
+/// Address of the given tracker, if it is a udp tracker that could be resolved.
+///
+/// Resolving the address blocks, so this should not be called on the reactor thread.
+fn udp_tracker_addr(tracker: &str) -> Option<SocketAddr> {
+    if !tracker.starts_with("udp://") {
+        return None;
+    }
+    let host_port = tracker["udp://".len()..].split('/').next().unwrap_or("");
+
+    host_port.to_socket_addrs().ok().and_then(|mut addrs| addrs.next())
+}
+
+fn peer_manager_to_uber(item: Either<OPeerManagerMessage<MetadataMessage>, ()>, tick: Duration) -> Option<IUberMessage> {
+    match item {
+        Either::A(OPeerManagerMessage::ReceivedMessage(info, PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::Extended(extended)))) => {
+            Some(IUberMessage::Extended(IExtendedMessage::RecievedExtendedMessage(info, extended)))
+        },
+        Either::A(OPeerManagerMessage::ReceivedMessage(info, PeerWireProtocolMessage::ProtExtension(PeerExtensionProtocolMessage::UtMetadata(message)))) => {
+            Some(IUberMessage::Discovery(IDiscoveryMessage::ReceivedUtMetadataMessage(info, message)))
+        },
+        Either::A(OPeerManagerMessage::PeerAdded(info)) => {
+            Some(IUberMessage::Control(ControlMessage::PeerConnected(info)))
+        },
+        Either::A(OPeerManagerMessage::PeerRemoved(info)) |
+        Either::A(OPeerManagerMessage::PeerDisconnect(info)) |
+        Either::A(OPeerManagerMessage::PeerError(info, _)) => {
+            Some(IUberMessage::Control(ControlMessage::PeerDisconnected(info)))
+        },
+        Either::B(()) => {
+            Some(IUberMessage::Control(ControlMessage::Tick(tick)))
+        },
+        _ => None,
+    }
+}
+
+fn uber_to_peer_manager<P>(message: OUberMessage) -> Option<IPeerManagerMessage<P>>
+where
+    P: Sink<SinkItem = MetadataMessage>,
+{
+    match message {
+        OUberMessage::Extended(OExtendedMessage::SendExtendedMessage(info, ext_message)) => {
+            Some(IPeerManagerMessage::SendMessage(
+                info,
+                0,
+                PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::Extended(ext_message)),
+            ))
+        },
+        OUberMessage::Discovery(ODiscoveryMessage::SendUtMetadataMessage(info, message)) => {
+            Some(IPeerManagerMessage::SendMessage(
+                info,
+                0,
+                PeerWireProtocolMessage::ProtExtension(PeerExtensionProtocolMessage::UtMetadata(message)),
+            ))
+        },
+        OUberMessage::Discovery(ODiscoveryMessage::BanPeer(info)) => {
+            Some(IPeerManagerMessage::RemovePeer(info))
+        },
+        _ => None,
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// Transport whose listeners stop accepting connections once the shutdown signal fires.
+///
+/// Once the listener stops, the handshaker tears down the rest of its tasks as its halves are dropped.
+struct ShutdownTransport<T> {
+    inner: T,
+    shutdown: Shutdown,
+}
+
+impl<T> ShutdownTransport<T> {
+    fn new(inner: T, shutdown: Shutdown) -> ShutdownTransport<T> {
+        ShutdownTransport {
+            inner: inner,
+            shutdown: shutdown,
+        }
+    }
+}
+
+impl<T> Transport for ShutdownTransport<T>
+where
+    T: Transport,
+{
+    type Socket = T::Socket;
+    type FutureSocket = T::FutureSocket;
+    type Listener = ShutdownListener<T::Listener>;
+
+    fn connect(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<Self::FutureSocket> {
+        self.inner.connect(addr, handle)
+    }
+
+    fn listen(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<Self::Listener> {
+        self.inner.listen(addr, handle).map(|listener| ShutdownListener::new(listener, self.shutdown.clone()))
+    }
+
+    fn listen_with_backlog(&self, addr: &SocketAddr, backlog: i32, handle: &Handle) -> io::Result<Self::Listener> {
+        self.inner
+            .listen_with_backlog(addr, backlog, handle)
+            .map(|listener| ShutdownListener::new(listener, self.shutdown.clone()))
+    }
+
+    fn configure_socket(&self, socket: &Self::Socket, options: &SocketOptions) -> io::Result<()> {
+        self.inner.configure_socket(socket, options)
+    }
+
+    fn listen_with_options(&self, addr: &SocketAddr, opt_backlog: Option<i32>, options: &SocketOptions, handle: &Handle) -> io::Result<Self::Listener> {
+        self.inner
+            .listen_with_options(addr, opt_backlog, options, handle)
+            .map(|listener| ShutdownListener::new(listener, self.shutdown.clone()))
+    }
+
+    fn kind(&self) -> TransportKind {
+        self.inner.kind()
+    }
+}
+
+/// Listener that ends once the shutdown signal fires.
+struct ShutdownListener<L> {
+    inner: L,
+    shutdown: Shutdown,
+}
+
+impl<L> ShutdownListener<L> {
+    fn new(inner: L, shutdown: Shutdown) -> ShutdownListener<L> {
+        ShutdownListener {
+            inner: inner,
+            shutdown: shutdown,
+        }
+    }
+}
+
+impl<L> Stream for ShutdownListener<L>
+where
+    L: Stream,
+{
+    type Item = L::Item;
+    type Error = L::Error;
+
+    fn poll(&mut self) -> Poll<Option<L::Item>, L::Error> {
+        match self.shutdown.poll() {
+            Ok(Async::NotReady) => self.inner.poll(),
+            _ => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+impl<L> LocalAddr for ShutdownListener<L>
+where
+    L: LocalAddr,
+{
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// Handshaker for the dht, which forwards peers on to our handshaker.
+struct DhtHandshaker<S> {
+    id: PeerId,
+    sender: Wait<S>,
+}
+
+impl<S> DhtHandshaker<S>
+where
+    S: DiscoveryInfo + Sink,
+{
+    fn new(sink: S) -> DhtHandshaker<S> {
+        DhtHandshaker {
+            id: sink.peer_id(),
+            sender: sink.wait(),
+        }
+    }
+}
+
+impl<S> Handshaker for DhtHandshaker<S>
+where
+    S: DiscoveryInfo + Sink<SinkItem = InitiateMessage> + Send,
+    S::SinkError: Debug,
+{
+    type MetadataEnvelope = ();
+
+    fn id(&self) -> PeerId {
+        self.id
+    }
+
+    fn port(&self) -> u16 {
+        // Port may have been changed by a port mapping since we were created
+        self.sender.get_ref().port()
+    }
+
+    fn connect(&mut self, _expected: Option<PeerId>, hash: InfoHash, addr: SocketAddr) {
+        // Nothing we can do if our handshaker went away
+        let _ = self.sender.send(InitiateMessage::new(Protocol::BitTorrent, hash, addr));
+    }
+
+    fn metadata(&mut self, _data: ()) {
+        ()
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// Handshaker for the tracker client, which forwards peers on to our handshaker.
+///
+/// Tracker responses are ignored, since the peers in them are forwarded separately.
+struct TrackerHandshaker<S> {
+    inner: S,
+}
+
+impl<S> TrackerHandshaker<S> {
+    fn new(inner: S) -> TrackerHandshaker<S> {
+        TrackerHandshaker { inner: inner }
+    }
+}
+
+impl<S> DiscoveryInfo for TrackerHandshaker<S>
+where
+    S: DiscoveryInfo,
+{
+    fn port(&self) -> u16 {
+        self.inner.port()
+    }
+
+    fn peer_id(&self) -> PeerId {
+        self.inner.peer_id()
+    }
+}
+
+impl<S> Sink for TrackerHandshaker<S>
+where
+    S: Sink<SinkItem = InitiateMessage>,
+{
+    type SinkItem = Either<InitiateMessage, ClientMetadata>;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        match item {
+            Either::A(initiate) => self.inner.start_send(initiate).map(|async_sink| async_sink.map(Either::A)),
+            Either::B(_) => Ok(AsyncSink::Ready),
+        }
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.inner.poll_complete()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MetainfoFetcherBuilder, ShutdownTransport, peer_manager_to_uber, spawn_until_shutdown, udp_tracker_addr};
+    use {ControlMessage, IUberMessage};
+    use bip_handshake::Transport;
+    use bip_handshake::transports::TcpTransport;
+    use bip_magnet::MagnetLink;
+    use futures::{Future, Stream};
+    use futures::future::{self, Either};
+    use futures::sync::oneshot;
+    use std::io;
+    use std::time::Duration;
+    use tokio_core::reactor::Core;
+
+    #[test]
+    fn positive_udp_tracker_addr() {
+        assert_eq!(Some("127.0.0.1:6969".parse().unwrap()), udp_tracker_addr("udp://127.0.0.1:6969/announce"));
+        assert_eq!(Some("127.0.0.1:6969".parse().unwrap()), udp_tracker_addr("udp://127.0.0.1:6969"));
+    }
+
+    #[test]
+    fn negative_http_tracker_addr() {
+        assert_eq!(None, udp_tracker_addr("http://127.0.0.1:6969/announce"));
+    }
+
+    #[test]
+    fn positive_tick_to_uber() {
+        let tick = Duration::from_millis(100);
+
+        assert_eq!(Some(IUberMessage::Control(ControlMessage::Tick(tick))), peer_manager_to_uber(Either::B(()), tick));
+    }
+
+    #[test]
+    fn positive_shutdown_stops_spawned_task() {
+        let mut core = Core::new().unwrap();
+        let (shutdown_send, shutdown_recv) = oneshot::channel();
+        let (guard_send, guard_recv) = oneshot::channel::<()>();
+
+        // Task never completes on its own, so the guard is only dropped if the task is
+        spawn_until_shutdown(&core.handle(), future::empty().map(move |()| drop(guard_send)), shutdown_recv.shared());
+        shutdown_send.send(()).unwrap();
+
+        assert!(core.run(guard_recv).is_err());
+    }
+
+    #[test]
+    fn positive_shutdown_stops_listener() {
+        let mut core = Core::new().unwrap();
+        let (shutdown_send, shutdown_recv) = oneshot::channel();
+
+        let transport = ShutdownTransport::new(TcpTransport, shutdown_recv.shared());
+        let listener = transport.listen(&"127.0.0.1:0".parse().unwrap(), &core.handle()).unwrap();
+        drop(shutdown_send);
+
+        let (opt_item, _) = core.run(listener.into_future()).map_err(|(error, _)| error).unwrap();
+        assert!(opt_item.is_none());
+    }
+
+    #[test]
+    fn negative_fetch_without_info_hash() {
+        let core = Core::new().unwrap();
+        let link = MagnetLink::parse("magnet:?dn=test").unwrap();
+
+        match MetainfoFetcherBuilder::new().fetch(&link, core.handle()) {
+            Err(error) => assert_eq!(io::ErrorKind::InvalidInput, error.kind()),
+            Ok(_) => panic!("Fetch Started Without An Info Hash"),
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Module for peer connection policies.
+
+use bip_peer::PeerInfo;
+
+/// Trait for deciding whether or not to accept a newly connected peer.
+///
+/// The policy is consulted every time a `ControlMessage::PeerConnected`
+/// is sent to the `UberModule`, before any other module sees the message.
+/// This allows decisions such as rejecting peers for a torrent that is
+/// paused, or a torrent that is seeding and has met its ratio, to live
+/// in one place instead of being scattered across the embedding code.
+pub trait PeerPolicy {
+    /// Returns true if the given peer should be accepted.
+    ///
+    /// If false is returned, the peer will not be forwarded to any modules,
+    /// and an `OUberMessage::DisconnectPeer` will be emitted for the peer.
+    fn accept_peer(&mut self, info: &PeerInfo) -> bool;
+}
+
+impl<F> PeerPolicy for F
+where
+    F: FnMut(&PeerInfo) -> bool,
+{
+    fn accept_peer(&mut self, info: &PeerInfo) -> bool {
+        self(info)
+    }
+}
+
+/// `PeerPolicy` which accepts all peers.
+///
+/// This is the policy used by default.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AcceptAllPolicy;
+
+impl AcceptAllPolicy {
+    /// Create a new `AcceptAllPolicy`.
+    pub fn new() -> AcceptAllPolicy {
+        AcceptAllPolicy
+    }
+}
+
+impl PeerPolicy for AcceptAllPolicy {
+    fn accept_peer(&mut self, _info: &PeerInfo) -> bool {
+        true
+    }
+}
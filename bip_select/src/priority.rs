@@ -0,0 +1,236 @@
+//! Module for prioritizing torrents against each other.
+
+use ControlMessage;
+use bip_handshake::InfoHash;
+use std::collections::HashMap;
+
+/// Priority of a torrent relative to other torrents.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TorrentPriority {
+    /// Torrent is given four times the share of a `Low` torrent.
+    High,
+    /// Torrent is given twice the share of a `Low` torrent.
+    Normal,
+    /// Torrent is given the smallest share.
+    Low,
+    /// Torrent is not given any share, no pieces will be selected for it.
+    Paused,
+}
+
+impl TorrentPriority {
+    /// Relative share given to torrents with this priority.
+    pub fn weight(&self) -> u64 {
+        match *self {
+            TorrentPriority::High => 4,
+            TorrentPriority::Normal => 2,
+            TorrentPriority::Low => 1,
+            TorrentPriority::Paused => 0,
+        }
+    }
+}
+
+impl Default for TorrentPriority {
+    fn default() -> TorrentPriority {
+        TorrentPriority::Normal
+    }
+}
+
+struct TorrentShare {
+    priority: TorrentPriority,
+    // Credit for the smooth weighted round robin, the torrent with the most credit goes next
+    credit: i64,
+}
+
+/// Shares request dispatch and unchoke slots across torrents, weighted by their `TorrentPriority`.
+///
+/// Torrents are tracked through `ControlMessage::AddTorrent` and `ControlMessage::RemoveTorrent`,
+/// and start out at `TorrentPriority::Normal`. Priorities can be changed at any time by sending
+/// `ControlMessage::SetTorrentPriority`, so a user initiated download can outrank background seeding.
+///
+/// A `PieceSelectionModule` schedules the torrents it was given with its own `PriorityScheduler`.
+pub struct PriorityScheduler {
+    torrents: HashMap<InfoHash, TorrentShare>,
+}
+
+impl PriorityScheduler {
+    /// Create a new `PriorityScheduler`.
+    pub fn new() -> PriorityScheduler {
+        PriorityScheduler { torrents: HashMap::new() }
+    }
+
+    /// Process the given control message.
+    pub fn process_message(&mut self, message: &ControlMessage) {
+        match *message {
+            ControlMessage::AddTorrent(ref metainfo) => {
+                self.torrents
+                    .entry(metainfo.info().info_hash())
+                    .or_insert_with(|| TorrentShare { priority: TorrentPriority::default(), credit: 0 });
+            },
+            ControlMessage::RemoveTorrent(ref metainfo) => {
+                self.torrents.remove(&metainfo.info().info_hash());
+            },
+            ControlMessage::SetTorrentPriority(hash, priority) => {
+                self.torrents
+                    .entry(hash)
+                    .or_insert_with(|| TorrentShare { priority: priority, credit: 0 })
+                    .priority = priority;
+            },
+            _ => (),
+        }
+    }
+
+    /// Current `TorrentPriority` for the given torrent.
+    pub fn priority(&self, hash: &InfoHash) -> Option<TorrentPriority> {
+        self.torrents.get(hash).map(|share| share.priority)
+    }
+
+    /// Next torrent that should dispatch a block request.
+    ///
+    /// Only torrents that `is_ready` returns true for (such as torrents with a peer able to take
+    /// another request) are considered. Over many calls, each torrent is picked in proportion to
+    /// its weight, with picks interleaved instead of bunched together. Paused torrents are never picked.
+    pub fn next_torrent<F>(&mut self, mut is_ready: F) -> Option<InfoHash>
+    where
+        F: FnMut(&InfoHash) -> bool,
+    {
+        let mut total_weight = 0;
+        let mut opt_selected: Option<(InfoHash, i64)> = None;
+
+        for (hash, share) in self.torrents.iter_mut() {
+            let weight = share.priority.weight() as i64;
+
+            if weight == 0 || !is_ready(hash) {
+                continue;
+            }
+
+            share.credit += weight;
+            total_weight += weight;
+
+            // Ties are broken by the lowest hash, so the order does not depend on the map
+            let is_better = opt_selected
+                .map(|(sel_hash, sel_credit)| (share.credit, sel_hash) > (sel_credit, *hash))
+                .unwrap_or(true);
+            if is_better {
+                opt_selected = Some((*hash, share.credit));
+            }
+        }
+
+        opt_selected.map(|(hash, _)| {
+            self.torrents.get_mut(&hash).unwrap().credit -= total_weight;
+
+            hash
+        })
+    }
+
+    /// Number of unchoke slots each torrent should get out of the total slots available.
+    ///
+    /// Slots are split in proportion to the weight of each torrent, with any left over slots
+    /// going to the torrents that were closest to getting another one. Paused torrents get no slots.
+    pub fn unchoke_slots(&self, total_slots: usize) -> HashMap<InfoHash, usize> {
+        let total_weight = self.torrents
+            .values()
+            .map(|share| share.priority.weight())
+            .sum::<u64>();
+        if total_weight == 0 {
+            return HashMap::new();
+        }
+
+        let mut slots = HashMap::new();
+        let mut remainders = Vec::new();
+        let mut assigned = 0;
+        for (hash, share) in self.torrents.iter().filter(|&(_, share)| share.priority.weight() != 0) {
+            let scaled = total_slots as u64 * share.priority.weight();
+            let torrent_slots = (scaled / total_weight) as usize;
+
+            slots.insert(*hash, torrent_slots);
+            remainders.push((scaled % total_weight, share.priority.weight(), *hash));
+            assigned += torrent_slots;
+        }
+
+        // Largest remainder first, then highest weight, then lowest hash
+        remainders.sort_by(|a, b| (b.0, b.1, a.2).cmp(&(a.0, a.1, b.2)));
+        for &(_, _, hash) in remainders.iter().take(total_slots - assigned) {
+            *slots.get_mut(&hash).unwrap() += 1;
+        }
+
+        slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PriorityScheduler, TorrentPriority};
+    use ControlMessage;
+    use bip_handshake::InfoHash;
+    use bip_util::bt;
+
+    fn hash(byte: u8) -> InfoHash {
+        [byte; bt::INFO_HASH_LEN].into()
+    }
+
+    fn scheduler(priorities: &[(u8, TorrentPriority)]) -> PriorityScheduler {
+        let mut scheduler = PriorityScheduler::new();
+
+        for &(byte, priority) in priorities {
+            scheduler.process_message(&ControlMessage::SetTorrentPriority(hash(byte), priority));
+        }
+
+        scheduler
+    }
+
+    #[test]
+    fn positive_next_torrent_weighted() {
+        let mut scheduler = scheduler(&[(1, TorrentPriority::High), (2, TorrentPriority::Low)]);
+
+        let picks = (0..10)
+            .map(|_| scheduler.next_torrent(|_| true).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(8, picks.iter().filter(|&&pick| pick == hash(1)).count());
+        assert_eq!(2, picks.iter().filter(|&&pick| pick == hash(2)).count());
+    }
+
+    #[test]
+    fn positive_next_torrent_skips_not_ready() {
+        let mut scheduler = scheduler(&[(1, TorrentPriority::High), (2, TorrentPriority::Low)]);
+
+        for _ in 0..5 {
+            assert_eq!(Some(hash(2)), scheduler.next_torrent(|&pick| pick == hash(2)));
+        }
+    }
+
+    #[test]
+    fn negative_next_torrent_paused() {
+        let mut scheduler = scheduler(&[(1, TorrentPriority::Paused)]);
+
+        assert_eq!(None, scheduler.next_torrent(|_| true));
+    }
+
+    #[test]
+    fn positive_priority_changed_at_runtime() {
+        let mut scheduler = scheduler(&[(1, TorrentPriority::Normal), (2, TorrentPriority::Normal)]);
+        scheduler.process_message(&ControlMessage::SetTorrentPriority(hash(2), TorrentPriority::Paused));
+
+        assert_eq!(Some(TorrentPriority::Paused), scheduler.priority(&hash(2)));
+        for _ in 0..5 {
+            assert_eq!(Some(hash(1)), scheduler.next_torrent(|_| true));
+        }
+    }
+
+    #[test]
+    fn positive_unchoke_slots_weighted() {
+        let scheduler = scheduler(&[
+            (1, TorrentPriority::High),
+            (2, TorrentPriority::Normal),
+            (3, TorrentPriority::Low),
+            (4, TorrentPriority::Paused),
+        ]);
+
+        let slots = scheduler.unchoke_slots(8);
+
+        assert_eq!(Some(&5), slots.get(&hash(1)));
+        assert_eq!(Some(&2), slots.get(&hash(2)));
+        assert_eq!(Some(&1), slots.get(&hash(3)));
+        assert_eq!(None, slots.get(&hash(4)));
+    }
+}
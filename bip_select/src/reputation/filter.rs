@@ -0,0 +1,91 @@
+use bip_handshake::{FilterDecision, HandshakeFilter};
+use std::any::Any;
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, RwLock};
+
+/// `HandshakeFilter` which blocks handshakes from banned addresses.
+///
+/// Filters are created from, and kept up to date by, a `ReputationModule`.
+#[derive(Clone)]
+pub struct BanFilter {
+    banned: Arc<RwLock<HashSet<IpAddr>>>,
+}
+
+impl BanFilter {
+    /// Create a new `BanFilter` with no banned addresses.
+    pub fn new() -> BanFilter {
+        BanFilter { banned: Arc::new(RwLock::new(HashSet::new())) }
+    }
+
+    /// Returns true if the given address has been banned.
+    pub fn is_banned(&self, addr: &IpAddr) -> bool {
+        self.banned
+            .read()
+            .expect("bip_select: BanFilter Failed To Read Banned Addresses")
+            .contains(addr)
+    }
+
+    /// Ban the given address.
+    pub fn ban(&self, addr: IpAddr) {
+        self.banned
+            .write()
+            .expect("bip_select: BanFilter Failed To Write Banned Addresses")
+            .insert(addr);
+    }
+}
+
+impl PartialEq for BanFilter {
+    fn eq(&self, other: &BanFilter) -> bool {
+        // Filters are equal if they share the same ban list
+        Arc::ptr_eq(&self.banned, &other.banned)
+    }
+}
+
+impl Eq for BanFilter {}
+
+impl HandshakeFilter for BanFilter {
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn on_addr(&self, opt_addr: Option<&SocketAddr>) -> FilterDecision {
+        match opt_addr {
+            Some(addr) if self.is_banned(&addr.ip()) => FilterDecision::Block,
+            Some(_) => FilterDecision::Pass,
+            None => FilterDecision::NeedData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BanFilter;
+    use bip_handshake::{FilterDecision, HandshakeFilter};
+
+    #[test]
+    fn positive_block_banned_addr() {
+        let filter = BanFilter::new();
+        filter.ban("1.1.1.1".parse().unwrap());
+
+        assert_eq!(FilterDecision::Block, filter.on_addr(Some(&"1.1.1.1:6881".parse().unwrap())));
+    }
+
+    #[test]
+    fn positive_pass_unbanned_addr() {
+        let filter = BanFilter::new();
+        filter.ban("1.1.1.1".parse().unwrap());
+
+        assert_eq!(FilterDecision::Pass, filter.on_addr(Some(&"2.2.2.2:6881".parse().unwrap())));
+    }
+
+    #[test]
+    fn positive_clones_share_bans() {
+        let filter = BanFilter::new();
+        let clone_filter = filter.clone();
+        filter.ban("1.1.1.1".parse().unwrap());
+
+        assert!(filter == clone_filter);
+        assert!(clone_filter.is_banned(&"1.1.1.1".parse().unwrap()));
+    }
+}
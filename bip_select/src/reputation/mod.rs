@@ -0,0 +1,33 @@
+//! Module for peer reputation.
+
+use ControlMessage;
+use bip_peer::PeerInfo;
+
+mod filter;
+mod tracker;
+
+pub use self::filter::BanFilter;
+pub use self::tracker::{ReputationConfig, ReputationModule};
+
+/// Enumeration of reputation messages that can be sent to a reputation module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IReputationMessage {
+    /// Control message.
+    Control(ControlMessage),
+    /// Peer contributed the given number of bytes to a piece that was found to be good.
+    ContributedGoodPiece(PeerInfo, u64),
+    /// Peer contributed the given number of bytes to a piece that was found to be bad.
+    ContributedBadPiece(PeerInfo, u64),
+    /// Peer sent us an invalid message.
+    ReceivedInvalidMessage(PeerInfo),
+}
+
+/// Enumeration of reputation messages that can be received from a reputation module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OReputationMessage {
+    /// Peer has been banned.
+    ///
+    /// The peer should be removed from the `PeerManager`, and any `BanFilter`
+    /// created by the module will block future handshakes from the peer address.
+    Ban(PeerInfo),
+}
@@ -0,0 +1,351 @@
+use ControlMessage;
+use bip_peer::PeerInfo;
+use futures::{Async, AsyncSink, Sink};
+use futures::Poll;
+use futures::StartSend;
+use futures::Stream;
+use futures::task;
+use futures::task::Task;
+use reputation::{IReputationMessage, OReputationMessage};
+use reputation::filter::BanFilter;
+use std::collections::{HashMap, VecDeque};
+
+const DEFAULT_MAX_INVALID_MESSAGES: usize = 5;
+const DEFAULT_MIN_BAD_BYTES: u64 = 4 * 16 * 1024;
+const DEFAULT_MAX_BAD_RATIO: f64 = 0.5;
+
+/// Configuration for when a `ReputationModule` should ban a peer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReputationConfig {
+    max_invalid_messages: usize,
+    min_bad_bytes: u64,
+    max_bad_ratio: f64,
+}
+
+impl ReputationConfig {
+    /// Create a new `ReputationConfig`.
+    pub fn new() -> ReputationConfig {
+        ReputationConfig {
+            max_invalid_messages: DEFAULT_MAX_INVALID_MESSAGES,
+            min_bad_bytes: DEFAULT_MIN_BAD_BYTES,
+            max_bad_ratio: DEFAULT_MAX_BAD_RATIO,
+        }
+    }
+
+    /// Maximum number of invalid messages a peer can send before being banned.
+    pub fn with_max_invalid_messages(mut self, max_messages: usize) -> ReputationConfig {
+        self.max_invalid_messages = max_messages;
+        self
+    }
+
+    /// Minimum number of bytes a peer has to contribute to bad pieces before being considered for a ban.
+    pub fn with_min_bad_bytes(mut self, min_bytes: u64) -> ReputationConfig {
+        self.min_bad_bytes = min_bytes;
+        self
+    }
+
+    /// Maximum ratio of bad bytes to total bytes contributed that a peer can have before being banned.
+    pub fn with_max_bad_ratio(mut self, max_ratio: f64) -> ReputationConfig {
+        self.max_bad_ratio = max_ratio;
+        self
+    }
+
+    /// Retrieve the max invalid messages.
+    pub fn max_invalid_messages(&self) -> usize {
+        self.max_invalid_messages
+    }
+
+    /// Retrieve the min bad bytes.
+    pub fn min_bad_bytes(&self) -> u64 {
+        self.min_bad_bytes
+    }
+
+    /// Retrieve the max bad ratio.
+    pub fn max_bad_ratio(&self) -> f64 {
+        self.max_bad_ratio
+    }
+}
+
+#[derive(Default)]
+struct PeerReputation {
+    good_bytes: u64,
+    bad_bytes: u64,
+    invalid_messages: usize,
+}
+
+impl PeerReputation {
+    fn should_ban(&self, config: &ReputationConfig) -> bool {
+        let total_bytes = self.good_bytes + self.bad_bytes;
+        let too_many_invalid = self.invalid_messages > config.max_invalid_messages;
+        let too_many_bad = self.bad_bytes >= config.min_bad_bytes && (self.bad_bytes as f64 / total_bytes as f64) > config.max_bad_ratio;
+
+        too_many_invalid || too_many_bad
+    }
+}
+
+//------------------------------------------------------//
+
+/// Reputation module that bans peers which contribute to bad pieces, or send invalid messages.
+pub struct ReputationModule {
+    config: ReputationConfig,
+    peers: HashMap<PeerInfo, PeerReputation>,
+    filter: BanFilter,
+    out_queue: VecDeque<OReputationMessage>,
+    opt_stream: Option<Task>,
+}
+
+impl ReputationModule {
+    /// Create a new `ReputationModule`.
+    pub fn new(config: ReputationConfig) -> ReputationModule {
+        ReputationModule {
+            config: config,
+            peers: HashMap::new(),
+            filter: BanFilter::new(),
+            out_queue: VecDeque::new(),
+            opt_stream: None,
+        }
+    }
+
+    /// Create a `BanFilter` which will block handshakes from any peers we ban.
+    pub fn ban_filter(&self) -> BanFilter {
+        self.filter.clone()
+    }
+
+    fn add_peer(&mut self, info: PeerInfo) {
+        if self.filter.is_banned(&info.addr().ip()) {
+            self.out_queue.push_back(OReputationMessage::Ban(info));
+        } else {
+            self.peers.entry(info).or_insert_with(PeerReputation::default);
+        }
+    }
+
+    fn remove_peer(&mut self, info: PeerInfo) {
+        self.peers.remove(&info);
+    }
+
+    fn update_peer<F>(&mut self, info: PeerInfo, update: F)
+    where
+        F: FnOnce(&mut PeerReputation),
+    {
+        // Peers we don't know about have already disconnected (or been banned), so ignore them
+        let should_ban = match self.peers.get_mut(&info) {
+            Some(reputation) => {
+                update(reputation);
+
+                reputation.should_ban(&self.config)
+            },
+            None => false,
+        };
+
+        if should_ban {
+            self.peers.remove(&info);
+            self.filter.ban(info.addr().ip());
+
+            self.out_queue.push_back(OReputationMessage::Ban(info));
+        }
+    }
+
+    //------------------------------------------------------//
+
+    fn check_stream_unblock(&mut self) {
+        if !self.out_queue.is_empty() {
+            self.opt_stream.take().as_ref().map(Task::notify);
+        }
+    }
+}
+
+impl Sink for ReputationModule {
+    type SinkItem = IReputationMessage;
+    type SinkError = ();
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        match item {
+            IReputationMessage::Control(ControlMessage::PeerConnected(info)) => {
+                self.add_peer(info)
+            },
+            IReputationMessage::Control(ControlMessage::PeerDisconnected(info)) => {
+                self.remove_peer(info)
+            },
+            IReputationMessage::ContributedGoodPiece(info, bytes) => {
+                self.update_peer(info, |reputation| reputation.good_bytes += bytes)
+            },
+            IReputationMessage::ContributedBadPiece(info, bytes) => {
+                self.update_peer(info, |reputation| reputation.bad_bytes += bytes)
+            },
+            IReputationMessage::ReceivedInvalidMessage(info) => {
+                self.update_peer(info, |reputation| reputation.invalid_messages += 1)
+            },
+            IReputationMessage::Control(ControlMessage::AddTorrent(_)) |
+            IReputationMessage::Control(ControlMessage::RemoveTorrent(_)) |
+            IReputationMessage::Control(ControlMessage::Tick(_)) |
+            IReputationMessage::Control(ControlMessage::SetDownloadStrategy(_, _)) |
+            IReputationMessage::Control(ControlMessage::SetTorrentPriority(_, _)) |
+            IReputationMessage::Control(ControlMessage::Shutdown) => (),
+        };
+
+        self.check_stream_unblock();
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        Ok(Async::Ready(()))
+    }
+}
+
+impl Stream for ReputationModule {
+    type Item = OReputationMessage;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let next_item = self.out_queue
+            .pop_front()
+            .map(|item| Ok(Async::Ready(Some(item))));
+
+        next_item.unwrap_or_else(|| {
+            self.opt_stream = Some(task::current());
+
+            Ok(Async::NotReady)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReputationConfig, ReputationModule};
+    use ControlMessage;
+    use bip_handshake::{Direction, Extensions, FilterDecision, HandshakeFilter, TransportKind};
+    use bip_peer::PeerInfo;
+    use bip_util::bt;
+    use futures::{Async, Sink, Stream};
+    use futures_test::harness::Harness;
+    use reputation::{IReputationMessage, OReputationMessage};
+
+    fn peer_info() -> PeerInfo {
+        PeerInfo::new("1.1.1.1:6881".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new(), Direction::Outbound, TransportKind::Tcp)
+    }
+
+    #[test]
+    fn positive_ban_on_invalid_messages() {
+        let module = ReputationModule::new(ReputationConfig::new().with_max_invalid_messages(1));
+        let filter = module.ban_filter();
+        let (send, recv) = module.split();
+        let peer_info = peer_info();
+
+        let mut block_send = send.wait();
+        let mut block_recv = recv.wait();
+
+        block_send
+            .send(IReputationMessage::Control(ControlMessage::PeerConnected(peer_info)))
+            .unwrap();
+        block_send
+            .send(IReputationMessage::ReceivedInvalidMessage(peer_info))
+            .unwrap();
+        block_send
+            .send(IReputationMessage::ReceivedInvalidMessage(peer_info))
+            .unwrap();
+
+        assert_eq!(OReputationMessage::Ban(peer_info), block_recv.next().unwrap().unwrap());
+        assert_eq!(FilterDecision::Block, filter.on_addr(Some(peer_info.addr())));
+    }
+
+    #[test]
+    fn positive_ban_on_bad_ratio() {
+        let module = ReputationModule::new(ReputationConfig::new().with_min_bad_bytes(10).with_max_bad_ratio(0.5));
+        let (send, recv) = module.split();
+        let peer_info = peer_info();
+
+        let mut block_send = send.wait();
+        let mut block_recv = recv.wait();
+
+        block_send
+            .send(IReputationMessage::Control(ControlMessage::PeerConnected(peer_info)))
+            .unwrap();
+        block_send
+            .send(IReputationMessage::ContributedGoodPiece(peer_info, 10))
+            .unwrap();
+        block_send
+            .send(IReputationMessage::ContributedBadPiece(peer_info, 20))
+            .unwrap();
+
+        assert_eq!(OReputationMessage::Ban(peer_info), block_recv.next().unwrap().unwrap());
+    }
+
+    #[test]
+    fn positive_ban_on_reconnect() {
+        let module = ReputationModule::new(ReputationConfig::new().with_max_invalid_messages(0));
+        let (send, recv) = module.split();
+        let peer_info = peer_info();
+
+        let mut block_send = send.wait();
+        let mut block_recv = recv.wait();
+
+        block_send
+            .send(IReputationMessage::Control(ControlMessage::PeerConnected(peer_info)))
+            .unwrap();
+        block_send
+            .send(IReputationMessage::ReceivedInvalidMessage(peer_info))
+            .unwrap();
+        block_send
+            .send(IReputationMessage::Control(ControlMessage::PeerConnected(peer_info)))
+            .unwrap();
+
+        assert_eq!(OReputationMessage::Ban(peer_info), block_recv.next().unwrap().unwrap());
+        assert_eq!(OReputationMessage::Ban(peer_info), block_recv.next().unwrap().unwrap());
+    }
+
+    #[test]
+    fn negative_no_ban_below_min_bad_bytes() {
+        let module = ReputationModule::new(ReputationConfig::new().with_min_bad_bytes(100));
+        let (send, recv) = module.split();
+        let peer_info = peer_info();
+
+        let mut block_send = send.wait();
+        let mut non_block_recv = Harness::new(recv);
+
+        block_send
+            .send(IReputationMessage::Control(ControlMessage::PeerConnected(peer_info)))
+            .unwrap();
+        block_send
+            .send(IReputationMessage::ContributedBadPiece(peer_info, 50))
+            .unwrap();
+
+        assert!(
+            non_block_recv
+                .poll_next()
+                .as_ref()
+                .map(Async::is_not_ready)
+                .unwrap_or(false)
+        );
+    }
+
+    #[test]
+    fn negative_no_ban_after_disconnect() {
+        let module = ReputationModule::new(ReputationConfig::new().with_max_invalid_messages(0));
+        let filter = module.ban_filter();
+        let (send, recv) = module.split();
+        let peer_info = peer_info();
+
+        let mut block_send = send.wait();
+        let mut non_block_recv = Harness::new(recv);
+
+        block_send
+            .send(IReputationMessage::Control(ControlMessage::PeerConnected(peer_info)))
+            .unwrap();
+        block_send
+            .send(IReputationMessage::Control(ControlMessage::PeerDisconnected(peer_info)))
+            .unwrap();
+        block_send
+            .send(IReputationMessage::ReceivedInvalidMessage(peer_info))
+            .unwrap();
+
+        assert!(
+            non_block_recv
+                .poll_next()
+                .as_ref()
+                .map(Async::is_not_ready)
+                .unwrap_or(false)
+        );
+        assert_eq!(FilterDecision::Pass, filter.on_addr(Some(peer_info.addr())));
+    }
+}
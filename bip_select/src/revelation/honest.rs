@@ -199,7 +199,12 @@ impl Sink for HonestRevealModule {
             IRevealMessage::FoundGoodPiece(hash, index) => {
                 self.insert_piece(hash, index)
             },
-            IRevealMessage::Control(ControlMessage::Tick(_)) | IRevealMessage::ReceivedBitField(_, _) | IRevealMessage::ReceivedHave(_, _) => {
+            IRevealMessage::Control(ControlMessage::Tick(_)) |
+            IRevealMessage::Control(ControlMessage::SetDownloadStrategy(_, _)) |
+            IRevealMessage::Control(ControlMessage::SetTorrentPriority(_, _)) |
+            IRevealMessage::Control(ControlMessage::Shutdown) |
+            IRevealMessage::ReceivedBitField(_, _) |
+            IRevealMessage::ReceivedHave(_, _) => {
                 Ok(AsyncSink::Ready)
             },
         };
@@ -235,7 +240,7 @@ impl Stream for HonestRevealModule {
 mod tests {
     use super::HonestRevealModule;
     use ControlMessage;
-    use bip_handshake::Extensions;
+    use bip_handshake::{Direction, Extensions, TransportKind};
     use bip_metainfo::{DirectAccessor, Metainfo, MetainfoBuilder, PieceLength};
     use bip_peer::PeerInfo;
     use bip_util::bt;
@@ -258,7 +263,7 @@ mod tests {
     }
 
     fn peer_info(hash: InfoHash) -> PeerInfo {
-        PeerInfo::new("0.0.0.0:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), hash, Extensions::new())
+        PeerInfo::new("0.0.0.0:0".parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), hash, Extensions::new(), Direction::Outbound, TransportKind::Tcp)
     }
 
     #[test]
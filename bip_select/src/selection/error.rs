@@ -0,0 +1,31 @@
+//! Module for selection error types.
+
+use bip_handshake::InfoHash;
+
+error_chain! {
+    types {
+        SelectError, SelectErrorKind, SelectResultExt;
+    }
+
+    errors {
+        InvalidMetainfoExists {
+            hash: InfoHash
+        } {
+            description("Metainfo Has Already Been Added")
+            display("Metainfo With Hash {:?} Has Already Been Added", hash)
+        }
+        InvalidMetainfoNotExists {
+            hash: InfoHash
+        } {
+            description("Metainfo Was Not Already Added")
+            display("Metainfo With Hash {:?} Was Not Already Added", hash)
+        }
+        InvalidPieceOutOfRange {
+            hash: InfoHash,
+            index: u64
+        } {
+            description("Piece Index Was Out Of Range")
+            display("Piece Index {:?} Was Out Of Range For Hash {:?}", index, hash)
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Module for piece selection.
+
+use ControlMessage;
+use bip_handshake::InfoHash;
+use bip_peer::{PeerInfo, PeerProtocolStats};
+use bip_peer::messages::{BitFieldMessage, HaveMessage, PieceMessage, RequestMessage};
+
+pub mod error;
+
+mod piece;
+
+pub use self::piece::PieceSelectionModule;
+
+/// Policy for blocks that we never requested from a peer.
+///
+/// Blocks that duplicate a block we have already received are always dropped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnsolicitedPiecePolicy {
+    /// Accept the block if we still need it, otherwise drop it.
+    AcceptIfNeeded,
+    /// Drop the block.
+    Drop,
+    /// Drop the block, and penalize the peer that sent it.
+    Penalize,
+}
+
+impl Default for UnsolicitedPiecePolicy {
+    fn default() -> UnsolicitedPiecePolicy {
+        UnsolicitedPiecePolicy::AcceptIfNeeded
+    }
+}
+
+/// Strategy used to select which pieces of a torrent to download next.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DownloadStrategy {
+    /// Download the pieces that the fewest of our peers have first.
+    RarestFirst,
+    /// Download pieces in order, starting from the first piece.
+    Sequential,
+    /// Download pieces in order within the window starting at the given
+    /// piece with the given number of pieces, falling back to rarest
+    /// first for pieces outside of the window.
+    ///
+    /// Useful for streaming, where the window follows the playback position.
+    Window(u64, u64),
+}
+
+impl Default for DownloadStrategy {
+    fn default() -> DownloadStrategy {
+        DownloadStrategy::RarestFirst
+    }
+}
+
+/// Enumeration of selection messages that can be sent to a selection module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ISelectMessage {
+    /// Control message.
+    Control(ControlMessage),
+    /// Good piece for the given `InfoHash` was found.
+    FoundGoodPiece(InfoHash, u64),
+    /// Bad piece for the given `InfoHash` was found.
+    ///
+    /// Blocks received for the piece will be needed again.
+    FoundBadPiece(InfoHash, u64),
+    /// Received a `BitFieldMessage`.
+    ReceivedBitField(PeerInfo, BitFieldMessage),
+    /// Received a `HaveMessage`.
+    ReceivedHave(PeerInfo, HaveMessage),
+    /// Sent a `RequestMessage` to the peer.
+    SentRequest(PeerInfo, RequestMessage),
+    /// Received a `PieceMessage` from the peer.
+    ReceivedPiece(PeerInfo, PieceMessage),
+    /// Received protocol statistics for a peer.
+    ///
+    /// Whether or not the peer is snubbing us will be recorded in the statistics.
+    PeerStats(PeerInfo, PeerProtocolStats),
+}
+
+/// Enumeration of selection messages that can be received from a selection module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OSelectMessage {
+    /// Block should be written to disk.
+    AcceptedPiece(PeerInfo, PieceMessage),
+    /// Peer sent us a block that we never requested from it.
+    ///
+    /// If the block is still needed, and the `UnsolicitedPiecePolicy` allows
+    /// it, this message will be followed by an `AcceptedPiece` message.
+    ReceivedUnsolicitedPiece(PeerInfo, PieceMessage),
+    /// Peer sent us a block that we have already received, the block was dropped.
+    ReceivedDuplicatePiece(PeerInfo, PieceMessage),
+    /// Request with the peer should be cancelled, another peer already sent us the block.
+    ///
+    /// Typically, this would be sent to the peer as a `CancelMessage`.
+    CancelRequest(PeerInfo, RequestMessage),
+    /// Peer should be penalized for sending us an unsolicited block.
+    ///
+    /// Typically, this would be forwarded to a reputation module as an invalid message.
+    PenalizePeer(PeerInfo),
+    /// Peer told us that it is only uploading, and so did we (BEP 21), neither side will request blocks from the other.
+    ///
+    /// Typically, the peer would be disconnected.
+    UninterestedPeer(PeerInfo),
+}
@@ -0,0 +1,1366 @@
+use ControlMessage;
+use bip_handshake::InfoHash;
+use bip_metainfo::Metainfo;
+use bip_peer::{PeerInfo, PeerProtocolStats};
+use bip_peer::messages::{BitFieldMessage, HaveMessage, PieceMessage, RequestMessage};
+use bit_set::BitSet;
+use extended::{ExtendedListener, ExtendedPeerInfo};
+use futures::{Async, AsyncSink, Poll, Sink, StartSend, Stream};
+use futures::task::{self, Task};
+use priority::{PriorityScheduler, TorrentPriority};
+use selection::{DownloadStrategy, ISelectMessage, OSelectMessage, UnsolicitedPiecePolicy};
+use selection::error::{SelectError, SelectErrorKind};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::hash_map::Entry;
+use std::time::Duration;
+
+/// Default time a peer can leave our requests outstanding without sending a block before it is snubbed.
+const DEFAULT_SNUB_TIMEOUT_SECS: u64 = 60;
+/// Maximum number of messages queued for the stream before the sink is blocked.
+const MAX_QUEUED_MESSAGES: usize = 100;
+
+/// Requests outstanding with a peer, and whether or not the peer is snubbing us.
+struct PeerRequests {
+    pending: Vec<RequestMessage>,
+    // Requests re-dispatched to other peers because the peer snubbed us
+    redispatched: Vec<RequestMessage>,
+    // Requests cancelled because another peer sent us the block first
+    cancelled: Vec<RequestMessage>,
+    // Time spent waiting on the peer since it last sent us a block
+    waiting: Duration,
+    snubbed: bool,
+    stats: Option<PeerProtocolStats>,
+}
+
+impl PeerRequests {
+    fn new() -> PeerRequests {
+        PeerRequests {
+            pending: Vec::new(),
+            redispatched: Vec::new(),
+            cancelled: Vec::new(),
+            waiting: Duration::from_secs(0),
+            snubbed: false,
+            stats: None,
+        }
+    }
+
+    fn set_snubbed(&mut self, snubbed: bool) {
+        self.snubbed = snubbed;
+
+        if let Some(ref stats) = self.stats {
+            stats.set_snubbed(snubbed);
+        }
+    }
+}
+
+struct TorrentSelection {
+    strategy: DownloadStrategy,
+    priority: TorrentPriority,
+    // Pieces that we have already downloaded
+    have: BitSet,
+    piece_counts: Vec<usize>,
+    peers: HashMap<PeerInfo, BitSet>,
+    requests: HashMap<PeerInfo, PeerRequests>,
+    // Blocks that were requested from snubbed (or disconnected) peers, and should be requested from others
+    redispatch: Vec<RequestMessage>,
+    // Blocks (piece index, block offset) accepted for pieces that have not been verified yet
+    received: HashSet<(u32, u32)>,
+    // Peers that told us they are only uploading (BEP 21)
+    upload_only: HashSet<PeerInfo>,
+}
+
+/// Outcome of receiving a block from a peer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PieceOutcome {
+    Accepted,
+    Duplicate,
+    Unsolicited { needed: bool },
+}
+
+impl TorrentSelection {
+    fn new(num_pieces: usize) -> TorrentSelection {
+        TorrentSelection {
+            strategy: DownloadStrategy::default(),
+            priority: TorrentPriority::default(),
+            have: BitSet::with_capacity(num_pieces),
+            piece_counts: vec![0; num_pieces],
+            peers: HashMap::new(),
+            requests: HashMap::new(),
+            redispatch: Vec::new(),
+            received: HashSet::new(),
+            upload_only: HashSet::new(),
+        }
+    }
+
+    fn is_snubbed(&self, info: &PeerInfo) -> bool {
+        self.requests.get(info).map(|requests| requests.snubbed).unwrap_or(false)
+    }
+
+    /// Whether or not the piece is available from any peer, other than the given peer, that is not snubbing us.
+    fn available_unsnubbed(&self, info: &PeerInfo, index: usize) -> bool {
+        self.peers
+            .iter()
+            .any(|(peer, pieces)| peer != info && pieces.contains(index) && !self.is_snubbed(peer))
+    }
+
+    fn sent_request(&mut self, info: PeerInfo, request: RequestMessage) {
+        // Re-dispatched blocks are no longer waiting for a peer
+        self.redispatch.retain(|pending| *pending != request);
+
+        let requests = self.requests.entry(info).or_insert_with(PeerRequests::new);
+        if requests.pending.is_empty() {
+            requests.waiting = Duration::from_secs(0);
+        }
+
+        requests.pending.push(request);
+    }
+
+    fn received_piece(&mut self, info: PeerInfo, piece: &PieceMessage) -> PieceOutcome {
+        let block = (piece.piece_index(), piece.block_offset());
+        let is_block = |request: &RequestMessage| (request.piece_index(), request.block_offset()) == block;
+
+        // Pieces from peers we never requested from are unsolicited, they should not get any state
+        let requested = match self.requests.get_mut(&info) {
+            Some(requests) => {
+                let num_pending = requests.pending.len() + requests.redispatched.len();
+                let num_cancelled = requests.cancelled.len();
+
+                requests.pending.retain(|pending| !is_block(pending));
+                requests.redispatched.retain(|redispatched| !is_block(redispatched));
+                requests.cancelled.retain(|cancelled| !is_block(cancelled));
+                if requests.pending.len() + requests.redispatched.len() == num_pending {
+                    // Blocks can still arrive after we cancel them, they were requested all the same
+                    requests.cancelled.len() != num_cancelled
+                } else {
+                    requests.waiting = Duration::from_secs(0);
+
+                    if requests.snubbed {
+                        requests.set_snubbed(false);
+                    }
+
+                    true
+                }
+            },
+            None => false,
+        };
+
+        let index = piece.piece_index() as usize;
+        let in_range = index < self.piece_counts.len();
+        let duplicate = in_range && (self.have.contains(index) || self.received.contains(&block));
+
+        if !requested {
+            PieceOutcome::Unsolicited { needed: in_range && !duplicate }
+        } else if duplicate {
+            PieceOutcome::Duplicate
+        } else {
+            self.received.insert(block);
+
+            PieceOutcome::Accepted
+        }
+    }
+
+    fn accept_unsolicited(&mut self, piece: &PieceMessage) {
+        self.received.insert((piece.piece_index(), piece.block_offset()));
+    }
+
+    /// Stop waiting on any other peer for the accepted block, returning the requests that should be cancelled.
+    fn purge_block(&mut self, info: &PeerInfo, piece: &PieceMessage) -> Vec<(PeerInfo, RequestMessage)> {
+        let block = (piece.piece_index(), piece.block_offset());
+        let is_block = |request: &RequestMessage| (request.piece_index(), request.block_offset()) == block;
+
+        // Nobody else should be asked for the block anymore
+        self.redispatch.retain(|pending| !is_block(pending));
+
+        let mut cancels = Vec::new();
+        for (peer, requests) in self.requests.iter_mut().filter(|&(peer, _)| peer != info) {
+            let PeerRequests { ref mut pending, ref mut redispatched, ref mut cancelled, .. } = *requests;
+
+            for outstanding in [pending, redispatched].iter_mut() {
+                if let Some(position) = outstanding.iter().position(|request| is_block(request)) {
+                    let request = outstanding.remove(position);
+
+                    cancelled.push(request);
+                    cancels.push((*peer, request));
+                }
+            }
+        }
+
+        cancels
+    }
+
+    fn clear_received(&mut self, index: u64) {
+        self.received.retain(|&(piece_index, _)| piece_index as u64 != index);
+        self.redispatch.retain(|redispatch| redispatch.piece_index() as u64 != index);
+
+        // Blocks re-dispatched or cancelled for the piece are not expected anymore
+        for requests in self.requests.values_mut() {
+            requests
+                .redispatched
+                .retain(|redispatched| redispatched.piece_index() as u64 != index);
+            requests
+                .cancelled
+                .retain(|cancelled| cancelled.piece_index() as u64 != index);
+        }
+    }
+
+    fn peer_stats(&mut self, info: PeerInfo, stats: PeerProtocolStats) {
+        let requests = self.requests.entry(info).or_insert_with(PeerRequests::new);
+
+        stats.set_snubbed(requests.snubbed);
+        requests.stats = Some(stats);
+    }
+
+    fn tick(&mut self, duration: Duration, snub_timeout: Duration) {
+        let redispatch = &mut self.redispatch;
+
+        for requests in self.requests.values_mut().filter(|requests| !requests.snubbed && !requests.pending.is_empty()) {
+            requests.waiting += duration;
+
+            if requests.waiting >= snub_timeout {
+                // Requests are remembered for the peer, so a late block from it is not unsolicited
+                requests.set_snubbed(true);
+                requests.redispatched.extend(requests.pending.iter().cloned());
+                redispatch.extend(requests.pending.drain(..));
+            }
+        }
+    }
+
+    fn has_redispatch(&self, info: &PeerInfo) -> bool {
+        if self.is_snubbed(info) {
+            return false;
+        }
+
+        self.peers
+            .get(info)
+            .map(|pieces| self.redispatch.iter().any(|request| pieces.contains(request.piece_index() as usize)))
+            .unwrap_or(false)
+    }
+
+    fn num_pending(&self, info: &PeerInfo) -> usize {
+        self.requests.get(info).map(|requests| requests.pending.len()).unwrap_or(0)
+    }
+
+    fn next_redispatch(&mut self, info: &PeerInfo) -> Option<RequestMessage> {
+        if self.is_snubbed(info) {
+            return None;
+        }
+
+        let opt_position = match self.peers.get(info) {
+            Some(pieces) => self.redispatch
+                .iter()
+                .position(|request| pieces.contains(request.piece_index() as usize)),
+            None => None,
+        };
+
+        opt_position.map(|position| self.redispatch.remove(position))
+    }
+
+    fn add_piece(&mut self, info: PeerInfo, index: usize) {
+        if index >= self.piece_counts.len() {
+            return;
+        }
+
+        if self.peers.entry(info).or_insert_with(BitSet::new).insert(index) {
+            self.piece_counts[index] += 1;
+        }
+    }
+
+    fn remove_peer(&mut self, info: &PeerInfo) {
+        if let Some(pieces) = self.peers.remove(info) {
+            for index in pieces.iter() {
+                self.piece_counts[index] -= 1;
+            }
+        }
+    }
+
+    fn disconnect_peer(&mut self, info: &PeerInfo) {
+        self.remove_peer(info);
+        self.upload_only.remove(info);
+
+        // Any blocks we were waiting on from the peer will have to come from someone else
+        // (blocks pending when the peer was snubbed were already re-dispatched)
+        if let Some(mut requests) = self.requests.remove(info) {
+            self.redispatch.extend(requests.pending.drain(..));
+        }
+    }
+
+    fn next_piece<F>(&self, info: &PeerInfo, mut skip: F) -> Option<u64>
+    where
+        F: FnMut(u64) -> bool,
+    {
+        if self.priority == TorrentPriority::Paused {
+            return None;
+        }
+
+        let peer_pieces = match self.peers.get(info) {
+            Some(pieces) => pieces,
+            None => return None,
+        };
+        // Snubbed peers are only given pieces that no other (unsnubbed) peer can give us
+        let snubbed = self.is_snubbed(info);
+        let mut candidates = peer_pieces.iter().filter(|&index| {
+            !self.have.contains(index) && !(snubbed && self.available_unsnubbed(info, index)) && !skip(index as u64)
+        });
+
+        // Peer pieces are iterated in order, so the first candidate is the lowest index
+        match self.strategy {
+            DownloadStrategy::RarestFirst => {
+                rarest_piece(&self.piece_counts, candidates)
+            },
+            DownloadStrategy::Sequential => {
+                candidates.next().map(|index| index as u64)
+            },
+            DownloadStrategy::Window(start, size) => {
+                let end = start.saturating_add(size);
+                let (in_window, out_window): (Vec<usize>, Vec<usize>) = candidates.partition(|&index| {
+                    let index = index as u64;
+
+                    index >= start && index < end
+                });
+
+                in_window
+                    .first()
+                    .map(|&index| index as u64)
+                    .or_else(|| rarest_piece(&self.piece_counts, out_window.into_iter()))
+            },
+        }
+    }
+}
+
+/// Rarest piece out of the candidates, ties are broken by the lowest index.
+fn rarest_piece<I>(piece_counts: &[usize], candidates: I) -> Option<u64>
+where
+    I: Iterator<Item = usize>,
+{
+    candidates
+        .min_by_key(|&index| (piece_counts[index], index))
+        .map(|index| index as u64)
+}
+
+/// Module for selecting which pieces to download from peers.
+///
+/// Each torrent has a `DownloadStrategy`, which defaults to `DownloadStrategy::RarestFirst`,
+/// and can be changed at any time by sending `ControlMessage::SetDownloadStrategy`. Torrents
+/// paused through `ControlMessage::SetTorrentPriority` will not have any pieces or blocks
+/// selected for them, while other priorities weight the torrent when picking the next peer
+/// to request from, through `PieceSelectionModule::next_peer`, and when splitting unchoke
+/// slots, through `PieceSelectionModule::unchoke_slots`. Piece
+/// availability is learned through `ISelectMessage::ReceivedBitField` and
+/// `ISelectMessage::ReceivedHave`, and pieces we have are learned through
+/// `ISelectMessage::FoundGoodPiece`.
+///
+/// Requests sent to peers are tracked through `ISelectMessage::SentRequest` and
+/// `ISelectMessage::ReceivedPiece`. A peer that leaves our requests outstanding, without
+/// sending us any blocks, for longer than the snub timeout is marked as snubbed. Snubbed
+/// peers are only given pieces that no other peer has, and the blocks that were outstanding
+/// with them are handed out to other peers through `PieceSelectionModule::next_redispatch`.
+///
+/// Received blocks should only be written to disk once the module yields an
+/// `OSelectMessage::AcceptedPiece` for them from the `PieceSelectionModule` stream. Requests
+/// for the same block that are still outstanding with other peers (such as re-dispatched
+/// blocks that a snubbed peer sent us after all) are then yielded as `OSelectMessage::CancelRequest`.
+/// Blocks that duplicate a block we already received are dropped, and blocks that we never requested
+/// from the peer are handled according to the `UnsolicitedPiecePolicy`.
+pub struct PieceSelectionModule {
+    torrents: HashMap<InfoHash, TorrentSelection>,
+    scheduler: PriorityScheduler,
+    snub_timeout: Duration,
+    unsolicited_policy: UnsolicitedPiecePolicy,
+    out_queue: VecDeque<OSelectMessage>,
+    opt_sink: Option<Task>,
+    opt_stream: Option<Task>,
+}
+
+impl PieceSelectionModule {
+    /// Create a new `PieceSelectionModule`.
+    pub fn new() -> PieceSelectionModule {
+        PieceSelectionModule {
+            torrents: HashMap::new(),
+            scheduler: PriorityScheduler::new(),
+            snub_timeout: Duration::from_secs(DEFAULT_SNUB_TIMEOUT_SECS),
+            unsolicited_policy: UnsolicitedPiecePolicy::default(),
+            out_queue: VecDeque::new(),
+            opt_sink: None,
+            opt_stream: None,
+        }
+    }
+
+    /// Time a peer can leave our requests outstanding without sending us a block before it is snubbed.
+    ///
+    /// Defaults to 60 seconds.
+    pub fn with_snub_timeout(mut self, timeout: Duration) -> PieceSelectionModule {
+        self.snub_timeout = timeout;
+        self
+    }
+
+    /// Policy for blocks that we never requested from the peer that sent them.
+    ///
+    /// Defaults to `UnsolicitedPiecePolicy::AcceptIfNeeded`.
+    pub fn with_unsolicited_policy(mut self, policy: UnsolicitedPiecePolicy) -> PieceSelectionModule {
+        self.unsolicited_policy = policy;
+        self
+    }
+
+    fn process_message(&mut self, message: ISelectMessage) -> Result<(), SelectError> {
+        match message {
+            ISelectMessage::Control(ControlMessage::AddTorrent(metainfo)) => {
+                self.add_torrent(metainfo)
+            },
+            ISelectMessage::Control(ControlMessage::RemoveTorrent(metainfo)) => {
+                self.remove_torrent(metainfo)
+            },
+            ISelectMessage::Control(ControlMessage::PeerDisconnected(info)) => {
+                self.remove_peer(info)
+            },
+            ISelectMessage::Control(ControlMessage::Tick(duration)) => {
+                self.tick(duration)
+            },
+            ISelectMessage::Control(ControlMessage::SetDownloadStrategy(hash, strategy)) => {
+                self.set_strategy(hash, strategy)
+            },
+            ISelectMessage::Control(ControlMessage::SetTorrentPriority(hash, priority)) => {
+                self.set_priority(hash, priority)
+            },
+            ISelectMessage::Control(ControlMessage::PeerConnected(_)) |
+            ISelectMessage::Control(ControlMessage::Shutdown) => {
+                Ok(())
+            },
+            ISelectMessage::FoundGoodPiece(hash, index) => {
+                self.insert_piece(hash, index)
+            },
+            ISelectMessage::FoundBadPiece(hash, index) => {
+                self.reset_piece(hash, index)
+            },
+            ISelectMessage::ReceivedBitField(info, bitfield) => {
+                self.recv_bitfield(info, bitfield)
+            },
+            ISelectMessage::ReceivedHave(info, have) => {
+                self.recv_have(info, have)
+            },
+            ISelectMessage::SentRequest(info, request) => {
+                self.sent_request(info, request)
+            },
+            ISelectMessage::ReceivedPiece(info, piece) => {
+                self.recv_piece(info, piece)
+            },
+            ISelectMessage::PeerStats(info, stats) => {
+                self.peer_stats(info, stats)
+            },
+        }
+    }
+
+    /// Whether or not the given peer is currently snubbing us.
+    pub fn is_snubbed(&self, info: &PeerInfo) -> bool {
+        self.torrents
+            .get(info.hash())
+            .map(|torrent| torrent.is_snubbed(info))
+            .unwrap_or(false)
+    }
+
+    /// Whether or not the given peer told us that it is only uploading (BEP 21).
+    ///
+    /// Such peers will never request blocks from us, so they should not be given unchoke slots.
+    pub fn is_upload_only(&self, info: &PeerInfo) -> bool {
+        self.torrents
+            .get(info.hash())
+            .map(|torrent| torrent.upload_only.contains(info))
+            .unwrap_or(false)
+    }
+
+    /// Next block, previously requested from a snubbed or disconnected peer, that should be requested from the given peer.
+    ///
+    /// Only blocks for pieces that the peer has will be given, and snubbed peers will not be given any blocks.
+    pub fn next_redispatch(&mut self, info: &PeerInfo) -> Option<RequestMessage> {
+        self.torrents
+            .get_mut(info.hash())
+            .and_then(|torrent| if torrent.priority == TorrentPriority::Paused {
+                None
+            } else {
+                torrent.next_redispatch(info)
+            })
+    }
+
+    /// Next peer, out of the given peers, that a piece or block should be requested from.
+    ///
+    /// Only peers with a re-dispatched block, or a piece that `next_piece` would select, for
+    /// them are considered. Over many calls, torrents are picked in proportion to the weight
+    /// of their `TorrentPriority`, and the peer with the fewest outstanding requests is picked
+    /// from the torrent. Peers for paused torrents are never picked.
+    pub fn next_peer<'a, I, F>(&mut self, peers: I, mut skip: F) -> Option<PeerInfo>
+    where
+        I: IntoIterator<Item = &'a PeerInfo>,
+        F: FnMut(&PeerInfo, u64) -> bool,
+    {
+        // Least loaded peer, that we can request from, for each torrent
+        let mut ready: HashMap<InfoHash, (PeerInfo, usize)> = HashMap::new();
+        for info in peers {
+            let torrent = match self.torrents.get(info.hash()) {
+                Some(torrent) => torrent,
+                None => continue,
+            };
+            let can_request = torrent.priority != TorrentPriority::Paused &&
+                (torrent.has_redispatch(info) || torrent.next_piece(info, |index| skip(info, index)).is_some());
+            if !can_request {
+                continue;
+            }
+
+            let num_pending = torrent.num_pending(info);
+            let is_better = ready
+                .get(info.hash())
+                .map(|&(_, least_pending)| num_pending < least_pending)
+                .unwrap_or(true);
+            if is_better {
+                ready.insert(*info.hash(), (*info, num_pending));
+            }
+        }
+
+        self.scheduler
+            .next_torrent(|hash| ready.contains_key(hash))
+            .and_then(|hash| ready.remove(&hash))
+            .map(|(info, _)| info)
+    }
+
+    /// Number of unchoke slots each torrent should get out of the total slots available.
+    ///
+    /// Slots are split in proportion to the weight of the `TorrentPriority` of each torrent,
+    /// paused torrents get no slots.
+    pub fn unchoke_slots(&self, total_slots: usize) -> HashMap<InfoHash, usize> {
+        self.scheduler.unchoke_slots(total_slots)
+    }
+
+    /// Current `TorrentPriority` for the given torrent.
+    pub fn torrent_priority(&self, hash: &InfoHash) -> Option<TorrentPriority> {
+        self.torrents.get(hash).map(|torrent| torrent.priority)
+    }
+
+    /// Current `DownloadStrategy` for the given torrent.
+    pub fn download_strategy(&self, hash: &InfoHash) -> Option<DownloadStrategy> {
+        self.torrents.get(hash).map(|torrent| torrent.strategy)
+    }
+
+    /// Next piece that should be downloaded from the given peer.
+    ///
+    /// Pieces that we already have, or that `skip` returns true for (such as pieces
+    /// that have already been requested from other peers), will not be selected.
+    pub fn next_piece<F>(&self, info: &PeerInfo, skip: F) -> Option<u64>
+    where
+        F: FnMut(u64) -> bool,
+    {
+        self.torrents
+            .get(info.hash())
+            .and_then(|torrent| torrent.next_piece(info, skip))
+    }
+
+    fn add_torrent(&mut self, metainfo: Metainfo) -> Result<(), SelectError> {
+        let info_hash = metainfo.info().info_hash();
+
+        match self.torrents.entry(info_hash) {
+            Entry::Occupied(_) => {
+                Err(SelectError::from_kind(SelectErrorKind::InvalidMetainfoExists { hash: info_hash }))
+            },
+            Entry::Vacant(vac) => {
+                vac.insert(TorrentSelection::new(metainfo.info().pieces().count()));
+                self.scheduler.process_message(&ControlMessage::AddTorrent(metainfo));
+
+                Ok(())
+            },
+        }
+    }
+
+    fn remove_torrent(&mut self, metainfo: Metainfo) -> Result<(), SelectError> {
+        let info_hash = metainfo.info().info_hash();
+
+        if self.torrents.remove(&info_hash).is_none() {
+            Err(SelectError::from_kind(SelectErrorKind::InvalidMetainfoNotExists { hash: info_hash }))
+        } else {
+            self.scheduler.process_message(&ControlMessage::RemoveTorrent(metainfo));
+
+            Ok(())
+        }
+    }
+
+    fn remove_peer(&mut self, info: PeerInfo) -> Result<(), SelectError> {
+        self.torrents
+            .get_mut(info.hash())
+            .map(|torrent| torrent.disconnect_peer(&info));
+
+        Ok(())
+    }
+
+    fn tick(&mut self, duration: Duration) -> Result<(), SelectError> {
+        let snub_timeout = self.snub_timeout;
+
+        for torrent in self.torrents.values_mut() {
+            torrent.tick(duration, snub_timeout);
+        }
+
+        Ok(())
+    }
+
+    fn sent_request(&mut self, info: PeerInfo, request: RequestMessage) -> Result<(), SelectError> {
+        let info_hash = *info.hash();
+
+        self.torrents
+            .get_mut(&info_hash)
+            .map(|torrent| {
+                torrent.sent_request(info, request);
+
+                Ok(())
+            })
+            .unwrap_or_else(|| Err(SelectError::from_kind(SelectErrorKind::InvalidMetainfoNotExists { hash: info_hash })))
+    }
+
+    fn recv_piece(&mut self, info: PeerInfo, piece: PieceMessage) -> Result<(), SelectError> {
+        let info_hash = *info.hash();
+        let policy = self.unsolicited_policy;
+        let out_queue = &mut self.out_queue;
+
+        self.torrents
+            .get_mut(&info_hash)
+            .map(|torrent| {
+                match torrent.received_piece(info, &piece) {
+                    PieceOutcome::Accepted => {
+                        let cancels = torrent.purge_block(&info, &piece);
+
+                        out_queue.push_back(OSelectMessage::AcceptedPiece(info, piece));
+                        out_queue.extend(cancels.into_iter().map(|(peer, request)| OSelectMessage::CancelRequest(peer, request)));
+                    },
+                    PieceOutcome::Duplicate => {
+                        out_queue.push_back(OSelectMessage::ReceivedDuplicatePiece(info, piece));
+                    },
+                    PieceOutcome::Unsolicited { needed } => {
+                        out_queue.push_back(OSelectMessage::ReceivedUnsolicitedPiece(info, piece.clone()));
+
+                        match policy {
+                            UnsolicitedPiecePolicy::AcceptIfNeeded if needed => {
+                                torrent.accept_unsolicited(&piece);
+                                let cancels = torrent.purge_block(&info, &piece);
+
+                                out_queue.push_back(OSelectMessage::AcceptedPiece(info, piece));
+                                out_queue.extend(cancels.into_iter().map(|(peer, request)| OSelectMessage::CancelRequest(peer, request)));
+                            },
+                            UnsolicitedPiecePolicy::AcceptIfNeeded |
+                            UnsolicitedPiecePolicy::Drop => (),
+                            UnsolicitedPiecePolicy::Penalize => {
+                                out_queue.push_back(OSelectMessage::PenalizePeer(info));
+                            },
+                        }
+                    },
+                }
+
+                Ok(())
+            })
+            .unwrap_or_else(|| Err(SelectError::from_kind(SelectErrorKind::InvalidMetainfoNotExists { hash: info_hash })))
+    }
+
+    fn peer_stats(&mut self, info: PeerInfo, stats: PeerProtocolStats) -> Result<(), SelectError> {
+        let info_hash = *info.hash();
+
+        self.torrents
+            .get_mut(&info_hash)
+            .map(|torrent| {
+                torrent.peer_stats(info, stats);
+
+                Ok(())
+            })
+            .unwrap_or_else(|| Err(SelectError::from_kind(SelectErrorKind::InvalidMetainfoNotExists { hash: info_hash })))
+    }
+
+    fn set_strategy(&mut self, hash: InfoHash, strategy: DownloadStrategy) -> Result<(), SelectError> {
+        self.torrents
+            .get_mut(&hash)
+            .map(|torrent| {
+                torrent.strategy = strategy;
+
+                Ok(())
+            })
+            .unwrap_or_else(|| Err(SelectError::from_kind(SelectErrorKind::InvalidMetainfoNotExists { hash: hash })))
+    }
+
+    fn set_priority(&mut self, hash: InfoHash, priority: TorrentPriority) -> Result<(), SelectError> {
+        let scheduler = &mut self.scheduler;
+
+        self.torrents
+            .get_mut(&hash)
+            .map(|torrent| {
+                torrent.priority = priority;
+                scheduler.process_message(&ControlMessage::SetTorrentPriority(hash, priority));
+
+                Ok(())
+            })
+            .unwrap_or_else(|| Err(SelectError::from_kind(SelectErrorKind::InvalidMetainfoNotExists { hash: hash })))
+    }
+
+    fn insert_piece(&mut self, hash: InfoHash, index: u64) -> Result<(), SelectError> {
+        self.torrents
+            .get_mut(&hash)
+            .map(|torrent| {
+                if index as usize >= torrent.piece_counts.len() {
+                    Err(SelectError::from_kind(SelectErrorKind::InvalidPieceOutOfRange {
+                        hash: hash,
+                        index: index,
+                    }))
+                } else {
+                    torrent.have.insert(index as usize);
+                    torrent.clear_received(index);
+
+                    Ok(())
+                }
+            })
+            .unwrap_or_else(|| Err(SelectError::from_kind(SelectErrorKind::InvalidMetainfoNotExists { hash: hash })))
+    }
+
+    fn reset_piece(&mut self, hash: InfoHash, index: u64) -> Result<(), SelectError> {
+        self.torrents
+            .get_mut(&hash)
+            .map(|torrent| {
+                torrent.clear_received(index);
+
+                Ok(())
+            })
+            .unwrap_or_else(|| Err(SelectError::from_kind(SelectErrorKind::InvalidMetainfoNotExists { hash: hash })))
+    }
+
+    fn recv_bitfield(&mut self, info: PeerInfo, bitfield: BitFieldMessage) -> Result<(), SelectError> {
+        let info_hash = *info.hash();
+
+        self.torrents
+            .get_mut(&info_hash)
+            .map(|torrent| {
+                // A bitfield replaces anything we knew about the peer
+                torrent.remove_peer(&info);
+
+                for have in bitfield.iter() {
+                    torrent.add_piece(info, have.piece_index() as usize);
+                }
+
+                Ok(())
+            })
+            .unwrap_or_else(|| Err(SelectError::from_kind(SelectErrorKind::InvalidMetainfoNotExists { hash: info_hash })))
+    }
+
+    fn recv_have(&mut self, info: PeerInfo, have: HaveMessage) -> Result<(), SelectError> {
+        let info_hash = *info.hash();
+
+        self.torrents
+            .get_mut(&info_hash)
+            .map(|torrent| {
+                torrent.add_piece(info, have.piece_index() as usize);
+
+                Ok(())
+            })
+            .unwrap_or_else(|| Err(SelectError::from_kind(SelectErrorKind::InvalidMetainfoNotExists { hash: info_hash })))
+    }
+}
+
+impl Sink for PieceSelectionModule {
+    type SinkItem = ISelectMessage;
+    type SinkError = SelectError;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        if self.out_queue.len() >= MAX_QUEUED_MESSAGES {
+            self.opt_sink = Some(task::current());
+
+            return Ok(AsyncSink::NotReady(item));
+        }
+        try!(self.process_message(item));
+
+        if !self.out_queue.is_empty() {
+            self.opt_stream.take().as_ref().map(Task::notify);
+        }
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        Ok(Async::Ready(()))
+    }
+}
+
+impl ExtendedListener for PieceSelectionModule {
+    fn on_update(&mut self, info: &PeerInfo, extended: &ExtendedPeerInfo) {
+        let torrent = match self.torrents.get_mut(info.hash()) {
+            Some(torrent) => torrent,
+            None => return,
+        };
+
+        if !extended.their_upload_only() {
+            torrent.upload_only.remove(info);
+        } else if torrent.upload_only.insert(*info) && extended.our_upload_only() {
+            self.out_queue.push_back(OSelectMessage::UninterestedPeer(*info));
+            self.opt_stream.take().as_ref().map(Task::notify);
+        }
+    }
+}
+
+impl Stream for PieceSelectionModule {
+    type Item = OSelectMessage;
+    type Error = SelectError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let next_item = self.out_queue
+            .pop_front()
+            .map(|item| Ok(Async::Ready(Some(item))));
+
+        if next_item.is_some() && self.out_queue.len() < MAX_QUEUED_MESSAGES {
+            self.opt_sink.take().as_ref().map(Task::notify);
+        }
+
+        next_item.unwrap_or_else(|| {
+            self.opt_stream = Some(task::current());
+
+            Ok(Async::NotReady)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PieceSelectionModule, MAX_QUEUED_MESSAGES};
+    use ControlMessage;
+    use bip_handshake::{Direction, Extensions, TransportKind};
+    use bip_metainfo::{DirectAccessor, Metainfo, MetainfoBuilder, PieceLength};
+    use bip_peer::{PeerInfo, PeerProtocolStats};
+    use bip_peer::messages::{BitFieldMessage, HaveMessage, PieceMessage, RequestMessage};
+    use bip_peer::messages::builders::ExtendedMessageBuilder;
+    use bip_util::bt;
+    use bip_util::bt::InfoHash;
+    use bytes::Bytes;
+    use extended::{ExtendedListener, ExtendedPeerInfo};
+    use futures::{future, Async, Future, Sink};
+    use futures_test::harness::Harness;
+    use priority::TorrentPriority;
+    use selection::{DownloadStrategy, ISelectMessage, OSelectMessage, UnsolicitedPiecePolicy};
+    use std::time::Duration;
+
+    fn metainfo(num_pieces: usize) -> Metainfo {
+        let data = vec![0u8; num_pieces];
+
+        let accessor = DirectAccessor::new("MyFile.txt", &data);
+        let bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(1))
+            .build(1, accessor, |_| ())
+            .unwrap();
+
+        Metainfo::from_bytes(bytes).unwrap()
+    }
+
+    fn peer_info(hash: InfoHash, port: u16) -> PeerInfo {
+        PeerInfo::new(
+            format!("127.0.0.1:{}", port).parse().unwrap(),
+            [0u8; bt::PEER_ID_LEN].into(),
+            hash,
+            Extensions::new(),
+            Direction::Outbound,
+            TransportKind::Tcp,
+        )
+    }
+
+    // Torrent with 8 pieces, where peer one has all pieces, and peer two has pieces 2 and 6
+    fn selection_module() -> (PieceSelectionModule, InfoHash) {
+        let mut module = PieceSelectionModule::new();
+        let metainfo = metainfo(8);
+        let info_hash = metainfo.info().info_hash();
+
+        module
+            .start_send(ISelectMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+        module
+            .start_send(ISelectMessage::ReceivedBitField(peer_info(info_hash, 1), BitFieldMessage::new(Bytes::from(vec![0xFF]))))
+            .unwrap();
+        module
+            .start_send(ISelectMessage::ReceivedHave(peer_info(info_hash, 2), HaveMessage::new(2)))
+            .unwrap();
+        module
+            .start_send(ISelectMessage::ReceivedHave(peer_info(info_hash, 2), HaveMessage::new(6)))
+            .unwrap();
+
+        (module, info_hash)
+    }
+
+    fn set_strategy(module: &mut PieceSelectionModule, hash: InfoHash, strategy: DownloadStrategy) {
+        module
+            .start_send(ISelectMessage::Control(ControlMessage::SetDownloadStrategy(hash, strategy)))
+            .unwrap();
+    }
+
+    #[test]
+    fn positive_rarest_first_by_default() {
+        let (mut module, info_hash) = selection_module();
+
+        assert_eq!(Some(DownloadStrategy::RarestFirst), module.download_strategy(&info_hash));
+        assert_eq!(Some(0), module.next_piece(&peer_info(info_hash, 1), |_| false));
+
+        module
+            .start_send(ISelectMessage::FoundGoodPiece(info_hash, 0))
+            .unwrap();
+
+        assert_eq!(Some(1), module.next_piece(&peer_info(info_hash, 1), |_| false));
+        assert_eq!(Some(2), module.next_piece(&peer_info(info_hash, 2), |_| false));
+    }
+
+    #[test]
+    fn positive_rarest_first_skips_common_pieces() {
+        let (mut module, info_hash) = selection_module();
+
+        // Pieces 2 and 6 are the most common, so they should be downloaded last
+        module
+            .start_send(ISelectMessage::ReceivedHave(peer_info(info_hash, 3), HaveMessage::new(0)))
+            .unwrap();
+
+        assert_eq!(Some(1), module.next_piece(&peer_info(info_hash, 1), |_| false));
+        assert_eq!(Some(3), module.next_piece(&peer_info(info_hash, 1), |index| index == 1));
+    }
+
+    #[test]
+    fn positive_sequential() {
+        let (mut module, info_hash) = selection_module();
+        set_strategy(&mut module, info_hash, DownloadStrategy::Sequential);
+
+        module
+            .start_send(ISelectMessage::ReceivedHave(peer_info(info_hash, 3), HaveMessage::new(0)))
+            .unwrap();
+
+        assert_eq!(Some(0), module.next_piece(&peer_info(info_hash, 1), |_| false));
+        assert_eq!(Some(1), module.next_piece(&peer_info(info_hash, 1), |index| index == 0));
+    }
+
+    #[test]
+    fn positive_window_falls_back_to_rarest_first() {
+        let (mut module, info_hash) = selection_module();
+        set_strategy(&mut module, info_hash, DownloadStrategy::Window(5, 2));
+
+        assert_eq!(Some(5), module.next_piece(&peer_info(info_hash, 1), |_| false));
+        assert_eq!(Some(6), module.next_piece(&peer_info(info_hash, 1), |index| index == 5));
+        assert_eq!(Some(0), module.next_piece(&peer_info(info_hash, 1), |index| index == 5 || index == 6));
+    }
+
+    #[test]
+    fn positive_paused_torrent_selects_nothing() {
+        let (mut module, info_hash) = selection_module();
+
+        module
+            .start_send(ISelectMessage::Control(ControlMessage::SetTorrentPriority(info_hash, TorrentPriority::Paused)))
+            .unwrap();
+        assert_eq!(None, module.next_piece(&peer_info(info_hash, 1), |_| false));
+
+        module
+            .start_send(ISelectMessage::Control(ControlMessage::SetTorrentPriority(info_hash, TorrentPriority::High)))
+            .unwrap();
+        assert_eq!(Some(TorrentPriority::High), module.torrent_priority(&info_hash));
+        assert_eq!(Some(0), module.next_piece(&peer_info(info_hash, 1), |_| false));
+    }
+
+    // Two torrents, each with a single peer that has every piece
+    fn priority_module(priority_one: TorrentPriority, priority_two: TorrentPriority) -> (PieceSelectionModule, PeerInfo, PeerInfo) {
+        let mut module = PieceSelectionModule::new();
+        let (metainfo_one, metainfo_two) = (metainfo(8), metainfo(16));
+        let (peer_one, peer_two) = (peer_info(metainfo_one.info().info_hash(), 1), peer_info(metainfo_two.info().info_hash(), 2));
+
+        for &(ref metainfo, peer, priority) in [(metainfo_one, peer_one, priority_one), (metainfo_two, peer_two, priority_two)].iter() {
+            let info_hash = metainfo.info().info_hash();
+            let num_bytes = (metainfo.info().pieces().count() + 7) / 8;
+
+            module
+                .start_send(ISelectMessage::Control(ControlMessage::AddTorrent(metainfo.clone())))
+                .unwrap();
+            module
+                .start_send(ISelectMessage::ReceivedBitField(peer, BitFieldMessage::new(Bytes::from(vec![0xFF; num_bytes]))))
+                .unwrap();
+            module
+                .start_send(ISelectMessage::Control(ControlMessage::SetTorrentPriority(info_hash, priority)))
+                .unwrap();
+        }
+
+        (module, peer_one, peer_two)
+    }
+
+    #[test]
+    fn positive_next_peer_weighted_by_priority() {
+        let (mut module, peer_one, peer_two) = priority_module(TorrentPriority::High, TorrentPriority::Low);
+
+        let picks = (0..10)
+            .map(|_| module.next_peer(&[peer_one, peer_two], |_, _| false).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(8, picks.iter().filter(|&&pick| pick == peer_one).count());
+        assert_eq!(2, picks.iter().filter(|&&pick| pick == peer_two).count());
+    }
+
+    #[test]
+    fn positive_next_peer_skips_paused_torrent() {
+        let (mut module, peer_one, peer_two) = priority_module(TorrentPriority::High, TorrentPriority::Paused);
+
+        for _ in 0..5 {
+            assert_eq!(Some(peer_one), module.next_peer(&[peer_one, peer_two], |_, _| false));
+        }
+
+        module
+            .start_send(ISelectMessage::Control(ControlMessage::SetTorrentPriority(*peer_one.hash(), TorrentPriority::Paused)))
+            .unwrap();
+        assert_eq!(None, module.next_peer(&[peer_one, peer_two], |_, _| false));
+    }
+
+    #[test]
+    fn positive_next_peer_skips_peer_without_pieces() {
+        let (mut module, peer_one, peer_two) = priority_module(TorrentPriority::High, TorrentPriority::Low);
+
+        // Every piece of the high priority torrent is already being requested
+        for _ in 0..5 {
+            assert_eq!(Some(peer_two), module.next_peer(&[peer_one, peer_two], |info, _| *info == peer_one));
+        }
+    }
+
+    #[test]
+    fn positive_next_peer_least_loaded_in_torrent() {
+        let (mut module, info_hash) = selection_module();
+        let (peer_one, peer_two) = (peer_info(info_hash, 1), peer_info(info_hash, 2));
+
+        assert_eq!(Some(peer_one), module.next_peer(&[peer_one, peer_two], |_, _| false));
+
+        module
+            .start_send(ISelectMessage::SentRequest(peer_one, RequestMessage::new(0, 0, 1)))
+            .unwrap();
+        assert_eq!(Some(peer_two), module.next_peer(&[peer_one, peer_two], |_, _| false));
+    }
+
+    #[test]
+    fn positive_unchoke_slots_by_priority() {
+        let (mut module, peer_one, peer_two) = priority_module(TorrentPriority::High, TorrentPriority::Low);
+
+        let slots = module.unchoke_slots(5);
+        assert_eq!(Some(&4), slots.get(peer_one.hash()));
+        assert_eq!(Some(&1), slots.get(peer_two.hash()));
+
+        module
+            .start_send(ISelectMessage::Control(ControlMessage::SetTorrentPriority(*peer_one.hash(), TorrentPriority::Paused)))
+            .unwrap();
+
+        let slots = module.unchoke_slots(5);
+        assert_eq!(None, slots.get(peer_one.hash()));
+        assert_eq!(Some(&5), slots.get(peer_two.hash()));
+    }
+
+    #[test]
+    fn positive_remove_peer() {
+        let (mut module, info_hash) = selection_module();
+
+        module
+            .start_send(ISelectMessage::Control(ControlMessage::PeerDisconnected(peer_info(info_hash, 2))))
+            .unwrap();
+
+        assert_eq!(None, module.next_piece(&peer_info(info_hash, 2), |_| false));
+    }
+
+    fn snubbed_module() -> (PieceSelectionModule, InfoHash, PeerProtocolStats) {
+        let (module, info_hash) = selection_module();
+        let mut module = module.with_snub_timeout(Duration::from_secs(10));
+        let stats = PeerProtocolStats::new();
+
+        module
+            .start_send(ISelectMessage::PeerStats(peer_info(info_hash, 2), stats.clone()))
+            .unwrap();
+        module
+            .start_send(ISelectMessage::SentRequest(peer_info(info_hash, 2), RequestMessage::new(2, 0, 1)))
+            .unwrap();
+        module
+            .start_send(ISelectMessage::Control(ControlMessage::Tick(Duration::from_secs(10))))
+            .unwrap();
+
+        (module, info_hash, stats)
+    }
+
+    #[test]
+    fn positive_snubbed_peer_redispatched() {
+        let (mut module, info_hash, stats) = snubbed_module();
+
+        assert!(module.is_snubbed(&peer_info(info_hash, 2)));
+        assert!(stats.is_snubbed());
+
+        // Snubbed peers get no re-dispatched blocks, and only pieces no one else has
+        assert_eq!(None, module.next_redispatch(&peer_info(info_hash, 2)));
+        assert_eq!(None, module.next_piece(&peer_info(info_hash, 2), |_| false));
+
+        assert_eq!(Some(RequestMessage::new(2, 0, 1)), module.next_redispatch(&peer_info(info_hash, 1)));
+        assert_eq!(None, module.next_redispatch(&peer_info(info_hash, 1)));
+    }
+
+    #[test]
+    fn positive_snubbed_peer_unsnubbed_on_piece() {
+        let (mut module, info_hash, stats) = snubbed_module();
+
+        module
+            .start_send(ISelectMessage::ReceivedPiece(peer_info(info_hash, 2), PieceMessage::new(2, 0, Bytes::from(vec![0u8]))))
+            .unwrap();
+
+        assert!(!module.is_snubbed(&peer_info(info_hash, 2)));
+        assert!(!stats.is_snubbed());
+        assert_eq!(Some(2), module.next_piece(&peer_info(info_hash, 2), |_| false));
+    }
+
+    #[test]
+    fn positive_snubbed_peer_piece_cancels_redispatch() {
+        let (mut module, info_hash, _) = snubbed_module();
+        let (peer_one, peer_two) = (peer_info(info_hash, 1), peer_info(info_hash, 2));
+        let piece = PieceMessage::new(2, 0, Bytes::from(vec![0u8]));
+
+        let request = module.next_redispatch(&peer_one).unwrap();
+        module
+            .start_send(ISelectMessage::SentRequest(peer_one, request))
+            .unwrap();
+
+        // Snubbed peer sends the block after all, so it should not be downloaded again
+        assert_eq!(
+            vec![
+                OSelectMessage::AcceptedPiece(peer_two, piece.clone()),
+                OSelectMessage::CancelRequest(peer_one, request),
+            ],
+            recv_piece(&mut module, peer_two, piece.clone())
+        );
+        assert_eq!(None, module.next_redispatch(&peer_one));
+        assert_eq!(vec![OSelectMessage::ReceivedDuplicatePiece(peer_one, piece.clone())], recv_piece(&mut module, peer_one, piece));
+    }
+
+    #[test]
+    fn positive_snubbed_peer_piece_purges_redispatch() {
+        let (mut module, info_hash, _) = snubbed_module();
+        let piece = PieceMessage::new(2, 0, Bytes::from(vec![0u8]));
+
+        recv_piece(&mut module, peer_info(info_hash, 2), piece);
+
+        assert_eq!(None, module.next_redispatch(&peer_info(info_hash, 1)));
+    }
+
+    #[test]
+    fn positive_good_piece_clears_redispatch() {
+        let (mut module, info_hash, _) = snubbed_module();
+
+        module
+            .start_send(ISelectMessage::FoundGoodPiece(info_hash, 2))
+            .unwrap();
+
+        assert_eq!(None, module.next_redispatch(&peer_info(info_hash, 1)));
+    }
+
+    #[test]
+    fn negative_snubbed_peer_blocks_not_redispatched_twice() {
+        let (module, info_hash) = selection_module();
+        let mut module = module.with_snub_timeout(Duration::from_secs(10));
+        let peer_two = peer_info(info_hash, 2);
+
+        module
+            .start_send(ISelectMessage::SentRequest(peer_two, RequestMessage::new(2, 0, 1)))
+            .unwrap();
+        module
+            .start_send(ISelectMessage::SentRequest(peer_two, RequestMessage::new(6, 0, 1)))
+            .unwrap();
+        module
+            .start_send(ISelectMessage::Control(ControlMessage::Tick(Duration::from_secs(10))))
+            .unwrap();
+
+        // Peer sends one of the blocks, and is no longer snubbed, but the other block was already re-dispatched
+        recv_piece(&mut module, peer_two, PieceMessage::new(6, 0, Bytes::from(vec![0u8])));
+        module
+            .start_send(ISelectMessage::Control(ControlMessage::Tick(Duration::from_secs(10))))
+            .unwrap();
+
+        assert!(!module.is_snubbed(&peer_two));
+        assert_eq!(Some(RequestMessage::new(2, 0, 1)), module.next_redispatch(&peer_info(info_hash, 1)));
+        assert_eq!(None, module.next_redispatch(&peer_info(info_hash, 1)));
+    }
+
+    #[test]
+    fn negative_peer_not_snubbed_before_timeout() {
+        let (module, info_hash) = selection_module();
+        let mut module = module.with_snub_timeout(Duration::from_secs(10));
+
+        module
+            .start_send(ISelectMessage::SentRequest(peer_info(info_hash, 2), RequestMessage::new(2, 0, 1)))
+            .unwrap();
+        module
+            .start_send(ISelectMessage::Control(ControlMessage::Tick(Duration::from_secs(9))))
+            .unwrap();
+
+        assert!(!module.is_snubbed(&peer_info(info_hash, 2)));
+        assert_eq!(None, module.next_redispatch(&peer_info(info_hash, 1)));
+    }
+
+    fn recv_piece(module: &mut PieceSelectionModule, info: PeerInfo, piece: PieceMessage) -> Vec<OSelectMessage> {
+        module
+            .start_send(ISelectMessage::ReceivedPiece(info, piece))
+            .unwrap();
+
+        let mut messages = Vec::new();
+        while let Async::Ready(Some(message)) = Harness::new(&mut *module).poll_next().unwrap() {
+            messages.push(message);
+        }
+
+        messages
+    }
+
+    #[test]
+    fn positive_requested_piece_accepted_then_duplicate() {
+        let (mut module, info_hash) = selection_module();
+        let (peer_one, peer_two) = (peer_info(info_hash, 1), peer_info(info_hash, 2));
+        let piece = PieceMessage::new(2, 0, Bytes::from(vec![0u8]));
+
+        // Block requested from both peers, only the first one received should be written
+        module
+            .start_send(ISelectMessage::SentRequest(peer_one, RequestMessage::new(2, 0, 1)))
+            .unwrap();
+        module
+            .start_send(ISelectMessage::SentRequest(peer_two, RequestMessage::new(2, 0, 1)))
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                OSelectMessage::AcceptedPiece(peer_one, piece.clone()),
+                OSelectMessage::CancelRequest(peer_two, RequestMessage::new(2, 0, 1)),
+            ],
+            recv_piece(&mut module, peer_one, piece.clone())
+        );
+        assert_eq!(vec![OSelectMessage::ReceivedDuplicatePiece(peer_two, piece.clone())], recv_piece(&mut module, peer_two, piece));
+    }
+
+    #[test]
+    fn positive_bad_piece_block_needed_again() {
+        let (mut module, info_hash) = selection_module();
+        let peer_one = peer_info(info_hash, 1);
+        let piece = PieceMessage::new(2, 0, Bytes::from(vec![0u8]));
+
+        module
+            .start_send(ISelectMessage::SentRequest(peer_one, RequestMessage::new(2, 0, 1)))
+            .unwrap();
+        recv_piece(&mut module, peer_one, piece.clone());
+
+        module
+            .start_send(ISelectMessage::FoundBadPiece(info_hash, 2))
+            .unwrap();
+        module
+            .start_send(ISelectMessage::SentRequest(peer_one, RequestMessage::new(2, 0, 1)))
+            .unwrap();
+
+        assert_eq!(vec![OSelectMessage::AcceptedPiece(peer_one, piece.clone())], recv_piece(&mut module, peer_one, piece));
+    }
+
+    #[test]
+    fn positive_unsolicited_piece_accepted_if_needed() {
+        let (mut module, info_hash) = selection_module();
+        let peer_one = peer_info(info_hash, 1);
+        let piece = PieceMessage::new(2, 0, Bytes::from(vec![0u8]));
+
+        assert_eq!(
+            vec![
+                OSelectMessage::ReceivedUnsolicitedPiece(peer_one, piece.clone()),
+                OSelectMessage::AcceptedPiece(peer_one, piece.clone()),
+            ],
+            recv_piece(&mut module, peer_one, piece.clone())
+        );
+        assert_eq!(vec![OSelectMessage::ReceivedUnsolicitedPiece(peer_one, piece.clone())], recv_piece(&mut module, peer_one, piece));
+
+        // Peer was never requested from, so it should not have picked up any request state
+        assert!(!module.torrents[&info_hash].requests.contains_key(&peer_one));
+    }
+
+    #[test]
+    fn positive_unsolicited_piece_penalized() {
+        let (module, info_hash) = selection_module();
+        let mut module = module.with_unsolicited_policy(UnsolicitedPiecePolicy::Penalize);
+        let peer_one = peer_info(info_hash, 1);
+        let piece = PieceMessage::new(2, 0, Bytes::from(vec![0u8]));
+
+        assert_eq!(
+            vec![
+                OSelectMessage::ReceivedUnsolicitedPiece(peer_one, piece.clone()),
+                OSelectMessage::PenalizePeer(peer_one),
+            ],
+            recv_piece(&mut module, peer_one, piece)
+        );
+    }
+
+    #[test]
+    fn negative_unsolicited_piece_dropped() {
+        let (module, info_hash) = selection_module();
+        let mut module = module.with_unsolicited_policy(UnsolicitedPiecePolicy::Drop);
+        let peer_one = peer_info(info_hash, 1);
+        let piece = PieceMessage::new(2, 0, Bytes::from(vec![0u8]));
+
+        assert_eq!(vec![OSelectMessage::ReceivedUnsolicitedPiece(peer_one, piece.clone())], recv_piece(&mut module, peer_one, piece));
+    }
+
+    fn upload_only_info(ours: bool, theirs: bool) -> ExtendedPeerInfo {
+        let ours = ExtendedMessageBuilder::new().with_upload_only(Some(ours)).build();
+        let theirs = ExtendedMessageBuilder::new().with_upload_only(Some(theirs)).build();
+
+        ExtendedPeerInfo::new(Some(ours), Some(theirs))
+    }
+
+    #[test]
+    fn positive_their_upload_only_tracked_until_disconnect() {
+        let (mut module, info_hash) = selection_module();
+        let peer_one = peer_info(info_hash, 1);
+
+        module.on_update(&peer_one, &upload_only_info(false, true));
+        assert!(module.is_upload_only(&peer_one));
+        assert!(!module.is_upload_only(&peer_info(info_hash, 2)));
+
+        module
+            .start_send(ISelectMessage::Control(ControlMessage::PeerDisconnected(peer_one)))
+            .unwrap();
+        assert!(!module.is_upload_only(&peer_one));
+    }
+
+    #[test]
+    fn positive_both_upload_only_peer_uninterested() {
+        let (mut module, info_hash) = selection_module();
+        let (peer_one, peer_two) = (peer_info(info_hash, 1), peer_info(info_hash, 2));
+
+        // Repeated updates for the same peer are only reported once
+        module.on_update(&peer_one, &upload_only_info(true, true));
+        module.on_update(&peer_one, &upload_only_info(true, true));
+        module.on_update(&peer_two, &upload_only_info(true, false));
+
+        let mut messages = Vec::new();
+        while let Async::Ready(Some(message)) = Harness::new(&mut module).poll_next().unwrap() {
+            messages.push(message);
+        }
+
+        assert_eq!(vec![OSelectMessage::UninterestedPeer(peer_one)], messages);
+        assert!(!module.is_upload_only(&peer_two));
+    }
+
+    #[test]
+    fn positive_full_stream_blocks_sink() {
+        let (module, info_hash) = selection_module();
+        let mut module = module.with_unsolicited_policy(UnsolicitedPiecePolicy::Drop);
+        let received_piece = || ISelectMessage::ReceivedPiece(peer_info(info_hash, 1), PieceMessage::new(2, 0, Bytes::from(vec![0u8])));
+
+        // Each dropped unsolicited block queues a single message
+        for _ in 0..MAX_QUEUED_MESSAGES {
+            assert!(module.start_send(received_piece()).unwrap().is_ready());
+        }
+        assert!(
+            future::lazy(|| module.start_send(received_piece()))
+                .wait()
+                .unwrap()
+                .is_not_ready()
+        );
+
+        Harness::new(&mut module).poll_next().unwrap();
+        assert!(module.start_send(received_piece()).unwrap().is_ready());
+    }
+
+    #[test]
+    fn negative_set_strategy_torrent_not_exists() {
+        let mut module = PieceSelectionModule::new();
+
+        assert!(
+            module
+                .start_send(ISelectMessage::Control(ControlMessage::SetDownloadStrategy(
+                    [0u8; bt::INFO_HASH_LEN].into(),
+                    DownloadStrategy::Sequential,
+                )))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Module for torrent statistics.
+
+use ControlMessage;
+use bip_handshake::InfoHash;
+use bip_peer::{PeerInfo, PeerProtocolStats};
+use bip_peer::messages::{BitFieldMessage, HaveMessage};
+
+mod module;
+mod session;
+mod totals;
+
+pub use self::module::StatisticsModule;
+pub use self::session::TorrentStats;
+pub use self::totals::TorrentTotals;
+
+/// Enumeration of statistics messages that can be sent to a statistics module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IStatisticsMessage {
+    /// Control message.
+    Control(ControlMessage),
+    /// Received protocol statistics for a peer.
+    ///
+    /// These should be forwarded from `OPeerManagerMessage::PeerStats`, which the
+    /// `PeerManager` sends for every peer right before it is removed.
+    PeerStats(PeerInfo, PeerProtocolStats),
+    /// Restore previously persisted totals for the given `InfoHash`.
+    ///
+    /// Replaces any totals accumulated for the torrent so far, so this should
+    /// be sent before any peer statistics for the torrent are received.
+    RestoreTotals(InfoHash, TorrentTotals),
+    /// Good piece for the given `InfoHash` was found.
+    FoundGoodPiece(InfoHash, u64),
+    /// Received a `BitFieldMessage`.
+    ReceivedBitField(PeerInfo, BitFieldMessage),
+    /// Received a `HaveMessage`.
+    ReceivedHave(PeerInfo, HaveMessage),
+    /// Query the current `TorrentStats` for the given `InfoHash`.
+    ///
+    /// Answered with an `OStatisticsMessage::TorrentStats`, if the torrent was added.
+    QueryStats(InfoHash),
+}
+
+/// Enumeration of statistics messages that can be received from a statistics module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OStatisticsMessage {
+    /// Lifetime totals for the given `InfoHash` that should be persisted.
+    ///
+    /// Sent when a torrent is removed, and for every torrent on shutdown.
+    PersistTotals(InfoHash, TorrentTotals),
+    /// Session statistics for the given `InfoHash`, as of the last tick.
+    TorrentStats(InfoHash, TorrentStats),
+}
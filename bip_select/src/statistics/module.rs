@@ -0,0 +1,555 @@
+use ControlMessage;
+use bip_handshake::InfoHash;
+use bip_metainfo::Metainfo;
+use bip_peer::{PeerInfo, PeerProtocolStats};
+use bip_peer::messages::{BitFieldMessage, HaveMessage};
+use bit_set::BitSet;
+use futures::{Async, AsyncSink, Sink};
+use futures::Poll;
+use futures::StartSend;
+use futures::Stream;
+use futures::task;
+use futures::task::Task;
+use statistics::{IStatisticsMessage, OStatisticsMessage};
+use statistics::session::TorrentStats;
+use statistics::totals::TorrentTotals;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Snapshot of the counters last seen for a peer.
+#[derive(Default)]
+struct PeerSnapshot {
+    bytes_sent: usize,
+    bytes_received: usize,
+    pieces: BitSet,
+}
+
+/// Session state for a torrent that was added.
+struct TorrentSession {
+    piece_length: u64,
+    total_length: u64,
+    num_pieces: u64,
+    have: BitSet,
+    bytes_left: u64,
+    // Totals as of the last tick, for calculating rates
+    tick_totals: TorrentTotals,
+    stats: TorrentStats,
+}
+
+impl TorrentSession {
+    fn new(metainfo: &Metainfo, totals: TorrentTotals) -> TorrentSession {
+        let info = metainfo.info();
+        let total_length = info.files().map(|file| file.length()).sum::<u64>();
+
+        TorrentSession {
+            piece_length: info.piece_length(),
+            total_length: total_length,
+            num_pieces: info.pieces().count() as u64,
+            have: BitSet::new(),
+            bytes_left: total_length,
+            tick_totals: totals,
+            stats: TorrentStats::new(totals, 0, 0, total_length, 0, 0),
+        }
+    }
+
+    fn found_good_piece(&mut self, index: u64) {
+        if index >= self.num_pieces || !self.have.insert(index as usize) {
+            return;
+        }
+
+        // Last piece holds whatever is left over
+        let length = if index == self.num_pieces - 1 {
+            self.total_length - self.piece_length * index
+        } else {
+            self.piece_length
+        };
+        self.bytes_left = self.bytes_left.saturating_sub(length);
+    }
+}
+
+/// Statistics module that accumulates lifetime upload and download totals for each torrent.
+///
+/// Totals are fed from the protocol statistics of each peer, and count the message payload bytes
+/// exchanged with the peer (message headers are protocol overhead, and are not counted). Totals are yielded through `OStatisticsMessage::PersistTotals`
+/// so that applications can persist them, and can be restored after a restart through
+/// `IStatisticsMessage::RestoreTotals`.
+///
+/// Session statistics (rates, ETA, and peer counts) are recomputed on every `ControlMessage::Tick`,
+/// and can be queried with `IStatisticsMessage::QueryStats`. Rates are only as fresh as the peer
+/// statistics, so those should be forwarded at least once per tick. Pieces are only tracked for
+/// peers that are connected, bitfields and haves for any other peer are ignored.
+pub struct StatisticsModule {
+    torrents: HashMap<InfoHash, TorrentTotals>,
+    sessions: HashMap<InfoHash, TorrentSession>,
+    peers: HashMap<PeerInfo, PeerSnapshot>,
+    out_queue: VecDeque<OStatisticsMessage>,
+    opt_stream: Option<Task>,
+}
+
+impl StatisticsModule {
+    /// Create a new `StatisticsModule`.
+    pub fn new() -> StatisticsModule {
+        StatisticsModule {
+            torrents: HashMap::new(),
+            sessions: HashMap::new(),
+            peers: HashMap::new(),
+            out_queue: VecDeque::new(),
+            opt_stream: None,
+        }
+    }
+
+    /// Retrieve the current totals for the given `InfoHash`.
+    pub fn totals(&self, hash: &InfoHash) -> Option<TorrentTotals> {
+        self.torrents.get(hash).cloned()
+    }
+
+    /// Retrieve the session statistics, as of the last tick, for the given `InfoHash`.
+    pub fn stats(&self, hash: &InfoHash) -> Option<TorrentStats> {
+        self.sessions.get(hash).map(|session| session.stats)
+    }
+
+    fn add_torrent(&mut self, metainfo: &Metainfo) {
+        let hash = metainfo.info().info_hash();
+        let totals = *self.torrents.entry(hash).or_insert_with(TorrentTotals::default);
+
+        self.sessions.entry(hash).or_insert_with(|| TorrentSession::new(metainfo, totals));
+    }
+
+    fn remove_torrent(&mut self, metainfo: &Metainfo) {
+        let hash = metainfo.info().info_hash();
+        self.sessions.remove(&hash);
+
+        if let Some(totals) = self.torrents.remove(&hash) {
+            self.peers.retain(|info, _| *info.hash() != hash);
+
+            self.out_queue.push_back(OStatisticsMessage::PersistTotals(hash, totals));
+        }
+    }
+
+    fn restore_totals(&mut self, hash: InfoHash, totals: TorrentTotals) {
+        self.torrents.insert(hash, totals);
+
+        // Restored bytes were not transferred this session, so they should not count towards rates
+        if let Some(session) = self.sessions.get_mut(&hash) {
+            session.tick_totals = totals;
+        }
+    }
+
+    fn found_good_piece(&mut self, hash: InfoHash, index: u64) {
+        if let Some(session) = self.sessions.get_mut(&hash) {
+            session.found_good_piece(index);
+        }
+    }
+
+    fn recv_bitfield(&mut self, info: PeerInfo, bitfield: BitFieldMessage) {
+        let num_pieces = match self.sessions.get(info.hash()) {
+            Some(session) => session.num_pieces,
+            None => return,
+        };
+        // Bitfield may arrive after the peer disconnected, which should not bring it back
+        let snapshot = match self.peers.get_mut(&info) {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+
+        // A bitfield replaces anything we knew about the peer, spare bits are ignored
+        snapshot.pieces.clear();
+        for have in bitfield.iter().take_while(|have| (have.piece_index() as u64) < num_pieces) {
+            snapshot.pieces.insert(have.piece_index() as usize);
+        }
+    }
+
+    fn recv_have(&mut self, info: PeerInfo, have: HaveMessage) {
+        let num_pieces = match self.sessions.get(info.hash()) {
+            Some(session) => session.num_pieces,
+            None => return,
+        };
+
+        if (have.piece_index() as u64) < num_pieces {
+            if let Some(snapshot) = self.peers.get_mut(&info) {
+                snapshot.pieces.insert(have.piece_index() as usize);
+            }
+        }
+    }
+
+    fn query_stats(&mut self, hash: InfoHash) {
+        if let Some(stats) = self.stats(&hash) {
+            self.out_queue.push_back(OStatisticsMessage::TorrentStats(hash, stats));
+        }
+    }
+
+    fn tick(&mut self, elapsed: Duration) {
+        let elapsed_millis = elapsed
+            .as_secs()
+            .saturating_mul(1000)
+            .saturating_add((elapsed.subsec_nanos() / 1_000_000) as u64);
+
+        for (hash, session) in self.sessions.iter_mut() {
+            let totals = self.torrents.get(hash).cloned().unwrap_or_default();
+            let (mut download_rate, mut upload_rate) = (session.stats.download_rate(), session.stats.upload_rate());
+
+            // Leave the rates alone if no time has passed, the bytes will count towards the next tick
+            if elapsed_millis != 0 {
+                let downloaded = totals.downloaded().saturating_sub(session.tick_totals.downloaded());
+                let uploaded = totals.uploaded().saturating_sub(session.tick_totals.uploaded());
+
+                download_rate = downloaded.saturating_mul(1000) / elapsed_millis;
+                upload_rate = uploaded.saturating_mul(1000) / elapsed_millis;
+                session.tick_totals = totals;
+            }
+
+            let (mut connected_peers, mut seeding_peers) = (0, 0);
+            for (_, snapshot) in self.peers.iter().filter(|&(info, _)| info.hash() == hash) {
+                connected_peers += 1;
+
+                if snapshot.pieces.len() as u64 == session.num_pieces {
+                    seeding_peers += 1;
+                }
+            }
+
+            session.stats = TorrentStats::new(totals, download_rate, upload_rate, session.bytes_left, connected_peers, seeding_peers);
+        }
+    }
+
+    fn add_peer(&mut self, info: PeerInfo) {
+        self.peers.entry(info).or_insert_with(PeerSnapshot::default);
+    }
+
+    fn remove_peer(&mut self, info: PeerInfo) {
+        self.peers.remove(&info);
+    }
+
+    fn update_peer(&mut self, info: PeerInfo, stats: &PeerProtocolStats) {
+        let totals = match self.torrents.get_mut(info.hash()) {
+            Some(totals) => totals,
+            None => return,
+        };
+        // Stats can still arrive after the peer disconnected, should not bring it back
+        let snapshot = match self.peers.get_mut(&info) {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+
+        // Counters only ever grow, so only the difference since our last snapshot is new
+        let (bytes_sent, bytes_received) = (stats.bytes_sent(), stats.bytes_received());
+        let uploaded = bytes_sent.saturating_sub(snapshot.bytes_sent);
+        let downloaded = bytes_received.saturating_sub(snapshot.bytes_received);
+
+        totals.add(uploaded as u64, downloaded as u64);
+
+        snapshot.bytes_sent = bytes_sent;
+        snapshot.bytes_received = bytes_received;
+    }
+
+    fn shutdown(&mut self) {
+        for (hash, totals) in self.torrents.iter() {
+            self.out_queue.push_back(OStatisticsMessage::PersistTotals(*hash, *totals));
+        }
+    }
+
+    //------------------------------------------------------//
+
+    fn check_stream_unblock(&mut self) {
+        if !self.out_queue.is_empty() {
+            self.opt_stream.take().as_ref().map(Task::notify);
+        }
+    }
+}
+
+impl Sink for StatisticsModule {
+    type SinkItem = IStatisticsMessage;
+    type SinkError = ();
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        match item {
+            IStatisticsMessage::Control(ControlMessage::AddTorrent(metainfo)) => {
+                self.add_torrent(&metainfo)
+            },
+            IStatisticsMessage::Control(ControlMessage::RemoveTorrent(metainfo)) => {
+                self.remove_torrent(&metainfo)
+            },
+            IStatisticsMessage::Control(ControlMessage::PeerConnected(info)) => {
+                self.add_peer(info)
+            },
+            IStatisticsMessage::Control(ControlMessage::PeerDisconnected(info)) => {
+                self.remove_peer(info)
+            },
+            IStatisticsMessage::Control(ControlMessage::Shutdown) => {
+                self.shutdown()
+            },
+            IStatisticsMessage::PeerStats(info, stats) => {
+                self.update_peer(info, &stats)
+            },
+            IStatisticsMessage::RestoreTotals(hash, totals) => {
+                self.restore_totals(hash, totals)
+            },
+            IStatisticsMessage::FoundGoodPiece(hash, index) => {
+                self.found_good_piece(hash, index)
+            },
+            IStatisticsMessage::ReceivedBitField(info, bitfield) => {
+                self.recv_bitfield(info, bitfield)
+            },
+            IStatisticsMessage::ReceivedHave(info, have) => {
+                self.recv_have(info, have)
+            },
+            IStatisticsMessage::QueryStats(hash) => {
+                self.query_stats(hash)
+            },
+            IStatisticsMessage::Control(ControlMessage::Tick(elapsed)) => {
+                self.tick(elapsed)
+            },
+            IStatisticsMessage::Control(ControlMessage::SetDownloadStrategy(_, _)) |
+            IStatisticsMessage::Control(ControlMessage::SetTorrentPriority(_, _)) => (),
+        };
+
+        self.check_stream_unblock();
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        Ok(Async::Ready(()))
+    }
+}
+
+impl Stream for StatisticsModule {
+    type Item = OStatisticsMessage;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let next_item = self.out_queue
+            .pop_front()
+            .map(|item| Ok(Async::Ready(Some(item))));
+
+        next_item.unwrap_or_else(|| {
+            self.opt_stream = Some(task::current());
+
+            Ok(Async::NotReady)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatisticsModule;
+    use ControlMessage;
+    use bip_handshake::{Direction, Extensions, TransportKind};
+    use bip_metainfo::{DirectAccessor, Metainfo, MetainfoBuilder, PieceLength};
+    use bip_peer::{PeerInfo, PeerProtocolStats};
+    use bip_peer::messages::{BitFieldMessage, HaveMessage};
+    use bip_util::bt;
+    use bip_util::bt::InfoHash;
+    use bytes::Bytes;
+    use futures::{Sink, Stream};
+    use statistics::{IStatisticsMessage, OStatisticsMessage, TorrentStats, TorrentTotals};
+    use std::time::Duration;
+
+    fn metainfo() -> Metainfo {
+        let data = vec![0u8; 10];
+
+        let accessor = DirectAccessor::new("MyFile.txt", &data);
+        let bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(1))
+            .build(1, accessor, |_| ())
+            .unwrap();
+
+        Metainfo::from_bytes(bytes).unwrap()
+    }
+
+    fn peer_info(hash: InfoHash) -> PeerInfo {
+        peer_info_with_port(hash, 0)
+    }
+
+    fn peer_info_with_port(hash: InfoHash, port: u16) -> PeerInfo {
+        PeerInfo::new(format!("0.0.0.0:{}", port).parse().unwrap(), [0u8; bt::PEER_ID_LEN].into(), hash, Extensions::new(), Direction::Outbound, TransportKind::Tcp)
+    }
+
+    #[test]
+    fn positive_accumulate_peer_stats_deltas() {
+        let metainfo = metainfo();
+        let hash = metainfo.info().info_hash();
+        let peer_info = peer_info(hash);
+        let stats = PeerProtocolStats::new();
+
+        let mut module = StatisticsModule::new();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::AddTorrent(metainfo))).unwrap();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::PeerConnected(peer_info))).unwrap();
+
+        // Each recorded message includes a 5 byte header, which is not counted towards the totals
+        stats.record_bytes_sent(105);
+        stats.record_bytes_received(55);
+        module.start_send(IStatisticsMessage::PeerStats(peer_info, stats.clone())).unwrap();
+
+        stats.record_bytes_sent(25);
+        module.start_send(IStatisticsMessage::PeerStats(peer_info, stats.clone())).unwrap();
+
+        assert_eq!(Some(TorrentTotals::new(120, 50)), module.totals(&hash));
+    }
+
+    #[test]
+    fn positive_restore_and_persist_totals() {
+        let metainfo = metainfo();
+        let hash = metainfo.info().info_hash();
+        let peer_info = peer_info(hash);
+        let stats = PeerProtocolStats::new();
+
+        let (send, recv) = StatisticsModule::new().split();
+        let mut block_send = send.wait();
+        let mut block_recv = recv.wait();
+
+        block_send.send(IStatisticsMessage::RestoreTotals(hash, TorrentTotals::new(1000, 500))).unwrap();
+        block_send.send(IStatisticsMessage::Control(ControlMessage::AddTorrent(metainfo.clone()))).unwrap();
+        block_send.send(IStatisticsMessage::Control(ControlMessage::PeerConnected(peer_info))).unwrap();
+
+        stats.record_bytes_received(15);
+        block_send.send(IStatisticsMessage::PeerStats(peer_info, stats)).unwrap();
+        block_send.send(IStatisticsMessage::Control(ControlMessage::RemoveTorrent(metainfo))).unwrap();
+
+        assert_eq!(OStatisticsMessage::PersistTotals(hash, TorrentTotals::new(1000, 510)), block_recv.next().unwrap().unwrap());
+    }
+
+    #[test]
+    fn negative_ignore_stats_for_unknown_torrent() {
+        let hash = [1u8; bt::INFO_HASH_LEN].into();
+        let stats = PeerProtocolStats::new();
+
+        let mut module = StatisticsModule::new();
+
+        stats.record_bytes_sent(105);
+        module.start_send(IStatisticsMessage::PeerStats(peer_info(hash), stats)).unwrap();
+
+        assert_eq!(None, module.totals(&hash));
+    }
+
+    #[test]
+    fn negative_ignore_stats_for_unknown_peer() {
+        let metainfo = metainfo();
+        let hash = metainfo.info().info_hash();
+        let peer_info = peer_info(hash);
+        let stats = PeerProtocolStats::new();
+
+        let mut module = StatisticsModule::new();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::AddTorrent(metainfo))).unwrap();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::PeerConnected(peer_info))).unwrap();
+
+        stats.record_bytes_received(15);
+        module.start_send(IStatisticsMessage::PeerStats(peer_info, stats.clone())).unwrap();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::PeerDisconnected(peer_info))).unwrap();
+
+        // Stats that were still in flight when the peer disconnected, and stats for a peer that never connected
+        module.start_send(IStatisticsMessage::PeerStats(peer_info, stats.clone())).unwrap();
+        module.start_send(IStatisticsMessage::PeerStats(peer_info_with_port(hash, 1), stats)).unwrap();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::Tick(Duration::from_secs(1)))).unwrap();
+
+        assert_eq!(Some(TorrentTotals::new(0, 15)), module.totals(&hash));
+        assert_eq!(0, module.stats(&hash).unwrap().connected_peers());
+    }
+
+    #[test]
+    fn positive_stats_recomputed_on_tick() {
+        let metainfo = metainfo();
+        let hash = metainfo.info().info_hash();
+        let (seeder, leecher) = (peer_info_with_port(hash, 1), peer_info_with_port(hash, 2));
+        let stats = PeerProtocolStats::new();
+
+        let mut module = StatisticsModule::new();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::AddTorrent(metainfo))).unwrap();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::PeerConnected(seeder))).unwrap();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::PeerConnected(leecher))).unwrap();
+
+        // Spare bits at the end of the bitfield should not count towards the pieces
+        module.start_send(IStatisticsMessage::ReceivedBitField(seeder, BitFieldMessage::new(Bytes::from(vec![0xFF, 0xFF])))).unwrap();
+        module.start_send(IStatisticsMessage::ReceivedHave(leecher, HaveMessage::new(0))).unwrap();
+
+        stats.record_bytes_received(9);
+        stats.record_bytes_sent(7);
+        module.start_send(IStatisticsMessage::PeerStats(seeder, stats)).unwrap();
+        for index in 0..4 {
+            module.start_send(IStatisticsMessage::FoundGoodPiece(hash, index)).unwrap();
+        }
+
+        // Nothing is recomputed until the next tick
+        assert_eq!(Some(TorrentStats::new(TorrentTotals::default(), 0, 0, 10, 0, 0)), module.stats(&hash));
+
+        module.start_send(IStatisticsMessage::Control(ControlMessage::Tick(Duration::from_millis(500)))).unwrap();
+        let stats = module.stats(&hash).unwrap();
+
+        assert_eq!(8, stats.download_rate());
+        assert_eq!(4, stats.upload_rate());
+        assert_eq!(6, stats.bytes_left());
+        assert_eq!(Some(Duration::from_secs(1)), stats.eta());
+        assert_eq!(0.5, stats.ratio());
+        assert_eq!(2, stats.connected_peers());
+        assert_eq!(1, stats.seeding_peers());
+
+        // Rates drop back down when nothing was transferred during a tick
+        module.start_send(IStatisticsMessage::Control(ControlMessage::Tick(Duration::from_millis(500)))).unwrap();
+        assert_eq!(0, module.stats(&hash).unwrap().download_rate());
+    }
+
+    #[test]
+    fn negative_ignore_pieces_for_unknown_peer() {
+        let metainfo = metainfo();
+        let hash = metainfo.info().info_hash();
+        let peer_info = peer_info(hash);
+
+        let mut module = StatisticsModule::new();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::AddTorrent(metainfo))).unwrap();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::PeerConnected(peer_info))).unwrap();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::PeerDisconnected(peer_info))).unwrap();
+
+        // Messages from the peer that were still in flight when it disconnected
+        module.start_send(IStatisticsMessage::ReceivedBitField(peer_info, BitFieldMessage::new(Bytes::from(vec![0xFF, 0xFF])))).unwrap();
+        module.start_send(IStatisticsMessage::ReceivedHave(peer_info_with_port(hash, 1), HaveMessage::new(0))).unwrap();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::Tick(Duration::from_secs(1)))).unwrap();
+
+        let stats = module.stats(&hash).unwrap();
+        assert_eq!(0, stats.connected_peers());
+        assert_eq!(0, stats.seeding_peers());
+    }
+
+    #[test]
+    fn positive_tick_with_huge_elapsed_time() {
+        let metainfo = metainfo();
+        let hash = metainfo.info().info_hash();
+
+        let mut module = StatisticsModule::new();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::AddTorrent(metainfo))).unwrap();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::Tick(Duration::from_secs(u64::max_value())))).unwrap();
+
+        assert_eq!(0, module.stats(&hash).unwrap().download_rate());
+    }
+
+    #[test]
+    fn positive_query_stats() {
+        let metainfo = metainfo();
+        let hash = metainfo.info().info_hash();
+
+        let (send, recv) = StatisticsModule::new().split();
+        let mut block_send = send.wait();
+        let mut block_recv = recv.wait();
+
+        block_send.send(IStatisticsMessage::Control(ControlMessage::AddTorrent(metainfo))).unwrap();
+        block_send.send(IStatisticsMessage::QueryStats([1u8; bt::INFO_HASH_LEN].into())).unwrap();
+        block_send.send(IStatisticsMessage::QueryStats(hash)).unwrap();
+
+        let expected = TorrentStats::new(TorrentTotals::default(), 0, 0, 10, 0, 0);
+        assert_eq!(OStatisticsMessage::TorrentStats(hash, expected), block_recv.next().unwrap().unwrap());
+    }
+
+    #[test]
+    fn positive_restored_totals_do_not_count_towards_rates() {
+        let metainfo = metainfo();
+        let hash = metainfo.info().info_hash();
+
+        let mut module = StatisticsModule::new();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::AddTorrent(metainfo))).unwrap();
+        module.start_send(IStatisticsMessage::RestoreTotals(hash, TorrentTotals::new(1000, 500))).unwrap();
+        module.start_send(IStatisticsMessage::Control(ControlMessage::Tick(Duration::from_secs(1)))).unwrap();
+
+        let stats = module.stats(&hash).unwrap();
+        assert_eq!(TorrentTotals::new(1000, 500), stats.totals());
+        assert_eq!(0, stats.download_rate());
+        assert_eq!(0, stats.upload_rate());
+    }
+}
@@ -0,0 +1,112 @@
+use statistics::totals::TorrentTotals;
+use std::time::Duration;
+
+/// Session statistics for a single torrent, recomputed on every `ControlMessage::Tick`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TorrentStats {
+    totals: TorrentTotals,
+    download_rate: u64,
+    upload_rate: u64,
+    bytes_left: u64,
+    connected_peers: usize,
+    seeding_peers: usize,
+}
+
+impl TorrentStats {
+    /// Create a new `TorrentStats`.
+    pub fn new(
+        totals: TorrentTotals,
+        download_rate: u64,
+        upload_rate: u64,
+        bytes_left: u64,
+        connected_peers: usize,
+        seeding_peers: usize,
+    ) -> TorrentStats {
+        TorrentStats {
+            totals: totals,
+            download_rate: download_rate,
+            upload_rate: upload_rate,
+            bytes_left: bytes_left,
+            connected_peers: connected_peers,
+            seeding_peers: seeding_peers,
+        }
+    }
+
+    /// Lifetime totals for the torrent.
+    pub fn totals(&self) -> TorrentTotals {
+        self.totals
+    }
+
+    /// Download rate, in bytes per second, since the last tick.
+    pub fn download_rate(&self) -> u64 {
+        self.download_rate
+    }
+
+    /// Upload rate, in bytes per second, since the last tick.
+    pub fn upload_rate(&self) -> u64 {
+        self.upload_rate
+    }
+
+    /// Number of bytes of the torrent that have not been verified yet.
+    pub fn bytes_left(&self) -> u64 {
+        self.bytes_left
+    }
+
+    /// Estimated time until the torrent is complete, at the current download rate.
+    ///
+    /// Returns `None` if nothing is being downloaded, and the torrent is not complete.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.bytes_left == 0 {
+            Some(Duration::from_secs(0))
+        } else if self.download_rate == 0 {
+            None
+        } else {
+            let secs = (self.bytes_left + self.download_rate - 1) / self.download_rate;
+
+            Some(Duration::from_secs(secs))
+        }
+    }
+
+    /// Ratio of bytes uploaded to bytes downloaded, see `TorrentTotals::ratio`.
+    pub fn ratio(&self) -> f64 {
+        self.totals.ratio()
+    }
+
+    /// Number of peers we are connected to for the torrent.
+    pub fn connected_peers(&self) -> usize {
+        self.connected_peers
+    }
+
+    /// Number of connected peers that have every piece of the torrent.
+    pub fn seeding_peers(&self) -> usize {
+        self.seeding_peers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TorrentStats;
+    use statistics::TorrentTotals;
+    use std::time::Duration;
+
+    #[test]
+    fn positive_eta_rounds_up() {
+        let stats = TorrentStats::new(TorrentTotals::default(), 100, 0, 250, 1, 1);
+
+        assert_eq!(Some(Duration::from_secs(3)), stats.eta());
+    }
+
+    #[test]
+    fn positive_eta_complete() {
+        let stats = TorrentStats::new(TorrentTotals::default(), 0, 100, 0, 1, 0);
+
+        assert_eq!(Some(Duration::from_secs(0)), stats.eta());
+    }
+
+    #[test]
+    fn negative_eta_stalled() {
+        let stats = TorrentStats::new(TorrentTotals::default(), 0, 0, 250, 0, 0);
+
+        assert_eq!(None, stats.eta());
+    }
+}
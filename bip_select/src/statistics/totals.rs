@@ -0,0 +1,89 @@
+use byteorder::{BigEndian, ByteOrder};
+
+const TOTALS_LEN: usize = 16;
+
+/// Lifetime transfer totals for a single torrent.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TorrentTotals {
+    uploaded: u64,
+    downloaded: u64,
+}
+
+impl TorrentTotals {
+    /// Create a new `TorrentTotals`.
+    pub fn new(uploaded: u64, downloaded: u64) -> TorrentTotals {
+        TorrentTotals {
+            uploaded: uploaded,
+            downloaded: downloaded,
+        }
+    }
+
+    /// Parse `TorrentTotals` previously written by `TorrentTotals::to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Option<TorrentTotals> {
+        if bytes.len() != TOTALS_LEN {
+            return None;
+        }
+
+        Some(TorrentTotals::new(BigEndian::read_u64(&bytes[..8]), BigEndian::read_u64(&bytes[8..])))
+    }
+
+    /// Write the `TorrentTotals` out so that they can be persisted.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0u8; TOTALS_LEN];
+
+        BigEndian::write_u64(&mut bytes[..8], self.uploaded);
+        BigEndian::write_u64(&mut bytes[8..], self.downloaded);
+
+        bytes
+    }
+
+    /// Total number of bytes uploaded.
+    pub fn uploaded(&self) -> u64 {
+        self.uploaded
+    }
+
+    /// Total number of bytes downloaded.
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded
+    }
+
+    /// Ratio of bytes uploaded to bytes downloaded.
+    ///
+    /// Returns `0.0` if nothing has been downloaded.
+    pub fn ratio(&self) -> f64 {
+        if self.downloaded == 0 {
+            0.0
+        } else {
+            self.uploaded as f64 / self.downloaded as f64
+        }
+    }
+
+    /// Add the given number of bytes to the totals.
+    pub fn add(&mut self, uploaded: u64, downloaded: u64) {
+        self.uploaded = self.uploaded.saturating_add(uploaded);
+        self.downloaded = self.downloaded.saturating_add(downloaded);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TorrentTotals;
+
+    #[test]
+    fn positive_round_trip_bytes() {
+        let totals = TorrentTotals::new(u64::max_value() - 1, 500);
+
+        assert_eq!(Some(totals), TorrentTotals::from_bytes(&totals.to_bytes()));
+    }
+
+    #[test]
+    fn negative_from_bytes_wrong_length() {
+        assert_eq!(None, TorrentTotals::from_bytes(&[0u8; 15]));
+    }
+
+    #[test]
+    fn positive_ratio() {
+        assert_eq!(0.0, TorrentTotals::new(100, 0).ratio());
+        assert_eq!(2.0, TorrentTotals::new(100, 50).ratio());
+    }
+}
@@ -1,4 +1,5 @@
 use ControlMessage;
+use bip_peer::PeerInfo;
 use bip_peer::messages::builders::ExtendedMessageBuilder;
 use discovery::IDiscoveryMessage;
 use discovery::ODiscoveryMessage;
@@ -13,6 +14,14 @@ use futures::Poll;
 use futures::Sink;
 use futures::StartSend;
 use futures::Stream;
+use futures::task::{self, Task};
+use policy::{AcceptAllPolicy, PeerPolicy};
+use selection::{ISelectMessage, OSelectMessage, PieceSelectionModule};
+use selection::error::SelectError;
+use statistics::{IStatisticsMessage, OStatisticsMessage, StatisticsModule};
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant};
 
 trait DiscoveryTrait
     : ExtendedListener + Sink<SinkItem = IDiscoveryMessage, SinkError = DiscoveryError> + Stream<Item = ODiscoveryMessage, Error = DiscoveryError>
@@ -24,6 +33,12 @@ where
 {
 }
 
+/// Stream of timer events that internal ticks are generated from.
+type BoxedTicks = Box<Stream<Item = (), Error = io::Error> + Send>;
+
+// TODO: Remove these bounds when something like https://github.com/rust-lang/rust/pull/45047 lands
+type BoxedDiscovery = Box<DiscoveryTrait<SinkItem = IDiscoveryMessage, SinkError = DiscoveryError, Item = ODiscoveryMessage, Error = DiscoveryError>>;
+
 /// Enumeration of uber messages that can be sent to the uber module.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum IUberMessage {
@@ -33,6 +48,13 @@ pub enum IUberMessage {
     Extended(IExtendedMessage),
     /// Send a discovery message to all discovery modules.
     Discovery(IDiscoveryMessage),
+    /// Send a statistics message to the statistics module.
+    Statistics(IStatisticsMessage),
+    /// Send a selection message to the selection module.
+    ///
+    /// Peer statistics, pieces that peers have, and good pieces that were found are also
+    /// forwarded to the statistics module, so they only have to be sent once.
+    Selection(ISelectMessage),
 }
 
 /// Enumeration of uber messages that can be received from the uber module.
@@ -42,13 +64,44 @@ pub enum OUberMessage {
     Extended(OExtendedMessage),
     /// Receive a discovery message from some discovery module.
     Discovery(ODiscoveryMessage),
+    /// Receive a statistics message from the statistics module.
+    Statistics(OStatisticsMessage),
+    /// Receive a selection message from the selection module.
+    Selection(OSelectMessage),
+    /// Disconnect from the given peer, since it was rejected by the `PeerPolicy`.
+    DisconnectPeer(PeerInfo),
+    /// Module with the given name failed with the given error, and was handled according to the `ModuleErrorPolicy`.
+    ModuleError(String, String),
 }
 
+/// Enumeration of actions the uber module can take when one of its modules fails.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ModuleErrorPolicy {
+    /// Keep the module, it will continue to receive messages.
+    Ignore,
+    /// Remove the module, it will no longer receive messages.
+    Remove,
+    /// Replace the module with a new one from its factory, or remove it if it was not added with a factory.
+    Restart,
+}
+
+/// Default number of peers that have to agree on our external address.
+const DEFAULT_MIN_ADDR_VOTES: usize = 3;
+/// Maximum number of module errors queued up before the oldest errors are dropped.
+const MAX_MODULE_ERRORS: usize = 64;
+/// Name that errors from the selection module are reported under.
+const SELECTION_MODULE_NAME: &'static str = "selection";
+
 /// Builder for constructing an `UberModule`.
 pub struct UberModuleBuilder {
-    // TODO: Remove these bounds when something like https://github.com/rust-lang/rust/pull/45047 lands
-    discovery: Vec<Box<DiscoveryTrait<SinkItem = IDiscoveryMessage, SinkError = DiscoveryError, Item = ODiscoveryMessage, Error = DiscoveryError>>>,
+    discovery: Vec<(BoxedDiscovery, ModuleInfo)>,
     ext_builder: Option<ExtendedMessageBuilder>,
+    statistics: Option<StatisticsModule>,
+    selection: Option<PieceSelectionModule>,
+    min_addr_votes: usize,
+    policy: Box<PeerPolicy + Send>,
+    error_policy: ModuleErrorPolicy,
+    ticks: Option<BoxedTicks>,
 }
 
 impl UberModuleBuilder {
@@ -57,9 +110,49 @@ impl UberModuleBuilder {
         UberModuleBuilder {
             discovery: Vec::new(),
             ext_builder: None,
+            statistics: None,
+            selection: None,
+            min_addr_votes: DEFAULT_MIN_ADDR_VOTES,
+            policy: Box::new(AcceptAllPolicy::new()),
+            error_policy: ModuleErrorPolicy::Remove,
+            ticks: None,
         }
     }
 
+    /// Specifies that the `UberModule` should generate its own `ControlMessage::Tick`s, whenever the given stream fires.
+    ///
+    /// Any timer stream will do, such as a `tokio_core::reactor::Interval`. Internal ticks are driven by polling the
+    /// `UberModule` stream, and carry the time elapsed since the last internal tick. External ticks are still accepted
+    /// (useful for testing), but are not needed. By default, no internal ticks are generated.
+    pub fn with_internal_ticks<S>(mut self, ticks: S) -> UberModuleBuilder
+    where
+        S: Stream<Item = (), Error = io::Error> + Send + 'static,
+    {
+        self.ticks = Some(Box::new(ticks));
+        self
+    }
+
+    /// Specifies the policy that will be applied when a module fails.
+    ///
+    /// Failures are always reported as an `OUberMessage::ModuleError` instead of failing the
+    /// `UberModule` itself. By default, modules that fail are removed.
+    pub fn with_module_error_policy(mut self, error_policy: ModuleErrorPolicy) -> UberModuleBuilder {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// Specifies the policy that will be consulted when a `ControlMessage::PeerConnected` is received.
+    ///
+    /// Peers that are rejected by the policy will not be forwarded to any modules, and instead, an
+    /// `OUberMessage::DisconnectPeer` will be emitted for them. By default, all peers are accepted.
+    pub fn with_peer_policy<P>(mut self, policy: P) -> UberModuleBuilder
+    where
+        P: PeerPolicy + Send + 'static,
+    {
+        self.policy = Box::new(policy);
+        self
+    }
+
     /// Specifies the given builder that all modules will add to when sending an extended message to a peer.
     ///
     /// This message will only be sent when the extension bit from the handshake it set. Note that if a builder
@@ -69,18 +162,79 @@ impl UberModuleBuilder {
         self
     }
 
+    /// Specifies the minimum number of peers that have to agree on our external ip address before
+    /// an `OExtendedMessage::ExternalAddress` is emitted.
+    ///
+    /// Defaults to 3 peers, only applies if an extended builder was given.
+    pub fn with_external_addr_votes(mut self, min_votes: usize) -> UberModuleBuilder {
+        self.min_addr_votes = min_votes;
+        self
+    }
+
+    /// Specifies the statistics module that control messages will be forwarded to.
+    ///
+    /// Torrent statistics can then be queried by sending an `IUberMessage::Statistics`, and will be
+    /// received as an `OUberMessage::Statistics`. By default, there is no statistics module.
+    pub fn with_statistics_module(mut self, module: StatisticsModule) -> UberModuleBuilder {
+        self.statistics = Some(module);
+        self
+    }
+
+    /// Specifies the selection module that control messages will be forwarded to.
+    ///
+    /// Selection messages can then be sent as an `IUberMessage::Selection`, and will be received
+    /// as an `OUberMessage::Selection`. Errors from the selection module are reported as an
+    /// `OUberMessage::ModuleError`, but the module is always kept. If an extended builder was given,
+    /// the selection module will also hear about extended peer information. By default, there is no selection module.
+    pub fn with_selection_module(mut self, module: PieceSelectionModule) -> UberModuleBuilder {
+        self.selection = Some(module);
+        self
+    }
+
     /// Add the given discovery module to the list of discovery modules.
-    pub fn with_discovery_module<T>(mut self, module: T) -> UberModuleBuilder
+    ///
+    /// The module will be named after its position in the list of discovery modules.
+    pub fn with_discovery_module<T>(self, module: T) -> UberModuleBuilder
+    where
+        T: ExtendedListener
+            + Sink<SinkItem = IDiscoveryMessage, SinkError = DiscoveryError>
+            + Stream<Item = ODiscoveryMessage, Error = DiscoveryError>
+            + 'static,
+    {
+        let name = format!("discovery_{}", self.discovery.len());
+
+        self.with_named_discovery_module(name, module)
+    }
+
+    /// Add the given discovery module, with the given name, to the list of discovery modules.
+    pub fn with_named_discovery_module<N, T>(mut self, name: N, module: T) -> UberModuleBuilder
     where
+        N: Into<String>,
         T: ExtendedListener
             + Sink<SinkItem = IDiscoveryMessage, SinkError = DiscoveryError>
             + Stream<Item = ODiscoveryMessage, Error = DiscoveryError>
             + 'static,
     {
-        self.discovery.push(Box::new(module)
-            as Box<
-                DiscoveryTrait<SinkItem = IDiscoveryMessage, SinkError = DiscoveryError, Item = ODiscoveryMessage, Error = DiscoveryError>,
-            >);
+        self.discovery.push((Box::new(module) as BoxedDiscovery, ModuleInfo::new(name.into(), None)));
+        self
+    }
+
+    /// Add a discovery module created by the given factory, with the given name, to the list of discovery modules.
+    ///
+    /// If the module fails and the `ModuleErrorPolicy` is `Restart`, the factory will be used to replace it.
+    pub fn with_restartable_discovery_module<N, F, T>(mut self, name: N, factory: F) -> UberModuleBuilder
+    where
+        N: Into<String>,
+        F: Fn() -> T + 'static,
+        T: ExtendedListener
+            + Sink<SinkItem = IDiscoveryMessage, SinkError = DiscoveryError>
+            + Stream<Item = ODiscoveryMessage, Error = DiscoveryError>
+            + 'static,
+    {
+        let boxed_factory = Box::new(move || Box::new(factory()) as BoxedDiscovery) as Box<Fn() -> BoxedDiscovery>;
+        let module = boxed_factory();
+
+        self.discovery.push((module, ModuleInfo::new(name.into(), Some(boxed_factory))));
         self
     }
 
@@ -111,30 +265,296 @@ impl<T> IsReady for Async<T> {
 
 //----------------------------------------------------------------------//
 
+/// Information the uber module needs to isolate failures of a module.
+struct ModuleInfo {
+    name: String,
+    factory: Option<Box<Fn() -> BoxedDiscovery>>,
+    failed: bool,
+}
+
+impl ModuleInfo {
+    fn new(name: String, factory: Option<Box<Fn() -> BoxedDiscovery>>) -> ModuleInfo {
+        ModuleInfo {
+            name: name,
+            factory: factory,
+            failed: false,
+        }
+    }
+}
+
+/// Discovery modules that have not failed, and so should still hear about extended peer information.
+fn active_modules<'a>(discovery: &'a mut [BoxedDiscovery], discovery_info: &[ModuleInfo]) -> Vec<&'a mut BoxedDiscovery> {
+    discovery
+        .iter_mut()
+        .zip(discovery_info)
+        .filter(|&(_, info)| !info.failed)
+        .map(|(module, _)| module)
+        .collect()
+}
+
+/// Statistics message carrying the information in the given selection message, if the statistics module needs it.
+fn selection_statistics(message: &ISelectMessage) -> Option<IStatisticsMessage> {
+    match *message {
+        ISelectMessage::FoundGoodPiece(hash, index) => Some(IStatisticsMessage::FoundGoodPiece(hash, index)),
+        ISelectMessage::ReceivedBitField(info, ref bitfield) => Some(IStatisticsMessage::ReceivedBitField(info, bitfield.clone())),
+        ISelectMessage::ReceivedHave(info, have) => Some(IStatisticsMessage::ReceivedHave(info, have)),
+        ISelectMessage::PeerStats(info, ref stats) => Some(IStatisticsMessage::PeerStats(info, stats.clone())),
+        ISelectMessage::Control(_) |
+        ISelectMessage::FoundBadPiece(_, _) |
+        ISelectMessage::SentRequest(_, _) |
+        ISelectMessage::ReceivedPiece(_, _) => None,
+    }
+}
+
+//----------------------------------------------------------------------//
+
+/// Timer owned by the uber module, for generating internal ticks.
+struct InternalTicker {
+    ticks: BoxedTicks,
+    finished: bool,
+    last_tick: Instant,
+}
+
+impl InternalTicker {
+    fn new(ticks: BoxedTicks) -> InternalTicker {
+        InternalTicker {
+            ticks: ticks,
+            finished: false,
+            last_tick: Instant::now(),
+        }
+    }
+
+    /// Poll the timer, returning the time elapsed since the last tick if the timer fired.
+    fn poll_elapsed(&mut self) -> io::Result<Option<Duration>> {
+        let mut fired = false;
+        while !self.finished {
+            match try!(self.ticks.poll()) {
+                Async::Ready(Some(())) => fired = true,
+                Async::Ready(None) => self.finished = true,
+                Async::NotReady => break,
+            }
+        }
+
+        if fired {
+            let now = Instant::now();
+            let elapsed = now - self.last_tick;
+            self.last_tick = now;
+
+            Ok(Some(elapsed))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+//----------------------------------------------------------------------//
+
 /// Module for multiplexing messages across zero or more other modules.
 pub struct UberModule {
-    discovery: Vec<Box<DiscoveryTrait<SinkItem = IDiscoveryMessage, SinkError = DiscoveryError, Item = ODiscoveryMessage, Error = DiscoveryError>>>,
+    discovery: Vec<BoxedDiscovery>,
+    discovery_info: Vec<ModuleInfo>,
     extended: Option<ExtendedModule>,
+    statistics: Option<StatisticsModule>,
+    selection: Option<PieceSelectionModule>,
+    policy: Box<PeerPolicy + Send>,
+    error_policy: ModuleErrorPolicy,
+    rejected: VecDeque<PeerInfo>,
+    module_errors: VecDeque<OUberMessage>,
+    stream_task: Option<Task>,
     last_sink_state: Option<ModuleState>,
     last_stream_state: Option<ModuleState>,
+    shutdown: bool,
+    ticker: Option<InternalTicker>,
+    // Elapsed time from the internal ticker that has not been delivered yet
+    pending_tick: Option<Duration>,
+    // Internal tick that was partially delivered, and must finish before any other message is sent
+    tick_in_progress: Option<Duration>,
+    // Internal tick was delivered, but the modules have not been flushed since
+    tick_unflushed: bool,
 }
 
 #[derive(Debug, Copy, Clone)]
 enum ModuleState {
     Extended,
+    Statistics,
+    Selection,
     Discovery(usize),
 }
 
 impl UberModule {
     /// Create an `UberModule` from the given `UberModuleBuilder`.
     pub fn from_builder(builder: UberModuleBuilder) -> UberModule {
+        let (discovery, discovery_info) = builder.discovery.into_iter().unzip();
+
         UberModule {
-            discovery: builder.discovery,
+            discovery: discovery,
+            discovery_info: discovery_info,
             extended: builder
                 .ext_builder
-                .map(|builder| ExtendedModule::new(builder)),
+                .map(|ext_builder| ExtendedModule::new(ext_builder, builder.min_addr_votes)),
+            statistics: builder.statistics,
+            selection: builder.selection,
+            policy: builder.policy,
+            error_policy: builder.error_policy,
+            rejected: VecDeque::new(),
+            module_errors: VecDeque::new(),
+            stream_task: None,
             last_sink_state: None,
             last_stream_state: None,
+            shutdown: false,
+            ticker: builder.ticks.map(InternalTicker::new),
+            pending_tick: None,
+            tick_in_progress: None,
+            tick_unflushed: false,
+        }
+    }
+
+    /// Poll the internal ticker, if there is one, and queue up any elapsed time to be delivered.
+    fn poll_internal_ticker(&mut self) -> Result<(), UberError> {
+        let opt_elapsed = match self.ticker {
+            Some(ref mut ticker) => try!(ticker.poll_elapsed()),
+            None => None,
+        };
+
+        if let Some(elapsed) = opt_elapsed {
+            self.pending_tick = Some(self.pending_tick.map_or(elapsed, |pending| pending + elapsed));
+        }
+
+        Ok(())
+    }
+
+    /// Deliver any queued internal tick to the modules.
+    ///
+    /// Returns false if an internal tick is still being delivered, in which case other messages have to wait.
+    fn deliver_internal_tick(&mut self) -> Result<bool, UberError> {
+        // Cant interleave a tick with a message from the user that is part way through being delivered
+        if self.last_sink_state.is_some() && self.tick_in_progress.is_none() {
+            return Ok(true);
+        }
+
+        let tick = match self.tick_in_progress.take().or_else(|| self.pending_tick.take()) {
+            Some(tick) => tick,
+            None => return Ok(true),
+        };
+
+        match try!(self.start_sink_state(&IUberMessage::Control(ControlMessage::Tick(tick)))) {
+            AsyncSink::Ready => {
+                self.tick_unflushed = true;
+
+                Ok(true)
+            },
+            AsyncSink::NotReady(()) => {
+                self.tick_in_progress = Some(tick);
+
+                Ok(false)
+            },
+        }
+    }
+
+    /// Flush the modules after an internal tick, since no one else will call `poll_complete` for it.
+    ///
+    /// Unlike `poll_sink_state`, this does not track progress through the modules, so it is safe to
+    /// call while a message from the user is part way through being delivered.
+    fn flush_internal_tick(&mut self) -> Result<(), UberError> {
+        if !self.tick_unflushed {
+            return Ok(());
+        }
+
+        let mut flushed = true;
+        for index in 0..self.discovery.len() {
+            if !self.discovery_info[index].failed {
+                let result = self.discovery[index].poll_complete();
+
+                flushed &= try!(self.isolate_discovery(index, result, Async::Ready(()))).is_ready();
+            }
+        }
+
+        let result = self.selection
+            .as_mut()
+            .map(|select_module| select_module.poll_complete())
+            .unwrap_or(Ok(Async::Ready(())));
+        flushed &= try!(self.isolate_selection(result, Async::Ready(()))).is_ready();
+
+        // Modules that were not ready will wake up the stream when they are
+        self.tick_unflushed = !flushed;
+
+        Ok(())
+    }
+
+    /// Queue up an error from the module with the given name.
+    fn module_error(&mut self, name: String, error: String) {
+        if self.module_errors.len() >= MAX_MODULE_ERRORS {
+            self.module_errors.pop_front();
+        }
+        self.module_errors.push_back(OUberMessage::ModuleError(name, error));
+        self.stream_task.take().map(|task| task.notify());
+    }
+
+    /// Report an error from the discovery module at the given index, and apply the `ModuleErrorPolicy` to it.
+    fn discovery_error(&mut self, index: usize, error: DiscoveryError) {
+        let name = self.discovery_info[index].name.clone();
+        self.module_error(name, error.to_string());
+
+        let opt_module = match (self.error_policy, &self.discovery_info[index].factory) {
+            (ModuleErrorPolicy::Ignore, _) => return,
+            (ModuleErrorPolicy::Restart, &Some(ref factory)) => Some(factory()),
+            _ => None,
+        };
+
+        match opt_module {
+            Some(module) => self.discovery[index] = module,
+            // Cant remove it now, since that would shift the states of the other modules
+            None => self.discovery_info[index].failed = true,
+        }
+    }
+
+    /// Isolate the result of the discovery module at the given index, so an error does not fail the uber module.
+    fn isolate_discovery<T>(&mut self, index: usize, result: Result<T, DiscoveryError>, on_error: T) -> Result<T, UberError> {
+        match result {
+            Ok(value) => Ok(value),
+            Err(error) => {
+                self.discovery_error(index, error);
+
+                Ok(on_error)
+            },
+        }
+    }
+
+    /// Isolate the result of the selection module, errors are reported but the module is kept.
+    fn isolate_selection<T>(&mut self, result: Result<T, SelectError>, on_error: T) -> Result<T, UberError> {
+        match result {
+            Ok(value) => Ok(value),
+            Err(error) => {
+                self.module_error(SELECTION_MODULE_NAME.to_owned(), error.to_string());
+
+                Ok(on_error)
+            },
+        }
+    }
+
+    /// Let the selection module know about the extended information for the given peer.
+    fn update_selection_extended(&mut self, info: &PeerInfo) {
+        let opt_ext_info = self.extended.as_ref().and_then(|ext_module| ext_module.peer_info(info));
+
+        if let (Some(ext_info), Some(select_module)) = (opt_ext_info, self.selection.as_mut()) {
+            select_module.on_update(info, ext_info);
+        }
+    }
+
+    /// Remove any failed modules, if we are not in the middle of iterating over them.
+    fn remove_failed_modules(&mut self) {
+        if self.last_sink_state.is_some() || self.last_stream_state.is_some() {
+            return;
+        }
+
+        let mut index = 0;
+        while index < self.discovery.len() {
+            if self.discovery_info[index].failed {
+                self.discovery.remove(index);
+                self.discovery_info.remove(index);
+            } else {
+                index += 1;
+            }
         }
     }
 
@@ -148,6 +568,10 @@ impl UberModule {
             None => {
                 if self.extended.is_some() {
                     Some(ModuleState::Extended)
+                } else if self.statistics.is_some() {
+                    Some(ModuleState::Statistics)
+                } else if self.selection.is_some() {
+                    Some(ModuleState::Selection)
                 } else if !self.discovery.is_empty() {
                     Some(ModuleState::Discovery(0))
                 } else {
@@ -155,6 +579,26 @@ impl UberModule {
                 }
             },
             Some(ModuleState::Extended) => {
+                if self.statistics.is_some() {
+                    Some(ModuleState::Statistics)
+                } else if self.selection.is_some() {
+                    Some(ModuleState::Selection)
+                } else if !self.discovery.is_empty() {
+                    Some(ModuleState::Discovery(0))
+                } else {
+                    None
+                }
+            },
+            Some(ModuleState::Statistics) => {
+                if self.selection.is_some() {
+                    Some(ModuleState::Selection)
+                } else if !self.discovery.is_empty() {
+                    Some(ModuleState::Discovery(0))
+                } else {
+                    None
+                }
+            },
+            Some(ModuleState::Selection) => {
                 if !self.discovery.is_empty() {
                     Some(ModuleState::Discovery(0))
                 } else {
@@ -233,41 +677,104 @@ impl UberModule {
                 uber.last_sink_state = state;
             },
             |uber, state| match (state, message) {
+                (ModuleState::Discovery(index), _) if uber.discovery_info[index].failed => {
+                    Ok(AsyncSink::Ready)
+                },
                 (ModuleState::Discovery(index), &IUberMessage::Control(ref control)) => {
-                    uber.discovery[index]
+                    let result = uber.discovery[index]
                         .start_send(IDiscoveryMessage::Control(control.clone()))
-                        .map(|async| async.map(|_| ()))
-                        .map_err(|err| err.into())
+                        .map(|async| async.map(|_| ()));
+
+                    uber.isolate_discovery(index, result, AsyncSink::Ready)
                 },
                 (ModuleState::Discovery(index), &IUberMessage::Discovery(ref discovery)) => {
-                    uber.discovery[index]
+                    let result = uber.discovery[index]
                         .start_send(discovery.clone())
-                        .map(|async| async.map(|_| ()))
-                        .map_err(|err| err.into())
+                        .map(|async| async.map(|_| ()));
+
+                    uber.isolate_discovery(index, result, AsyncSink::Ready)
                 },
                 (ModuleState::Extended, &IUberMessage::Control(ref control)) => {
-                    let d_modules = &mut uber.discovery[..];
+                    let result = {
+                        let mut d_modules = active_modules(&mut uber.discovery, &uber.discovery_info);
 
-                    uber.extended
-                        .as_mut()
-                        .map(|ext_module| {
-                            ext_module.process_message(IExtendedMessage::Control(control.clone()), d_modules);
+                        uber.extended
+                            .as_mut()
+                            .map(|ext_module| {
+                                ext_module.process_message(IExtendedMessage::Control(control.clone()), &mut d_modules);
 
-                            Ok(AsyncSink::Ready)
-                        })
-                        .unwrap_or(Ok(AsyncSink::Ready))
+                                Ok(AsyncSink::Ready)
+                            })
+                            .unwrap_or(Ok(AsyncSink::Ready))
+                    };
+
+                    if let ControlMessage::PeerConnected(info) = *control {
+                        uber.update_selection_extended(&info);
+                    }
+
+                    result
                 },
                 (ModuleState::Extended, &IUberMessage::Extended(ref extended)) => {
-                    let d_modules = &mut uber.discovery[..];
+                    let result = {
+                        let mut d_modules = active_modules(&mut uber.discovery, &uber.discovery_info);
 
-                    uber.extended
+                        uber.extended
+                            .as_mut()
+                            .map(|ext_module| {
+                                ext_module.process_message(extended.clone(), &mut d_modules);
+
+                                Ok(AsyncSink::Ready)
+                            })
+                            .unwrap_or(Ok(AsyncSink::Ready))
+                    };
+
+                    if let IExtendedMessage::RecievedExtendedMessage(info, _) = *extended {
+                        uber.update_selection_extended(&info);
+                    }
+
+                    result
+                },
+                // Statistics module never fails, and is always ready for more messages
+                (ModuleState::Statistics, &IUberMessage::Control(ref control)) => {
+                    uber.statistics
                         .as_mut()
-                        .map(|ext_module| {
-                            ext_module.process_message(extended.clone(), d_modules);
+                        .map(|stats_module| stats_module.start_send(IStatisticsMessage::Control(control.clone())));
 
-                            Ok(AsyncSink::Ready)
-                        })
+                    Ok(AsyncSink::Ready)
+                },
+                (ModuleState::Statistics, &IUberMessage::Statistics(ref statistics)) => {
+                    uber.statistics
+                        .as_mut()
+                        .map(|stats_module| stats_module.start_send(statistics.clone()));
+
+                    Ok(AsyncSink::Ready)
+                },
+                (ModuleState::Statistics, &IUberMessage::Selection(ref selection)) => {
+                    if let Some(statistics) = selection_statistics(selection) {
+                        uber.statistics
+                            .as_mut()
+                            .map(|stats_module| stats_module.start_send(statistics));
+                    }
+
+                    Ok(AsyncSink::Ready)
+                },
+                (ModuleState::Selection, &IUberMessage::Control(ref control)) => {
+                    let result = uber.selection
+                        .as_mut()
+                        .map(|select_module| select_module.start_send(ISelectMessage::Control(control.clone())))
                         .unwrap_or(Ok(AsyncSink::Ready))
+                        .map(|async| async.map(|_| ()));
+
+                    uber.isolate_selection(result, AsyncSink::Ready)
+                },
+                (ModuleState::Selection, &IUberMessage::Selection(ref selection)) => {
+                    let result = uber.selection
+                        .as_mut()
+                        .map(|select_module| select_module.start_send(selection.clone()))
+                        .unwrap_or(Ok(AsyncSink::Ready))
+                        .map(|async| async.map(|_| ()));
+
+                    uber.isolate_selection(result, AsyncSink::Ready)
                 },
                 _ => {
                     Ok(AsyncSink::Ready)
@@ -285,12 +792,23 @@ impl UberModule {
                 uber.last_sink_state = state;
             },
             |uber, state| match state {
+                ModuleState::Discovery(index) if uber.discovery_info[index].failed => {
+                    Ok(Async::Ready(()))
+                },
                 ModuleState::Discovery(index) => {
-                    uber.discovery[index]
-                        .poll_complete()
-                        .map_err(|err| err.into())
+                    let result = uber.discovery[index].poll_complete();
+
+                    uber.isolate_discovery(index, result, Async::Ready(()))
                 },
-                ModuleState::Extended => {
+                ModuleState::Selection => {
+                    let result = uber.selection
+                        .as_mut()
+                        .map(|select_module| select_module.poll_complete())
+                        .unwrap_or(Ok(Async::Ready(())));
+
+                    uber.isolate_selection(result, Async::Ready(()))
+                },
+                ModuleState::Extended | ModuleState::Statistics => {
                     Ok(Async::Ready(()))
                 },
             },
@@ -319,13 +837,42 @@ impl UberModule {
                         })
                         .unwrap_or(Ok(Async::Ready(None)))
                 },
+                ModuleState::Statistics => {
+                    let opt_message = uber.statistics
+                        .as_mut()
+                        .and_then(|stats_module| stats_module.poll().ok())
+                        .and_then(|async_opt_message| match async_opt_message {
+                            Async::Ready(opt_message) => opt_message,
+                            Async::NotReady => None,
+                        });
+
+                    Ok(opt_message
+                        .map(|message| Async::Ready(Some(OUberMessage::Statistics(message))))
+                        .unwrap_or(Async::NotReady))
+                },
+                ModuleState::Selection => {
+                    let result = uber.selection
+                        .as_mut()
+                        .map(|select_module| {
+                            select_module.poll().map(|async_opt_message| {
+                                async_opt_message.map(|opt_message| opt_message.map(|message| OUberMessage::Selection(message)))
+                            })
+                        })
+                        .unwrap_or(Ok(Async::NotReady));
+
+                    uber.isolate_selection(result, Async::NotReady)
+                },
+                ModuleState::Discovery(index) if uber.discovery_info[index].failed => {
+                    Ok(Async::NotReady)
+                },
                 ModuleState::Discovery(index) => {
-                    uber.discovery[index]
+                    let result = uber.discovery[index]
                         .poll()
                         .map(|async_opt_message| {
                             async_opt_message.map(|opt_message| opt_message.map(|message| OUberMessage::Discovery(message)))
-                        })
-                        .map_err(|err| err.into())
+                        });
+
+                    uber.isolate_discovery(index, result, Async::NotReady)
                 },
             },
         )
@@ -337,9 +884,38 @@ impl Sink for UberModule {
     type SinkError = UberError;
 
     fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        self.remove_failed_modules();
+
+        if !try!(self.deliver_internal_tick()) {
+            return Ok(AsyncSink::NotReady(item));
+        }
+
+        // Only consult the policy if this is a new message, not one we are resuming
+        if self.last_sink_state.is_none() {
+            if let IUberMessage::Control(ControlMessage::PeerConnected(ref info)) = item {
+                if !self.policy.accept_peer(info) {
+                    self.rejected.push_back(info.clone());
+                    self.stream_task.take().map(|task| task.notify());
+
+                    return Ok(AsyncSink::Ready);
+                }
+            }
+        }
+
         // Currently we dont return NotReady from the module directly, so no saving our task state here
-        self.start_sink_state(&item)
-            .map(|async| async.map(|_| item))
+        let result = self.start_sink_state(&item);
+
+        // Once every module has seen the shutdown, the stream can end after draining the modules
+        let is_shutdown = match item {
+            IUberMessage::Control(ControlMessage::Shutdown) => true,
+            _ => false,
+        };
+        if is_shutdown && result.as_ref().map(|async| async.is_ready()).unwrap_or(false) {
+            self.shutdown = true;
+            self.stream_task.take().map(|task| task.notify());
+        }
+
+        result.map(|async| async.map(|_| item))
     }
 
     fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
@@ -352,8 +928,637 @@ impl Stream for UberModule {
     type Error = UberError;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if let Some(info) = self.rejected.pop_front() {
+            return Ok(Async::Ready(Some(OUberMessage::DisconnectPeer(info))));
+        }
+        if let Some(message) = self.module_errors.pop_front() {
+            return Ok(Async::Ready(Some(message)));
+        }
+        self.remove_failed_modules();
+
+        // Ticker is only polled from here, so it always wakes up the stream
+        try!(self.poll_internal_ticker());
+        try!(self.deliver_internal_tick());
+        try!(self.flush_internal_tick());
+
+        // Flush the modules while shutting down, so writes they are holding on to are not lost when we end
+        let flushed = if self.shutdown {
+            try!(self.poll_sink_state()).is_ready()
+        } else {
+            false
+        };
+
         let result = self.poll_stream_state();
 
-        result
+        match result {
+            // Modules may have failed while we were polling them
+            Ok(Async::NotReady) if !self.module_errors.is_empty() => Ok(Async::Ready(self.module_errors.pop_front())),
+            // No modules have anything left to give us or to flush, so we are done shutting down
+            Ok(Async::NotReady) if self.shutdown && flushed => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => {
+                // Make sure we get woken up if a peer is rejected while we are not ready
+                self.stream_task = Some(task::current());
+
+                result
+            },
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IUberMessage, MAX_MODULE_ERRORS, ModuleErrorPolicy, OUberMessage, UberModuleBuilder};
+    use ControlMessage;
+    use bip_handshake::{Direction, Extensions, TransportKind};
+    use bip_metainfo::{DirectAccessor, Metainfo, MetainfoBuilder, PieceLength};
+    use bip_peer::{PeerInfo, PeerProtocolStats};
+    use bip_peer::messages::{BitFieldMessage, PieceMessage, RequestMessage};
+    use bip_peer::messages::builders::ExtendedMessageBuilder;
+    use bip_util::bt;
+    use bytes::Bytes;
+    use discovery::{IDiscoveryMessage, ODiscoveryMessage};
+    use discovery::error::DiscoveryError;
+    use extended::{ExtendedListener, IExtendedMessage};
+    use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+    use futures::{executor, future, stream, task};
+    use futures::sync::mpsc;
+    use futures_test::harness::Harness;
+    use selection::{ISelectMessage, OSelectMessage, PieceSelectionModule};
+    use statistics::{IStatisticsMessage, OStatisticsMessage, StatisticsModule};
+    use std::cell::Cell;
+    use std::io;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    /// Discovery module that fails on every message sent to it.
+    struct FailingModule {
+        sends: Rc<Cell<usize>>,
+        extends: Rc<Cell<usize>>,
+    }
+
+    impl FailingModule {
+        fn new(sends: Rc<Cell<usize>>) -> FailingModule {
+            FailingModule {
+                sends: sends,
+                extends: Rc::new(Cell::new(0)),
+            }
+        }
+    }
+
+    impl ExtendedListener for FailingModule {
+        fn extend(&self, _info: &PeerInfo, builder: ExtendedMessageBuilder) -> ExtendedMessageBuilder {
+            self.extends.set(self.extends.get() + 1);
+
+            builder
+        }
+    }
+
+    impl Sink for FailingModule {
+        type SinkItem = IDiscoveryMessage;
+        type SinkError = DiscoveryError;
+
+        fn start_send(&mut self, _item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+            self.sends.set(self.sends.get() + 1);
+
+            Err("Module Failed".into())
+        }
+
+        fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    impl Stream for FailingModule {
+        type Item = ODiscoveryMessage;
+        type Error = DiscoveryError;
+
+        fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+            Ok(Async::NotReady)
+        }
+    }
+
+    /// Discovery module that always has a message for us.
+    struct YieldingModule;
+
+    impl ExtendedListener for YieldingModule {}
+
+    impl Sink for YieldingModule {
+        type SinkItem = IDiscoveryMessage;
+        type SinkError = DiscoveryError;
+
+        fn start_send(&mut self, _item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+            Ok(AsyncSink::Ready)
+        }
+
+        fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    impl Stream for YieldingModule {
+        type Item = ODiscoveryMessage;
+        type Error = DiscoveryError;
+
+        fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+            Ok(Async::Ready(Some(ODiscoveryMessage::SendDhtAnnounce([0u8; bt::INFO_HASH_LEN].into(), false))))
+        }
+    }
+
+    /// Discovery module that counts the ticks sent to it.
+    struct TickingModule {
+        ticks: Rc<Cell<usize>>,
+    }
+
+    impl ExtendedListener for TickingModule {}
+
+    impl Sink for TickingModule {
+        type SinkItem = IDiscoveryMessage;
+        type SinkError = DiscoveryError;
+
+        fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+            if let IDiscoveryMessage::Control(ControlMessage::Tick(_)) = item {
+                self.ticks.set(self.ticks.get() + 1);
+            }
+
+            Ok(AsyncSink::Ready)
+        }
+
+        fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    impl Stream for TickingModule {
+        type Item = ODiscoveryMessage;
+        type Error = DiscoveryError;
+
+        fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+            Ok(Async::NotReady)
+        }
+    }
+
+    /// Discovery module that needs a poll_complete for every message sent to it before it is flushed.
+    struct FlushingModule {
+        pending: Rc<Cell<usize>>,
+    }
+
+    impl ExtendedListener for FlushingModule {}
+
+    impl Sink for FlushingModule {
+        type SinkItem = IDiscoveryMessage;
+        type SinkError = DiscoveryError;
+
+        fn start_send(&mut self, _item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+            self.pending.set(self.pending.get() + 1);
+
+            Ok(AsyncSink::Ready)
+        }
+
+        fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+            if self.pending.get() == 0 {
+                Ok(Async::Ready(()))
+            } else {
+                self.pending.set(self.pending.get() - 1);
+                task::current().notify();
+
+                Ok(Async::NotReady)
+            }
+        }
+    }
+
+    impl Stream for FlushingModule {
+        type Item = ODiscoveryMessage;
+        type Error = DiscoveryError;
+
+        fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+            Ok(Async::NotReady)
+        }
+    }
+
+    fn tick() -> IUberMessage {
+        IUberMessage::Control(ControlMessage::Tick(Duration::from_millis(100)))
+    }
+
+    fn expect_module_error<S>(recv: &mut Harness<S>)
+    where
+        S: Stream<Item = OUberMessage>,
+        S::Error: ::std::fmt::Debug,
+    {
+        match recv.poll_next() {
+            Ok(Async::Ready(Some(OUberMessage::ModuleError(ref name, _)))) => assert_eq!("failing", name),
+            _ => panic!("bip_select: Expected Module Error Message"),
+        }
+    }
+
+    #[test]
+    fn positive_stream_ends_after_shutdown() {
+        let (send, recv) = UberModuleBuilder::new().build().split();
+
+        let mut block_send = send.wait();
+        let mut non_block_recv = Harness::new(recv);
+
+        assert!(
+            non_block_recv
+                .poll_next()
+                .as_ref()
+                .map(Async::is_not_ready)
+                .unwrap_or(false)
+        );
+
+        block_send
+            .send(IUberMessage::Control(ControlMessage::Shutdown))
+            .unwrap();
+
+        assert!(
+            non_block_recv
+                .poll_next()
+                .as_ref()
+                .map(|async| async == &Async::Ready(None))
+                .unwrap_or(false)
+        );
+    }
+
+    #[test]
+    fn positive_shutdown_flushes_modules() {
+        let pending = Rc::new(Cell::new(0));
+        let mut uber = UberModuleBuilder::new()
+            .with_named_discovery_module("flushing", FlushingModule { pending: pending.clone() })
+            .build();
+
+        assert!(
+            uber.start_send(IUberMessage::Control(ControlMessage::Shutdown))
+                .unwrap()
+                .is_ready()
+        );
+        assert_eq!(1, pending.get());
+
+        assert!(executor::spawn(uber).wait_stream().is_none());
+        assert_eq!(0, pending.get());
+    }
+
+    #[test]
+    fn positive_module_error_removes_module() {
+        let sends = Rc::new(Cell::new(0));
+        let uber = UberModuleBuilder::new()
+            .with_named_discovery_module("failing", FailingModule::new(sends.clone()))
+            .build();
+        let (send, recv) = uber.split();
+
+        let mut block_send = send.wait();
+        let mut non_block_recv = Harness::new(recv);
+
+        block_send.send(tick()).unwrap();
+        expect_module_error(&mut non_block_recv);
+
+        block_send.send(tick()).unwrap();
+        assert!(non_block_recv.poll_next().map(|async| async.is_not_ready()).unwrap_or(false));
+        assert_eq!(1, sends.get());
+    }
+
+    #[test]
+    fn positive_module_error_restarts_module() {
+        let sends = Rc::new(Cell::new(0));
+        let restarts = Rc::new(Cell::new(0));
+
+        let (factory_sends, factory_restarts) = (sends.clone(), restarts.clone());
+        let uber = UberModuleBuilder::new()
+            .with_module_error_policy(ModuleErrorPolicy::Restart)
+            .with_restartable_discovery_module("failing", move || {
+                factory_restarts.set(factory_restarts.get() + 1);
+
+                FailingModule::new(factory_sends.clone())
+            })
+            .build();
+        let (send, recv) = uber.split();
+
+        let mut block_send = send.wait();
+        let mut non_block_recv = Harness::new(recv);
+
+        block_send.send(tick()).unwrap();
+        expect_module_error(&mut non_block_recv);
+
+        block_send.send(tick()).unwrap();
+        expect_module_error(&mut non_block_recv);
+
+        assert_eq!(2, sends.get());
+        assert_eq!(3, restarts.get());
+    }
+
+    #[test]
+    fn positive_module_error_ignored_keeps_module() {
+        let sends = Rc::new(Cell::new(0));
+        let mut uber = UberModuleBuilder::new()
+            .with_module_error_policy(ModuleErrorPolicy::Ignore)
+            .with_named_discovery_module("failing", FailingModule::new(sends.clone()))
+            .build();
+
+        assert!(uber.start_send(tick()).unwrap().is_ready());
+        assert!(uber.start_send(tick()).unwrap().is_ready());
+
+        assert_eq!(2, sends.get());
+        assert_eq!(2, uber.module_errors.len());
+    }
+
+    #[test]
+    fn positive_module_errors_bounded() {
+        let sends = Rc::new(Cell::new(0));
+        let mut uber = UberModuleBuilder::new()
+            .with_module_error_policy(ModuleErrorPolicy::Ignore)
+            .with_named_discovery_module("failing", FailingModule::new(sends.clone()))
+            .build();
+
+        for _ in 0..(MAX_MODULE_ERRORS + 1) {
+            assert!(uber.start_send(tick()).unwrap().is_ready());
+        }
+
+        assert_eq!(MAX_MODULE_ERRORS + 1, sends.get());
+        assert_eq!(MAX_MODULE_ERRORS, uber.module_errors.len());
+    }
+
+    #[test]
+    fn positive_module_error_skips_extended_listener() {
+        let failing = FailingModule::new(Rc::new(Cell::new(0)));
+        let extends = failing.extends.clone();
+        let mut uber = UberModuleBuilder::new()
+            .with_extended_builder(Some(ExtendedMessageBuilder::new()))
+            .with_named_discovery_module("failing", failing)
+            .with_named_discovery_module("yielding", YieldingModule)
+            .build();
+
+        // Leaves the stream part way through the modules, so the failed module can not be removed right away
+        let polled = future::lazy(|| future::ok::<bool, ()>(uber.poll().unwrap().is_ready()))
+            .wait()
+            .unwrap();
+        assert!(polled);
+        assert!(uber.start_send(tick()).unwrap().is_ready());
+
+        let info = PeerInfo::new(
+            "127.0.0.1:6881".parse().unwrap(),
+            [0u8; bt::PEER_ID_LEN].into(),
+            [0u8; bt::INFO_HASH_LEN].into(),
+            Extensions::new(),
+            Direction::Outbound,
+            TransportKind::Tcp,
+        );
+        assert!(
+            uber.start_send(IUberMessage::Control(ControlMessage::PeerConnected(info)))
+                .unwrap()
+                .is_ready()
+        );
+
+        assert_eq!(0, extends.get());
+    }
+
+    #[test]
+    fn positive_internal_ticks_without_external_ticks() {
+        let (timer_send, timer_recv) = mpsc::unbounded();
+        let ticks = Rc::new(Cell::new(0));
+        let mut uber = UberModuleBuilder::new()
+            .with_internal_ticks(timer_recv.map_err(|_| io::Error::new(io::ErrorKind::Other, "Timer Failed")))
+            .with_named_discovery_module("ticking", TickingModule { ticks: ticks.clone() })
+            .build();
+
+        future::lazy(|| {
+            assert!(uber.poll().unwrap().is_not_ready());
+            assert_eq!(0, ticks.get());
+
+            // Timer firing multiple times between polls results in a single tick
+            timer_send.unbounded_send(()).unwrap();
+            timer_send.unbounded_send(()).unwrap();
+            assert!(uber.poll().unwrap().is_not_ready());
+            assert_eq!(1, ticks.get());
+
+            timer_send.unbounded_send(()).unwrap();
+            assert!(uber.poll().unwrap().is_not_ready());
+            assert_eq!(2, ticks.get());
+
+            Ok::<_, ()>(())
+        }).wait().unwrap();
+    }
+
+    #[test]
+    fn positive_internal_ticks_flush_modules() {
+        let (timer_send, timer_recv) = mpsc::unbounded();
+        let pending = Rc::new(Cell::new(0));
+        let mut uber = UberModuleBuilder::new()
+            .with_internal_ticks(timer_recv.map_err(|_| io::Error::new(io::ErrorKind::Other, "Timer Failed")))
+            .with_named_discovery_module("flushing", FlushingModule { pending: pending.clone() })
+            .build();
+
+        future::lazy(|| {
+            timer_send.unbounded_send(()).unwrap();
+            assert!(uber.poll().unwrap().is_not_ready());
+
+            // Tick was flushed without the user calling poll_complete, module woke us up to finish flushing
+            assert_eq!(0, pending.get());
+            assert!(uber.poll().unwrap().is_not_ready());
+            assert!(!uber.tick_unflushed);
+
+            Ok::<_, ()>(())
+        }).wait().unwrap();
+    }
+
+    #[test]
+    fn positive_query_statistics_module() {
+        let data = vec![0u8; 10];
+        let bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(5))
+            .build(1, DirectAccessor::new("MyFile.txt", &data), |_| ())
+            .unwrap();
+        let metainfo = Metainfo::from_bytes(bytes).unwrap();
+        let hash = metainfo.info().info_hash();
+
+        let (send, recv) = UberModuleBuilder::new()
+            .with_statistics_module(StatisticsModule::new())
+            .build()
+            .split();
+        let mut block_send = send.wait();
+        let mut non_block_recv = Harness::new(recv);
+
+        block_send
+            .send(IUberMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+        block_send
+            .send(IUberMessage::Control(ControlMessage::Tick(Duration::from_secs(1))))
+            .unwrap();
+        block_send
+            .send(IUberMessage::Statistics(IStatisticsMessage::QueryStats(hash)))
+            .unwrap();
+
+        match non_block_recv.poll_next() {
+            Ok(Async::Ready(Some(OUberMessage::Statistics(OStatisticsMessage::TorrentStats(stats_hash, stats))))) => {
+                assert_eq!(hash, stats_hash);
+                assert_eq!(10, stats.bytes_left());
+            },
+            _ => panic!("bip_select: Expected Torrent Stats Message"),
+        }
+    }
+
+    #[test]
+    fn positive_selection_module_accepts_requested_piece() {
+        let data = vec![0u8; 10];
+        let bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(5))
+            .build(1, DirectAccessor::new("MyFile.txt", &data), |_| ())
+            .unwrap();
+        let metainfo = Metainfo::from_bytes(bytes).unwrap();
+        let info = PeerInfo::new(
+            "127.0.0.1:0".parse().unwrap(),
+            [0u8; bt::PEER_ID_LEN].into(),
+            metainfo.info().info_hash(),
+            Extensions::new(),
+            Direction::Outbound,
+            TransportKind::Tcp,
+        );
+        let piece = PieceMessage::new(0, 0, Bytes::from(vec![0u8; 5]));
+
+        let (send, recv) = UberModuleBuilder::new()
+            .with_selection_module(PieceSelectionModule::new())
+            .build()
+            .split();
+        let mut block_send = send.wait();
+        let mut non_block_recv = Harness::new(recv);
+
+        block_send
+            .send(IUberMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+        block_send
+            .send(IUberMessage::Selection(ISelectMessage::SentRequest(info, RequestMessage::new(0, 0, 5))))
+            .unwrap();
+        block_send
+            .send(IUberMessage::Selection(ISelectMessage::ReceivedPiece(info, piece.clone())))
+            .unwrap();
+
+        match non_block_recv.poll_next() {
+            Ok(Async::Ready(Some(OUberMessage::Selection(OSelectMessage::AcceptedPiece(accepted_info, accepted_piece))))) => {
+                assert_eq!(info, accepted_info);
+                assert_eq!(piece, accepted_piece);
+            },
+            _ => panic!("bip_select: Expected Accepted Piece Message"),
+        }
+    }
+
+    #[test]
+    fn positive_selection_module_hears_upload_only() {
+        let data = vec![0u8; 10];
+        let bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(5))
+            .build(1, DirectAccessor::new("MyFile.txt", &data), |_| ())
+            .unwrap();
+        let metainfo = Metainfo::from_bytes(bytes).unwrap();
+        let info = PeerInfo::new(
+            "127.0.0.1:0".parse().unwrap(),
+            [0u8; bt::PEER_ID_LEN].into(),
+            metainfo.info().info_hash(),
+            Extensions::new(),
+            Direction::Outbound,
+            TransportKind::Tcp,
+        );
+        let upload_only = ExtendedMessageBuilder::new().with_upload_only(Some(true));
+
+        let (send, recv) = UberModuleBuilder::new()
+            .with_extended_builder(Some(upload_only.clone()))
+            .with_selection_module(PieceSelectionModule::new())
+            .build()
+            .split();
+        let mut block_send = send.wait();
+        let mut non_block_recv = Harness::new(recv);
+
+        block_send
+            .send(IUberMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+        block_send
+            .send(IUberMessage::Control(ControlMessage::PeerConnected(info)))
+            .unwrap();
+        block_send
+            .send(IUberMessage::Extended(IExtendedMessage::RecievedExtendedMessage(info, upload_only.build())))
+            .unwrap();
+
+        let mut uninterested = false;
+        while let Ok(Async::Ready(Some(message))) = non_block_recv.poll_next() {
+            uninterested |= message == OUberMessage::Selection(OSelectMessage::UninterestedPeer(info));
+        }
+        assert!(uninterested);
+    }
+
+    #[test]
+    fn positive_selection_messages_forwarded_to_statistics() {
+        let data = vec![0u8; 10];
+        let bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(5))
+            .build(1, DirectAccessor::new("MyFile.txt", &data), |_| ())
+            .unwrap();
+        let metainfo = Metainfo::from_bytes(bytes).unwrap();
+        let hash = metainfo.info().info_hash();
+        let info = PeerInfo::new(
+            "127.0.0.1:0".parse().unwrap(),
+            [0u8; bt::PEER_ID_LEN].into(),
+            hash,
+            Extensions::new(),
+            Direction::Outbound,
+            TransportKind::Tcp,
+        );
+        let stats = PeerProtocolStats::new();
+        stats.record_bytes_received(105);
+
+        let mut uber = UberModuleBuilder::new()
+            .with_statistics_module(StatisticsModule::new())
+            .with_selection_module(PieceSelectionModule::new())
+            .build();
+
+        let messages = vec![
+            IUberMessage::Control(ControlMessage::AddTorrent(metainfo)),
+            IUberMessage::Control(ControlMessage::PeerConnected(info)),
+            IUberMessage::Selection(ISelectMessage::ReceivedBitField(info, BitFieldMessage::new(Bytes::from(vec![0xC0])))),
+            IUberMessage::Selection(ISelectMessage::FoundGoodPiece(hash, 0)),
+            IUberMessage::Selection(ISelectMessage::PeerStats(info, stats.clone())),
+        ];
+
+        for message in messages {
+            assert!(uber.start_send(message).unwrap().is_ready());
+        }
+        assert!(uber.start_send(IUberMessage::Control(ControlMessage::Tick(Duration::from_secs(1)))).unwrap().is_ready());
+
+        let torrent_stats = uber.statistics.as_ref().unwrap().stats(&hash).unwrap();
+        assert_eq!(100, torrent_stats.download_rate());
+        assert_eq!(5, torrent_stats.bytes_left());
+        assert_eq!(1, torrent_stats.seeding_peers());
+    }
+
+    #[test]
+    fn positive_selection_module_error_keeps_module() {
+        let mut uber = UberModuleBuilder::new()
+            .with_selection_module(PieceSelectionModule::new())
+            .build();
+        let hash = [0u8; bt::INFO_HASH_LEN].into();
+
+        assert!(
+            uber.start_send(IUberMessage::Selection(ISelectMessage::FoundGoodPiece(hash, 0)))
+                .unwrap()
+                .is_ready()
+        );
+
+        match Harness::new(&mut uber).poll_next() {
+            Ok(Async::Ready(Some(OUberMessage::ModuleError(ref name, _)))) => assert_eq!("selection", name),
+            _ => panic!("bip_select: Expected Module Error Message"),
+        }
+        assert!(
+            uber.start_send(IUberMessage::Selection(ISelectMessage::FoundGoodPiece(hash, 0)))
+                .unwrap()
+                .is_ready()
+        );
+    }
+
+    #[test]
+    fn positive_internal_ticks_accept_external_ticks() {
+        let ticks = Rc::new(Cell::new(0));
+        let mut uber = UberModuleBuilder::new()
+            .with_internal_ticks(stream::empty())
+            .with_named_discovery_module("ticking", TickingModule { ticks: ticks.clone() })
+            .build();
+
+        assert!(uber.start_send(tick()).unwrap().is_ready());
+        assert_eq!(1, ticks.get());
     }
 }
@@ -139,6 +139,122 @@ pub fn bytes_be_to_sock_v6(bytes: [u8; 18]) -> SocketAddrV6 {
     SocketAddrV6::new(ip, port, 0, 0)
 }
 
+const HEX_ALPHABET: &'static [u8] = b"0123456789abcdef";
+const BASE32_ALPHABET: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Convert the bytes to a lowercase hex string.
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+
+    for &byte in bytes.iter() {
+        hex.push(HEX_ALPHABET[(byte >> 4) as usize] as char);
+        hex.push(HEX_ALPHABET[(byte & 0x0F) as usize] as char);
+    }
+
+    hex
+}
+
+/// Convert a (case insensitive) hex string to bytes.
+///
+/// Returns None if the string is not valid hex.
+pub fn hex_to_bytes(hex: &str) -> Option<Vec<u8>> {
+    let hex_bytes = hex.as_bytes();
+    if hex_bytes.len() % 2 != 0 {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(hex_bytes.len() / 2);
+    for chunk in hex_bytes.chunks(2) {
+        match (hex_value(chunk[0]), hex_value(chunk[1])) {
+            (Some(high), Some(low)) => bytes.push((high << 4) | low),
+            _ => return None,
+        }
+    }
+
+    Some(bytes)
+}
+
+fn hex_value(character: u8) -> Option<u8> {
+    match character {
+        b'0'...b'9' => Some(character - b'0'),
+        b'a'...b'f' => Some(character - b'a' + 10),
+        b'A'...b'F' => Some(character - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Convert the bytes to an uppercase, unpadded, RFC 4648 base32 string.
+pub fn bytes_to_base32(bytes: &[u8]) -> String {
+    let mut base32 = String::with_capacity((bytes.len() * 8 + 4) / 5);
+
+    let (mut buffer, mut buffer_bits) = (0u16, 0);
+    for &byte in bytes.iter() {
+        buffer = (buffer << 8) | byte as u16;
+        buffer_bits += 8;
+
+        while buffer_bits >= 5 {
+            buffer_bits -= 5;
+            base32.push(BASE32_ALPHABET[((buffer >> buffer_bits) & 0x1F) as usize] as char);
+        }
+    }
+
+    if buffer_bits != 0 {
+        base32.push(BASE32_ALPHABET[((buffer << (5 - buffer_bits)) & 0x1F) as usize] as char);
+    }
+
+    base32
+}
+
+/// Convert a (case insensitive) RFC 4648 base32 string, with optional padding, to bytes.
+///
+/// Returns None if the string is not valid base32, including strings whose length could not
+/// have been produced by encoding some bytes, or whose unused trailing bits are non-zero.
+pub fn base32_to_bytes(base32: &str) -> Option<Vec<u8>> {
+    let base32_bytes = base32.trim_right_matches('=').as_bytes();
+    // Every 5 bytes encode to 8 characters, a partial group can only end in 2, 4, 5, or 7 characters
+    match base32_bytes.len() % 8 {
+        1 | 3 | 6 => return None,
+        _ => (),
+    }
+    let mut bytes = Vec::with_capacity(base32_bytes.len() * 5 / 8);
+
+    let (mut buffer, mut buffer_bits) = (0u16, 0);
+    for &character in base32_bytes.iter() {
+        let value = match character {
+            b'A'...b'Z' => character - b'A',
+            b'a'...b'z' => character - b'a',
+            b'2'...b'7' => character - b'2' + 26,
+            _ => return None,
+        };
+        buffer = (buffer << 5) | value as u16;
+        buffer_bits += 5;
+
+        if buffer_bits >= 8 {
+            buffer_bits -= 8;
+            bytes.push((buffer >> buffer_bits) as u8);
+        }
+    }
+
+    // Leftover bits are only there to fill out the last character, and should be zero
+    if buffer & ((1 << buffer_bits) - 1) != 0 {
+        return None;
+    }
+
+    Some(bytes)
+}
+
+/// Compare the two byte slices in time that only depends on their length.
+///
+/// Useful for comparing secrets, such as tokens, where an early return
+/// could leak how many of the leading bytes were correct.
+pub fn constant_time_eq(lhs: &[u8], rhs: &[u8]) -> bool {
+    if lhs.len() != rhs.len() {
+        return false;
+    }
+
+    lhs.iter().zip(rhs.iter()).fold(0u8, |diff, (l, r)| diff | (l ^ r)) == 0
+}
+
 #[cfg(test)]
 mod tests {
     use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
@@ -268,6 +384,60 @@ mod tests {
         assert_eq!(expected_sock, result_sock);
     }
 
+    #[test]
+    fn positive_hex_round_trip() {
+        let bytes = [0x00, 0x0F, 0xA0, 0xFF];
+
+        let hex = super::bytes_to_hex(&bytes);
+
+        assert_eq!("000fa0ff", hex);
+        assert_eq!(Some(bytes.to_vec()), super::hex_to_bytes("000FA0ff"));
+    }
+
+    #[test]
+    fn negative_hex_to_bytes_invalid() {
+        assert_eq!(None, super::hex_to_bytes("0"));
+        assert_eq!(None, super::hex_to_bytes("0g"));
+    }
+
+    #[test]
+    fn positive_base32_round_trip() {
+        let bytes = b"foobar";
+
+        let base32 = super::bytes_to_base32(bytes);
+
+        assert_eq!("MZXW6YTBOI", base32);
+        assert_eq!(Some(bytes.to_vec()), super::base32_to_bytes("mzxw6ytboi======"));
+    }
+
+    #[test]
+    fn negative_base32_to_bytes_invalid() {
+        assert_eq!(None, super::base32_to_bytes("MZXW1"));
+    }
+
+    #[test]
+    fn negative_base32_to_bytes_invalid_length() {
+        assert_eq!(None, super::base32_to_bytes("M"));
+        assert_eq!(None, super::base32_to_bytes("MZX"));
+        assert_eq!(None, super::base32_to_bytes("MZXW6Y"));
+        assert_eq!(None, super::base32_to_bytes("MZXW6Y=="));
+    }
+
+    #[test]
+    fn negative_base32_to_bytes_non_zero_padding_bits() {
+        // "MY" is the encoding of "f", the last character has two unused bits set
+        assert_eq!(Some(b"f".to_vec()), super::base32_to_bytes("MY"));
+        assert_eq!(None, super::base32_to_bytes("MZ"));
+        assert_eq!(None, super::base32_to_bytes("MZXW6YTBOJ"));
+    }
+
+    #[test]
+    fn positive_constant_time_eq() {
+        assert!(super::constant_time_eq(b"token", b"token"));
+        assert!(!super::constant_time_eq(b"token", b"tokem"));
+        assert!(!super::constant_time_eq(b"token", b"tokens"));
+    }
+
     #[test]
     fn positive_bytes_be_to_sock_v6() {
         let bytes = [0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1];
@@ -1,9 +1,12 @@
 //! Utilities used by the Bittorrent Infrastructure Project.
 
+extern crate bytes;
 extern crate crypto;
 extern crate num;
 extern crate rand;
 extern crate chrono;
+#[macro_use]
+extern crate lazy_static;
 
 /// Bittorrent specific types.
 pub mod bt;
@@ -14,9 +17,15 @@ pub mod contiguous;
 /// Converting between data.
 pub mod convert;
 
+/// Metrics reporting and exporting.
+pub mod metrics;
+
 /// Networking primitives and helpers.
 pub mod net;
 
+/// Pooling of block buffers.
+pub mod pool;
+
 /// Generic sender utilities.
 pub mod send;
 
@@ -0,0 +1,68 @@
+use std::fmt;
+use std::sync::Arc;
+
+mod prometheus;
+
+pub use metrics::prometheus::PrometheusMetrics;
+
+/// Trait for reporting metrics from a running client.
+///
+/// Names are static so that implementations can cheaply key on them; by
+/// convention, names are prefixed with the crate reporting them (for
+/// example, `bip_disk_blocks_written`).
+pub trait Metrics: Send + Sync {
+    /// Increment the counter with the given name by value.
+    fn counter(&self, name: &'static str, value: u64);
+
+    /// Adjust the gauge with the given name by delta.
+    fn gauge(&self, name: &'static str, delta: i64);
+
+    /// Record an observation for the histogram with the given name.
+    fn histogram(&self, name: &'static str, value: f64);
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Metrics")
+    }
+}
+
+impl<M> Metrics for Arc<M> where M: Metrics + ?Sized {
+    fn counter(&self, name: &'static str, value: u64) {
+        (**self).counter(name, value)
+    }
+
+    fn gauge(&self, name: &'static str, delta: i64) {
+        (**self).gauge(name, delta)
+    }
+
+    fn histogram(&self, name: &'static str, value: f64) {
+        (**self).histogram(name, value)
+    }
+}
+
+/// `Metrics` which discards everything reported to it.
+///
+/// This is the implementation used by default.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoopMetrics;
+
+impl NoopMetrics {
+    /// Create a new `NoopMetrics`.
+    pub fn new() -> NoopMetrics {
+        NoopMetrics
+    }
+}
+
+impl Metrics for NoopMetrics {
+    fn counter(&self, _name: &'static str, _value: u64) {}
+
+    fn gauge(&self, _name: &'static str, _delta: i64) {}
+
+    fn histogram(&self, _name: &'static str, _value: f64) {}
+}
+
+/// Create a shared `NoopMetrics`, for use as a default.
+pub fn noop() -> Arc<Metrics> {
+    Arc::new(NoopMetrics::new())
+}
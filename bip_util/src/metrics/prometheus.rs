@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use metrics::Metrics;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum MetricValue {
+    Counter(u64),
+    Gauge(i64),
+    Histogram { sum: f64, count: u64 },
+}
+
+/// `Metrics` which keeps the latest values in memory, and renders them
+/// in the Prometheus text exposition format.
+///
+/// Histograms are rendered as summaries, with a sum and a count.
+#[derive(Debug, Default)]
+pub struct PrometheusMetrics {
+    values: Mutex<BTreeMap<&'static str, MetricValue>>,
+}
+
+impl PrometheusMetrics {
+    /// Create a new `PrometheusMetrics`.
+    pub fn new() -> PrometheusMetrics {
+        PrometheusMetrics { values: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// Render all metrics in the Prometheus text exposition format.
+    ///
+    /// The output can be served as is from an HTTP endpoint for scraping.
+    pub fn render(&self) -> String {
+        let values = self.values.lock().expect("bip_util: PrometheusMetrics Failed To Lock Values");
+        let mut output = String::new();
+
+        for (name, value) in values.iter() {
+            // Writing to a String can not fail
+            let _ = match *value {
+                MetricValue::Counter(count) => write!(output, "# TYPE {} counter\n{} {}\n", name, name, count),
+                MetricValue::Gauge(value) => write!(output, "# TYPE {} gauge\n{} {}\n", name, name, value),
+                MetricValue::Histogram { sum, count } => {
+                    write!(output, "# TYPE {} summary\n{}_sum {}\n{}_count {}\n", name, name, sum, name, count)
+                }
+            };
+        }
+
+        output
+    }
+
+    fn update<F>(&self, name: &'static str, default: MetricValue, update: F)
+        where F: FnOnce(&mut MetricValue)
+    {
+        let mut values = self.values.lock().expect("bip_util: PrometheusMetrics Failed To Lock Values");
+
+        update(values.entry(name).or_insert(default));
+    }
+}
+
+impl Metrics for PrometheusMetrics {
+    fn counter(&self, name: &'static str, value: u64) {
+        self.update(name, MetricValue::Counter(0), |metric| {
+            if let MetricValue::Counter(ref mut count) = *metric {
+                *count += value;
+            }
+        });
+    }
+
+    fn gauge(&self, name: &'static str, delta: i64) {
+        self.update(name, MetricValue::Gauge(0), |metric| {
+            if let MetricValue::Gauge(ref mut value) = *metric {
+                *value += delta;
+            }
+        });
+    }
+
+    fn histogram(&self, name: &'static str, value: f64) {
+        self.update(name, MetricValue::Histogram { sum: 0.0, count: 0 }, |metric| {
+            if let MetricValue::Histogram { ref mut sum, ref mut count } = *metric {
+                *sum += value;
+                *count += 1;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrometheusMetrics;
+    use metrics::Metrics;
+
+    #[test]
+    fn positive_render_counter() {
+        let metrics = PrometheusMetrics::new();
+
+        metrics.counter("bip_test_counter", 2);
+        metrics.counter("bip_test_counter", 3);
+
+        assert_eq!("# TYPE bip_test_counter counter\nbip_test_counter 5\n", metrics.render());
+    }
+
+    #[test]
+    fn positive_render_gauge() {
+        let metrics = PrometheusMetrics::new();
+
+        metrics.gauge("bip_test_gauge", 4);
+        metrics.gauge("bip_test_gauge", -1);
+
+        assert_eq!("# TYPE bip_test_gauge gauge\nbip_test_gauge 3\n", metrics.render());
+    }
+
+    #[test]
+    fn positive_render_histogram() {
+        let metrics = PrometheusMetrics::new();
+
+        metrics.histogram("bip_test_histogram", 1.5);
+        metrics.histogram("bip_test_histogram", 2.5);
+
+        assert_eq!("# TYPE bip_test_histogram summary\nbip_test_histogram_sum 4\nbip_test_histogram_count 2\n",
+                   metrics.render());
+    }
+
+    #[test]
+    fn positive_render_sorted_by_name() {
+        let metrics = PrometheusMetrics::new();
+
+        metrics.counter("bip_b", 1);
+        metrics.counter("bip_a", 1);
+
+        assert_eq!("# TYPE bip_a counter\nbip_a 1\n# TYPE bip_b counter\nbip_b 1\n", metrics.render());
+    }
+}
@@ -0,0 +1,337 @@
+use std::cmp;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use bytes::{Bytes, BytesMut};
+
+/// Default size of buffers in a `BufferPool`, the size of a block.
+pub const DEFAULT_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Default maximum number of bytes held by a `BufferPool`.
+pub const DEFAULT_MAX_POOLED_BYTES: usize = 16 * 1024 * 1024;
+
+/// Builder for configuring a `BufferPool`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BufferPoolBuilder {
+    buffer_size: usize,
+    max_pooled_bytes: usize
+}
+
+impl BufferPoolBuilder {
+    /// Create a new `BufferPoolBuilder`.
+    pub fn new() -> BufferPoolBuilder {
+        BufferPoolBuilder{ buffer_size: DEFAULT_BUFFER_SIZE, max_pooled_bytes: DEFAULT_MAX_POOLED_BYTES }
+    }
+
+    /// Size of the buffers that will be pooled.
+    ///
+    /// Requests for buffers larger than this will not be pooled.
+    pub fn with_buffer_size(mut self, size: usize) -> BufferPoolBuilder {
+        self.buffer_size = size;
+        self
+    }
+
+    /// Maximum number of bytes that will be held by the pool.
+    ///
+    /// Buffers recycled while the pool is full are freed.
+    pub fn with_max_pooled_bytes(mut self, max_bytes: usize) -> BufferPoolBuilder {
+        self.max_pooled_bytes = max_bytes;
+        self
+    }
+
+    /// Build a `BufferPool` from the current builder.
+    pub fn build(self) -> BufferPool {
+        BufferPool{ inner: Arc::new(Mutex::new(PoolState::new(self))) }
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// Statistics for a `BufferPool`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Number of buffers that were freshly allocated.
+    pub allocated: u64,
+    /// Number of buffers that were reused from the pool.
+    pub reused: u64,
+    /// Number of buffers that were recycled back into the pool.
+    pub recycled: u64,
+    /// Number of buffers that were freed, because they could not be pooled.
+    pub freed: u64,
+    /// Number of bytes currently held by the pool, ready to be reused.
+    ///
+    /// Each pooled buffer is counted as the buffer size of the pool.
+    pub pooled_bytes: usize,
+    /// Number of bytes recycled while still shared, waiting for their other handles to be dropped.
+    ///
+    /// Each pending buffer is counted as the buffer size of the pool.
+    pub pending_bytes: usize
+}
+
+struct PoolState {
+    config: BufferPoolBuilder,
+    free: Vec<BytesMut>,
+    // Recycled buffers that were still shared, reclaimed once all other handles are dropped
+    pending: Vec<Bytes>,
+    stats: PoolStats
+}
+
+impl PoolState {
+    fn new(config: BufferPoolBuilder) -> PoolState {
+        PoolState{ config: config, free: Vec::new(), pending: Vec::new(), stats: PoolStats::default() }
+    }
+
+    fn reclaim_pending(&mut self) {
+        let size = self.config.buffer_size;
+        let pending = mem::replace(&mut self.pending, Vec::new());
+
+        for bytes in pending {
+            match bytes.try_mut() {
+                Ok(buffer) => {
+                    self.stats.pending_bytes -= size;
+
+                    // Shared bytes were only checked by length when recycled, so check the capacity we got back
+                    if self.stats.pooled_bytes + size > self.config.max_pooled_bytes || buffer.capacity() < size {
+                        self.stats.freed += 1;
+                    } else {
+                        self.stats.recycled += 1;
+                        self.stats.pooled_bytes += size;
+                        self.free.push(buffer);
+                    }
+                },
+                Err(bytes) => self.pending.push(bytes)
+            }
+        }
+    }
+}
+
+/// Pool of fixed size buffers, used to reduce allocator churn when allocating blocks.
+///
+/// Buffers are recycled by passing them back to `BufferPool::recycle`. Buffers that are
+/// still shared (for example, a block that was frozen and cloned into a `PieceMessage`)
+/// are held by the pool until every other handle to them has been dropped, at which
+/// point they are reused by the next allocation.
+///
+/// Both the buffers ready to be reused, and the buffers waiting to be reclaimed, are
+/// bounded by the maximum number of pooled bytes.
+///
+/// Cloning a `BufferPool` creates a new handle to the same pool.
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<Mutex<PoolState>>
+}
+
+impl BufferPool {
+    /// Global `BufferPool`, with the default configuration.
+    pub fn global() -> BufferPool {
+        lazy_static! {
+            static ref GLOBAL: BufferPool = BufferPoolBuilder::new().build();
+        }
+
+        GLOBAL.clone()
+    }
+
+    /// Allocate a zeroed buffer of the given length.
+    ///
+    /// If the length is no larger than the buffer size of the pool, the buffer will be
+    /// taken from the pool when possible.
+    pub fn alloc(&self, len: usize) -> BytesMut {
+        let (opt_buffer, size) = self.run_with_lock(|state| {
+            let size = state.config.buffer_size;
+
+            if len > size {
+                state.stats.allocated += 1;
+
+                return (None, size);
+            }
+
+            if state.free.is_empty() {
+                state.reclaim_pending();
+            }
+
+            match state.free.pop() {
+                Some(buffer) => {
+                    state.stats.reused += 1;
+                    state.stats.pooled_bytes -= size;
+
+                    (Some(buffer), size)
+                },
+                None => {
+                    state.stats.allocated += 1;
+
+                    (None, size)
+                }
+            }
+        });
+
+        let mut buffer = opt_buffer.unwrap_or_else(|| BytesMut::with_capacity(cmp::max(size, len)));
+        buffer.clear();
+        buffer.resize(len, 0);
+
+        buffer
+    }
+
+    /// Recycle the given buffer back into the pool.
+    ///
+    /// Buffers that are still shared are reclaimed once every other handle to them has been dropped.
+    /// Buffers smaller than the buffer size of the pool, or recycled while the pool is full, are freed.
+    pub fn recycle<B>(&self, buffer: B)
+        where B: Into<Bytes> {
+        let bytes = buffer.into();
+
+        self.run_with_lock(|state| {
+            let size = state.config.buffer_size;
+
+            let (pooled_full, pending_full) = (state.stats.pooled_bytes + size > state.config.max_pooled_bytes,
+                                               state.stats.pending_bytes + size > state.config.max_pooled_bytes);
+
+            // Shared bytes dont expose their capacity, but their length is a lower bound for it
+            match bytes.try_mut() {
+                Ok(ref buffer) if pooled_full || buffer.capacity() < size => state.stats.freed += 1,
+                Err(ref bytes) if pending_full || bytes.len() < size      => state.stats.freed += 1,
+                Ok(buffer) => {
+                    state.stats.recycled += 1;
+                    state.stats.pooled_bytes += size;
+                    state.free.push(buffer);
+                },
+                Err(bytes) => {
+                    state.stats.pending_bytes += size;
+                    state.pending.push(bytes);
+                }
+            }
+        });
+    }
+
+    /// Current statistics for the pool.
+    pub fn stats(&self) -> PoolStats {
+        self.run_with_lock(|state| state.stats)
+    }
+
+    fn run_with_lock<C, R>(&self, call: C) -> R
+        where C: FnOnce(&mut PoolState) -> R {
+        let mut lock_state = self.inner.lock()
+            .expect("bip_util: Failed To Lock BufferPool State");
+
+        call(&mut *lock_state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BufferPool, BufferPoolBuilder, DEFAULT_BUFFER_SIZE};
+
+    #[test]
+    fn positive_alloc_zeroed() {
+        let pool = BufferPoolBuilder::new().with_buffer_size(4).build();
+
+        let buffer = pool.alloc(4);
+        assert_eq!(&[0u8; 4], &buffer[..]);
+    }
+
+    #[test]
+    fn positive_recycle_unique_buffer() {
+        let pool = BufferPoolBuilder::new().with_buffer_size(64).build();
+
+        let mut buffer = pool.alloc(64);
+        buffer[0] = 1;
+        pool.recycle(buffer);
+
+        let buffer = pool.alloc(64);
+        assert_eq!(0, buffer[0]);
+
+        let stats = pool.stats();
+        assert_eq!(1, stats.allocated);
+        assert_eq!(1, stats.reused);
+        assert_eq!(1, stats.recycled);
+        assert_eq!(0, stats.pooled_bytes);
+    }
+
+    #[test]
+    fn positive_recycle_shared_buffer_once_dropped() {
+        let pool = BufferPoolBuilder::new().with_buffer_size(64).build();
+
+        let bytes = pool.alloc(64).freeze();
+        let shared = bytes.clone();
+        pool.recycle(bytes);
+
+        let stats = pool.stats();
+        assert_eq!(0, stats.recycled);
+        assert_eq!(0, stats.pooled_bytes);
+        assert_eq!(64, stats.pending_bytes);
+
+        drop(shared);
+        pool.alloc(64);
+
+        let stats = pool.stats();
+        assert_eq!(1, stats.recycled);
+        assert_eq!(1, stats.reused);
+        assert_eq!(0, stats.pending_bytes);
+    }
+
+    #[test]
+    fn positive_pending_buffers_dont_fill_pool() {
+        let pool = BufferPoolBuilder::new().with_buffer_size(64).with_max_pooled_bytes(64).build();
+
+        let (bytes, buffer) = (pool.alloc(64).freeze(), pool.alloc(64));
+        let shared = bytes.clone();
+        pool.recycle(bytes);
+        pool.recycle(buffer);
+
+        let stats = pool.stats();
+        assert_eq!(0, stats.freed);
+        assert_eq!(64, stats.pooled_bytes);
+        assert_eq!(64, stats.pending_bytes);
+
+        drop(shared);
+    }
+
+    #[test]
+    fn positive_reclaim_split_buffer() {
+        let pool = BufferPoolBuilder::new().with_buffer_size(64).build();
+
+        let mut bytes = pool.alloc(128).freeze();
+        let front = bytes.split_to(64);
+        pool.recycle(front);
+
+        drop(bytes);
+        let buffer = pool.alloc(64);
+
+        let stats = pool.stats();
+        assert!(buffer.capacity() >= 64);
+        assert_eq!(1, stats.recycled);
+        assert_eq!(1, stats.reused);
+        assert_eq!(0, stats.freed);
+    }
+
+    #[test]
+    fn positive_global_pool_shared() {
+        let pool = BufferPool::global();
+
+        pool.recycle(BufferPool::global().alloc(DEFAULT_BUFFER_SIZE));
+        assert!(pool.stats().recycled >= 1);
+    }
+
+    #[test]
+    fn negative_recycle_over_max_pooled_bytes() {
+        let pool = BufferPoolBuilder::new().with_buffer_size(64).with_max_pooled_bytes(64).build();
+
+        let (buffer_one, buffer_two) = (pool.alloc(64), pool.alloc(64));
+        pool.recycle(buffer_one);
+        pool.recycle(buffer_two);
+
+        let stats = pool.stats();
+        assert_eq!(1, stats.freed);
+        assert_eq!(64, stats.pooled_bytes);
+    }
+
+    #[test]
+    fn negative_alloc_larger_than_buffer_size() {
+        let pool = BufferPoolBuilder::new().with_buffer_size(64).build();
+
+        pool.recycle(pool.alloc(64));
+        let buffer = pool.alloc(128);
+
+        assert_eq!(128, buffer.len());
+        assert_eq!(0, pool.stats().reused);
+    }
+}
@@ -1,5 +1,7 @@
+use std::fmt;
 use std::ops::BitXor;
 
+use convert;
 use error::{LengthError, LengthResult, LengthErrorKind};
 
 mod builder;
@@ -34,6 +36,51 @@ impl ShaHash {
         }
     }
 
+    /// Create a ShaHash from the given (case insensitive) hex string.
+    ///
+    /// Returns None if the string is not valid hex or is not the length of a hash.
+    pub fn from_hex(hex: &str) -> Option<ShaHash> {
+        convert::hex_to_bytes(hex).and_then(|bytes| ShaHash::from_hash(&bytes).ok())
+    }
+
+    /// Create a ShaHash from the given (case insensitive) base32 string, as found in magnet links.
+    ///
+    /// Returns None if the string is not valid base32 or is not the length of a hash.
+    pub fn from_base32(base32: &str) -> Option<ShaHash> {
+        convert::base32_to_bytes(base32).and_then(|bytes| ShaHash::from_hash(&bytes).ok())
+    }
+
+    /// Convert the ShaHash to a lowercase hex string.
+    pub fn to_hex(&self) -> String {
+        convert::bytes_to_hex(&self.hash)
+    }
+
+    /// Convert the ShaHash to an uppercase base32 string, as found in magnet links.
+    pub fn to_base32(&self) -> String {
+        convert::bytes_to_base32(&self.hash)
+    }
+
+    /// Compare the ShaHash with another in constant time.
+    ///
+    /// This should be used instead of `==` when the hashes are secret.
+    pub fn ct_eq(&self, other: &ShaHash) -> bool {
+        convert::constant_time_eq(&self.hash, &other.hash)
+    }
+
+    /// Create a new ShaHash with the bit at the given index flipped.
+    ///
+    /// Bits are indexed from the most significant bit of the first byte.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn flip_bit(&self, index: usize) -> ShaHash {
+        let mut hash = self.hash;
+        let (byte_index, bit_index) = (index / 8, index % 8);
+
+        hash[byte_index] ^= 1 << (7 - bit_index);
+
+        ShaHash { hash: hash }
+    }
+
     pub fn bits<'a>(&'a self) -> Bits<'a> {
         Bits::new(&self.hash)
     }
@@ -69,6 +116,12 @@ impl PartialEq<[u8]> for ShaHash {
     }
 }
 
+impl fmt::Display for ShaHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
 impl BitXor<ShaHash> for ShaHash {
     type Output = ShaHash;
 
@@ -187,6 +240,32 @@ mod tests {
         assert!(leading_zeroes == 0);
     }
 
+    #[test]
+    fn positive_flip_first_bit() {
+        let zero_bits = ShaHash::from([0u8; super::SHA_HASH_LEN]);
+
+        let mut expected = [0u8; super::SHA_HASH_LEN];
+        expected[0] = 128;
+
+        assert_eq!(ShaHash::from(expected), zero_bits.flip_bit(0));
+    }
+
+    #[test]
+    fn positive_flip_last_bit() {
+        let one_bits = ShaHash::from([255u8; super::SHA_HASH_LEN]);
+
+        let mut expected = [255u8; super::SHA_HASH_LEN];
+        expected[super::SHA_HASH_LEN - 1] = 254;
+
+        assert_eq!(ShaHash::from(expected), one_bits.flip_bit(super::SHA_HASH_LEN * 8 - 1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_flip_bit_out_of_bounds() {
+        ShaHash::from([0u8; super::SHA_HASH_LEN]).flip_bit(super::SHA_HASH_LEN * 8);
+    }
+
     #[test]
     fn positive_all_leading_zeroes() {
         let first_one_bits = ShaHash::from([255u8; super::SHA_HASH_LEN]);
@@ -226,6 +305,36 @@ mod tests {
         assert!(leading_zeroes == 0);
     }
 
+    #[test]
+    fn positive_hex_round_trip() {
+        let hash = ShaHash::from_bytes(b"bip_util");
+
+        assert_eq!(Some(hash), ShaHash::from_hex(&hash.to_hex()));
+        assert_eq!(hash.to_hex(), format!("{}", hash));
+    }
+
+    #[test]
+    fn positive_base32_round_trip() {
+        let hash = ShaHash::from_bytes(b"bip_util");
+        let base32 = hash.to_base32();
+
+        assert_eq!(32, base32.len());
+        assert_eq!(Some(hash), ShaHash::from_base32(&base32.to_lowercase()));
+    }
+
+    #[test]
+    fn negative_from_hex_wrong_length() {
+        assert_eq!(None, ShaHash::from_hex("0123456789abcdef"));
+    }
+
+    #[test]
+    fn positive_ct_eq() {
+        let hash = ShaHash::from_bytes(b"bip_util");
+
+        assert!(hash.ct_eq(&hash));
+        assert!(!hash.ct_eq(&ShaHash::from_bytes(b"bip_utils")));
+    }
+
     #[test]
     #[should_panic]
     fn negative_from_hash_too_long() {
@@ -0,0 +1,16 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate bip_utracker;
+
+use bip_utracker::request::TrackerRequest;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(request) = TrackerRequest::from_bytes_exact(data) {
+        let mut bytes = Vec::new();
+        request.write_bytes(&mut bytes).unwrap();
+
+        // Anything we accept, we should be able to accept again after writing it out
+        TrackerRequest::from_bytes_exact(&bytes).unwrap();
+    }
+});
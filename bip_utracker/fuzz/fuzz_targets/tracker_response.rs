@@ -0,0 +1,16 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate bip_utracker;
+
+use bip_utracker::response::TrackerResponse;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(response) = TrackerResponse::from_bytes_exact(data) {
+        let mut bytes = Vec::new();
+        response.write_bytes(&mut bytes).unwrap();
+
+        // Anything we accept, we should be able to accept again after writing it out
+        TrackerResponse::from_bytes_exact(&bytes).unwrap();
+    }
+});
@@ -1,24 +1,29 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::collections::hash_map::Entry;
+use std::cmp;
 use std::io::{self, Cursor};
+use std::mem;
 use std::net::SocketAddr;
 use std::thread;
 
 use bip_handshake::{DiscoveryInfo, InitiateMessage, Protocol};
-use bip_util::bt::PeerId;
+use bip_util::bt::{InfoHash, PeerId};
+use byteorder::{BigEndian, ReadBytesExt};
 use chrono::{DateTime, Duration};
 use chrono::offset::Utc;
 use futures::future::Either;
 use futures::sink::{Wait, Sink};
-use nom::IResult;
 use rand;
 use umio::{ELoopBuilder, Dispatcher, Provider};
 use umio::external::{self, Timeout};
 
-use announce::{AnnounceRequest, SourceIP, DesiredPeers};
-use client::{ClientToken, ClientRequest, RequestLimiter, ClientMetadata, ClientResponse};
-use client::error::{ClientResult, ClientError};
+use announce::{AnnounceRequest, ClientState, SourceIP, DesiredPeers};
+use client::{ClientToken, ClientRequest, RequestLimiter, ClientMetadata, ClientResponse,
+             ClientConfig, NormalizedPeers, EndpointAttempt};
+use client;
+use client::error::{ClientError, ClientWarning};
 use option::AnnounceOptions;
+use parse::ParseError;
 use request::{self, TrackerRequest, RequestType};
 use response::{TrackerResponse, ResponseType};
 use scrape::ScrapeRequest;
@@ -26,7 +31,12 @@ use scrape::ScrapeRequest;
 const EXPECTED_PACKET_LENGTH: usize = 1500;
 
 const CONNECTION_ID_VALID_DURATION_MILLIS: i64 = 60000;
-const MAXIMUM_REQUEST_RETRANSMIT_ATTEMPTS: u64 = 8;
+// Past this, doubling the timeout would overflow (and be an absurd timeout anyways)
+const MAXIMUM_TIMEOUT_DOUBLINGS: u64 = 32;
+// Maximum number of failing endpoints to remember, so a client talking to many trackers stays bounded
+const MAXIMUM_TRACKED_ENDPOINTS: usize = 1024;
+// Maximum number of warnings kept for a single request, older warnings are dropped first.
+const MAXIMUM_TRACKED_WARNINGS: usize = 16;
 
 /// Internal dispatch timeout.
 enum DispatchTimeout {
@@ -36,7 +46,7 @@ enum DispatchTimeout {
 
 /// Internal dispatch message for clients.
 pub enum DispatchMessage {
-    Request(SocketAddr, ClientToken, ClientRequest),
+    Request(Vec<SocketAddr>, ClientToken, ClientRequest, Option<u64>),
     StartTimer,
     Shutdown,
 }
@@ -47,7 +57,8 @@ pub enum DispatchMessage {
 pub fn create_dispatcher<H>(bind: SocketAddr,
                             handshaker: H,
                             msg_capacity: usize,
-                            limiter: RequestLimiter)
+                            limiter: RequestLimiter,
+                            config: ClientConfig)
                             -> io::Result<external::Sender<DispatchMessage>>
     where H: Sink + DiscoveryInfo + 'static + Send,
           H::SinkItem: From<Either<InitiateMessage, ClientMetadata>>
@@ -62,7 +73,7 @@ pub fn create_dispatcher<H>(bind: SocketAddr,
     let mut eloop = try!(builder.build());
     let channel = eloop.channel();
 
-    let dispatch = ClientDispatcher::new(handshaker, bind, limiter);
+    let dispatch = ClientDispatcher::new(handshaker, bind, limiter, config);
 
     thread::spawn(move || {
         eloop.run(dispatch).expect("bip_utracker: ELoop Shutdown Unexpectedly...");
@@ -80,11 +91,12 @@ pub fn create_dispatcher<H>(bind: SocketAddr,
 struct ClientDispatcher<H> {
     handshaker:      Wait<H>,
     pid:             PeerId,
-    port:            u16,
     bound_addr:      SocketAddr,
     active_requests: HashMap<ClientToken, ConnectTimer>,
     id_cache:        ConnectIdCache,
+    health:          EndpointHealth,
     limiter:         RequestLimiter,
+    config:          ClientConfig,
 }
 
 impl<H> ClientDispatcher<H>
@@ -92,18 +104,18 @@ impl<H> ClientDispatcher<H>
           H::SinkItem: From<Either<InitiateMessage, ClientMetadata>>
 {
     /// Create a new ClientDispatcher.
-    pub fn new(handshaker: H, bind: SocketAddr, limiter: RequestLimiter) -> ClientDispatcher<H> {
+    pub fn new(handshaker: H, bind: SocketAddr, limiter: RequestLimiter, config: ClientConfig) -> ClientDispatcher<H> {
         let peer_id = handshaker.peer_id();
-        let port = handshaker.port();
 
         ClientDispatcher {
             handshaker: handshaker.wait(),
             pid: peer_id,
-            port: port,
             bound_addr: bind,
             active_requests: HashMap::new(),
             id_cache: ConnectIdCache::new(),
+            health: EndpointHealth::new(),
             limiter: limiter,
+            config: config,
         }
     }
 
@@ -111,9 +123,14 @@ impl<H> ClientDispatcher<H>
     pub fn shutdown<'a>(&mut self, provider: &mut Provider<'a, ClientDispatcher<H>>) {
         // Notify all active requests with the appropriate error
         for token_index in 0..self.active_requests.len() {
-            let next_token = *self.active_requests.keys().skip(token_index).next().unwrap();
-
-            self.notify_client(next_token, Err(ClientError::ClientShutdown));
+            let (next_token, attempts) = self.active_requests.iter()
+                .skip(token_index)
+                .map(|(&token, conn_timer)| (token, conn_timer.attempts()))
+                .next()
+                .unwrap();
+
+            self.notify_client_metadata(
+                ClientMetadata::new(next_token, Err(ClientError::ClientShutdown)).with_attempts(attempts));
         }
         // TODO: Clear active timeouts
         self.active_requests.clear();
@@ -121,43 +138,100 @@ impl<H> ClientDispatcher<H>
         provider.shutdown();
     }
 
-    /// Finish a request by sending the result back to the client.
-    pub fn notify_client(&mut self, token: ClientToken, result: ClientResult<ClientResponse>) {
-        self.handshaker.send(Either::B(ClientMetadata::new(token, result)).into())
+    /// Finish a request by sending the given metadata back to the client.
+    pub fn notify_client_metadata(&mut self, metadata: ClientMetadata) {
+        self.handshaker.send(Either::B(metadata).into())
             .unwrap_or_else(|_| panic!("NEED TO FIX"));
 
         self.limiter.acknowledge();
     }
 
-    /// Process a request to be sent to the given address and associated with the given token.
+    /// Process a request to be sent to the given addresses and associated with the given token.
     pub fn send_request<'a>(&mut self,
                             provider: &mut Provider<'a, ClientDispatcher<H>>,
-                            addr: SocketAddr,
+                            mut addrs: Vec<SocketAddr>,
                             token: ClientToken,
-                            request: ClientRequest) {
-        // Check for IP version mismatch between source addr and dest addr
-        match (self.bound_addr, addr) {
-            (SocketAddr::V4(_), SocketAddr::V6(_)) |
-            (SocketAddr::V6(_), SocketAddr::V4(_)) => {
-                self.notify_client(token, Err(ClientError::IPVersionMismatch));
+                            request: ClientRequest,
+                            opt_timeout: Option<u64>) {
+        self.health.order(&mut addrs);
+
+        // Check for IP version mismatch between source addr and dest addrs
+        let bound_addr = self.bound_addr;
+        let (mut usable, mismatched): (VecDeque<SocketAddr>, Vec<SocketAddr>) = addrs.into_iter()
+            .partition(|&addr| is_same_ip_version(bound_addr, addr));
+        let skipped = mismatched.into_iter()
+            .map(|addr| EndpointAttempt::new(addr, 0, Some(ClientError::IPVersionMismatch)))
+            .collect();
+
+        let addr = match usable.pop_front() {
+            Some(addr) => addr,
+            None => {
+                self.notify_client_metadata(
+                    ClientMetadata::new(token, Err(ClientError::IPVersionMismatch)).with_endpoints(skipped));
 
                 return;
             }
-            _ => (),
         };
-        self.active_requests.insert(token, ConnectTimer::new(addr, request));
+        let base_timeout = opt_timeout.unwrap_or(client::duration_to_millis(self.config.base_timeout()));
+        self.active_requests.insert(token, ConnectTimer::new(addr, request, base_timeout, self.config.max_retransmits())
+            .with_fallbacks(usable, skipped));
 
         self.process_request(provider, token, false);
     }
 
+    /// Give up on the current endpoint of a request, moving on to the next endpoint if there is one.
+    fn fail_endpoint<'a>(&mut self,
+                         provider: &mut Provider<'a, ClientDispatcher<H>>,
+                         token: ClientToken,
+                         mut conn_timer: ConnectTimer,
+                         error: ClientError) {
+        self.health.record_failure(conn_timer.message_params().0);
+
+        if conn_timer.next_endpoint(error.clone()) {
+            self.active_requests.insert(token, conn_timer);
+
+            self.process_request(provider, token, false);
+        } else {
+            let attempts = conn_timer.attempts();
+            let metadata = conn_timer.annotate(ClientMetadata::new(token, Err(error)));
+
+            self.notify_client_metadata(
+                metadata.with_attempts(attempts).with_endpoints(conn_timer.into_endpoints()));
+        }
+    }
+
+    /// Process a response that we could not parse, recording it against the matching request if there is one.
+    ///
+    /// The request is left to time out (or receive a valid response), since the tracker may have sent
+    /// a nonstandard response that a retransmit will not fix, but which an integrator may want to see.
+    pub fn recv_malformed_response(&mut self, addr: SocketAddr, message: &[u8], error: ParseError) {
+        // Transaction id follows the action id in every response
+        let token = match message.get(4..8).map(|mut tid_bytes| tid_bytes.read_u32::<BigEndian>()) {
+            Some(Ok(tid)) => ClientToken(tid),
+            _ => return,
+        };
+        let raw_responses = self.config.raw_responses();
+
+        if let Some(conn_timer) = self.active_requests.get_mut(&token) {
+            if conn_timer.message_params().0 == addr {
+                conn_timer.add_warning(ClientWarning::MalformedResponse(addr, error));
+
+                if raw_responses {
+                    conn_timer.set_raw_response(message.to_vec());
+                }
+            }
+        }
+    }
+
     /// Process a response received from some tracker and match it up against our sent requests.
     pub fn recv_response<'a, 'b>(&mut self,
                                  provider: &mut Provider<'a, ClientDispatcher<H>>,
                                  addr: SocketAddr,
+                                 message: &[u8],
                                  response: TrackerResponse<'b>) {
         let token = ClientToken(response.transaction_id());
 
-        let conn_timer = if let Some(conn_timer) = self.active_requests.remove(&token) {
+        let mut conn_timer = if let Some(conn_timer) = self.active_requests.remove(&token) {
             if conn_timer.message_params().0 == addr {
                 conn_timer
             } else {
@@ -170,6 +244,10 @@ impl<H> ClientDispatcher<H>
         provider.clear_timeout(conn_timer.timeout_id()
             .expect("bip_utracker: Failed To Clear Request Timeout"));
 
+        if self.config.raw_responses() {
+            conn_timer.set_raw_response(message.to_vec());
+        }
+
         // Check if the response requires us to update the connection timer
         if let &ResponseType::Connect(id) = response.response_type() {
             self.id_cache.put(addr, id);
@@ -178,25 +256,38 @@ impl<H> ClientDispatcher<H>
             self.process_request(provider, token, false);
         } else {
             // Match the request type against the response type and update our client
-            match (conn_timer.message_params().1, response.response_type()) {
-                (&ClientRequest::Announce(hash, _), &ResponseType::Announce(ref res)) => {
-                    // Forward contact information on to the handshaker
-                    for addr in res.peers().iter() {
+            let opt_metadata = match (conn_timer.message_params().1, response.response_type()) {
+                (&ClientRequest::Announce(hash, _), &ResponseType::Announce(ref res)) |
+                (&ClientRequest::AnnounceWithOptions(hash, _, _), &ResponseType::Announce(ref res)) => {
+                    let peers = NormalizedPeers::new(res.peers().iter(), self.bound_addr, self.handshaker.get_ref().port());
+
+                    // Forward normalized contact information on to the handshaker
+                    for &addr in peers.peers() {
                         self.handshaker.send(Either::A(InitiateMessage::new(Protocol::BitTorrent, hash, addr)).into())
                             .unwrap_or_else(|_| panic!("NEED TO FIX"));
                     }
 
-                    self.notify_client(token, Ok(ClientResponse::Announce(res.to_owned())));
+                    Ok(ClientMetadata::with_peers(token, Ok(ClientResponse::Announce(res.to_owned())), peers))
                 }
                 (&ClientRequest::Scrape(..), &ResponseType::Scrape(ref res)) => {
-                    self.notify_client(token, Ok(ClientResponse::Scrape(res.to_owned())));
-                }
-                (_, &ResponseType::Error(ref res)) => {
-                    self.notify_client(token, Err(ClientError::ServerMessage(res.to_owned())));
+                    Ok(ClientMetadata::new(token, Ok(ClientResponse::Scrape(res.to_owned()))))
                 }
-                _ => {
-                    self.notify_client(token, Err(ClientError::ServerError));
+                (_, &ResponseType::Error(ref res)) => Err(ClientError::ServerMessage(res.to_owned())),
+                _ => Err(ClientError::ServerError),
+            };
+
+            // Errors may just be specific to this endpoint, so try the next one
+            match opt_metadata {
+                Ok(metadata) => {
+                    self.health.record_success(addr);
+
+                    let attempts = conn_timer.attempts();
+                    let metadata = conn_timer.annotate(metadata);
+
+                    self.notify_client_metadata(
+                        metadata.with_attempts(attempts).with_endpoints(conn_timer.finish(None)));
                 }
+                Err(error) => self.fail_endpoint(provider, token, conn_timer, error),
             }
         }
     }
@@ -218,33 +309,24 @@ impl<H> ClientDispatcher<H>
         let next_timeout = match conn_timer.current_timeout(timed_out) {
             Some(timeout) => timeout,
             None => {
-                self.notify_client(token, Err(ClientError::MaxTimeout));
+                self.fail_endpoint(provider, token, conn_timer, ClientError::MaxTimeout);
 
                 return;
             }
         };
 
         let addr = conn_timer.message_params().0;
+        // Look up the port on every request, since it may have been changed by a port mapping
+        let port = self.handshaker.get_ref().port();
         let opt_conn_id = self.id_cache.get(conn_timer.message_params().0);
 
         // Resolve the type of request we need to make
         let (conn_id, request_type) = match (opt_conn_id, conn_timer.message_params().1) {
             (Some(id), &ClientRequest::Announce(hash, state)) => {
-                let source_ip = match addr {
-                    SocketAddr::V4(_) => SourceIP::ImpliedV4,
-                    SocketAddr::V6(_) => SourceIP::ImpliedV6,
-                };
-                let key = rand::random::<u32>();
-
-                (id,
-                 RequestType::Announce(AnnounceRequest::new(hash,
-                                                            self.pid,
-                                                            state,
-                                                            source_ip,
-                                                            key,
-                                                            DesiredPeers::Default,
-                                                            self.port,
-                                                            AnnounceOptions::new())))
+                (id, announce_request(hash, state, AnnounceOptions::new(), addr, self.pid, port))
+            }
+            (Some(id), &ClientRequest::AnnounceWithOptions(hash, state, ref options)) => {
+                (id, announce_request(hash, state, options.clone(), addr, self.pid, port))
             }
             (Some(id), &ClientRequest::Scrape(hash)) => {
                 let mut scrape_request = ScrapeRequest::new();
@@ -271,7 +353,12 @@ impl<H> ClientDispatcher<H>
 
         // If message was not sent (too long to fit) then end the request
         if !write_success {
-            self.notify_client(token, Err(ClientError::MaxLength));
+            let attempts = conn_timer.attempts();
+            let metadata = conn_timer.annotate(ClientMetadata::new(token, Err(ClientError::MaxLength)));
+
+            self.notify_client_metadata(metadata
+                .with_attempts(attempts)
+                .with_endpoints(conn_timer.finish(Some(ClientError::MaxLength))));
         } else {
             conn_timer.set_timeout_id(
                 provider.set_timeout(DispatchTimeout::Connect(token), next_timeout)
@@ -293,18 +380,22 @@ impl<H> Dispatcher for ClientDispatcher<H>
                     mut provider: Provider<'a, Self>,
                     message: &[u8],
                     addr: SocketAddr) {
-        let response = match TrackerResponse::from_bytes(message) {
-            IResult::Done(_, rsp) => rsp,
-            _ => return, // TODO: Add Logging
+        let response = match TrackerResponse::from_bytes_exact(message) {
+            Ok(rsp) => rsp,
+            Err(error) => {
+                self.recv_malformed_response(addr, message, error);
+
+                return;
+            }
         };
 
-        self.recv_response(&mut provider, addr, response);
+        self.recv_response(&mut provider, addr, message, response);
     }
 
     fn notify<'a>(&mut self, mut provider: Provider<'a, Self>, message: DispatchMessage) {
         match message {
-            DispatchMessage::Request(addr, token, req_type) => {
-                self.send_request(&mut provider, addr, token, req_type);
+            DispatchMessage::Request(addrs, token, req_type, opt_timeout) => {
+                self.send_request(&mut provider, addrs, token, req_type, opt_timeout);
             }
             DispatchMessage::StartTimer => self.timeout(provider, DispatchTimeout::CleanUp),
             DispatchMessage::Shutdown => self.shutdown(&mut provider),
@@ -325,38 +416,142 @@ impl<H> Dispatcher for ClientDispatcher<H>
     }
 }
 
+/// Create an announce request for the given tracker address with the given options attached.
+fn announce_request(hash: InfoHash,
+                    state: ClientState,
+                    options: AnnounceOptions<'static>,
+                    addr: SocketAddr,
+                    pid: PeerId,
+                    port: u16)
+                    -> RequestType<'static> {
+    let source_ip = match addr {
+        SocketAddr::V4(_) => SourceIP::ImpliedV4,
+        SocketAddr::V6(_) => SourceIP::ImpliedV6,
+    };
+    let key = rand::random::<u32>();
+
+    RequestType::Announce(AnnounceRequest::new(hash,
+                                               pid,
+                                               state,
+                                               source_ip,
+                                               key,
+                                               DesiredPeers::Default,
+                                               port,
+                                               options))
+}
+
 // ----------------------------------------------------------------------------//
 
 /// Contains logic for making sure a valid connection id is present
 /// and correctly timing out when sending requests to the server.
 struct ConnectTimer {
     addr: SocketAddr,
+    fallbacks: VecDeque<SocketAddr>,
+    tried: Vec<EndpointAttempt>,
+    prior_attempts: u64,
     attempt: u64,
+    sent: bool,
+    base_timeout: u64,
+    max_retransmits: u64,
     request: ClientRequest,
     timeout_id: Option<Timeout>,
+    warnings: Vec<ClientWarning>,
+    raw_response: Option<Vec<u8>>,
 }
 
 impl ConnectTimer {
     /// Create a new ConnectTimer.
-    pub fn new(addr: SocketAddr, request: ClientRequest) -> ConnectTimer {
+    pub fn new(addr: SocketAddr, request: ClientRequest, base_timeout: u64, max_retransmits: u64) -> ConnectTimer {
         ConnectTimer {
             addr: addr,
+            fallbacks: VecDeque::new(),
+            tried: Vec::new(),
+            prior_attempts: 0,
             attempt: 0,
+            sent: false,
+            base_timeout: base_timeout,
+            max_retransmits: max_retransmits,
             request: request,
             timeout_id: None,
+            warnings: Vec::new(),
+            raw_response: None,
         }
     }
 
+    /// Sets the endpoints to fall back to, as well as any endpoints that were already skipped.
+    pub fn with_fallbacks(mut self, fallbacks: VecDeque<SocketAddr>, skipped: Vec<EndpointAttempt>) -> ConnectTimer {
+        self.fallbacks = fallbacks;
+        self.tried = skipped;
+
+        self
+    }
+
+    /// Gives up on the current endpoint with the given error, and moves on to the next endpoint.
+    ///
+    /// Returns false if there are no more endpoints to try.
+    pub fn next_endpoint(&mut self, error: ClientError) -> bool {
+        // Request carries on, so keep whatever the tracker told us for the final metadata
+        if let (&ClientError::ServerMessage(ref response), false) = (&error, self.fallbacks.is_empty()) {
+            let warning = ClientWarning::TrackerMessage(self.addr, response.message().to_owned());
+
+            self.add_warning(warning);
+        }
+
+        let endpoint_attempts = self.endpoint_attempts();
+        self.tried.push(EndpointAttempt::new(self.addr, endpoint_attempts, Some(error)));
+
+        match self.fallbacks.pop_front() {
+            Some(addr) => {
+                self.prior_attempts += endpoint_attempts;
+                self.addr = addr;
+                self.attempt = 0;
+                self.sent = false;
+                self.timeout_id = None;
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Finishes the request on the current endpoint, yielding all endpoints that were tried.
+    pub fn finish(mut self, opt_error: Option<ClientError>) -> Vec<EndpointAttempt> {
+        let endpoint_attempts = self.endpoint_attempts();
+        self.tried.push(EndpointAttempt::new(self.addr, endpoint_attempts, opt_error));
+
+        self.tried
+    }
+
+    /// Yields all endpoints that were tried, after `ConnectTimer::next_endpoint` returned false.
+    pub fn into_endpoints(self) -> Vec<EndpointAttempt> {
+        self.tried
+    }
+
     /// Yields the current timeout value to use or None if the request should time out completely.
     pub fn current_timeout(&mut self, timed_out: bool) -> Option<u64> {
-        if self.attempt == MAXIMUM_REQUEST_RETRANSMIT_ATTEMPTS {
+        if timed_out && self.attempt == self.max_retransmits {
             None
         } else {
             if timed_out {
                 self.attempt += 1;
             }
+            self.sent = true;
+
+            Some(calculate_message_timeout_millis(self.base_timeout, self.attempt))
+        }
+    }
 
-            Some(calculate_message_timeout_millis(self.attempt))
+    /// Yields the number of attempts that have been made for the request, across all endpoints.
+    pub fn attempts(&self) -> u64 {
+        self.prior_attempts + self.endpoint_attempts()
+    }
+
+    /// Yields the number of attempts that have been made to the current endpoint.
+    fn endpoint_attempts(&self) -> u64 {
+        if self.sent {
+            self.attempt + 1
+        } else {
+            0
         }
     }
 
@@ -370,15 +565,96 @@ impl ConnectTimer {
         self.timeout_id = Some(id);
     }
 
+    /// Records a warning for the request, dropping the oldest warning if we are tracking too many.
+    pub fn add_warning(&mut self, warning: ClientWarning) {
+        if self.warnings.len() >= MAXIMUM_TRACKED_WARNINGS {
+            self.warnings.remove(0);
+        }
+
+        self.warnings.push(warning);
+    }
+
+    /// Records the raw bytes of the last response received for the request.
+    pub fn set_raw_response(&mut self, raw_response: Vec<u8>) {
+        self.raw_response = Some(raw_response);
+    }
+
+    /// Moves the recorded warnings and raw response into the given metadata.
+    pub fn annotate(&mut self, metadata: ClientMetadata) -> ClientMetadata {
+        let metadata = metadata.with_warnings(mem::replace(&mut self.warnings, Vec::new()));
+
+        match self.raw_response.take() {
+            Some(raw_response) => metadata.with_raw_response(raw_response),
+            None => metadata,
+        }
+    }
+
     /// Yields the message parameters for the current connection.
     pub fn message_params(&self) -> (SocketAddr, &ClientRequest) {
         (self.addr, &self.request)
     }
 }
 
-/// Calculates the timeout for the request given the attempt count.
-fn calculate_message_timeout_millis(attempt: u64) -> u64 {
-    (15 * 2u64.pow(attempt as u32)) * 1000
+/// Calculates the timeout for the request given the base timeout and attempt count.
+fn calculate_message_timeout_millis(base_timeout: u64, attempt: u64) -> u64 {
+    let doublings = cmp::min(attempt, MAXIMUM_TIMEOUT_DOUBLINGS);
+
+    base_timeout.saturating_mul(1u64 << doublings)
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Tracks the health of tracker endpoints, so that endpoints which failed recently are tried last.
+struct EndpointHealth {
+    failures: HashMap<SocketAddr, u64>,
+}
+
+impl EndpointHealth {
+    /// Create a new endpoint health tracker.
+    fn new() -> EndpointHealth {
+        EndpointHealth { failures: HashMap::new() }
+    }
+
+    /// Record that the endpoint responded successfully.
+    fn record_success(&mut self, addr: SocketAddr) {
+        self.failures.remove(&addr);
+    }
+
+    /// Record that the endpoint failed to respond successfully.
+    ///
+    /// If we are already tracking the maximum number of endpoints, the endpoint with the fewest
+    /// failures is forgotten to make room.
+    fn record_failure(&mut self, addr: SocketAddr) {
+        if !self.failures.contains_key(&addr) && self.failures.len() >= MAXIMUM_TRACKED_ENDPOINTS {
+            let opt_healthiest = self.failures.iter()
+                .min_by_key(|&(_, &failures)| failures)
+                .map(|(&healthiest, _)| healthiest);
+
+            opt_healthiest.map(|healthiest| self.failures.remove(&healthiest));
+        }
+
+        let failures = self.failures.entry(addr).or_insert(0);
+        *failures = failures.saturating_add(1);
+    }
+
+    /// Number of consecutive failures for the endpoint.
+    fn failures(&self, addr: SocketAddr) -> u64 {
+        self.failures.get(&addr).cloned().unwrap_or(0)
+    }
+
+    /// Order the endpoints by consecutive failures, preserving the given order for ties.
+    fn order(&self, addrs: &mut Vec<SocketAddr>) {
+        addrs.sort_by_key(|&addr| self.failures(addr));
+    }
+}
+
+/// Returns true if both addresses are of the same IP version.
+fn is_same_ip_version(source: SocketAddr, dest: SocketAddr) -> bool {
+    match (source, dest) {
+        (SocketAddr::V4(_), SocketAddr::V4(_)) |
+        (SocketAddr::V6(_), SocketAddr::V6(_)) => true,
+        _ => false,
+    }
 }
 
 // ----------------------------------------------------------------------------//
@@ -444,3 +720,187 @@ fn is_expired(curr_time: DateTime<Utc>, prev_time: DateTime<Utc>) -> bool {
 
     difference >= valid_duration
 }
+
+#[cfg(test)]
+mod tests {
+    use bip_util::bt;
+
+    use std::collections::VecDeque;
+    use std::net::SocketAddr;
+
+    use announce::{ClientState, AnnounceEvent};
+    use client::ClientRequest;
+    use client::{ClientMetadata, ClientToken};
+    use client::error::{ClientError, ClientWarning};
+    use error::ErrorResponse;
+    use parse::ParseError;
+    use super::{ConnectTimer, EndpointHealth};
+
+    fn any_connect_timer(base_timeout: u64, max_retransmits: u64) -> ConnectTimer {
+        let request = ClientRequest::Announce([0u8; bt::INFO_HASH_LEN].into(),
+                                              ClientState::new(0, 0, 0, AnnounceEvent::Started));
+
+        ConnectTimer::new("127.0.0.1:6969".parse().unwrap(), request, base_timeout, max_retransmits)
+    }
+
+    #[test]
+    fn positive_default_retransmit_schedule() {
+        let mut timer = any_connect_timer(15000, 8);
+
+        assert_eq!(Some(15000), timer.current_timeout(false));
+        assert_eq!(Some(30000), timer.current_timeout(true));
+        assert_eq!(Some(60000), timer.current_timeout(true));
+        assert_eq!(3, timer.attempts());
+    }
+
+    #[test]
+    fn positive_connect_response_does_not_double_timeout() {
+        let mut timer = any_connect_timer(1000, 8);
+
+        assert_eq!(Some(1000), timer.current_timeout(false));
+        assert_eq!(Some(1000), timer.current_timeout(false));
+        assert_eq!(1, timer.attempts());
+    }
+
+    #[test]
+    fn negative_retransmits_past_max() {
+        let mut timer = any_connect_timer(1000, 2);
+
+        assert_eq!(Some(1000), timer.current_timeout(false));
+        assert_eq!(Some(2000), timer.current_timeout(true));
+        assert_eq!(Some(4000), timer.current_timeout(true));
+        assert_eq!(None, timer.current_timeout(true));
+        assert_eq!(3, timer.attempts());
+    }
+
+    #[test]
+    fn positive_no_attempts_before_sent() {
+        let timer = any_connect_timer(1000, 2);
+
+        assert_eq!(0, timer.attempts());
+    }
+
+    #[test]
+    fn positive_next_endpoint_resets_timeout() {
+        let fallback: SocketAddr = "127.0.0.2:6969".parse().unwrap();
+        let mut timer = any_connect_timer(1000, 1)
+            .with_fallbacks(vec![fallback].into_iter().collect::<VecDeque<_>>(), Vec::new());
+
+        assert_eq!(Some(1000), timer.current_timeout(false));
+        assert_eq!(Some(2000), timer.current_timeout(true));
+        assert_eq!(None, timer.current_timeout(true));
+
+        assert!(timer.next_endpoint(ClientError::MaxTimeout));
+        assert_eq!(fallback, timer.message_params().0);
+        assert_eq!(Some(1000), timer.current_timeout(false));
+        assert_eq!(3, timer.attempts());
+
+        let endpoints = timer.finish(None);
+        assert_eq!(2, endpoints.len());
+        assert_eq!(Some(&ClientError::MaxTimeout), endpoints[0].error());
+        assert_eq!(2, endpoints[0].attempts());
+        assert_eq!(fallback, endpoints[1].addr());
+        assert_eq!(None, endpoints[1].error());
+    }
+
+    #[test]
+    fn negative_next_endpoint_without_fallbacks() {
+        let mut timer = any_connect_timer(1000, 1);
+
+        assert_eq!(Some(1000), timer.current_timeout(false));
+        assert!(!timer.next_endpoint(ClientError::ServerError));
+
+        let endpoints = timer.into_endpoints();
+        assert_eq!(1, endpoints.len());
+        assert_eq!(Some(&ClientError::ServerError), endpoints[0].error());
+    }
+
+    #[test]
+    fn positive_annotate_moves_warnings_and_raw_response() {
+        let mut timer = any_connect_timer(1000, 1);
+        let addr = timer.message_params().0;
+
+        timer.add_warning(ClientWarning::MalformedResponse(addr, ParseError::Incomplete));
+        timer.set_raw_response(vec![0, 0, 0, 1]);
+
+        let metadata = timer.annotate(ClientMetadata::new(ClientToken(0), Err(ClientError::MaxTimeout)));
+        assert_eq!(&[ClientWarning::MalformedResponse(addr, ParseError::Incomplete)][..], metadata.warnings());
+        assert_eq!(Some(&[0, 0, 0, 1][..]), metadata.raw_response());
+
+        let metadata = timer.annotate(ClientMetadata::new(ClientToken(0), Err(ClientError::MaxTimeout)));
+        assert!(metadata.warnings().is_empty());
+        assert_eq!(None, metadata.raw_response());
+    }
+
+    #[test]
+    fn positive_warnings_bounded() {
+        let mut timer = any_connect_timer(1000, 1);
+        let addr = timer.message_params().0;
+
+        for _ in 0..(super::MAXIMUM_TRACKED_WARNINGS + 1) {
+            timer.add_warning(ClientWarning::MalformedResponse(addr, ParseError::Incomplete));
+        }
+        timer.add_warning(ClientWarning::TrackerMessage(addr, "Last Warning".to_owned()));
+
+        let metadata = timer.annotate(ClientMetadata::new(ClientToken(0), Err(ClientError::MaxTimeout)));
+        assert_eq!(super::MAXIMUM_TRACKED_WARNINGS, metadata.warnings().len());
+        assert_eq!(Some(&ClientWarning::TrackerMessage(addr, "Last Warning".to_owned())), metadata.warnings().last());
+    }
+
+    #[test]
+    fn positive_next_endpoint_keeps_tracker_message() {
+        let fallback: SocketAddr = "127.0.0.2:6969".parse().unwrap();
+        let mut timer = any_connect_timer(1000, 1)
+            .with_fallbacks(vec![fallback].into_iter().collect::<VecDeque<_>>(), Vec::new());
+        let addr = timer.message_params().0;
+
+        assert_eq!(Some(1000), timer.current_timeout(false));
+        assert!(timer.next_endpoint(ClientError::ServerMessage(ErrorResponse::new("Slow Down").to_owned())));
+
+        let metadata = timer.annotate(ClientMetadata::new(ClientToken(0), Err(ClientError::MaxTimeout)));
+        assert_eq!(&[ClientWarning::TrackerMessage(addr, "Slow Down".to_owned())][..], metadata.warnings());
+    }
+
+    #[test]
+    fn negative_last_endpoint_tracker_message_not_warning() {
+        let mut timer = any_connect_timer(1000, 1);
+
+        assert_eq!(Some(1000), timer.current_timeout(false));
+        assert!(!timer.next_endpoint(ClientError::ServerMessage(ErrorResponse::new("Go Away").to_owned())));
+
+        let metadata = timer.annotate(ClientMetadata::new(ClientToken(0), Err(ClientError::MaxTimeout)));
+        assert!(metadata.warnings().is_empty());
+    }
+
+    #[test]
+    fn positive_health_orders_failed_endpoints_last() {
+        let one: SocketAddr = "127.0.0.1:6969".parse().unwrap();
+        let two: SocketAddr = "127.0.0.2:6969".parse().unwrap();
+        let three: SocketAddr = "127.0.0.3:6969".parse().unwrap();
+        let mut health = EndpointHealth::new();
+
+        health.record_failure(one);
+        health.record_failure(two);
+        health.record_success(two);
+
+        let mut addrs = vec![one, two, three];
+        health.order(&mut addrs);
+
+        assert_eq!(vec![two, three, one], addrs);
+    }
+
+    #[test]
+    fn positive_health_bounded_tracked_endpoints() {
+        let mut health = EndpointHealth::new();
+        let unhealthy: SocketAddr = "127.0.0.1:1".parse().unwrap();
+
+        health.record_failure(unhealthy);
+        health.record_failure(unhealthy);
+        for port in 0..(super::MAXIMUM_TRACKED_ENDPOINTS as u16 * 2) {
+            health.record_failure(SocketAddr::new("127.0.0.2".parse().unwrap(), port));
+        }
+
+        assert_eq!(super::MAXIMUM_TRACKED_ENDPOINTS, health.failures.len());
+        assert_eq!(2, health.failures(unhealthy));
+    }
+}
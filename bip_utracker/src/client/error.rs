@@ -1,4 +1,7 @@
+use std::net::SocketAddr;
+
 use error::ErrorResponse;
+use parse::ParseError;
 
 /// Result type for a ClientRequest.
 pub type ClientResult<T> = Result<T, ClientError>;
@@ -19,3 +22,12 @@ pub enum ClientError {
     /// Server returned an error message.
     ServerMessage(ErrorResponse<'static>),
 }
+
+/// Non fatal issues encountered while processing a ClientRequest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientWarning {
+    /// Server sent a response for the request that we could not parse, which was ignored.
+    MalformedResponse(SocketAddr, ParseError),
+    /// Server sent an error message for the request, which was then retried on another endpoint.
+    TrackerMessage(SocketAddr, String),
+}
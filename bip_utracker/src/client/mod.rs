@@ -2,6 +2,7 @@ use std::io;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 use bip_handshake::{DiscoveryInfo, InitiateMessage};
 use bip_util::bt::{InfoHash};
@@ -12,19 +13,30 @@ use umio::external::{Sender};
 
 use announce::{AnnounceResponse, ClientState};
 use client::dispatcher::DispatchMessage;
-use client::error::ClientResult;
+use client::error::{ClientError, ClientResult, ClientWarning};
+use option::AnnounceOptions;
 use scrape::ScrapeResponse;
 
+pub use client::normalize::NormalizedPeers;
+
 mod dispatcher;
 pub mod error;
+mod normalize;
 
 /// Capacity of outstanding requests (assuming each request uses at most 1 timer at any time)
 const DEFAULT_CAPACITY: usize = 4096;
 
+/// Number of times a request will be retransmitted before timing out (BEP 15)
+const DEFAULT_MAX_RETRANSMITS: u64 = 8;
+/// Timeout for the first transmission of a request, doubled on each retransmit (BEP 15)
+const DEFAULT_BASE_TIMEOUT_MILLIS: u64 = 15000;
+
 /// Request made by the TrackerClient.
 #[derive(Debug)]
 pub enum ClientRequest {
     Announce(InfoHash, ClientState),
+    /// Announce with options, such as the URL data of the tracker, attached (BEP 41).
+    AnnounceWithOptions(InfoHash, ClientState, AnnounceOptions<'static>),
     Scrape(InfoHash),
 }
 
@@ -33,6 +45,11 @@ pub enum ClientRequest {
 pub struct ClientMetadata {
     token: ClientToken,
     result: ClientResult<ClientResponse>,
+    peers: Option<NormalizedPeers>,
+    attempts: u64,
+    endpoints: Vec<EndpointAttempt>,
+    warnings: Vec<ClientWarning>,
+    raw_response: Option<Vec<u8>>,
 }
 
 impl ClientMetadata {
@@ -41,9 +58,58 @@ impl ClientMetadata {
         ClientMetadata {
             token: token,
             result: result,
+            peers: None,
+            attempts: 0,
+            endpoints: Vec::new(),
+            warnings: Vec::new(),
+            raw_response: None,
         }
     }
 
+    /// Create a new ClientMetadata container with normalized announce peers.
+    pub fn with_peers(token: ClientToken,
+                      result: ClientResult<ClientResponse>,
+                      peers: NormalizedPeers)
+                      -> ClientMetadata {
+        ClientMetadata {
+            token: token,
+            result: result,
+            peers: Some(peers),
+            attempts: 0,
+            endpoints: Vec::new(),
+            warnings: Vec::new(),
+            raw_response: None,
+        }
+    }
+
+    /// Set the number of attempts that were made for the request.
+    pub fn with_attempts(mut self, attempts: u64) -> ClientMetadata {
+        self.attempts = attempts;
+
+        self
+    }
+
+    /// Set the endpoints that were tried for the request.
+    pub fn with_endpoints(mut self, endpoints: Vec<EndpointAttempt>) -> ClientMetadata {
+        self.endpoints = endpoints;
+
+        self
+    }
+
+    /// Set the warnings that were encountered for the request.
+    pub fn with_warnings(mut self, warnings: Vec<ClientWarning>) -> ClientMetadata {
+        self.warnings = warnings;
+
+        self
+    }
+
+    /// Set the raw bytes of the last response received for the request.
+    pub fn with_raw_response(mut self, raw_response: Vec<u8>) -> ClientMetadata {
+        self.raw_response = Some(raw_response);
+
+        self
+    }
+
     /// Access the request token corresponding to this metadata.
     pub fn token(&self) -> ClientToken {
         self.token
@@ -53,6 +119,82 @@ impl ClientMetadata {
     pub fn result(&self) -> &ClientResult<ClientResponse> {
         &self.result
     }
+
+    /// Access the normalized peers for the request.
+    ///
+    /// Only present for successful announce requests; these are the
+    /// peers that were forwarded on to the handshaker.
+    pub fn normalized_peers(&self) -> Option<&NormalizedPeers> {
+        self.peers.as_ref()
+    }
+
+    /// Access the number of attempts that were made for the request.
+    ///
+    /// Each time a request times out it is retransmitted, so a value larger
+    /// than one indicates the tracker was slow to respond or dropped packets.
+    /// Zero indicates the request was never sent.
+    pub fn attempts(&self) -> u64 {
+        self.attempts
+    }
+
+    /// Access the endpoints that were tried for the request, in the order they were tried.
+    ///
+    /// Requests made to a tracker with multiple endpoints will move on to the next
+    /// endpoint when an endpoint times out or responds with an error.
+    pub fn endpoints(&self) -> &[EndpointAttempt] {
+        &self.endpoints
+    }
+
+    /// Access the warnings that were encountered for the request.
+    ///
+    /// Responses from nonstandard trackers that we could not parse are
+    /// ignored (the request may still time out), but are recorded here, as
+    /// are error messages from endpoints that the request moved on from.
+    /// Only the most recent warnings are kept.
+    pub fn warnings(&self) -> &[ClientWarning] {
+        &self.warnings
+    }
+
+    /// Access the raw bytes of the last response received for the request.
+    ///
+    /// Only present if raw responses were enabled in the ClientConfig.
+    pub fn raw_response(&self) -> Option<&[u8]> {
+        self.raw_response.as_ref().map(|raw| &raw[..])
+    }
+}
+
+/// Outcome of a request made to a single endpoint of a tracker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointAttempt {
+    addr: SocketAddr,
+    attempts: u64,
+    error: Option<ClientError>,
+}
+
+impl EndpointAttempt {
+    /// Create a new EndpointAttempt.
+    pub fn new(addr: SocketAddr, attempts: u64, error: Option<ClientError>) -> EndpointAttempt {
+        EndpointAttempt {
+            addr: addr,
+            attempts: attempts,
+            error: error,
+        }
+    }
+
+    /// Address of the endpoint.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Number of attempts that were made to the endpoint.
+    pub fn attempts(&self) -> u64 {
+        self.attempts
+    }
+
+    /// Error that caused us to give up on the endpoint, if any.
+    pub fn error(&self) -> Option<&ClientError> {
+        self.error.as_ref()
+    }
 }
 
 /// Response received by the TrackerClient.
@@ -92,6 +234,82 @@ impl ClientResponse {
 
 // ----------------------------------------------------------------------------//
 
+/// Configures the internals of a TrackerClient.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ClientConfig {
+    capacity: usize,
+    max_retransmits: u64,
+    base_timeout: Duration,
+    raw_responses: bool,
+}
+
+impl ClientConfig {
+    /// Sets the maximum number of requests that can be in progress at once.
+    ///
+    /// Panics (when the client is created) if capacity == usize::max_value().
+    pub fn with_capacity(mut self, capacity: usize) -> ClientConfig {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets the number of times a request will be retransmitted
+    /// after timing out, before giving up on the request.
+    pub fn with_max_retransmits(mut self, max: u64) -> ClientConfig {
+        self.max_retransmits = max;
+        self
+    }
+
+    /// Sets the timeout for the first transmission of a request.
+    ///
+    /// The timeout is doubled on each retransmit, so by default
+    /// the timeouts follow the 15 * 2 ^ n schedule from BEP 15.
+    pub fn with_base_timeout(mut self, timeout: Duration) -> ClientConfig {
+        self.base_timeout = timeout;
+        self
+    }
+
+    /// Sets whether or not the raw bytes of responses are kept in the ClientMetadata.
+    ///
+    /// Useful for debugging nonstandard trackers, disabled by default.
+    pub fn with_raw_responses(mut self, raw_responses: bool) -> ClientConfig {
+        self.raw_responses = raw_responses;
+        self
+    }
+
+    /// Gets the request capacity.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Gets the max number of retransmits.
+    pub fn max_retransmits(&self) -> u64 {
+        self.max_retransmits
+    }
+
+    /// Gets the base request timeout.
+    pub fn base_timeout(&self) -> Duration {
+        self.base_timeout
+    }
+
+    /// Gets whether or not raw responses are kept.
+    pub fn raw_responses(&self) -> bool {
+        self.raw_responses
+    }
+}
+
+impl Default for ClientConfig {
+    fn default() -> ClientConfig {
+        ClientConfig {
+            capacity: DEFAULT_CAPACITY,
+            max_retransmits: DEFAULT_MAX_RETRANSMITS,
+            base_timeout: Duration::from_millis(DEFAULT_BASE_TIMEOUT_MILLIS),
+            raw_responses: false,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
 /// Tracker client that executes requests asynchronously.
 ///
 /// Client will shutdown on drop.
@@ -108,7 +326,7 @@ impl TrackerClient {
     where H: Sink + DiscoveryInfo + Send + 'static,
           H::SinkItem: From<Either<InitiateMessage, ClientMetadata>>
     {
-        TrackerClient::with_capacity(bind, handshaker, DEFAULT_CAPACITY)
+        TrackerClient::with_config(bind, handshaker, ClientConfig::default())
     }
 
     /// Create a new TrackerClient with the given message capacity.
@@ -121,6 +339,21 @@ impl TrackerClient {
     where H: Sink + DiscoveryInfo + Send + 'static,
           H::SinkItem: From<Either<InitiateMessage, ClientMetadata>>
     {
+        TrackerClient::with_config(bind, handshaker, ClientConfig::default().with_capacity(capacity))
+    }
+
+    /// Create a new TrackerClient with the given configuration.
+    ///
+    /// Panics if the configured capacity == usize::max_value().
+    pub fn with_config<H>(bind: SocketAddr,
+                          handshaker: H,
+                          config: ClientConfig)
+                          -> io::Result<TrackerClient>
+    where H: Sink + DiscoveryInfo + Send + 'static,
+          H::SinkItem: From<Either<InitiateMessage, ClientMetadata>>
+    {
+        let capacity = config.capacity();
+
         // Need channel capacity to be 1 more in case channel is saturated and client
         // is dropped so shutdown message can get through in the worst case
         let (chan_capacity, would_overflow) = capacity.overflowing_add(1);
@@ -130,7 +363,7 @@ impl TrackerClient {
         // Limit the capacity of messages (channel capacity - 1)
         let limiter = RequestLimiter::new(capacity);
 
-        dispatcher::create_dispatcher(bind, handshaker, chan_capacity, limiter.clone())
+        dispatcher::create_dispatcher(bind, handshaker, chan_capacity, limiter.clone(), config)
             .map(|chan| {
                 TrackerClient {
                     send: chan,
@@ -144,10 +377,46 @@ impl TrackerClient {
     ///
     /// If the maximum number of requests are currently in progress, return None.
     pub fn request(&mut self, addr: SocketAddr, request: ClientRequest) -> Option<ClientToken> {
+        self.request_with_opt_timeout(vec![addr], request, None)
+    }
+
+    /// Execute an asynchronous request to a tracker reachable at any of the given endpoints.
+    ///
+    /// Endpoints are tried one at a time, moving on to the next endpoint when one times
+    /// out or responds with an error. Endpoints that failed recently are tried last.
+    ///
+    /// If the maximum number of requests are currently in progress, or no endpoints
+    /// were given, return None.
+    pub fn request_endpoints(&mut self, addrs: Vec<SocketAddr>, request: ClientRequest) -> Option<ClientToken> {
+        if addrs.is_empty() {
+            None
+        } else {
+            self.request_with_opt_timeout(addrs, request, None)
+        }
+    }
+
+    /// Execute an asynchronous request to the given tracker, overriding the base timeout.
+    ///
+    /// The timeout is still doubled on each retransmit of the request.
+    ///
+    /// If the maximum number of requests are currently in progress, return None.
+    pub fn request_with_timeout(&mut self,
+                                addr: SocketAddr,
+                                request: ClientRequest,
+                                timeout: Duration)
+                                -> Option<ClientToken> {
+        self.request_with_opt_timeout(vec![addr], request, Some(duration_to_millis(timeout)))
+    }
+
+    fn request_with_opt_timeout(&mut self,
+                                addrs: Vec<SocketAddr>,
+                                request: ClientRequest,
+                                opt_timeout: Option<u64>)
+                                -> Option<ClientToken> {
         if self.limiter.can_initiate() {
             let token = self.generator.generate();
             self.send
-                .send(DispatchMessage::Request(addr, token, request))
+                .send(DispatchMessage::Request(addrs, token, request, opt_timeout))
                 .expect("bip_utracker: Failed To Send Client Request Message...");
 
             Some(token)
@@ -165,6 +434,11 @@ impl Drop for TrackerClient {
     }
 }
 
+/// Convert the given duration to milliseconds.
+fn duration_to_millis(duration: Duration) -> u64 {
+    duration.as_secs().saturating_mul(1000).saturating_add((duration.subsec_nanos() / 1_000_000) as u64)
+}
+
 // ----------------------------------------------------------------------------//
 
 /// Associates a ClientRequest with a ClientResponse.
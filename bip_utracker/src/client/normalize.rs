@@ -0,0 +1,165 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+
+/// Peer list from an announce response with invalid entries removed.
+///
+/// Removes duplicate peers, peers that point back to ourselves, and
+/// martian peers (unspecified, multicast, or broadcast addresses as
+/// well as addresses with a port of zero).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NormalizedPeers {
+    peers: Vec<SocketAddr>,
+    duplicates: usize,
+    ourselves: usize,
+    martians: usize,
+}
+
+impl NormalizedPeers {
+    /// Normalize the given peers.
+    ///
+    /// The bind address and port are used to detect if a peer is ourselves; if
+    /// the bind address is unspecified, only loopback addresses are checked.
+    pub fn new<I>(peers: I, bind: SocketAddr, port: u16) -> NormalizedPeers
+        where I: IntoIterator<Item = SocketAddr>
+    {
+        let mut seen = HashSet::new();
+        let mut normalized = NormalizedPeers {
+            peers: Vec::new(),
+            duplicates: 0,
+            ourselves: 0,
+            martians: 0,
+        };
+
+        for peer in peers {
+            if is_martian(peer) {
+                normalized.martians += 1;
+            } else if is_ourselves(peer, bind, port) {
+                normalized.ourselves += 1;
+            } else if !seen.insert(peer) {
+                normalized.duplicates += 1;
+            } else {
+                normalized.peers.push(peer);
+            }
+        }
+
+        normalized
+    }
+
+    /// Peers that passed normalization, in the order they were received.
+    pub fn peers(&self) -> &[SocketAddr] {
+        &self.peers
+    }
+
+    /// Number of peers that were filtered as duplicates.
+    pub fn num_duplicates(&self) -> usize {
+        self.duplicates
+    }
+
+    /// Number of peers that were filtered as being ourselves.
+    pub fn num_ourselves(&self) -> usize {
+        self.ourselves
+    }
+
+    /// Number of peers that were filtered as martian addresses.
+    pub fn num_martians(&self) -> usize {
+        self.martians
+    }
+
+    /// Total number of peers that were filtered.
+    pub fn num_filtered(&self) -> usize {
+        self.duplicates + self.ourselves + self.martians
+    }
+}
+
+/// Returns true if the address could never be a valid remote peer.
+fn is_martian(addr: SocketAddr) -> bool {
+    if addr.port() == 0 {
+        return true;
+    }
+
+    match addr.ip() {
+        IpAddr::V4(ip) => ip.is_unspecified() || ip.is_multicast() || ip.is_broadcast(),
+        IpAddr::V6(ip) => ip.is_unspecified() || ip.is_multicast(),
+    }
+}
+
+/// Returns true if the address points back to our own client.
+fn is_ourselves(addr: SocketAddr, bind: SocketAddr, port: u16) -> bool {
+    if addr.port() != port {
+        return false;
+    }
+
+    let bind_ip = bind.ip();
+    let is_unspecified = match bind_ip {
+        IpAddr::V4(ip) => ip.is_unspecified(),
+        IpAddr::V6(ip) => ip.is_unspecified(),
+    };
+
+    if is_unspecified {
+        addr.ip().is_loopback()
+    } else {
+        addr.ip() == bind_ip
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::NormalizedPeers;
+
+    fn addrs(addrs: &[&str]) -> Vec<SocketAddr> {
+        addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn positive_keep_valid_peers() {
+        let peers = addrs(&["10.0.0.1:6881", "10.0.0.2:6881", "[fe80::1]:6881"]);
+
+        let normalized = NormalizedPeers::new(peers.clone(), "0.0.0.0:0".parse().unwrap(), 6969);
+
+        assert_eq!(normalized.peers(), &peers[..]);
+        assert_eq!(normalized.num_filtered(), 0);
+    }
+
+    #[test]
+    fn positive_filter_duplicates() {
+        let peers = addrs(&["10.0.0.1:6881", "10.0.0.2:6881", "10.0.0.1:6881"]);
+
+        let normalized = NormalizedPeers::new(peers.clone(), "0.0.0.0:0".parse().unwrap(), 6969);
+
+        assert_eq!(normalized.peers(), &peers[..2]);
+        assert_eq!(normalized.num_duplicates(), 1);
+    }
+
+    #[test]
+    fn positive_filter_martians() {
+        let peers = addrs(&["0.0.0.0:6881", "224.0.0.1:6881", "255.255.255.255:6881",
+                            "10.0.0.1:0", "[::]:6881", "[ff02::1]:6881"]);
+
+        let normalized = NormalizedPeers::new(peers, "0.0.0.0:0".parse().unwrap(), 6969);
+
+        assert!(normalized.peers().is_empty());
+        assert_eq!(normalized.num_martians(), 6);
+    }
+
+    #[test]
+    fn positive_filter_ourselves_bound_ip() {
+        let peers = addrs(&["10.0.0.1:6969", "10.0.0.2:6969", "10.0.0.1:6881"]);
+
+        let normalized = NormalizedPeers::new(peers.clone(), "10.0.0.1:4501".parse().unwrap(), 6969);
+
+        assert_eq!(normalized.peers(), &peers[1..]);
+        assert_eq!(normalized.num_ourselves(), 1);
+    }
+
+    #[test]
+    fn positive_filter_ourselves_unspecified_ip() {
+        let peers = addrs(&["127.0.0.1:6969", "10.0.0.1:6969"]);
+
+        let normalized = NormalizedPeers::new(peers.clone(), "0.0.0.0:4501".parse().unwrap(), 6969);
+
+        assert_eq!(normalized.peers(), &peers[1..]);
+        assert_eq!(normalized.num_ourselves(), 1);
+    }
+}
@@ -5,7 +5,9 @@ use std::io::{self, Write};
 use std::net::{SocketAddrV4, SocketAddrV6, SocketAddr};
 
 use bip_util::convert;
-use nom::{IResult, Needed};
+use nom::{IResult, ErrorKind};
+
+use parse;
 
 const SOCKET_ADDR_V4_BYTES: usize = 6;
 const SOCKET_ADDR_V6_BYTES: usize = 18;
@@ -152,7 +154,7 @@ fn parse_peers_v4<'a>(bytes: &'a [u8]) -> IResult<&'a [u8], CompactPeersV4<'a>>
     let remainder_bytes = bytes.len() % SOCKET_ADDR_V4_BYTES;
 
     if remainder_bytes != 0 {
-        IResult::Incomplete(Needed::Size(SOCKET_ADDR_V4_BYTES - remainder_bytes))
+        IResult::Error(ErrorKind::Custom(parse::INVALID_PEERS_LENGTH_CODE))
     } else {
         let end_of_bytes = &bytes[bytes.len()..bytes.len()];
 
@@ -252,7 +254,7 @@ fn parse_peers_v6<'a>(bytes: &'a [u8]) -> IResult<&'a [u8], CompactPeersV6<'a>>
     let remainder_bytes = bytes.len() % SOCKET_ADDR_V6_BYTES;
 
     if remainder_bytes != 0 {
-        IResult::Incomplete(Needed::Size(SOCKET_ADDR_V6_BYTES - remainder_bytes))
+        IResult::Error(ErrorKind::Custom(parse::INVALID_PEERS_LENGTH_CODE))
     } else {
         let end_of_bytes = &bytes[bytes.len()..bytes.len()];
 
@@ -495,4 +497,19 @@ mod tests {
 
         assert_eq!(&received[..], &expected[..]);
     }
+
+    #[test]
+    fn negative_parse_invalid_length_v4() {
+        let bytes = [127, 0, 0, 1, 0, 15, 127];
+
+        assert!(CompactPeersV4::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn negative_parse_invalid_length_v6() {
+        let bytes = [0xAD, 0xBB, 0x23, 0x4A, 0x55, 0xBD, 0xFF, 0x34, 0x3D, 0x3A, 0x00, 0x00, 0x23,
+                     0x4A, 0x55, 0xBD, 1];
+
+        assert!(CompactPeersV6::from_bytes(&bytes).is_err());
+    }
 }
@@ -30,13 +30,15 @@ pub mod announce;
 pub mod contact;
 pub mod error;
 pub mod option;
+pub mod parse;
 pub mod scrape;
 
 mod client;
 mod server;
 
-pub use client::{TrackerClient, ClientRequest, ClientResponse, ClientToken, ClientMetadata};
-pub use client::error::{ClientResult, ClientError};
+pub use client::{TrackerClient, ClientRequest, ClientResponse, ClientToken, ClientMetadata,
+                 ClientConfig, NormalizedPeers, EndpointAttempt};
+pub use client::error::{ClientResult, ClientError, ClientWarning};
 
 pub use server::TrackerServer;
 pub use server::handler::{ServerResult, ServerHandler};
@@ -2,7 +2,7 @@
 
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::collections::hash_map::Entry;
+use std::collections::hash_map::{self, Entry};
 use std::io::{self, Write};
 
 use byteorder::WriteBytesExt;
@@ -82,6 +82,23 @@ impl<'a> AnnounceOptions<'a> {
         self.raw_options.get(&O::option_byte()).and_then(|bytes| O::read_option(&*bytes))
     }
 
+    /// Raw bytes of the option with the given option byte, if present.
+    ///
+    /// Chunked options are returned as a single, concatenated, payload.
+    pub fn get_raw(&self, byte: u8) -> Option<&[u8]> {
+        self.raw_options.get(&byte).map(|bytes| &**bytes)
+    }
+
+    /// Iterator over the option byte and raw bytes of all options.
+    pub fn raw_options<'b>(&'b self) -> RawOptions<'b, 'a> {
+        RawOptions { iter: self.raw_options.iter() }
+    }
+
+    /// Iterator over the option byte and raw bytes of options we do not have a type for.
+    pub fn unknown_options<'b>(&'b self) -> UnknownOptions<'b, 'a> {
+        UnknownOptions { iter: self.raw_options() }
+    }
+
     /// Add an AnnounceOption to the current set of AnnounceOptions.
     ///
     /// Any existing option with a matching option byte will be replaced.
@@ -114,6 +131,92 @@ impl<'a> AnnounceOptions<'a> {
     }
 }
 
+/// Whether or not the option byte corresponds to a typed AnnounceOption we provide.
+fn is_known_option(byte: u8) -> bool {
+    byte == URL_DATA_BYTE
+}
+
+/// Iterator over the raw options within an AnnounceOptions.
+pub struct RawOptions<'b, 'a: 'b> {
+    iter: hash_map::Iter<'b, u8, Cow<'a, [u8]>>,
+}
+
+impl<'b, 'a> Iterator for RawOptions<'b, 'a> {
+    type Item = (u8, &'b [u8]);
+
+    fn next(&mut self) -> Option<(u8, &'b [u8])> {
+        self.iter.next().map(|(&byte, bytes)| (byte, &**bytes))
+    }
+}
+
+/// Iterator over the raw options within an AnnounceOptions that have no typed AnnounceOption.
+pub struct UnknownOptions<'b, 'a: 'b> {
+    iter: RawOptions<'b, 'a>,
+}
+
+impl<'b, 'a> Iterator for UnknownOptions<'b, 'a> {
+    type Item = (u8, &'b [u8]);
+
+    fn next(&mut self) -> Option<(u8, &'b [u8])> {
+        while let Some((byte, bytes)) = self.iter.next() {
+            if !is_known_option(byte) {
+                return Some((byte, bytes));
+            }
+        }
+
+        None
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Builder for the AnnounceOptions attached to an outgoing AnnounceRequest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OptionsBuilder {
+    options: AnnounceOptions<'static>,
+}
+
+impl OptionsBuilder {
+    /// Create a new OptionsBuilder.
+    pub fn new() -> OptionsBuilder {
+        OptionsBuilder { options: AnnounceOptions::new() }
+    }
+
+    /// Attach the concatenated PATH and QUERY of the tracker URL (BEP 41).
+    ///
+    /// Trackers hosted on a shared address may use this to identify the tracker
+    /// being announced to, or to authenticate the client.
+    pub fn with_url_data(self, url_data: &[u8]) -> OptionsBuilder {
+        self.with_raw_option(URL_DATA_BYTE, url_data)
+    }
+
+    /// Attach the given AnnounceOption.
+    pub fn with_option<'a, O>(self, option: &O) -> OptionsBuilder
+        where O: AnnounceOption<'a>
+    {
+        let mut bytes = vec![0u8; option.option_length()];
+        option.write_option(&mut bytes[..]);
+
+        self.with_raw_option(O::option_byte(), &bytes)
+    }
+
+    /// Attach the raw bytes of an option we do not have a type for.
+    ///
+    /// Option bytes reserved for the end of options and no operation markers are ignored.
+    pub fn with_raw_option(mut self, byte: u8, contents: &[u8]) -> OptionsBuilder {
+        if byte != END_OF_OPTIONS_BYTE && byte != NO_OPERATION_BYTE {
+            self.options.insert_bytes(byte, contents.to_vec());
+        }
+
+        self
+    }
+
+    /// Build the AnnounceOptions.
+    pub fn build(self) -> AnnounceOptions<'static> {
+        self.options
+    }
+}
+
 /// Parse the options in the byte slice and store them in the option map.
 fn parse_options<'a>(bytes: &'a [u8],
                      option_map: &mut HashMap<u8, Cow<'a, [u8]>>)
@@ -188,6 +291,11 @@ impl<'a> URLDataOption<'a> {
     pub fn new(url_data: &'a [u8]) -> URLDataOption<'a> {
         URLDataOption { url_data: url_data }
     }
+
+    /// Concatenated PATH and QUERY bytes.
+    pub fn url_data(&self) -> &'a [u8] {
+        self.url_data
+    }
 }
 
 impl<'a> AnnounceOption<'a> for URLDataOption<'a> {
@@ -215,7 +323,7 @@ mod tests {
 
     use nom::IResult;
 
-    use super::{AnnounceOptions, URLDataOption};
+    use super::{AnnounceOptions, OptionsBuilder, URLDataOption};
 
     #[test]
     fn positive_write_eof_option() {
@@ -457,6 +565,42 @@ mod tests {
         assert_eq!(received, IResult::Done(&b""[..], expected));
     }
 
+    #[test]
+    fn positive_parse_unknown_option_chain() {
+        let bytes = [super::URL_DATA_BYTE, 2, b'/', b'a', super::NO_OPERATION_BYTE, 0x80, 3, 1, 2, 3,
+                     0x80, 1, 4, super::END_OF_OPTIONS_BYTE];
+
+        let options = match AnnounceOptions::from_bytes(&bytes) {
+            IResult::Done(_, options) => options,
+            _ => panic!("Failed To Parse Option Chain"),
+        };
+
+        assert_eq!(options.get::<URLDataOption>().unwrap().url_data(), b"/a");
+        assert_eq!(options.get_raw(0x80), Some(&[1, 2, 3, 4][..]));
+        assert_eq!(options.raw_options().count(), 2);
+
+        let unknown: Vec<(u8, &[u8])> = options.unknown_options().collect();
+        assert_eq!(unknown, vec![(0x80, &[1, 2, 3, 4][..])]);
+    }
+
+    #[test]
+    fn positive_builder_writes_options() {
+        let options = OptionsBuilder::new()
+            .with_url_data(b"/announce?key=1")
+            .with_raw_option(0x80, &[9])
+            .with_raw_option(super::NO_OPERATION_BYTE, &[1])
+            .build();
+
+        let mut bytes = Vec::new();
+        options.write_bytes(&mut bytes).unwrap();
+
+        let received = AnnounceOptions::from_bytes(&bytes);
+        assert_eq!(received, IResult::Done(&b""[..], options.clone()));
+
+        assert_eq!(options.get_raw(super::NO_OPERATION_BYTE), None);
+        assert_eq!(options.get::<URLDataOption>().unwrap().url_data(), b"/announce?key=1");
+    }
+
     #[test]
     fn negative_parse_url_data_incomplete() {
         let bytes = [super::URL_DATA_BYTE, 5, 0, 0];
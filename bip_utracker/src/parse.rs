@@ -0,0 +1,123 @@
+//! Strict parsing of complete tracker messages.
+
+use nom::{IResult, ErrorKind};
+
+/// Custom nom error code for compact peers not divisible by the address length.
+pub const INVALID_PEERS_LENGTH_CODE: u32 = 1;
+/// Custom nom error code for scrape stats not divisible by the stats length.
+pub const INVALID_STATS_LENGTH_CODE: u32 = 2;
+/// Custom nom error code for scrape hashes not divisible by the hash length.
+pub const INVALID_HASHES_LENGTH_CODE: u32 = 3;
+
+/// Result type for parsing a complete tracker message.
+pub type ParseResult<T> = Result<T, ParseError>;
+
+/// Errors occuring when parsing a complete tracker message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// Message ended before all fields were read.
+    Incomplete,
+    /// Compact peers were not a multiple of the address length.
+    InvalidPeersLength,
+    /// Scrape stats were not a multiple of the stats length.
+    InvalidStatsLength,
+    /// Scrape hashes were not a multiple of the hash length.
+    InvalidHashesLength,
+    /// Message had the given number of bytes left over after parsing.
+    TrailingBytes(usize),
+    /// Message was otherwise malformed (unknown action, invalid string, etc).
+    Malformed,
+}
+
+/// Convert the result of a nom parser over an entire message into a `ParseResult`.
+///
+/// Since tracker messages are sent in a single datagram, incomplete input and
+/// input with trailing bytes are both treated as errors.
+pub fn complete<'a, O>(result: IResult<&'a [u8], O>) -> ParseResult<O> {
+    match result {
+        IResult::Done(remaining, _) if !remaining.is_empty() => Err(ParseError::TrailingBytes(remaining.len())),
+        IResult::Done(_, value) => Ok(value),
+        IResult::Incomplete(_) => Err(ParseError::Incomplete),
+        IResult::Error(ErrorKind::Custom(INVALID_PEERS_LENGTH_CODE)) => Err(ParseError::InvalidPeersLength),
+        IResult::Error(ErrorKind::Custom(INVALID_STATS_LENGTH_CODE)) => Err(ParseError::InvalidStatsLength),
+        IResult::Error(ErrorKind::Custom(INVALID_HASHES_LENGTH_CODE)) => Err(ParseError::InvalidHashesLength),
+        IResult::Error(_) => Err(ParseError::Malformed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{BigEndian, WriteBytesExt};
+
+    use parse::ParseError;
+    use request::{self, TrackerRequest};
+    use response::TrackerResponse;
+
+    fn response_header(action_id: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        bytes.write_u32::<BigEndian>(action_id).unwrap();
+        bytes.write_u32::<BigEndian>(0).unwrap();
+
+        bytes
+    }
+
+    #[test]
+    fn positive_parse_exact_connect_response() {
+        let mut bytes = response_header(::CONNECT_ACTION_ID);
+        bytes.write_u64::<BigEndian>(5).unwrap();
+
+        assert!(TrackerResponse::from_bytes_exact(&bytes).is_ok());
+    }
+
+    #[test]
+    fn negative_parse_exact_trailing_bytes() {
+        let mut bytes = response_header(::CONNECT_ACTION_ID);
+        bytes.write_u64::<BigEndian>(5).unwrap();
+        bytes.push(0);
+
+        assert_eq!(ParseError::TrailingBytes(1), TrackerResponse::from_bytes_exact(&bytes).err().unwrap());
+    }
+
+    #[test]
+    fn negative_parse_exact_incomplete() {
+        let bytes = response_header(::CONNECT_ACTION_ID);
+
+        assert_eq!(ParseError::Incomplete, TrackerResponse::from_bytes_exact(&bytes).err().unwrap());
+    }
+
+    #[test]
+    fn negative_parse_exact_invalid_peers_length() {
+        let mut bytes = response_header(::ANNOUNCE_IPV4_ACTION_ID);
+        bytes.extend_from_slice(&[0u8; 12]);
+        bytes.extend_from_slice(&[127, 0, 0, 1, 0]);
+
+        assert_eq!(ParseError::InvalidPeersLength, TrackerResponse::from_bytes_exact(&bytes).err().unwrap());
+    }
+
+    #[test]
+    fn negative_parse_exact_invalid_stats_length() {
+        let mut bytes = response_header(::SCRAPE_ACTION_ID);
+        bytes.extend_from_slice(&[0u8; 13]);
+
+        assert_eq!(ParseError::InvalidStatsLength, TrackerResponse::from_bytes_exact(&bytes).err().unwrap());
+    }
+
+    #[test]
+    fn negative_parse_exact_unknown_action() {
+        let bytes = response_header(10);
+
+        assert_eq!(ParseError::Malformed, TrackerResponse::from_bytes_exact(&bytes).err().unwrap());
+    }
+
+    #[test]
+    fn negative_parse_exact_invalid_hashes_length() {
+        let mut bytes = Vec::new();
+        bytes.write_u64::<BigEndian>(request::CONNECT_ID_PROTOCOL_ID).unwrap();
+        bytes.write_u32::<BigEndian>(::SCRAPE_ACTION_ID).unwrap();
+        bytes.write_u32::<BigEndian>(0).unwrap();
+        bytes.extend_from_slice(&[0u8; 21]);
+
+        assert_eq!(ParseError::InvalidHashesLength, TrackerRequest::from_bytes_exact(&bytes).err().unwrap());
+    }
+}
@@ -3,9 +3,10 @@
 use std::io::{self, Write};
 
 use byteorder::{BigEndian, WriteBytesExt};
-use nom::{be_u64, be_u32, IResult};
+use nom::{be_u64, be_u32, IResult, ErrorKind};
 
 use announce::AnnounceRequest;
+use parse::{self, ParseResult};
 use scrape::ScrapeRequest;
 
 // For all practical applications, this value should be hardcoded as a valid
@@ -57,6 +58,11 @@ impl<'a> TrackerRequest<'a> {
         parse_request(bytes)
     }
 
+    /// Create a new TrackerRequest from the given bytes, which must contain exactly one request.
+    pub fn from_bytes_exact(bytes: &'a [u8]) -> ParseResult<TrackerRequest<'a>> {
+        parse::complete(parse_request(bytes))
+    }
+
     /// Write the TrackerRequest to the given writer.
     pub fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
         where W: Write
@@ -119,18 +125,22 @@ impl<'a> TrackerRequest<'a> {
 }
 
 fn parse_request<'a>(bytes: &'a [u8]) -> IResult<&'a [u8], TrackerRequest<'a>> {
-    switch!(bytes, tuple!(be_u64, be_u32, be_u32),
-        (CONNECT_ID_PROTOCOL_ID, ::CONNECT_ACTION_ID, tid) => value!(
-            TrackerRequest::new(CONNECT_ID_PROTOCOL_ID, tid, RequestType::Connect)
-        ) |
-        (cid, ::ANNOUNCE_IPV4_ACTION_ID, tid) => map!(call!(AnnounceRequest::from_bytes_v4), |ann_req| {
+    // Dispatch on the action id by hand, since switch! would discard the custom error codes of the body
+    let (body, (cid, action_id, tid)) = try_parse!(bytes, tuple!(be_u64, be_u32, be_u32));
+
+    match (cid, action_id) {
+        (CONNECT_ID_PROTOCOL_ID, ::CONNECT_ACTION_ID) => {
+            IResult::Done(body, TrackerRequest::new(CONNECT_ID_PROTOCOL_ID, tid, RequestType::Connect))
+        }
+        (_, ::ANNOUNCE_IPV4_ACTION_ID) => map!(body, call!(AnnounceRequest::from_bytes_v4), |ann_req| {
             TrackerRequest::new(cid, tid, RequestType::Announce(ann_req))
-        }) |
-        (cid, ::SCRAPE_ACTION_ID, tid) => map!(call!(ScrapeRequest::from_bytes), |scr_req| {
+        }),
+        (_, ::SCRAPE_ACTION_ID) => map!(body, call!(ScrapeRequest::from_bytes), |scr_req| {
             TrackerRequest::new(cid, tid, RequestType::Scrape(scr_req))
-        }) |
-        (cid, ::ANNOUNCE_IPV6_ACTION_ID, tid) => map!(call!(AnnounceRequest::from_bytes_v6), |ann_req| {
+        }),
+        (_, ::ANNOUNCE_IPV6_ACTION_ID) => map!(body, call!(AnnounceRequest::from_bytes_v6), |ann_req| {
             TrackerRequest::new(cid, tid, RequestType::Announce(ann_req))
-        })
-    )
+        }),
+        _ => IResult::Error(ErrorKind::Switch),
+    }
 }
@@ -3,11 +3,12 @@
 use std::io::{self, Write};
 
 use byteorder::{BigEndian, WriteBytesExt};
-use nom::{be_u32, IResult, be_u64};
+use nom::{be_u32, IResult, ErrorKind, be_u64};
 
 use announce::AnnounceResponse;
 use contact::CompactPeers;
 use error::ErrorResponse;
+use parse::{self, ParseResult};
 use scrape::ScrapeResponse;
 
 /// Error action ids only occur in responses.
@@ -53,6 +54,11 @@ impl<'a> TrackerResponse<'a> {
         parse_response(bytes)
     }
 
+    /// Create a new TrackerResponse from the given bytes, which must contain exactly one response.
+    pub fn from_bytes_exact(bytes: &'a [u8]) -> ParseResult<TrackerResponse<'a>> {
+        parse::complete(parse_response(bytes))
+    }
+
     /// Write the TrackerResponse to the given writer.
     pub fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
         where W: Write
@@ -112,19 +118,23 @@ impl<'a> TrackerResponse<'a> {
 }
 
 fn parse_response<'a>(bytes: &'a [u8]) -> IResult<&'a [u8], TrackerResponse<'a>> {
-    switch!(bytes, tuple!(be_u32, be_u32),
-        (::CONNECT_ACTION_ID, tid)  => map!(be_u64, |cid| TrackerResponse::new(tid, ResponseType::Connect(cid)) ) |
-        (::ANNOUNCE_IPV4_ACTION_ID, tid) => map!(call!(AnnounceResponse::from_bytes_v4), |ann_res| {
+    // Dispatch on the action id by hand, since switch! would discard the custom error codes of the body
+    let (body, (action_id, tid)) = try_parse!(bytes, tuple!(be_u32, be_u32));
+
+    match action_id {
+        ::CONNECT_ACTION_ID => map!(body, be_u64, |cid| TrackerResponse::new(tid, ResponseType::Connect(cid))),
+        ::ANNOUNCE_IPV4_ACTION_ID => map!(body, call!(AnnounceResponse::from_bytes_v4), |ann_res| {
             TrackerResponse::new(tid, ResponseType::Announce(ann_res))
-        }) |
-        (::SCRAPE_ACTION_ID, tid)   => map!(call!(ScrapeResponse::from_bytes), |scr_res| {
+        }),
+        ::SCRAPE_ACTION_ID => map!(body, call!(ScrapeResponse::from_bytes), |scr_res| {
             TrackerResponse::new(tid, ResponseType::Scrape(scr_res))
-        }) |
-        (ERROR_ACTION_ID, tid)    => map!(call!(ErrorResponse::from_bytes), |err_res| {
+        }),
+        ERROR_ACTION_ID => map!(body, call!(ErrorResponse::from_bytes), |err_res| {
             TrackerResponse::new(tid, ResponseType::Error(err_res))
-        }) |
-        (::ANNOUNCE_IPV6_ACTION_ID, tid) => map!(call!(AnnounceResponse::from_bytes_v6), |ann_req| {
-            TrackerResponse::new(tid, ResponseType::Announce(ann_req))
-        })
-    )
+        }),
+        ::ANNOUNCE_IPV6_ACTION_ID => map!(body, call!(AnnounceResponse::from_bytes_v6), |ann_res| {
+            TrackerResponse::new(tid, ResponseType::Announce(ann_res))
+        }),
+        _ => IResult::Error(ErrorKind::Switch),
+    }
 }
@@ -5,7 +5,9 @@ use std::io::{self, Write};
 
 use bip_util::bt::{self, InfoHash};
 use bip_util::convert;
-use nom::{IResult, Needed, be_i32};
+use nom::{IResult, ErrorKind, be_i32};
+
+use parse;
 
 const SCRAPE_STATS_BYTES: usize = 12;
 
@@ -107,7 +109,7 @@ fn parse_request<'a>(bytes: &'a [u8]) -> IResult<&'a [u8], ScrapeRequest<'a>> {
     let remainder_bytes = bytes.len() % bt::INFO_HASH_LEN;
 
     if remainder_bytes != 0 {
-        IResult::Incomplete(Needed::Size(bt::INFO_HASH_LEN - remainder_bytes))
+        IResult::Error(ErrorKind::Custom(parse::INVALID_HASHES_LENGTH_CODE))
     } else {
         let end_of_bytes = &bytes[bytes.len()..bytes.len()];
 
@@ -174,7 +176,7 @@ fn parse_response<'a>(bytes: &'a [u8]) -> IResult<&'a [u8], ScrapeResponse<'a>>
     let remainder_bytes = bytes.len() % SCRAPE_STATS_BYTES;
 
     if remainder_bytes != 0 {
-        IResult::Incomplete(Needed::Size(SCRAPE_STATS_BYTES - remainder_bytes))
+        IResult::Error(ErrorKind::Custom(parse::INVALID_STATS_LENGTH_CODE))
     } else {
         let end_of_bytes = &bytes[bytes.len()..bytes.len()];
 
@@ -446,4 +448,18 @@ mod tests {
 
         assert_eq!(received, IResult::Done(&b""[..], expected));
     }
+
+    #[test]
+    fn negative_parse_request_invalid_length() {
+        let hash_bytes = [0u8; 25];
+
+        assert!(ScrapeRequest::from_bytes(&hash_bytes).is_err());
+    }
+
+    #[test]
+    fn negative_parse_response_invalid_length() {
+        let stats_bytes = [0, 0, 0, 255, 0, 0, 1, 0, 0, 0, 2, 0, 0];
+
+        assert!(ScrapeResponse::from_bytes(&stats_bytes).is_err());
+    }
 }
@@ -1,11 +1,11 @@
 use std::io::{self, Cursor};
-use std::net::SocketAddr;
+use std::net::{SocketAddr, SocketAddrV4};
 use std::thread;
 
-use nom::IResult;
 use umio::{ELoopBuilder, Dispatcher, Provider};
 
-use announce::AnnounceRequest;
+use announce::{AnnounceRequest, AnnounceResponse};
+use contact::CompactPeers;
 use error::ErrorResponse;
 use request::{self, TrackerRequest, RequestType};
 use response::{TrackerResponse, ResponseType};
@@ -88,7 +88,7 @@ impl<H> ServerDispatcher<H>
                            provider: &mut Provider<'a, ServerDispatcher<H>>,
                            trans_id: u32,
                            addr: SocketAddr) {
-        self.handler.connect(addr, |result| {
+        self.handler.connect(normalize_addr(addr), |result| {
             let response_type = match result {
                 Ok(conn_id) => ResponseType::Connect(conn_id),
                 Err(err_msg) => ResponseType::Error(ErrorResponse::new(err_msg)),
@@ -106,8 +106,13 @@ impl<H> ServerDispatcher<H>
                                 conn_id: u64,
                                 request: &AnnounceRequest<'b>,
                                 addr: SocketAddr) {
-        self.handler.announce(addr, conn_id, request, |result| {
+        let wants_v6 = request.source_ip().is_ipv6();
+
+        self.handler.announce(normalize_addr(addr), conn_id, request, |result| {
             let response_type = match result {
+                Ok(ref response) if !is_matching_ip_version(response, wants_v6) => {
+                    ResponseType::Error(ErrorResponse::new("Announce Response IP Version Does Not Match Request"))
+                }
                 Ok(response) => ResponseType::Announce(response),
                 Err(err_msg) => ResponseType::Error(ErrorResponse::new(err_msg)),
             };
@@ -124,7 +129,7 @@ impl<H> ServerDispatcher<H>
                               conn_id: u64,
                               request: &ScrapeRequest<'b>,
                               addr: SocketAddr) {
-        self.handler.scrape(addr, conn_id, request, |result| {
+        self.handler.scrape(normalize_addr(addr), conn_id, request, |result| {
             let response_type = match result {
                 Ok(response) => ResponseType::Scrape(response),
                 Err(err_msg) => ResponseType::Error(ErrorResponse::new(err_msg)),
@@ -136,6 +141,34 @@ impl<H> ServerDispatcher<H>
     }
 }
 
+/// Map an IPv4 mapped IPv6 address (received on a dual stack socket) back to IPv4.
+///
+/// Responses are still written to the original address, only handlers see the normalized address.
+fn normalize_addr(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(v6_addr) => {
+            let segments = v6_addr.ip().segments();
+
+            if segments[..5].iter().all(|&s| s == 0) && segments[5] == 0xFFFF {
+                let ip = [(segments[6] >> 8) as u8, segments[6] as u8, (segments[7] >> 8) as u8, segments[7] as u8];
+
+                SocketAddr::V4(SocketAddrV4::new(ip.into(), v6_addr.port()))
+            } else {
+                addr
+            }
+        }
+        SocketAddr::V4(_) => addr,
+    }
+}
+
+/// Returns true if the peers in the response match the IP version of the request.
+fn is_matching_ip_version(response: &AnnounceResponse, wants_v6: bool) -> bool {
+    match response.peers() {
+        &CompactPeers::V4(_) => !wants_v6,
+        &CompactPeers::V6(_) => wants_v6,
+    }
+}
+
 /// Write the given tracker response through to the given provider.
 fn write_response<'a, 'b, H>(provider: &mut Provider<'a, ServerDispatcher<H>>,
                              response: TrackerResponse<'b>,
@@ -164,9 +197,9 @@ impl<H> Dispatcher for ServerDispatcher<H>
                     mut provider: Provider<'a, Self>,
                     message: &[u8],
                     addr: SocketAddr) {
-        let request = match TrackerRequest::from_bytes(message) {
-            IResult::Done(_, req) => req,
-            _ => return, // TODO: Add Logging
+        let request = match TrackerRequest::from_bytes_exact(message) {
+            Ok(req) => req,
+            Err(_) => return, // TODO: Add Logging
         };
 
         self.process_request(&mut provider, request, addr);
@@ -180,3 +213,30 @@ impl<H> Dispatcher for ServerDispatcher<H>
 
     fn timeout<'a>(&mut self, _: Provider<'a, Self>, _: ()) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    #[test]
+    fn positive_normalize_mapped_v4_addr() {
+        let mapped: SocketAddr = "[::ffff:192.168.0.1]:6881".parse().unwrap();
+        let expected: SocketAddr = "192.168.0.1:6881".parse().unwrap();
+
+        assert_eq!(expected, super::normalize_addr(mapped));
+    }
+
+    #[test]
+    fn positive_normalize_v6_addr_unchanged() {
+        let addr: SocketAddr = "[2001:db8::1]:6881".parse().unwrap();
+
+        assert_eq!(addr, super::normalize_addr(addr));
+    }
+
+    #[test]
+    fn positive_normalize_v4_addr_unchanged() {
+        let addr: SocketAddr = "127.0.0.1:6881".parse().unwrap();
+
+        assert_eq!(addr, super::normalize_addr(addr));
+    }
+}
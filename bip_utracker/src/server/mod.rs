@@ -18,6 +18,10 @@ pub struct TrackerServer {
 
 impl TrackerServer {
     /// Run a new TrackerServer.
+    ///
+    /// Binding to an unspecified IPv6 address (`[::]`) will, on platforms that support dual stack
+    /// sockets, service both IPv4 and IPv6 clients; IPv4 mapped addresses are passed to the handler
+    /// as plain IPv4 addresses.
     pub fn run<H>(bind: SocketAddr, handler: H) -> io::Result<TrackerServer>
         where H: ServerHandler + 'static
     {
@@ -20,13 +20,17 @@ use futures::stream::{Stream};
 use futures::future::Either;
 use futures::{StartSend, Poll};
 
+mod test_announce_mapped_v4;
 mod test_announce_start;
+mod test_announce_start_v6;
 mod test_announce_stop;
+mod test_announce_stop_v6;
 mod test_client_drop;
 mod test_client_full;
 mod test_connect;
 mod test_connect_cache;
 mod test_scrape;
+mod test_scrape_v6;
 mod test_server_drop;
 
 const NUM_PEERS_RETURNED: usize = 20;
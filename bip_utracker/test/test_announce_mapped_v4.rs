@@ -0,0 +1,105 @@
+use std::net::SocketAddr;
+use std::thread::{self};
+use std::time::{Duration};
+
+use bip_util::bt::{self};
+use bip_utracker::{TrackerClient, TrackerServer, ClientRequest, ClientError, ServerHandler, ServerResult};
+use bip_utracker::announce::{ClientState, AnnounceEvent, AnnounceRequest, AnnounceResponse};
+use bip_utracker::contact::{CompactPeers, CompactPeersV6};
+use bip_utracker::scrape::{ScrapeRequest, ScrapeResponse};
+use futures::stream::Stream;
+use futures::future::Either;
+
+use {handshaker, MockTrackerHandler};
+
+/// Handler that always responds to announces with IPv6 peers.
+struct V6OnlyTrackerHandler {
+    inner: MockTrackerHandler
+}
+
+impl ServerHandler for V6OnlyTrackerHandler {
+    fn connect<R>(&mut self, addr: SocketAddr, result: R)
+        where R: for<'a> FnOnce(ServerResult<'a, u64>) {
+        self.inner.connect(addr, result)
+    }
+
+    fn announce<'b, R>(&mut self, _: SocketAddr, _: u64, _: &AnnounceRequest<'b>, result: R)
+        where R: for<'a> FnOnce(ServerResult<'a, AnnounceResponse<'a>>) {
+        result(Ok(AnnounceResponse::new(1800, 0, 0, CompactPeers::V6(CompactPeersV6::new()))));
+    }
+
+    fn scrape<'b, R>(&mut self, addr: SocketAddr, id: u64, req: &ScrapeRequest<'b>, result: R)
+        where R: for<'a> FnOnce(ServerResult<'a, ScrapeResponse<'a>>) {
+        self.inner.scrape(addr, id, req, result)
+    }
+}
+
+#[test]
+#[allow(unused)]
+fn positive_announce_mapped_v4_normalized() {
+    let (sink, stream) = handshaker();
+
+    // Dual stack server, so our IPv4 client shows up as an IPv4 mapped IPv6 address
+    let server_addr = "[::]:3512".parse().unwrap();
+    let mock_handler = MockTrackerHandler::new();
+    let server = TrackerServer::run(server_addr, mock_handler).unwrap();
+
+    thread::sleep(Duration::from_millis(100));
+
+    let mut client = TrackerClient::new("127.0.0.1:4512".parse().unwrap(), sink).unwrap();
+
+    let hash = [0u8; bt::INFO_HASH_LEN].into();
+    let send_token = client.request("127.0.0.1:3512".parse().unwrap(), ClientRequest::Announce(
+        hash,
+        ClientState::new(0, 0, 0, AnnounceEvent::Started)
+    )).unwrap();
+
+    let mut blocking_stream = stream.wait();
+
+    let metadata = match blocking_stream.next().unwrap().unwrap() {
+        Either::B(b) => b,
+        Either::A(_) => unreachable!()
+    };
+    assert_eq!(send_token, metadata.token());
+
+    // Handler stored us under our IPv4 address, otherwise it would have no IPv4 peers to give back
+    let metadata_result = metadata.result().as_ref().unwrap().announce_response().unwrap();
+    assert_eq!(metadata_result.peers().iter().count(), 1);
+    assert!(metadata_result.peers().iter().all(|addr| addr.is_ipv4()));
+    assert_eq!(metadata.normalized_peers().unwrap().num_ourselves(), 1);
+}
+
+#[test]
+#[allow(unused)]
+fn negative_announce_ip_version_mismatch() {
+    let (sink, stream) = handshaker();
+
+    let server_addr = "[::]:3513".parse().unwrap();
+    let handler = V6OnlyTrackerHandler{ inner: MockTrackerHandler::new() };
+    let server = TrackerServer::run(server_addr, handler).unwrap();
+
+    thread::sleep(Duration::from_millis(100));
+
+    let mut client = TrackerClient::new("127.0.0.1:4513".parse().unwrap(), sink).unwrap();
+
+    let hash = [0u8; bt::INFO_HASH_LEN].into();
+    let send_token = client.request("127.0.0.1:3513".parse().unwrap(), ClientRequest::Announce(
+        hash,
+        ClientState::new(0, 0, 0, AnnounceEvent::Started)
+    )).unwrap();
+
+    let mut blocking_stream = stream.wait();
+
+    let metadata = match blocking_stream.next().unwrap().unwrap() {
+        Either::B(b) => b,
+        Either::A(_) => unreachable!()
+    };
+    assert_eq!(send_token, metadata.token());
+
+    match metadata.result() {
+        &Err(ClientError::ServerMessage(ref response)) => {
+            assert_eq!("Announce Response IP Version Does Not Match Request", response.message())
+        },
+        other => panic!("Unexpected Result: {:?}", other)
+    }
+}
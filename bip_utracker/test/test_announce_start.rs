@@ -1,8 +1,6 @@
 use std::thread::{self};
 use std::time::{Duration};
-use std::net::SocketAddr;
 
-use bip_handshake::{Protocol};
 use bip_util::bt::{self};
 use bip_utracker::{TrackerClient, TrackerServer, ClientRequest};
 use bip_utracker::announce::{ClientState, AnnounceEvent};
@@ -32,17 +30,6 @@ fn positive_announce_started() {
     
     let mut blocking_stream = stream.wait();
 
-    let init_msg = match blocking_stream.next().unwrap().unwrap() {
-        Either::A(a) => a,
-        Either::B(_) => unreachable!()
-    };
-
-    let exp_peer_addr: SocketAddr = "127.0.0.1:6969".parse().unwrap();
-
-    assert_eq!(&Protocol::BitTorrent, init_msg.protocol());
-    assert_eq!(&exp_peer_addr, init_msg.address());
-    assert_eq!(&hash, init_msg.hash());
-
     let metadata = match blocking_stream.next().unwrap().unwrap() {
         Either::B(b) => b,
         Either::A(_) => unreachable!()   
@@ -52,4 +39,10 @@ fn positive_announce_started() {
     assert_eq!(metadata_result.leechers(), 1);
     assert_eq!(metadata_result.seeders(), 1);
     assert_eq!(metadata_result.peers().iter().count(), 1);
+
+    // Tracker returned ourselves, which should not be forwarded to the handshaker
+    let normalized_peers = metadata.normalized_peers().unwrap();
+    assert!(normalized_peers.peers().is_empty());
+    assert_eq!(normalized_peers.num_ourselves(), 1);
+    assert_eq!(normalized_peers.num_filtered(), 1);
 }
\ No newline at end of file
@@ -0,0 +1,49 @@
+use std::thread::{self};
+use std::time::{Duration};
+
+use bip_util::bt::{self};
+use bip_utracker::{TrackerClient, TrackerServer, ClientRequest};
+use bip_utracker::announce::{ClientState, AnnounceEvent};
+use futures::stream::Stream;
+use futures::future::Either;
+
+use {handshaker, MockTrackerHandler};
+
+#[test]
+#[allow(unused)]
+fn positive_announce_started_v6() {
+    let (sink, stream) = handshaker();
+    
+    let server_addr = "[::1]:3509".parse().unwrap();
+    let mock_handler = MockTrackerHandler::new();
+    let server = TrackerServer::run(server_addr, mock_handler).unwrap();
+    
+    thread::sleep(Duration::from_millis(100));
+    
+    let mut client = TrackerClient::new("[::1]:4509".parse().unwrap(), sink).unwrap();
+    
+    let hash = [0u8; bt::INFO_HASH_LEN].into();
+    let send_token = client.request(server_addr, ClientRequest::Announce(
+        hash,
+        ClientState::new(0, 0, 0, AnnounceEvent::Started)
+    )).unwrap();
+    
+    let mut blocking_stream = stream.wait();
+
+    let metadata = match blocking_stream.next().unwrap().unwrap() {
+        Either::B(b) => b,
+        Either::A(_) => unreachable!()   
+    };
+    let metadata_result = metadata.result().as_ref().unwrap().announce_response().unwrap();
+
+    assert_eq!(metadata_result.leechers(), 1);
+    assert_eq!(metadata_result.seeders(), 1);
+    assert_eq!(metadata_result.peers().iter().count(), 1);
+    assert!(metadata_result.peers().iter().all(|addr| addr.is_ipv6()));
+
+    // Tracker returned ourselves, which should not be forwarded to the handshaker
+    let normalized_peers = metadata.normalized_peers().unwrap();
+    assert!(normalized_peers.peers().is_empty());
+    assert_eq!(normalized_peers.num_ourselves(), 1);
+    assert_eq!(normalized_peers.num_filtered(), 1);
+}
\ No newline at end of file
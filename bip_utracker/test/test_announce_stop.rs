@@ -32,11 +32,6 @@ fn positive_announce_stopped() {
             ClientState::new(0, 0, 0, AnnounceEvent::Started)
         )).unwrap();
         
-        let init_msg = match blocking_stream.next().unwrap().unwrap() {
-            Either::A(a) => a,
-            Either::B(_) => unreachable!()
-        };
-
         let metadata = match blocking_stream.next().unwrap().unwrap() {
             Either::B(b) => b,
             Either::A(_) => unreachable!()   
@@ -48,6 +43,8 @@ fn positive_announce_stopped() {
         assert_eq!(response.leechers(), 1);
         assert_eq!(response.seeders(), 1);
         assert_eq!(response.peers().iter().count(), 1);
+        // Only peer in the swarm is ourselves, which is not forwarded
+        assert_eq!(metadata.normalized_peers().unwrap().num_ourselves(), 1);
     }
     
     // Stopped
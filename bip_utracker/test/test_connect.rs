@@ -29,11 +29,6 @@ fn positive_receive_connect_id() {
     
     let mut blocking_stream = stream.wait();
 
-    let _init_msg = match blocking_stream.next().unwrap().unwrap() {
-        Either::A(a) => a,
-        Either::B(_) => unreachable!()
-    };
-
     let metadata = match blocking_stream.next().unwrap().unwrap() {
         Either::B(b) => b,
         Either::A(_) => unreachable!()   
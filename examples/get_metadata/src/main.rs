@@ -16,10 +16,10 @@ use bip_handshake::{Extension, Extensions, HandshakerBuilder, HandshakerConfig,
 use bip_handshake::DiscoveryInfo;
 use bip_handshake::PeerId;
 use bip_handshake::transports::TcpTransport;
-use bip_peer::{IPeerManagerMessage, OPeerManagerMessage, PeerInfo, PeerManagerBuilder, PeerProtocolCodec};
+use bip_peer::{IPeerManagerMessage, OPeerManagerMessage, PeerManagerBuilder};
 use bip_peer::messages::{BitsExtensionMessage, PeerExtensionProtocolMessage, PeerWireProtocolMessage};
 use bip_peer::messages::builders::ExtendedMessageBuilder;
-use bip_peer::protocols::{NullProtocol, PeerExtensionProtocol, PeerWireProtocol};
+use bip_peer::protocols::{NullProtocol, PeerExtensionProtocolFactory};
 use bip_select::{ControlMessage, IExtendedMessage, IUberMessage, OExtendedMessage, OUberMessage, UberModuleBuilder};
 use bip_select::discovery::{IDiscoveryMessage, ODiscoveryMessage, UtMetadataModule};
 use futures::{Future, Sink, Stream};
@@ -32,7 +32,6 @@ use std::io::Write;
 use std::net::SocketAddr;
 use std::time::Duration;
 use tokio_core::reactor::Core;
-use tokio_io::AsyncRead;
 use pendulum::{HashedWheelBuilder};
 use pendulum::future::{TimerBuilder};
 
@@ -123,27 +122,17 @@ fn main() {
         handshaker_recv
             .map_err(|_| ())
             .map(|complete_msg| {
-                // Our handshaker finished handshaking some peer, get
-                // the peer info as well as the peer itself (socket)
-                let (_, extensions, hash, pid, addr, sock) = complete_msg.into_parts();
+                // Our handshaker finished handshaking some peer, frame the socket with the peer
+                // wire protocol (only allowing the extensions the peer negotiated), with a nested
+                // null protocol for the extension protocol, and a max payload of 24KB
+                let (peer_info, peer) = bip_peer::frame_peer(
+                    complete_msg,
+                    PeerExtensionProtocolFactory::new(NullProtocol::new()),
+                    24 * 1024,
+                );
 
-                // Only connect to peer that support the extension protocol...
-                if extensions.contains(Extension::ExtensionProtocol) {
-                    // Frame our socket with the peer wire protocol with no
-                    // extensions (nested null protocol), and a max payload of 24KB
-                    let peer = sock.framed(PeerProtocolCodec::with_max_payload(
-                        PeerWireProtocol::new(PeerExtensionProtocol::new(NullProtocol::new())),
-                        24 * 1024,
-                    ));
-
-                    // Create our peer identifier used by our peer manager
-                    let peer_info = PeerInfo::new(addr, pid, hash, extensions);
-
-                    // Map to a message that can be fed to our peer manager
-                    IPeerManagerMessage::AddPeer(peer_info, peer)
-                } else {
-                    panic!("Chosen Peer Does Not Support Extended Messages")
-                }
+                // Map to a message that can be fed to our peer manager
+                IPeerManagerMessage::AddPeer(peer_info, peer)
             })
             .forward(peer_manager_send.clone().sink_map_err(|_| ()))
             .map(|_| ()),
@@ -280,6 +269,7 @@ fn main() {
                                 PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::Extended(ext_message)),
                             ))
                         },
+                        OUberMessage::Extended(OExtendedMessage::ExternalAddress(_)) => None,
                         OUberMessage::Discovery(ODiscoveryMessage::SendUtMetadataMessage(info, message)) => {
                             Some(IPeerManagerMessage::SendMessage(
                                 info,
@@ -287,6 +277,9 @@ fn main() {
                                 PeerWireProtocolMessage::ProtExtension(PeerExtensionProtocolMessage::UtMetadata(message)),
                             ))
                         },
+                        OUberMessage::Discovery(ODiscoveryMessage::BanPeer(info)) => {
+                            Some(IPeerManagerMessage::RemovePeer(info))
+                        },
                         OUberMessage::Discovery(ODiscoveryMessage::DownloadedMetainfo(metainfo)) => {
                             opt_metainfo = Some(metainfo);
                             None
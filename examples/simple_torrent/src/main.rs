@@ -2,6 +2,7 @@ extern crate bip_disk;
 extern crate bip_handshake;
 extern crate bip_metainfo;
 extern crate bip_peer;
+extern crate bip_util;
 #[macro_use]
 extern crate clap;
 extern crate futures;
@@ -9,7 +10,6 @@ extern crate tokio_core;
 extern crate tokio_io;
 extern crate tokio_timer;
 
-use std::collections::HashMap;
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::fs::File;
@@ -22,10 +22,11 @@ use bip_disk::fs_cache::FileHandleCache;
 //use bip_dht::{DhtBuilder, Handshaker, Router};
 use bip_handshake::{HandshakerBuilder, PeerId, InitiateMessage, Protocol, HandshakerConfig};
 use bip_handshake::transports::TcpTransport;
-use bip_peer::{PeerManagerBuilder, IPeerManagerMessage, PeerInfo, PeerProtocolCodec, OPeerManagerMessage};
+use bip_peer::{PeerManagerBuilder, IPeerManagerMessage, PeerInfo, PeerProtocolCodec, OPeerManagerMessage, PeerRequests};
 use bip_peer::protocols::{PeerWireProtocol, NullProtocol};
 use bip_peer::message::{HaveMessage, BitFieldMessage, PeerWireProtocolMessage, PieceMessage, RequestMessage};
 use bip_metainfo::{MetainfoFile, InfoDictionary};
+use bip_util::pool::BufferPool;
 use tokio_core::reactor::Core;
 use tokio_io::{AsyncRead,};
 use futures::{future, stream, Future, Stream, Sink};
@@ -40,7 +41,6 @@ use futures::future::{Loop, Either};
       * We dont do any banning of malicious peers
       
     Things the example doesnt do, unrelated to bip_select:
-      * Matching peers up to disk requests isnt as good as it could be
       * Doesnt use a shared BytesMut for servicing piece requests
       * Good logging
 */
@@ -151,6 +151,8 @@ fn main() {
         // for the peer manager as well.
         .with_sink_buffer_capacity(0)
         .with_stream_buffer_capacity(0)
+        // Blocks we upload are loaded into buffers from the global pool, hand them back once sent
+        .with_buffer_pool(Some(BufferPool::global()))
         .build(core.handle())
         .into_parts();
 
@@ -161,12 +163,12 @@ fn main() {
         .map(|complete_msg| {
             // Our handshaker finished handshaking some peer, get
             // the peer info as well as the peer itself (socket)
-            let (_, _, hash, pid, addr, sock) = complete_msg.into_parts();
+            let parts = complete_msg.into_parts();
             // Frame our socket with the peer wire protocol with no extensions (nested null protocol), and a max payload of 24KB
-            let peer = sock.framed(PeerProtocolCodec::with_max_payload(PeerWireProtocol::new(NullProtocol::new()), 24 * 1024));
+            let peer = parts.socket.framed(PeerProtocolCodec::with_max_payload(PeerWireProtocol::new(NullProtocol::new()), 24 * 1024));
             
             // Create our peer identifier used by our peer manager
-            let peer_info = PeerInfo::new(addr, pid, hash);
+            let peer_info = PeerInfo::new(parts.address, parts.peer_id, parts.hash, parts.extensions, parts.direction, parts.transport);
 
             // Map to a message that can be fed to our peer manager
             IPeerManagerMessage::AddPeer(peer_info, peer)
@@ -175,8 +177,8 @@ fn main() {
         .map(|_| ())
     );
 
-    // Will hold a mapping of BlockMetadata -> Vec<PeerInfo> to track which peers to send a queued block to
-    let disk_request_map = Rc::new(RefCell::new(HashMap::new()));
+    // Tracks which peers to send a loaded block to, and lets cancelled requests skip the disk entirely
+    let disk_request_map = Rc::new(RefCell::new(PeerRequests::new()));
     let (select_send, select_recv) = mpsc::channel(50);
 
     // Map out the errors for these sinks so they match
@@ -199,16 +201,22 @@ fn main() {
                             PeerWireProtocolMessage::Have(have)         => Some(Either::A(SelectState::Have(info, have))),
                             PeerWireProtocolMessage::BitField(bitfield) => Some(Either::A(SelectState::BitField(info, bitfield))),
                             PeerWireProtocolMessage::Request(request)   => {
-                                let block_metadata = BlockMetadata::new(info_hash, request.piece_index() as u64, request.block_offset() as u64, request.block_length());
                                 let mut request_map_mut = disk_request_map.borrow_mut();
 
-                                // Add the block metadata to our request map, and add the peer as an entry there
-                                let block_entry = request_map_mut.entry(block_metadata);
-                                let peers_requested = block_entry.or_insert(Vec::new());
+                                // Track the peer as waiting on the block (ignored if the peer has too many requests outstanding), only loading the block if no other peer beat us to it
+                                request_map_mut.request_received(info, request);
 
-                                peers_requested.push(info);
+                                request_map_mut.next_load().map(|(hash, request)| {
+                                    let block_metadata = BlockMetadata::new(hash, request.piece_index() as u64, request.block_offset() as u64, request.block_length());
 
-                                Some(Either::B(IDiskMessage::LoadBlock(BlockMut::new(block_metadata, vec![0u8; block_metadata.block_length()].into()))))
+                                    Either::B(IDiskMessage::LoadBlock(BlockMut::new(block_metadata, BufferPool::global().alloc(block_metadata.block_length()))))
+                                })
+                            },
+                            PeerWireProtocolMessage::Cancel(cancel)     => {
+                                // Peer no longer wants the block, so we wont send it to them once it is loaded
+                                disk_request_map.borrow_mut().cancel_received(&info, cancel);
+
+                                None
                             },
                             PeerWireProtocolMessage::Piece(piece)       => {
                                 let block_metadata = BlockMetadata::new(info_hash, piece.piece_index() as u64, piece.block_offset() as u64, piece.block_length());
@@ -221,9 +229,11 @@ fn main() {
                     },
                     OPeerManagerMessage::PeerAdded(info)        => Some(Either::A(SelectState::NewPeer(info))),
                     OPeerManagerMessage::SentMessage(_, _)      => None,
-                    OPeerManagerMessage::PeerRemoved(info)      => { println!("We Removed Peer {:?} From The Peer Manager", info); Some(Either::A(SelectState::RemovedPeer(info))) },
-                    OPeerManagerMessage::PeerDisconnect(info)   => { println!("Peer {:?} Disconnected From Us", info); Some(Either::A(SelectState::RemovedPeer(info))) },
-                    OPeerManagerMessage::PeerError(info, error) => { println!("Peer {:?} Disconnected With Error: {:?}", info, error); Some(Either::A(SelectState::RemovedPeer(info))) }
+                    OPeerManagerMessage::SentMessages(_, _)     => None,
+                    OPeerManagerMessage::PeerStats(_, _)        => None,
+                    OPeerManagerMessage::PeerRemoved(info)      => { println!("We Removed Peer {:?} From The Peer Manager", info); disk_request_map.borrow_mut().remove_peer(&info); Some(Either::A(SelectState::RemovedPeer(info))) },
+                    OPeerManagerMessage::PeerDisconnect(info)   => { println!("Peer {:?} Disconnected From Us", info); disk_request_map.borrow_mut().remove_peer(&info); Some(Either::A(SelectState::RemovedPeer(info))) },
+                    OPeerManagerMessage::PeerError(info, error) => { println!("Peer {:?} Disconnected With Error: {:?}", info, error); disk_request_map.borrow_mut().remove_peer(&info); Some(Either::A(SelectState::RemovedPeer(info))) }
                 };
 
                 // Could optimize out the box, but for the example, this is cleaner and shorter
@@ -253,18 +263,29 @@ fn main() {
                     ODiskMessage::BlockLoaded(block)       => {
                         let (metadata, block) = block.into_parts();
 
-                        // Lookup the peer info given the block metadata
-                        let mut request_map_mut = disk_request_map.borrow_mut();
-                        let mut peer_list = request_map_mut.get_mut(&metadata).unwrap();
-                        let peer_info = peer_list.remove(1);
-                        
-                        // Pack up our block into a peer wire protocol message and send it off to the peer
+                        // Lookup the peers still waiting on the block (peers that cancelled wont be in here)
+                        let request = RequestMessage::new(metadata.piece_index() as u32, metadata.block_offset() as u32, metadata.block_length());
+                        let peers = disk_request_map.borrow_mut().block_loaded(&metadata.info_hash(), &request);
+
+                        // Pack up our block into a peer wire protocol message and send it off to the peers
                         let piece = PieceMessage::new(metadata.piece_index() as u32, metadata.block_offset() as u32, block.freeze());
-                        let pwp_message = PeerWireProtocolMessage::Piece(piece);
+                        let peer_messages: Vec<_> = peers.into_iter()
+                            .map(|peer_info| IPeerManagerMessage::SendMessage(peer_info, 0, PeerWireProtocolMessage::Piece(piece.clone())))
+                            .collect();
+
+                        Some(Either::B(peer_messages))
+                    },
+                    ODiskMessage::LoadBlockError(block, error) => {
+                        let metadata = block.metadata();
+
+                        // Drop the requests for the block, otherwise later requests for it would never be loaded
+                        let request = RequestMessage::new(metadata.piece_index() as u32, metadata.block_offset() as u32, metadata.block_length());
+                        let peers = disk_request_map.borrow_mut().load_failed(&metadata.info_hash(), &request);
+                        println!("Failed To Load Block {:?} For Peers {:?}: {:?}", metadata, peers, error);
 
-                        Some(Either::B(IPeerManagerMessage::SendMessage(peer_info, 0, pwp_message)))
+                        None
                     },
-                    ODiskMessage::TorrentAdded(_)          => Some(Either::A(SelectState::TorrentAdded)),
+                    ODiskMessage::TorrentAdded(_, _)      => Some(Either::A(SelectState::TorrentAdded)),
                     ODiskMessage::TorrentSynced(_)         => Some(Either::A(SelectState::TorrentSynced)),
                     ODiskMessage::FoundGoodPiece(_, index) => Some(Either::A(SelectState::GoodPiece(index))),
                     ODiskMessage::FoundBadPiece(_, index)  => Some(Either::A(SelectState::BadPiece(index))),
@@ -276,8 +297,8 @@ fn main() {
                 let result_future: Box<Future<Item=Loop<(), _>, Error=()>> = match opt_message {
                     Some(Either::A(select_message)) =>
                         Box::new(select_send.send(select_message).map(|select_send| Loop::Continue((disk_manager_recv, disk_request_map, select_send, peer_manager_send)))),
-                    Some(Either::B(peer_message))   =>
-                        Box::new(peer_manager_send.send(peer_message).map(|peer_manager_send| Loop::Continue((disk_manager_recv, disk_request_map, select_send, peer_manager_send)))),
+                    Some(Either::B(peer_messages))  =>
+                        Box::new(peer_manager_send.send_all(stream::iter_ok::<_, ()>(peer_messages)).map(|(peer_manager_send, _)| Loop::Continue((disk_manager_recv, disk_request_map, select_send, peer_manager_send)))),
                     None                            =>
                         Box::new(future::ok(Loop::Continue((disk_manager_recv, disk_request_map, select_send, peer_manager_send))))
                 };